@@ -0,0 +1,114 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Futex testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{
+	EAGAIN, ETIMEDOUT, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE, MAP_ANONYMOUS, MAP_FAILED,
+	MAP_SHARED, PROT_READ, PROT_WRITE, c_int, c_long, timespec,
+};
+use std::{
+	io, ptr,
+	ptr::null,
+	sync::atomic::{AtomicU32, Ordering::SeqCst},
+};
+
+fn futex(
+	uaddr: &AtomicU32,
+	op: c_int,
+	val: u32,
+	timeout: Option<&timespec>,
+) -> io::Result<c_long> {
+	let timeout = timeout.map(|t| t as *const _).unwrap_or(null());
+	let res = unsafe { libc::syscall(libc::SYS_futex, uaddr.as_ptr(), op, val, timeout, 0, 0) };
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn wait_wake() -> TestResult {
+	let val = AtomicU32::new(0);
+	let op_wait = FUTEX_WAIT | FUTEX_PRIVATE_FLAG;
+	let op_wake = FUTEX_WAKE | FUTEX_PRIVATE_FLAG;
+
+	log!("Wait on mismatching value");
+	let res = futex(&val, op_wait, 1, None);
+	test_assert_eq!(res.unwrap_err().raw_os_error(), Some(EAGAIN));
+
+	log!("Wait with timeout");
+	let timeout = timespec {
+		tv_sec: 0,
+		tv_nsec: 10_000_000,
+	};
+	let res = futex(&val, op_wait, 0, Some(&timeout));
+	test_assert_eq!(res.unwrap_err().raw_os_error(), Some(ETIMEDOUT));
+
+	log!("Wake without waiters");
+	test_assert_eq!(futex(&val, op_wake, 1, None)?, 0);
+
+	Ok(())
+}
+
+pub fn shared() -> TestResult {
+	log!("Map shared memory");
+	let ptr = unsafe {
+		libc::mmap(
+			ptr::null_mut(),
+			4096,
+			PROT_READ | PROT_WRITE,
+			MAP_SHARED | MAP_ANONYMOUS,
+			-1,
+			0,
+		)
+	};
+	test_assert!(ptr != MAP_FAILED);
+	let val = unsafe { AtomicU32::from_ptr(ptr as *mut u32) };
+
+	log!("Wait in child process");
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if pid == 0 {
+		while val.load(SeqCst) == 0 {
+			match futex(val, FUTEX_WAIT, 0, None) {
+				Ok(_) => {}
+				Err(e) if e.raw_os_error() == Some(EAGAIN) => {}
+				Err(_) => unsafe { libc::_exit(1) },
+			}
+		}
+		unsafe { libc::_exit(0) };
+	}
+
+	log!("Wake from parent process");
+	val.store(1, SeqCst);
+	futex(val, FUTEX_WAKE, 1, None)?;
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	test_assert_eq!(res, pid);
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 0);
+
+	unsafe {
+		libc::munmap(ptr, 4096);
+	}
+	Ok(())
+}
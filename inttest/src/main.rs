@@ -27,10 +27,19 @@ use crate::{
 use std::{path::Path, process::exit};
 
 mod filesystem;
+mod futex;
+mod mem;
 mod module;
 mod mount;
+mod network;
+mod poll;
 mod procfs;
+mod ptrace;
+mod sched;
+mod session;
 mod signal;
+mod swap;
+mod thread;
 mod util;
 
 /*
@@ -146,10 +155,156 @@ const TESTS: &[TestSuite] = &[
 				name: "handler",
 				desc: "Register and use a signal handler",
 				start: signal::handler,
-			}, /* TODO signal masking
-			    * TODO pause */
+			},
+			Test {
+				name: "signalfd",
+				desc: "Accept a blocked signal through a signalfd",
+				start: signal::signalfd,
+			},
+			Test {
+				name: "siginfo",
+				desc: "Queue a signal with a value to a handler running on an alternate stack",
+				start: signal::siginfo,
+			},
+			Test {
+				name: "sigwait",
+				desc: "Wait for a blocked signal and check pending signals",
+				start: signal::sigwait,
+			},
+			Test {
+				name: "alarm",
+				desc: "Wait for an alarm with pause",
+				start: signal::alarm,
+			},
+			Test {
+				name: "itimer",
+				desc: "Receive a signal from the profiling interval timer",
+				start: signal::itimer,
+			},
+		],
+	},
+	TestSuite {
+		name: "sched",
+		desc: "Test scheduling priorities and policies",
+		tests: &[
+			Test {
+				name: "priority",
+				desc: "Get and set the nice value",
+				start: sched::priority,
+			},
+			Test {
+				name: "policy",
+				desc: "Get and set the scheduling policy",
+				start: sched::policy,
+			},
+		],
+	},
+	TestSuite {
+		name: "futex",
+		desc: "Test futex operations",
+		tests: &[
+			Test {
+				name: "wait_wake",
+				desc: "Wait on and wake a private futex",
+				start: futex::wait_wake,
+			},
+			Test {
+				name: "shared",
+				desc: "Wake a process waiting on a futex in a shared mapping",
+				start: futex::shared,
+			},
 		],
 	},
+	TestSuite {
+		name: "mem",
+		desc: "Test memory mappings",
+		tests: &[
+			Test {
+				name: "mremap",
+				desc: "Resize and move a mapping",
+				start: mem::mremap,
+			},
+			Test {
+				name: "mlock",
+				desc: "Lock pages in memory and check their residency",
+				start: mem::mlock,
+			},
+		],
+	},
+	TestSuite {
+		name: "poll",
+		desc: "Test waiting for events on file descriptors",
+		tests: &[
+			Test {
+				name: "poll",
+				desc: "Poll a pipe and wait for data",
+				start: poll::poll_pipe,
+			},
+			Test {
+				name: "select",
+				desc: "Select on a pipe with a timeout",
+				start: poll::select_pipe,
+			},
+			Test {
+				name: "epoll",
+				desc: "Monitor a pipe with epoll in level- and edge-triggered modes",
+				start: poll::epoll_pipe,
+			},
+			Test {
+				name: "eventfd",
+				desc: "Write to and read from an eventfd",
+				start: poll::eventfd,
+			},
+			Test {
+				name: "timerfd",
+				desc: "Wait for relative and absolute timerfd expirations",
+				start: poll::timerfd,
+			},
+		],
+	},
+	TestSuite {
+		name: "thread",
+		desc: "Test threads and thread groups",
+		tests: &[
+			Test {
+				name: "spawn_join",
+				desc: "Spawn a thread in the current thread group and join it",
+				start: thread::spawn_join,
+			},
+			Test {
+				name: "tgkill",
+				desc: "Send signals to a thread of a thread group",
+				start: thread::tgkill_thread,
+			},
+		],
+	},
+	TestSuite {
+		name: "session",
+		desc: "Test sessions and process groups",
+		tests: &[Test {
+			name: "setsid",
+			desc: "Create a new session and check its properties",
+			start: session::setsid,
+		}],
+	},
+	TestSuite {
+		name: "ptrace",
+		desc: "Test process tracing",
+		tests: &[Test {
+			name: "traceme",
+			desc: "Stop a traced child and access its memory",
+			start: ptrace::traceme,
+		}],
+	},
+	TestSuite {
+		name: "swap",
+		desc: "Test swap areas",
+		tests: &[Test {
+			name: "swapon",
+			desc: "Activate and deactivate a swap file",
+			start: swap::swapon_swapoff,
+		}],
+	},
 	// TODO ELF files (execve)
 	// TODO user/group file accesses (including SUID/SGID)
 	// TODO time ((non-)monotonic clock, sleep and timer_*)
@@ -179,6 +334,11 @@ const TESTS: &[TestSuite] = &[
 				desc: "/proc/self/environ",
 				start: procfs::environ,
 			},
+			Test {
+				name: "/proc/self/oom_score",
+				desc: "/proc/self/oom_score and /proc/self/oom_score_adj",
+				start: procfs::oom_score,
+			},
 			// TODO /proc/self/stat
 		],
 	},
@@ -214,7 +374,32 @@ const TESTS: &[TestSuite] = &[
 	},*/
 	// TODO scripts (Shell/Perl)
 	// TODO compilation (C/C++/Rust)
-	// TODO network
+	TestSuite {
+		name: "network",
+		desc: "Test network communications over the loopback interface",
+		tests: &[
+			Test {
+				name: "udp",
+				desc: "Exchange UDP datagrams",
+				start: network::udp,
+			},
+			Test {
+				name: "tcp",
+				desc: "Establish a TCP connection and transfer data",
+				start: network::tcp,
+			},
+			Test {
+				name: "nosignal",
+				desc: "Send on a shut down TCP connection with and without MSG_NOSIGNAL",
+				start: network::nosignal,
+			},
+			Test {
+				name: "sockopt",
+				desc: "Get and set socket options",
+				start: network::sockopt,
+			},
+		],
+	},
 	TestSuite {
 		name: "Unmount",
 		desc: "Unmount filesystems",
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Memory mappings testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{
+	MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, MCL_CURRENT, MREMAP_MAYMOVE, PROT_READ, PROT_WRITE,
+	c_void,
+};
+use std::{io, ptr::null_mut, slice};
+
+/// The size of a page in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// Maps `pages` anonymous pages.
+fn map(pages: usize) -> io::Result<*mut u8> {
+	let ptr = unsafe {
+		libc::mmap(
+			null_mut(),
+			pages * PAGE_SIZE,
+			PROT_READ | PROT_WRITE,
+			MAP_PRIVATE | MAP_ANONYMOUS,
+			-1,
+			0,
+		)
+	};
+	if ptr == MAP_FAILED {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(ptr as _)
+}
+
+/// Returns the residency of the `pages` pages beginning at `ptr`.
+fn mincore(ptr: *mut u8, pages: usize) -> io::Result<Vec<u8>> {
+	let mut vec = vec![0u8; pages];
+	let res = unsafe { libc::mincore(ptr as _, pages * PAGE_SIZE, vec.as_mut_ptr()) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(vec)
+}
+
+pub fn mremap() -> TestResult {
+	log!("Map and fill pages");
+	let ptr = map(2)?;
+	let buf = unsafe { slice::from_raw_parts_mut(ptr, 2 * PAGE_SIZE) };
+	buf.fill(0xaa);
+
+	log!("Grow without moving");
+	// Block the following range to prevent growing in place
+	let block = unsafe {
+		libc::mmap(
+			ptr.add(2 * PAGE_SIZE) as _,
+			PAGE_SIZE,
+			PROT_READ,
+			MAP_PRIVATE | MAP_ANONYMOUS | libc::MAP_FIXED,
+			-1,
+			0,
+		)
+	};
+	test_assert!(block != MAP_FAILED);
+	let res = unsafe { libc::mremap(ptr as _, 2 * PAGE_SIZE, 4 * PAGE_SIZE, 0) };
+	test_assert_eq!(res, MAP_FAILED);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::ENOMEM)
+	);
+
+	log!("Grow and move");
+	let new = unsafe { libc::mremap(ptr as _, 2 * PAGE_SIZE, 4 * PAGE_SIZE, MREMAP_MAYMOVE) };
+	test_assert!(new != MAP_FAILED);
+	let new = new as *mut u8;
+	let buf = unsafe { slice::from_raw_parts_mut(new, 4 * PAGE_SIZE) };
+	test_assert!(buf[..2 * PAGE_SIZE].iter().all(|b| *b == 0xaa));
+	test_assert!(buf[2 * PAGE_SIZE..].iter().all(|b| *b == 0));
+	// The old range must have been unmapped
+	test_assert_eq!(
+		mincore(ptr, 1).unwrap_err().raw_os_error(),
+		Some(libc::ENOMEM)
+	);
+
+	log!("Shrink");
+	let res = unsafe { libc::mremap(new as _, 4 * PAGE_SIZE, PAGE_SIZE, 0) };
+	test_assert_eq!(res, new as *mut c_void);
+	test_assert_eq!(
+		mincore(unsafe { new.add(PAGE_SIZE) }, 1)
+			.unwrap_err()
+			.raw_os_error(),
+		Some(libc::ENOMEM)
+	);
+
+	unsafe {
+		libc::munmap(new as _, PAGE_SIZE);
+		libc::munmap(block, PAGE_SIZE);
+	}
+	Ok(())
+}
+
+pub fn mlock() -> TestResult {
+	log!("Map pages");
+	let ptr = map(4)?;
+	test_assert_eq!(mincore(ptr, 4)?, [0, 0, 0, 0]);
+
+	log!("Lock pages");
+	let res = unsafe { libc::mlock(ptr.add(PAGE_SIZE) as _, 2 * PAGE_SIZE) };
+	test_assert_eq!(res, 0);
+	// Locked pages are populated
+	test_assert_eq!(mincore(ptr, 4)?, [0, 1, 1, 0]);
+
+	log!("Unlock pages");
+	let res = unsafe { libc::munlock(ptr as _, 4 * PAGE_SIZE) };
+	test_assert_eq!(res, 0);
+
+	log!("Lock all");
+	let res = unsafe { libc::mlockall(MCL_CURRENT) };
+	test_assert_eq!(res, 0);
+	test_assert_eq!(mincore(ptr, 4)?, [1, 1, 1, 1]);
+	let res = unsafe { libc::munlockall() };
+	test_assert_eq!(res, 0);
+
+	log!("Invalid flags");
+	let res = unsafe { libc::mlockall(0) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::EINVAL)
+	);
+
+	unsafe {
+		libc::munmap(ptr as _, 4 * PAGE_SIZE);
+	}
+	Ok(())
+}
@@ -0,0 +1,165 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Network testing, over the loopback interface.
+
+use crate::{
+	log, test_assert, test_assert_eq,
+	util::{TestResult, signal},
+};
+use libc::{SIG_IGN, SIGPIPE};
+use std::{
+	ffi::c_int,
+	io,
+	io::{Read, Write},
+	net::{Shutdown, TcpListener, TcpStream, UdpSocket},
+	os::fd::AsRawFd,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+	thread,
+	time::Duration,
+};
+
+pub fn udp() -> TestResult {
+	log!("Bind sockets");
+	let a = UdpSocket::bind("127.0.0.1:0")?;
+	let b = UdpSocket::bind("127.0.0.1:0")?;
+
+	log!("Send datagrams");
+	a.send_to(b"hello", b.local_addr()?)?;
+	a.send_to(b"world", b.local_addr()?)?;
+
+	log!("Receive datagrams");
+	let mut buf = [0u8; 16];
+	let (len, src) = b.recv_from(&mut buf)?;
+	test_assert_eq!(&buf[..len], b"hello");
+	test_assert_eq!(src, a.local_addr()?);
+	let (len, _) = b.recv_from(&mut buf)?;
+	test_assert_eq!(&buf[..len], b"world");
+
+	Ok(())
+}
+
+pub fn tcp() -> TestResult {
+	log!("Listen");
+	let listener = TcpListener::bind("127.0.0.1:0")?;
+
+	log!("Connect");
+	let mut client = TcpStream::connect(listener.local_addr()?)?;
+	let (mut server, peer) = listener.accept()?;
+	test_assert_eq!(peer, client.local_addr()?);
+
+	log!("Transfer data");
+	let data: Vec<u8> = (0..100000).map(|i| i as u8).collect();
+	client.write_all(&data)?;
+	client.shutdown(Shutdown::Write)?;
+	let mut received = Vec::new();
+	server.read_to_end(&mut received)?;
+	test_assert_eq!(received, data);
+
+	log!("Reply");
+	server.write_all(b"bye")?;
+	drop(server);
+	let mut buf = Vec::new();
+	client.read_to_end(&mut buf)?;
+	test_assert_eq!(buf, b"bye");
+
+	Ok(())
+}
+
+static SIGPIPE_HIT: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn sigpipe_handler(_: c_int) {
+	SIGPIPE_HIT.store(true, Release);
+}
+
+pub fn nosignal() -> TestResult {
+	let listener = TcpListener::bind("127.0.0.1:0")?;
+	let client = TcpStream::connect(listener.local_addr()?)?;
+	client.shutdown(Shutdown::Write)?;
+	signal(SIGPIPE, sigpipe_handler as usize)?;
+	let send = |flags| {
+		let res = unsafe { libc::send(client.as_raw_fd(), b"x".as_ptr() as _, 1, flags) };
+		test_assert_eq!(res, -1);
+		test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPIPE));
+		Ok(())
+	};
+
+	log!("Send with MSG_NOSIGNAL");
+	send(libc::MSG_NOSIGNAL)?;
+	test_assert!(!SIGPIPE_HIT.load(Acquire));
+
+	log!("Send without MSG_NOSIGNAL");
+	send(0)?;
+	test_assert!(SIGPIPE_HIT.load(Acquire));
+
+	log!("Cleanup");
+	SIGPIPE_HIT.store(false, Release);
+	signal(SIGPIPE, SIG_IGN)?;
+	Ok(())
+}
+
+pub fn sockopt() -> TestResult {
+	let listener = TcpListener::bind("127.0.0.1:0")?;
+	let client = TcpStream::connect(listener.local_addr()?)?;
+
+	log!("TCP_NODELAY");
+	test_assert_eq!(client.nodelay()?, false);
+	client.set_nodelay(true)?;
+	test_assert_eq!(client.nodelay()?, true);
+
+	log!("SO_ERROR");
+	test_assert_eq!(client.take_error()?.map(|e| e.kind()), None);
+
+	log!("Refused datagram");
+	let sock = UdpSocket::bind("127.0.0.1:0")?;
+	let addr = UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+	sock.connect(addr)?;
+	sock.set_read_timeout(Some(Duration::from_secs(1)))?;
+	sock.send(b"hello")?;
+	let mut buf = [0u8; 16];
+	let err = sock.recv(&mut buf).unwrap_err();
+	test_assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+	// The error has been reported by the previous call
+	sock.send(b"hello")?;
+	let mut err = None;
+	for _ in 0..100 {
+		err = sock.take_error()?;
+		if err.is_some() {
+			break;
+		}
+		thread::sleep(Duration::from_millis(10));
+	}
+	test_assert_eq!(
+		err.map(|e| e.kind()),
+		Some(io::ErrorKind::ConnectionRefused)
+	);
+	test_assert_eq!(sock.take_error()?.map(|e| e.kind()), None);
+
+	log!("SO_RCVTIMEO");
+	let timeout = Duration::from_millis(100);
+	let sock = UdpSocket::bind("127.0.0.1:0")?;
+	sock.set_read_timeout(Some(timeout))?;
+	test_assert_eq!(sock.read_timeout()?, Some(timeout));
+	let mut buf = [0u8; 16];
+	let err = sock.recv_from(&mut buf).unwrap_err();
+	test_assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+	Ok(())
+}
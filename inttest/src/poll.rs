@@ -0,0 +1,351 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `poll`, `select`, `epoll` and pollable file descriptors testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{
+	EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD, EPOLLET, EPOLLIN, EPOLLONESHOT, POLLHUP, POLLIN,
+	POLLOUT, c_int, epoll_event, pollfd,
+};
+use std::{io, mem, ptr::null_mut, time::Instant};
+
+fn pipe() -> io::Result<[c_int; 2]> {
+	let mut fds = [0; 2];
+	let res = unsafe { libc::pipe(fds.as_mut_ptr()) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(fds)
+}
+
+fn poll(fds: &mut [pollfd], timeout: c_int) -> io::Result<usize> {
+	let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(res as _)
+}
+
+pub fn poll_pipe() -> TestResult {
+	let [rd, wr] = pipe()?;
+	let mut fds = [
+		pollfd {
+			fd: rd,
+			events: POLLIN,
+			revents: 0,
+		},
+		pollfd {
+			fd: wr,
+			events: POLLOUT,
+			revents: 0,
+		},
+	];
+
+	log!("Poll empty pipe");
+	test_assert_eq!(poll(&mut fds[..1], 0)?, 0);
+	let start = Instant::now();
+	test_assert_eq!(poll(&mut fds[..1], 100)?, 0);
+	test_assert!(start.elapsed().as_millis() >= 100);
+	test_assert_eq!(poll(&mut fds, -1)?, 1);
+	test_assert_eq!(fds[0].revents, 0);
+	test_assert_eq!(fds[1].revents, POLLOUT);
+
+	log!("Wake up on write from child process");
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if pid == 0 {
+		unsafe {
+			libc::usleep(100_000);
+			libc::write(wr, b"a".as_ptr() as _, 1);
+			libc::_exit(0);
+		}
+	}
+	test_assert_eq!(poll(&mut fds[..1], -1)?, 1);
+	test_assert_eq!(fds[0].revents, POLLIN);
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	test_assert_eq!(res, pid);
+
+	log!("Hang up");
+	unsafe {
+		libc::close(wr);
+	}
+	test_assert_eq!(poll(&mut fds[..1], -1)?, 1);
+	test_assert_eq!(fds[0].revents, POLLIN | POLLHUP);
+
+	unsafe {
+		libc::close(rd);
+	}
+	Ok(())
+}
+
+pub fn select_pipe() -> TestResult {
+	let [rd, wr] = pipe()?;
+	let select = |timeout: Option<libc::timeval>| {
+		let mut readfds: libc::fd_set = unsafe { mem::zeroed() };
+		unsafe {
+			libc::FD_SET(rd, &mut readfds);
+		}
+		let mut timeout = timeout;
+		let timeout_ptr = timeout.as_mut().map(|t| t as *mut _).unwrap_or(null_mut());
+		let res =
+			unsafe { libc::select(rd + 1, &mut readfds, null_mut(), null_mut(), timeout_ptr) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok((res, unsafe { libc::FD_ISSET(rd, &readfds) }))
+	};
+
+	log!("Select empty pipe");
+	let timeout = libc::timeval {
+		tv_sec: 0,
+		tv_usec: 100_000,
+	};
+	let start = Instant::now();
+	test_assert_eq!(select(Some(timeout))?, (0, false));
+	test_assert!(start.elapsed().as_millis() >= 100);
+
+	log!("Select readable pipe");
+	unsafe {
+		libc::write(wr, b"a".as_ptr() as _, 1);
+	}
+	test_assert_eq!(select(None)?, (1, true));
+
+	unsafe {
+		libc::close(rd);
+		libc::close(wr);
+	}
+	Ok(())
+}
+
+fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, events: c_int) -> io::Result<()> {
+	let mut event = epoll_event {
+		events: events as _,
+		u64: fd as _,
+	};
+	let res = unsafe { libc::epoll_ctl(epfd, op, fd, &mut event) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn epoll_wait(epfd: c_int, timeout: c_int) -> io::Result<Vec<epoll_event>> {
+	let mut events = [epoll_event {
+		events: 0,
+		u64: 0,
+	}; 8];
+	let res = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), events.len() as _, timeout) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(events[..res as usize].to_vec())
+}
+
+pub fn epoll_pipe() -> TestResult {
+	let [rd, wr] = pipe()?;
+	let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+	if epfd < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let write = || unsafe {
+		libc::write(wr, b"a".as_ptr() as _, 1);
+	};
+
+	log!("Level-triggered");
+	epoll_ctl(epfd, EPOLL_CTL_ADD, rd, EPOLLIN)?;
+	test_assert_eq!(
+		epoll_ctl(epfd, EPOLL_CTL_ADD, rd, EPOLLIN)
+			.unwrap_err()
+			.raw_os_error(),
+		Some(libc::EEXIST)
+	);
+	let start = Instant::now();
+	test_assert!(epoll_wait(epfd, 100)?.is_empty());
+	test_assert!(start.elapsed().as_millis() >= 100);
+	write();
+	for _ in 0..2 {
+		let events = epoll_wait(epfd, -1)?;
+		test_assert_eq!(events.len(), 1);
+		test_assert_eq!({ events[0].events }, EPOLLIN as u32);
+		test_assert_eq!({ events[0].u64 }, rd as u64);
+	}
+
+	log!("Edge-triggered");
+	epoll_ctl(epfd, EPOLL_CTL_MOD, rd, EPOLLIN | EPOLLET)?;
+	test_assert_eq!(epoll_wait(epfd, -1)?.len(), 1);
+	test_assert!(epoll_wait(epfd, 0)?.is_empty());
+	write();
+	test_assert_eq!(epoll_wait(epfd, -1)?.len(), 1);
+
+	log!("One-shot");
+	epoll_ctl(epfd, EPOLL_CTL_MOD, rd, EPOLLIN | EPOLLONESHOT)?;
+	test_assert_eq!(epoll_wait(epfd, -1)?.len(), 1);
+	test_assert!(epoll_wait(epfd, 0)?.is_empty());
+	epoll_ctl(epfd, EPOLL_CTL_MOD, rd, EPOLLIN)?;
+	test_assert_eq!(epoll_wait(epfd, 0)?.len(), 1);
+
+	log!("Wake up on write from child process");
+	let mut buf = [0u8; 2];
+	unsafe {
+		libc::read(rd, buf.as_mut_ptr() as _, buf.len());
+	}
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if pid == 0 {
+		unsafe {
+			libc::usleep(100_000);
+			libc::write(wr, b"a".as_ptr() as _, 1);
+			libc::_exit(0);
+		}
+	}
+	test_assert_eq!(epoll_wait(epfd, -1)?.len(), 1);
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	test_assert_eq!(res, pid);
+
+	log!("Remove");
+	epoll_ctl(epfd, EPOLL_CTL_DEL, rd, 0)?;
+	test_assert!(epoll_wait(epfd, 0)?.is_empty());
+
+	unsafe {
+		libc::close(epfd);
+		libc::close(rd);
+		libc::close(wr);
+	}
+	Ok(())
+}
+
+pub fn eventfd() -> TestResult {
+	let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let read = || {
+		let mut val = 0u64;
+		let res = unsafe { libc::read(fd, &mut val as *mut _ as _, size_of::<u64>()) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(val)
+	};
+	let write = |val: u64| unsafe {
+		libc::write(fd, &val as *const _ as _, size_of::<u64>());
+	};
+	let mut fds = [pollfd {
+		fd,
+		events: POLLIN,
+		revents: 0,
+	}];
+
+	log!("Read empty counter");
+	test_assert_eq!(read().unwrap_err().raw_os_error(), Some(libc::EAGAIN));
+	test_assert_eq!(poll(&mut fds, 0)?, 0);
+
+	log!("Write and read");
+	write(2);
+	write(3);
+	test_assert_eq!(poll(&mut fds, 0)?, 1);
+	test_assert_eq!(read()?, 5);
+	test_assert_eq!(poll(&mut fds, 0)?, 0);
+
+	unsafe {
+		libc::close(fd);
+	}
+	Ok(())
+}
+
+pub fn timerfd() -> TestResult {
+	let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, 0) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let settime = |flags: c_int, value: libc::timespec| {
+		let new = libc::itimerspec {
+			it_interval: libc::timespec {
+				tv_sec: 0,
+				tv_nsec: 0,
+			},
+			it_value: value,
+		};
+		let res = unsafe { libc::timerfd_settime(fd, flags, &new, null_mut()) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	};
+	let read = || {
+		let mut val = 0u64;
+		let res = unsafe { libc::read(fd, &mut val as *mut _ as _, size_of::<u64>()) };
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(val)
+	};
+
+	log!("Relative timer");
+	let start = Instant::now();
+	settime(
+		0,
+		libc::timespec {
+			tv_sec: 0,
+			tv_nsec: 100_000_000,
+		},
+	)?;
+	test_assert_eq!(read()?, 1);
+	test_assert!(start.elapsed().as_millis() >= 100);
+
+	log!("Absolute timer");
+	let mut now: libc::timespec = unsafe { mem::zeroed() };
+	unsafe {
+		libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now);
+	}
+	let start = Instant::now();
+	settime(
+		libc::TFD_TIMER_ABSTIME,
+		libc::timespec {
+			tv_sec: now.tv_sec + 1,
+			tv_nsec: now.tv_nsec,
+		},
+	)?;
+	let mut fds = [pollfd {
+		fd,
+		events: POLLIN,
+		revents: 0,
+	}];
+	test_assert_eq!(poll(&mut fds, -1)?, 1);
+	test_assert!(start.elapsed().as_millis() >= 900);
+	test_assert_eq!(read()?, 1);
+
+	log!("Disarmed timer");
+	let mut cur: libc::itimerspec = unsafe { mem::zeroed() };
+	unsafe {
+		libc::timerfd_gettime(fd, &mut cur);
+	}
+	test_assert_eq!((cur.it_value.tv_sec, cur.it_value.tv_nsec), (0, 0));
+
+	unsafe {
+		libc::close(fd);
+	}
+	Ok(())
+}
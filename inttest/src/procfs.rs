@@ -19,9 +19,10 @@
 //! procfs filesystem testing.
 
 use crate::{
-	test_assert_eq,
-	util::{TestError, TestResult},
+	test_assert, test_assert_eq,
+	util::{TestError, TestResult, unprivileged},
 };
+use libc::{EACCES, EINVAL};
 use std::{collections::HashMap, env, env::current_dir, fs, os::unix::ffi::OsStrExt};
 
 pub fn cwd() -> TestResult {
@@ -69,3 +70,24 @@ pub fn environ() -> TestResult {
 	test_assert_eq!(args0, args1);
 	Ok(())
 }
+
+pub fn oom_score() -> TestResult {
+	let score: u32 = fs::read_to_string("/proc/self/oom_score")?.trim().parse()?;
+	test_assert!(score <= 2000);
+	// Adjust the score
+	fs::write("/proc/self/oom_score_adj", "500\n")?;
+	let adj = fs::read_to_string("/proc/self/oom_score_adj")?;
+	test_assert_eq!(adj, "500\n");
+	let new_score: u32 = fs::read_to_string("/proc/self/oom_score")?.trim().parse()?;
+	test_assert!(new_score >= 1500);
+	// Out of range
+	let res = fs::write("/proc/self/oom_score_adj", "1001");
+	test_assert_eq!(res.unwrap_err().raw_os_error(), Some(EINVAL));
+	// Lowering the adjustment requires privileges
+	let res = unprivileged(|| fs::write("/proc/self/oom_score_adj", "0"))?;
+	test_assert_eq!(res.unwrap_err().raw_os_error(), Some(EACCES));
+	fs::write("/proc/self/oom_score_adj", "0")?;
+	let adj = fs::read_to_string("/proc/self/oom_score_adj")?;
+	test_assert_eq!(adj, "0\n");
+	Ok(())
+}
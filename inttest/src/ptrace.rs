@@ -0,0 +1,102 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Process tracing testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{
+	PTRACE_CONT, PTRACE_PEEKDATA, PTRACE_POKEDATA, PTRACE_TRACEME, SIGSTOP, c_long, c_void, pid_t,
+};
+use std::{io, ptr::null_mut};
+
+/// `arch_prctl` code: get the FS segment base.
+#[cfg(target_arch = "x86_64")]
+const ARCH_GET_FS: libc::c_int = 0x1003;
+
+static mut VALUE: c_long = 42;
+
+fn waitpid(pid: pid_t) -> io::Result<i32> {
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	if res >= 0 {
+		Ok(status)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn traceme() -> TestResult {
+	log!("Fork tracee");
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	if pid == 0 {
+		unsafe {
+			libc::ptrace(
+				PTRACE_TRACEME,
+				0,
+				null_mut::<c_void>(),
+				null_mut::<c_void>(),
+			);
+			libc::raise(SIGSTOP);
+			let value = (&raw const VALUE).read_volatile();
+			libc::_exit(if value == 84 { 0 } else { 1 });
+		}
+	}
+
+	log!("Wait for the tracee to stop");
+	let status = waitpid(pid)?;
+	test_assert!(libc::WIFSTOPPED(status));
+	test_assert_eq!(libc::WSTOPSIG(status), SIGSTOP);
+
+	#[cfg(target_arch = "x86_64")]
+	{
+		log!("Read the tracee's FS base");
+		// The tracee inherits the thread pointer of the current thread
+		let mut fs_base = 0u64;
+		let res = unsafe { libc::syscall(libc::SYS_arch_prctl, ARCH_GET_FS, &mut fs_base) };
+		test_assert_eq!(res, 0);
+		let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+		let res =
+			unsafe { libc::ptrace(libc::PTRACE_GETREGS, pid, null_mut::<c_void>(), &mut regs) };
+		test_assert_eq!(res, 0);
+		test_assert_eq!(regs.fs_base, fs_base);
+		let off = std::mem::offset_of!(libc::user_regs_struct, fs_base);
+		let value = unsafe { libc::ptrace(libc::PTRACE_PEEKUSER, pid, off, null_mut::<c_void>()) };
+		test_assert_eq!(value as u64, fs_base);
+	}
+
+	log!("Read and write the tracee's memory");
+	let addr = &raw mut VALUE;
+	let value = unsafe { libc::ptrace(PTRACE_PEEKDATA, pid, addr, null_mut::<c_void>()) };
+	test_assert_eq!(value, 42);
+	let res = unsafe { libc::ptrace(PTRACE_POKEDATA, pid, addr, 84 as *mut c_void) };
+	test_assert_eq!(res, 0);
+
+	log!("Resume the tracee");
+	let res =
+		unsafe { libc::ptrace(PTRACE_CONT, pid, null_mut::<c_void>(), null_mut::<c_void>()) };
+	test_assert_eq!(res, 0);
+	let status = waitpid(pid)?;
+	test_assert!(libc::WIFEXITED(status));
+	test_assert_eq!(libc::WEXITSTATUS(status), 0);
+	test_assert_eq!(unsafe { (&raw const VALUE).read_volatile() }, 42);
+
+	Ok(())
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Scheduler testing.
+
+use crate::{log, test_assert, test_assert_eq, util, util::TestResult};
+use libc::{PRIO_PROCESS, SCHED_OTHER, SCHED_RR, c_int, c_long, sched_param, timespec};
+use std::{io, io::ErrorKind, mem::MaybeUninit};
+
+fn getpriority() -> io::Result<c_int> {
+	// `getpriority` may legitimately return `-1`, so `errno` has to be checked
+	unsafe {
+		*libc::__errno_location() = 0;
+	}
+	let res = unsafe { libc::getpriority(PRIO_PROCESS, 0) };
+	let err = io::Error::last_os_error();
+	if res == -1 && err.raw_os_error() != Some(0) {
+		Err(err)
+	} else {
+		Ok(res)
+	}
+}
+
+fn setpriority(prio: c_int) -> io::Result<()> {
+	let res = unsafe { libc::setpriority(PRIO_PROCESS, 0, prio) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+fn syscall_result(res: c_long) -> io::Result<c_long> {
+	if res >= 0 {
+		Ok(res)
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+// The libc wrappers for the following system calls are stubs on musl, hence the direct invocations
+
+fn sched_setscheduler(policy: c_int, prio: c_int) -> io::Result<()> {
+	let param = sched_param {
+		sched_priority: prio,
+	};
+	let res = unsafe { libc::syscall(libc::SYS_sched_setscheduler, 0, policy, &param) };
+	syscall_result(res)?;
+	Ok(())
+}
+
+fn sched_getscheduler() -> io::Result<c_int> {
+	let res = unsafe { libc::syscall(libc::SYS_sched_getscheduler, 0) };
+	Ok(syscall_result(res)? as _)
+}
+
+fn sched_getparam() -> io::Result<c_int> {
+	let mut param = MaybeUninit::<sched_param>::uninit();
+	let res = unsafe { libc::syscall(libc::SYS_sched_getparam, 0, param.as_mut_ptr()) };
+	syscall_result(res)?;
+	Ok(unsafe { param.assume_init() }.sched_priority)
+}
+
+pub fn priority() -> TestResult {
+	log!("Get priority");
+	test_assert_eq!(getpriority()?, 0);
+
+	log!("Lower priority");
+	setpriority(5)?;
+	test_assert_eq!(getpriority()?, 5);
+
+	log!("Raise priority without privilege");
+	let res = util::unprivileged(|| setpriority(0))?;
+	test_assert_eq!(res.map_err(|e| e.kind()), Err(ErrorKind::PermissionDenied));
+	test_assert_eq!(getpriority()?, 5);
+
+	log!("Restore priority");
+	setpriority(0)?;
+	test_assert_eq!(getpriority()?, 0);
+
+	Ok(())
+}
+
+pub fn policy() -> TestResult {
+	log!("Get priority range");
+	let min = unsafe { libc::sched_get_priority_min(SCHED_RR) };
+	let max = unsafe { libc::sched_get_priority_max(SCHED_RR) };
+	test_assert_eq!((min, max), (1, 99));
+	let min = unsafe { libc::sched_get_priority_min(SCHED_OTHER) };
+	let max = unsafe { libc::sched_get_priority_max(SCHED_OTHER) };
+	test_assert_eq!((min, max), (0, 0));
+
+	log!("Switch to round-robin");
+	test_assert_eq!(sched_getscheduler()?, SCHED_OTHER);
+	sched_setscheduler(SCHED_RR, 10)?;
+	test_assert_eq!(sched_getscheduler()?, SCHED_RR);
+	test_assert_eq!(sched_getparam()?, 10);
+
+	log!("Get round-robin interval");
+	let mut ts = MaybeUninit::<timespec>::uninit();
+	let res = unsafe { libc::syscall(libc::SYS_sched_rr_get_interval, 0, ts.as_mut_ptr()) };
+	syscall_result(res)?;
+	let ts = unsafe { ts.assume_init() };
+	test_assert!(ts.tv_sec > 0 || ts.tv_nsec > 0);
+
+	log!("Yield");
+	let res = unsafe { libc::sched_yield() };
+	test_assert_eq!(res, 0);
+
+	log!("Invalid priority");
+	let res = sched_setscheduler(SCHED_RR, 100);
+	test_assert_eq!(res.map_err(|e| e.kind()), Err(ErrorKind::InvalidInput));
+	test_assert_eq!(sched_getparam()?, 10);
+
+	log!("Restore policy");
+	sched_setscheduler(SCHED_OTHER, 0)?;
+	test_assert_eq!(sched_getscheduler()?, SCHED_OTHER);
+
+	Ok(())
+}
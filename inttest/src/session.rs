@@ -0,0 +1,73 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Sessions and process groups testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{ENOTTY, EPERM, STDIN_FILENO, TIOCGPGRP, pid_t};
+use std::io;
+
+/// Runs `f` in a child process, returning whether it succeeded.
+fn in_child(f: fn() -> bool) -> io::Result<bool> {
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	if pid == 0 {
+		let code = if f() { 0 } else { 1 };
+		unsafe { libc::_exit(code) };
+	}
+	let mut status = 0;
+	let res = unsafe { libc::waitpid(pid, &mut status, 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0)
+}
+
+fn errno() -> Option<i32> {
+	io::Error::last_os_error().raw_os_error()
+}
+
+pub fn setsid() -> TestResult {
+	let sid = unsafe { libc::getsid(0) };
+	log!("Check the session is inherited");
+	test_assert!(sid > 0);
+	test_assert!(in_child(|| unsafe {
+		libc::getsid(0) == libc::getsid(libc::getppid())
+	})?);
+
+	log!("Create a new session");
+	let res = in_child(|| unsafe {
+		let pid = libc::getpid();
+		if libc::setsid() != pid || libc::getsid(0) != pid || libc::getpgrp() != pid {
+			return false;
+		}
+		// A session leader cannot create another session
+		if libc::setsid() >= 0 || errno() != Some(EPERM) {
+			return false;
+		}
+		// The new session has no controlling terminal
+		let mut pgrp: pid_t = 0;
+		libc::ioctl(STDIN_FILENO, TIOCGPGRP, &mut pgrp) < 0 && errno() == Some(ENOTTY)
+	})?;
+	test_assert!(res);
+	test_assert_eq!(unsafe { libc::getsid(0) }, sid);
+
+	Ok(())
+}
@@ -19,14 +19,16 @@
 //! Signals testing.
 
 use crate::{
-	log,
+	log, test_assert, test_assert_eq,
 	util::{TestResult, kill, signal},
 };
-use libc::{SIG_DFL, SIGINT, getpid};
+use libc::{SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIGALRM, SIGINT, SIGPROF, SIGUSR1, getpid};
 use std::{
-	ffi::c_int,
+	ffi::{c_int, c_void},
+	io, mem,
+	ptr::null_mut,
 	sync::atomic::{
-		AtomicBool,
+		AtomicBool, AtomicI32, AtomicUsize,
 		Ordering::{Acquire, Release},
 	},
 };
@@ -54,3 +56,248 @@ pub fn handler() -> TestResult {
 
 	Ok(())
 }
+
+pub fn signalfd() -> TestResult {
+	log!("Block signal");
+	let mut mask: libc::sigset_t = unsafe { mem::zeroed() };
+	let mut old: libc::sigset_t = unsafe { mem::zeroed() };
+	unsafe {
+		libc::sigemptyset(&mut mask);
+		libc::sigaddset(&mut mask, SIGUSR1);
+		libc::sigprocmask(SIG_BLOCK, &mask, &mut old);
+	}
+	let fd = unsafe { libc::signalfd(-1, &mask, libc::SFD_NONBLOCK) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let read = || {
+		let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+		let res = unsafe {
+			libc::read(
+				fd,
+				&mut info as *mut _ as _,
+				size_of::<libc::signalfd_siginfo>(),
+			)
+		};
+		if res < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(info.ssi_signo)
+	};
+
+	log!("Read without pending signal");
+	test_assert_eq!(read().unwrap_err().raw_os_error(), Some(libc::EAGAIN));
+
+	log!("Accept signal");
+	unsafe {
+		kill(getpid(), SIGUSR1)?;
+	}
+	test_assert_eq!(read()?, SIGUSR1 as u32);
+	test_assert_eq!(read().unwrap_err().raw_os_error(), Some(libc::EAGAIN));
+
+	log!("Cleanup");
+	unsafe {
+		libc::close(fd);
+		libc::sigprocmask(SIG_SETMASK, &old, null_mut());
+	}
+	Ok(())
+}
+
+static INFO_CODE: AtomicI32 = AtomicI32::new(0);
+static INFO_PID: AtomicI32 = AtomicI32::new(0);
+static INFO_VALUE: AtomicUsize = AtomicUsize::new(0);
+static HANDLER_SP: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn siginfo_handler(_: c_int, info: *mut libc::siginfo_t, _: *mut c_void) {
+	let local = 0u8;
+	HANDLER_SP.store(&local as *const _ as usize, Release);
+	unsafe {
+		let info = &*info;
+		INFO_CODE.store(info.si_code, Release);
+		INFO_PID.store(info.si_pid(), Release);
+		INFO_VALUE.store(info.si_value().sival_ptr as usize, Release);
+	}
+}
+
+pub fn siginfo() -> TestResult {
+	log!("Set alternate stack");
+	let mut stack = vec![0u8; 65536];
+	let ss = libc::stack_t {
+		ss_sp: stack.as_mut_ptr() as _,
+		ss_flags: 0,
+		ss_size: stack.len(),
+	};
+	if unsafe { libc::sigaltstack(&ss, null_mut()) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+
+	log!("Register signal handler");
+	let sig = libc::SIGRTMIN();
+	let mut act: libc::sigaction = unsafe { mem::zeroed() };
+	act.sa_sigaction = siginfo_handler as usize;
+	act.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+	if unsafe { libc::sigaction(sig, &act, null_mut()) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+
+	log!("Queue signal with a value");
+	let pid = unsafe { getpid() };
+	// Offset of the union in `siginfo_t`
+	let base = if cfg!(target_pointer_width = "64") {
+		16
+	} else {
+		12
+	};
+	let mut info = [0u8; 128];
+	info[0..4].copy_from_slice(&sig.to_ne_bytes());
+	info[8..12].copy_from_slice(&libc::SI_QUEUE.to_ne_bytes());
+	info[base..(base + 4)].copy_from_slice(&pid.to_ne_bytes());
+	info[(base + 8)..(base + 8 + size_of::<usize>())].copy_from_slice(&42usize.to_ne_bytes());
+	let res = unsafe { libc::syscall(libc::SYS_rt_sigqueueinfo, pid, sig, info.as_ptr()) };
+	if res < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	test_assert_eq!(INFO_CODE.load(Acquire), libc::SI_QUEUE);
+	test_assert_eq!(INFO_PID.load(Acquire), pid);
+	test_assert_eq!(INFO_VALUE.load(Acquire), 42);
+	let sp = HANDLER_SP.load(Acquire) as *const u8;
+	test_assert!(stack.as_ptr_range().contains(&sp));
+
+	log!("Cleanup");
+	signal(sig, SIG_DFL)?;
+	let ss = libc::stack_t {
+		ss_sp: null_mut(),
+		ss_flags: libc::SS_DISABLE,
+		ss_size: 0,
+	};
+	if unsafe { libc::sigaltstack(&ss, null_mut()) } < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	Ok(())
+}
+
+pub fn sigwait() -> TestResult {
+	log!("Block signal");
+	let mut mask: libc::sigset_t = unsafe { mem::zeroed() };
+	let mut old: libc::sigset_t = unsafe { mem::zeroed() };
+	unsafe {
+		libc::sigemptyset(&mut mask);
+		libc::sigaddset(&mut mask, SIGUSR1);
+		libc::sigprocmask(SIG_BLOCK, &mask, &mut old);
+	}
+	let pending = || {
+		let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+		unsafe {
+			libc::sigpending(&mut set);
+			libc::sigismember(&set, SIGUSR1) == 1
+		}
+	};
+	let timeout = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+
+	log!("Wait without pending signal");
+	let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+	let res = unsafe { libc::sigtimedwait(&mask, &mut info, &timeout) };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::EAGAIN)
+	);
+
+	log!("Wait for pending signal");
+	unsafe {
+		kill(getpid(), SIGUSR1)?;
+	}
+	test_assert!(pending());
+	let res = unsafe { libc::sigtimedwait(&mask, &mut info, &timeout) };
+	test_assert_eq!(res, SIGUSR1);
+	test_assert_eq!(info.si_signo, SIGUSR1);
+	test_assert_eq!(info.si_code, libc::SI_USER);
+	test_assert!(!pending());
+
+	log!("Cleanup");
+	unsafe {
+		libc::sigprocmask(SIG_SETMASK, &old, null_mut());
+	}
+	Ok(())
+}
+
+pub fn alarm() -> TestResult {
+	log!("Register signal handler");
+	signal(SIGALRM, signal_handler as usize)?;
+
+	log!("Set alarm");
+	test_assert_eq!(unsafe { libc::alarm(1) }, 0);
+	// The remaining time is rounded to the nearest second
+	test_assert_eq!(unsafe { libc::alarm(1) }, 1);
+
+	log!("Wait for alarm");
+	let res = unsafe { libc::pause() };
+	test_assert_eq!(res, -1);
+	test_assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EINTR));
+	test_assert!(HIT.load(Acquire));
+	test_assert_eq!(unsafe { libc::alarm(0) }, 0);
+
+	log!("Cleanup");
+	HIT.store(false, Release);
+	signal(SIGALRM, SIG_DFL)?;
+	Ok(())
+}
+
+pub fn itimer() -> TestResult {
+	log!("Register signal handler");
+	signal(SIGPROF, signal_handler as usize)?;
+
+	log!("Set profiling timer");
+	let tv = |usec| libc::timeval {
+		tv_sec: 0,
+		tv_usec: usec,
+	};
+	let setitimer = |which: c_int, val: &libc::itimerval| unsafe {
+		libc::syscall(
+			libc::SYS_setitimer,
+			which,
+			val,
+			null_mut::<libc::itimerval>(),
+		)
+	};
+	let getitimer = |which: c_int| unsafe {
+		let mut cur: libc::itimerval = mem::zeroed();
+		libc::syscall(libc::SYS_getitimer, which, &mut cur);
+		cur
+	};
+	let val = libc::itimerval {
+		it_interval: tv(0),
+		it_value: tv(10000),
+	};
+	if setitimer(libc::ITIMER_PROF, &val) < 0 {
+		return Err(io::Error::last_os_error().into());
+	}
+	let cur = getitimer(libc::ITIMER_PROF);
+	test_assert!(cur.it_value.tv_sec == 0 && cur.it_value.tv_usec <= 10000);
+
+	log!("Consume CPU time");
+	// CPU time is charged when returning to userspace, so make system calls
+	while !HIT.load(Acquire) {
+		unsafe {
+			libc::syscall(libc::SYS_getpid);
+		}
+	}
+	let cur = getitimer(libc::ITIMER_PROF);
+	test_assert!(cur.it_value.tv_sec == 0 && cur.it_value.tv_usec == 0);
+
+	log!("Invalid timer");
+	let res = setitimer(3, &val);
+	test_assert_eq!(res, -1);
+	test_assert_eq!(
+		io::Error::last_os_error().raw_os_error(),
+		Some(libc::EINVAL)
+	);
+
+	log!("Cleanup");
+	HIT.store(false, Release);
+	signal(SIGPROF, SIG_DFL)?;
+	Ok(())
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Swap areas testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{EBUSY, EINVAL};
+use std::{ffi::CStr, fs, io};
+
+/// The path to the swap file.
+const PATH: &CStr = c"/tmp/swapfile";
+/// The size of a page in bytes.
+const PAGE_SIZE: usize = 4096;
+/// The number of pages of the swap file.
+const PAGES: usize = 16;
+
+fn swapon(path: &CStr) -> io::Result<()> {
+	let res = unsafe { libc::swapon(path.as_ptr(), 0) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn swapoff(path: &CStr) -> io::Result<()> {
+	let res = unsafe { libc::swapoff(path.as_ptr()) };
+	if res < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Tells whether the swap file is listed in `/proc/swaps`.
+fn is_listed() -> io::Result<bool> {
+	let swaps = fs::read_to_string("/proc/swaps")?;
+	let path = PATH.to_str().unwrap();
+	Ok(swaps
+		.lines()
+		.skip(1)
+		.any(|l| l.split_whitespace().next() == Some(path)))
+}
+
+pub fn swapon_swapoff() -> TestResult {
+	let path = PATH.to_str().unwrap();
+	log!("Create swap file without header");
+	let mut buf = vec![0u8; PAGES * PAGE_SIZE];
+	fs::write(path, &buf)?;
+	test_assert_eq!(swapon(PATH).unwrap_err().raw_os_error(), Some(EINVAL));
+
+	log!("Write swap header");
+	// Version
+	buf[1024..1028].copy_from_slice(&1u32.to_ne_bytes());
+	// Last page
+	buf[1028..1032].copy_from_slice(&(PAGES as u32 - 1).to_ne_bytes());
+	buf[PAGE_SIZE - 10..PAGE_SIZE].copy_from_slice(b"SWAPSPACE2");
+	fs::write(path, &buf)?;
+
+	log!("Activate swap file");
+	swapon(PATH)?;
+	test_assert!(is_listed()?);
+	test_assert_eq!(swapon(PATH).unwrap_err().raw_os_error(), Some(EBUSY));
+
+	log!("Deactivate swap file");
+	swapoff(PATH)?;
+	test_assert!(!is_listed()?);
+	test_assert_eq!(swapoff(PATH).unwrap_err().raw_os_error(), Some(EINVAL));
+
+	fs::remove_file(path)?;
+	Ok(())
+}
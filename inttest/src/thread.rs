@@ -0,0 +1,62 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Threads testing.
+
+use crate::{log, test_assert, test_assert_eq, util::TestResult};
+use libc::{ESRCH, c_int, pid_t};
+use std::{io, thread};
+
+fn gettid() -> pid_t {
+	unsafe { libc::syscall(libc::SYS_gettid) as _ }
+}
+
+fn tgkill(tgid: pid_t, tid: pid_t, sig: c_int) -> io::Result<()> {
+	let res = unsafe { libc::syscall(libc::SYS_tgkill, tgid, tid, sig) };
+	if res >= 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+pub fn spawn_join() -> TestResult {
+	let pid = unsafe { libc::getpid() };
+	log!("Check the main thread's IDs");
+	test_assert_eq!(gettid(), pid);
+
+	log!("Spawn thread");
+	let (thread_pid, thread_tid) = thread::spawn(|| (unsafe { libc::getpid() }, gettid()))
+		.join()
+		.unwrap();
+	test_assert_eq!(thread_pid, pid);
+	test_assert!(thread_tid != pid);
+	Ok(())
+}
+
+pub fn tgkill_thread() -> TestResult {
+	let pid = unsafe { libc::getpid() };
+	let tid = gettid();
+	log!("Probe the current thread");
+	tgkill(pid, tid, 0)?;
+
+	log!("Probe with the wrong thread group");
+	let res = tgkill(pid + 1, tid, 0);
+	test_assert_eq!(res.unwrap_err().raw_os_error(), Some(ESRCH));
+	Ok(())
+}
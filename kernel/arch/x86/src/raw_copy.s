@@ -23,6 +23,7 @@
 .section .text
 
 .global raw_copy
+.global raw_cmpxchg
 .global copy_fault
 
 // TODO can be optimized
@@ -41,6 +42,24 @@ raw_copy:
 	mov eax, 1
 	ret
 
+raw_cmpxchg:
+	push esi
+	push edi
+
+	mov edi, 12[esp]
+	mov eax, 16[esp]
+	mov esi, 20[esp]
+
+	lock cmpxchg [edi], esi
+
+	mov ecx, 24[esp]
+	mov [ecx], eax
+
+	pop edi
+	pop esi
+	mov eax, 1
+	ret
+
 copy_fault:
 	pop edi
 	pop esi
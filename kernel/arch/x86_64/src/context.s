@@ -97,6 +97,10 @@ IRQ 12
 IRQ 13
 IRQ 14
 IRQ 15
+# Local APIC vectors
+IRQ 16
+IRQ 17
+IRQ 31
 
 .macro STORE_REGS
     push fs
@@ -149,12 +153,22 @@ IRQ 15
 .type idle_task, @function
 
 int_common:
+	# If coming from userspace, switch to the kernel's GS base (`cs` is after the ID, code and `rip`)
+	test qword ptr [rsp + 24], 3
+	jz 2f
+	swapgs
+2:
 STORE_REGS
 	cld
 	mov rdi, rsp
 	call interrupt_handler
 LOAD_REGS
 	add rsp, 16
+	# If returning to userspace, restore the user's GS base
+	test qword ptr [rsp + 8], 3
+	jz 2f
+	swapgs
+2:
 	iretq
 
 init_ctx:
@@ -188,8 +202,6 @@ syscall:
     mov [gs:0x8], rsp
     mov rsp, [gs:0x0]
 
-    sti
-
     # Push artificial iret frame
     push 0x23
     push [gs:0x8]
@@ -197,6 +209,9 @@ syscall:
     push 0x2b
     push rcx
 
+    # Enable interrupts only once the user stack is saved, since `gs:0x8` is shared by the core
+    sti
+
     push 0 # code (absent)
     push 0 # interrupt ID (absent)
 
@@ -208,15 +223,22 @@ STORE_REGS
     # Cleanup
 LOAD_REGS
 	cli
+	# `sysretq` returns to `rcx` with the flags in `r11`, which are clobbered by `syscall` anyway.
+	# Take them from the frame since the kernel may have changed them (signals, tracing)
+	mov rcx, [rsp + 0x10]
+	mov r11, [rsp + 0x20]
 	mov rsp, [rsp + 0x28]
 	swapgs
     sysretq
 
 idle_task:
-    # Lazy cleanup
-    xor ax, ax
-    mov fs, ax
-    mov gs, ax
+    # Lazy cleanup, preserving the GS base which points to the core-local structure
+    mov ecx, 0xc0000101
+    rdmsr
+    xor bx, bx
+    mov fs, bx
+    mov gs, bx
+    wrmsr
 0:
     sti
     hlt
@@ -23,6 +23,7 @@
 .section .text
 
 .global raw_copy
+.global raw_cmpxchg
 .global copy_fault
 
 // TODO can be optimized
@@ -32,6 +33,13 @@ raw_copy:
 	mov rax, 1
 	ret
 
+raw_cmpxchg:
+	mov eax, esi
+	lock cmpxchg [rdi], edx
+	mov [rcx], eax
+	mov rax, 1
+	ret
+
 copy_fault:
 	xor rax, rax
 	ret
@@ -18,22 +18,30 @@
 
 //! This module handles ACPI's Fixed ACPI Description Table (FADT).
 
-use super::{Table, TableHdr, dsdt::Dsdt};
-use core::{ptr, slice};
+use super::{Table, TableHdr, dsdt::Dsdt, map_table};
+use crate::memory::PhysAddr;
+use core::{mem::offset_of, slice};
+use utils::errno::AllocResult;
 
-/// TODO doc
+/// A Generic Address Structure, describing the location of a register.
+#[repr(C, packed)]
 pub struct GenericAddr {
-	addr_space: u8,
+	/// The address space of the register.
+	pub addr_space: u8,
 	bit_width: u8,
 	bit_offset: u8,
 	access_size: u8,
-	address: u8,
+	/// The address of the register in its address space.
+	pub address: u64,
 }
 
+/// Address space: system memory
+pub const ADDR_SPACE_MEMORY: u8 = 0;
+
 /// The Fixed ACPI Description Table.
 ///
 /// The documentation of every field can be found in the ACPI documentation.
-#[repr(C)]
+#[repr(C, packed)]
 pub struct Fadt {
 	/// The table's header.
 	pub header: TableHdr,
@@ -101,25 +109,28 @@ pub struct Fadt {
 
 impl Fadt {
 	/// Returns a reference to the DSDT if it exists.
-	pub fn get_dsdt(&self) -> Option<&Dsdt> {
-		let dsdt = if self.x_dsdt != 0 {
+	pub fn get_dsdt(&self) -> AllocResult<Option<&Dsdt>> {
+		// The extended fields are absent from the first revision of the table
+		let length = self.header.length as usize;
+		let x_dsdt = if length >= offset_of!(Self, x_dsdt) + size_of::<u64>() {
 			self.x_dsdt
 		} else {
-			self.dsdt as _
+			0
 		};
-		let dsdt: *const TableHdr = ptr::with_exposed_provenance(dsdt as _);
-		if !dsdt.is_null() {
+		let dsdt = if x_dsdt != 0 { x_dsdt } else { self.dsdt as _ };
+		if dsdt != 0 {
 			let dsdt = unsafe {
-				let len = (*dsdt).length as usize;
-				let dsdt_slice = slice::from_raw_parts(dsdt as *const u8, len);
+				let dsdt = map_table(PhysAddr(dsdt as _))?;
+				let len = dsdt.length as usize;
+				let dsdt_slice = slice::from_raw_parts(dsdt as *const _ as *const u8, len);
 				&*(dsdt_slice as *const [_] as *const [()] as *const Dsdt)
 			};
 			if !dsdt.hdr().check::<Dsdt>() {
 				panic!("Invalid ACPI structure!");
 			}
-			Some(dsdt)
+			Ok(Some(dsdt))
 		} else {
-			None
+			Ok(None)
 		}
 	}
 }
@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ACPI's High Precision Event Timer table handling.
+
+use super::{
+	Table, TableHdr,
+	fadt::{ADDR_SPACE_MEMORY, GenericAddr},
+};
+use crate::memory::PhysAddr;
+
+/// The HPET Description Table.
+#[repr(C, packed)]
+pub struct Hpet {
+	/// The table's header.
+	pub header: TableHdr,
+
+	/// The hardware ID of the event timer block.
+	pub event_timer_block_id: u32,
+	/// The location of the timer's registers.
+	pub base_address: GenericAddr,
+	/// The sequence number of the timer.
+	pub hpet_number: u8,
+	/// The minimum clock tick in periodic mode without losing interrupts.
+	pub minimum_tick: u16,
+	/// Page protection and OEM attributes.
+	pub page_protection: u8,
+}
+
+impl Hpet {
+	/// Returns the physical address of the timer's registers.
+	///
+	/// If the registers are not located in system memory, the function returns `None`.
+	pub fn registers(&self) -> Option<PhysAddr> {
+		let base = &self.base_address;
+		(base.addr_space == ADDR_SPACE_MEMORY).then_some(PhysAddr(base.address as _))
+	}
+}
+
+impl Table for Hpet {
+	const SIGNATURE: &'static [u8; 4] = b"HPET";
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Storage for a table, aligned like its header.
+	#[repr(C, align(8))]
+	struct Buf([u8; size_of::<Hpet>()]);
+
+	/// Builds the table of an HPET whose registers are at `address` in `addr_space`, with a valid
+	/// checksum.
+	fn build(addr_space: u8, address: u64) -> Buf {
+		let mut buf = Buf([0; size_of::<Hpet>()]);
+		let b = &mut buf.0;
+		b[..4].copy_from_slice(b"HPET");
+		b[4..8].copy_from_slice(&(size_of::<Hpet>() as u32).to_le_bytes());
+		b[8] = 1;
+		b[36..40].copy_from_slice(&0x8086a201u32.to_le_bytes());
+		b[40] = addr_space;
+		b[41] = 64;
+		b[44..52].copy_from_slice(&address.to_le_bytes());
+		b[52] = 0;
+		b[53..55].copy_from_slice(&0x80u16.to_le_bytes());
+		let sum = b.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+		b[9] = 0u8.wrapping_sub(sum);
+		buf
+	}
+
+	/// Interprets `buf` as an HPET table.
+	fn parse(buf: &Buf) -> &Hpet {
+		unsafe { &*(buf.0.as_ptr() as *const Hpet) }
+	}
+
+	#[test_case]
+	fn hpet_table() {
+		assert_eq!(size_of::<Hpet>(), 56);
+		let buf = build(ADDR_SPACE_MEMORY, 0xfed00000);
+		let hpet = parse(&buf);
+		assert!(hpet.hdr().check::<Hpet>());
+		assert_eq!({ hpet.event_timer_block_id }, 0x8086a201);
+		assert_eq!(hpet.hpet_number, 0);
+		assert_eq!({ hpet.minimum_tick }, 0x80);
+		assert_eq!(hpet.registers(), Some(PhysAddr(0xfed00000)));
+		// Registers in I/O space
+		let buf = build(1, 0xfed00000);
+		let hpet = parse(&buf);
+		assert!(hpet.hdr().check::<Hpet>());
+		assert_eq!(hpet.registers(), None);
+	}
+
+	#[test_case]
+	fn hpet_table_invalid() {
+		// Bad checksum
+		let mut buf = build(ADDR_SPACE_MEMORY, 0xfed00000);
+		buf.0[52] ^= 1;
+		assert!(!parse(&buf).hdr().check::<Hpet>());
+		// Bad signature
+		let mut buf = build(ADDR_SPACE_MEMORY, 0xfed00000);
+		buf.0[..4].copy_from_slice(b"HPEU");
+		buf.0[9] = buf.0[9].wrapping_sub(1);
+		assert!(!parse(&buf).hdr().check::<Hpet>());
+		// Truncated
+		let mut buf = build(ADDR_SPACE_MEMORY, 0xfed00000);
+		buf.0[4..8].copy_from_slice(&16u32.to_le_bytes());
+		assert!(!parse(&buf).hdr().check::<Hpet>());
+	}
+}
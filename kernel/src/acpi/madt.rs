@@ -19,7 +19,7 @@
 //! ACPI's Multiple APIC Description Table (MADT) handling.
 
 use super::{Table, TableHdr};
-use core::{ffi::c_void, hint::likely};
+use core::hint::likely;
 
 /// The offset of the entries in the MADT.
 const ENTRIES_OFF: usize = 0x2c;
@@ -56,6 +56,9 @@ impl Table for Madt {
 	const SIGNATURE: &'static [u8; 4] = b"APIC";
 }
 
+/// Local APIC flag: the processor is enabled.
+pub const LAPIC_ENABLED: u32 = 0b1;
+
 /// Represents an MADT entry header.
 #[repr(C)]
 #[derive(Debug)]
@@ -66,6 +69,80 @@ pub struct EntryHeader {
 	pub length: u8,
 }
 
+impl EntryHeader {
+	/// Returns the entry as the structure `E`, if it has the corresponding type.
+	pub fn as_entry<E: Entry>(&self) -> Option<&E> {
+		if self.entry_type != E::TYPE || (self.length as usize) < size_of::<E>() {
+			return None;
+		}
+		Some(unsafe { &*(self as *const _ as *const E) })
+	}
+}
+
+/// Trait representing an entry of the MADT.
+pub trait Entry {
+	/// The type of the entry.
+	const TYPE: u8;
+}
+
+/// Entry describing a processor and its Local APIC.
+#[repr(C, packed)]
+pub struct LocalApic {
+	/// The entry's header.
+	pub header: EntryHeader,
+
+	/// The ACPI ID of the processor.
+	pub processor_id: u8,
+	/// The ID of the processor's Local APIC.
+	pub apic_id: u8,
+	/// Processor flags.
+	pub flags: u32,
+}
+
+impl Entry for LocalApic {
+	const TYPE: u8 = 0;
+}
+
+/// Entry describing an I/O APIC.
+#[repr(C, packed)]
+pub struct IoApic {
+	/// The entry's header.
+	pub header: EntryHeader,
+
+	/// The ID of the I/O APIC.
+	pub id: u8,
+	/// Reserved.
+	reserved: u8,
+	/// The physical address of the I/O APIC's registers.
+	pub addr: u32,
+	/// The first Global System Interrupt handled by the I/O APIC.
+	pub gsi_base: u32,
+}
+
+impl Entry for IoApic {
+	const TYPE: u8 = 1;
+}
+
+/// Entry describing how an ISA interrupt is mapped to a Global System Interrupt.
+#[repr(C, packed)]
+pub struct InterruptSourceOverride {
+	/// The entry's header.
+	pub header: EntryHeader,
+
+	/// The bus. Always `0` (ISA).
+	pub bus: u8,
+	/// The ISA interrupt.
+	pub source: u8,
+	/// The Global System Interrupt the ISA interrupt is connected to.
+	pub gsi: u32,
+	/// Polarity and trigger mode flags.
+	pub flags: u16,
+}
+
+impl Entry for InterruptSourceOverride {
+	const TYPE: u8 = 2;
+}
+
 /// Iterator over MADT entries.
 pub struct EntriesIterator<'m> {
 	madt: &'m Madt,
@@ -78,12 +155,16 @@ impl<'m> Iterator for EntriesIterator<'m> {
 
 	fn next(&mut self) -> Option<Self::Item> {
 		let entries_len = self.madt.header.length as usize - ENTRIES_OFF;
-		if likely(self.cursor < entries_len) {
+		if likely(self.cursor + size_of::<EntryHeader>() <= entries_len) {
 			let entry = unsafe {
-				let ptr = (self as *const _ as *const c_void).add(ENTRIES_OFF + self.cursor)
+				let ptr = (self.madt as *const Madt as *const u8).add(ENTRIES_OFF + self.cursor)
 					as *const EntryHeader;
 				&*ptr
 			};
+			// Avoid looping forever on an invalid entry
+			if entry.length == 0 {
+				return None;
+			}
 			self.cursor += entry.length as usize;
 			Some(entry)
 		} else {
@@ -22,22 +22,39 @@
 //! ACPI initialization is done through the following phases:
 //! - Read the `RSDP` table in order to get a pointer to the `RSDT`, referring to every other
 //!   available tables.
+//! - Read the `MADT` to register CPU cores and I/O APICs.
+//! - Read the `HPET` table to register the High Precision Event Timer.
 //! - TODO
 
-use crate::{acpi::rsdt::Rsdt, memory};
+use crate::{
+	acpi::rsdt::Rsdt,
+	arch::x86::{
+		ioapic,
+		paging::{FLAG_GLOBAL, FLAG_WRITE},
+	},
+	memory::{PhysAddr, VirtAddr, mmio::MMIO, oom, vmem::KERNEL_VMEM},
+	process::scheduler::cpu,
+	sync::mutex::Mutex,
+	time,
+};
 use core::{
 	hint::{likely, unlikely},
+	mem,
 	mem::{align_of, size_of},
-	ptr, slice,
+	num::NonZeroUsize,
+	slice,
 	sync::{atomic, atomic::AtomicBool},
 };
 use dsdt::Dsdt;
 use fadt::Fadt;
-use madt::Madt;
+use hpet::Hpet;
+use madt::{InterruptSourceOverride, IoApic, LocalApic, Madt};
+use utils::{collections::vec::Vec, errno::AllocResult, limits::PAGE_SIZE};
 
 mod aml;
 mod dsdt;
 mod fadt;
+mod hpet;
 mod madt;
 mod rsdt;
 
@@ -95,8 +112,9 @@ impl Rsdp {
 	/// # Safety
 	///
 	/// This function is safe only if [`check`] returns `true`.
-	pub unsafe fn get_rsdt(&self) -> &Rsdt {
-		&*ptr::with_exposed_provenance(self.rsdt_address as _)
+	pub unsafe fn get_rsdt(&self) -> AllocResult<&'static Rsdt> {
+		let hdr = map_table(PhysAddr(self.rsdt_address as _))?;
+		Ok(&*(hdr as *const _ as *const Rsdt))
 	}
 }
 
@@ -168,10 +186,63 @@ pub trait Table {
 	}
 }
 
+/// Mappings of the memory holding ACPI tables outside of the kernel's direct mapping.
+///
+/// Each entry contains the physical address of the first page, the number of pages and the
+/// virtual address of the mapping.
+static MAPPINGS: Mutex<Vec<(PhysAddr, usize, VirtAddr)>> = Mutex::new(Vec::new());
+
+/// Ensures the `len` bytes of physical memory at `addr` are accessible in kernelspace, and returns
+/// the corresponding virtual address.
+///
+/// ACPI tables may be located above the memory mapped by the kernel at boot, in which case they
+/// are mapped like MMIO. Since tables remain in use, such mappings are never released.
+fn map_phys(addr: PhysAddr, len: usize) -> AllocResult<VirtAddr> {
+	let begin = addr.down_align_to(PAGE_SIZE);
+	let off = addr.0 - begin.0;
+	let pages = (off + len).div_ceil(PAGE_SIZE).max(1);
+	let last = begin + (pages - 1) * PAGE_SIZE;
+	if let (Some(virt), Some(_)) = (addr.kernel_to_virtual(), last.kernel_to_virtual()) {
+		let mut vmem = KERNEL_VMEM.lock();
+		for i in 0..pages {
+			let phys = begin + i * PAGE_SIZE;
+			let virt = phys.kernel_to_virtual().unwrap();
+			if vmem.translate(virt).is_none() {
+				vmem.map(phys, virt, FLAG_WRITE | FLAG_GLOBAL);
+			}
+		}
+		return Ok(virt);
+	}
+	let mut mappings = MAPPINGS.lock();
+	// Reuse an existing mapping if it covers the whole range
+	let end = begin.0 + pages * PAGE_SIZE;
+	let mapping = mappings
+		.iter()
+		.find(|(phys, pages, _)| phys.0 <= begin.0 && end <= phys.0 + pages * PAGE_SIZE);
+	if let Some((phys, _, virt)) = mapping {
+		return Ok(*virt + (addr.0 - phys.0));
+	}
+	let mmio = MMIO::new(begin, NonZeroUsize::new(pages).unwrap(), true)?;
+	let virt = VirtAddr::from(mmio.as_ptr());
+	mem::forget(mmio);
+	mappings.push((begin, pages, virt))?;
+	Ok(virt + off)
+}
+
+/// Maps the ACPI table at the physical address `addr` and returns its header.
+fn map_table(addr: PhysAddr) -> AllocResult<&'static TableHdr> {
+	let hdr = map_phys(addr, size_of::<TableHdr>())?.as_ptr::<TableHdr>();
+	unsafe {
+		let len = (*hdr).length as usize;
+		let hdr = map_phys(addr, len.max(size_of::<TableHdr>()))?.as_ptr::<TableHdr>();
+		Ok(&*hdr)
+	}
+}
+
 /// Finds the [`Rsdp`] and returns a reference to it.
 unsafe fn find_rsdp() -> Option<&'static Rsdp> {
-	let begin = (memory::PROCESS_END + 0xe0000).as_ptr();
-	let end = (memory::PROCESS_END + 0xfffff).as_ptr();
+	let begin = PhysAddr(0xe0000).kernel_to_virtual()?.as_ptr::<u8>();
+	let end = PhysAddr(0xfffff).kernel_to_virtual()?.as_ptr::<u8>();
 	let mut ptr = begin;
 	while ptr < end {
 		let signature_slice = slice::from_raw_parts::<u8>(ptr, RSDP_SIGNATURE.len());
@@ -194,38 +265,54 @@ pub fn is_century_register_present() -> bool {
 /// Initializes ACPI.
 ///
 /// This function must be called only once, at boot.
-pub(crate) fn init() {
+///
+/// If the tables cannot be mapped, the function returns an error before registering anything,
+/// so that the system keeps running on a single core with the legacy PIC.
+pub(crate) fn init() -> AllocResult<()> {
 	let rsdp = unsafe { find_rsdp() };
 	let Some(rsdp) = rsdp else {
-		return;
+		return Ok(());
 	};
 	if unlikely(!rsdp.check()) {
 		panic!("ACPI: invalid RSDP checksum");
 	}
-	// Safe because `check` returned `true`
-	let rsdt = unsafe { rsdp.get_rsdt() };
+	// Map every needed table first. Safe because `check` returned `true`
+	let rsdt = unsafe { rsdp.get_rsdt()? };
+	let madt = rsdt.get_table::<Madt>()?;
+	let hpet = rsdt.get_table::<Hpet>()?;
+	let fadt = rsdt.get_table::<Fadt>()?;
+	let dsdt = match rsdt.get_table_unsized::<Dsdt>()? {
+		Some(dsdt) => Some(dsdt),
+		None => fadt.map(Fadt::get_dsdt).transpose()?.flatten(),
+	};
 	// Read MADT
-	if let Some(madt) = rsdt.get_table::<Madt>() {
-		// Register CPU cores
+	if let Some(madt) = madt {
 		for e in madt.entries() {
-			if e.entry_type == 0 {
-				// TODO Register a new CPU
+			if let Some(lapic) = e.as_entry::<LocalApic>() {
+				// Register the CPU core if it can be used
+				if lapic.flags & madt::LAPIC_ENABLED != 0 {
+					cpu::register(lapic.apic_id as _);
+				}
+			} else if let Some(io) = e.as_entry::<IoApic>() {
+				let addr = PhysAddr(io.addr as _);
+				let gsi_base = io.gsi_base;
+				oom::wrap_nofail(|| ioapic::register(addr, gsi_base));
+			} else if let Some(ovr) = e.as_entry::<InterruptSourceOverride>() {
+				ioapic::set_override(ovr.source, ovr.gsi, ovr.flags);
 			}
 		}
 	}
+	// Read HPET
+	if let Some(addr) = hpet.and_then(Hpet::registers) {
+		oom::wrap_nofail(|| time::hw::hpet::register(addr));
+	}
 	// Read FADT
-	let fadt = rsdt.get_table::<Fadt>();
 	if let Some(fadt) = fadt {
 		CENTURY_REGISTER.store(fadt.century != 0, atomic::Ordering::Relaxed);
 	}
-	// Get the DSDT
-	let dsdt = rsdt
-		.get_table_unsized::<Dsdt>()
-		.or_else(|| fadt.and_then(Fadt::get_dsdt));
 	if let Some(dsdt) = dsdt {
-		// Parse AML code
-		let aml = dsdt.get_aml();
-		let _ast = aml::parse(aml);
-		// TODO
+		// TODO parse AML code
+		let _aml = dsdt.get_aml();
 	}
+	Ok(())
 }
@@ -18,8 +18,10 @@
 
 //! This module handles ACPI's Root System Description Table (RSDT).
 
-use super::{Table, TableHdr};
+use super::{Table, TableHdr, map_table};
+use crate::memory::PhysAddr;
 use core::{mem::size_of, ptr, ptr::Pointee, slice};
+use utils::errno::AllocResult;
 
 /// The Root System Description Table.
 #[repr(C)]
@@ -33,28 +35,46 @@ pub struct Rsdt {
 
 impl Rsdt {
 	/// Iterates over every ACPI tables.
-	pub fn tables(&self) -> impl Iterator<Item = &TableHdr> {
+	///
+	/// If a table cannot be mapped, the iterator yields an error.
+	pub fn tables(&self) -> impl Iterator<Item = AllocResult<&'static TableHdr>> {
 		let entries_len = self.header.length as usize - size_of::<Rsdt>();
 		let entries_count = entries_len / size_of::<u32>();
 		unsafe {
 			let entries_start = (self as *const Self).add(1) as *const u32;
 			slice::from_raw_parts(entries_start, entries_count)
 				.iter()
-				.map(|p| &*ptr::with_exposed_provenance(*p as usize))
+				.map(|p| map_table(PhysAddr(*p as _)))
 		}
 	}
 
-	/// Returns a reference to the ACPI table with type `T`.
+	/// Returns the header of the ACPI table with the given signature.
 	///
 	/// If the table does not exist, the function returns `None`.
 	///
 	/// If the table is invalid, the function panics.
-	pub fn get_table<T: Table>(&self) -> Option<&T> {
-		let hdr = self.tables().find(|hdr| hdr.signature == *T::SIGNATURE)?;
-		if !hdr.check::<T>() {
-			panic!("APCI: invalid table for signature {:?}", hdr.signature)
+	fn find<T: Table + ?Sized>(&self) -> AllocResult<Option<&'static TableHdr>> {
+		for hdr in self.tables() {
+			let hdr = hdr?;
+			if hdr.signature != *T::SIGNATURE {
+				continue;
+			}
+			if !hdr.check::<T>() {
+				panic!("APCI: invalid table for signature {:?}", hdr.signature)
+			}
+			return Ok(Some(hdr));
 		}
-		Some(unsafe { &*(hdr as *const _ as *const T) })
+		Ok(None)
+	}
+
+	/// Returns a reference to the ACPI table with type `T`.
+	///
+	/// If the table does not exist, the function returns `None`.
+	///
+	/// If the table is invalid, the function panics.
+	pub fn get_table<T: Table>(&self) -> AllocResult<Option<&T>> {
+		let hdr = self.find::<T>()?;
+		Ok(hdr.map(|hdr| unsafe { &*(hdr as *const _ as *const T) }))
 	}
 
 	/// Returns a reference to the ACPI table with type `T`.
@@ -62,15 +82,14 @@ impl Rsdt {
 	/// The table must be `Unsized`.
 	///
 	/// If the table doesn't exist, the function returns `None`.
-	pub fn get_table_unsized<T: Table + ?Sized + Pointee<Metadata = usize>>(&self) -> Option<&T> {
-		let hdr = self.tables().find(|hdr| hdr.signature == *T::SIGNATURE)?;
-		if !hdr.check::<T>() {
-			panic!("APCI: invalid table for signature {:?}", hdr.signature)
-		}
-		Some(unsafe {
+	pub fn get_table_unsized<T: Table + ?Sized + Pointee<Metadata = usize>>(
+		&self,
+	) -> AllocResult<Option<&T>> {
+		let hdr = self.find::<T>()?;
+		Ok(hdr.map(|hdr| unsafe {
 			let ptr = ptr::from_raw_parts::<T>(hdr as *const _ as *const (), hdr.length as usize);
 			&*ptr
-		})
+		}))
 	}
 }
 
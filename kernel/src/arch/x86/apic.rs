@@ -0,0 +1,248 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The Local APIC (Advanced Programmable Interrupt Controller) is the interrupt controller
+//! attached to each CPU core.
+//!
+//! It receives interrupts routed by the I/O APIC and allows cores to send Inter-Processor
+//! Interrupts (IPI) to each other.
+
+use crate::{
+	arch::x86::{cpuid, rdmsr, wrmsr},
+	memory::{PhysAddr, mmio::MMIO},
+};
+use core::{
+	hint, mem,
+	num::NonZeroUsize,
+	ptr,
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicPtr,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::errno::AllocResult;
+
+/// MSR: Local APIC base address
+const IA32_APIC_BASE: u32 = 0x1b;
+/// `IA32_APIC_BASE` flag: global enable
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+/// Register: Local APIC ID
+const REG_ID: usize = 0x20;
+/// Register: Task Priority
+const REG_TPR: usize = 0x80;
+/// Register: End Of Interrupt
+const REG_EOI: usize = 0xb0;
+/// Register: Spurious Interrupt Vector
+const REG_SVR: usize = 0xf0;
+/// Register: Error Status
+const REG_ESR: usize = 0x280;
+/// Register: Interrupt Command (low)
+const REG_ICR_LOW: usize = 0x300;
+/// Register: Interrupt Command (high)
+const REG_ICR_HIGH: usize = 0x310;
+/// Register: LVT Timer
+const REG_LVT_TIMER: usize = 0x320;
+/// Register: timer Initial Count
+const REG_TIMER_INIT: usize = 0x380;
+/// Register: timer Current Count
+const REG_TIMER_CURRENT: usize = 0x390;
+/// Register: timer Divide Configuration
+const REG_TIMER_DIVIDE: usize = 0x3e0;
+
+/// Spurious Interrupt Vector flag: software enable
+const SVR_ENABLE: u32 = 1 << 8;
+
+/// ICR delivery mode: fixed
+const ICR_FIXED: u32 = 0b000 << 8;
+/// ICR delivery mode: NMI
+const ICR_NMI: u32 = 0b100 << 8;
+/// ICR delivery mode: INIT
+const ICR_INIT: u32 = 0b101 << 8;
+/// ICR delivery mode: start-up
+const ICR_STARTUP: u32 = 0b110 << 8;
+/// ICR flag: delivery pending
+const ICR_PENDING: u32 = 1 << 12;
+/// ICR flag: level assert
+const ICR_ASSERT: u32 = 1 << 14;
+/// ICR flag: level triggered
+const ICR_LEVEL: u32 = 1 << 15;
+/// ICR destination shorthand: the current core
+const ICR_SELF: u32 = 0b01 << 18;
+/// ICR destination shorthand: all cores except the current one
+const ICR_ALL_BUT_SELF: u32 = 0b11 << 18;
+
+/// LVT flag: masked
+const LVT_MASKED: u32 = 1 << 16;
+/// LVT timer mode: periodic
+const LVT_TIMER_PERIODIC: u32 = 0b01 << 17;
+/// Timer divide configuration: divide by 16
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+/// The interrupt vector used to ask a core to run its scheduler.
+pub const RESCHEDULE_VECTOR: u8 = 0x30;
+/// The interrupt vector of the Local APIC timer.
+pub const TIMER_VECTOR: u8 = 0x31;
+/// The interrupt vector of spurious interrupts.
+pub const SPURIOUS_VECTOR: u8 = 0x3f;
+
+/// The pointer to the Local APIC's registers. If null, the Local APIC is not used.
+static REGS: AtomicPtr<u32> = AtomicPtr::new(null_mut());
+
+/// The destination of an IPI.
+#[derive(Clone, Copy, Debug)]
+pub enum IpiDest {
+	/// The core with the given APIC ID.
+	Core(u32),
+	/// The current core.
+	Current,
+	/// All cores, except the current one.
+	AllButSelf,
+}
+
+/// Tells whether the CPU has a Local APIC.
+pub fn is_present() -> bool {
+	cpuid(1, 0, 0, 0).3 & (1 << 9) != 0
+}
+
+/// Tells whether the Local APIC is in use.
+#[inline]
+pub fn is_enabled() -> bool {
+	!REGS.load(Acquire).is_null()
+}
+
+/// Reads the register at offset `reg`.
+#[inline]
+fn read(reg: usize) -> u32 {
+	unsafe { ptr::read_volatile(REGS.load(Acquire).byte_add(reg)) }
+}
+
+/// Writes `val` to the register at offset `reg`.
+#[inline]
+fn write(reg: usize, val: u32) {
+	unsafe { ptr::write_volatile(REGS.load(Acquire).byte_add(reg), val) }
+}
+
+/// Maps the Local APIC's registers, then enables it on the current core.
+///
+/// If the CPU has no Local APIC, the function does nothing.
+pub(crate) fn init() -> AllocResult<()> {
+	if !is_present() {
+		return Ok(());
+	}
+	let base = PhysAddr((rdmsr(IA32_APIC_BASE) & !0xfff) as _);
+	let mmio = MMIO::new(base, NonZeroUsize::MIN, false)?;
+	REGS.store(mmio.as_ptr().cast().as_ptr(), Release);
+	// The registers remain mapped for the whole lifetime of the system
+	mem::forget(mmio);
+	enable();
+	Ok(())
+}
+
+/// Enables the Local APIC on the current core.
+///
+/// Registers must have been mapped by [`init`] beforehand.
+pub(crate) fn enable() {
+	wrmsr(IA32_APIC_BASE, rdmsr(IA32_APIC_BASE) | APIC_BASE_ENABLE);
+	// Accept all interrupts
+	write(REG_TPR, 0);
+	write(REG_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+}
+
+/// Returns the APIC ID of the current core.
+#[inline]
+pub fn id() -> u32 {
+	read(REG_ID) >> 24
+}
+
+/// Sends an End-Of-Interrupt message to the Local APIC of the current core.
+#[inline]
+pub fn end_of_interrupt() {
+	write(REG_EOI, 0);
+}
+
+/// Writes the Interrupt Command Register to send an IPI, then waits for its delivery.
+fn send(dest: IpiDest, cmd: u32) {
+	// Clear previous errors
+	write(REG_ESR, 0);
+	let cmd = match dest {
+		IpiDest::Core(id) => {
+			write(REG_ICR_HIGH, id << 24);
+			cmd
+		}
+		IpiDest::Current => cmd | ICR_SELF,
+		IpiDest::AllButSelf => cmd | ICR_ALL_BUT_SELF,
+	};
+	// Writing the low part sends the IPI
+	write(REG_ICR_LOW, cmd);
+	while read(REG_ICR_LOW) & ICR_PENDING != 0 {
+		hint::spin_loop();
+	}
+}
+
+/// Sends an interrupt with the given `vector` to `dest`.
+pub fn send_ipi(dest: IpiDest, vector: u8) {
+	send(dest, ICR_FIXED | ICR_ASSERT | vector as u32);
+}
+
+/// Sends a Non-Maskable Interrupt to `dest`.
+pub fn send_nmi(dest: IpiDest) {
+	send(dest, ICR_NMI | ICR_ASSERT);
+}
+
+/// Sends an INIT IPI to the core with the given APIC ID, resetting it.
+pub fn send_init(apic_id: u32) {
+	send(IpiDest::Core(apic_id), ICR_INIT | ICR_LEVEL | ICR_ASSERT);
+	// De-assert, required by older processors
+	send(IpiDest::Core(apic_id), ICR_INIT | ICR_LEVEL);
+}
+
+/// Sends a Start-up IPI to the core with the given APIC ID.
+///
+/// The core starts executing in real mode at the physical address `page * 0x1000`.
+pub fn send_startup(apic_id: u32, page: u8) {
+	send(
+		IpiDest::Core(apic_id),
+		ICR_STARTUP | ICR_ASSERT | page as u32,
+	);
+}
+
+/// Starts the Local APIC timer of the current core.
+///
+/// The timer fires an interrupt with the vector [`TIMER_VECTOR`] once `count` ticks of the bus
+/// clock divided by 16 have elapsed. If `periodic` is set, the timer then restarts.
+///
+/// If `count` is zero, the timer is stopped.
+pub fn timer_start(count: u32, periodic: bool) {
+	if count == 0 {
+		write(REG_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
+		write(REG_TIMER_INIT, 0);
+		return;
+	}
+	let mode = if periodic { LVT_TIMER_PERIODIC } else { 0 };
+	write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+	write(REG_LVT_TIMER, mode | TIMER_VECTOR as u32);
+	// Writing the initial count starts the timer
+	write(REG_TIMER_INIT, count);
+}
+
+/// Returns the remaining number of ticks before the Local APIC timer of the current core fires.
+#[inline]
+pub fn timer_count() -> u32 {
+	read(REG_TIMER_CURRENT)
+}
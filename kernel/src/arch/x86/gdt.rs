@@ -22,15 +22,9 @@
 //! It is a deprecated structure that still must be used in order to switch to protected mode,
 //! handle protection rings and load the Task State Segment (TSS).
 
-use crate::{
-	boot::{GDT_VIRT_ADDR, InitGdt},
-	memory::{PhysAddr, VirtAddr},
-};
+use crate::{boot::InitGdt, memory::VirtAddr, process::scheduler::core_local};
 use core::{arch::asm, fmt, ptr};
 
-/// The address in physical memory to the beginning of the GDT.
-const PHYS_PTR: PhysAddr = PhysAddr(0x800);
-
 /// The offset of the kernel code segment.
 pub const KERNEL_CS: usize = 8;
 /// The offset of the kernel data segment.
@@ -171,17 +165,13 @@ impl fmt::Debug for Entry {
 	}
 }
 
-/// Returns the pointer to the segment at offset `offset`.
+/// Returns the pointer to the segment at offset `offset` in the GDT of the current core.
 ///
 /// # Safety
 ///
 /// The caller must ensure the given `offset` is in bounds of the GDT.
 pub unsafe fn get_segment_ptr(offset: usize) -> *mut Entry {
-	PHYS_PTR
-		.kernel_to_virtual()
-		.unwrap()
-		.as_ptr::<Entry>()
-		.byte_add(offset)
+	core_local().gdt().cast::<Entry>().byte_add(offset)
 }
 
 /// A GDT descriptor.
@@ -193,12 +183,12 @@ struct Gdt {
 	addr: VirtAddr,
 }
 
-/// Refreshes the GDT's cache.
+/// Loads the GDT of the current core, refreshing its cache.
 #[inline(always)]
 pub fn flush() {
 	let gdt = Gdt {
 		size: (size_of::<InitGdt>() - 1) as _,
-		addr: GDT_VIRT_ADDR,
+		addr: VirtAddr::from(core_local().gdt()),
 	};
 	unsafe {
 		asm!("lgdt [{}]", in(reg) &gdt);
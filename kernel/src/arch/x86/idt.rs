@@ -23,7 +23,7 @@
 use crate::{
 	arch::{
 		x86,
-		x86::{DEFAULT_FLAGS, cli, gdt, pic, sti},
+		x86::{DEFAULT_FLAGS, apic, cli, gdt, ioapic, pic, sti},
 	},
 	syscall::syscall_int,
 };
@@ -321,6 +321,9 @@ unsafe extern "C" {
 	fn irq13();
 	fn irq14();
 	fn irq15();
+	fn irq16();
+	fn irq17();
+	fn irq31();
 }
 
 /// The list of IDT entries.
@@ -360,8 +363,8 @@ fn enable_syscall_inst() {
 	);
 	// LSTAR
 	super::wrmsr(0xc0000082, crate::syscall::syscall as usize as u64);
-	// SFMASK (clear direction and interrupt flag)
-	super::wrmsr(0xc0000084, 0x600);
+	// SFMASK (clear direction, interrupt and trap flag)
+	super::wrmsr(0xc0000084, 0x700);
 }
 
 /// Initializes the IDT.
@@ -424,15 +427,61 @@ pub fn init() {
 		IDT_ENTRIES[0x2d] = InterruptDescriptor::new(irq13 as _, 0x8, 0x8e);
 		IDT_ENTRIES[0x2e] = InterruptDescriptor::new(irq14 as _, 0x8, 0x8e);
 		IDT_ENTRIES[0x2f] = InterruptDescriptor::new(irq15 as _, 0x8, 0x8e);
+		// Local APIC
+		IDT_ENTRIES[apic::RESCHEDULE_VECTOR as usize] =
+			InterruptDescriptor::new(irq16 as _, 0x8, 0x8e);
+		IDT_ENTRIES[apic::TIMER_VECTOR as usize] = InterruptDescriptor::new(irq17 as _, 0x8, 0x8e);
+		IDT_ENTRIES[apic::SPURIOUS_VECTOR as usize] =
+			InterruptDescriptor::new(irq31 as _, 0x8, 0x8e);
 		// System calls
 		IDT_ENTRIES[SYSCALL_ENTRY] = InterruptDescriptor::new(syscall_int as _, 0x8, 0xee);
-		// Load
-		let idt = InterruptDescriptorTable {
-			size: (size_of::<InterruptDescriptor>() * ENTRIES_COUNT - 1) as u16,
-			offset: addr_of!(IDT_ENTRIES) as _,
-		};
+	}
+	load();
+}
+
+/// Loads the IDT on the current core.
+///
+/// The IDT must have been initialized with [`init`] beforehand.
+pub(crate) fn load() {
+	let idt = InterruptDescriptorTable {
+		size: (size_of::<InterruptDescriptor>() * ENTRIES_COUNT - 1) as u16,
+		offset: addr_of!(IDT_ENTRIES) as _,
+	};
+	unsafe {
 		asm!("lidt [{}]", in(reg) &idt);
-		#[cfg(target_arch = "x86_64")]
-		enable_syscall_inst();
+	}
+	#[cfg(target_arch = "x86_64")]
+	enable_syscall_inst();
+}
+
+/// Unmasks the given IRQ on the interrupt controller in use.
+pub fn enable_irq(irq: u8) {
+	if ioapic::is_enabled() {
+		ioapic::set_masked(irq, false);
+	} else {
+		pic::enable_irq(irq);
+	}
+}
+
+/// Masks the given IRQ on the interrupt controller in use.
+pub fn disable_irq(irq: u8) {
+	if ioapic::is_enabled() {
+		ioapic::set_masked(irq, true);
+	} else {
+		pic::disable_irq(irq);
+	}
+}
+
+/// Sends an End-Of-Interrupt message for the given interrupt `irq` to the interrupt controllers
+/// in use.
+///
+/// `irq` is the interrupt vector minus `0x20`.
+pub fn end_of_interrupt(irq: u8) {
+	if apic::is_enabled() {
+		apic::end_of_interrupt();
+	}
+	// Without I/O APIC, legacy IRQs go through the PIC
+	if !ioapic::is_enabled() && irq < 0x10 {
+		pic::end_of_interrupt(irq);
 	}
 }
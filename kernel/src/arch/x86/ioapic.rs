@@ -0,0 +1,244 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The I/O APIC routes external interrupts to the Local APICs of the CPU cores, replacing the
+//! legacy PIC.
+//!
+//! Each I/O APIC handles a range of Global System Interrupts (GSI). Legacy ISA IRQs are
+//! identity-mapped to GSIs, unless the ACPI specifies an override.
+
+use crate::{
+	arch::x86::{apic, pic},
+	memory::{PhysAddr, mmio::MMIO},
+	sync::mutex::IntMutex,
+};
+use core::{
+	mem,
+	num::NonZeroUsize,
+	ptr,
+	ptr::NonNull,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
+use utils::{collections::vec::Vec, errno::AllocResult};
+
+/// Register selection offset.
+const REG_SEL: usize = 0x00;
+/// Register window offset.
+const REG_WIN: usize = 0x10;
+
+/// Register: version, including the number of redirection entries
+const REG_VER: u32 = 0x01;
+/// Register: first redirection entry
+const REG_REDTBL: u32 = 0x10;
+
+/// Redirection entry flag: the interrupt is masked
+const REDIR_MASKED: u64 = 1 << 16;
+/// Redirection entry flag: level triggered
+const REDIR_LEVEL: u64 = 1 << 15;
+/// Redirection entry flag: active low
+const REDIR_ACTIVE_LOW: u64 = 1 << 13;
+
+/// The number of legacy ISA IRQs.
+const ISA_IRQS_COUNT: usize = 16;
+/// The ISA IRQ used to cascade the legacy PICs, which does not exist on the I/O APIC.
+const ISA_CASCADE_IRQ: u8 = 2;
+
+/// Interrupt source override flags: polarity mask
+const POLARITY_MASK: u16 = 0b11;
+/// Interrupt source override flags: active low
+const POLARITY_LOW: u16 = 0b11;
+/// Interrupt source override flags: trigger mode mask
+const TRIGGER_MASK: u16 = 0b1100;
+/// Interrupt source override flags: level triggered
+const TRIGGER_LEVEL: u16 = 0b1100;
+
+/// An I/O APIC.
+struct IoApic {
+	/// The pointer to the registers.
+	regs: NonNull<u32>,
+	/// The first GSI handled by the I/O APIC.
+	gsi_base: u32,
+	/// The number of GSIs handled by the I/O APIC.
+	count: u32,
+}
+
+impl IoApic {
+	/// Reads the register `reg`.
+	fn read(&self, reg: u32) -> u32 {
+		unsafe {
+			ptr::write_volatile(self.regs.byte_add(REG_SEL).as_ptr(), reg);
+			ptr::read_volatile(self.regs.byte_add(REG_WIN).as_ptr())
+		}
+	}
+
+	/// Writes `val` to the register `reg`.
+	fn write(&self, reg: u32, val: u32) {
+		unsafe {
+			ptr::write_volatile(self.regs.byte_add(REG_SEL).as_ptr(), reg);
+			ptr::write_volatile(self.regs.byte_add(REG_WIN).as_ptr(), val);
+		}
+	}
+
+	/// Reads the redirection entry for the `n`th GSI of the I/O APIC.
+	fn read_redir(&self, n: u32) -> u64 {
+		let low = self.read(REG_REDTBL + n * 2) as u64;
+		let high = self.read(REG_REDTBL + n * 2 + 1) as u64;
+		(high << 32) | low
+	}
+
+	/// Writes the redirection entry for the `n`th GSI of the I/O APIC.
+	fn write_redir(&self, n: u32, val: u64) {
+		// Write the high part first since the low part contains the mask
+		self.write(REG_REDTBL + n * 2 + 1, (val >> 32) as _);
+		self.write(REG_REDTBL + n * 2, val as _);
+	}
+}
+
+/// The mapping of an ISA IRQ to a GSI.
+#[derive(Clone, Copy)]
+struct IsaIrq {
+	/// The GSI.
+	gsi: u32,
+	/// Override flags, as specified by the ACPI.
+	flags: u16,
+}
+
+/// The list of I/O APICs.
+static IO_APICS: IntMutex<Vec<IoApic>> = IntMutex::new(Vec::new());
+/// The mapping of ISA IRQs to GSIs.
+static ISA_IRQS: IntMutex<[IsaIrq; ISA_IRQS_COUNT]> = IntMutex::new(
+	const {
+		let mut irqs = [IsaIrq {
+			gsi: 0,
+			flags: 0,
+		}; ISA_IRQS_COUNT];
+		let mut i = 0;
+		while i < ISA_IRQS_COUNT {
+			irqs[i].gsi = i as _;
+			i += 1;
+		}
+		irqs
+	},
+);
+/// Tells whether the I/O APICs are used instead of the PIC.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Registers the I/O APIC whose registers are at the physical address `addr`, handling GSIs
+/// starting from `gsi_base`.
+pub(crate) fn register(addr: PhysAddr, gsi_base: u32) -> AllocResult<()> {
+	let mmio = MMIO::new(addr, NonZeroUsize::MIN, false)?;
+	let mut io_apic = IoApic {
+		regs: mmio.as_ptr().cast(),
+		gsi_base,
+		count: 0,
+	};
+	io_apic.count = ((io_apic.read(REG_VER) >> 16) & 0xff) + 1;
+	IO_APICS.lock().push(io_apic)?;
+	// The registers remain mapped for the whole lifetime of the system
+	mem::forget(mmio);
+	Ok(())
+}
+
+/// Registers an interrupt source override, mapping the ISA IRQ `irq` to the GSI `gsi`.
+///
+/// `flags` are the MPS INTI flags, as specified by the ACPI.
+pub(crate) fn set_override(irq: u8, gsi: u32, flags: u16) {
+	if let Some(ent) = ISA_IRQS.lock().get_mut(irq as usize) {
+		*ent = IsaIrq {
+			gsi,
+			flags,
+		};
+	}
+}
+
+/// Tells whether the I/O APICs are used instead of the PIC.
+#[inline]
+pub fn is_enabled() -> bool {
+	ENABLED.load(Acquire)
+}
+
+/// Calls `f` with the I/O APIC handling `gsi` and the index of the GSI on it.
+fn with_gsi<F: FnOnce(&IoApic, u32)>(io_apics: &[IoApic], gsi: u32, f: F) {
+	let io_apic = io_apics
+		.iter()
+		.find(|a| (a.gsi_base..(a.gsi_base + a.count)).contains(&gsi));
+	if let Some(io_apic) = io_apic {
+		f(io_apic, gsi - io_apic.gsi_base);
+	}
+}
+
+/// Routes ISA IRQs to the current core through the I/O APICs, then disables the PIC.
+///
+/// Interrupt vectors are the same as the ones used with the PIC, and IRQs masked on the PIC
+/// remain masked.
+///
+/// If no I/O APIC is present or if the Local APIC is not in use, the function does nothing.
+pub(crate) fn init() {
+	let io_apics = IO_APICS.lock();
+	if io_apics.is_empty() || !apic::is_enabled() {
+		return;
+	}
+	let mask = pic::get_mask();
+	pic::disable();
+	// Mask everything first
+	for io_apic in io_apics.iter() {
+		for n in 0..io_apic.count {
+			io_apic.write_redir(n, io_apic.read_redir(n) | REDIR_MASKED);
+		}
+	}
+	let dest = (apic::id() as u64) << 56;
+	let isa_irqs = ISA_IRQS.lock();
+	for (irq, ent) in isa_irqs.iter().enumerate() {
+		if irq as u8 == ISA_CASCADE_IRQ {
+			continue;
+		}
+		let mut redir = dest | (0x20 + irq as u64);
+		if ent.flags & POLARITY_MASK == POLARITY_LOW {
+			redir |= REDIR_ACTIVE_LOW;
+		}
+		if ent.flags & TRIGGER_MASK == TRIGGER_LEVEL {
+			redir |= REDIR_LEVEL;
+		}
+		if mask & (1 << irq) != 0 {
+			redir |= REDIR_MASKED;
+		}
+		with_gsi(&io_apics, ent.gsi, |io_apic, n| {
+			io_apic.write_redir(n, redir)
+		});
+	}
+	ENABLED.store(true, Release);
+}
+
+/// Masks or unmasks the ISA IRQ `irq`.
+pub fn set_masked(irq: u8, masked: bool) {
+	let Some(ent) = ISA_IRQS.lock().get(irq as usize).copied() else {
+		return;
+	};
+	let io_apics = IO_APICS.lock();
+	with_gsi(&io_apics, ent.gsi, |io_apic, n| {
+		let redir = io_apic.read_redir(n);
+		let redir = if masked {
+			redir | REDIR_MASKED
+		} else {
+			redir & !REDIR_MASKED
+		};
+		io_apic.write_redir(n, redir);
+	});
+}
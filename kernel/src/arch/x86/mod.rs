@@ -18,12 +18,15 @@
 
 //! x86-specific code.
 
+pub mod apic;
 pub mod gdt;
 #[macro_use]
 pub mod idt;
 pub mod io;
+pub mod ioapic;
 pub mod paging;
 pub mod pic;
+pub mod smp;
 pub mod tss;
 
 use core::arch::asm;
@@ -246,7 +249,7 @@ pub unsafe fn set_smap_enabled(enabled: bool) {
 }
 
 /// FXstate buffer.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 #[repr(align(16))]
 pub struct FxState(pub [u8; 512]);
 
@@ -38,6 +38,9 @@ type Entry = AtomicUsize;
 /// **x86 paging flag**: If set, execution of instruction is disabled.
 #[cfg(target_arch = "x86_64")]
 pub const FLAG_XD: usize = 1 << 63;
+/// Software flag: on a non-present entry, tells the entry holds a swap entry, located in the bits
+/// of the address.
+pub const FLAG_SWAP: usize = 0b1000000000;
 /// **x86 paging flag**: If set, prevents the CPU from updating the associated
 /// addresses when the TLB is flushed.
 pub const FLAG_GLOBAL: usize = 0b100000000;
@@ -161,7 +164,8 @@ impl Table {
 		// TODO Use a counter instead. Increment it when mapping a page in the table and
 		// decrement it when unmapping. Then return `true` if the counter has the value
 		// `0`
-		self.iter().all(|e| e.load(Relaxed) & FLAG_PRESENT == 0)
+		// Swap entries are not present but must be kept
+		self.iter().all(|e| e.load(Relaxed) == 0)
 	}
 }
 
@@ -375,6 +379,47 @@ pub fn poll_dirty(table: &Table, virtaddr: VirtAddr) -> Option<(PhysAddr, bool)>
 	Some((physaddr, entry & FLAG_DIRTY != 0))
 }
 
+/// Returns the entry of the last level of the tree for the given virtual address.
+///
+/// If the tables leading to this entry are not present, the function returns `None`.
+fn get_entry(mut table: &Table, virtaddr: VirtAddr) -> Option<&Entry> {
+	for level in (1..DEPTH).rev() {
+		let index = get_addr_element_index(virtaddr, level);
+		let entry = table[index].load(Relaxed);
+		if entry & FLAG_PRESENT == 0 || entry & FLAG_PAGE_SIZE != 0 {
+			return None;
+		}
+		table = unsafe { unwrap_entry(entry).0.as_ref() };
+	}
+	Some(&table[get_addr_element_index(virtaddr, 0)])
+}
+
+/// Inner implementation of [`crate::memory::vmem::VMem::map_swap`] for x86.
+///
+/// # Safety
+///
+/// The caller must ensure `virtaddr` is not in kernelspace.
+pub unsafe fn map_swap(table: &mut Table, virtaddr: VirtAddr, swap_entry: usize) {
+	if let Some(entry) = get_entry(table, virtaddr) {
+		entry.store((swap_entry << 12) | FLAG_SWAP, Relaxed);
+	}
+}
+
+/// Inner implementation of [`crate::memory::vmem::VMem::swap_entry`] for x86.
+pub fn swap_entry(table: &Table, virtaddr: VirtAddr) -> Option<usize> {
+	let entry = get_entry(table, virtaddr)?.load(Relaxed);
+	(entry & (FLAG_PRESENT | FLAG_SWAP) == FLAG_SWAP).then_some(entry >> 12)
+}
+
+/// Inner implementation of [`crate::memory::vmem::VMem::poll_accessed`] for x86.
+pub fn poll_accessed(table: &Table, virtaddr: VirtAddr) -> bool {
+	let Some(entry) = get_entry(table, virtaddr) else {
+		return false;
+	};
+	let flags = FLAG_PRESENT | FLAG_ACCESSED;
+	entry.fetch_and(!FLAG_ACCESSED, Relaxed) & flags == flags
+}
+
 /// Binds the given page directory to the current CPU.
 ///
 /// # Safety
@@ -417,6 +462,20 @@ pub fn flush_current() {
 	}
 }
 
+/// Flush the Translation Lookaside Buffer (TLB) on the current CPU, including global pages.
+pub fn flush_global() {
+	let cr4 = register_get!("cr4");
+	if cr4 & (1 << 7) == 0 {
+		flush_current();
+		return;
+	}
+	// Toggling the GLOBAL flag flushes everything
+	unsafe {
+		register_set!("cr4", cr4 & !(1 << 7));
+		register_set!("cr4", cr4);
+	}
+}
+
 unsafe fn free_impl(mut page_dir: NonNull<Table>, depth: usize) {
 	if depth < DEPTH - 1 {
 		let pd = unsafe { page_dir.as_mut() };
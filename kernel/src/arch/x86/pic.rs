@@ -68,6 +68,19 @@ pub fn init(offset1: u8, offset2: u8) {
 	}
 }
 
+/// Returns the current mask of IRQs. Bit `n` is set if IRQ `n` is masked.
+pub fn get_mask() -> u16 {
+	unsafe { ((inb(SLAVE_DATA) as u16) << 8) | inb(MASTER_DATA) as u16 }
+}
+
+/// Masks every IRQ, effectively disabling the PIC.
+pub fn disable() {
+	unsafe {
+		outb(MASTER_DATA, 0xff);
+		outb(SLAVE_DATA, 0xff);
+	}
+}
+
 /// Enable interruptions on the given IRQ.
 pub fn enable_irq(mut n: u8) {
 	let port = if n < 8 {
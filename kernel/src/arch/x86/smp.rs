@@ -0,0 +1,277 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Startup of Application Processors (APs).
+//!
+//! After reset, an AP waits for a Start-up IPI (SIPI), then begins executing in real mode at the
+//! beginning of the page given by the IPI.
+//!
+//! A trampoline is copied to this page. It switches the core to the same mode as the kernel using
+//! the boot page directory, then jumps to the given entry point.
+
+use crate::{
+	arch::x86::{apic, io::outb},
+	boot::REMAP,
+	memory::PhysAddr,
+};
+use core::{
+	arch::{asm, global_asm},
+	ptr,
+	ptr::{NonNull, addr_of},
+};
+use utils::limits::PAGE_SIZE;
+
+/// The physical address at which the trampoline is copied.
+const TRAMPOLINE_ADDR: usize = 0x8000;
+
+/// Data passed to the trampoline, located at its end.
+#[repr(C)]
+struct TrampolineData {
+	/// The physical address of the page directory to use.
+	page_dir: u64,
+	/// The initial stack pointer.
+	stack: u64,
+	/// The entry point.
+	entry: u64,
+}
+
+unsafe extern "C" {
+	/// The beginning of the trampoline code.
+	static smp_trampoline: u8;
+	/// The [`TrampolineData`] of the trampoline.
+	static smp_trampoline_data: u8;
+	/// The end of the trampoline code.
+	static smp_trampoline_end: u8;
+}
+
+#[cfg(target_arch = "x86")]
+global_asm!(r#"
+.section .rodata
+
+.global smp_trampoline
+.global smp_trampoline_data
+.global smp_trampoline_end
+
+.code16
+smp_trampoline:
+	cli
+	cld
+	xor ax, ax
+	mov ds, ax
+	mov ss, ax
+	mov sp, {ADDR}
+
+	# Enable protected mode
+	lgdt [{ADDR} + TRAMPOLINE_GDT_DESC]
+	mov eax, cr0
+	or eax, 1
+	mov cr0, eax
+	ljmp 8, OFFSET TRAMPOLINE_32 + {ADDR} # kernel code segment
+
+.code32
+smp_trampoline_32:
+	mov ax, 16 # kernel data segment
+	mov ds, ax
+	mov es, ax
+	mov ss, ax
+	xor ax, ax
+	mov fs, ax
+	mov gs, ax
+	mov ebx, OFFSET TRAMPOLINE_DATA + {ADDR}
+
+	# Enable PSE
+	mov eax, cr4
+	or eax, 0x10
+	mov cr4, eax
+
+	# Enable paging and write protect
+	mov eax, [ebx]
+	mov cr3, eax
+	mov eax, cr0
+	or eax, 0x80010000
+	mov cr0, eax
+
+	mov esp, [ebx + 8]
+	mov eax, [ebx + 16]
+	xor ebp, ebp
+	call eax
+	# cannot return
+	ud2
+
+.align 8
+smp_trampoline_gdt:
+	.quad 0
+	# Kernel code segment
+	.quad 0x00cf9a000000ffff
+	# Kernel data segment
+	.quad 0x00cf92000000ffff
+smp_trampoline_gdt_desc:
+	.word smp_trampoline_gdt_desc - smp_trampoline_gdt - 1
+	.long {ADDR} + (smp_trampoline_gdt - smp_trampoline)
+
+.align 8
+smp_trampoline_data:
+	.skip 24
+smp_trampoline_end:
+
+# Offsets from the beginning of the trampoline
+.set TRAMPOLINE_32, smp_trampoline_32 - smp_trampoline
+.set TRAMPOLINE_GDT_DESC, smp_trampoline_gdt_desc - smp_trampoline
+.set TRAMPOLINE_DATA, smp_trampoline_data - smp_trampoline
+"#, ADDR = const TRAMPOLINE_ADDR);
+
+#[cfg(target_arch = "x86_64")]
+global_asm!(r#"
+.section .rodata
+
+.global smp_trampoline
+.global smp_trampoline_data
+.global smp_trampoline_end
+
+.code16
+smp_trampoline:
+	cli
+	cld
+	xor ax, ax
+	mov ds, ax
+	mov ss, ax
+	mov sp, {ADDR}
+
+	# Enable protected mode
+	lgdt [{ADDR} + TRAMPOLINE_GDT_DESC]
+	mov eax, cr0
+	or eax, 1
+	mov cr0, eax
+	ljmp 24, OFFSET TRAMPOLINE_32 + {ADDR} # 32 bits code segment
+
+.code32
+smp_trampoline_32:
+	mov ax, 16 # kernel data segment
+	mov ds, ax
+	mov es, ax
+	mov ss, ax
+	mov ebx, OFFSET TRAMPOLINE_DATA + {ADDR}
+
+	# Enable PSE and PAE
+	mov eax, cr4
+	or eax, 0x30
+	mov cr4, eax
+
+	mov eax, [ebx]
+	mov cr3, eax
+
+	# Enable LME
+	mov ecx, 0xc0000080 # EFER
+	rdmsr
+	or eax, 0x901
+	wrmsr
+
+	# Enable paging and write protect
+	mov eax, cr0
+	or eax, 0x80010000
+	mov cr0, eax
+
+	ljmp 8, OFFSET TRAMPOLINE_64 + {ADDR} # kernel code segment
+
+.code64
+smp_trampoline_64:
+	xor ax, ax
+	mov fs, ax
+	mov gs, ax
+
+	mov ebx, OFFSET TRAMPOLINE_DATA + {ADDR}
+	mov rsp, [rbx + 8]
+	mov rax, [rbx + 16]
+	xor rbp, rbp
+	call rax
+	# cannot return
+	ud2
+
+.align 8
+smp_trampoline_gdt:
+	.quad 0
+	# Kernel code segment (64 bits)
+	.quad 0x00af9a000000ffff
+	# Kernel data segment
+	.quad 0x00cf92000000ffff
+	# Kernel code segment (32 bits)
+	.quad 0x00cf9a000000ffff
+smp_trampoline_gdt_desc:
+	.word smp_trampoline_gdt_desc - smp_trampoline_gdt - 1
+	.long {ADDR} + (smp_trampoline_gdt - smp_trampoline)
+
+.align 8
+smp_trampoline_data:
+	.skip 24
+smp_trampoline_end:
+
+# Offsets from the beginning of the trampoline
+.set TRAMPOLINE_32, smp_trampoline_32 - smp_trampoline
+.set TRAMPOLINE_64, smp_trampoline_64 - smp_trampoline
+.set TRAMPOLINE_GDT_DESC, smp_trampoline_gdt_desc - smp_trampoline
+.set TRAMPOLINE_DATA, smp_trampoline_data - smp_trampoline
+"#, ADDR = const TRAMPOLINE_ADDR);
+
+/// Waits for approximately `us` microseconds.
+///
+/// Timers cannot be used since they rely on interrupts handled by the scheduler.
+pub(crate) fn delay(us: usize) {
+	// Writing to port `0x80` takes about one microsecond
+	for _ in 0..us {
+		unsafe {
+			outb(0x80, 0);
+		}
+	}
+}
+
+/// Starts the core with the given APIC ID, making it execute `entry` on the stack `stack`.
+///
+/// The function does not wait for the core to be running. Only one core can be started at a
+/// time.
+///
+/// # Safety
+///
+/// `stack` must be the top of a valid stack, which is not used by anything else.
+pub(crate) unsafe fn start(apic_id: u32, stack: NonNull<u8>, entry: extern "C" fn() -> !) {
+	// Copy the trampoline
+	let begin = addr_of!(smp_trampoline);
+	let len = addr_of!(smp_trampoline_end).offset_from(begin) as usize;
+	let dst = PhysAddr(TRAMPOLINE_ADDR)
+		.kernel_to_virtual()
+		.unwrap()
+		.as_ptr::<u8>();
+	ptr::copy_nonoverlapping(begin, dst, len);
+	// The boot page directory is located in low memory, out of reach of regular relocations
+	let page_dir: usize;
+	asm!("mov {}, offset {}", out(reg) page_dir, sym REMAP, options(nomem, nostack, preserves_flags));
+	// Pass data
+	let data_off = addr_of!(smp_trampoline_data).offset_from(begin) as usize;
+	dst.add(data_off)
+		.cast::<TrampolineData>()
+		.write_volatile(TrampolineData {
+			page_dir: page_dir as u64,
+			stack: stack.as_ptr() as u64,
+			entry: entry as usize as u64,
+		});
+	// INIT-SIPI-SIPI sequence
+	apic::send_init(apic_id);
+	delay(10000);
+	for _ in 0..2 {
+		apic::send_startup(apic_id, (TRAMPOLINE_ADDR / PAGE_SIZE) as u8);
+		delay(200);
+	}
+}
@@ -26,8 +26,8 @@
 //! The structure has to be registered into the GDT into the TSS segment, and must be loaded using
 //! instruction `ltr`.
 
-use crate::arch::x86::gdt;
-use core::{arch::asm, mem, ptr::addr_of};
+use crate::{arch::x86::gdt, process::scheduler::core_local};
+use core::arch::asm;
 
 /// Task State Segment.
 #[repr(C)]
@@ -85,14 +85,10 @@ pub struct Tss {
 	pub iopb: u16,
 }
 
-/// The Task State Segment.
-#[unsafe(no_mangle)]
-static mut TSS: Tss = unsafe { mem::zeroed() };
-
-/// Initializes the TSS.
+/// Initializes the TSS of the current core.
 pub(crate) fn init() {
 	let [gdt_entry_low, gdt_entry_high] = gdt::Entry::new64(
-		addr_of!(TSS) as u64,
+		core_local().tss() as u64,
 		size_of::<Tss>() as u32 - 1,
 		0b10001001,
 		0,
@@ -109,20 +105,21 @@ pub(crate) fn init() {
 	}
 }
 
-/// Sets the kernel stack pointer on the TSS.
+/// Sets the kernel stack pointer on the TSS of the current core.
 ///
 /// # Safety
 ///
 /// This function is **not** reentrant.
 pub unsafe fn set_kernel_stack(kernel_stack: *mut u8) {
+	let tss = &mut *core_local().tss();
 	#[cfg(target_arch = "x86")]
 	{
-		TSS.esp0 = kernel_stack as _;
-		TSS.ss0 = gdt::KERNEL_DS as _;
-		TSS.ss = gdt::USER_DS as _;
+		tss.esp0 = kernel_stack as _;
+		tss.ss0 = gdt::KERNEL_DS as _;
+		tss.ss = gdt::USER_DS as _;
 	}
 	#[cfg(target_arch = "x86_64")]
 	{
-		TSS.rsp0 = kernel_stack as _;
+		tss.rsp0 = kernel_stack as _;
 	}
 }
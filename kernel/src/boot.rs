@@ -66,10 +66,12 @@ static INIT_GDT: InitGdt = [
 
 /// The paging object used to remap the kernel to higher memory.
 ///
+/// It is also used by application processors until they bind the kernel's virtual memory context.
+///
 /// The static is marked as **mutable** because the CPU will set the dirty flag.
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".boot.data")]
-static mut REMAP: Table = const {
+pub(crate) static mut REMAP: Table = const {
 	#[cfg(target_arch = "x86")]
 	{
 		use crate::arch::x86::paging::{FLAG_PAGE_SIZE, FLAG_PRESENT, FLAG_WRITE};
@@ -131,6 +131,8 @@ pub struct ArgsParser<'s> {
 	init: Option<&'s [u8]>,
 	/// Whether the kernel boots silently.
 	silent: bool,
+	/// Whether the network is configured with DHCP.
+	dhcp: bool,
 }
 
 impl<'s> ArgsParser<'s> {
@@ -140,6 +142,7 @@ impl<'s> ArgsParser<'s> {
 			root: None,
 			init: None,
 			silent: false,
+			dhcp: false,
 		};
 
 		let mut iter = TokenIterator {
@@ -192,6 +195,26 @@ impl<'s> ArgsParser<'s> {
 
 				b"-silent" => s.silent = true,
 
+				b"-ip" => {
+					let Some((_, conf)) = iter.next() else {
+						return Err(ParseError {
+							cmdline,
+							err: "not enough arguments for `-ip`",
+							token: Some((token.begin, token.s.len())),
+						});
+					};
+					match conf.s {
+						b"dhcp" => s.dhcp = true,
+						_ => {
+							return Err(ParseError {
+								cmdline,
+								err: "invalid network configuration",
+								token: Some((conf.begin, conf.s.len())),
+							});
+						}
+					}
+				}
+
 				_ => {
 					return Err(ParseError {
 						cmdline,
@@ -219,6 +242,11 @@ impl<'s> ArgsParser<'s> {
 	pub fn is_silent(&self) -> bool {
 		self.silent
 	}
+
+	/// If `true`, the network is configured with DHCP once an interface is up.
+	pub fn is_dhcp(&self) -> bool {
+		self.dhcp
+	}
 }
 
 #[cfg(test)]
@@ -264,4 +292,16 @@ mod test {
 	fn cmdline7() {
 		assert!(ArgsParser::parse(b"-root 1 0 -init bleh -silent").is_ok());
 	}
+
+	#[test_case]
+	fn cmdline8() {
+		assert!(ArgsParser::parse(b"-root 1 0 -ip").is_err());
+		assert!(ArgsParser::parse(b"-root 1 0 -ip bleh").is_err());
+	}
+
+	#[test_case]
+	fn cmdline9() {
+		let args = ArgsParser::parse(b"-root 1 0 -ip dhcp").unwrap();
+		assert!(args.is_dhcp());
+	}
 }
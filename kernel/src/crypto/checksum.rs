@@ -43,6 +43,52 @@ pub fn compute_rfc1071(data: &[u8]) -> u16 {
 	(!sum) as u16
 }
 
+/// Incremental computation of a checksum according to RFC1071, over several non-contiguous
+/// buffers.
+///
+/// Feeding buffers with [`Self::update`] gives the same result as computing the checksum of their
+/// concatenation with [`compute_rfc1071`].
+#[derive(Default)]
+pub struct Rfc1071 {
+	/// The current sum.
+	sum: u64,
+	/// Tells whether an odd number of bytes has been fed so far.
+	odd: bool,
+}
+
+impl Rfc1071 {
+	/// Feeds `data` to the checksum.
+	pub fn update(&mut self, data: &[u8]) {
+		let mut data = data;
+		// Complete the previous word
+		if self.odd {
+			if let Some((b, rest)) = data.split_first() {
+				self.sum += (*b as u64) << 8;
+				self.odd = false;
+				data = rest;
+			}
+		}
+		let mut chunks = data.chunks_exact(2);
+		for c in &mut chunks {
+			self.sum += ((c[1] as u64) << 8) | (c[0] as u64);
+		}
+		if let [b] = chunks.remainder() {
+			self.sum += *b as u64;
+			self.odd = true;
+		}
+	}
+
+	/// Returns the resulting checksum.
+	pub fn finish(&self) -> u16 {
+		// Folding into 16-bits
+		let mut sum = self.sum;
+		while (sum >> 16) != 0 {
+			sum = (sum & 0xffff) + (sum >> 16);
+		}
+		(!sum) as u16
+	}
+}
+
 /// Computes the lookup table for the given generator polynomial.
 ///
 /// Arguments:
@@ -94,6 +140,17 @@ mod test {
 		}
 	}
 
+	#[test_case]
+	fn rfc1071_incremental() {
+		let data: [u8; 9] = [0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40];
+		for i in 0..=data.len() {
+			let mut cs = Rfc1071::default();
+			cs.update(&data[..i]);
+			cs.update(&data[i..]);
+			assert_eq!(cs.finish(), compute_rfc1071(&data));
+		}
+	}
+
 	// TODO More tests on RFC1071
 	// TODO Test CRC32
 }
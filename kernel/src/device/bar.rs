@@ -23,7 +23,7 @@ use crate::arch::x86::io::{inb, inl, inw, outb, outl, outw};
 use core::{mem::size_of, num::NonZeroUsize, ptr, ptr::NonNull};
 
 /// Enumeration of Memory Space BAR types.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum BARType {
 	/// The base register is 32 bits wide.
 	Size32,
@@ -32,7 +32,7 @@ pub enum BARType {
 }
 
 /// Structure representing a Base Address Register.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum BAR {
 	/// A memory mapped register.
 	MemorySpace {
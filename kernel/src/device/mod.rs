@@ -50,6 +50,7 @@ use crate::{
 		perm::AccessProfile,
 		vfs,
 		vfs::{ResolutionSettings, Resolved},
+		wait_queue::PollTable,
 	},
 	memory::{
 		buddy::FrameOrder,
@@ -390,7 +391,7 @@ impl FileOps for BlkDevFileOps {
 		Ok(buf_off)
 	}
 
-	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
+	fn poll(&self, file: &File, mask: u32, _table: Option<&mut PollTable>) -> EResult<u32> {
 		let dev = file.as_block_device().ok_or_else(|| errno!(ENODEV))?;
 		dev.ops.poll(mask)
 	}
@@ -20,18 +20,14 @@
 //! communicate with it.
 
 use crate::{
-	file::{File, fs::FileOps},
+	file::{File, fs::FileOps, wait_queue::PollTable},
 	memory::user::{UserPtr, UserSlice},
-	process::{
-		Process,
-		pid::Pid,
-		signal::{Signal, SignalHandler},
-	},
+	process::{Process, pid::Pid, signal::Signal},
 	syscall::{
 		FromSyscallArg, ioctl,
 		select::{POLLIN, POLLOUT},
 	},
-	tty::{TTY, TTYDisplay, WinSize, termios, termios::Termios},
+	tty::{TTY, WinSize, termios::Termios},
 };
 use core::ffi::c_void;
 use utils::{errno, errno::EResult};
@@ -40,72 +36,91 @@ use utils::{errno, errno::EResult};
 #[derive(Debug)]
 pub struct TTYDeviceHandle;
 
-impl TTYDeviceHandle {
-	/// Checks whether the current process is allowed to read from the TTY.
-	///
-	/// If not, it is killed with a `SIGTTIN` signal.
-	///
-	/// This function must be called before performing the read operation.
-	fn check_sigttin(&self, tty: &TTYDisplay) -> EResult<()> {
-		let proc = Process::current();
-		if proc.get_pgid() == tty.get_pgrp() {
-			return Ok(());
-		}
-		if proc.is_in_orphan_process_group() {
-			return Err(errno!(EIO));
-		}
-		{
-			let signal_manager = proc.signal.lock();
-			if signal_manager.is_signal_blocked(Signal::SIGTTIN) {
-				return Err(errno!(EIO));
-			}
-			let handler = signal_manager.handlers.lock()[Signal::SIGTTIN as usize].clone();
-			if matches!(handler, SignalHandler::Ignore) {
-				return Err(errno!(EIO));
-			}
+impl FileOps for TTYDeviceHandle {
+	fn poll(&self, _file: &File, mask: u32, table: Option<&mut PollTable>) -> EResult<u32> {
+		if let Some(table) = table {
+			TTY.poll_register(table)?;
 		}
-		proc.kill_group(Signal::SIGTTIN);
-		Ok(())
+		let input = TTY.has_input_available();
+		let res = (if input { POLLIN } else { 0 } | POLLOUT) & mask;
+		Ok(res)
 	}
 
-	/// Checks whether the current process is allowed to write to the TTY.
-	///
-	/// If not, it is killed with a `SIGTTOU` signal.
-	///
-	/// This function must be called before performing the write operation.
-	fn check_sigttou(&self, tty: &TTYDisplay) -> EResult<()> {
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		let proc = Process::current();
-		if tty.get_termios().c_lflag & termios::consts::TOSTOP == 0 {
-			return Ok(());
+		let request = request.get_old_format();
+		// Changing settings is subject to job control
+		if matches!(
+			request,
+			ioctl::TCSETS | ioctl::TCSETSW | ioctl::TCSETSF | ioctl::TIOCSPGRP
+		) {
+			TTY.check_job_control(Signal::SIGTTOU)?;
 		}
-		{
-			let signal_manager = proc.signal.lock();
-			if signal_manager.is_signal_blocked(Signal::SIGTTOU) {
-				return Err(errno!(EIO));
+		match request {
+			ioctl::TIOCSCTTY => {
+				// Only a session leader can acquire a controlling terminal
+				let sid = proc.get_sid();
+				if sid != proc.get_pid() {
+					return Err(errno!(EPERM));
+				}
+				let steal = argp as usize == 1 && proc.fs.lock().access_profile.is_privileged();
+				let pgid = proc.get_pgid();
+				let mut tty = TTY.display.lock();
+				if tty.get_sid() == sid {
+					return Ok(0);
+				}
+				// The TTY is already the controlling terminal of another session
+				if tty.get_sid() != 0 && !steal {
+					return Err(errno!(EPERM));
+				}
+				tty.set_session(sid, pgid);
+				return Ok(0);
 			}
-			let handler = signal_manager.handlers.lock()[Signal::SIGTTOU as usize].clone();
-			if matches!(handler, SignalHandler::Ignore) {
-				return Err(errno!(EIO));
+			ioctl::TIOCNOTTY => {
+				if !TTY.display.lock().is_controlling(&proc) {
+					return Err(errno!(ENOTTY));
+				}
+				// When the session leader gives up the TTY, the whole session loses it
+				let sid = proc.get_sid();
+				if sid == proc.get_pid() {
+					TTY.hangup(sid);
+				}
+				// TODO detach other processes individually
+				return Ok(0);
 			}
+			ioctl::TIOCGSID => {
+				let sid = {
+					let tty = TTY.display.lock();
+					if !tty.is_controlling(&proc) {
+						return Err(errno!(ENOTTY));
+					}
+					tty.get_sid()
+				};
+				let sid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
+				sid_ptr.copy_to_user(&sid)?;
+				return Ok(0);
+			}
+			ioctl::TIOCSPGRP => {
+				let pgid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
+				let pgid = pgid_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+				let sid = Process::get_by_pid(pgid)
+					.ok_or_else(|| errno!(ESRCH))?
+					.get_sid();
+				let mut tty = TTY.display.lock();
+				if !tty.is_controlling(&proc) {
+					return Err(errno!(ENOTTY));
+				}
+				// The process group must be in the TTY's session
+				if sid != tty.get_sid() {
+					return Err(errno!(EPERM));
+				}
+				tty.set_pgrp(pgid);
+				return Ok(0);
+			}
+			_ => {}
 		}
-		if proc.is_in_orphan_process_group() {
-			return Err(errno!(EIO));
-		}
-		proc.kill_group(Signal::SIGTTOU);
-		Ok(())
-	}
-}
-
-impl FileOps for TTYDeviceHandle {
-	fn poll(&self, _file: &File, mask: u32) -> EResult<u32> {
-		let input = TTY.has_input_available();
-		let res = (if input { POLLIN } else { 0 } | POLLOUT) & mask;
-		Ok(res)
-	}
-
-	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
 		let mut tty = TTY.display.lock();
-		match request.get_old_format() {
+		match request {
 			ioctl::TCGETS => {
 				let termios_ptr = UserPtr::<Termios>::from_ptr(argp as usize);
 				termios_ptr.copy_to_user(tty.get_termios())?;
@@ -113,7 +128,6 @@ impl FileOps for TTYDeviceHandle {
 			}
 			// TODO Implement correct behaviours for each
 			ioctl::TCSETS | ioctl::TCSETSW | ioctl::TCSETSF => {
-				self.check_sigttou(&tty)?;
 				let termios_ptr = UserPtr::<Termios>::from_ptr(argp as usize);
 				let termios = termios_ptr
 					.copy_from_user()?
@@ -122,17 +136,13 @@ impl FileOps for TTYDeviceHandle {
 				Ok(0)
 			}
 			ioctl::TIOCGPGRP => {
+				if !tty.is_controlling(&proc) {
+					return Err(errno!(ENOTTY));
+				}
 				let pgid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
 				pgid_ptr.copy_to_user(&tty.get_pgrp())?;
 				Ok(0)
 			}
-			ioctl::TIOCSPGRP => {
-				self.check_sigttou(&tty)?;
-				let pgid_ptr = UserPtr::<Pid>::from_ptr(argp as usize);
-				let pgid = pgid_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-				tty.set_pgrp(pgid);
-				Ok(0)
-			}
 			ioctl::TIOCGWINSZ => {
 				let winsize = UserPtr::<WinSize>::from_ptr(argp as usize);
 				winsize.copy_to_user(tty.get_winsize())?;
@@ -151,22 +161,10 @@ impl FileOps for TTYDeviceHandle {
 	}
 
 	fn read(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		self.check_sigttin(&TTY.display.lock())?;
-		let len = TTY.read(buf)?;
-		Ok(len)
+		TTY.read(buf)
 	}
 
 	fn write(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
-		let mut tty = TTY.display.lock();
-		self.check_sigttou(&tty)?;
-		// Write
-		let mut i = 0;
-		let mut b: [u8; 128] = [0; 128];
-		while i < buf.len() {
-			let l = buf.copy_from_user(i, &mut b)?;
-			tty.write(&b[..l]);
-			i += l;
-		}
-		Ok(buf.len())
+		TTY.write(buf)
 	}
 }
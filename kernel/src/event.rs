@@ -19,10 +19,11 @@
 //! Interrupt callback register interface.
 
 use crate::{
-	arch::x86::{idt, idt::IntFrame, pic},
+	arch::x86::{apic, idt, idt::IntFrame},
 	crypto::rand,
 	memory::user::UserSlice,
 	process,
+	process::scheduler::cpu,
 	sync::mutex::IntMutex,
 };
 use core::ptr;
@@ -149,6 +150,12 @@ pub fn register_callback(id: u32, callback: Callback) -> AllocResult<Option<Call
 /// `frame` is the stack frame of the interruption, with general purpose registers saved.
 #[unsafe(no_mangle)]
 extern "C" fn interrupt_handler(frame: &mut IntFrame) {
+	// Non-maskable interrupts may occur while locks are held: handle them without touching
+	// anything else
+	if frame.int == 0x2 {
+		cpu::handle_nmi();
+		return;
+	}
 	// Ignore page faults to avoid a deadlock (might occur when writing entropy to userspace on
 	// non-mapped page)
 	if frame.int != 0xe {
@@ -161,6 +168,9 @@ extern "C" fn interrupt_handler(frame: &mut IntFrame) {
 	}
 	let id = frame.int as u32;
 	let ring = (frame.cs & 0b11) as u8;
+	if ring == 3 {
+		process::enter_kernel();
+	}
 	let code = frame.code as u32;
 	// Call corresponding callbacks
 	let callbacks = &CALLBACKS[id as usize];
@@ -180,9 +190,11 @@ extern "C" fn interrupt_handler(frame: &mut IntFrame) {
 			}
 		}
 	}
-	// If not a hardware exception, send EOI
+	// If not a hardware exception, send EOI. Spurious interrupts must not be acknowledged
 	if let Some(irq) = id.checked_sub(ERROR_MESSAGES.len() as u32) {
-		pic::end_of_interrupt(irq as _);
+		if id != apic::SPURIOUS_VECTOR as u32 {
+			idt::end_of_interrupt(irq as _);
+		}
 	}
 	process::yield_current(ring, frame);
 }
@@ -0,0 +1,447 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! epoll is an I/O event notification facility, allowing to monitor a large number of files.
+//!
+//! An epoll instance holds an *interest list*, the set of files it monitors. Each entry of the
+//! list, or *item*, is registered on the wait queues of its file. When one of these queues is
+//! woken up, the item is marked as ready and the threads waiting on the instance are woken up.
+//!
+//! Since ready items are marked without knowing which events occurred, the files are polled
+//! again when collecting events.
+
+use crate::{
+	file::{
+		File, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
+	sync::{atomic::AtomicU64, mutex::Mutex},
+	syscall::select::{POLLIN, POLLRDNORM},
+};
+use core::{
+	ffi::c_int,
+	mem, ptr,
+	ptr::NonNull,
+	sync::atomic::{
+		AtomicBool, AtomicU32,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use utils::{collections::vec::Vec, errno, errno::EResult, ptr::arc::Arc};
+
+/// Event: the file is available for reading.
+pub const EPOLLIN: u32 = 0x1;
+/// Event: the file is available for writing.
+pub const EPOLLOUT: u32 = 0x4;
+/// Event: an error occurred on the file.
+pub const EPOLLERR: u32 = 0x8;
+/// Event: hang up happened on the file.
+pub const EPOLLHUP: u32 = 0x10;
+/// Flag: prevents the system from suspending while the event is pending.
+pub const EPOLLWAKEUP: u32 = 1 << 29;
+/// Flag: when several epoll instances watch the same file, only one of them is woken up.
+pub const EPOLLEXCLUSIVE: u32 = 1 << 28;
+/// Flag: the item is disabled after reporting an event, until it is modified.
+pub const EPOLLONESHOT: u32 = 1 << 30;
+/// Flag: edge-triggered notification.
+pub const EPOLLET: u32 = 1 << 31;
+
+/// The mask of bits that are flags rather than events.
+const FLAGS_MASK: u32 = EPOLLWAKEUP | EPOLLEXCLUSIVE | EPOLLONESHOT | EPOLLET;
+/// The maximum depth of nested epoll instances.
+const MAX_NESTS: usize = 4;
+
+/// Lock serializing the changes to interest lists, so that an item can be safely removed from
+/// both its instance and its file.
+static EPOLL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Userspace structure describing an event.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EPollEvent {
+	/// The mask of events.
+	pub events: u32,
+	/// User data, returned as is along with events.
+	pub data: u64,
+}
+
+/// An entry of an epoll instance's interest list.
+///
+/// The item does not hold a reference to its file, so that closing the file is not prevented by
+/// monitoring it. Instead, the item is removed when the file is dropped.
+#[derive(Debug)]
+pub struct Item {
+	/// The file of the epoll instance the item belongs to.
+	epoll_file: NonNull<File>,
+	/// The file descriptor the file has been added with.
+	fd: c_int,
+	/// The monitored file.
+	file: NonNull<File>,
+	/// The wait queues of the file the item is registered on.
+	queues: Mutex<Vec<NonNull<WaitQueue>>>,
+	/// The mask of events to monitor, along with flags.
+	events: AtomicU32,
+	/// The user data.
+	data: AtomicU64,
+	/// Tells whether the file may have events to report.
+	ready: AtomicBool,
+}
+
+impl Item {
+	/// Returns the epoll instance the item belongs to.
+	fn epoll(&self) -> &EPoll {
+		// The item is removed from its instance before the instance's file is dropped
+		unsafe { self.epoll_file.as_ref() }
+			.get_buffer::<EPoll>()
+			.unwrap()
+	}
+
+	/// Returns the monitored file.
+	fn file(&self) -> &File {
+		// The item is removed from its instance before the file is dropped
+		unsafe { self.file.as_ref() }
+	}
+
+	/// Tells whether the item has been added with [`EPOLLEXCLUSIVE`].
+	pub(super) fn is_exclusive(&self) -> bool {
+		self.events.load(Relaxed) & EPOLLEXCLUSIVE != 0
+	}
+
+	/// Marks the item as ready and wakes up the threads waiting on its instance.
+	///
+	/// This function is called when one of the file's wait queues is woken up.
+	pub(super) fn notify(&self) {
+		self.ready.store(true, Release);
+		self.epoll().queue.wake_all();
+	}
+
+	/// Polls the file, returning the monitored events that occurred.
+	fn poll(&self) -> EResult<u32> {
+		let events = self.events.load(Relaxed);
+		if events & !FLAGS_MASK == 0 {
+			// Disabled
+			return Ok(0);
+		}
+		let file = self.file();
+		let revents = file.ops.poll(file, events & !FLAGS_MASK, None)?;
+		Ok(revents & events & !FLAGS_MASK)
+	}
+
+	/// Unregisters the item from the wait queues and from its file.
+	fn detach(&self) {
+		for queue in mem::take(&mut *self.queues.lock()) {
+			unsafe { queue.as_ref() }.unregister_epoll(self);
+		}
+		self.file()
+			.epoll_items
+			.lock()
+			.retain(|i| !ptr::eq(Arc::as_ptr(i), self));
+	}
+}
+
+/// The interest list of an epoll instance.
+#[derive(Debug, Default)]
+struct Interest {
+	/// The list of items.
+	items: Vec<Arc<Item>>,
+	/// The index at which the next collection of events starts, so that every ready item gets
+	/// reported even if the caller's buffer is small.
+	cursor: usize,
+}
+
+impl Interest {
+	/// Returns the index of the item for `file`, added with the file descriptor `fd`.
+	fn find(&self, fd: c_int, file: &File) -> Option<usize> {
+		self.items
+			.iter()
+			.position(|i| i.fd == fd && ptr::eq(i.file.as_ptr(), file))
+	}
+}
+
+/// An epoll instance.
+#[derive(Debug, Default)]
+pub struct EPoll {
+	/// The interest list.
+	interest: Mutex<Interest>,
+	/// The queue of threads waiting for events on the instance.
+	queue: WaitQueue,
+}
+
+impl EPoll {
+	/// Returns the depth of nested epoll instances monitored by `self`.
+	///
+	/// If `root` is reachable from `self`, the function returns `None`.
+	fn depth(&self, root: &EPoll) -> Option<usize> {
+		let interest = self.interest.lock();
+		let mut depth = 0;
+		for item in &interest.items {
+			let Some(child) = item.file().get_buffer::<EPoll>() else {
+				continue;
+			};
+			if ptr::eq(child, root) {
+				return None;
+			}
+			depth = depth.max(child.depth(root)? + 1);
+		}
+		Some(depth)
+	}
+
+	/// Adds `file`, with the file descriptor `fd`, to the interest list.
+	///
+	/// `epoll_file` is the file of the instance.
+	pub fn add(
+		&self,
+		epoll_file: &File,
+		fd: c_int,
+		file: &File,
+		event: EPollEvent,
+	) -> EResult<()> {
+		let _guard = EPOLL_LOCK.lock();
+		let mut interest = self.interest.lock();
+		if interest.find(fd, file).is_some() {
+			return Err(errno!(EEXIST));
+		}
+		// Forbid loops and deep nesting
+		if let Some(child) = file.get_buffer::<EPoll>() {
+			if ptr::eq(child, self) {
+				return Err(errno!(ELOOP));
+			}
+			let depth = child.depth(self).ok_or_else(|| errno!(ELOOP))?;
+			if height(epoll_file) + depth + 1 > MAX_NESTS {
+				return Err(errno!(ELOOP));
+			}
+		}
+		interest.items.reserve(1)?;
+		file.epoll_items.lock().reserve(1)?;
+		let events = event.events | EPOLLERR | EPOLLHUP;
+		let item = Arc::new(Item {
+			epoll_file: NonNull::from(epoll_file),
+			fd,
+			file: NonNull::from(file),
+			queues: Mutex::new(Vec::new()),
+			events: AtomicU32::new(events),
+			data: AtomicU64::new(event.data),
+			ready: AtomicBool::new(false),
+		})?;
+		// Register on the file's wait queues
+		let mut table = PollTable::for_epoll(item.clone());
+		let revents = file
+			.ops
+			.poll(file, events & !FLAGS_MASK, Some(&mut table))?;
+		*item.queues.lock() = table.into_queues()?;
+		// Cannot fail since memory has been reserved
+		file.epoll_items.lock().push(item.clone())?;
+		interest.items.push(item.clone())?;
+		if revents & events != 0 {
+			item.notify();
+		}
+		Ok(())
+	}
+
+	/// Modifies the events and data of `file`, with the file descriptor `fd`.
+	///
+	/// This re-enables an item disabled by [`EPOLLONESHOT`].
+	pub fn modify(&self, fd: c_int, file: &File, event: EPollEvent) -> EResult<()> {
+		let interest = self.interest.lock();
+		let i = interest.find(fd, file).ok_or_else(|| errno!(ENOENT))?;
+		let item = &interest.items[i];
+		if item.is_exclusive() {
+			return Err(errno!(EINVAL));
+		}
+		item.events
+			.store(event.events | EPOLLERR | EPOLLHUP, Relaxed);
+		item.data.store(event.data, Relaxed);
+		// Report the events that are already pending
+		if item.poll()? != 0 {
+			item.notify();
+		}
+		Ok(())
+	}
+
+	/// Removes `file`, with the file descriptor `fd`, from the interest list.
+	pub fn remove(&self, fd: c_int, file: &File) -> EResult<()> {
+		let _guard = EPOLL_LOCK.lock();
+		let mut interest = self.interest.lock();
+		let i = interest.find(fd, file).ok_or_else(|| errno!(ENOENT))?;
+		interest.items.remove(i).detach();
+		Ok(())
+	}
+
+	/// Collects the events that occurred on the monitored files into `events`, until it contains
+	/// `max` elements.
+	///
+	/// If `table` is specified, the current thread is registered on the instance's wait queue.
+	///
+	/// The function returns the number of collected events.
+	pub fn collect<'f>(
+		&'f self,
+		table: Option<&mut PollTable<'f>>,
+		max: usize,
+		events: &mut Vec<EPollEvent>,
+	) -> EResult<usize> {
+		if let Some(table) = table {
+			table.register(&self.queue)?;
+		}
+		let mut interest = self.interest.lock();
+		let len = interest.items.len();
+		let start = interest.cursor;
+		for i in (start..len).chain(0..start.min(len)) {
+			if events.len() >= max {
+				break;
+			}
+			let item = &interest.items[i];
+			if !item.ready.swap(false, Acquire) {
+				continue;
+			}
+			let revents = item
+				.poll()
+				.inspect_err(|_| item.ready.store(true, Release))?;
+			if revents == 0 {
+				// Nothing to report until the next notification
+				continue;
+			}
+			events.push(EPollEvent {
+				events: revents,
+				data: item.data.load(Relaxed),
+			})?;
+			let flags = item.events.load(Relaxed);
+			if flags & EPOLLONESHOT != 0 {
+				// Disable until the next modification
+				item.events.store(flags & FLAGS_MASK, Relaxed);
+			} else if flags & EPOLLET == 0 {
+				// Level-triggered: keep reporting as long as the file is ready
+				item.ready.store(true, Release);
+			}
+			interest.cursor = i + 1;
+		}
+		Ok(events.len())
+	}
+}
+
+impl FileOps for EPoll {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: 0o600,
+			..Default::default()
+		})
+	}
+
+	fn poll<'f>(
+		&'f self,
+		_file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&self.queue)?;
+		}
+		// The instance is readable if one of its items has events to report
+		let interest = self.interest.lock();
+		for item in &interest.items {
+			if item.ready.load(Acquire) && item.poll()? != 0 {
+				return Ok((POLLIN | POLLRDNORM) & mask);
+			}
+		}
+		Ok(0)
+	}
+}
+
+/// Returns the number of nested epoll instances monitoring `file`.
+fn height(file: &File) -> usize {
+	file.epoll_items
+		.lock()
+		.iter()
+		.map(|item| height(unsafe { item.epoll_file.as_ref() }) + 1)
+		.max()
+		.unwrap_or(0)
+}
+
+/// Removes `file` from the epoll instances monitoring it and, if `file` is itself an epoll
+/// instance, empties its interest list.
+///
+/// This function is called when `file` is dropped.
+pub(super) fn release_file(file: &File) {
+	let epoll = file.get_buffer::<EPoll>();
+	if epoll.is_none() && file.epoll_items.lock().is_empty() {
+		return;
+	}
+	let _guard = EPOLL_LOCK.lock();
+	let items = mem::take(&mut *file.epoll_items.lock());
+	for item in items {
+		item.epoll()
+			.interest
+			.lock()
+			.items
+			.retain(|i| !ptr::eq(Arc::as_ptr(i), Arc::as_ptr(&item)));
+		for queue in mem::take(&mut *item.queues.lock()) {
+			unsafe { queue.as_ref() }.unregister_epoll(&item);
+		}
+	}
+	if let Some(epoll) = epoll {
+		let items = mem::take(&mut epoll.interest.lock().items);
+		for item in items {
+			item.detach();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{file, file::pipe::PipeBuffer, memory::user::UserSlice};
+
+	#[test_case]
+	fn epoll_pipe() {
+		let pipe = Arc::new(PipeBuffer::new().unwrap()).unwrap();
+		let rd = File::open_floating(pipe.clone(), file::O_RDONLY).unwrap();
+		let wr = File::open_floating(pipe, file::O_WRONLY).unwrap();
+		let epoll_file =
+			File::open_floating(Arc::new(EPoll::default()).unwrap(), file::O_RDWR).unwrap();
+		let epoll = epoll_file.get_buffer::<EPoll>().unwrap();
+		let event = EPollEvent {
+			events: EPOLLIN | EPOLLET,
+			data: 42,
+		};
+		epoll.add(&epoll_file, 0, &rd, event).unwrap();
+		let mut events = Vec::new();
+		assert_eq!(epoll.collect(None, 8, &mut events), Ok(0));
+		let mut buf = [0u8; 4];
+		wr.ops
+			.write(&wr, 0, UserSlice::from_slice_mut(&mut buf))
+			.unwrap();
+		assert_eq!(epoll.collect(None, 8, &mut events), Ok(1));
+		assert_eq!({ events[0].data }, 42);
+		// Edge-triggered: not reported again until the next notification
+		events.clear();
+		assert_eq!(epoll.collect(None, 8, &mut events), Ok(0));
+		// Level-triggered: reported as long as there is data to read
+		let event = EPollEvent {
+			events: EPOLLIN,
+			data: 42,
+		};
+		epoll.modify(0, &rd, event).unwrap();
+		for _ in 0..2 {
+			events.clear();
+			assert_eq!(epoll.collect(None, 8, &mut events), Ok(1));
+		}
+		// Dropping the file removes it from the interest list
+		drop(rd);
+		assert!(epoll.interest.lock().items.is_empty());
+	}
+}
@@ -0,0 +1,157 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! An eventfd is a file holding a counter, used as an event wait/notify mechanism.
+//!
+//! Writing to the file adds to the counter, and reading from it resets the counter. In
+//! *semaphore* mode, reading decrements the counter by one instead.
+
+use crate::{
+	file::{
+		File, O_NONBLOCK, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
+	memory::user::UserSlice,
+	sync::mutex::Mutex,
+	syscall::select::{POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+};
+use core::hint::unlikely;
+use utils::{errno, errno::EResult};
+
+/// The maximum value of the counter.
+const MAX: u64 = u64::MAX - 1;
+
+/// An eventfd.
+#[derive(Debug)]
+pub struct EventFd {
+	/// The counter.
+	counter: Mutex<u64>,
+	/// Tells whether the file is in semaphore mode.
+	semaphore: bool,
+	/// The queue of threads waiting for the counter to change.
+	queue: WaitQueue,
+}
+
+impl EventFd {
+	/// Creates a new instance.
+	///
+	/// Arguments:
+	/// - `initval` is the initial value of the counter
+	/// - `semaphore` tells whether the file is in semaphore mode
+	pub fn new(initval: u32, semaphore: bool) -> Self {
+		Self {
+			counter: Mutex::new(initval as _),
+			semaphore,
+			queue: WaitQueue::new(),
+		}
+	}
+}
+
+impl FileOps for EventFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: 0o600,
+			..Default::default()
+		})
+	}
+
+	fn poll<'f>(
+		&'f self,
+		_file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&self.queue)?;
+		}
+		let counter = *self.counter.lock();
+		let mut events = 0;
+		if counter > 0 {
+			events |= POLLIN | POLLRDNORM;
+		}
+		if counter < MAX {
+			events |= POLLOUT | POLLWRNORM;
+		}
+		Ok(events & mask)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		if unlikely(buf.len() < size_of::<u64>()) {
+			return Err(errno!(EINVAL));
+		}
+		let val = self.queue.wait_until(|| {
+			let mut counter = self.counter.lock();
+			if *counter == 0 {
+				return (file.get_flags() & O_NONBLOCK != 0).then(|| Err(errno!(EAGAIN)));
+			}
+			let val = if self.semaphore { 1 } else { *counter };
+			*counter -= val;
+			Some(Ok(val))
+		})??;
+		self.queue.wake_all();
+		buf.copy_to_user(0, &val.to_ne_bytes())?;
+		Ok(size_of::<u64>())
+	}
+
+	fn write(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let mut val = [0u8; size_of::<u64>()];
+		let len = buf.copy_from_user(0, &mut val)?;
+		if unlikely(len < size_of::<u64>()) {
+			return Err(errno!(EINVAL));
+		}
+		let val = u64::from_ne_bytes(val);
+		if unlikely(val == u64::MAX) {
+			return Err(errno!(EINVAL));
+		}
+		self.queue.wait_until(|| {
+			let mut counter = self.counter.lock();
+			if *counter > MAX - val {
+				return (file.get_flags() & O_NONBLOCK != 0).then(|| Err(errno!(EAGAIN)));
+			}
+			*counter += val;
+			Some(Ok(()))
+		})??;
+		self.queue.wake_all();
+		Ok(size_of::<u64>())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::file::O_RDWR;
+	use utils::ptr::arc::Arc;
+
+	#[test_case]
+	fn eventfd_semaphore() {
+		let efd = Arc::new(EventFd::new(2, true)).unwrap();
+		let file = File::open_floating(efd, O_RDWR | O_NONBLOCK).unwrap();
+		let mut buf = 0u64.to_ne_bytes();
+		for _ in 0..2 {
+			let len = file
+				.ops
+				.read(&file, 0, UserSlice::from_slice_mut(&mut buf))
+				.unwrap();
+			assert_eq!(len, 8);
+			assert_eq!(u64::from_ne_bytes(buf), 1);
+		}
+		let res = file.ops.read(&file, 0, UserSlice::from_slice_mut(&mut buf));
+		assert_eq!(res, Err(errno!(EAGAIN)));
+	}
+}
@@ -29,13 +29,17 @@ use super::{
 	DirContext, File, INode, Mode, Stat,
 	perm::{Gid, Uid},
 	vfs,
+	wait_queue::PollTable,
 };
 use crate::{
 	device::BlkDev,
 	file::vfs::node::Node,
 	memory::{cache::RcFrame, user::UserSlice},
 	sync::mutex::Mutex,
-	syscall::ioctl,
+	syscall::{
+		ioctl,
+		select::{POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+	},
 	time::unit::Timestamp,
 };
 use core::{
@@ -279,11 +283,20 @@ pub trait FileOps: Any + Debug {
 	/// Arguments:
 	/// - `file` is the file to perform the operation onto
 	/// - `mask` is the mask of events to wait for
+	/// - `table` is the table to register the file's wait queues on, if the caller is to sleep
+	///   until an event occurs
 	///
 	/// On success, the function returns the mask events that occurred.
-	fn poll(&self, file: &File, mask: u32) -> EResult<u32> {
-		let _ = (file, mask);
-		Err(errno!(EINVAL))
+	///
+	/// The default implementation reports the file as always ready for reading and writing.
+	fn poll<'f>(
+		&'f self,
+		file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		let _ = (file, table);
+		Ok((POLLIN | POLLRDNORM | POLLOUT | POLLWRNORM) & mask)
 	}
 
 	/// Performs an ioctl operation on the device file.
@@ -514,6 +527,28 @@ impl Filesystem {
 		Ok(buf)
 	}
 
+	/// Returns the buffer associated with the ID `inode`, if any.
+	pub fn buffer_get(&self, inode: INode) -> Option<Arc<dyn FileOps>> {
+		self.buffers.lock().get(&inode).cloned()
+	}
+
+	/// Associates the buffer `buf` with the ID `inode`.
+	///
+	/// If a buffer is already associated with the ID, the function returns [`errno::EADDRINUSE`].
+	pub fn buffer_insert(&self, inode: INode, buf: Arc<dyn FileOps>) -> EResult<()> {
+		let mut buffers = self.buffers.lock();
+		if buffers.contains_key(&inode) {
+			return Err(errno!(EADDRINUSE));
+		}
+		buffers.insert(inode, buf)?;
+		Ok(())
+	}
+
+	/// Removes the buffer associated with the ID `inode`.
+	pub fn buffer_remove(&self, inode: INode) {
+		self.buffers.lock().remove(&inode);
+	}
+
 	/// Inserts a node in cache. If already present, the previous entry is dropped.
 	pub fn node_insert(&self, node: Arc<Node>) -> EResult<()> {
 		self.nodes.lock().insert(NodeWrapper(node))?;
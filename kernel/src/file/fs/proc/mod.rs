@@ -22,6 +22,7 @@
 mod mem_info;
 mod proc_dir;
 mod self_link;
+mod swaps;
 mod sys_dir;
 mod uptime;
 mod version;
@@ -46,9 +47,11 @@ use crate::{
 };
 use mem_info::MemInfo;
 use proc_dir::{
-	cmdline::Cmdline, cwd::Cwd, exe::Exe, mounts::Mounts, stat::StatNode, status::Status,
+	cmdline::Cmdline, cwd::Cwd, exe::Exe, mounts::Mounts, oom_score::OomScore,
+	oom_score_adj::OomScoreAdj, stat::StatNode, status::Status,
 };
 use self_link::SelfNode;
+use swaps::Swaps;
 use sys_dir::OsRelease;
 use uptime::Uptime;
 use utils::{
@@ -114,6 +117,14 @@ impl RootDir {
 				},
 				init: EitherOps::Node(|_| box_node(SelfNode)),
 			},
+			StaticEntry {
+				name: b"swaps",
+				stat: |_| Stat {
+					mode: FileType::Regular.to_mode() | 0o444,
+					..Default::default()
+				},
+				init: EitherOps::File(|_| box_file(Swaps)),
+			},
 			StaticEntry {
 				name: b"sys",
 				stat: |_| static_dir_stat(),
@@ -214,6 +225,20 @@ impl NodeOps for RootDir {
 								},
 								init: EitherOps::File(|pid| box_file(Mounts(pid))),
 							},
+							StaticEntry {
+								name: b"oom_score",
+								stat: |pid| {
+									proc_file_stat(pid, FileType::Regular.to_mode() | 0o444)
+								},
+								init: EitherOps::File(|pid| box_file(OomScore(pid))),
+							},
+							StaticEntry {
+								name: b"oom_score_adj",
+								stat: |pid| {
+									proc_file_stat(pid, FileType::Regular.to_mode() | 0o644)
+								},
+								init: EitherOps::File(|pid| box_file(OomScoreAdj(pid))),
+							},
 							StaticEntry {
 								name: b"stat",
 								stat: |pid| {
@@ -257,7 +282,11 @@ impl NodeOps for RootDir {
 		// Iterate on processes
 		let off = ctx.off as usize - Self::STATIC.entries.len();
 		let sched = SCHEDULER.lock();
-		let proc_iter = sched.iter_process().skip(off);
+		// Threads other than leaders are not listed
+		let proc_iter = sched
+			.iter_process()
+			.filter(|(_, proc)| proc.is_thread_leader())
+			.skip(off);
 		for (pid, _) in proc_iter {
 			let name = format!("{pid}")?;
 			let ent = DirEntry {
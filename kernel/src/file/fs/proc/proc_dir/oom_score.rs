@@ -0,0 +1,40 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `oom_score` file, which allows to retrieve the score used by the OOM
+//! killer to select the process to kill.
+
+use crate::{
+	file::{File, fs::FileOps},
+	format_content,
+	memory::{oom, user::UserSlice},
+	process::{Process, pid::Pid},
+};
+use utils::{errno, errno::EResult};
+
+/// The `oom_score` node of the proc.
+#[derive(Debug)]
+pub struct OomScore(pub Pid);
+
+impl FileOps for OomScore {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let score = oom::score(&proc);
+		format_content!(off, buf, "{score}\n")
+	}
+}
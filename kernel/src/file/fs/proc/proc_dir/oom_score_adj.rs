@@ -0,0 +1,70 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Implementation of the `oom_score_adj` file, which allows to retrieve and modify the
+//! adjustment applied to the process's OOM score.
+
+use crate::{
+	file::{File, fs::FileOps},
+	format_content,
+	memory::{
+		oom::{OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN},
+		user::UserSlice,
+	},
+	process::{Process, pid::Pid},
+};
+use core::{str, sync::atomic::Ordering::Relaxed};
+use utils::{errno, errno::EResult};
+
+/// The `oom_score_adj` node of the proc.
+#[derive(Debug)]
+pub struct OomScoreAdj(pub Pid);
+
+impl FileOps for OomScoreAdj {
+	fn read(&self, _file: &File, off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		let adj = proc.oom_score_adj.load(Relaxed);
+		format_content!(off, buf, "{adj}\n")
+	}
+
+	fn write(&self, _file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let mut val = [0u8; 16];
+		if buf.len() > val.len() {
+			return Err(errno!(EINVAL));
+		}
+		let len = buf.copy_from_user(0, &mut val)?;
+		let adj: i16 = str::from_utf8(&val[..len])
+			.ok()
+			.and_then(|s| s.trim().parse().ok())
+			.filter(|adj| (OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(adj))
+			.ok_or_else(|| errno!(EINVAL))?;
+		let proc = Process::get_by_pid(self.0).ok_or_else(|| errno!(ENOENT))?;
+		// Making the process less likely to be killed requires privileges
+		let privileged = Process::current().fs.lock().access_profile.is_privileged();
+		if adj < proc.oom_score_adj.load(Relaxed) && !privileged {
+			return Err(errno!(EACCES));
+		}
+		proc.oom_score_adj.store(adj, Relaxed);
+		Ok(len)
+	}
+
+	fn truncate(&self, _file: &File, _size: u64) -> EResult<()> {
+		// Allows opening the file with `O_TRUNC`, as shells do on redirections
+		Ok(())
+	}
+}
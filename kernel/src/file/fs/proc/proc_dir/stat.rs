@@ -42,6 +42,12 @@ impl FileOps for StatNode {
 				.map(|m| (m.exe_info.exe.name.as_bytes(), m.get_vmem_usage()))
 				.unwrap_or_default();
 			let user_regs = proc.user_regs();
+			let nice = proc.sched.get_nice() as i32;
+			let priority = if proc.sched.get_policy().is_realtime() {
+				-1 - proc.sched.get_rt_priority() as i32
+			} else {
+				20 + nice
+			};
 			// TODO Fill every fields with process's data
 			write!(
 				f,
@@ -54,11 +60,9 @@ TODO TODO TODO TODO TODO TODO TODO TODO TODO",
 				state_char = proc.get_state().as_char(),
 				ppid = proc.get_parent_pid(),
 				pgid = proc.get_pgid(),
-				sid = 0,            // TODO
+				sid = proc.get_sid(),
 				user_jiffies = 0,   // TODO
 				kernel_jiffies = 0, // TODO
-				priority = 0,       // TODO
-				nice = 0,           // TODO
 				num_threads = 1,    // TODO
 				sp = VirtAddr(user_regs.get_stack_address() as _),
 				pc = VirtAddr(user_regs.get_program_counter() as _),
@@ -24,11 +24,15 @@
 //! The root filesystem is passed to the kernel as an argument on boot.
 //! Other filesystems are mounted into subdirectories.
 
+pub mod epoll;
+pub mod eventfd;
 pub mod fd;
 pub mod fs;
 pub mod perm;
 pub mod pipe;
+pub mod signalfd;
 pub mod socket;
+pub mod timerfd;
 pub mod util;
 pub mod vfs;
 pub mod wait_queue;
@@ -39,11 +43,9 @@ use crate::{
 		fs::FileOps,
 		perm::{Gid, Uid},
 		pipe::PipeBuffer,
-		socket::Socket,
 		vfs::node::Node,
 	},
 	memory::user::UserSlice,
-	net::{SocketDesc, SocketDomain, SocketType},
 	sync::{atomic::AtomicU64, mutex::Mutex, once::OnceInit},
 	time::{
 		clock::{Clock, current_time_sec},
@@ -348,6 +350,8 @@ pub struct File {
 	pub flags: Mutex<i32>,
 	/// The current offset in the file.
 	pub off: AtomicU64,
+	/// The epoll items monitoring the file.
+	pub epoll_items: Mutex<Vec<Arc<epoll::Item>>>,
 }
 
 impl File {
@@ -366,15 +370,8 @@ impl File {
 			Some(FileType::Fifo) => {
 				FileOpsWrapper::Owned(node.fs.buffer_get_or_insert(node.inode, PipeBuffer::new)?)
 			}
-			Some(FileType::Socket) => {
-				FileOpsWrapper::Owned(node.fs.buffer_get_or_insert(node.inode, || {
-					Socket::new(SocketDesc {
-						domain: SocketDomain::AfUnix,
-						type_: SocketType::SockStream,
-						protocol: 0,
-					})
-				})?)
-			}
+			// UNIX sockets are reached through `connect`
+			Some(FileType::Socket) => return Err(errno!(ENXIO)),
 			Some(FileType::BlockDevice) => FileOpsWrapper::Owned(Arc::new(BlkDevFileOps)?),
 			Some(FileType::CharDevice) => {
 				let devices = CHAR_DEVICES.lock();
@@ -393,6 +390,7 @@ impl File {
 			ops,
 			flags: Mutex::new(flags),
 			off: Default::default(),
+			epoll_items: Default::default(),
 		};
 		file.ops.acquire(&file);
 		Ok(Arc::new(file)?)
@@ -405,6 +403,7 @@ impl File {
 			ops: FileOpsWrapper::Owned(ops),
 			flags: Mutex::new(flags),
 			off: Default::default(),
+			epoll_items: Default::default(),
 		};
 		file.ops.acquire(&file);
 		Ok(Arc::new(file)?)
@@ -420,6 +419,14 @@ impl File {
 		(self.ops.deref() as &dyn Any).downcast_ref::<B>()
 	}
 
+	/// Returns the underlying buffer as an [`Arc`], if any.
+	pub fn get_buffer_arc<B: FileOps>(&self) -> Option<Arc<B>> {
+		let FileOpsWrapper::Owned(ops) = &self.ops else {
+			return None;
+		};
+		(ops.clone() as Arc<dyn Any>).downcast().ok()
+	}
+
 	/// If the file is a block device, returns the associated device.
 	pub fn as_block_device(&self) -> Option<Arc<BlkDev>> {
 		let stat = self.stat().unwrap();
@@ -521,15 +528,21 @@ impl File {
 
 	/// Closes the file, removing the underlying node if no link remain and this was the last
 	/// use of it.
-	pub fn close(self) -> EResult<()> {
+	pub fn close(mut self) -> EResult<()> {
 		self.ops.release(&self);
-		if let Some(ent) = self.vfs_entry {
+		if let Some(ent) = self.vfs_entry.take() {
 			vfs::Entry::release(ent)?;
 		}
 		Ok(())
 	}
 }
 
+impl Drop for File {
+	fn drop(&mut self) {
+		epoll::release_file(self);
+	}
+}
+
 impl AccessProfile {
 	fn check_read_access_impl(uid: Uid, gid: Gid, stat: &Stat) -> bool {
 		// If root, bypass checks
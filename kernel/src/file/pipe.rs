@@ -20,14 +20,21 @@
 //! and another writing, with a buffer in between.
 
 use crate::{
-	file::{File, FileType, O_NONBLOCK, Stat, fs::FileOps, wait_queue::WaitQueue},
+	file::{
+		File, FileType, O_NONBLOCK, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
 	memory::{
 		ring_buffer::RingBuffer,
 		user::{UserPtr, UserSlice},
 	},
 	process::{Process, signal::Signal},
 	sync::mutex::Mutex,
-	syscall::{FromSyscallArg, ioctl},
+	syscall::{
+		FromSyscallArg, ioctl,
+		select::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+	},
 };
 use core::{
 	ffi::{c_int, c_void},
@@ -113,8 +120,34 @@ impl FileOps for PipeBuffer {
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll<'f>(
+		&'f self,
+		file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&self.rd_queue)?;
+			table.register(&self.wr_queue)?;
+		}
+		let inner = self.inner.lock();
+		let mut events = 0;
+		if file.can_read() {
+			if !inner.buffer.is_empty() {
+				events |= POLLIN | POLLRDNORM;
+			}
+			if inner.writers == 0 {
+				events |= POLLHUP;
+			}
+		}
+		if file.can_write() {
+			if inner.readers == 0 {
+				events |= POLLERR;
+			} else if !inner.buffer.is_full() {
+				events |= POLLOUT | POLLWRNORM;
+			}
+		}
+		Ok(events & mask)
 	}
 
 	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
@@ -140,7 +173,7 @@ impl FileOps for PipeBuffer {
 				Err(e) => return Some(Err(e)),
 			};
 			if len > 0 {
-				self.wr_queue.wake_next();
+				self.wr_queue.wake_all();
 				return Some(Ok(len));
 			}
 			// Nothing to read
@@ -171,7 +204,7 @@ impl FileOps for PipeBuffer {
 				Err(e) => return Some(Err(e)),
 			};
 			if len > 0 {
-				self.rd_queue.wake_next();
+				self.rd_queue.wake_all();
 				return Some(Ok(len));
 			}
 			// No space left to write
@@ -0,0 +1,205 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A signalfd is a file allowing to accept signals, as an alternative to signal handlers.
+//!
+//! Reading from the file consumes the pending signals of the current thread that are in the
+//! file's mask. The signals are usually blocked, so that they are not handled otherwise.
+
+use crate::{
+	file::{
+		File, O_NONBLOCK, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
+	memory::user::UserSlice,
+	process::{
+		Process,
+		signal::{SigInfo, SigSet, Signal},
+	},
+	sync::mutex::Mutex,
+	syscall::select::{POLLIN, POLLRDNORM},
+};
+use core::hint::unlikely;
+use utils::{bytes::as_bytes, errno, errno::EResult};
+
+/// The queue of threads waiting for a signal on a signalfd or through `sigtimedwait`.
+///
+/// A single queue is shared by all waiters since signals are rare enough.
+pub static WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Wakes up the threads waiting for a signal on a signalfd or through `sigtimedwait`.
+///
+/// This function is called each time a signal is sent.
+pub fn notify() {
+	WAIT_QUEUE.wake_all();
+}
+
+/// Userspace structure describing a signal read from a signalfd.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SignalfdSiginfo {
+	/// Signal number.
+	pub ssi_signo: u32,
+	/// Error number (unused).
+	pub ssi_errno: i32,
+	/// Signal code.
+	pub ssi_code: i32,
+	/// PID of the sender.
+	pub ssi_pid: u32,
+	/// Real UID of the sender.
+	pub ssi_uid: u32,
+	/// File descriptor (`SIGIO`).
+	pub ssi_fd: i32,
+	/// Kernel timer ID (POSIX timers).
+	pub ssi_tid: u32,
+	/// Band event (`SIGIO`).
+	pub ssi_band: u32,
+	/// POSIX timer overrun count.
+	pub ssi_overrun: u32,
+	/// Trap number that caused the signal.
+	pub ssi_trapno: u32,
+	/// Exit status or signal (`SIGCHLD`).
+	pub ssi_status: i32,
+	/// Integer sent by `sigqueue`.
+	pub ssi_int: i32,
+	/// Pointer sent by `sigqueue`.
+	pub ssi_ptr: u64,
+	/// User CPU time consumed (`SIGCHLD`).
+	pub ssi_utime: u64,
+	/// System CPU time consumed (`SIGCHLD`).
+	pub ssi_stime: u64,
+	/// Address that generated the signal (for hardware-generated signals).
+	pub ssi_addr: u64,
+	/// Least significant bit of address (`SIGBUS`).
+	pub ssi_addr_lsb: u16,
+	/// Padding.
+	pub __pad2: u16,
+	/// System call number (`SIGSYS`).
+	pub ssi_syscall: i32,
+	/// Address of the system call instruction (`SIGSYS`).
+	pub ssi_call_addr: u64,
+	/// Architecture of the attempted system call (`SIGSYS`).
+	pub ssi_arch: u32,
+	/// Padding, to make the structure 128 bytes long.
+	pub __pad: [u8; 28],
+}
+
+impl From<&SigInfo> for SignalfdSiginfo {
+	fn from(info: &SigInfo) -> Self {
+		Self {
+			ssi_signo: info.si_signo as _,
+			ssi_errno: info.si_errno,
+			ssi_code: info.si_code,
+			ssi_pid: info.si_pid as _,
+			ssi_uid: info.si_uid as _,
+			ssi_fd: info.si_fd,
+			ssi_tid: info.si_timerid as _,
+			ssi_band: info.si_band as _,
+			ssi_overrun: info.si_overrun as _,
+			ssi_status: info.si_status,
+			ssi_int: info.si_value as _,
+			ssi_ptr: info.si_value as _,
+			ssi_utime: info.si_utime as _,
+			ssi_stime: info.si_stime as _,
+			ssi_addr: info.si_addr as _,
+			..Default::default()
+		}
+	}
+}
+
+/// A signalfd.
+#[derive(Debug)]
+pub struct SignalFd {
+	/// The set of signals to accept.
+	mask: Mutex<SigSet>,
+}
+
+impl SignalFd {
+	/// Creates a new instance accepting the signals in `mask`.
+	pub fn new(mask: SigSet) -> Self {
+		let this = Self {
+			mask: Mutex::new(SigSet::default()),
+		};
+		this.set_mask(mask);
+		this
+	}
+
+	/// Sets the set of signals to accept.
+	///
+	/// `SIGKILL` and `SIGSTOP` cannot be accepted and are silently ignored.
+	pub fn set_mask(&self, mut mask: SigSet) {
+		mask.clear(Signal::SIGKILL as _);
+		mask.clear(Signal::SIGSTOP as _);
+		*self.mask.lock() = mask;
+	}
+}
+
+impl FileOps for SignalFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: 0o600,
+			..Default::default()
+		})
+	}
+
+	fn poll<'f>(
+		&'f self,
+		_file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&WAIT_QUEUE)?;
+		}
+		let pending = Process::current().signal.lock().pending();
+		let events = if pending.0 & self.mask.lock().0 != 0 {
+			POLLIN | POLLRDNORM
+		} else {
+			0
+		};
+		Ok(events & mask)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let max = buf.len() / size_of::<SignalfdSiginfo>();
+		if unlikely(max == 0) {
+			return Err(errno!(EINVAL));
+		}
+		let mask = *self.mask.lock();
+		let proc = Process::current();
+		// Wait for the first signal, then take the other pending ones
+		let first = WAIT_QUEUE.wait_until(|| match proc.signal.lock().take_signal(mask) {
+			Some(info) => Some(Ok(info)),
+			None if file.get_flags() & O_NONBLOCK != 0 => Some(Err(errno!(EAGAIN))),
+			None => None,
+		})??;
+		let mut info = Some(first);
+		let mut count = 0;
+		while let Some(i) = info {
+			let info_user = SignalfdSiginfo::from(&i);
+			buf.copy_to_user(count * size_of::<SignalfdSiginfo>(), as_bytes(&info_user))?;
+			count += 1;
+			if count >= max {
+				break;
+			}
+			info = proc.signal.lock().take_signal(mask);
+		}
+		Ok(count * size_of::<SignalfdSiginfo>())
+	}
+}
@@ -19,28 +19,213 @@
 //! This file implements sockets.
 
 use crate::{
-	file::{File, FileType, Stat, fs::FileOps, wait_queue::WaitQueue},
+	file::{
+		File, FileType, O_NONBLOCK, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
 	memory::{ring_buffer::RingBuffer, user::UserSlice},
-	net::{SocketDesc, osi},
+	net,
+	net::{
+		SocketDesc, SocketDomain, SocketType, netlink, osi, sockaddr::SockAddr, tcp, udp, unix,
+		unix::UnixState,
+	},
+	process::{Process, signal::Signal},
 	sync::mutex::Mutex,
-	syscall::ioctl,
+	syscall::{
+		ioctl,
+		select::{POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM},
+	},
+	time::unit::{TimeUnit, Timestamp, Timeval, Timeval32},
 };
 use core::{
-	ffi::{c_int, c_void},
+	ffi::{c_int, c_long, c_void},
+	hint::unlikely,
+	mem::size_of,
 	num::NonZeroUsize,
 	sync::{atomic, atomic::AtomicUsize},
 };
 use utils::{
+	bytes::as_bytes,
 	collections::vec::Vec,
 	errno,
-	errno::{AllocResult, EResult},
+	errno::{AllocResult, EResult, Errno},
+	ptr::arc::Arc,
 };
 
-/// The maximum size of a socket's buffers.
+/// A message received on a socket.
+#[derive(Debug, Default)]
+pub struct Message {
+	/// The number of bytes received.
+	pub len: usize,
+	/// The address of the sender, if available.
+	pub src: Option<Vec<u8>>,
+	/// The files received along with the data (`SCM_RIGHTS`).
+	pub rights: Vec<Arc<File>>,
+	/// Tells whether the message has been truncated because the buffer was too small.
+	pub truncated: bool,
+}
+
+/// The default size of a socket's buffers.
 const BUFFER_SIZE: usize = 65536;
+/// The minimum size of a socket's buffers.
+const MIN_BUFFER_SIZE: usize = 2048;
+/// The maximum size of a socket's buffers.
+const MAX_BUFFER_SIZE: usize = 4 * 1024 * 1024;
 
 /// Socket option level: Socket
-const SOL_SOCKET: c_int = 1;
+pub const SOL_SOCKET: c_int = 1;
+/// Socket option level: TCP
+pub const IPPROTO_TCP: c_int = 6;
+
+/// Socket option: allow reusing local addresses
+const SO_REUSEADDR: c_int = 2;
+/// Socket option: socket type
+const SO_TYPE: c_int = 3;
+/// Socket option: get and clear the pending error
+const SO_ERROR: c_int = 4;
+/// Socket option: size of the transmit buffer
+const SO_SNDBUF: c_int = 7;
+/// Socket option: size of the receive buffer
+const SO_RCVBUF: c_int = 8;
+/// Socket option: send keepalive probes on connections
+const SO_KEEPALIVE: c_int = 9;
+/// Socket option: receive timeout, as a `timeval` with `long` fields
+const SO_RCVTIMEO_OLD: c_int = 20;
+/// Socket option: transmit timeout, as a `timeval` with `long` fields
+const SO_SNDTIMEO_OLD: c_int = 21;
+/// Socket option: receive timeout, as a `timeval` with 64 bits fields
+const SO_RCVTIMEO_NEW: c_int = 66;
+/// Socket option: transmit timeout, as a `timeval` with 64 bits fields
+const SO_SNDTIMEO_NEW: c_int = 67;
+
+/// TCP option: disable Nagle's algorithm
+const TCP_NODELAY: c_int = 1;
+
+/// A socket option.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SockOpt {
+	/// `SO_REUSEADDR`
+	ReuseAddr,
+	/// `SO_TYPE`
+	Type,
+	/// `SO_ERROR`
+	Error,
+	/// `SO_SNDBUF`
+	SndBuf,
+	/// `SO_RCVBUF`
+	RcvBuf,
+	/// `SO_KEEPALIVE`
+	KeepAlive,
+	/// `SO_RCVTIMEO`. `true` if the value uses 64 bits fields regardless of the architecture.
+	RcvTimeo(bool),
+	/// `SO_SNDTIMEO`. `true` if the value uses 64 bits fields regardless of the architecture.
+	SndTimeo(bool),
+	/// `TCP_NODELAY`
+	TcpNoDelay,
+}
+
+impl SockOpt {
+	/// Returns the option with the given `level` and `optname`.
+	///
+	/// If the option does not exist, the function returns [`errno::ENOPROTOOPT`].
+	fn new(level: c_int, optname: c_int) -> EResult<Self> {
+		let opt = match (level, optname) {
+			(SOL_SOCKET, SO_REUSEADDR) => Self::ReuseAddr,
+			(SOL_SOCKET, SO_TYPE) => Self::Type,
+			(SOL_SOCKET, SO_ERROR) => Self::Error,
+			(SOL_SOCKET, SO_SNDBUF) => Self::SndBuf,
+			(SOL_SOCKET, SO_RCVBUF) => Self::RcvBuf,
+			(SOL_SOCKET, SO_KEEPALIVE) => Self::KeepAlive,
+			(SOL_SOCKET, SO_RCVTIMEO_OLD) => Self::RcvTimeo(false),
+			(SOL_SOCKET, SO_SNDTIMEO_OLD) => Self::SndTimeo(false),
+			(SOL_SOCKET, SO_RCVTIMEO_NEW) => Self::RcvTimeo(true),
+			(SOL_SOCKET, SO_SNDTIMEO_NEW) => Self::SndTimeo(true),
+			(IPPROTO_TCP, TCP_NODELAY) => Self::TcpNoDelay,
+			_ => return Err(errno!(ENOPROTOOPT)),
+		};
+		Ok(opt)
+	}
+}
+
+/// Encodes the timeout `ts` as a `timeval` structure.
+///
+/// If `long` is set, the structure uses 64 bits fields. Else, it uses `long` fields.
+fn timeval_to_bytes(ts: Timestamp, long: bool) -> AllocResult<Vec<u8>> {
+	if long {
+		Vec::try_from(as_bytes(&Timeval::from_nano(ts)))
+	} else {
+		Vec::try_from(as_bytes(&Timeval32::from_nano(ts)))
+	}
+}
+
+/// Decodes a timeout from the `timeval` structure in `buf`.
+///
+/// If `long` is set, the structure uses 64 bits fields. Else, it uses `long` fields.
+///
+/// If the structure is invalid, the function returns [`errno::EINVAL`], or [`errno::EDOM`] if
+/// the microseconds are out of range.
+fn timeval_from_bytes(buf: &[u8], long: bool) -> EResult<Timestamp> {
+	let (sec, usec) = if long {
+		let Some(buf) = buf.first_chunk::<16>() else {
+			return Err(errno!(EINVAL));
+		};
+		let sec = i64::from_ne_bytes(buf[..8].try_into().unwrap());
+		let usec = i64::from_ne_bytes(buf[8..].try_into().unwrap());
+		(sec, usec)
+	} else {
+		let Some(buf) = buf.first_chunk::<8>() else {
+			return Err(errno!(EINVAL));
+		};
+		let sec = i32::from_ne_bytes(buf[..4].try_into().unwrap());
+		let usec = i32::from_ne_bytes(buf[4..].try_into().unwrap());
+		(sec as i64, usec as i64)
+	};
+	if !(0..1_000_000).contains(&usec) {
+		return Err(errno!(EDOM));
+	}
+	// A negative timeout is treated as no timeout at all
+	if sec < 0 {
+		return Ok(0);
+	}
+	Ok((sec as u64)
+		.saturating_mul(1_000_000_000)
+		.saturating_add(usec as u64 * 1000))
+}
+
+/// Options of a socket.
+#[derive(Clone, Debug, Default)]
+pub struct SockOpts {
+	/// `SO_REUSEADDR`: allows binding to a local address used by connections, as long as no
+	/// socket is listening on it.
+	pub reuse_addr: bool,
+	/// `SO_KEEPALIVE`: sends probes on idle connections to detect dead peers.
+	pub keepalive: bool,
+	/// `SO_RCVTIMEO`: timeout of blocking receive operations, in nanoseconds. Zero means no
+	/// timeout.
+	pub rcv_timeout: Timestamp,
+	/// `SO_SNDTIMEO`: timeout of blocking transmit operations, in nanoseconds. Zero means no
+	/// timeout.
+	pub snd_timeout: Timestamp,
+	/// `TCP_NODELAY`: disables Nagle's algorithm.
+	pub tcp_nodelay: bool,
+}
+
+/// Message flag: return data without removing it from the queue.
+pub const MSG_PEEK: c_int = 0x2;
+/// Message flag: return the real length of the message, even if it was truncated.
+pub const MSG_TRUNC: c_int = 0x20;
+/// Message flag: do not raise `SIGPIPE` when sending on a connection that has been shut down.
+pub const MSG_NOSIGNAL: c_int = 0x4000;
+
+/// Returns the error for a send on a connection that has been shut down, raising `SIGPIPE` on
+/// the current process unless `flags` contains [`MSG_NOSIGNAL`].
+pub fn broken_pipe(flags: c_int) -> Errno {
+	if flags & MSG_NOSIGNAL == 0 {
+		Process::current().kill(Signal::SIGPIPE);
+	}
+	errno!(EPIPE)
+}
 
 /// A UNIX socket.
 #[derive(Debug)]
@@ -48,7 +233,7 @@ pub struct Socket {
 	/// The socket's stack descriptor.
 	desc: SocketDesc,
 	/// The socket's network stack corresponding to the descriptor.
-	stack: Option<osi::Stack>,
+	stack: Mutex<Option<osi::Stack>>,
 	/// The number of entities owning a reference to the socket. When this count reaches zero, the
 	/// socket is closed.
 	open_count: AtomicUsize,
@@ -65,6 +250,14 @@ pub struct Socket {
 	rx_queue: WaitQueue,
 	/// Transmit wait queue.
 	tx_queue: WaitQueue,
+
+	/// The socket's options.
+	opts: Mutex<SockOpts>,
+	/// The pending error on the socket, reported asynchronously by the protocol.
+	error: Mutex<Option<Errno>>,
+
+	/// The state of the socket, if in the `AF_UNIX` domain.
+	unix: Mutex<UnixState>,
 }
 
 impl Socket {
@@ -72,7 +265,7 @@ impl Socket {
 	pub fn new(desc: SocketDesc) -> AllocResult<Self> {
 		Ok(Self {
 			desc,
-			stack: None,
+			stack: Mutex::new(None),
 			open_count: AtomicUsize::new(0),
 
 			sockname: Default::default(),
@@ -86,9 +279,30 @@ impl Socket {
 
 			rx_queue: WaitQueue::new(),
 			tx_queue: WaitQueue::new(),
+
+			opts: Default::default(),
+			error: Mutex::new(None),
+
+			unix: Default::default(),
 		})
 	}
 
+	/// Creates a socket for a connection accepted by `self`, inheriting its options and buffer
+	/// sizes.
+	pub fn new_child(&self) -> EResult<Self> {
+		let child = Self::new(self.desc)?;
+		*child.opts.lock() = self.opts.lock().clone();
+		let rx_size = self.rx_buff.lock().as_ref().map(RingBuffer::capacity);
+		if let Some(size) = rx_size {
+			child.set_buff_size(&child.rx_buff, size)?;
+		}
+		let tx_size = self.tx_buff.lock().as_ref().map(RingBuffer::capacity);
+		if let Some(size) = tx_size {
+			child.set_buff_size(&child.tx_buff, size)?;
+		}
+		Ok(child)
+	}
+
 	/// Returns the socket's descriptor.
 	#[inline(always)]
 	pub fn desc(&self) -> &SocketDesc {
@@ -97,8 +311,93 @@ impl Socket {
 
 	/// Returns the socket's network stack.
 	#[inline(always)]
-	pub fn stack(&self) -> Option<&osi::Stack> {
-		self.stack.as_ref()
+	pub fn stack(&self) -> &Mutex<Option<osi::Stack>> {
+		&self.stack
+	}
+
+	/// Returns the buffer containing received data.
+	#[inline(always)]
+	pub fn rx_buff(&self) -> &Mutex<Option<RingBuffer>> {
+		&self.rx_buff
+	}
+
+	/// Returns the buffer containing data to be transmitted.
+	#[inline(always)]
+	pub fn tx_buff(&self) -> &Mutex<Option<RingBuffer>> {
+		&self.tx_buff
+	}
+
+	/// Returns the receive wait queue.
+	#[inline(always)]
+	pub fn rx_queue(&self) -> &WaitQueue {
+		&self.rx_queue
+	}
+
+	/// Returns the transmit wait queue.
+	#[inline(always)]
+	pub fn tx_queue(&self) -> &WaitQueue {
+		&self.tx_queue
+	}
+
+	/// Returns the socket's options.
+	#[inline(always)]
+	pub fn opts(&self) -> &Mutex<SockOpts> {
+		&self.opts
+	}
+
+	/// Sets the pending error on the socket, replacing the previous one.
+	///
+	/// The error is reported by the next `SO_ERROR` query or receive operation.
+	pub fn set_error(&self, err: Errno) {
+		*self.error.lock() = Some(err);
+	}
+
+	/// Returns and clears the pending error on the socket, if any.
+	pub fn take_error(&self) -> Option<Errno> {
+		self.error.lock().take()
+	}
+
+	/// Returns the state of the socket in the `AF_UNIX` domain.
+	#[inline(always)]
+	pub fn unix(&self) -> &Mutex<UnixState> {
+		&self.unix
+	}
+
+	/// Tells whether the socket is in the `AF_UNIX` domain.
+	fn is_unix(&self) -> bool {
+		self.desc.domain == SocketDomain::AfUnix
+	}
+
+	/// Tells whether the socket is in the `AF_NETLINK` domain.
+	fn is_netlink(&self) -> bool {
+		self.desc.domain == SocketDomain::AfNetlink
+	}
+
+	/// Tells whether the socket uses the TCP protocol.
+	fn is_tcp(&self) -> bool {
+		matches!(
+			self.desc.domain,
+			SocketDomain::AfInet | SocketDomain::AfInet6
+		) && self.desc.type_ == SocketType::SockStream
+	}
+
+	/// Tells whether the socket uses the UDP protocol.
+	fn is_udp(&self) -> bool {
+		matches!(
+			self.desc.domain,
+			SocketDomain::AfInet | SocketDomain::AfInet6
+		) && self.desc.type_ == SocketType::SockDgram
+	}
+
+	/// Resizes the buffer `buff` to `size` bytes.
+	///
+	/// If the buffer has been shut down, the function does nothing.
+	fn set_buff_size(&self, buff: &Mutex<Option<RingBuffer>>, size: usize) -> AllocResult<()> {
+		let size = size.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE);
+		if let Some(buff) = buff.lock().as_mut() {
+			buff.resize(NonZeroUsize::new(size).unwrap())?;
+		}
+		Ok(())
 	}
 
 	/// Reads the given socket option.
@@ -106,9 +405,35 @@ impl Socket {
 	/// Arguments:
 	/// - `level` is the level (protocol) at which the option is located.
 	/// - `optname` is the name of the option.
-	pub fn get_opt(&self, _level: c_int, _optname: c_int) -> EResult<&[u8]> {
-		// TODO
-		todo!()
+	/// - `compat` tells whether the caller is a 32 bits process.
+	///
+	/// If the option does not exist, the function returns [`errno::ENOPROTOOPT`].
+	pub fn get_opt(&self, level: c_int, optname: c_int, compat: bool) -> EResult<Vec<u8>> {
+		let opt = SockOpt::new(level, optname)?;
+		let int = |val: c_int| Vec::try_from(val.to_ne_bytes().as_slice());
+		let buff_size = |buff: &Mutex<Option<RingBuffer>>| {
+			buff.lock().as_ref().map(RingBuffer::capacity).unwrap_or(0) as c_int
+		};
+		let long = !compat && size_of::<c_long>() == 8;
+		let opts = self.opts.lock().clone();
+		let val = match opt {
+			SockOpt::ReuseAddr => int(opts.reuse_addr as _)?,
+			SockOpt::Type => int(self.desc.type_.get_id() as _)?,
+			SockOpt::Error => {
+				let err = self
+					.take_error()
+					.or_else(|| self.is_tcp().then(|| tcp::take_error(self)).flatten());
+				int(err.map(|e| e.as_int()).unwrap_or(0))?
+			}
+			SockOpt::SndBuf => int(buff_size(&self.tx_buff))?,
+			SockOpt::RcvBuf => int(buff_size(&self.rx_buff))?,
+			SockOpt::KeepAlive => int(opts.keepalive as _)?,
+			SockOpt::RcvTimeo(new) => timeval_to_bytes(opts.rcv_timeout, new || long)?,
+			SockOpt::SndTimeo(new) => timeval_to_bytes(opts.snd_timeout, new || long)?,
+			SockOpt::TcpNoDelay if self.is_tcp() => int(opts.tcp_nodelay as _)?,
+			SockOpt::TcpNoDelay => return Err(errno!(EOPNOTSUPP)),
+		};
+		Ok(val)
 	}
 
 	/// Writes the given socket option.
@@ -117,11 +442,55 @@ impl Socket {
 	/// - `level` is the level (protocol) at which the option is located.
 	/// - `optname` is the name of the option.
 	/// - `optval` is the value of the option.
+	/// - `compat` tells whether the caller is a 32 bits process.
 	///
-	/// The function returns a value to be returned by the syscall on success.
-	pub fn set_opt(&self, _level: c_int, _optname: c_int, _optval: &[u8]) -> EResult<c_int> {
-		// TODO
-		Ok(0)
+	/// If the option does not exist, the function returns [`errno::ENOPROTOOPT`]. If the option
+	/// cannot be written, the function returns [`errno::EINVAL`].
+	pub fn set_opt(
+		&self,
+		level: c_int,
+		optname: c_int,
+		optval: &[u8],
+		compat: bool,
+	) -> EResult<()> {
+		let opt = SockOpt::new(level, optname)?;
+		let int = || {
+			optval
+				.first_chunk()
+				.map(|b| c_int::from_ne_bytes(*b))
+				.ok_or_else(|| errno!(EINVAL))
+		};
+		let long = !compat && size_of::<c_long>() == 8;
+		match opt {
+			SockOpt::ReuseAddr => self.opts.lock().reuse_addr = int()? != 0,
+			SockOpt::Type | SockOpt::Error => return Err(errno!(ENOPROTOOPT)),
+			// The size is doubled to account for bookkeeping overhead, like Linux does
+			SockOpt::SndBuf => {
+				let size = (int()?.max(0) as usize).saturating_mul(2);
+				self.set_buff_size(&self.tx_buff, size)?;
+			}
+			SockOpt::RcvBuf => {
+				let size = (int()?.max(0) as usize).saturating_mul(2);
+				self.set_buff_size(&self.rx_buff, size)?;
+			}
+			SockOpt::KeepAlive => self.opts.lock().keepalive = int()? != 0,
+			SockOpt::RcvTimeo(new) => {
+				self.opts.lock().rcv_timeout = timeval_from_bytes(optval, new || long)?;
+			}
+			SockOpt::SndTimeo(new) => {
+				self.opts.lock().snd_timeout = timeval_from_bytes(optval, new || long)?;
+			}
+			SockOpt::TcpNoDelay if self.is_tcp() => {
+				let nodelay = int()? != 0;
+				self.opts.lock().tcp_nodelay = nodelay;
+				// Send data held back by Nagle's algorithm
+				if nodelay {
+					tcp::flush(self);
+				}
+			}
+			SockOpt::TcpNoDelay => return Err(errno!(EOPNOTSUPP)),
+		}
+		Ok(())
 	}
 
 	/// Returns the name of the socket.
@@ -135,27 +504,207 @@ impl Socket {
 	///
 	/// If the socket is already bound, or if the address is invalid, or if the address is already
 	/// in used, the function returns an error.
-	pub fn bind(&self, sockaddr: &[u8]) -> EResult<()> {
-		let mut sockname = self.sockname.lock();
+	pub fn bind(this: &Arc<Self>, sockaddr: &[u8]) -> EResult<()> {
+		if this.is_udp() {
+			return udp::bind(this, SockAddr::from_bytes(sockaddr)?);
+		}
+		if this.is_tcp() {
+			return tcp::bind(this, SockAddr::from_bytes(sockaddr)?);
+		}
+		if this.is_unix() {
+			return unix::bind(this, sockaddr);
+		}
+		if this.is_netlink() {
+			return netlink::bind(this, sockaddr);
+		}
+		let mut sockname = this.sockname.lock();
 		if !sockname.is_empty() {
 			return Err(errno!(EINVAL));
 		}
 		// TODO check if address is already in used (EADDRINUSE)
 		// TODO check the requested network interface exists (EADDRNOTAVAIL)
-		// TODO check address against stack's domain
+		if matches!(
+			this.desc.domain,
+			SocketDomain::AfInet | SocketDomain::AfInet6
+		) {
+			let addr = SockAddr::from_bytes(sockaddr)?;
+			if addr.domain() != this.desc.domain {
+				return Err(errno!(EAFNOSUPPORT));
+			}
+		}
 
 		*sockname = Vec::try_from(sockaddr)?;
 		Ok(())
 	}
 
+	/// Connects the socket to the given address.
+	///
+	/// Arguments:
+	/// - `sockaddr` is the address to connect to.
+	/// - `nonblock` tells whether the function shall return without waiting for the connection to
+	///   be established.
+	pub fn connect(this: &Arc<Self>, sockaddr: &[u8], nonblock: bool) -> EResult<()> {
+		if this.is_tcp() {
+			tcp::connect(this, sockaddr, nonblock)
+		} else if this.is_udp() {
+			udp::connect(this, sockaddr)
+		} else if this.is_unix() {
+			unix::connect(this, sockaddr, nonblock)
+		} else {
+			// TODO other protocols
+			Err(errno!(EOPNOTSUPP))
+		}
+	}
+
+	/// Marks the socket as accepting connections.
+	///
+	/// `backlog` is the maximum number of pending connections.
+	pub fn listen(this: &Arc<Self>, backlog: usize) -> EResult<()> {
+		if this.is_tcp() {
+			tcp::listen(this, backlog)
+		} else if this.is_unix() {
+			unix::listen(this, backlog)
+		} else {
+			Err(errno!(EOPNOTSUPP))
+		}
+	}
+
+	/// Accepts a pending connection, returning a new socket for it.
+	///
+	/// If `nonblock` is set and no connection is pending, the function returns
+	/// [`errno::EAGAIN`].
+	pub fn accept(&self, nonblock: bool) -> EResult<Arc<Socket>> {
+		if self.is_tcp() {
+			tcp::accept(self, nonblock)
+		} else if self.is_unix() {
+			unix::accept(self, nonblock)
+		} else {
+			Err(errno!(EOPNOTSUPP))
+		}
+	}
+
+	/// Connects both sockets to each other.
+	///
+	/// If the sockets' domain does not support this operation, the function returns
+	/// [`errno::EOPNOTSUPP`].
+	pub fn pair(a: &Arc<Self>, b: &Arc<Self>) -> EResult<()> {
+		if !a.is_unix() {
+			return Err(errno!(EOPNOTSUPP));
+		}
+		unix::pair(a, b);
+		Ok(())
+	}
+
+	/// Returns the address of the peer the socket is connected to.
+	///
+	/// If the socket is not connected, the function returns [`errno::ENOTCONN`].
+	pub fn get_peername(&self) -> EResult<Vec<u8>> {
+		if self.is_unix() {
+			return unix::get_peer_addr(self);
+		}
+		let addr = if self.is_tcp() {
+			tcp::get_peer_addr(self)
+		} else if self.is_udp() {
+			udp::get_peer_addr(self)
+		} else {
+			None
+		};
+		let addr = addr.ok_or_else(|| errno!(ENOTCONN))?;
+		Ok(addr.to_bytes()?)
+	}
+
+	/// Sends the message in `buf`.
+	///
+	/// Arguments:
+	/// - `dst` is the address of the destination. If `None`, the message is sent to the peer the
+	///   socket is connected to.
+	/// - `rights` is the list of files to be passed along with the message (`SCM_RIGHTS`).
+	/// - `flags` are the `MSG_*` flags passed to the system call.
+	/// - `nonblock` tells whether the function shall return instead of waiting for buffer space.
+	///
+	/// The function returns the number of bytes sent.
+	pub fn send_msg(
+		this: &Arc<Self>,
+		buf: UserSlice<u8>,
+		dst: Option<&[u8]>,
+		rights: Vec<Arc<File>>,
+		flags: c_int,
+		nonblock: bool,
+	) -> EResult<usize> {
+		if this.is_unix() {
+			return unix::send(this, buf, dst, rights, flags, nonblock);
+		}
+		// Passing files is supported only by UNIX sockets
+		if !rights.is_empty() {
+			return Err(errno!(EINVAL));
+		}
+		if this.is_udp() {
+			return udp::send_to(this, buf, dst);
+		}
+		if this.is_netlink() {
+			return netlink::send(this, buf, dst);
+		}
+		// Connection-mode sockets ignore the destination
+		if unlikely(buf.is_empty()) {
+			return Ok(0);
+		}
+		if this.is_tcp() {
+			return tcp::write(this, buf, flags, nonblock);
+		}
+		Err(errno!(EOPNOTSUPP))
+	}
+
+	/// Receives a message into `buf`.
+	///
+	/// Arguments:
+	/// - `flags` are the `MSG_*` flags passed to the system call.
+	/// - `nonblock` tells whether the function shall return instead of waiting for a message.
+	pub fn recv_msg(&self, buf: UserSlice<u8>, flags: c_int, nonblock: bool) -> EResult<Message> {
+		if self.is_unix() {
+			return unix::recv(self, buf, nonblock);
+		}
+		// TODO support flags for other domains
+		if self.is_netlink() {
+			return netlink::recv(self, buf, flags, nonblock);
+		}
+		if self.is_udp() {
+			let (len, src) = udp::recv_from(self, buf, nonblock)?;
+			return Ok(Message {
+				len,
+				src: src.map(|src| src.to_bytes()).transpose()?,
+				..Default::default()
+			});
+		}
+		if unlikely(buf.is_empty()) {
+			return Ok(Message::default());
+		}
+		if self.is_tcp() {
+			return Ok(Message {
+				len: tcp::read(self, buf, nonblock)?,
+				..Default::default()
+			});
+		}
+		Err(errno!(EOPNOTSUPP))
+	}
+
 	/// Shuts down the reception side of the socket.
 	pub fn shutdown_reception(&self) {
 		*self.rx_buff.lock() = None;
+		self.rx_queue.wake_all();
 	}
 
 	/// Shuts down the transmit side of the socket.
 	pub fn shutdown_transmit(&self) {
-		*self.tx_buff.lock() = None;
+		if self.is_tcp() {
+			// Remaining data is sent before closing
+			tcp::shutdown(self);
+		} else {
+			*self.tx_buff.lock() = None;
+			if self.is_unix() {
+				unix::shutdown(self);
+			}
+		}
+		self.tx_queue.wake_all();
 	}
 }
 
@@ -173,31 +722,97 @@ impl FileOps for Socket {
 
 	fn release(&self, _file: &File) {
 		let cnt = self.open_count.fetch_sub(1, atomic::Ordering::Release);
-		if cnt == 0 {
-			// TODO close the socket
+		if cnt == 1 {
+			if self.is_tcp() {
+				tcp::close(self);
+			} else if self.is_udp() {
+				udp::close(self);
+			} else if self.is_unix() {
+				unix::close(self);
+			} else if self.is_netlink() {
+				netlink::close(self);
+			}
 		}
 	}
 
-	fn poll(&self, _file: &File, _mask: u32) -> EResult<u32> {
-		todo!()
+	fn poll<'f>(
+		&'f self,
+		_file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&self.rx_queue)?;
+			table.register(&self.tx_queue)?;
+		}
+		let events = if self.is_tcp() {
+			tcp::poll(self)
+		} else if self.is_unix() {
+			unix::poll(self)
+		} else {
+			// Connectionless sockets never block on transmission
+			let mut events = POLLOUT | POLLWRNORM;
+			if self.rx_buff.lock().as_ref().is_none_or(|rx| !rx.is_empty()) {
+				events |= POLLIN | POLLRDNORM;
+			}
+			events
+		};
+		Ok(events & mask)
 	}
 
-	fn ioctl(&self, _file: &File, _request: ioctl::Request, _argp: *const c_void) -> EResult<u32> {
-		todo!()
+	fn ioctl(&self, _file: &File, request: ioctl::Request, argp: *const c_void) -> EResult<u32> {
+		net::ioctl::handle(request.get_old_format(), argp)
 	}
 
-	fn read(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
-		if !self.desc.type_.is_stream() {
-			// TODO error
-		}
-		todo!()
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		// Files received along with the data are discarded
+		Ok(self.recv_msg(buf, 0, nonblock)?.len)
 	}
 
-	fn write(&self, _file: &File, _off: u64, _buf: UserSlice<u8>) -> EResult<usize> {
-		// A destination address is required
-		let Some(_stack) = self.stack.as_ref() else {
-			return Err(errno!(EDESTADDRREQ));
-		};
-		todo!()
+	fn write(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		let nonblock = file.get_flags() & O_NONBLOCK != 0;
+		let this = file
+			.get_buffer_arc::<Self>()
+			.ok_or_else(|| errno!(EINVAL))?;
+		Self::send_msg(&this, buf, None, Vec::new(), 0, nonblock)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn sockopt_timeval() {
+		let mut buf = [0u8; 16];
+		buf[..8].copy_from_slice(&2i64.to_ne_bytes());
+		buf[8..].copy_from_slice(&500i64.to_ne_bytes());
+		assert_eq!(timeval_from_bytes(&buf, true), Ok(2_000_500_000));
+		assert_eq!(timeval_from_bytes(&buf[..8], true), Err(errno!(EINVAL)));
+		let val = timeval_to_bytes(2_000_500_000, true).unwrap();
+		assert_eq!(val.as_slice(), buf.as_slice());
+		// 32 bits layout
+		let mut buf = [0u8; 8];
+		buf[..4].copy_from_slice(&1i32.to_ne_bytes());
+		buf[4..].copy_from_slice(&1_000_000i32.to_ne_bytes());
+		assert_eq!(timeval_from_bytes(&buf, false), Err(errno!(EDOM)));
+		buf[..4].copy_from_slice(&(-1i32).to_ne_bytes());
+		buf[4..].copy_from_slice(&0i32.to_ne_bytes());
+		assert_eq!(timeval_from_bytes(&buf, false), Ok(0));
+	}
+
+	#[test_case]
+	fn sockopt_lookup() {
+		assert_eq!(SockOpt::new(SOL_SOCKET, SO_ERROR), Ok(SockOpt::Error));
+		assert_eq!(
+			SockOpt::new(IPPROTO_TCP, TCP_NODELAY),
+			Ok(SockOpt::TcpNoDelay)
+		);
+		assert_eq!(
+			SockOpt::new(SOL_SOCKET, TCP_NODELAY),
+			Err(errno!(ENOPROTOOPT))
+		);
+		assert_eq!(SockOpt::new(42, SO_TYPE), Err(errno!(ENOPROTOOPT)));
 	}
 }
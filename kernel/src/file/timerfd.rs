@@ -0,0 +1,162 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A timerfd is a file notifying the expirations of a timer.
+//!
+//! Reading from the file returns the number of expirations since the last read.
+
+use crate::{
+	file::{
+		File, O_NONBLOCK, Stat,
+		fs::FileOps,
+		wait_queue::{PollTable, WaitQueue},
+	},
+	memory::user::UserSlice,
+	sync::mutex::{IntMutex, Mutex},
+	syscall::select::{POLLIN, POLLRDNORM},
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::Timer,
+		unit::Timestamp,
+	},
+};
+use core::{fmt, hint::unlikely, mem};
+use utils::{
+	errno,
+	errno::{AllocResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// The state of a timerfd shared with its timer's callback.
+#[derive(Debug, Default)]
+struct Shared {
+	/// The number of expirations since the last read.
+	expirations: IntMutex<u64>,
+	/// The queue of threads waiting for the timer to expire.
+	queue: WaitQueue,
+}
+
+/// A timerfd.
+pub struct TimerFd {
+	/// The timer.
+	timer: Mutex<Timer>,
+	/// The state shared with the timer's callback.
+	shared: Arc<Shared>,
+}
+
+impl TimerFd {
+	/// Creates a new instance, with an unarmed timer using `clock`.
+	pub fn new(clock: Clock) -> AllocResult<Self> {
+		let shared = Arc::new(Shared::default())?;
+		let timer = {
+			let shared = shared.clone();
+			Timer::with_callback(clock, move || {
+				*shared.expirations.lock() += 1;
+				shared.queue.wake_all();
+			})?
+		};
+		Ok(Self {
+			timer: Mutex::new(timer),
+			shared,
+		})
+	}
+
+	/// Returns the interval of the timer and the remaining time until its next expiration, in
+	/// nanoseconds.
+	pub fn get_time(&self) -> (Timestamp, Timestamp) {
+		self.timer.lock().get_time_ns()
+	}
+
+	/// Arms or disarms the timer.
+	///
+	/// Arguments:
+	/// - `interval` is the interval between two expirations, in nanoseconds. If zero, the timer
+	///   expires only once
+	/// - `value` is the time of the first expiration, in nanoseconds. If zero, the timer is
+	///   disarmed
+	/// - `abstime` tells whether `value` is an absolute time on the timer's clock. If not, it is
+	///   relative to the current time
+	///
+	/// The function returns the previous setting, as returned by [`Self::get_time`].
+	pub fn set_time(
+		&self,
+		interval: Timestamp,
+		value: Timestamp,
+		abstime: bool,
+	) -> AllocResult<(Timestamp, Timestamp)> {
+		let mut timer = self.timer.lock();
+		let old = timer.get_time_ns();
+		let value = if abstime && value != 0 {
+			// If the time has already passed, expire as soon as possible
+			value
+				.saturating_sub(current_time_ns(timer.get_clock()))
+				.max(1)
+		} else {
+			value
+		};
+		*self.shared.expirations.lock() = 0;
+		timer.set_time(interval, value)?;
+		Ok(old)
+	}
+}
+
+impl fmt::Debug for TimerFd {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("TimerFd")
+			.field("shared", &self.shared)
+			.finish_non_exhaustive()
+	}
+}
+
+impl FileOps for TimerFd {
+	fn get_stat(&self, _file: &File) -> EResult<Stat> {
+		Ok(Stat {
+			mode: 0o600,
+			..Default::default()
+		})
+	}
+
+	fn poll<'f>(
+		&'f self,
+		_file: &'f File,
+		mask: u32,
+		table: Option<&mut PollTable<'f>>,
+	) -> EResult<u32> {
+		if let Some(table) = table {
+			table.register(&self.shared.queue)?;
+		}
+		let expired = *self.shared.expirations.lock() > 0;
+		let events = if expired { POLLIN | POLLRDNORM } else { 0 };
+		Ok(events & mask)
+	}
+
+	fn read(&self, file: &File, _off: u64, buf: UserSlice<u8>) -> EResult<usize> {
+		if unlikely(buf.len() < size_of::<u64>()) {
+			return Err(errno!(EINVAL));
+		}
+		let count = self.shared.queue.wait_until(|| {
+			let mut expirations = self.shared.expirations.lock();
+			if *expirations == 0 {
+				return (file.get_flags() & O_NONBLOCK != 0).then(|| Err(errno!(EAGAIN)));
+			}
+			Some(Ok(mem::take(&mut *expirations)))
+		})??;
+		buf.copy_to_user(0, &count.to_ne_bytes())?;
+		Ok(size_of::<u64>())
+	}
+}
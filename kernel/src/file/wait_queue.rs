@@ -20,12 +20,72 @@
 //! the resource is available.
 
 use crate::{
+	file::epoll::Item,
 	process,
-	process::{Process, pid::Pid, scheduler::Scheduler},
+	process::{
+		Process,
+		pid::Pid,
+		scheduler::Scheduler,
+		signal::{SIGEV_NONE, SigEvent},
+	},
 	sync::mutex::{IntMutex, Mutex},
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::Timer,
+		unit::Timestamp,
+	},
 };
-use core::mem;
-use utils::{collections::vec::Vec, errno, errno::EResult};
+use core::{ptr, ptr::NonNull};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, CollectResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// An entry of a [`WaitQueue`].
+#[derive(Debug)]
+enum Waiter {
+	/// A thread, removed from the queue when woken up.
+	Thread(Pid),
+	/// An epoll interest, notified each time the queue is woken up. It remains in the queue until
+	/// it is unregistered.
+	Epoll(Arc<Item>),
+}
+
+impl Waiter {
+	/// Tells whether `self` and `other` designate the same waiter.
+	fn is(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Self::Thread(a), Self::Thread(b)) => a == b,
+			(Self::Epoll(a), Self::Epoll(b)) => ptr::eq(Arc::as_ptr(a), Arc::as_ptr(b)),
+			_ => false,
+		}
+	}
+}
+
+/// Notifies the epoll interests among `waiters`.
+///
+/// Interests registered with `EPOLLEXCLUSIVE` are notified one at a time, in a round-robin
+/// fashion.
+fn notify_epoll(waiters: &mut [Waiter]) {
+	let mut exclusive = None;
+	for (i, waiter) in waiters.iter().enumerate() {
+		let Waiter::Epoll(item) = waiter else {
+			continue;
+		};
+		if !item.is_exclusive() {
+			item.notify();
+		} else if exclusive.is_none() {
+			item.notify();
+			exclusive = Some(i);
+		}
+	}
+	// Move the notified exclusive interest to the back so that the next one is picked next time
+	if let Some(i) = exclusive {
+		waiters[i..].rotate_left(1);
+	}
+}
 
 /// A queue of processes waiting on a resource.
 ///
@@ -33,7 +93,7 @@ use utils::{collections::vec::Vec, errno, errno::EResult};
 ///
 /// **Note**: dropping this structure while processes are waiting on it makes them starve.
 #[derive(Debug, Default)]
-pub struct WaitQueue(IntMutex<Vec<Pid>>); // TODO use a VecDeque
+pub struct WaitQueue(IntMutex<Vec<Waiter>>); // TODO use a VecDeque
 
 impl WaitQueue {
 	/// Creates a new empty queue.
@@ -52,7 +112,7 @@ impl WaitQueue {
 			// Queue
 			{
 				let proc = Process::current();
-				self.0.lock().push(proc.get_pid())?;
+				self.0.lock().push(Waiter::Thread(proc.get_tid()))?;
 				proc.set_state(process::State::Sleeping);
 			}
 			// Yield
@@ -68,36 +128,233 @@ impl WaitQueue {
 		}
 	}
 
+	/// Same as [`Self::wait_until`], except the function returns [`errno::EAGAIN`] if the closure
+	/// did not return `Some` after `timeout` nanoseconds.
+	///
+	/// If `timeout` is zero, the function waits indefinitely.
+	pub fn wait_until_timeout<F: FnMut() -> Option<T>, T>(
+		&self,
+		timeout: Timestamp,
+		mut f: F,
+	) -> EResult<T> {
+		if timeout == 0 {
+			return self.wait_until(f);
+		}
+		// The timer wakes the process up when expiring
+		let mut timer = Timer::new(
+			Clock::Monotonic,
+			Process::current().get_tid(),
+			SigEvent {
+				sigev_notify: SIGEV_NONE,
+				..Default::default()
+			},
+		)?;
+		timer.set_time(0, timeout)?;
+		self.wait_until(|| {
+			if let Some(val) = f() {
+				return Some(Ok(val));
+			}
+			timer
+				.has_expired(current_time_ns(Clock::Monotonic))
+				.then(|| Err(errno!(EAGAIN)))
+		})?
+	}
+
 	/// Wakes the next process in queue.
+	///
+	/// Epoll interests registered on the queue are notified as well.
 	pub fn wake_next(&self) {
-		let proc = loop {
-			// TODO: inefficient, must use a linked list
-			let pid = {
-				let mut pids = self.0.lock();
-				if pids.is_empty() {
+		let proc = {
+			let mut waiters = self.0.lock();
+			notify_epoll(&mut waiters);
+			loop {
+				// TODO: inefficient, must use a linked list
+				let Some(i) = waiters.iter().position(|w| matches!(w, Waiter::Thread(_))) else {
 					// No process to wake, stop
 					return;
+				};
+				let Waiter::Thread(pid) = waiters.remove(i) else {
+					unreachable!();
+				};
+				if let Some(proc) = Process::get_by_tid(pid) {
+					break proc;
 				}
-				pids.remove(0)
-			};
-			let Some(proc) = Process::get_by_pid(pid) else {
 				// Process does not exist, try next
-				continue;
-			};
-			break proc;
+			}
 		};
 		proc.wake();
 	}
 
 	/// Wakes all processes.
+	///
+	/// Epoll interests registered on the queue are notified as well.
 	pub fn wake_all(&self) {
-		let mut pids = self.0.lock();
-		for pid in mem::take(&mut *pids) {
-			let Some(proc) = Process::get_by_pid(pid) else {
-				// Process does not exist, try next
-				continue;
+		let mut waiters = self.0.lock();
+		notify_epoll(&mut waiters);
+		waiters.retain(|waiter| {
+			let Waiter::Thread(pid) = waiter else {
+				return true;
 			};
-			proc.wake();
+			// If the process does not exist anymore, skip it
+			if let Some(proc) = Process::get_by_tid(*pid) {
+				proc.wake();
+			}
+			false
+		});
+	}
+
+	/// Removes the epoll interest `item` from the queue.
+	pub(super) fn unregister_epoll(&self, item: &Item) {
+		self.0
+			.lock()
+			.retain(|w| !matches!(w, Waiter::Epoll(i) if ptr::eq(Arc::as_ptr(i), item)));
+	}
+}
+
+/// A set of wait queues on which the current thread waits at the same time, to implement
+/// `poll`-like system calls.
+///
+/// Files register their wait queues on the table through [`FileOps::poll`]. The thread is
+/// removed from the queues when the table is dropped.
+///
+/// A table can also register an epoll interest instead of the current thread, in which case the
+/// registration outlives the table (see [`Self::into_queues`]).
+///
+/// [`FileOps::poll`]: crate::file::fs::FileOps::poll
+#[derive(Default)]
+pub struct PollTable<'q> {
+	/// The epoll interest to register. If `None`, the current thread is registered.
+	item: Option<Arc<Item>>,
+	/// The queues the waiter is registered on.
+	queues: Vec<&'q WaitQueue>,
+}
+
+impl<'q> PollTable<'q> {
+	/// Creates a table registering the epoll interest `item` instead of the current thread.
+	pub(super) fn for_epoll(item: Arc<Item>) -> Self {
+		Self {
+			item: Some(item),
+			queues: Vec::new(),
+		}
+	}
+
+	/// Returns the waiter to register on queues.
+	fn waiter(&self) -> Waiter {
+		match &self.item {
+			Some(item) => Waiter::Epoll(item.clone()),
+			None => Waiter::Thread(Process::current().get_tid()),
+		}
+	}
+
+	/// Registers the current thread on `queue`, so that it is woken up along with the queue.
+	pub fn register(&mut self, queue: &'q WaitQueue) -> AllocResult<()> {
+		let waiter = self.waiter();
+		{
+			let mut waiters = queue.0.lock();
+			if !waiters.iter().any(|w| w.is(&waiter)) {
+				waiters.push(waiter)?;
+			}
+		}
+		if !self.queues.iter().any(|q| ptr::eq(*q, queue)) {
+			self.queues.push(queue)?;
+		}
+		Ok(())
+	}
+
+	/// Tells whether one of the registered queues has been woken up since the current thread was
+	/// registered on it.
+	fn is_woken(&self) -> bool {
+		let waiter = self.waiter();
+		self.queues
+			.iter()
+			.any(|q| !q.0.lock().iter().any(|w| w.is(&waiter)))
+	}
+
+	/// Returns the queues the waiter has been registered on, leaving the registrations in place.
+	///
+	/// The caller is responsible for unregistering the waiter from the queues before they are
+	/// freed.
+	pub(super) fn into_queues(mut self) -> AllocResult<Vec<NonNull<WaitQueue>>> {
+		let queues = self
+			.queues
+			.iter()
+			.map(|q| NonNull::from(*q))
+			.collect::<CollectResult<Vec<_>>>()
+			.0?;
+		self.queues.clear();
+		Ok(queues)
+	}
+
+	/// Makes the current thread wait until `f` reports events.
+	///
+	/// `f` polls the files and returns the number of events that occurred. It is given the table
+	/// to register wait queues on, or `None` if the function is not going to sleep.
+	///
+	/// `timeout` is the maximum duration to wait for, in nanoseconds. If `None`, the function
+	/// waits indefinitely. If zero, the function polls once without sleeping.
+	///
+	/// If no event occurred before the timeout, the function returns zero. If waiting is
+	/// interrupted by a signal, the function returns [`errno::EINTR`].
+	pub fn wait<F: FnMut(Option<&mut Self>) -> EResult<usize>>(
+		timeout: Option<Timestamp>,
+		mut f: F,
+	) -> EResult<usize> {
+		if timeout == Some(0) {
+			return f(None);
+		}
+		// The timer wakes the thread up when expiring
+		let timer = timeout
+			.map(|timeout| {
+				let mut timer = Timer::new(
+					Clock::Monotonic,
+					Process::current().get_tid(),
+					SigEvent {
+						sigev_notify: SIGEV_NONE,
+						..Default::default()
+					},
+				)?;
+				timer.set_time(0, timeout)?;
+				EResult::Ok(timer)
+			})
+			.transpose()?;
+		let expired = || {
+			timer
+				.as_ref()
+				.is_some_and(|t| t.has_expired(current_time_ns(Clock::Monotonic)))
+		};
+		let mut table = Self::default();
+		loop {
+			let count = f(Some(&mut table))?;
+			if count > 0 {
+				return Ok(count);
+			}
+			let proc = Process::current();
+			if proc.has_pending_signal() {
+				return Err(errno!(EINTR));
+			}
+			if expired() {
+				return Ok(0);
+			}
+			proc.set_state(process::State::Sleeping);
+			// Check again now that the thread is sleeping, since a wakeup happening before would
+			// have been missed
+			if proc.has_pending_signal() || expired() || table.is_woken() {
+				proc.set_state(process::State::Running);
+			} else {
+				Scheduler::tick();
+			}
+		}
+	}
+}
+
+impl Drop for PollTable<'_> {
+	fn drop(&mut self) {
+		if self.queues.is_empty() {
+			return;
+		}
+		let waiter = self.waiter();
+		for queue in &self.queues {
+			queue.0.lock().retain(|w| !w.is(&waiter));
 		}
 	}
 }
@@ -80,14 +80,14 @@ pub mod time;
 pub mod tty;
 
 use crate::{
-	arch::x86::{enable_sse, has_sse, idt, idt::IntFrame},
+	arch::x86::{apic, enable_sse, has_sse, idt, idt::IntFrame, ioapic},
 	file::{fs::initramfs, vfs, vfs::ResolutionSettings},
 	logger::LOGGER,
 	memory::{cache, vmem},
 	process::{
 		Process, exec,
 		exec::{ExecInfo, exec},
-		scheduler::{SCHEDULER, switch, switch::idle_task},
+		scheduler::{SCHEDULER, cpu, switch, switch::idle_task},
 	},
 	sync::mutex::Mutex,
 	tty::TTY,
@@ -154,6 +154,7 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 		enable_sse();
 		// Initialize IDT
 		idt::init();
+		cpu::init();
 	}
 
 	// Read multiboot information
@@ -193,9 +194,15 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 
 	println!("Booting Maestro kernel version {VERSION}");
 
-	// FIXME
-	//println!("Initializing ACPI...");
-	//acpi::init();
+	println!("Initializing ACPI...");
+	if let Err(e) = acpi::init() {
+		println!("Failed to initialize ACPI ({e}), running on a single core");
+	}
+	#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+	{
+		apic::init().unwrap_or_else(|_| panic!("Failed to initialize the APIC! (out of memory)"));
+		ioapic::init();
+	}
 
 	println!("Initializing time management...");
 	time::init().unwrap_or_else(|e| panic!("Failed to initialize time management! ({e})"));
@@ -203,6 +210,8 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 	println!("Initializing devices management...");
 	device::init().unwrap_or_else(|e| panic!("Failed to initialize devices management! ({e})"));
 	net::osi::init().unwrap_or_else(|e| panic!("Failed to initialize network! ({e})"));
+	net::lo::init()
+		.unwrap_or_else(|e| panic!("Failed to initialize the loopback interface! ({e})"));
 	crypto::init()
 		.unwrap_or_else(|_| panic!("Failed to initialize cryptography! (out of memory)"));
 
@@ -227,6 +236,21 @@ fn kernel_main_inner(magic: u32, multiboot_ptr: *const c_void) {
 
 	Process::new_kthread(None, cache::flush_task, true)
 		.unwrap_or_else(|e| panic!("Cannot launch the cache flush task: {e}"));
+	Process::new_kthread(None, net::tcp::timer_task, true)
+		.unwrap_or_else(|e| panic!("Cannot launch the TCP timer task: {e}"));
+	Process::new_kthread(None, net::rx_task, true)
+		.unwrap_or_else(|e| panic!("Cannot launch the network receive task: {e}"));
+	Process::new_kthread(None, net::arp::timer_task, true)
+		.unwrap_or_else(|e| panic!("Cannot launch the ARP timer task: {e}"));
+	if args_parser.is_dhcp() {
+		Process::new_kthread(None, net::dhcp::client_task, true)
+			.unwrap_or_else(|e| panic!("Cannot launch the DHCP client task: {e}"));
+	}
+
+	if cpu::count() > 1 {
+		println!("Starting application processors...");
+		cpu::start_aps();
+	}
 
 	unsafe {
 		switch::init_ctx(&init_frame);
@@ -433,7 +433,7 @@ pub fn alloc(order: FrameOrder, flags: Flags) -> AllocResult<PhysAddr> {
 		if flags & BUDDY_RETRY != 0 {
 			// Avoid deadlock
 			guard = None;
-			oom::reclaim();
+			oom::reclaim()?;
 		} else {
 			return Err(AllocError);
 		}
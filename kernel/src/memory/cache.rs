@@ -220,6 +220,18 @@ impl RcFrame {
 		self.pages_count() * PAGE_SIZE
 	}
 
+	/// Returns the node from which the data of the frame originates.
+	#[inline]
+	pub fn owner(&self) -> &FrameOwner {
+		&self.0.owner
+	}
+
+	/// Tells whether this is the only reference to the frame.
+	#[inline]
+	pub fn is_unique(&self) -> bool {
+		Arc::strong_count(&self.0) == 1
+	}
+
 	/// Returns the device offset of the frame, if any.
 	#[inline]
 	pub fn dev_offset(&self) -> u64 {
@@ -96,6 +96,6 @@ impl MMIO {
 
 impl Drop for MMIO {
 	fn drop(&mut self) {
-		oom::wrap(|| self.unmap());
+		oom::wrap_nofail(|| self.unmap());
 	}
 }
@@ -44,6 +44,7 @@ pub mod mmio;
 pub mod oom;
 pub mod ring_buffer;
 pub mod stats;
+pub mod swap;
 #[cfg(feature = "memtrace")]
 mod trace;
 pub mod user;
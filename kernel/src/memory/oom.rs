@@ -24,38 +24,245 @@
 //!
 //! This is an emergency procedure which is not supposed to be used under normal conditions.
 
-use crate::{file::vfs, memory::cache};
-use utils::errno::AllocResult;
+use crate::{
+	arch::x86::is_interrupt_enabled,
+	file::vfs,
+	memory::{cache, stats, swap},
+	println,
+	process::{
+		Process, State, scheduler,
+		scheduler::{SCHEDULER, Scheduler},
+		signal::Signal,
+	},
+};
+use core::{alloc::AllocError, ptr, sync::atomic::Ordering::Relaxed};
+use utils::{errno::AllocResult, ptr::arc::Arc};
 
-/// Attempts to reclaim memory from different places, or panics on failure.
-pub fn reclaim() {
+/// The minimum OOM score adjustment. A process with this adjustment is never killed by the OOM
+/// killer.
+pub const OOM_SCORE_ADJ_MIN: i16 = -1000;
+/// The maximum OOM score adjustment.
+pub const OOM_SCORE_ADJ_MAX: i16 = 1000;
+
+/// Returns the total number of pages processes can use, either in memory or in swap areas.
+fn total_pages() -> usize {
+	let info = stats::MEM_INFO.lock();
+	((info.mem_total + info.swap_total) / 4).max(1)
+}
+
+/// Returns the number of pages used by the process `proc`, or `None` if the OOM killer cannot
+/// kill it.
+///
+/// Kernel threads, the init process and processes whose OOM score adjustment is
+/// [`OOM_SCORE_ADJ_MIN`] cannot be killed.
+///
+/// If `try_lock` is set and the memory space of the process is locked, the function returns
+/// `None`.
+fn usage(proc: &Process, try_lock: bool) -> Option<usize> {
+	if !proc.is_thread_leader() || proc.is_init() || proc.get_state() == State::Zombie {
+		return None;
+	}
+	if proc.oom_score_adj.load(Relaxed) == OOM_SCORE_ADJ_MIN {
+		return None;
+	}
+	// Kernel threads do not have a memory space
+	let mem_space = proc.mem_space.as_ref()?;
+	if try_lock {
+		mem_space.try_get_phys_usage()
+	} else {
+		Some(mem_space.get_phys_usage())
+	}
+}
+
+/// Returns the badness of a process using `usage` pages, with the OOM score adjustment `adj`.
+///
+/// `total` is the value returned by [`total_pages`]. The adjustment is a proportion of it, in
+/// thousandths.
+fn badness(usage: usize, adj: i16, total: usize) -> i64 {
+	usage as i64 + adj as i64 * total as i64 / 1000
+}
+
+/// Returns the OOM score of the process `proc`, in the range `0..=2000`. The process with the
+/// highest score is the first to be killed by the OOM killer.
+///
+/// If the process cannot be killed, its score is zero.
+pub fn score(proc: &Process) -> u32 {
+	let Some(usage) = usage(proc, false) else {
+		return 0;
+	};
+	let total = total_pages();
+	let badness = badness(usage, proc.oom_score_adj.load(Relaxed), total);
+	(1000 + badness * 1000 / total as i64).clamp(0, 2000) as u32
+}
+
+/// The outcome of [`kill_victim`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Victim {
+	/// A process has been killed, or was already being killed.
+	Killed,
+	/// No process can be killed.
+	None,
+	/// The scheduler is held by the caller, so no process could be selected.
+	Contended,
+}
+
+/// Kills the process with the highest OOM score.
+///
+/// If the selected process has already been killed, no other process is killed since the memory
+/// of the former is about to be released. If the scheduler is locked, the selection cannot be
+/// performed and the function does nothing.
+fn kill_victim() -> Victim {
+	if !scheduler::is_ready() {
+		return Victim::None;
+	}
+	let total = total_pages();
+	let victim = {
+		// The caller may be holding the scheduler
+		let Some(sched) = SCHEDULER.try_lock() else {
+			return Victim::Contended;
+		};
+		sched
+			.iter_process()
+			.filter_map(|(_, proc)| {
+				let usage = usage(proc, true)?;
+				let badness = badness(usage, proc.oom_score_adj.load(Relaxed), total);
+				Some((badness, proc))
+			})
+			.max_by_key(|(badness, _)| *badness)
+			.map(|(_, proc)| proc.clone())
+	};
+	let Some(victim) = victim else {
+		return Victim::None;
+	};
+	if victim.is_killed() {
+		return Victim::Killed;
+	}
+	println!("Out of memory: killing process {}", victim.get_pid());
+	victim.kill_process(Signal::SIGKILL);
+	// Release the victim's memory right away, unless it is used by other processes (for example
+	// the parent of a `vfork`)
+	let Some(mem_space) = victim.mem_space.as_ref().cloned() else {
+		return Victim::Killed;
+	};
+	let shared = SCHEDULER.try_lock().is_none_or(|sched| {
+		sched.iter_process().any(|(_, proc)| {
+			proc.get_pid() != victim.get_pid()
+				&& proc
+					.mem_space
+					.as_ref()
+					.is_some_and(|m| ptr::eq(Arc::as_ptr(m), Arc::as_ptr(&mem_space)))
+		})
+	});
+	if !shared {
+		mem_space.reap();
+	}
+	Victim::Killed
+}
+
+/// Tells whether the current process has been killed, in which case it must give up allocating
+/// memory so that it can exit.
+fn is_current_killed() -> bool {
+	if !scheduler::is_ready() {
+		return false;
+	}
+	// The caller may be holding the scheduler
+	let Some(proc) = SCHEDULER
+		.try_lock()
+		.map(|sched| sched.get_current_process())
+	else {
+		return false;
+	};
+	proc.is_killed()
+}
+
+/// Attempts to reclaim memory from caches, or by swapping memory to disk.
+///
+/// If no memory could be reclaimed, the function returns `false`.
+pub fn try_reclaim() -> bool {
 	// Attempt to shrink the page cache
-	if cache::shrink() {
-		return;
+	cache::shrink()
+		// Attempt to shrink the directory entries cache
+		|| vfs::shrink_entries()
+		// Attempt to swap memory to disk
+		|| swap::evict()
+}
+
+/// Attempts to reclaim memory from different places, killing the process with the highest OOM
+/// score as a last resort.
+///
+/// On success, the caller may retry its allocation. If the current process has been killed, if
+/// no process could be selected because the caller holds the scheduler, or if it is not possible
+/// to wait for the killed process to exit, the function returns an error.
+///
+/// If no process can be killed, the kernel panics.
+pub fn reclaim() -> AllocResult<()> {
+	if try_reclaim() {
+		return Ok(());
+	}
+	match kill_victim() {
+		Victim::Killed => {}
+		Victim::None => panic!("Out of memory"),
+		Victim::Contended => return Err(AllocError),
 	}
-	// Attempt to shrink the directory entries cache
-	if vfs::shrink_entries() {
-		return;
+	if is_current_killed() || !is_interrupt_enabled() {
+		return Err(AllocError);
 	}
-	// TODO Attempt to:
-	// - swap memory to disk
-	// - if the kernel is configured for it, prompt the user to select processes to kill
-	// - if the kernel is configured for it, kill the process with the highest OOM score (ignore
-	//   init process)
-	// - else, panic:
-	panic!("Out of memory");
+	// Let the killed process run so that it can exit
+	Scheduler::tick();
+	Ok(())
 }
 
-/// Executes the given function. On failure due to a lack of memory, the function runs the OOM
-/// killer, then tries again.
+/// Executes the given function. On failure due to a lack of memory, the function reclaims
+/// memory, then tries again.
 ///
-/// If the OOM killer is unable to free enough memory, the kernel may panic.
-pub fn wrap<T, F: FnMut() -> AllocResult<T>>(mut f: F) -> T {
+/// If [`reclaim`] fails, in particular if the current process has been killed by the OOM killer,
+/// the function gives up and returns an error.
+pub fn wrap<T, F: FnMut() -> AllocResult<T>>(mut f: F) -> AllocResult<T> {
+	loop {
+		if let Ok(r) = f() {
+			return Ok(r);
+		}
+		reclaim()?;
+	}
+}
+
+/// The number of consecutive times [`wrap_nofail`] retries an allocation after [`reclaim`]
+/// failed, before giving up.
+const NOFAIL_RETRIES: usize = 8;
+
+/// Same as [`wrap`], except the function does not return an error. This is meant for allocations
+/// that cannot be failed.
+///
+/// If memory cannot be reclaimed, for example because the caller holds the scheduler with
+/// interrupts disabled, the allocation is retried a bounded number of times, then the kernel
+/// panics.
+pub fn wrap_nofail<T, F: FnMut() -> AllocResult<T>>(mut f: F) -> T {
+	let mut failures = 0;
 	loop {
 		if let Ok(r) = f() {
 			return r;
 		}
-		reclaim();
-		// TODO Check if current process has been killed
+		if reclaim().is_ok() {
+			failures = 0;
+			continue;
+		}
+		failures += 1;
+		if failures > NOFAIL_RETRIES {
+			panic!("Out of memory");
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn oom_badness() {
+		assert_eq!(badness(100, 0, 1000), 100);
+		assert_eq!(badness(100, 500, 1000), 600);
+		assert_eq!(badness(100, OOM_SCORE_ADJ_MIN, 1000), -900);
+		// The adjustment dominates regardless of the usage
+		assert!(badness(0, OOM_SCORE_ADJ_MAX, 1000) > badness(999, 0, 1000));
 	}
 }
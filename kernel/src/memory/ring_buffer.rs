@@ -22,7 +22,12 @@ use crate::memory::{
 	malloc::{__alloc, __dealloc},
 	user::UserSlice,
 };
-use core::{alloc::Layout, cmp::min, num::NonZeroUsize, ptr::NonNull};
+use core::{
+	alloc::Layout,
+	cmp::{max, min},
+	num::NonZeroUsize,
+	ptr::NonNull,
+};
 use utils::errno::{AllocResult, EResult};
 
 /// Ring buffer of `u8`.
@@ -102,8 +107,15 @@ impl RingBuffer {
 	///
 	/// The function returns the number of bytes read.
 	pub fn peek(&mut self, buf: UserSlice<u8>) -> EResult<usize> {
-		let cursor = self.read_cursor;
-		let len = min(buf.len(), self.get_data_len());
+		self.peek_at(0, buf)
+	}
+
+	/// Same as [`Self::peek`], except data is read starting `off` bytes after the read cursor.
+	///
+	/// If `off` is greater than the length of the data, the function returns zero.
+	pub fn peek_at(&mut self, off: usize, buf: UserSlice<u8>) -> EResult<usize> {
+		let cursor = (self.read_cursor + off) % self.capacity();
+		let len = min(buf.len(), self.get_data_len().saturating_sub(off));
 		let capacity = self.capacity();
 		let buffer = self.inner_buffer();
 		// First read
@@ -124,6 +136,15 @@ impl RingBuffer {
 		Ok(len)
 	}
 
+	/// Consumes at most `len` bytes from the buffer without reading them.
+	///
+	/// The function returns the number of bytes consumed.
+	pub fn discard(&mut self, len: usize) -> usize {
+		let len = min(len, self.get_data_len());
+		self.read_cursor = (self.read_cursor + len) % self.capacity();
+		len
+	}
+
 	/// Writes data in `buf` to the buffer.
 	///
 	/// The function returns the number of bytes written.
@@ -143,6 +164,22 @@ impl RingBuffer {
 		Ok(len)
 	}
 
+	/// Resizes the buffer to `capacity` bytes, keeping its data.
+	///
+	/// If the data does not fit in the new capacity, the buffer is resized to the smallest
+	/// capacity that can hold it.
+	pub fn resize(&mut self, capacity: NonZeroUsize) -> AllocResult<()> {
+		let len = self.get_data_len();
+		let capacity = max(capacity, NonZeroUsize::new(len + 1).unwrap());
+		let mut new = Self::new(capacity)?;
+		let buf = &mut new.inner_buffer()[..len];
+		// Cannot fail since the destination is in kernelspace
+		let _ = self.peek(UserSlice::from_slice_mut(buf));
+		new.write_cursor = len;
+		*self = new;
+		Ok(())
+	}
+
 	/// Clears the buffer.
 	#[inline(always)]
 	pub fn clear(&mut self) {
@@ -199,4 +236,42 @@ mod test {
 	}
 
 	// TODO peek
+
+	#[test_case]
+	fn ring_buffer_peek_at() {
+		let mut rb = RingBuffer::new(NonZeroUsize::new(8).unwrap()).unwrap();
+		let mut buf: [u8; 6] = [1, 2, 3, 4, 5, 6];
+		assert_eq!(rb.write(UserSlice::from_slice_mut(&mut buf)).unwrap(), 6);
+		assert_eq!(rb.discard(4), 4);
+		// Wrap around
+		assert_eq!(rb.write(UserSlice::from_slice_mut(&mut buf)).unwrap(), 5);
+
+		let mut out: [u8; 4] = [0; 4];
+		let len = rb.peek_at(1, UserSlice::from_slice_mut(&mut out)).unwrap();
+		assert_eq!(len, 4);
+		assert_eq!(out, [6, 1, 2, 3]);
+		let len = rb.peek_at(10, UserSlice::from_slice_mut(&mut out)).unwrap();
+		assert_eq!(len, 0);
+		assert_eq!(rb.get_data_len(), 7);
+	}
+
+	#[test_case]
+	fn ring_buffer_resize() {
+		let mut rb = RingBuffer::new(NonZeroUsize::new(8).unwrap()).unwrap();
+		let mut buf: [u8; 6] = [1, 2, 3, 4, 5, 6];
+		rb.write(UserSlice::from_slice_mut(&mut buf)).unwrap();
+		assert_eq!(rb.discard(4), 4);
+		rb.write(UserSlice::from_slice_mut(&mut buf)).unwrap();
+
+		rb.resize(NonZeroUsize::new(16).unwrap()).unwrap();
+		assert_eq!(rb.capacity(), 16);
+		assert_eq!(rb.get_data_len(), 7);
+		// Too small to hold the data
+		rb.resize(NonZeroUsize::new(2).unwrap()).unwrap();
+		assert_eq!(rb.capacity(), 8);
+
+		let mut out: [u8; 7] = [0; 7];
+		assert_eq!(rb.read(UserSlice::from_slice_mut(&mut out)).unwrap(), 7);
+		assert_eq!(out, [5, 6, 1, 2, 3, 4, 5]);
+	}
 }
@@ -37,6 +37,10 @@ pub struct MemInfo {
 	pub active: usize,
 	/// The total amount of inactive (not mapped but cached) memory.
 	pub inactive: usize,
+	/// The total amount of swap space.
+	pub swap_total: usize,
+	/// The amount of unused swap space.
+	pub swap_free: usize,
 }
 
 impl Display for MemInfo {
@@ -47,8 +51,16 @@ impl Display for MemInfo {
 MemFree: {} kB
 MemAvailable: {} kB
 Active: {} kB
-Inactive: {} kB",
-			self.mem_total, self.mem_free, self.mem_available, self.active, self.inactive
+Inactive: {} kB
+SwapTotal: {} kB
+SwapFree: {} kB",
+			self.mem_total,
+			self.mem_free,
+			self.mem_available,
+			self.active,
+			self.inactive,
+			self.swap_total,
+			self.swap_free
 		)
 	}
 }
@@ -60,4 +72,6 @@ pub static MEM_INFO: Mutex<MemInfo> = Mutex::new(MemInfo {
 	mem_available: 0,
 	active: 0,
 	inactive: 0,
+	swap_total: 0,
+	swap_free: 0,
 });
@@ -0,0 +1,538 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Swapping allows to free physical memory by moving anonymous pages of processes to disk.
+//!
+//! A swap area is a block device or a regular file, starting with a header in the format used by
+//! Linux (as written by `mkswap`). Each page of the area following the header is a *slot*, which
+//! can hold one page of memory.
+//!
+//! When a page is evicted, its content is written to a slot and the page table entry is replaced
+//! by a *swap entry*, which identifies the area and the slot. The next access to the page
+//! triggers a page fault, which reads the page back into memory.
+
+use crate::{
+	device::BlkDev,
+	file::{File, FileType, vfs, vfs::node::Node},
+	memory::{
+		buddy::ZONE_KERNEL,
+		cache::{FrameOwner, RcFrame},
+		stats::MEM_INFO,
+		user::UserSlice,
+	},
+	process::{mem_space::MemSpace, pid::Pid, scheduler::SCHEDULER},
+	sync::mutex::IntMutex,
+};
+use core::{
+	cmp::min,
+	fmt,
+	fmt::Formatter,
+	hint::unlikely,
+	ptr,
+	sync::atomic::{
+		AtomicBool, AtomicU16, AtomicU32, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use macros::AnyRepr;
+use utils::{
+	bytes::{from_bytes, slice_from_bytes},
+	collections::vec::Vec,
+	errno,
+	errno::EResult,
+	limits::PAGE_SIZE,
+	ptr::arc::Arc,
+};
+
+/// The maximum number of swap areas that can be active at the same time.
+pub const MAX_SWAP_AREAS: usize = 8;
+/// The number of bits of a swap entry used to store the index of the area.
+const AREA_BITS: u32 = MAX_SWAP_AREAS.trailing_zeros();
+/// The maximum number of slots in a swap area, bounded by the space available for a swap entry
+/// in a page table entry.
+const MAX_SLOTS: usize = 1 << (usize::BITS - PAGE_SIZE.trailing_zeros() - AREA_BITS);
+
+/// The signature of a swap area, located at the end of its first page.
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+/// The offset of [`SwapHeader`] in the first page of a swap area.
+const HEADER_OFF: usize = 1024;
+/// The offset of the list of bad pages in the first page of a swap area.
+const BADPAGES_OFF: usize = 1536;
+
+/// Value of a slot's reference counter marking it as unusable.
+const SLOT_BAD: u32 = u32::MAX;
+
+/// The number of pages to evict each time memory is reclaimed.
+const EVICT_BATCH: usize = 32;
+
+/// The header of a swap area, as written by `mkswap`.
+#[repr(C)]
+#[derive(AnyRepr)]
+struct SwapHeader {
+	/// The version of the header's format
+	version: u32,
+	/// The index of the last usable page of the area
+	last_page: u32,
+	/// The number of bad pages
+	nr_badpages: u32,
+}
+
+/// Parses the header of a swap area, `page` being the first page of the area.
+///
+/// On success, the function returns the index of the last usable page and the list of bad
+/// pages.
+///
+/// If the header is invalid, the function returns [`errno::EINVAL`].
+fn parse_header(page: &[u8]) -> EResult<(u32, &[u32])> {
+	let magic_off = PAGE_SIZE - SWAP_MAGIC.len();
+	if unlikely(page.get(magic_off..PAGE_SIZE) != Some(SWAP_MAGIC)) {
+		return Err(errno!(EINVAL));
+	}
+	let hdr: &SwapHeader = from_bytes(&page[HEADER_OFF..]).ok_or_else(|| errno!(EINVAL))?;
+	if unlikely(hdr.version != 1 || hdr.last_page == 0) {
+		return Err(errno!(EINVAL));
+	}
+	let badpages = slice_from_bytes::<u32>(&page[BADPAGES_OFF..magic_off])
+		.and_then(|b| b.get(..hdr.nr_badpages as usize))
+		.ok_or_else(|| errno!(EINVAL))?;
+	Ok((hdr.last_page, badpages))
+}
+
+/// Reads the page at offset `off` (in pages) of a swap area into a new frame.
+///
+/// Arguments:
+/// - `file` is the file of the area
+/// - `dev` is the block device of the area, if any. If set, the page cache is bypassed
+fn read_page(file: &File, dev: Option<&BlkDev>, off: u64) -> EResult<RcFrame> {
+	if let Some(dev) = dev {
+		return dev.ops.read_frame(off, 0, FrameOwner::Anon);
+	}
+	let frame = RcFrame::new(0, ZONE_KERNEL, FrameOwner::Anon, 0)?;
+	let buf = UserSlice::from_slice_mut(unsafe { frame.slice_mut::<u8>() });
+	let len = file.ops.read(file, off * PAGE_SIZE as u64, buf)?;
+	if unlikely(len < PAGE_SIZE) {
+		return Err(errno!(EIO));
+	}
+	Ok(frame)
+}
+
+/// An active swap area.
+#[derive(Debug)]
+pub struct SwapArea {
+	/// The index of the area, stored in swap entries
+	index: usize,
+	/// The file of the area
+	file: Arc<File>,
+	/// The block device of the area, if it is not a regular file
+	dev: Option<Arc<BlkDev>>,
+	/// The priority of the area. Areas with a higher priority are used first
+	prio: i16,
+
+	/// The reference counter of each slot. A free slot has a counter of `0`
+	slots: Vec<AtomicU32>,
+	/// The number of usable slots
+	total: usize,
+	/// The number of slots in use
+	used: AtomicUsize,
+	/// The slot at which the search for a free slot begins
+	cursor: AtomicUsize,
+	/// Tells whether the area is being deactivated. If set, no new slot can be allocated
+	draining: AtomicBool,
+}
+
+impl SwapArea {
+	/// Tells whether a slot can be allocated on the area.
+	fn is_available(&self) -> bool {
+		!self.draining.load(Acquire) && self.used.load(Relaxed) < self.total
+	}
+
+	/// Allocates a free slot on the area.
+	///
+	/// If no slot is available, the function returns `None`.
+	fn alloc_slot(this: &Arc<Self>) -> Option<SwapSlot> {
+		let len = this.slots.len();
+		let start = this.cursor.load(Relaxed);
+		let slot = (0..len).map(|i| (start + i) % len).find(|s| {
+			this.slots[*s]
+				.compare_exchange(0, 1, Acquire, Relaxed)
+				.is_ok()
+		})?;
+		this.cursor.store(slot + 1, Relaxed);
+		this.used.fetch_add(1, Relaxed);
+		MEM_INFO.lock().swap_free -= PAGE_SIZE / 1024;
+		Some(SwapSlot {
+			area: this.clone(),
+			slot,
+		})
+	}
+}
+
+/// A reference to a slot of a swap area, holding a page of memory.
+///
+/// The slot is freed when the last reference to it is dropped.
+#[derive(Debug)]
+pub struct SwapSlot {
+	/// The area of the slot
+	area: Arc<SwapArea>,
+	/// The index of the slot in the area
+	slot: usize,
+}
+
+impl SwapSlot {
+	/// Returns the swap entry identifying the slot, to be stored in a page table entry.
+	#[inline]
+	pub fn entry(&self) -> usize {
+		(self.slot << AREA_BITS) | self.area.index
+	}
+
+	/// Returns the area of the slot.
+	#[inline]
+	pub fn area(&self) -> &Arc<SwapArea> {
+		&self.area
+	}
+
+	/// Reads the content of the slot into a new frame.
+	pub fn read(&self) -> EResult<RcFrame> {
+		let area = &self.area;
+		read_page(&area.file, area.dev.as_deref(), self.slot as _)
+	}
+
+	/// Writes the content of `frame` to the slot.
+	pub fn write(&self, frame: &RcFrame) -> EResult<()> {
+		let area = &self.area;
+		let buf = frame.slice::<u8>();
+		if let Some(dev) = &area.dev {
+			return dev.ops.write_pages(self.slot as _, buf);
+		}
+		let off = self.slot as u64 * PAGE_SIZE as u64;
+		let len = area
+			.file
+			.ops
+			.write(&area.file, off, unsafe { UserSlice::from_slice(buf) })?;
+		if unlikely(len < PAGE_SIZE) {
+			return Err(errno!(EIO));
+		}
+		Ok(())
+	}
+}
+
+impl Clone for SwapSlot {
+	fn clone(&self) -> Self {
+		self.area.slots[self.slot].fetch_add(1, Relaxed);
+		Self {
+			area: self.area.clone(),
+			slot: self.slot,
+		}
+	}
+}
+
+impl Drop for SwapSlot {
+	fn drop(&mut self) {
+		if self.area.slots[self.slot].fetch_sub(1, Release) == 1 {
+			self.area.used.fetch_sub(1, Relaxed);
+			MEM_INFO.lock().swap_free += PAGE_SIZE / 1024;
+		}
+	}
+}
+
+/// The list of active swap areas, indexed by the area index stored in swap entries.
+static AREAS: IntMutex<[Option<Arc<SwapArea>>; MAX_SWAP_AREAS]> =
+	IntMutex::new([const { None }; MAX_SWAP_AREAS]);
+
+/// Tells whether the swap area `area` is located on the node `node`.
+fn is_on_node(area: &SwapArea, node: &Node) -> bool {
+	area.file.node().is_some_and(|n| ptr::eq(n.as_ref(), node))
+}
+
+/// Activates the swap area on the file `file`.
+///
+/// `prio` is the priority of the area. If `None`, the area gets a lower priority than all
+/// other areas.
+///
+/// If the file is already used as a swap area, the function returns [`errno::EBUSY`]. If the
+/// file is not a valid swap area, the function returns [`errno::EINVAL`].
+pub fn swapon(file: Arc<File>, prio: Option<i16>) -> EResult<()> {
+	let node = file.node().ok_or_else(|| errno!(EINVAL))?.clone();
+	let (dev, size) = match file.get_type()? {
+		FileType::BlockDevice => {
+			let dev = file.as_block_device().ok_or_else(|| errno!(ENODEV))?;
+			let size = dev.ops.blocks_count() * dev.ops.block_size().get();
+			(Some(dev), size)
+		}
+		FileType::Regular => (None, file.stat()?.size),
+		_ => return Err(errno!(EINVAL)),
+	};
+	if AREAS.lock().iter().flatten().any(|a| is_on_node(a, &node)) {
+		return Err(errno!(EBUSY));
+	}
+	// Read header
+	let header = read_page(&file, dev.as_deref(), 0)?;
+	let (last_page, badpages) = parse_header(header.slice())?;
+	let pages = usize::try_from(size / PAGE_SIZE as u64).unwrap_or(usize::MAX);
+	let len = min(min(last_page as usize + 1, pages), MAX_SLOTS);
+	// Init slots, the first one being the header
+	let mut slots = Vec::with_capacity(len)?;
+	slots.push(AtomicU32::new(SLOT_BAD))?;
+	for _ in 1..len {
+		slots.push(AtomicU32::new(0))?;
+	}
+	for b in badpages {
+		if let Some(slot) = slots.get(*b as usize) {
+			slot.store(SLOT_BAD, Relaxed);
+		}
+	}
+	let total = slots.iter().filter(|s| s.load(Relaxed) == 0).count();
+	if unlikely(total == 0) {
+		return Err(errno!(EINVAL));
+	}
+	let (index, prio) = {
+		let areas = AREAS.lock();
+		let index = areas
+			.iter()
+			.position(Option::is_none)
+			.ok_or_else(|| errno!(EPERM))?;
+		// By default, each new area gets a lower priority
+		let prio = prio.unwrap_or_else(|| {
+			areas
+				.iter()
+				.flatten()
+				.map(|a| a.prio)
+				.filter(|p| *p < 0)
+				.min()
+				.unwrap_or(0)
+				.saturating_sub(1)
+		});
+		(index, prio)
+	};
+	let area = Arc::new(SwapArea {
+		index,
+		file,
+		dev,
+		prio,
+
+		slots,
+		total,
+		used: AtomicUsize::new(0),
+		cursor: AtomicUsize::new(1),
+		draining: AtomicBool::new(false),
+	})?;
+	let mut areas = AREAS.lock();
+	// Check again in case another area has been activated in the meantime
+	if unlikely(areas[index].is_some() || areas.iter().flatten().any(|a| is_on_node(a, &node))) {
+		return Err(errno!(EBUSY));
+	}
+	areas[index] = Some(area);
+	let mut mem_info = MEM_INFO.lock();
+	mem_info.swap_total += total * PAGE_SIZE / 1024;
+	mem_info.swap_free += total * PAGE_SIZE / 1024;
+	Ok(())
+}
+
+/// Deactivates the swap area located on `node`, reading all the pages stored on it back into
+/// memory.
+///
+/// If no swap area is located on `node`, the function returns [`errno::EINVAL`].
+pub fn swapoff(node: &Node) -> EResult<()> {
+	let area = AREAS
+		.lock()
+		.iter()
+		.flatten()
+		.find(|a| is_on_node(a, node) && !a.draining.swap(true, Acquire))
+		.cloned()
+		.ok_or_else(|| errno!(EINVAL))?;
+	let res = swap_in_area(&area);
+	if res.is_err() {
+		area.draining.store(false, Release);
+		return res;
+	}
+	AREAS.lock()[area.index] = None;
+	let mut mem_info = MEM_INFO.lock();
+	mem_info.swap_total -= area.total * PAGE_SIZE / 1024;
+	mem_info.swap_free -= area.total * PAGE_SIZE / 1024;
+	Ok(())
+}
+
+/// Reads all the pages stored on `area` back into the memory spaces of processes.
+fn swap_in_area(area: &SwapArea) -> EResult<()> {
+	loop {
+		let used = area.used.load(Relaxed);
+		if used == 0 {
+			break;
+		}
+		let mut pid = 0;
+		while let Some((p, mem_space)) = next_mem_space(pid, false) {
+			mem_space.swap_in_area(area)?;
+			let Some(next) = p.checked_add(1) else {
+				break;
+			};
+			pid = next;
+		}
+		// Slots are held somewhere else than in processes
+		if unlikely(area.used.load(Relaxed) >= used) {
+			return Err(errno!(EBUSY));
+		}
+	}
+	Ok(())
+}
+
+/// Returns the memory space of the first process whose PID is greater than or equal to `pid`,
+/// along with the PID of the process.
+///
+/// If `try_lock` is set and the scheduler is already locked, the function returns `None`.
+fn next_mem_space(pid: Pid, try_lock: bool) -> Option<(Pid, Arc<MemSpace>)> {
+	let sched = if try_lock {
+		SCHEDULER.try_lock()?
+	} else {
+		SCHEDULER.lock()
+	};
+	sched.iter_process().find_map(|(p, proc)| {
+		let mem_space = proc.mem_space.as_ref()?;
+		(*p >= pid).then(|| (*p, mem_space.clone()))
+	})
+}
+
+/// Reserves a slot on the swap area with the highest priority that has free slots.
+///
+/// If no slot is available, the function returns `None`.
+pub fn reserve() -> Option<SwapSlot> {
+	let area = AREAS
+		.lock()
+		.iter()
+		.flatten()
+		.filter(|a| a.is_available())
+		.max_by_key(|a| a.prio)
+		.cloned()?;
+	SwapArea::alloc_slot(&area)
+}
+
+/// Tells whether an eviction is in progress, preventing recursive evictions when writing to a
+/// swap area requires memory.
+static EVICTING: AtomicBool = AtomicBool::new(false);
+/// The PID of the process at which the next eviction begins.
+static EVICT_CURSOR: AtomicU16 = AtomicU16::new(0);
+
+/// Evicts anonymous pages of processes to swap areas, to free memory.
+///
+/// Processes are visited in a round-robin fashion. Pages that have been accessed recently are
+/// given a second chance, so that pages in use remain in memory.
+///
+/// If no page could be evicted, the function returns `false`.
+pub fn evict() -> bool {
+	if !AREAS.lock().iter().flatten().any(|a| a.is_available()) {
+		return false;
+	}
+	if EVICTING.swap(true, Acquire) {
+		return false;
+	}
+	let start = EVICT_CURSOR.load(Relaxed);
+	let mut pid = start;
+	let mut evicted = 0;
+	// Visit each process twice, since the first visit may only clear the accessed flags
+	let mut wraps = 0;
+	while evicted < EVICT_BATCH {
+		let Some((p, mem_space)) = next_mem_space(pid, true) else {
+			wraps += 1;
+			if wraps > 2 {
+				break;
+			}
+			pid = 0;
+			continue;
+		};
+		if wraps == 2 && p >= start {
+			break;
+		}
+		evicted += mem_space.evict(EVICT_BATCH - evicted);
+		pid = p.wrapping_add(1);
+	}
+	EVICT_CURSOR.store(pid, Relaxed);
+	EVICTING.store(false, Release);
+	evicted > 0
+}
+
+/// Displays the list of active swap areas, in the format of `/proc/swaps`.
+pub struct SwapsDisplay;
+
+impl fmt::Display for SwapsDisplay {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority")?;
+		let areas = AREAS.lock().clone();
+		for area in areas.iter().flatten() {
+			let Some(path) = area
+				.file
+				.vfs_entry
+				.as_ref()
+				.and_then(|ent| vfs::Entry::get_path(ent).ok())
+			else {
+				continue;
+			};
+			let kind = if area.dev.is_some() {
+				"partition"
+			} else {
+				"file"
+			};
+			let size = area.total * PAGE_SIZE / 1024;
+			let used = area.used.load(Relaxed) * PAGE_SIZE / 1024;
+			writeln!(
+				f,
+				"{path}\t\t\t\t{kind}\t{size}\t\t{used}\t\t{prio}",
+				prio = area.prio
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Returns a page containing a swap header with `last_page` and the given bad pages.
+	fn header(last_page: u32, badpages: &[u32]) -> [u32; PAGE_SIZE / 4] {
+		let mut page = [0u32; PAGE_SIZE / 4];
+		page[HEADER_OFF / 4] = 1;
+		page[HEADER_OFF / 4 + 1] = last_page;
+		page[HEADER_OFF / 4 + 2] = badpages.len() as _;
+		page[BADPAGES_OFF / 4..BADPAGES_OFF / 4 + badpages.len()].copy_from_slice(badpages);
+		let bytes = unsafe { page.align_to_mut::<u8>().1 };
+		bytes[PAGE_SIZE - SWAP_MAGIC.len()..].copy_from_slice(SWAP_MAGIC);
+		page
+	}
+
+	#[test_case]
+	fn swap_header_valid() {
+		let page = header(255, &[3, 7]);
+		let bytes = unsafe { page.align_to::<u8>().1 };
+		let (last_page, badpages) = parse_header(bytes).unwrap();
+		assert_eq!(last_page, 255);
+		assert_eq!(badpages, &[3, 7]);
+	}
+
+	#[test_case]
+	fn swap_header_invalid() {
+		let mut page = header(255, &[]);
+		let bytes = unsafe { page.align_to_mut::<u8>().1 };
+		bytes[PAGE_SIZE - 1] = b'1';
+		assert!(parse_header(bytes).is_err());
+		let mut page = header(0, &[]);
+		let bytes = unsafe { page.align_to_mut::<u8>().1 };
+		assert!(parse_header(bytes).is_err());
+		page[HEADER_OFF / 4] = 2;
+		let bytes = unsafe { page.align_to::<u8>().1 };
+		assert!(parse_header(bytes).is_err());
+	}
+}
@@ -38,7 +38,12 @@ use utils::{
 unsafe extern "C" {
 	/// Copy, with access check. On success, the function returns `true`.
 	pub fn raw_copy(dst: *mut u8, src: *const u8, n: usize) -> bool;
-	/// Function to be called back when a page fault occurs while using [`raw_copy`].
+	/// Atomic compare-and-exchange, with access check. On success, the function returns `true`.
+	///
+	/// The value found at `ptr` before the operation is written to `cur`.
+	pub fn raw_cmpxchg(ptr: *mut u32, old: u32, new: u32, cur: *mut u32) -> bool;
+	/// Function to be called back when a page fault occurs while using [`raw_copy`] or
+	/// [`raw_cmpxchg`].
 	pub fn copy_fault();
 }
 
@@ -119,6 +124,28 @@ impl<T: Sized + fmt::Debug> UserPtr<T> {
 	}
 }
 
+impl UserPtr<u32> {
+	/// Atomically replaces the value in userspace with `new` if it is equal to `old`.
+	///
+	/// The function returns the value that was found before the operation. The exchange
+	/// succeeded if it is equal to `old`.
+	///
+	/// If the pointer is null or if the value is not accessible, the function returns an error.
+	pub fn compare_exchange(&self, old: u32, new: u32) -> EResult<u32> {
+		let ptr = self.as_ptr();
+		if unlikely(ptr.is_null() || !ptr.is_aligned() || !bound_check(ptr as _, 4)) {
+			return Err(errno!(EFAULT));
+		}
+		let mut cur = 0;
+		let res = unsafe { vmem::smap_disable(|| raw_cmpxchg(ptr, old, new, &mut cur)) };
+		if likely(res) {
+			Ok(cur)
+		} else {
+			Err(errno!(EFAULT))
+		}
+	}
+}
+
 impl<T: fmt::Debug> fmt::Debug for UserPtr<T> {
 	fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let ptr = self.as_ptr();
@@ -211,6 +238,19 @@ impl<'a, T: Sized + fmt::Debug> UserSlice<'a, T> {
 		self.len == 0
 	}
 
+	/// Returns the subslice of at most `len` elements, starting at offset `off`.
+	///
+	/// The subslice is clamped to the bounds of the slice.
+	pub fn subslice(&self, off: usize, len: usize) -> Self {
+		let off = min(off, self.len);
+		Self {
+			ptr: self.ptr.map(|ptr| unsafe { ptr.add(off) }),
+			len: min(len, self.len - off),
+
+			phantom: PhantomData,
+		}
+	}
+
 	/// Same as [`Self::copy_from_user`], with a pointer `ptr` and length `len` instead of a slice.
 	///
 	/// # Safety
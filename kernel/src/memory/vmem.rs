@@ -28,12 +28,16 @@ use crate::{
 	},
 	elf, memory,
 	memory::{KERNELSPACE_SIZE, PhysAddr, VirtAddr, buddy, memmap::PHYS_MAP},
+	process::scheduler::{core_local, cpu},
 	sync::{mutex::Mutex, once::OnceInit},
 	tty::vga,
 };
 use core::{cmp::min, ptr::NonNull, sync::atomic::Ordering::Release};
 use utils::limits::PAGE_SIZE;
 
+/// The number of pages above which invalidating a range flushes the whole cache instead.
+const INVALIDATE_MAX_PAGES: usize = 32;
+
 /// A virtual memory context.
 ///
 /// This structure implements operations to modify virtual memory in an architecture-independent
@@ -92,7 +96,7 @@ impl VMem {
 		unsafe {
 			x86::paging::map(self.inner_mut(), physaddr, virtaddr, flags);
 		}
-		invalidate_page_current(virtaddr);
+		self.invalidate(virtaddr, 1);
 	}
 
 	/// Like [`Self::map`] but on a range of several pages.
@@ -108,8 +112,12 @@ impl VMem {
 		for i in 0..pages {
 			let physaddr = physaddr + i * PAGE_SIZE;
 			let virtaddr = virtaddr + i * PAGE_SIZE;
-			self.map(physaddr, virtaddr, flags);
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			unsafe {
+				x86::paging::map(self.inner_mut(), physaddr, virtaddr, flags);
+			}
 		}
+		self.invalidate(virtaddr, pages);
 	}
 
 	/// Unmaps a single page of virtual memory at `virtaddr`.
@@ -119,7 +127,7 @@ impl VMem {
 		unsafe {
 			x86::paging::unmap(self.inner_mut(), virtaddr);
 		}
-		invalidate_page_current(virtaddr);
+		self.invalidate(virtaddr, 1);
 	}
 
 	/// Like [`Self::unmap`] but on a range of several pages.
@@ -128,8 +136,20 @@ impl VMem {
 	pub fn unmap_range(&mut self, virtaddr: VirtAddr, pages: usize) {
 		for i in 0..pages {
 			let virtaddr = virtaddr + i * PAGE_SIZE;
-			self.unmap(virtaddr);
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			unsafe {
+				x86::paging::unmap(self.inner_mut(), virtaddr);
+			}
 		}
+		self.invalidate(virtaddr, pages);
+	}
+
+	/// Invalidates `pages` pages starting at `addr` from cache, on every core using the context.
+	fn invalidate(&self, addr: VirtAddr, pages: usize) {
+		invalidate_range_current(addr, pages);
+		// Kernelspace is shared by every context
+		let page_dir = (addr < memory::KERNEL_BEGIN).then(|| self.phys_addr());
+		cpu::shootdown(page_dir, addr, pages);
 	}
 
 	/// Polls the dirty flags on the range of `pages` pages starting at `addr`, clearing them
@@ -146,11 +166,46 @@ impl VMem {
 		}
 	}
 
+	/// Replaces the page mapped at `virtaddr` with the swap entry `entry`, revoking access to the
+	/// page.
+	///
+	/// If no paging table covers `virtaddr`, the page is not mapped and the function does
+	/// nothing.
+	pub fn map_swap(&mut self, virtaddr: VirtAddr, entry: usize) {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		unsafe {
+			x86::paging::map_swap(self.inner_mut(), virtaddr, entry);
+		}
+		self.invalidate(virtaddr, 1);
+	}
+
+	/// Returns the swap entry stored for the page at `virtaddr`, if any.
+	pub fn swap_entry(&self, virtaddr: VirtAddr) -> Option<usize> {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		x86::paging::swap_entry(self.inner(), virtaddr)
+	}
+
+	/// Tells whether the page at `virtaddr` has been accessed since the last call, clearing the
+	/// accessed flag atomically.
+	///
+	/// If the page is not mapped, the function returns `false`.
+	pub fn poll_accessed(&self, virtaddr: VirtAddr) -> bool {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		x86::paging::poll_accessed(self.inner(), virtaddr)
+	}
+
+	/// Returns the physical address of the root paging object.
+	fn phys_addr(&self) -> PhysAddr {
+		VirtAddr::from(self.table.as_ptr())
+			.kernel_to_physical()
+			.unwrap()
+	}
+
 	/// Binds the virtual memory context to the current CPU.
 	pub fn bind(&self) {
-		let phys_addr = VirtAddr::from(self.table.as_ptr())
-			.kernel_to_physical()
-			.unwrap();
+		let phys_addr = self.phys_addr();
+		// Record the context before binding so that no TLB shootdown can be missed
+		core_local().set_page_dir(phys_addr);
 		unsafe {
 			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 			x86::paging::bind(phys_addr);
@@ -183,6 +238,20 @@ pub fn invalidate_page_current(addr: VirtAddr) {
 	x86::paging::invlpg(addr);
 }
 
+/// Invalidate `pages` pages from cache starting at the given address on the current CPU.
+///
+/// If the range is large, the whole cache is flushed instead.
+pub fn invalidate_range_current(addr: VirtAddr, pages: usize) {
+	if pages > INVALIDATE_MAX_PAGES {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		x86::paging::flush_global();
+		return;
+	}
+	for i in 0..pages {
+		invalidate_page_current(addr + i * PAGE_SIZE);
+	}
+}
+
 /// Flush the Translation Lookaside Buffer (TLB) on the current CPU.
 ///
 /// This function should be called after applying modifications to the context for them to be
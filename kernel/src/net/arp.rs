@@ -0,0 +1,328 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The Address Resolution Protocol (RFC 826) maps IPv4 addresses to Ethernet MAC addresses.
+//!
+//! Resolved addresses are kept in a cache for [`REACHABLE_TIME`]. While an address is being
+//! resolved, packets sent to it are queued and transmitted once the reply is received.
+
+use super::{Address, Interface, MAC, buf::BufList, eth, eth::ETHERTYPE_IPV4};
+use crate::{
+	arch::x86::sti,
+	sync::mutex::Mutex,
+	time::{
+		clock::{Clock, current_time_ns},
+		sleep_for,
+		unit::Timestamp,
+	},
+};
+use core::mem::size_of;
+use macros::AnyRepr;
+use utils::{
+	bytes::{as_bytes, from_bytes},
+	collections::{hashmap::HashMap, vec::Vec},
+	errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// Hardware type: Ethernet
+const HTYPE_ETHERNET: u16 = 1;
+/// Operation: request
+const OP_REQUEST: u16 = 1;
+/// Operation: reply
+const OP_REPLY: u16 = 2;
+
+/// The duration during which a resolved address is valid, in nanoseconds.
+const REACHABLE_TIME: Timestamp = 60_000_000_000;
+/// The interval between two requests for an unresolved address, in nanoseconds.
+const RETRANS_TIME: Timestamp = 1_000_000_000;
+/// The maximum number of requests sent for an address before giving up.
+const MAX_PROBES: u32 = 3;
+/// The maximum number of packets queued on an unresolved address.
+const MAX_PENDING: usize = 8;
+
+/// An ARP packet for IPv4 over Ethernet.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+struct ArpHdr {
+	/// Hardware type.
+	htype: u16,
+	/// Protocol type.
+	ptype: u16,
+	/// Hardware address length.
+	hlen: u8,
+	/// Protocol address length.
+	plen: u8,
+	/// Operation.
+	oper: u16,
+	/// Sender hardware address.
+	sha: MAC,
+	/// Sender protocol address.
+	spa: [u8; 4],
+	/// Target hardware address.
+	tha: MAC,
+	/// Target protocol address.
+	tpa: [u8; 4],
+}
+
+impl ArpHdr {
+	/// Creates a packet with the given operation and addresses.
+	fn new(oper: u16, sha: MAC, spa: [u8; 4], tha: MAC, tpa: [u8; 4]) -> Self {
+		Self {
+			htype: HTYPE_ETHERNET.to_be(),
+			ptype: ETHERTYPE_IPV4.to_be(),
+			hlen: size_of::<MAC>() as _,
+			plen: 4,
+			oper: oper.to_be(),
+			sha,
+			spa,
+			tha,
+			tpa,
+		}
+	}
+}
+
+/// An entry of the ARP cache.
+enum Entry {
+	/// The address is resolved.
+	Resolved {
+		/// The hardware address.
+		mac: MAC,
+		/// The timestamp after which the entry is stale.
+		expiry: Timestamp,
+	},
+	/// The address is being resolved.
+	Incomplete {
+		/// The interface on which requests are sent.
+		iface: Arc<Mutex<dyn Interface>>,
+		/// The number of requests sent so far.
+		probes: u32,
+		/// The timestamp of the last request.
+		last: Timestamp,
+		/// Packets waiting for the address to be resolved.
+		pending: Vec<Vec<u8>>,
+	},
+}
+
+/// The ARP cache.
+static CACHE: Mutex<HashMap<[u8; 4], Entry>> = Mutex::new(HashMap::new());
+
+/// Sends an ARP packet with the sender hardware address of `iface`.
+///
+/// If `spa` is `None`, the sender protocol address is the address of the interface to be used
+/// to reach `tpa`, or zero if the interface has none.
+fn send(
+	iface: &Mutex<dyn Interface>,
+	oper: u16,
+	spa: Option<[u8; 4]>,
+	tha: MAC,
+	tpa: [u8; 4],
+) -> EResult<()> {
+	let mut iface = iface.lock();
+	let spa = spa.unwrap_or_else(|| {
+		iface
+			.get_addresses()
+			.iter()
+			.filter(|a| matches!(a.addr, Address::IPv4(_)))
+			.max_by_key(|a| a.is_matching(&Address::IPv4(tpa)))
+			.and_then(|a| match a.addr {
+				Address::IPv4(addr) => Some(addr),
+				Address::IPv6(_) => None,
+			})
+			.unwrap_or_default()
+	});
+	let hdr = ArpHdr::new(oper, *iface.get_mac(), spa, tha, tpa);
+	let dst = if oper == OP_REQUEST {
+		eth::BROADCAST
+	} else {
+		tha
+	};
+	eth::transmit(&mut *iface, dst, eth::ETHERTYPE_ARP, as_bytes(&hdr).into())
+}
+
+/// Returns the hardware address associated with `addr`.
+///
+/// If the address is not resolved yet, a request is sent on `iface`, a copy of `packet` is
+/// queued until the reply is received and the function returns `None`.
+pub fn resolve(
+	iface: &Arc<Mutex<dyn Interface>>,
+	addr: [u8; 4],
+	packet: &BufList<'_>,
+) -> EResult<Option<MAC>> {
+	let now = current_time_ns(Clock::Monotonic);
+	{
+		let mut cache = CACHE.lock();
+		match cache.get_mut(&addr) {
+			Some(Entry::Resolved {
+				mac,
+				expiry,
+			}) if *expiry > now => return Ok(Some(*mac)),
+			Some(Entry::Incomplete {
+				pending, ..
+			}) => {
+				if pending.len() >= MAX_PENDING {
+					pending.remove(0);
+				}
+				pending.push(packet.to_vec()?)?;
+				return Ok(None);
+			}
+			_ => {
+				let mut pending = Vec::new();
+				pending.push(packet.to_vec()?)?;
+				cache.insert(
+					addr,
+					Entry::Incomplete {
+						iface: iface.clone(),
+						probes: 1,
+						last: now,
+						pending,
+					},
+				)?;
+			}
+		}
+	}
+	send(iface, OP_REQUEST, None, [0; 6], addr)?;
+	Ok(None)
+}
+
+/// Announces the binding of `addr` to `iface` by sending a gratuitous ARP request, so that other
+/// hosts on the network update their caches.
+///
+/// Nothing is sent on loopback interfaces.
+pub fn announce(iface: &Mutex<dyn Interface>, addr: [u8; 4]) -> EResult<()> {
+	if iface.lock().is_loopback() {
+		return Ok(());
+	}
+	send(iface, OP_REQUEST, Some(addr), [0; 6], addr)
+}
+
+/// Handles an ARP packet received on `iface`.
+///
+/// Invalid packets are silently dropped.
+pub fn input(iface: &Arc<Mutex<dyn Interface>>, packet: &[u8]) -> EResult<()> {
+	let Some(hdr) = from_bytes::<ArpHdr>(packet) else {
+		return Ok(());
+	};
+	if u16::from_be(hdr.htype) != HTYPE_ETHERNET
+		|| u16::from_be(hdr.ptype) != ETHERTYPE_IPV4
+		|| hdr.hlen as usize != size_of::<MAC>()
+		|| hdr.plen != 4
+	{
+		return Ok(());
+	}
+	let (sha, spa, tpa) = (hdr.sha, hdr.spa, hdr.tpa);
+	let local = iface
+		.lock()
+		.get_addresses()
+		.iter()
+		.any(|a| a.addr == Address::IPv4(tpa));
+	// Update the cache (RFC 826, "Packet Reception"). Probes have no sender address
+	let mut flush = None;
+	if spa != [0; 4] {
+		let now = current_time_ns(Clock::Monotonic);
+		let resolved = Entry::Resolved {
+			mac: sha,
+			expiry: now + REACHABLE_TIME,
+		};
+		let mut cache = CACHE.lock();
+		if let Some(entry) = cache.get_mut(&spa) {
+			flush = match core::mem::replace(entry, resolved) {
+				Entry::Incomplete {
+					iface,
+					pending,
+					..
+				} => Some((iface, pending)),
+				Entry::Resolved {
+					..
+				} => None,
+			};
+		} else if local {
+			cache.insert(spa, resolved)?;
+		}
+	}
+	// Transmit packets that were waiting for the address
+	if let Some((iface, pending)) = flush {
+		let mut iface = iface.lock();
+		for packet in pending {
+			let _ = eth::transmit(
+				&mut *iface,
+				sha,
+				ETHERTYPE_IPV4,
+				BufList::from(packet.as_slice()),
+			);
+		}
+	}
+	if local && u16::from_be(hdr.oper) == OP_REQUEST {
+		send(iface, OP_REPLY, Some(tpa), sha, spa)?;
+	}
+	Ok(())
+}
+
+/// ARP timer task: retransmits requests for unresolved addresses and removes stale entries.
+pub(crate) fn timer_task() -> ! {
+	sti();
+	loop {
+		let now = current_time_ns(Clock::Monotonic);
+		let mut requests = Vec::new();
+		CACHE.lock().retain(|addr, entry| match entry {
+			Entry::Resolved {
+				expiry, ..
+			} => *expiry > now,
+			Entry::Incomplete {
+				iface,
+				probes,
+				last,
+				..
+			} => {
+				if now < *last + RETRANS_TIME {
+					return true;
+				}
+				// The address cannot be resolved: drop pending packets
+				if *probes >= MAX_PROBES {
+					return false;
+				}
+				*probes += 1;
+				*last = now;
+				// On allocation failure, retry next time
+				let _ = requests.push((iface.clone(), *addr));
+				true
+			}
+		});
+		for (iface, addr) in requests {
+			let _ = send(&iface, OP_REQUEST, None, [0; 6], addr);
+		}
+		// Sleep
+		let mut remain = 0;
+		let _ = sleep_for(Clock::Monotonic, RETRANS_TIME, &mut remain);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn arp_hdr() {
+		let hdr = ArpHdr::new(OP_REPLY, [1; 6], [10, 0, 0, 1], [2; 6], [10, 0, 0, 2]);
+		let bytes = as_bytes(&hdr);
+		assert_eq!(bytes.len(), 28);
+		assert_eq!(&bytes[0..8], &[0, 1, 8, 0, 6, 4, 0, 2]);
+		assert_eq!(&bytes[14..18], &[10, 0, 0, 1]);
+		assert_eq!(&bytes[24..28], &[10, 0, 0, 2]);
+	}
+}
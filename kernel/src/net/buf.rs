@@ -19,6 +19,7 @@
 //! Buffer list, to avoid allocations in network code.
 
 use core::ptr::NonNull;
+use utils::{collections::vec::Vec, errno::AllocResult};
 
 /// A linked-list of buffers representing a packet being built.
 pub struct BufList<'b> {
@@ -65,4 +66,15 @@ impl<'b> BufList<'b> {
 	pub fn next(&self) -> Option<&BufList<'b>> {
 		unsafe { self.next.map(|n| n.as_ref()) }
 	}
+
+	/// Copies the content of the whole list into a single buffer.
+	pub fn to_vec(&self) -> AllocResult<Vec<u8>> {
+		let mut vec = Vec::with_capacity(self.len())?;
+		let mut cur = Some(self);
+		while let Some(b) = cur {
+			vec.extend_from_slice(b.data)?;
+			cur = b.next();
+		}
+		Ok(vec)
+	}
 }
@@ -0,0 +1,746 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Dynamic Host Configuration Protocol client (RFC 2131), allowing to boot without local
+//! configuration.
+//!
+//! The client is enabled with the `-ip dhcp` command line argument. It runs as a kernel task,
+//! waiting for the first non-loopback interface to come up (network drivers are loaded as
+//! modules). The leased address is bound to the interface and a default route is added through
+//! the router given by the server. The lease is renewed until the server stops acknowledging it.
+//!
+//! The lease is obtained in the background: the root filesystem is mounted before the scheduler
+//! starts, and network drivers are loaded from it afterwards.
+//!
+//! Replies are received on a kernel UDP socket bound to the client port, which prevents another
+//! client from running in userspace at the same time.
+
+use super::{
+	Address, BindAddress, Interface, MAC, Route, SocketDesc, SocketDomain, SocketType, eth,
+	eth::ETHERTYPE_IPV4,
+	ioctl::{classful_prefix, mask_to_prefix},
+	ip,
+	ip::PROTO_UDP,
+	list_ifaces,
+	osi::Layer,
+	sockaddr::SockAddr,
+	udp,
+	udp::UDPLayer,
+};
+use crate::{
+	arch::x86::sti,
+	crypto::{rand, rand::GRND_NONBLOCK},
+	file::{socket::Socket, wait_queue::WaitQueue},
+	memory::user::UserSlice,
+	sync::mutex::Mutex,
+	time::{
+		clock::{Clock, current_time_ns},
+		sleep_for,
+		timer::Timer,
+		unit::Timestamp,
+	},
+};
+use core::{
+	cmp::min,
+	mem::size_of,
+	sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use macros::AnyRepr;
+use utils::{
+	TryClone,
+	bytes::{as_bytes, from_bytes},
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// The UDP port of servers.
+const SERVER_PORT: u16 = 67;
+/// The UDP port of clients.
+const CLIENT_PORT: u16 = 68;
+
+/// Operation: message from a client
+const OP_REQUEST: u8 = 1;
+/// Operation: message from a server
+const OP_REPLY: u8 = 2;
+/// Hardware type: Ethernet
+const HTYPE_ETHERNET: u8 = 1;
+/// Flag asking the server to broadcast its replies.
+const FLAG_BROADCAST: u16 = 0x8000;
+/// The magic cookie preceding options (RFC 2132 section 2).
+const MAGIC_COOKIE: u32 = 0x63825363;
+/// The minimum size of a message (RFC 1542 section 3.1.1).
+const MIN_MSG_LEN: usize = 300;
+/// The maximum size of a message the client accepts without negotiation (RFC 2131 section 2).
+const MAX_MSG_LEN: usize = 576;
+
+/// Option: padding
+const OPT_PAD: u8 = 0;
+/// Option: subnet mask
+const OPT_SUBNET_MASK: u8 = 1;
+/// Option: list of routers, by order of preference
+const OPT_ROUTER: u8 = 3;
+/// Option: address requested by the client
+const OPT_REQUESTED_ADDR: u8 = 50;
+/// Option: lease duration in seconds
+const OPT_LEASE_TIME: u8 = 51;
+/// Option: message type
+const OPT_MSG_TYPE: u8 = 53;
+/// Option: server identifier
+const OPT_SERVER_ID: u8 = 54;
+/// Option: list of requested options
+const OPT_PARAM_LIST: u8 = 55;
+/// Option: delay before entering the renewing state, in seconds
+const OPT_RENEWAL_TIME: u8 = 58;
+/// Option: delay before entering the rebinding state, in seconds
+const OPT_REBINDING_TIME: u8 = 59;
+/// Option: end of options
+const OPT_END: u8 = 255;
+
+/// Message type: client looking for servers
+const DHCPDISCOVER: u8 = 1;
+/// Message type: server offering an address
+const DHCPOFFER: u8 = 2;
+/// Message type: client requesting or renewing an address
+const DHCPREQUEST: u8 = 3;
+/// Message type: server acknowledging a lease
+const DHCPACK: u8 = 5;
+/// Message type: server refusing a lease
+const DHCPNAK: u8 = 6;
+
+/// The initial delay before retransmitting a message, in nanoseconds.
+const RETRANS_MIN: Timestamp = 4_000_000_000;
+/// The maximum delay before retransmitting a message, in nanoseconds.
+const RETRANS_MAX: Timestamp = 64_000_000_000;
+/// The minimum delay before retransmitting a message while renewing a lease, in nanoseconds.
+const RENEW_RETRANS_MIN: Timestamp = 60_000_000_000;
+/// The interval at which interfaces are checked while waiting for one to come up, in
+/// nanoseconds.
+const IFACE_POLL_INTERVAL: Timestamp = 1_000_000_000;
+
+/// The fixed part of a message.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+struct DhcpHdr {
+	/// Operation.
+	op: u8,
+	/// Hardware address type.
+	htype: u8,
+	/// Hardware address length.
+	hlen: u8,
+	/// Number of relay agents the message went through.
+	hops: u8,
+	/// Transaction ID.
+	xid: u32,
+	/// Seconds elapsed since the client began the acquisition or renewal.
+	secs: u16,
+	/// Flags.
+	flags: u16,
+	/// Client address, if already bound.
+	ciaddr: [u8; 4],
+	/// Address assigned to the client by the server.
+	yiaddr: [u8; 4],
+	/// Address of the next server to use in bootstrap.
+	siaddr: [u8; 4],
+	/// Relay agent address.
+	giaddr: [u8; 4],
+	/// Client hardware address.
+	chaddr: [u8; 16],
+	/// Optional server host name.
+	sname: [u8; 64],
+	/// Boot file name.
+	file: [u8; 128],
+	/// Magic cookie, preceding options.
+	magic: u32,
+}
+
+/// The parameters of a lease.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Lease {
+	/// The leased address.
+	addr: [u8; 4],
+	/// The prefix length of the subnet.
+	prefix: u8,
+	/// The default gateway.
+	router: Option<[u8; 4]>,
+	/// The identifier of the server granting the lease.
+	server: [u8; 4],
+	/// The duration of the lease, in seconds.
+	lease_time: u32,
+	/// The delay before renewing the lease, in seconds.
+	renewal_time: u32,
+	/// The delay before renewing the lease with any server, in seconds.
+	rebinding_time: u32,
+}
+
+/// A message received from a server.
+#[derive(Debug, Default)]
+struct Reply {
+	/// The message type.
+	msg_type: u8,
+	/// The address assigned to the client.
+	yiaddr: [u8; 4],
+	/// The server identifier.
+	server_id: Option<[u8; 4]>,
+	/// The subnet mask.
+	subnet_mask: Option<[u8; 4]>,
+	/// The preferred router.
+	router: Option<[u8; 4]>,
+	/// The lease duration, in seconds.
+	lease_time: Option<u32>,
+	/// The delay before renewal, in seconds.
+	renewal_time: Option<u32>,
+	/// The delay before rebinding, in seconds.
+	rebinding_time: Option<u32>,
+}
+
+impl Reply {
+	/// Parses the message `msg`.
+	///
+	/// If the message is invalid, or is not a reply to the transaction `xid` of the client with
+	/// hardware address `mac`, the function returns `None`.
+	fn parse(msg: &[u8], xid: u32, mac: &MAC) -> Option<Self> {
+		let hdr = from_bytes::<DhcpHdr>(msg)?;
+		if hdr.op != OP_REPLY
+			|| u32::from_be(hdr.xid) != xid
+			|| hdr.chaddr[..6] != mac[..]
+			|| u32::from_be(hdr.magic) != MAGIC_COOKIE
+		{
+			return None;
+		}
+		let mut reply = Self {
+			yiaddr: hdr.yiaddr,
+			..Default::default()
+		};
+		let mut opts = &msg[size_of::<DhcpHdr>()..];
+		while let Some((&code, rest)) = opts.split_first() {
+			match code {
+				OPT_PAD => {
+					opts = rest;
+					continue;
+				}
+				OPT_END => break,
+				_ => {}
+			}
+			let (&len, rest) = rest.split_first()?;
+			let (val, rest) = rest.split_at_checked(len as usize)?;
+			opts = rest;
+			let addr: Option<[u8; 4]> = val.try_into().ok();
+			let secs = addr.map(u32::from_be_bytes);
+			match code {
+				OPT_MSG_TYPE => reply.msg_type = *val.first()?,
+				OPT_SUBNET_MASK => reply.subnet_mask = addr,
+				// Routers are listed by order of preference
+				OPT_ROUTER => reply.router = val.first_chunk().copied(),
+				OPT_SERVER_ID => reply.server_id = addr,
+				OPT_LEASE_TIME => reply.lease_time = secs,
+				OPT_RENEWAL_TIME => reply.renewal_time = secs,
+				OPT_REBINDING_TIME => reply.rebinding_time = secs,
+				_ => {}
+			}
+		}
+		(reply.msg_type != 0).then_some(reply)
+	}
+
+	/// Returns the lease described by the reply.
+	///
+	/// If the reply lacks mandatory parameters, the function returns `None`.
+	fn lease(&self) -> Option<Lease> {
+		let server = self.server_id?;
+		let lease_time = self.lease_time?;
+		let prefix = self
+			.subnet_mask
+			.and_then(mask_to_prefix)
+			.unwrap_or_else(|| classful_prefix(self.yiaddr));
+		// Default timers (RFC 2131 section 4.4.5)
+		let rebinding_time = self
+			.rebinding_time
+			.unwrap_or((lease_time as u64 * 7 / 8) as u32);
+		let renewal_time = self
+			.renewal_time
+			.unwrap_or(lease_time / 2)
+			.min(rebinding_time);
+		Some(Lease {
+			addr: self.yiaddr,
+			prefix,
+			router: self.router,
+			server,
+			lease_time,
+			renewal_time,
+			rebinding_time,
+		})
+	}
+}
+
+/// Builds a message of type `msg_type` for the transaction `xid`.
+///
+/// Arguments:
+/// - `mac` is the hardware address of the client.
+/// - `ciaddr` is the address of the client, if it has one.
+/// - `selected` is the lease selected from an offer, if any.
+fn build_msg(
+	msg_type: u8,
+	xid: u32,
+	mac: &MAC,
+	ciaddr: Option<[u8; 4]>,
+	selected: Option<&Lease>,
+) -> AllocResult<Vec<u8>> {
+	let mut chaddr = [0; 16];
+	chaddr[..6].copy_from_slice(mac);
+	let hdr = DhcpHdr {
+		op: OP_REQUEST,
+		htype: HTYPE_ETHERNET,
+		hlen: 6,
+		hops: 0,
+		xid: xid.to_be(),
+		secs: 0,
+		// Without an address, unicast replies could not be received
+		flags: if ciaddr.is_none() {
+			FLAG_BROADCAST.to_be()
+		} else {
+			0
+		},
+		ciaddr: ciaddr.unwrap_or_default(),
+		yiaddr: [0; 4],
+		siaddr: [0; 4],
+		giaddr: [0; 4],
+		chaddr,
+		sname: [0; 64],
+		file: [0; 128],
+		magic: MAGIC_COOKIE.to_be(),
+	};
+	let mut msg = Vec::try_from(as_bytes(&hdr))?;
+	msg.extend_from_slice(&[OPT_MSG_TYPE, 1, msg_type])?;
+	if let Some(lease) = selected {
+		msg.extend_from_slice(&[OPT_REQUESTED_ADDR, 4])?;
+		msg.extend_from_slice(&lease.addr)?;
+		msg.extend_from_slice(&[OPT_SERVER_ID, 4])?;
+		msg.extend_from_slice(&lease.server)?;
+	}
+	msg.extend_from_slice(&[
+		OPT_PARAM_LIST,
+		5,
+		OPT_SUBNET_MASK,
+		OPT_ROUTER,
+		OPT_LEASE_TIME,
+		OPT_RENEWAL_TIME,
+		OPT_REBINDING_TIME,
+	])?;
+	msg.push(OPT_END)?;
+	if msg.len() < MIN_MSG_LEN {
+		msg.resize(MIN_MSG_LEN, OPT_PAD)?;
+	}
+	Ok(msg)
+}
+
+/// The state of the client.
+enum State {
+	/// Looking for a server.
+	Init,
+	/// An offer has been selected and is being requested to the server.
+	Requesting(Lease),
+	/// The lease is configured, and has been acknowledged at the given timestamp.
+	Bound(Lease, Timestamp),
+	/// The lease is being renewed with the server which granted it.
+	Renewing(Lease, Timestamp),
+	/// The lease is being renewed with any server.
+	Rebinding(Lease, Timestamp),
+}
+
+/// The state of the renewal timer, shared with its callback.
+#[derive(Default)]
+struct Renewal {
+	/// Tells whether the timer has expired.
+	expired: AtomicBool,
+	/// The queue on which the client waits for the timer to expire.
+	queue: WaitQueue,
+}
+
+/// A DHCP client, configuring a network interface.
+struct Client {
+	/// The name of the interface.
+	name: String,
+	/// The interface.
+	iface: Arc<Mutex<dyn Interface>>,
+	/// The hardware address of the interface.
+	mac: MAC,
+	/// The socket on which replies are received.
+	sock: Arc<Socket>,
+	/// The ID of the current transaction.
+	xid: u32,
+	/// The timer expiring when the lease has to be renewed.
+	timer: Timer,
+	/// The state shared with [`Self::timer`].
+	renewal: Arc<Renewal>,
+}
+
+impl Client {
+	/// Sends `msg` from the address `src`.
+	///
+	/// If `server` is `None`, the message is broadcast on the interface. Else, it is routed to
+	/// the server.
+	fn send(&self, msg: &[u8], src: [u8; 4], server: Option<[u8; 4]>) -> EResult<()> {
+		let src = Address::IPv4(src);
+		let dst = Address::IPv4(server.unwrap_or([255; 4]));
+		let udp = UDPLayer {
+			local: SockAddr {
+				port: CLIENT_PORT,
+				addr: src,
+			},
+			remote: Some(SockAddr {
+				port: SERVER_PORT,
+				addr: dst,
+			}),
+		};
+		let ip = ip::build_layer(PROTO_UDP, src, dst)?;
+		udp.transmit(msg.into(), &|buff| {
+			if server.is_some() {
+				ip.transmit(buff, &super::transmit)
+			} else {
+				ip.transmit(buff, &|buff| {
+					eth::transmit(
+						&mut *self.iface.lock(),
+						eth::BROADCAST,
+						ETHERTYPE_IPV4,
+						buff,
+					)
+				})
+			}
+		})
+	}
+
+	/// Waits until `deadline` for a reply to the current transaction which satisfies `accept`.
+	fn recv<F: Fn(&Reply) -> bool>(&self, deadline: Timestamp, accept: &F) -> Option<Reply> {
+		let mut buf = [0u8; MAX_MSG_LEN];
+		loop {
+			let now = current_time_ns(Clock::Monotonic);
+			if now >= deadline {
+				return None;
+			}
+			self.sock.opts().lock().rcv_timeout = deadline - now;
+			let res = udp::recv_from(&self.sock, UserSlice::from_slice_mut(&mut buf), false);
+			let len = match res {
+				Ok((len, _)) => len,
+				Err(e) => {
+					if e.as_int() != errno::EAGAIN {
+						// Do not retransmit early
+						let mut remain = 0;
+						let _ = sleep_for(Clock::Monotonic, deadline - now, &mut remain);
+					}
+					return None;
+				}
+			};
+			if let Some(reply) = Reply::parse(&buf[..len], self.xid, &self.mac) {
+				if accept(&reply) {
+					return Some(reply);
+				}
+			}
+		}
+	}
+
+	/// Sends `msg` and waits for a reply which satisfies `accept`, retransmitting the message
+	/// until `deadline` (RFC 2131 section 4.1).
+	///
+	/// If `renew` is set, retransmissions are spaced by half the remaining time. Else, they
+	/// are spaced by an exponential backoff.
+	///
+	/// For `src` and `server`, see [`Self::send`].
+	fn exchange<F: Fn(&Reply) -> bool>(
+		&self,
+		msg: &[u8],
+		src: [u8; 4],
+		server: Option<[u8; 4]>,
+		deadline: Timestamp,
+		renew: bool,
+		accept: F,
+	) -> Option<Reply> {
+		let mut delay = RETRANS_MIN;
+		loop {
+			let now = current_time_ns(Clock::Monotonic);
+			if now >= deadline {
+				return None;
+			}
+			if renew {
+				delay = ((deadline - now) / 2).max(RENEW_RETRANS_MIN);
+			}
+			// On failure, retry after the delay
+			let _ = self.send(msg, src, server);
+			if let Some(reply) = self.recv(min(now.saturating_add(delay), deadline), &accept) {
+				return Some(reply);
+			}
+			delay = min(delay * 2, RETRANS_MAX);
+		}
+	}
+
+	/// Binds the leased address to the interface and adds the default route.
+	fn configure(&self, lease: &Lease) -> EResult<()> {
+		let addr = BindAddress {
+			addr: Address::IPv4(lease.addr),
+			subnet_mask: lease.prefix,
+		};
+		match super::add_address(&self.name, &self.iface, addr) {
+			Err(e) if e.as_int() == errno::EEXIST => {}
+			res => res?,
+		}
+		if let Some(router) = lease.router {
+			let route = Route {
+				dst: None,
+				iface: self.name.try_clone()?,
+				gateway: Address::IPv4(router),
+				metric: 0,
+			};
+			super::add_route(route, true)?;
+		}
+		Ok(())
+	}
+
+	/// Removes the configuration added by [`Self::configure`].
+	fn unconfigure(&self, lease: &Lease) {
+		let addr = BindAddress {
+			addr: Address::IPv4(lease.addr),
+			subnet_mask: lease.prefix,
+		};
+		let _ = super::remove_address(&self.name, &self.iface, &addr);
+		if let Some(router) = lease.router {
+			let _ = super::remove_route(|r| {
+				r.dst.is_none() && r.iface == self.name && r.gateway == Address::IPv4(router)
+			});
+		}
+	}
+
+	/// Handles the acknowledgement of `lease`, replacing the `old` lease if any.
+	///
+	/// If the configuration fails, the lease is dropped and the client restarts from the
+	/// beginning.
+	fn bind(&self, lease: Lease, old: Option<&Lease>) -> State {
+		if let Some(old) = old {
+			if *old != lease {
+				self.unconfigure(old);
+			}
+		}
+		match self.configure(&lease) {
+			Ok(()) => State::Bound(lease, current_time_ns(Clock::Monotonic)),
+			Err(_) => {
+				self.unconfigure(&lease);
+				State::Init
+			}
+		}
+	}
+
+	/// Runs the actions of the state `state`, and returns the next state.
+	fn step(&mut self, state: State) -> State {
+		match state {
+			State::Init => {
+				self.xid = gen_xid();
+				let Ok(msg) = build_msg(DHCPDISCOVER, self.xid, &self.mac, None, None) else {
+					return State::Init;
+				};
+				self.exchange(&msg, [0; 4], None, Timestamp::MAX, false, |r| {
+					r.msg_type == DHCPOFFER && r.lease().is_some()
+				})
+				.and_then(|r| r.lease())
+				.map(State::Requesting)
+				.unwrap_or(State::Init)
+			}
+			State::Requesting(offer) => {
+				let Ok(msg) = build_msg(DHCPREQUEST, self.xid, &self.mac, None, Some(&offer))
+				else {
+					return State::Init;
+				};
+				let deadline = current_time_ns(Clock::Monotonic) + RETRANS_MAX;
+				let reply = self.exchange(&msg, [0; 4], None, deadline, false, |r| {
+					matches!(r.msg_type, DHCPACK | DHCPNAK) && r.server_id == Some(offer.server)
+				});
+				match reply {
+					Some(r) if r.msg_type == DHCPACK => {
+						self.bind(r.lease().unwrap_or(offer), None)
+					}
+					_ => State::Init,
+				}
+			}
+			State::Bound(lease, start) => {
+				let t1 = start.saturating_add(lease.renewal_time as Timestamp * 1_000_000_000);
+				let now = current_time_ns(Clock::Monotonic);
+				if now < t1 {
+					self.renewal.expired.store(false, Relaxed);
+					// If the timer cannot be armed, renew early
+					if self.timer.set_time(0, t1 - now).is_ok() {
+						let _ = self
+							.renewal
+							.queue
+							.wait_until(|| self.renewal.expired.load(Relaxed).then_some(()));
+						return State::Bound(lease, start);
+					}
+				}
+				self.xid = gen_xid();
+				State::Renewing(lease, start)
+			}
+			State::Renewing(lease, start) | State::Rebinding(lease, start) => {
+				let rebinding = matches!(state, State::Rebinding(..));
+				let (server, secs) = if rebinding {
+					(None, lease.lease_time)
+				} else {
+					(Some(lease.server), lease.rebinding_time)
+				};
+				let deadline = start.saturating_add(secs as Timestamp * 1_000_000_000);
+				let Ok(msg) = build_msg(DHCPREQUEST, self.xid, &self.mac, Some(lease.addr), None)
+				else {
+					return state;
+				};
+				let reply = self.exchange(&msg, lease.addr, server, deadline, true, |r| {
+					matches!(r.msg_type, DHCPACK | DHCPNAK)
+				});
+				match reply {
+					Some(r) if r.msg_type == DHCPACK => match r.lease() {
+						Some(new) => self.bind(new, Some(&lease)),
+						None => self.bind(lease, Some(&lease)),
+					},
+					None if !rebinding => State::Rebinding(lease, start),
+					// Refused or expired
+					_ => {
+						self.unconfigure(&lease);
+						State::Init
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Generates a transaction ID.
+fn gen_xid() -> u32 {
+	let mut buf = [0u8; 4];
+	let _ = rand::getrandom(UserSlice::from_slice_mut(&mut buf), GRND_NONBLOCK);
+	u32::from_ne_bytes(buf) ^ current_time_ns(Clock::Monotonic) as u32
+}
+
+/// Creates the socket on which replies are received.
+fn open_socket() -> EResult<Arc<Socket>> {
+	let sock = Arc::new(Socket::new(SocketDesc {
+		domain: SocketDomain::AfInet,
+		type_: SocketType::SockDgram,
+		protocol: 0,
+	})?)?;
+	udp::bind(
+		&sock,
+		SockAddr {
+			port: CLIENT_PORT,
+			addr: Address::IPv4([0; 4]),
+		},
+	)?;
+	Ok(sock)
+}
+
+/// Creates the timer used to renew leases, along with the state shared with its callback.
+fn renewal_timer() -> AllocResult<(Timer, Arc<Renewal>)> {
+	let renewal = Arc::new(Renewal::default())?;
+	let timer = {
+		let renewal = renewal.clone();
+		Timer::with_callback(Clock::Monotonic, move || {
+			renewal.expired.store(true, Relaxed);
+			renewal.queue.wake_all();
+		})?
+	};
+	Ok((timer, renewal))
+}
+
+/// Returns the first non-loopback interface which is up, along with its name.
+fn find_iface() -> Option<(String, Arc<Mutex<dyn Interface>>)> {
+	list_ifaces()
+		.ok()?
+		.into_iter()
+		.find(|(_, e)| {
+			let iface = e.iface.lock();
+			!iface.is_loopback() && iface.is_up()
+		})
+		.map(|(name, e)| (name, e.iface))
+}
+
+/// DHCP client task: configures the first interface to come up and maintains its lease.
+pub(crate) fn client_task() -> ! {
+	sti();
+	let mut remain = 0;
+	let (sock, (timer, renewal), (name, iface)) = loop {
+		if let (Ok(sock), Ok(timer), Some(iface)) = (open_socket(), renewal_timer(), find_iface())
+		{
+			break (sock, timer, iface);
+		}
+		let _ = sleep_for(Clock::Monotonic, IFACE_POLL_INTERVAL, &mut remain);
+	};
+	let mac = *iface.lock().get_mac();
+	let mut client = Client {
+		name,
+		iface,
+		mac,
+		sock,
+		xid: 0,
+		timer,
+		renewal,
+	};
+	let mut state = State::Init;
+	loop {
+		state = client.step(state);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn dhcp_msg() {
+		let mac = [2, 0, 0, 0, 0, 1];
+		let mut msg = build_msg(DHCPDISCOVER, 42, &mac, None, None).unwrap();
+		assert_eq!(msg.len(), MIN_MSG_LEN);
+		assert_eq!(
+			&msg[236..243],
+			&[0x63, 0x82, 0x53, 0x63, OPT_MSG_TYPE, 1, DHCPDISCOVER]
+		);
+		// A message from a client is not a reply
+		assert!(Reply::parse(&msg, 42, &mac).is_none());
+		// Turn it into an acknowledgement
+		msg[0] = OP_REPLY;
+		msg[16..20].copy_from_slice(&[192, 168, 1, 10]);
+		msg[242] = DHCPACK;
+		#[rustfmt::skip]
+		let opts = [
+			OPT_SERVER_ID, 4, 192, 168, 1, 1,
+			OPT_SUBNET_MASK, 4, 255, 255, 255, 0,
+			OPT_ROUTER, 8, 192, 168, 1, 254, 192, 168, 1, 253,
+			OPT_LEASE_TIME, 4, 0, 0, 0x0e, 0x10,
+			OPT_END,
+		];
+		msg[243..(243 + opts.len())].copy_from_slice(&opts);
+		assert!(Reply::parse(&msg, 43, &mac).is_none());
+		let reply = Reply::parse(&msg, 42, &mac).unwrap();
+		assert_eq!(reply.msg_type, DHCPACK);
+		assert_eq!(
+			reply.lease(),
+			Some(Lease {
+				addr: [192, 168, 1, 10],
+				prefix: 24,
+				router: Some([192, 168, 1, 254]),
+				server: [192, 168, 1, 1],
+				lease_time: 3600,
+				renewal_time: 1800,
+				rebinding_time: 3150,
+			})
+		);
+	}
+}
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! This module implements Ethernet II framing (IEEE 802.3).
+
+use super::{Interface, MAC, arp, buf::BufList, ip};
+use crate::sync::mutex::Mutex;
+use core::mem::size_of;
+use macros::AnyRepr;
+use utils::{
+	bytes::{as_bytes, from_bytes},
+	errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// EtherType: IPv4
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+/// EtherType: ARP
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+/// EtherType: IPv6
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// The broadcast MAC address.
+pub const BROADCAST: MAC = [0xff; 6];
+
+/// The Ethernet II header.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+pub struct EthernetHdr {
+	/// Destination MAC address.
+	pub dst: MAC,
+	/// Source MAC address.
+	pub src: MAC,
+	/// The protocol of the payload.
+	pub ethertype: u16,
+}
+
+/// Returns the multicast MAC address associated with the given IPv6 multicast address (RFC 2464
+/// section 7).
+pub fn ipv6_multicast_mac(addr: &[u8; 16]) -> MAC {
+	[0x33, 0x33, addr[12], addr[13], addr[14], addr[15]]
+}
+
+/// Prepends an Ethernet II header to the packet in `buff` and writes the resulting frame on
+/// `iface`.
+///
+/// Arguments:
+/// - `dst` is the MAC address of the next hop.
+/// - `ethertype` is the protocol of the packet.
+pub fn transmit(
+	iface: &mut dyn Interface,
+	dst: MAC,
+	ethertype: u16,
+	mut buff: BufList<'_>,
+) -> EResult<()> {
+	let hdr = EthernetHdr {
+		dst,
+		src: *iface.get_mac(),
+		ethertype: ethertype.to_be(),
+	};
+	let buff = buff.push_front(as_bytes(&hdr).into());
+	iface.write(&buff)?;
+	Ok(())
+}
+
+/// Handles a frame received on the network interface `iface`.
+///
+/// Frames which are not addressed to the interface or with an unsupported EtherType are silently
+/// dropped.
+pub fn input(iface: &Arc<Mutex<dyn Interface>>, frame: &[u8]) -> EResult<()> {
+	let Some(hdr) = from_bytes::<EthernetHdr>(frame) else {
+		return Ok(());
+	};
+	// Accept broadcast and multicast frames (least significant bit of the first octet)
+	let dst = hdr.dst;
+	if dst[0] & 1 == 0 && dst != *iface.lock().get_mac() {
+		return Ok(());
+	}
+	let payload = &frame[size_of::<EthernetHdr>()..];
+	match u16::from_be(hdr.ethertype) {
+		ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => ip::input(payload),
+		ETHERTYPE_ARP => arp::input(iface, payload),
+		_ => Ok(()),
+	}
+}
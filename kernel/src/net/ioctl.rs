@@ -0,0 +1,434 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Legacy `SIOC*` socket ioctls, used by tools such as `ifconfig` and `route` to configure
+//! network interfaces and the routing table.
+//!
+//! Only IPv4 is supported by these requests. IPv6 is to be configured through netlink.
+
+use super::{
+	Address, BindAddress, IFF_UP, Interface, Route, add_address, add_route, get_gateway_iface,
+	get_iface, get_iface_by_index, list_ifaces, remove_address, remove_route, sockaddr::SockAddr,
+};
+use crate::{
+	memory::user::{UserPtr, UserSlice, UserString},
+	process::Process,
+	sync::mutex::Mutex,
+	syscall::{FromSyscallArg, ioctl},
+};
+use core::{
+	ffi::{c_char, c_int, c_short, c_ulong, c_ushort, c_void},
+	mem::size_of,
+};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
+
+/// The maximum length of an interface name, including the terminating nul byte.
+const IFNAMSIZ: usize = 16;
+
+/// Route flag: the route is usable
+const RTF_UP: c_ushort = 0x1;
+/// Route flag: the destination is reached through a gateway
+const RTF_GATEWAY: c_ushort = 0x2;
+/// Route flag: the route is a host route
+const RTF_HOST: c_ushort = 0x4;
+
+/// Device mapping structure, which determines the size of the union in [`IfReq`].
+#[repr(C)]
+struct IfMap {
+	mem_start: c_ulong,
+	mem_end: c_ulong,
+	base_addr: c_ushort,
+	irq: u8,
+	dma: u8,
+	port: u8,
+}
+
+/// The size of the userspace `ifreq` structure.
+const IFREQ_SIZE: usize = IFNAMSIZ + size_of::<IfMap>();
+
+/// Request structure for interface ioctls (`ifreq`).
+///
+/// Only the part of the union used by the supported requests is present, so that the rest of the
+/// userspace structure is left untouched.
+#[repr(C)]
+#[derive(Debug)]
+struct IfReq {
+	/// The name of the interface.
+	name: [u8; IFNAMSIZ],
+	/// The request's argument.
+	data: [u8; 16],
+}
+
+impl IfReq {
+	/// Returns the name of the interface.
+	fn name(&self) -> &[u8] {
+		let len = self.name.iter().position(|b| *b == 0).unwrap_or(IFNAMSIZ);
+		&self.name[..len]
+	}
+
+	/// Sets the name of the interface.
+	///
+	/// If the name is too long, the function returns [`errno::ENAMETOOLONG`].
+	fn set_name(&mut self, name: &[u8]) -> EResult<()> {
+		if name.len() >= IFNAMSIZ {
+			return Err(errno!(ENAMETOOLONG));
+		}
+		self.name = [0; IFNAMSIZ];
+		self.name[..name.len()].copy_from_slice(name);
+		Ok(())
+	}
+
+	/// Returns the argument as an integer.
+	fn int(&self) -> c_int {
+		c_int::from_ne_bytes(*self.data.first_chunk().unwrap())
+	}
+
+	/// Sets the argument to the integer `val`.
+	fn set_int(&mut self, val: c_int) {
+		self.data[..size_of::<c_int>()].copy_from_slice(&val.to_ne_bytes());
+	}
+
+	/// Returns the IPv4 address in the argument.
+	///
+	/// If the argument is not a valid IPv4 address, the function returns [`errno::EINVAL`].
+	fn addr(&self) -> EResult<[u8; 4]> {
+		parse_addr(&self.data)
+	}
+
+	/// Sets the argument to the IPv4 address `addr`.
+	fn set_addr(&mut self, addr: [u8; 4]) -> EResult<()> {
+		let sockaddr = SockAddr {
+			port: 0,
+			addr: Address::IPv4(addr),
+		}
+		.to_bytes()?;
+		self.data.copy_from_slice(sockaddr.as_slice());
+		Ok(())
+	}
+}
+
+/// Interface configuration structure (`ifconf`).
+#[repr(C)]
+#[derive(Debug)]
+struct IfConf {
+	/// The size of the buffer.
+	len: c_int,
+	/// The buffer to fill with [`IfReq`] structures.
+	buf: *mut u8,
+}
+
+/// Routing table entry structure (`rtentry`).
+#[repr(C)]
+#[derive(Debug)]
+struct RtEntry {
+	pad1: c_ulong,
+	/// The destination address.
+	dst: [u8; 16],
+	/// The gateway address, if `RTF_GATEWAY` is set.
+	gateway: [u8; 16],
+	/// The destination's network mask.
+	genmask: [u8; 16],
+	/// Route flags.
+	flags: c_ushort,
+	pad2: c_short,
+	pad3: c_ulong,
+	pad4: *mut c_void,
+	/// The metric of the route, plus one.
+	metric: c_short,
+	/// The name of the interface to use, if not null.
+	dev: *mut c_char,
+	mtu: c_ulong,
+	window: c_ulong,
+	irtt: c_ushort,
+}
+
+/// Parses an IPv4 address from the sockaddr structure in `buf`.
+///
+/// If the structure is not a valid IPv4 address, the function returns [`errno::EINVAL`].
+fn parse_addr(buf: &[u8]) -> EResult<[u8; 4]> {
+	match SockAddr::from_bytes(buf) {
+		Ok(SockAddr {
+			addr: Address::IPv4(addr),
+			..
+		}) => Ok(addr),
+		_ => Err(errno!(EINVAL)),
+	}
+}
+
+/// Converts the network mask `mask` into a prefix length.
+///
+/// If the mask is not contiguous, the function returns `None`.
+pub(super) fn mask_to_prefix(mask: [u8; 4]) -> Option<u8> {
+	let mask = u32::from_be_bytes(mask);
+	let prefix = mask.leading_ones();
+	(mask == prefix_to_mask(prefix as _)).then_some(prefix as _)
+}
+
+/// Converts the prefix length `prefix` into a network mask, in host byte order.
+fn prefix_to_mask(prefix: u8) -> u32 {
+	u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+/// Returns the default prefix length for `addr` according to its class.
+pub(super) fn classful_prefix(addr: [u8; 4]) -> u8 {
+	match addr[0] {
+		0..128 => 8,
+		128..192 => 16,
+		_ => 24,
+	}
+}
+
+/// Returns the first IPv4 address bound to `iface`.
+///
+/// If the interface has no IPv4 address, the function returns [`errno::EADDRNOTAVAIL`].
+fn ipv4_addr(iface: &dyn Interface) -> EResult<([u8; 4], u8)> {
+	iface
+		.get_addresses()
+		.iter()
+		.find_map(|a| match a.addr {
+			Address::IPv4(addr) => Some((addr, a.subnet_mask)),
+			Address::IPv6(_) => None,
+		})
+		.ok_or_else(|| errno!(EADDRNOTAVAIL))
+}
+
+/// Replaces the IPv4 address of the interface with the given name, if any, by `addr`.
+fn set_ipv4_addr(
+	name: &[u8],
+	iface: &Arc<Mutex<dyn Interface>>,
+	addr: [u8; 4],
+	subnet_mask: u8,
+) -> EResult<()> {
+	let old = ipv4_addr(&*iface.lock()).ok();
+	if let Some((addr, subnet_mask)) = old {
+		let old = BindAddress {
+			addr: Address::IPv4(addr),
+			subnet_mask,
+		};
+		remove_address(name, iface, &old)?;
+	}
+	let addr = BindAddress {
+		addr: Address::IPv4(addr),
+		subnet_mask,
+	};
+	add_address(name, iface, addr)
+}
+
+/// Tells whether the current process is privileged. If not, the function returns
+/// [`errno::EPERM`].
+fn check_privileged() -> EResult<()> {
+	if Process::current().fs.lock().access_profile.is_privileged() {
+		Ok(())
+	} else {
+		Err(errno!(EPERM))
+	}
+}
+
+/// Handles `SIOCGIFCONF`.
+///
+/// If the buffer is null, the size required to store all entries is returned.
+fn get_conf(argp: *const c_void) -> EResult<()> {
+	// TODO handle the compat layout
+	let conf_ptr = UserPtr::<IfConf>::from_ptr(argp as usize);
+	let mut conf = conf_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let buf_len = conf.len.max(0) as usize;
+	let buf = UserSlice::from_user(conf.buf, buf_len)?;
+	let mut off = 0;
+	for (name, e) in list_ifaces()? {
+		let iface = e.iface.lock();
+		let addrs = iface.get_addresses().iter().filter_map(|a| match a.addr {
+			Address::IPv4(addr) => Some(addr),
+			Address::IPv6(_) => None,
+		});
+		for addr in addrs {
+			if buf.as_ptr().is_null() {
+				off += IFREQ_SIZE;
+				continue;
+			}
+			if off + IFREQ_SIZE > buf_len {
+				break;
+			}
+			let mut req = IfReq {
+				name: [0; IFNAMSIZ],
+				data: [0; 16],
+			};
+			req.set_name(&name)?;
+			req.set_addr(addr)?;
+			buf.copy_to_user(off, &req.name)?;
+			buf.copy_to_user(off + IFNAMSIZ, &req.data)?;
+			off += IFREQ_SIZE;
+		}
+	}
+	conf.len = off as _;
+	conf_ptr.copy_to_user(&conf)?;
+	Ok(())
+}
+
+/// Handles `SIOCADDRT` and `SIOCDELRT`.
+fn route(request: c_ulong, argp: *const c_void) -> EResult<()> {
+	check_privileged()?;
+	// TODO handle the compat layout
+	let entry = UserPtr::<RtEntry>::from_ptr(argp as usize)
+		.copy_from_user()?
+		.ok_or_else(|| errno!(EFAULT))?;
+	let dst_addr = parse_addr(&entry.dst)?;
+	let prefix = if entry.flags & RTF_HOST != 0 {
+		32
+	} else {
+		mask_to_prefix(parse_addr(&entry.genmask)?).ok_or_else(|| errno!(EINVAL))?
+	};
+	let dst = (prefix > 0).then_some(BindAddress {
+		addr: Address::IPv4(dst_addr),
+		subnet_mask: prefix,
+	});
+	let gateway = if entry.flags & RTF_GATEWAY != 0 {
+		parse_addr(&entry.gateway)?
+	} else {
+		[0; 4]
+	};
+	let gateway = Address::IPv4(gateway);
+	let dev = UserString::from_ptr(entry.dev as usize).copy_from_user()?;
+	if let Some(dev) = &dev {
+		get_iface(dev).ok_or_else(|| errno!(ENODEV))?;
+	}
+	// `route` passes the metric plus one, zero meaning unspecified
+	let metric = (entry.metric > 0).then(|| entry.metric as u32 - 1);
+	if request == ioctl::SIOCDELRT {
+		return remove_route(|r| {
+			r.gateway.same_family(&gateway)
+				&& r.has_dst(&dst)
+				&& (gateway.is_unspecified() || r.gateway == gateway)
+				&& metric.is_none_or(|m| m == r.metric)
+				&& dev.as_ref().is_none_or(|dev| *dev == r.iface)
+		});
+	}
+	if entry.flags & RTF_UP == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let iface = match dev {
+		Some(dev) => dev,
+		// Use the interface on which the gateway, or the destination, is reachable
+		None if gateway.is_unspecified() => get_gateway_iface(&Address::IPv4(dst_addr))?,
+		None => get_gateway_iface(&gateway)?,
+	};
+	let route = Route {
+		dst,
+		iface,
+		gateway,
+		metric: metric.unwrap_or(0),
+	};
+	add_route(route, false)
+}
+
+/// Handles requests taking an [`IfReq`] structure.
+fn ifreq(request: c_ulong, argp: *const c_void) -> EResult<()> {
+	let req_ptr = UserPtr::<IfReq>::from_ptr(argp as usize);
+	let mut req = req_ptr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if request == ioctl::SIOCGIFNAME {
+		let (name, _) = get_iface_by_index(req.int() as _)?;
+		req.set_name(&name)?;
+		return req_ptr.copy_to_user(&req);
+	}
+	let name = req.name();
+	let iface = get_iface(name).ok_or_else(|| errno!(ENODEV))?;
+	match request {
+		ioctl::SIOCGIFFLAGS => {
+			// Flags that do not fit in the structure are truncated
+			let flags = iface.lock().get_flags() as c_short;
+			req.data[..2].copy_from_slice(&flags.to_ne_bytes());
+		}
+		ioctl::SIOCSIFFLAGS => {
+			check_privileged()?;
+			let flags = c_short::from_ne_bytes(*req.data.first_chunk().unwrap());
+			iface.lock().set_up(flags as u32 & IFF_UP != 0);
+			return Ok(());
+		}
+		ioctl::SIOCGIFADDR => {
+			let (addr, _) = ipv4_addr(&*iface.lock())?;
+			req.set_addr(addr)?;
+		}
+		ioctl::SIOCSIFADDR => {
+			check_privileged()?;
+			let addr = req.addr()?;
+			// Keep the current mask if any
+			let subnet_mask = ipv4_addr(&*iface.lock())
+				.map(|(_, mask)| mask)
+				.unwrap_or_else(|_| classful_prefix(addr));
+			return set_ipv4_addr(name, &iface, addr, subnet_mask);
+		}
+		ioctl::SIOCGIFBRDADDR => {
+			let (addr, subnet_mask) = ipv4_addr(&*iface.lock())?;
+			let addr = u32::from_be_bytes(addr) | !prefix_to_mask(subnet_mask);
+			req.set_addr(addr.to_be_bytes())?;
+		}
+		ioctl::SIOCGIFNETMASK => {
+			let (_, subnet_mask) = ipv4_addr(&*iface.lock())?;
+			req.set_addr(prefix_to_mask(subnet_mask).to_be_bytes())?;
+		}
+		ioctl::SIOCSIFNETMASK => {
+			check_privileged()?;
+			let subnet_mask = mask_to_prefix(req.addr()?).ok_or_else(|| errno!(EINVAL))?;
+			let (addr, _) = ipv4_addr(&*iface.lock())?;
+			return set_ipv4_addr(name, &iface, addr, subnet_mask);
+		}
+		ioctl::SIOCGIFMTU => req.set_int(iface.lock().get_mtu() as _),
+		ioctl::SIOCGIFHWADDR => {
+			let iface = iface.lock();
+			req.data = [0; 16];
+			req.data[..2].copy_from_slice(&iface.get_hw_type().to_ne_bytes());
+			req.data[2..8].copy_from_slice(iface.get_mac());
+		}
+		ioctl::SIOCGIFINDEX => {
+			let index = list_ifaces()?
+				.into_iter()
+				.find(|(n, _)| n.as_bytes() == name)
+				.map(|(_, e)| e.index)
+				.ok_or_else(|| errno!(ENODEV))?;
+			req.set_int(index as _);
+		}
+		_ => return Err(errno!(ENOTTY)),
+	}
+	req_ptr.copy_to_user(&req)
+}
+
+/// Handles the socket ioctl `request` with argument `argp`.
+///
+/// If the request is not supported, the function returns [`errno::ENOTTY`].
+pub fn handle(request: c_ulong, argp: *const c_void) -> EResult<u32> {
+	match request {
+		ioctl::SIOCGIFCONF => get_conf(argp)?,
+		ioctl::SIOCADDRT | ioctl::SIOCDELRT => route(request, argp)?,
+		_ => ifreq(request, argp)?,
+	}
+	Ok(0)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn ioctl_netmask() {
+		assert_eq!(mask_to_prefix([255, 255, 255, 0]), Some(24));
+		assert_eq!(mask_to_prefix([255, 255, 255, 255]), Some(32));
+		assert_eq!(mask_to_prefix([0, 0, 0, 0]), Some(0));
+		assert_eq!(mask_to_prefix([255, 0, 255, 0]), None);
+		assert_eq!(prefix_to_mask(20).to_be_bytes(), [255, 255, 240, 0]);
+		assert_eq!(classful_prefix([172, 16, 0, 1]), 16);
+	}
+}
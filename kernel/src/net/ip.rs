@@ -18,11 +18,19 @@
 
 //! This module implements the IP protocol.
 
-use super::{buf::BufList, osi::Layer};
+use super::{Address, SocketDesc, buf::BufList, osi::Layer, sockaddr::SockAddr, tcp, udp};
 use crate::crypto::checksum;
-use core::mem::size_of;
+use core::{
+	mem::size_of,
+	sync::atomic::{AtomicU16, Ordering::Relaxed},
+};
 use macros::AnyRepr;
-use utils::{boxed::Box, bytes::as_bytes, errno::EResult};
+use utils::{
+	boxed::Box,
+	bytes::{as_bytes, from_bytes},
+	errno,
+	errno::{EResult, Errno},
+};
 
 /// The default TTL value.
 const DEFAULT_TTL: u8 = 128;
@@ -37,33 +45,36 @@ pub const PROTO_TCP: u8 = 0x06;
 /// Protocol: UDP
 pub const PROTO_UDP: u8 = 0x11;
 
+/// The identification number of the next IPv4 datagram to be sent.
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
 /// The IPv4 header (RFC 791).
 #[derive(AnyRepr)]
 #[repr(C, packed)]
-struct IPv4Header {
+pub struct IPv4Header {
 	/// The version of the header with the IHL (header length).
-	version_ihl: u8,
+	pub version_ihl: u8,
 	/// The type of service.
-	type_of_service: u8,
+	pub type_of_service: u8,
 	/// The total length of the datagram.
-	total_length: u16,
+	pub total_length: u16,
 
-	/// TODO doc
-	identification: u16,
-	/// TODO doc
-	flags_fragment_offset: u16,
+	/// Value identifying the datagram, to allow reassembling its fragments.
+	pub identification: u16,
+	/// The fragmentation flags, followed by the offset of the fragment in the datagram.
+	pub flags_fragment_offset: u16,
 
 	/// Time-To-Live.
-	ttl: u8,
+	pub ttl: u8,
 	/// Protocol number.
-	protocol: u8,
+	pub protocol: u8,
 	/// The checksum of the header (RFC 1071).
-	hdr_checksum: u16,
+	pub hdr_checksum: u16,
 
 	/// Source address.
-	src_addr: [u8; 4],
+	pub src_addr: [u8; 4],
 	/// Destination address.
-	dst_addr: [u8; 4],
+	pub dst_addr: [u8; 4],
 }
 
 impl IPv4Header {
@@ -84,22 +95,66 @@ impl IPv4Header {
 }
 
 /// The IPv6 header (RFC 8200).
+#[derive(AnyRepr)]
 #[repr(C, packed)]
-struct IPv6Header {
+pub struct IPv6Header {
 	/// The version, traffic class and flow label.
-	version_traffic_class_flow_label: u32,
+	pub version_traffic_class_flow_label: u32,
 
 	/// The length of the payload.
-	payload_length: u16,
+	pub payload_length: u16,
 	/// The type of the next header.
-	next_header: u8,
+	pub next_header: u8,
 	/// The number of hops remaining before discarding the packet.
-	hop_limit: u8,
+	pub hop_limit: u8,
 
 	/// Source address.
-	src_addr: [u8; 16],
+	pub src_addr: [u8; 16],
 	/// Destination address.
-	dst_addr: [u8; 16],
+	pub dst_addr: [u8; 16],
+}
+
+/// Returns the destination address of the given IP packet.
+///
+/// If the packet is invalid, the function returns `None`.
+pub fn get_dst_addr(packet: &[u8]) -> Option<Address> {
+	match packet.first()? >> 4 {
+		4 => Some(Address::IPv4(packet.get(16..20)?.try_into().ok()?)),
+		6 => Some(Address::IPv6(packet.get(24..40)?.try_into().ok()?)),
+		_ => None,
+	}
+}
+
+/// Computes the checksum of a transport layer segment, including the IP pseudo-header (RFC 9293
+/// section 3.1, RFC 8200 section 8.1).
+///
+/// Arguments:
+/// - `src` and `dst` are the source and destination addresses of the packet.
+/// - `protocol` is the transport protocol number.
+/// - `segment` is the list of buffers composing the segment, header included.
+pub fn transport_checksum(src: &Address, dst: &Address, protocol: u8, segment: &[&[u8]]) -> u16 {
+	let len: usize = segment.iter().map(|b| b.len()).sum();
+	let mut cs = checksum::Rfc1071::default();
+	match (src, dst) {
+		(Address::IPv4(src), Address::IPv4(dst)) => {
+			cs.update(src);
+			cs.update(dst);
+			cs.update(&[0, protocol]);
+			cs.update(&(len as u16).to_be_bytes());
+		}
+		(Address::IPv6(src), Address::IPv6(dst)) => {
+			cs.update(src);
+			cs.update(dst);
+			cs.update(&(len as u32).to_be_bytes());
+			cs.update(&[0, 0, 0, protocol]);
+		}
+		// Cannot happen
+		_ => {}
+	}
+	for b in segment {
+		cs.update(b);
+	}
+	cs.finish()
 }
 
 /// The network layer for the IPv4 protocol.
@@ -108,54 +163,266 @@ pub struct IPv4Layer {
 	/// The protocol ID.
 	pub protocol: u8,
 
+	/// The source IPv4.
+	pub src_addr: [u8; 4],
 	/// The destination IPv4.
 	pub dst_addr: [u8; 4],
 }
 
 impl Layer for IPv4Layer {
-	fn transmit<'c, F>(&self, mut buff: BufList<'c>, next: F) -> EResult<()>
-	where
-		F: Fn(BufList<'c>) -> EResult<()>,
-	{
-		let hdr_len = size_of::<IPv4Header>() as u16; // TODO add options support?
+	fn transmit(
+		&self,
+		mut buff: BufList<'_>,
+		next: &dyn Fn(BufList<'_>) -> EResult<()>,
+	) -> EResult<()> {
+		let hdr_len = size_of::<IPv4Header>(); // TODO add options support?
+		let total_length: u16 = (hdr_len + buff.len())
+			.try_into()
+			.map_err(|_| errno!(EMSGSIZE))?;
 
 		let dscp = 0; // TODO
 		let ecn = 0; // TODO
 
-		// TODO check endianness
 		let mut hdr = IPv4Header {
-			version_ihl: 4 | (((hdr_len / 4) as u8) << 4),
+			version_ihl: (4 << 4) | (hdr_len / 4) as u8,
 			type_of_service: (dscp << 2) | ecn,
-			total_length: hdr_len + buff.len() as u16,
+			total_length: total_length.to_be(),
 
-			identification: 0,        // TODO
-			flags_fragment_offset: 0, // TODO
+			identification: NEXT_ID.fetch_add(1, Relaxed).to_be(),
+			// Fragmentation is not supported, transport protocols are expected to fit the MTU
+			flags_fragment_offset: ((FLAG_DF as u16) << 13).to_be(),
 
 			// TODO allow setting a different value
 			ttl: DEFAULT_TTL,
 			protocol: self.protocol,
 			hdr_checksum: 0,
 
-			src_addr: [0; 4], // IPADDR_ANY
+			src_addr: self.src_addr,
 			dst_addr: self.dst_addr,
 		};
 		hdr.compute_checksum();
 		let hdr_buff = as_bytes(&hdr);
-		buff.push_front(hdr_buff.into());
+		let buff = buff.push_front(hdr_buff.into());
 		next(buff)
 	}
 }
 
-/// Builds an IPv4 layer with the given `sockaddr`.
-pub fn inet_build(_sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
-	// TODO
-	todo!()
+/// The network layer for the IPv6 protocol.
+#[derive(Debug)]
+pub struct IPv6Layer {
+	/// The protocol ID.
+	pub protocol: u8,
+
+	/// The source IPv6.
+	pub src_addr: [u8; 16],
+	/// The destination IPv6.
+	pub dst_addr: [u8; 16],
+}
+
+impl Layer for IPv6Layer {
+	fn transmit(
+		&self,
+		mut buff: BufList<'_>,
+		next: &dyn Fn(BufList<'_>) -> EResult<()>,
+	) -> EResult<()> {
+		let payload_length: u16 = buff.len().try_into().map_err(|_| errno!(EMSGSIZE))?;
+		let hdr = IPv6Header {
+			// TODO traffic class and flow label
+			version_traffic_class_flow_label: (6u32 << 28).to_be(),
+
+			payload_length: payload_length.to_be(),
+			next_header: self.protocol,
+			// TODO allow setting a different value
+			hop_limit: DEFAULT_TTL,
+
+			src_addr: self.src_addr,
+			dst_addr: self.dst_addr,
+		};
+		let hdr_buff = as_bytes(&hdr);
+		let buff = buff.push_front(hdr_buff.into());
+		next(buff)
+	}
 }
 
-// TODO IPv6
+/// Builds the network layer to transmit packets of the given `protocol` from `src` to `dst`.
+///
+/// If the addresses are not of the same family, the function returns [`errno::EAFNOSUPPORT`].
+pub fn build_layer(protocol: u8, src: Address, dst: Address) -> EResult<Box<dyn Layer>> {
+	match (src, dst) {
+		(Address::IPv4(src_addr), Address::IPv4(dst_addr)) => Ok(Box::new(IPv4Layer {
+			protocol,
+			src_addr,
+			dst_addr,
+		})?),
+		(Address::IPv6(src_addr), Address::IPv6(dst_addr)) => Ok(Box::new(IPv6Layer {
+			protocol,
+			src_addr,
+			dst_addr,
+		})?),
+		_ => Err(errno!(EAFNOSUPPORT)),
+	}
+}
+
+/// Builds an IP layer for the given socket descriptor, with the destination in `sockaddr`.
+fn build(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
+	let sockaddr = SockAddr::from_bytes(sockaddr)?;
+	if sockaddr.domain() != desc.domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	let src = super::get_src_addr(&sockaddr.addr)?;
+	let protocol = desc
+		.protocol
+		.try_into()
+		.map_err(|_| -> Errno { errno!(EPROTONOSUPPORT) })?;
+	build_layer(protocol, src, sockaddr.addr)
+}
+
+/// Builds an IPv4 layer with the given `sockaddr`.
+pub fn inet_build(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
+	build(desc, sockaddr)
+}
 
 /// Builds an IPv6 layer with the given `sockaddr`.
-pub fn inet6_build(_sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
-	// TODO
-	todo!()
+pub fn inet6_build(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
+	build(desc, sockaddr)
+}
+
+/// A packet received from the network, parsed by [`parse`].
+struct Packet<'p> {
+	/// The source address.
+	src: Address,
+	/// The destination address.
+	dst: Address,
+	/// The transport protocol number.
+	protocol: u8,
+	/// The payload of the packet.
+	payload: &'p [u8],
+}
+
+/// Parses and validates the given IPv4 or IPv6 packet.
+///
+/// If the packet is invalid or not supported, the function returns `None`.
+fn parse(packet: &[u8]) -> Option<Packet<'_>> {
+	match packet.first()? >> 4 {
+		4 => {
+			let hdr = from_bytes::<IPv4Header>(packet)?;
+			let hdr_len = ((hdr.version_ihl & 0xf) as usize) * 4;
+			let total_length = u16::from_be(hdr.total_length) as usize;
+			if hdr_len < size_of::<IPv4Header>() || total_length < hdr_len {
+				return None;
+			}
+			let packet = packet.get(..total_length)?;
+			let valid = if hdr_len == size_of::<IPv4Header>() {
+				hdr.check_checksum()
+			} else {
+				// Include options
+				checksum::compute_rfc1071(&packet[..hdr_len]) == 0
+			};
+			if !valid {
+				return None;
+			}
+			// TODO support reassembly
+			let flags_fragment_offset = u16::from_be(hdr.flags_fragment_offset);
+			let more_fragments = (flags_fragment_offset >> 13) as u8 & FLAG_MF != 0;
+			if more_fragments || flags_fragment_offset & 0x1fff != 0 {
+				return None;
+			}
+			Some(Packet {
+				src: Address::IPv4(hdr.src_addr),
+				dst: Address::IPv4(hdr.dst_addr),
+				protocol: hdr.protocol,
+				payload: &packet[hdr_len..],
+			})
+		}
+		6 => {
+			let hdr = from_bytes::<IPv6Header>(packet)?;
+			let payload_length = u16::from_be(hdr.payload_length) as usize;
+			let payload = packet
+				.get(size_of::<IPv6Header>()..)?
+				.get(..payload_length)?;
+			// TODO support extension headers
+			Some(Packet {
+				src: Address::IPv6(hdr.src_addr),
+				dst: Address::IPv6(hdr.dst_addr),
+				protocol: hdr.next_header,
+				payload,
+			})
+		}
+		_ => None,
+	}
+}
+
+/// Handles an IP packet received from the link layer, passing its payload to the transport
+/// protocol.
+///
+/// Invalid packets, and packets which are not addressed to the host, are silently dropped.
+pub fn input(packet: &[u8]) -> EResult<()> {
+	let Some(packet) = parse(packet) else {
+		return Ok(());
+	};
+	// TODO forwarding
+	if !super::is_local_addr(&packet.dst) {
+		return Ok(());
+	}
+	match packet.protocol {
+		PROTO_TCP => tcp::input(packet.src, packet.dst, packet.payload),
+		PROTO_UDP => udp::input(packet.src, packet.dst, packet.payload),
+		// TODO ICMP
+		_ => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn ip_parse_v4() {
+		let mut hdr = IPv4Header {
+			version_ihl: (4 << 4) | 5,
+			type_of_service: 0,
+			total_length: 24u16.to_be(),
+			identification: 0,
+			flags_fragment_offset: ((FLAG_DF as u16) << 13).to_be(),
+			ttl: DEFAULT_TTL,
+			protocol: PROTO_UDP,
+			hdr_checksum: 0,
+			src_addr: [10, 0, 0, 1],
+			dst_addr: [10, 0, 0, 2],
+		};
+		hdr.compute_checksum();
+		let mut packet = [0u8; 28];
+		packet[..20].copy_from_slice(as_bytes(&hdr));
+		packet[20..24].copy_from_slice(&[1, 2, 3, 4]);
+		// Trailing bytes (link layer padding) are ignored
+		let p = parse(&packet).unwrap();
+		assert_eq!(p.src, Address::IPv4([10, 0, 0, 1]));
+		assert_eq!(p.dst, Address::IPv4([10, 0, 0, 2]));
+		assert_eq!(p.protocol, PROTO_UDP);
+		assert_eq!(p.payload, &[1, 2, 3, 4]);
+		// Invalid checksum
+		packet[8] = 1;
+		assert!(parse(&packet).is_none());
+	}
+
+	#[test_case]
+	fn ip_parse_v6() {
+		let hdr = IPv6Header {
+			version_traffic_class_flow_label: (6u32 << 28).to_be(),
+			payload_length: 2u16.to_be(),
+			next_header: PROTO_TCP,
+			hop_limit: DEFAULT_TTL,
+			src_addr: [1; 16],
+			dst_addr: [2; 16],
+		};
+		let mut packet = [0u8; 42];
+		packet[..40].copy_from_slice(as_bytes(&hdr));
+		packet[40..].copy_from_slice(&[5, 6]);
+		let p = parse(&packet).unwrap();
+		assert_eq!(p.src, Address::IPv6([1; 16]));
+		assert_eq!(p.protocol, PROTO_TCP);
+		assert_eq!(p.payload, &[5, 6]);
+		// Truncated
+		assert!(parse(&packet[..41]).is_none());
+	}
 }
@@ -15,14 +15,59 @@
  * You should have received a copy of the GNU General Public License along with
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
-
 //! This module implements the local loopback.
 
-use super::{Address, BindAddress, Interface, MAC, buf::BufList};
-use utils::errno::EResult;
+use super::{
+	Address, BindAddress, Interface, MAC, ROUTING_TABLE, Route, buf::BufList, register_iface,
+	rx_notify,
+};
+use crate::memory::{ring_buffer::RingBuffer, user::UserSlice};
+use core::{hint::unlikely, mem::size_of, num::NonZeroUsize};
+use utils::{
+	TryClone,
+	collections::{string::String, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+};
+
+/// The size of the buffer holding frames written on the loopback, in bytes.
+const BUFFER_SIZE: usize = 256 * 1024;
 
 /// Local loopback interfaces allows the system to write data to itself.
-pub struct LocalLoopback {}
+///
+/// Frames written on the interface are stored in a buffer, each prefixed by its length. The
+/// receive task is then notified so that they are read back and passed up the stack.
+pub struct LocalLoopback {
+	/// The addresses bound to the interface.
+	addresses: Vec<BindAddress>,
+	/// Frames waiting to be received.
+	buf: RingBuffer,
+	/// Tells whether the interface is up.
+	up: bool,
+}
+
+impl LocalLoopback {
+	/// Creates a new loopback interface.
+	pub fn new() -> AllocResult<Self> {
+		Ok(Self {
+			addresses: Vec::try_from([
+				BindAddress {
+					addr: Address::IPv4([127, 0, 0, 1]),
+					subnet_mask: 8,
+				},
+				BindAddress {
+					addr: Address::IPv6([
+						0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+						0x00, 0x00, 0x00, 0x01,
+					]),
+					subnet_mask: 128,
+				},
+			])?,
+			buf: RingBuffer::new(NonZeroUsize::new(BUFFER_SIZE).unwrap())?,
+			up: true,
+		})
+	}
+}
 
 impl Interface for LocalLoopback {
 	fn get_name(&self) -> &[u8] {
@@ -30,6 +75,14 @@ impl Interface for LocalLoopback {
 	}
 
 	fn is_up(&self) -> bool {
+		self.up
+	}
+
+	fn set_up(&mut self, up: bool) {
+		self.up = up;
+	}
+
+	fn is_loopback(&self) -> bool {
 		true
 	}
 
@@ -37,29 +90,82 @@ impl Interface for LocalLoopback {
 		&[0x00; 6]
 	}
 
+	fn get_mtu(&self) -> u32 {
+		65536
+	}
+
 	fn get_addresses(&self) -> &[BindAddress] {
-		&[
-			BindAddress {
-				addr: Address::IPv4([127, 0, 0, 1]),
-				subnet_mask: 8,
-			},
-			BindAddress {
-				addr: Address::IPv6([
-					0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-					0x00, 0x00, 0x01,
-				]),
-				subnet_mask: 128,
-			},
-		]
+		&self.addresses
 	}
 
-	fn read(&mut self, _buff: &mut [u8]) -> EResult<u64> {
-		// TODO Write to ring buffer
-		todo!();
+	fn add_address(&mut self, addr: BindAddress) -> EResult<()> {
+		if self.addresses.iter().any(|a| a.addr == addr.addr) {
+			return Err(errno!(EEXIST));
+		}
+		self.addresses.push(addr)?;
+		Ok(())
 	}
 
-	fn write(&mut self, _buff: &BufList<'_>) -> EResult<u64> {
-		// TODO Read from ring buffer
-		todo!();
+	fn remove_address(&mut self, addr: &Address) -> EResult<()> {
+		let i = self
+			.addresses
+			.iter()
+			.position(|a| a.addr == *addr)
+			.ok_or_else(|| errno!(EADDRNOTAVAIL))?;
+		self.addresses.remove(i);
+		Ok(())
+	}
+
+	fn read(&mut self, buff: &mut [u8]) -> EResult<u64> {
+		let mut len = [0u8; size_of::<u32>()];
+		if self.buf.read(UserSlice::from_slice_mut(&mut len))? < len.len() {
+			return Ok(0);
+		}
+		let len = u32::from_ne_bytes(len) as usize;
+		let read_len = self
+			.buf
+			.read(UserSlice::from_slice_mut(buff).subslice(0, len))?;
+		// Discard the part of the frame that does not fit
+		self.buf.discard(len - read_len);
+		Ok(read_len as _)
+	}
+
+	fn write(&mut self, buff: &BufList<'_>) -> EResult<u64> {
+		let len = buff.len();
+		// If the buffer is full, the frame is dropped
+		if unlikely(size_of::<u32>() + len > self.buf.get_available_len()) {
+			return Err(errno!(ENOBUFS));
+		}
+		let len_bytes = (len as u32).to_ne_bytes();
+		self.buf
+			.write(unsafe { UserSlice::from_slice(&len_bytes) })?;
+		let mut cur = Some(buff);
+		while let Some(b) = cur {
+			self.buf.write(unsafe { UserSlice::from_slice(b.data) })?;
+			cur = b.next();
+		}
+		rx_notify();
+		Ok(len as _)
+	}
+}
+
+/// Registers the loopback interface, along with the routes to its addresses.
+pub(crate) fn init() -> EResult<()> {
+	let lo = LocalLoopback::new()?;
+	let name = String::try_from(lo.get_name())?;
+	{
+		let mut routing_table = ROUTING_TABLE.lock();
+		for a in lo.get_addresses() {
+			routing_table.push(Route {
+				dst: Some(BindAddress {
+					addr: a.addr,
+					subnet_mask: a.subnet_mask,
+				}),
+				iface: name.try_clone()?,
+				gateway: a.addr.unspecified(),
+				metric: 0,
+			})?;
+		}
 	}
+	register_iface(name, lo)
 }
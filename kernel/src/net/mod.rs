@@ -18,35 +18,69 @@
 
 //! Network stack implementation.
 
+pub mod arp;
 pub mod buf;
+pub mod dhcp;
+pub mod eth;
 pub mod icmp;
+pub mod ioctl;
 pub mod ip;
 pub mod lo;
+pub mod netlink;
 pub mod osi;
 pub mod sockaddr;
 pub mod tcp;
+pub mod udp;
+pub mod unix;
 
 use crate::{
-	file::perm::AccessProfile,
+	arch::x86::sti,
+	file::{perm::AccessProfile, wait_queue::WaitQueue},
 	net::sockaddr::{SockAddrIn, SockAddrIn6},
 	sync::mutex::Mutex,
 };
 use buf::BufList;
-use core::{cmp::Ordering, mem::size_of};
+use core::{
+	cmp::{Ordering, min},
+	mem::size_of,
+	sync::atomic::{
+		AtomicBool, AtomicU32,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
 use utils::{
+	TryClone,
 	collections::{hashmap::HashMap, string::String, vec::Vec},
 	errno,
-	errno::{EResult, Errno},
+	errno::{AllocResult, CollectResult, EResult, Errno},
 	ptr::arc::Arc,
 };
 
 /// Type representing a Media Access Control (MAC) address.
 pub type MAC = [u8; 6];
 
+/// Hardware type: Ethernet
+pub const ARPHRD_ETHER: u16 = 1;
+/// Hardware type: loopback
+pub const ARPHRD_LOOPBACK: u16 = 772;
+
+/// Interface flag: the interface is up
+pub const IFF_UP: u32 = 0x1;
+/// Interface flag: the interface supports broadcast
+pub const IFF_BROADCAST: u32 = 0x2;
+/// Interface flag: the interface is a loopback
+pub const IFF_LOOPBACK: u32 = 0x8;
+/// Interface flag: resources are allocated
+pub const IFF_RUNNING: u32 = 0x40;
+/// Interface flag: the interface supports multicast
+pub const IFF_MULTICAST: u32 = 0x1000;
+/// Interface flag: the link is up
+pub const IFF_LOWER_UP: u32 = 0x10000;
+
 // TODO allow implementation of custom protocols
 
 /// An enumeration of network address types.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Address {
 	/// Internet Protocol version 4.
 	IPv4([u8; 4]),
@@ -54,6 +88,29 @@ pub enum Address {
 	IPv6([u8; 16]),
 }
 
+impl Address {
+	/// Returns the unspecified address (`INADDR_ANY`) of the same family as `self`.
+	pub fn unspecified(&self) -> Self {
+		match self {
+			Self::IPv4(_) => Self::IPv4([0; 4]),
+			Self::IPv6(_) => Self::IPv6([0; 16]),
+		}
+	}
+
+	/// Tells whether the address is the unspecified address (`INADDR_ANY`).
+	pub fn is_unspecified(&self) -> bool {
+		*self == self.unspecified()
+	}
+
+	/// Tells whether both addresses are of the same family.
+	pub fn same_family(&self, other: &Self) -> bool {
+		matches!(
+			(self, other),
+			(Self::IPv4(_), Self::IPv4(_)) | (Self::IPv6(_), Self::IPv6(_))
+		)
+	}
+}
+
 /// An address/subnet mask pair to be bound to an interface.
 #[derive(Debug)]
 pub struct BindAddress {
@@ -72,11 +129,11 @@ impl BindAddress {
 				.zip(b.array_chunks::<4>())
 				.enumerate()
 				.all(|(i, (a, b))| {
-					let a = u32::from_ne_bytes(*a);
-					let b = u32::from_ne_bytes(*b);
+					let a = u32::from_be_bytes(*a);
+					let b = u32::from_be_bytes(*b);
 
-					let order = 32 - mask.saturating_sub(i * 32);
-					let mask = !((1 << order) - 1);
+					let bits = min(mask.saturating_sub(i * 32), 32);
+					let mask = u32::MAX.checked_shl(32 - bits as u32).unwrap_or(0);
 
 					(a & mask) == (b & mask)
 				})
@@ -88,6 +145,33 @@ impl BindAddress {
 			_ => false,
 		}
 	}
+
+	/// Returns the address of the subnet, with host bits cleared.
+	pub fn network(&self) -> Address {
+		fn mask<const N: usize>(mut a: [u8; N], mask: usize) -> [u8; N] {
+			for (i, b) in a.iter_mut().enumerate() {
+				let bits = min(mask.saturating_sub(i * 8), 8);
+				*b &= u8::MAX.checked_shl(8 - bits as u32).unwrap_or(0);
+			}
+			a
+		}
+
+		match self.addr {
+			Address::IPv4(a) => Address::IPv4(mask(a, self.subnet_mask as _)),
+			Address::IPv6(a) => Address::IPv6(mask(a, self.subnet_mask as _)),
+		}
+	}
+
+	/// Tells whether `addr` is the broadcast address of the subnet.
+	pub fn is_broadcast(&self, addr: &Address) -> bool {
+		match (&self.addr, addr) {
+			(Address::IPv4(_), Address::IPv4(b)) if self.subnet_mask < 31 => {
+				let host_mask = u32::MAX >> self.subnet_mask;
+				self.is_matching(addr) && u32::from_be_bytes(*b) & host_mask == host_mask
+			}
+			_ => false,
+		}
+	}
 }
 
 /// Trait representing a network interface.
@@ -98,15 +182,43 @@ pub trait Interface {
 	/// Tells whether the interface is UP.
 	fn is_up(&self) -> bool;
 
+	/// Brings the interface up or down.
+	fn set_up(&mut self, up: bool);
+
+	/// Tells whether the interface is a loopback interface.
+	///
+	/// Addresses are not resolved on loopback interfaces.
+	fn is_loopback(&self) -> bool {
+		false
+	}
+
 	/// Returns the mac address of the interface.
 	fn get_mac(&self) -> &MAC;
 
+	/// Returns the Maximum Transmission Unit of the interface.
+	fn get_mtu(&self) -> u32 {
+		1500
+	}
+
 	/// Returns the list of addresses bound to the interface.
 	fn get_addresses(&self) -> &[BindAddress];
 
-	/// Reads data from the network interface and writes it into `buff`.
+	/// Binds the given address to the interface.
+	///
+	/// If the address is already bound, the function returns [`errno::EEXIST`].
+	fn add_address(&mut self, addr: BindAddress) -> EResult<()>;
+
+	/// Unbinds the given address from the interface.
 	///
-	/// The function returns the number of bytes read.
+	/// If the address is not bound, the function returns [`errno::EADDRNOTAVAIL`].
+	fn remove_address(&mut self, addr: &Address) -> EResult<()>;
+
+	/// Reads the next frame received on the network interface and writes it into `buff`.
+	///
+	/// If the frame is larger than `buff`, the remaining data is discarded.
+	///
+	/// The function returns the number of bytes read. If no frame is available, the function
+	/// returns zero.
 	fn read(&mut self, buff: &mut [u8]) -> EResult<u64>;
 
 	/// Reads data from `buff` and writes it into the network interface.
@@ -115,6 +227,30 @@ pub trait Interface {
 	fn write(&mut self, buff: &BufList<'_>) -> EResult<u64>;
 }
 
+impl dyn Interface {
+	/// Returns the hardware type of the interface (`ARPHRD_*`).
+	pub fn get_hw_type(&self) -> u16 {
+		if self.is_loopback() {
+			ARPHRD_LOOPBACK
+		} else {
+			ARPHRD_ETHER
+		}
+	}
+
+	/// Returns the flags of the interface (`IFF_*`).
+	pub fn get_flags(&self) -> u32 {
+		let mut flags = if self.is_loopback() {
+			IFF_LOOPBACK
+		} else {
+			IFF_BROADCAST | IFF_MULTICAST
+		};
+		if self.is_up() {
+			flags |= IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+		}
+		flags
+	}
+}
+
 /// An entry in the routing table.
 pub struct Route {
 	/// The destination address. If `None`, this is the default destination.
@@ -130,6 +266,25 @@ pub struct Route {
 }
 
 impl Route {
+	/// Tells whether the route's destination is `dst`.
+	pub fn has_dst(&self, dst: &Option<BindAddress>) -> bool {
+		match (&self.dst, dst) {
+			(None, None) => true,
+			(Some(a), Some(b)) => a.subnet_mask == b.subnet_mask && a.network() == b.network(),
+			_ => false,
+		}
+	}
+
+	/// Returns the address of the next hop to reach `addr` through the route.
+	pub fn next_hop(&self, addr: &Address) -> Address {
+		if self.gateway.is_unspecified() {
+			// The destination is on the link
+			*addr
+		} else {
+			self.gateway
+		}
+	}
+
 	/// Tells whether the route matches the given address.
 	pub fn is_matching(&self, addr: &Address) -> bool {
 		// Check gateway
@@ -182,9 +337,19 @@ impl Route {
 	}
 }
 
-/// The list of network interfaces.
-pub static INTERFACES: Mutex<HashMap<String, Arc<Mutex<dyn Interface>>>> =
-	Mutex::new(HashMap::new());
+/// A registered network interface.
+#[derive(Clone)]
+pub struct IfaceEntry {
+	/// The index of the interface, unique for the lifetime of the system.
+	pub index: u32,
+	/// The interface.
+	pub iface: Arc<Mutex<dyn Interface>>,
+}
+
+/// The list of network interfaces, by name.
+pub static INTERFACES: Mutex<HashMap<String, IfaceEntry>> = Mutex::new(HashMap::new());
+/// The index of the next registered interface.
+static NEXT_IFINDEX: AtomicU32 = AtomicU32::new(1);
 /// The routing table.
 pub static ROUTING_TABLE: Mutex<Vec<Route>> = Mutex::new(Vec::new());
 
@@ -194,35 +359,326 @@ pub static ROUTING_TABLE: Mutex<Vec<Route>> = Mutex::new(Vec::new());
 /// - `name` is the name of the interface.
 /// - `iface` is the interface to register.
 pub fn register_iface<I: 'static + Interface>(name: String, iface: I) -> EResult<()> {
-	let mut interfaces = INTERFACES.lock();
-
-	let i = Arc::new(Mutex::new(iface))?;
-	interfaces.insert(name, i)?;
-
+	let i: Arc<Mutex<dyn Interface>> = Arc::new(Mutex::new(iface))?;
+	INTERFACES.lock().insert(
+		name,
+		IfaceEntry {
+			index: NEXT_IFINDEX.fetch_add(1, Relaxed),
+			iface: i.clone(),
+		},
+	)?;
+	// Announce addresses on the network
+	let addrs = i
+		.lock()
+		.get_addresses()
+		.iter()
+		.filter_map(|a| match a.addr {
+			Address::IPv4(addr) => Some(addr),
+			Address::IPv6(_) => None,
+		})
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	for addr in addrs {
+		let _ = arp::announce(&i, addr);
+	}
 	Ok(())
 }
 
-/// Unregisters the network interface with the given name.
+/// Unregisters the network interface with the given name, along with the routes using it.
 pub fn unregister_iface(name: &[u8]) {
 	let mut interfaces = INTERFACES.lock();
 	interfaces.remove(name);
+	ROUTING_TABLE
+		.lock()
+		.retain(|route| route.iface.as_bytes() != name);
+}
+
+/// Binds `addr` to the interface `iface` with the given name.
+///
+/// A route to the address's subnet is added, and the address is announced on the network.
+///
+/// If the address is already bound, the function returns [`errno::EEXIST`].
+pub fn add_address(
+	name: &[u8],
+	iface: &Arc<Mutex<dyn Interface>>,
+	addr: BindAddress,
+) -> EResult<()> {
+	let route = Route {
+		dst: Some(BindAddress {
+			addr: addr.network(),
+			subnet_mask: addr.subnet_mask,
+		}),
+		iface: String::try_from(name)?,
+		gateway: addr.addr.unspecified(),
+		metric: 0,
+	};
+	let a = addr.addr;
+	iface.lock().add_address(addr)?;
+	{
+		let mut routing_table = ROUTING_TABLE.lock();
+		let exists = routing_table.iter().any(|r| {
+			r.iface == route.iface && r.gateway == route.gateway && r.has_dst(&route.dst)
+		});
+		if !exists {
+			routing_table.push(route)?;
+		}
+	}
+	if let Address::IPv4(a) = a {
+		let _ = arp::announce(iface, a);
+	}
+	Ok(())
+}
+
+/// Unbinds `addr` from the interface `iface` with the given name, along with the route to the
+/// address's subnet.
+///
+/// If the address is not bound, the function returns [`errno::EADDRNOTAVAIL`].
+pub fn remove_address(
+	name: &[u8],
+	iface: &Arc<Mutex<dyn Interface>>,
+	addr: &BindAddress,
+) -> EResult<()> {
+	iface.lock().remove_address(&addr.addr)?;
+	let dst = Some(BindAddress {
+		addr: addr.network(),
+		subnet_mask: addr.subnet_mask,
+	});
+	ROUTING_TABLE.lock().retain(|r| {
+		!(r.iface.as_bytes() == name && r.gateway.is_unspecified() && r.has_dst(&dst))
+	});
+	Ok(())
+}
+
+/// Adds `route` to the routing table.
+///
+/// If a route with the same destination and metric already exists, it is replaced if `replace`
+/// is set. Else, the function returns [`errno::EEXIST`].
+pub fn add_route(route: Route, replace: bool) -> EResult<()> {
+	let mut routing_table = ROUTING_TABLE.lock();
+	let existing = routing_table.iter().position(|r| {
+		r.has_dst(&route.dst) && r.metric == route.metric && r.gateway.same_family(&route.gateway)
+	});
+	match existing {
+		Some(i) if replace => routing_table[i] = route,
+		Some(_) => return Err(errno!(EEXIST)),
+		None => routing_table.push(route)?,
+	}
+	Ok(())
+}
+
+/// Removes the first route for which `f` returns `true` from the routing table.
+///
+/// If no route matches, the function returns [`errno::ESRCH`].
+pub fn remove_route<F: Fn(&Route) -> bool>(f: F) -> EResult<()> {
+	let mut routing_table = ROUTING_TABLE.lock();
+	let i = routing_table
+		.iter()
+		.position(f)
+		.ok_or_else(|| errno!(ESRCH))?;
+	routing_table.remove(i);
+	Ok(())
+}
+
+/// Returns the name of the interface on which `gateway` is directly reachable.
+///
+/// If no such interface exists, the function returns [`errno::ENETUNREACH`].
+pub fn get_gateway_iface(gateway: &Address) -> EResult<String> {
+	let routing_table = ROUTING_TABLE.lock();
+	let route = routing_table
+		.iter()
+		.filter(|r| r.gateway.is_unspecified() && r.is_matching(gateway))
+		.max_by(|a, b| a.cmp_for(b, gateway))
+		.ok_or_else(|| errno!(ENETUNREACH))?;
+	Ok(route.iface.try_clone()?)
 }
 
 /// Returns the network interface with the given name.
 ///
 /// If the interface doesn't exist, thhe function returns `None`.
 pub fn get_iface(name: &[u8]) -> Option<Arc<Mutex<dyn Interface>>> {
-	INTERFACES.lock().get(name).cloned()
+	INTERFACES.lock().get(name).map(|e| e.iface.clone())
 }
 
-/// Returns the network interface to be used to transmit a packet to the given destination address.
-pub fn get_iface_for(addr: Address) -> Option<Arc<Mutex<dyn Interface>>> {
+/// Returns the network interface with the given index, along with its name.
+///
+/// If the interface doesn't exist, the function returns [`errno::ENODEV`].
+pub fn get_iface_by_index(index: u32) -> EResult<(String, Arc<Mutex<dyn Interface>>)> {
+	list_ifaces()?
+		.into_iter()
+		.find(|(_, e)| e.index == index)
+		.map(|(name, e)| (name, e.iface))
+		.ok_or_else(|| errno!(ENODEV))
+}
+
+/// Returns the list of registered network interfaces, with their names.
+pub fn list_ifaces() -> AllocResult<Vec<(String, IfaceEntry)>> {
+	let interfaces = INTERFACES.lock();
+	let mut ifaces = Vec::with_capacity(interfaces.len())?;
+	for (name, e) in interfaces.iter() {
+		ifaces.push((name.try_clone()?, e.clone()))?;
+	}
+	Ok(ifaces)
+}
+
+/// Returns the network interface to be used to transmit a packet to the given destination
+/// address, along with the address of the next hop.
+fn route_for(addr: Address) -> Option<(Arc<Mutex<dyn Interface>>, Address)> {
 	let routing_table = ROUTING_TABLE.lock();
 	let route = routing_table
 		.iter()
 		.filter(|route| route.is_matching(&addr))
 		.max_by(|a, b| a.cmp_for(b, &addr))?;
-	get_iface(&route.iface)
+	Some((get_iface(&route.iface)?, route.next_hop(&addr)))
+}
+
+/// Returns the network interface to be used to transmit a packet to the given destination address.
+pub fn get_iface_for(addr: Address) -> Option<Arc<Mutex<dyn Interface>>> {
+	route_for(addr).map(|(iface, _)| iface)
+}
+
+/// Returns the address to be used as source to transmit packets to the given destination address.
+///
+/// If no route leads to the destination, the function returns [`errno::ENETUNREACH`]. If the
+/// interface has no address of the destination's family, the function returns
+/// [`errno::EADDRNOTAVAIL`].
+pub fn get_src_addr(dst: &Address) -> EResult<Address> {
+	let iface = get_iface_for(*dst).ok_or_else(|| errno!(ENETUNREACH))?;
+	let iface = iface.lock();
+	iface
+		.get_addresses()
+		.iter()
+		.filter(|a| a.addr.same_family(dst))
+		// Prefer an address on the same network as the destination
+		.max_by_key(|a| a.is_matching(dst))
+		.map(|a| a.addr)
+		.ok_or_else(|| errno!(EADDRNOTAVAIL))
+}
+
+/// Transmits the given network layer packet on the interface it is routed to.
+///
+/// The destination is read from the packet's header. The packet is framed for the next hop on the
+/// route. If the hardware address of the next hop is not known yet, the packet is queued until
+/// it is resolved.
+pub fn transmit(buff: BufList<'_>) -> EResult<()> {
+	let dst = ip::get_dst_addr(buff.data).ok_or_else(|| errno!(EINVAL))?;
+	let (iface, next_hop) = route_for(dst).ok_or_else(|| errno!(ENETUNREACH))?;
+	let (loopback, broadcast) = {
+		let iface = iface.lock();
+		if !iface.is_up() {
+			return Err(errno!(ENETDOWN));
+		}
+		let broadcast = iface
+			.get_addresses()
+			.iter()
+			.any(|a| a.is_broadcast(&next_hop));
+		(iface.is_loopback(), broadcast)
+	};
+	let ethertype = match dst {
+		Address::IPv4(_) => eth::ETHERTYPE_IPV4,
+		Address::IPv6(_) => eth::ETHERTYPE_IPV6,
+	};
+	let mac = match next_hop {
+		_ if loopback => [0; 6],
+		Address::IPv4([255, 255, 255, 255]) => eth::BROADCAST,
+		Address::IPv4(_) if broadcast => eth::BROADCAST,
+		Address::IPv4(addr) => match arp::resolve(&iface, addr, &buff)? {
+			Some(mac) => mac,
+			// The packet has been queued
+			None => return Ok(()),
+		},
+		Address::IPv6(addr @ [0xff, ..]) => eth::ipv6_multicast_mac(&addr),
+		// TODO Neighbor Discovery
+		Address::IPv6(_) => return Err(errno!(EHOSTUNREACH)),
+	};
+	eth::transmit(&mut *iface.lock(), mac, ethertype, buff)
+}
+
+/// Tells whether a packet sent to `addr` is to be received by the host.
+pub fn is_local_addr(addr: &Address) -> bool {
+	match addr {
+		// Limited broadcast
+		Address::IPv4([255, 255, 255, 255]) => return true,
+		// Multicast
+		Address::IPv6([0xff, ..]) => return true,
+		_ => {}
+	}
+	let ifaces = INTERFACES
+		.lock()
+		.iter()
+		.map(|(_, e)| e.iface.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0;
+	let Ok(ifaces) = ifaces else {
+		return false;
+	};
+	ifaces
+		.iter()
+		.any(|iface| iface.lock().get_addresses().iter().any(|a| a.addr == *addr))
+}
+
+/// The maximum size of a frame received on a network interface.
+const MAX_FRAME_SIZE: usize = u16::MAX as usize + size_of::<eth::EthernetHdr>();
+
+/// Tells whether frames are pending on network interfaces.
+static RX_PENDING: AtomicBool = AtomicBool::new(false);
+/// The queue on which the receive task waits for incoming frames.
+static RX_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Notifies the receive task that frames are available on a network interface.
+///
+/// This function may be called from an interrupt handler.
+pub fn rx_notify() {
+	RX_PENDING.store(true, Release);
+	RX_QUEUE.wake_all();
+}
+
+/// Pulls pending frames from every network interface and passes them up the stack.
+///
+/// `buf` is the buffer used to receive frames. It must be able to hold [`MAX_FRAME_SIZE`] bytes.
+fn rx_poll(buf: &mut [u8]) {
+	let ifaces = INTERFACES
+		.lock()
+		.iter()
+		.map(|(_, e)| e.iface.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0;
+	// On allocation failure, retry next time
+	let Ok(ifaces) = ifaces else {
+		return;
+	};
+	for iface in ifaces {
+		loop {
+			// Do not keep the interface locked while handling the frame, since replies may be
+			// transmitted on it
+			let len = {
+				let mut iface = iface.lock();
+				if !iface.is_up() {
+					break;
+				}
+				iface.read(buf)
+			};
+			match len {
+				Ok(len) if len > 0 => {
+					let _ = eth::input(&iface, &buf[..len as usize]);
+				}
+				_ => break,
+			}
+		}
+	}
+}
+
+/// Receive task: pulls frames from the network interfaces and passes them up the stack.
+///
+/// The task sleeps until [`rx_notify`] is called.
+pub(crate) fn rx_task() -> ! {
+	sti();
+	let mut buf = Vec::new();
+	buf.resize(MAX_FRAME_SIZE, 0)
+		.unwrap_or_else(|_| panic!("Cannot allocate the receive buffer"));
+	loop {
+		rx_poll(&mut buf);
+		let _ = RX_QUEUE.wait_until(|| RX_PENDING.swap(false, Acquire).then_some(()));
+	}
 }
 
 /// Enumeration of socket domains.
@@ -346,7 +802,7 @@ impl AccessProfile {
 }
 
 /// Socket network stack descriptor.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct SocketDesc {
 	/// The socket's domain.
 	pub domain: SocketDomain,
@@ -355,3 +811,213 @@ pub struct SocketDesc {
 	/// The socket's protocol. `0` means using the default protocol for the domain/type pair.
 	pub protocol: i32,
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		file::socket::Socket,
+		memory::user::UserSlice,
+		net::{ip::IPv4Header, sockaddr::SockAddr},
+	};
+	use utils::bytes::as_bytes;
+
+	#[test_case]
+	fn bind_address_matching() {
+		let a = BindAddress {
+			addr: Address::IPv4([10, 0, 2, 15]),
+			subnet_mask: 24,
+		};
+		assert!(a.is_matching(&Address::IPv4([10, 0, 2, 2])));
+		assert!(!a.is_matching(&Address::IPv4([10, 0, 3, 15])));
+		assert!(!a.is_matching(&Address::IPv6([0; 16])));
+		assert_eq!(a.network(), Address::IPv4([10, 0, 2, 0]));
+		assert!(a.is_broadcast(&Address::IPv4([10, 0, 2, 255])));
+		let default = BindAddress {
+			addr: Address::IPv4([0; 4]),
+			subnet_mask: 0,
+		};
+		assert!(default.is_matching(&Address::IPv4([1, 2, 3, 4])));
+		let a = BindAddress {
+			addr: Address::IPv6([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+			subnet_mask: 64,
+		};
+		let mut b = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+		assert!(a.is_matching(&Address::IPv6(b)));
+		b[7] = 1;
+		assert!(!a.is_matching(&Address::IPv6(b)));
+	}
+
+	/// Frames written on [`TestIface`].
+	static TEST_TX: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+	/// A non-loopback interface receiving frames from a queue.
+	struct TestIface {
+		/// The addresses bound to the interface.
+		addresses: Vec<BindAddress>,
+		/// Frames waiting to be received.
+		rx: Vec<Vec<u8>>,
+	}
+
+	impl Interface for TestIface {
+		fn get_name(&self) -> &[u8] {
+			b"test0"
+		}
+
+		fn is_up(&self) -> bool {
+			true
+		}
+
+		fn set_up(&mut self, _up: bool) {}
+
+		fn get_mac(&self) -> &MAC {
+			&[0x52, 0x54, 0, 0, 0, 1]
+		}
+
+		fn get_addresses(&self) -> &[BindAddress] {
+			&self.addresses
+		}
+
+		fn add_address(&mut self, addr: BindAddress) -> EResult<()> {
+			self.addresses.push(addr)?;
+			Ok(())
+		}
+
+		fn remove_address(&mut self, _addr: &Address) -> EResult<()> {
+			Err(errno!(EADDRNOTAVAIL))
+		}
+
+		fn read(&mut self, buff: &mut [u8]) -> EResult<u64> {
+			if self.rx.is_empty() {
+				return Ok(0);
+			}
+			let frame = self.rx.remove(0);
+			buff[..frame.len()].copy_from_slice(&frame);
+			Ok(frame.len() as _)
+		}
+
+		fn write(&mut self, buff: &BufList<'_>) -> EResult<u64> {
+			TEST_TX.lock().push(buff.to_vec()?)?;
+			Ok(buff.len() as _)
+		}
+	}
+
+	#[test_case]
+	fn rx_udp_datagram() {
+		let local = SockAddr {
+			port: 4242,
+			addr: Address::IPv4([10, 0, 9, 1]),
+		};
+		// UDP datagram from 10.0.9.2:1234 to 10.0.9.1:4242, without checksum
+		let mut frame = Vec::new();
+		frame.extend_from_slice(&[0x52, 0x54, 0, 0, 0, 1]).unwrap();
+		frame.extend_from_slice(&[0x52, 0x54, 0, 0, 0, 2]).unwrap();
+		frame.extend_from_slice(&[0x08, 0x00]).unwrap();
+		let mut hdr = IPv4Header {
+			version_ihl: (4 << 4) | 5,
+			type_of_service: 0,
+			total_length: 32u16.to_be(),
+			identification: 0,
+			flags_fragment_offset: 0,
+			ttl: 64,
+			protocol: ip::PROTO_UDP,
+			hdr_checksum: 0,
+			src_addr: [10, 0, 9, 2],
+			dst_addr: [10, 0, 9, 1],
+		};
+		hdr.compute_checksum();
+		frame.extend_from_slice(as_bytes(&hdr)).unwrap();
+		frame
+			.extend_from_slice(&[0x04, 0xd2, 0x10, 0x92, 0, 12, 0, 0])
+			.unwrap();
+		frame.extend_from_slice(b"ping").unwrap();
+		let mut addresses = Vec::new();
+		addresses
+			.push(BindAddress {
+				addr: local.addr,
+				subnet_mask: 24,
+			})
+			.unwrap();
+		let mut rx = Vec::new();
+		rx.push(frame).unwrap();
+		register_iface(
+			String::try_from(b"test0".as_slice()).unwrap(),
+			TestIface {
+				addresses,
+				rx,
+			},
+		)
+		.unwrap();
+		let sock = Arc::new(
+			Socket::new(SocketDesc {
+				domain: SocketDomain::AfInet,
+				type_: SocketType::SockDgram,
+				protocol: 0,
+			})
+			.unwrap(),
+		)
+		.unwrap();
+		udp::bind(&sock, local).unwrap();
+		let mut buf = Vec::new();
+		buf.resize(MAX_FRAME_SIZE, 0).unwrap();
+		rx_poll(&mut buf);
+		unregister_iface(b"test0");
+		let mut data = [0u8; 16];
+		let msg = sock
+			.recv_msg(UserSlice::from_slice_mut(&mut data), 0, true)
+			.unwrap();
+		udp::close(&sock);
+		assert_eq!(&data[..msg.len], b"ping");
+	}
+
+	#[test_case]
+	fn rx_arp_request() {
+		let peer: MAC = [0x52, 0x54, 0, 0, 0, 2];
+		// ARP request from 10.0.9.2 for 10.0.9.1
+		let mut frame = Vec::new();
+		frame.extend_from_slice(&eth::BROADCAST).unwrap();
+		frame.extend_from_slice(&peer).unwrap();
+		frame.extend_from_slice(&[0x08, 0x06]).unwrap();
+		frame
+			.extend_from_slice(&[0, 1, 0x08, 0, 6, 4, 0, 1])
+			.unwrap();
+		frame.extend_from_slice(&peer).unwrap();
+		frame.extend_from_slice(&[10, 0, 9, 2]).unwrap();
+		frame.extend_from_slice(&[0; 6]).unwrap();
+		frame.extend_from_slice(&[10, 0, 9, 1]).unwrap();
+		let mut addresses = Vec::new();
+		addresses
+			.push(BindAddress {
+				addr: Address::IPv4([10, 0, 9, 1]),
+				subnet_mask: 24,
+			})
+			.unwrap();
+		let mut rx = Vec::new();
+		rx.push(frame).unwrap();
+		register_iface(
+			String::try_from(b"test0".as_slice()).unwrap(),
+			TestIface {
+				addresses,
+				rx,
+			},
+		)
+		.unwrap();
+		// Ignore the announcement
+		TEST_TX.lock().clear();
+		let mut buf = Vec::new();
+		buf.resize(MAX_FRAME_SIZE, 0).unwrap();
+		rx_poll(&mut buf);
+		unregister_iface(b"test0");
+		let tx = core::mem::take(&mut *TEST_TX.lock());
+		assert_eq!(tx.len(), 1);
+		let reply = &tx[0];
+		// Ethernet header
+		assert_eq!(&reply[..6], &peer);
+		assert_eq!(&reply[12..14], &[0x08, 0x06]);
+		// ARP reply from 10.0.9.1 to 10.0.9.2
+		assert_eq!(&reply[20..22], &[0, 2]);
+		assert_eq!(&reply[28..32], &[10, 0, 9, 1]);
+		assert_eq!(&reply[32..38], &peer);
+		assert_eq!(&reply[38..42], &[10, 0, 9, 2]);
+	}
+}
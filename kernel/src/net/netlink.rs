@@ -0,0 +1,869 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Netlink sockets (`AF_NETLINK`) allow communication between the kernel and userspace.
+//!
+//! Only the routing family (`NETLINK_ROUTE`) is supported, allowing userspace to list and
+//! configure network interfaces, addresses and routes.
+//!
+//! Requests are handled synchronously when they are sent. Replies are queued on the socket's
+//! receive buffer, one message per datagram, each prefixed by its length.
+
+use super::{
+	Address, BindAddress, IFF_UP, ROUTING_TABLE, Route, SocketDesc, SocketDomain, SocketType,
+	add_route, get_gateway_iface, get_iface_by_index, list_ifaces, remove_route,
+};
+use crate::{
+	file::socket::{MSG_PEEK, MSG_TRUNC, Message, Socket},
+	memory::user::UserSlice,
+	process::Process,
+	sync::mutex::Mutex,
+};
+use core::{hint::unlikely, mem::size_of};
+use macros::AnyRepr;
+use utils::{
+	bytes::{AnyRepr, as_bytes, from_bytes},
+	collections::{hashmap::HashMap, vec::Vec},
+	errno,
+	errno::{AllocResult, EResult},
+};
+
+/// Netlink family: routing and link configuration
+const NETLINK_ROUTE: i32 = 0;
+
+/// Message type: no operation
+const NLMSG_NOOP: u16 = 1;
+/// Message type: error or acknowledgement
+const NLMSG_ERROR: u16 = 2;
+/// Message type: end of a multipart message
+const NLMSG_DONE: u16 = 3;
+
+/// Message flag: the message is a request
+const NLM_F_REQUEST: u16 = 0x1;
+/// Message flag: the message is part of a multipart message
+const NLM_F_MULTI: u16 = 0x2;
+/// Message flag: reply with an acknowledgement on success
+const NLM_F_ACK: u16 = 0x4;
+/// Request flag: replace an existing object
+const NLM_F_REPLACE: u16 = 0x100;
+/// Request flag: do not modify an existing object
+const NLM_F_EXCL: u16 = 0x200;
+/// Request flag: dump all objects
+const NLM_F_DUMP: u16 = 0x300;
+
+/// rtnetlink message: link created or information about a link
+const RTM_NEWLINK: u16 = 16;
+/// rtnetlink message: get information about links
+const RTM_GETLINK: u16 = 18;
+/// rtnetlink message: set attributes of a link
+const RTM_SETLINK: u16 = 19;
+/// rtnetlink message: add an address to an interface
+const RTM_NEWADDR: u16 = 20;
+/// rtnetlink message: remove an address from an interface
+const RTM_DELADDR: u16 = 21;
+/// rtnetlink message: get addresses bound to interfaces
+const RTM_GETADDR: u16 = 22;
+/// rtnetlink message: add a route
+const RTM_NEWROUTE: u16 = 24;
+/// rtnetlink message: remove a route
+const RTM_DELROUTE: u16 = 25;
+/// rtnetlink message: get routes
+const RTM_GETROUTE: u16 = 26;
+
+/// Link attribute: hardware address
+const IFLA_ADDRESS: u16 = 1;
+/// Link attribute: hardware broadcast address
+const IFLA_BROADCAST: u16 = 2;
+/// Link attribute: interface name
+const IFLA_IFNAME: u16 = 3;
+/// Link attribute: Maximum Transmission Unit
+const IFLA_MTU: u16 = 4;
+/// Link attribute: operational state
+const IFLA_OPERSTATE: u16 = 16;
+
+/// Address attribute: interface address
+const IFA_ADDRESS: u16 = 1;
+/// Address attribute: local address
+const IFA_LOCAL: u16 = 2;
+/// Address attribute: interface name
+const IFA_LABEL: u16 = 3;
+
+/// Route attribute: destination address
+const RTA_DST: u16 = 1;
+/// Route attribute: output interface index
+const RTA_OIF: u16 = 4;
+/// Route attribute: gateway address
+const RTA_GATEWAY: u16 = 5;
+/// Route attribute: route metric
+const RTA_PRIORITY: u16 = 6;
+/// Route attribute: routing table
+const RTA_TABLE: u16 = 15;
+
+/// Operational state: down
+const IF_OPER_DOWN: u8 = 2;
+/// Operational state: up
+const IF_OPER_UP: u8 = 6;
+
+/// Address flag: the address does not expire
+const IFA_F_PERMANENT: u8 = 0x80;
+
+/// Routing table: main
+const RT_TABLE_MAIN: u8 = 254;
+/// Route origin: installed at boot or by the administrator
+const RTPROT_BOOT: u8 = 3;
+/// Route scope: global
+const RT_SCOPE_UNIVERSE: u8 = 0;
+/// Route scope: on the link
+const RT_SCOPE_LINK: u8 = 253;
+/// Route scope: on the host
+const RT_SCOPE_HOST: u8 = 254;
+/// Route type: gateway or direct route
+const RTN_UNICAST: u8 = 1;
+
+/// The header of a netlink message.
+#[derive(AnyRepr, Clone, Copy)]
+#[repr(C)]
+struct NlMsgHdr {
+	/// The length of the message, including the header.
+	len: u32,
+	/// The type of the message.
+	type_: u16,
+	/// Flags.
+	flags: u16,
+	/// Sequence number.
+	seq: u32,
+	/// The port ID of the sender.
+	pid: u32,
+}
+
+/// The payload of a `NLMSG_ERROR` message.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct NlMsgErr {
+	/// Negative errno, or zero for an acknowledgement.
+	error: i32,
+	/// The header of the message that caused the error.
+	msg: NlMsgHdr,
+}
+
+/// A netlink socket address.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct SockAddrNl {
+	/// Always `AF_NETLINK`.
+	family: u16,
+	/// Padding.
+	pad: u16,
+	/// The port ID.
+	pid: u32,
+	/// Multicast groups mask.
+	groups: u32,
+}
+
+/// The payload of link messages.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct IfInfoMsg {
+	/// Address family.
+	family: u8,
+	/// Padding.
+	pad: u8,
+	/// Hardware type.
+	type_: u16,
+	/// Interface index.
+	index: i32,
+	/// Interface flags.
+	flags: u32,
+	/// Change mask.
+	change: u32,
+}
+
+/// The payload of address messages.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct IfAddrMsg {
+	/// Address family.
+	family: u8,
+	/// Prefix length.
+	prefixlen: u8,
+	/// Address flags.
+	flags: u8,
+	/// Address scope.
+	scope: u8,
+	/// Interface index.
+	index: u32,
+}
+
+/// The payload of route messages.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct RtMsg {
+	/// Address family.
+	family: u8,
+	/// Length of the destination prefix.
+	dst_len: u8,
+	/// Length of the source prefix.
+	src_len: u8,
+	/// Type of service.
+	tos: u8,
+	/// Routing table.
+	table: u8,
+	/// Origin of the route.
+	protocol: u8,
+	/// Scope of the route.
+	scope: u8,
+	/// Type of the route.
+	type_: u8,
+	/// Flags.
+	flags: u32,
+}
+
+/// The header of a message attribute.
+#[derive(AnyRepr)]
+#[repr(C)]
+struct RtAttr {
+	/// The length of the attribute, including the header.
+	len: u16,
+	/// The type of the attribute.
+	type_: u16,
+}
+
+/// Bound port IDs.
+static PORTS: Mutex<HashMap<u32, ()>> = Mutex::new(HashMap::new());
+
+/// Rounds `len` up to the alignment of netlink messages and attributes.
+fn align(len: usize) -> usize {
+	len.next_multiple_of(4)
+}
+
+/// Returns an iterator over the attributes in `buf`, as type/payload pairs.
+///
+/// Iteration stops at the first malformed attribute.
+fn attrs(buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+	let mut off = 0;
+	core::iter::from_fn(move || {
+		let attr: &RtAttr = from_bytes(buf.get(off..)?)?;
+		let len = attr.len as usize;
+		let payload = buf.get((off + size_of::<RtAttr>())..(off + len))?;
+		off += align(len);
+		Some((attr.type_, payload))
+	})
+}
+
+/// Parses a network address of the given family from an attribute's payload.
+fn parse_addr(family: u8, buf: &[u8]) -> EResult<Address> {
+	match family as u32 {
+		f if f == SocketDomain::AfInet.get_id() => {
+			Ok(Address::IPv4(buf.try_into().map_err(|_| errno!(EINVAL))?))
+		}
+		f if f == SocketDomain::AfInet6.get_id() => {
+			Ok(Address::IPv6(buf.try_into().map_err(|_| errno!(EINVAL))?))
+		}
+		_ => Err(errno!(EAFNOSUPPORT)),
+	}
+}
+
+/// Returns the family and bytes of the given address.
+fn addr_bytes(addr: &Address) -> (u8, &[u8]) {
+	match addr {
+		Address::IPv4(a) => (SocketDomain::AfInet.get_id() as _, a),
+		Address::IPv6(a) => (SocketDomain::AfInet6.get_id() as _, a),
+	}
+}
+
+/// Returns the unspecified address of the given family.
+fn unspecified(family: u8) -> EResult<Address> {
+	match family as u32 {
+		f if f == SocketDomain::AfInet.get_id() => Ok(Address::IPv4([0; 4])),
+		f if f == SocketDomain::AfInet6.get_id() => Ok(Address::IPv6([0; 16])),
+		_ => Err(errno!(EAFNOSUPPORT)),
+	}
+}
+
+/// Builder for a reply message.
+struct MsgBuilder(Vec<u8>);
+
+impl MsgBuilder {
+	/// Creates a message with the given type and flags, in reply to the request `req`.
+	fn new(req: &NlMsgHdr, type_: u16, flags: u16) -> AllocResult<Self> {
+		let mut b = Self(Vec::new());
+		b.push(&NlMsgHdr {
+			len: 0,
+			type_,
+			flags,
+			seq: req.seq,
+			pid: req.pid,
+		})?;
+		Ok(b)
+	}
+
+	/// Appends `val` to the message, padded to the alignment.
+	fn push<T: AnyRepr>(&mut self, val: &T) -> AllocResult<()> {
+		self.push_bytes(as_bytes(val))
+	}
+
+	/// Appends `buf` to the message, padded to the alignment.
+	fn push_bytes(&mut self, buf: &[u8]) -> AllocResult<()> {
+		self.0.extend_from_slice(buf)?;
+		self.0.resize(align(self.0.len()), 0)
+	}
+
+	/// Appends an attribute to the message.
+	fn attr(&mut self, type_: u16, payload: &[u8]) -> AllocResult<()> {
+		self.push(&RtAttr {
+			len: (size_of::<RtAttr>() + payload.len()) as _,
+			type_,
+		})?;
+		self.push_bytes(payload)
+	}
+
+	/// Appends a string attribute to the message, with a terminating nul byte.
+	fn attr_str(&mut self, type_: u16, s: &[u8]) -> AllocResult<()> {
+		let mut payload = Vec::try_from(s)?;
+		payload.push(0)?;
+		self.attr(type_, &payload)
+	}
+
+	/// Finishes the message, returning its content.
+	fn finish(mut self) -> Vec<u8> {
+		let len = (self.0.len() as u32).to_ne_bytes();
+		self.0[..4].copy_from_slice(&len);
+		self.0
+	}
+}
+
+/// Handles `RTM_GETLINK`.
+fn get_link(req: &NlMsgHdr, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	let dump = req.flags & NLM_F_DUMP == NLM_F_DUMP;
+	// Filter
+	let (index, name) = match from_bytes::<IfInfoMsg>(payload) {
+		Some(msg) if !dump => {
+			let name = attrs(&payload[size_of::<IfInfoMsg>()..])
+				.find(|(t, _)| *t == IFLA_IFNAME)
+				.map(|(_, name)| name.split(|b| *b == 0).next().unwrap_or_default());
+			(msg.index as u32, name)
+		}
+		_ => (0, None),
+	};
+	let flags = if dump { NLM_F_MULTI } else { 0 };
+	let mut found = false;
+	for (iface_name, e) in list_ifaces()? {
+		if (index != 0 && index != e.index) || name.is_some_and(|n| n != iface_name.as_bytes()) {
+			continue;
+		}
+		found = true;
+		let iface = e.iface.lock();
+		let up = iface.is_up();
+		let mut b = MsgBuilder::new(req, RTM_NEWLINK, flags)?;
+		b.push(&IfInfoMsg {
+			family: 0,
+			pad: 0,
+			type_: iface.get_hw_type(),
+			index: e.index as _,
+			flags: iface.get_flags(),
+			change: 0,
+		})?;
+		b.attr_str(IFLA_IFNAME, &iface_name)?;
+		b.attr(IFLA_ADDRESS, iface.get_mac())?;
+		b.attr(IFLA_BROADCAST, &[0xff; 6])?;
+		b.attr(IFLA_MTU, &iface.get_mtu().to_ne_bytes())?;
+		b.attr(
+			IFLA_OPERSTATE,
+			&[if up { IF_OPER_UP } else { IF_OPER_DOWN }],
+		)?;
+		replies.push(b.finish())?;
+	}
+	if !dump && !found {
+		return Err(errno!(ENODEV));
+	}
+	Ok(())
+}
+
+/// Parses the payload of an address message, returning the interface index and the address.
+fn parse_addr_msg(payload: &[u8]) -> EResult<(u32, BindAddress)> {
+	let msg: &IfAddrMsg = from_bytes(payload).ok_or_else(|| errno!(EINVAL))?;
+	let mut addr = None;
+	for (type_, buf) in attrs(&payload[size_of::<IfAddrMsg>()..]) {
+		match type_ {
+			// The local address takes precedence (the other is the peer on point-to-point links)
+			IFA_LOCAL => addr = Some(parse_addr(msg.family, buf)?),
+			IFA_ADDRESS if addr.is_none() => addr = Some(parse_addr(msg.family, buf)?),
+			_ => {}
+		}
+	}
+	let addr = addr.ok_or_else(|| errno!(EINVAL))?;
+	let max_prefix = match addr {
+		Address::IPv4(_) => 32,
+		Address::IPv6(_) => 128,
+	};
+	if unlikely(msg.prefixlen > max_prefix) {
+		return Err(errno!(EINVAL));
+	}
+	Ok((
+		msg.index,
+		BindAddress {
+			addr,
+			subnet_mask: msg.prefixlen,
+		},
+	))
+}
+
+/// Handles `RTM_SETLINK`.
+fn set_link(payload: &[u8]) -> EResult<()> {
+	let msg = from_bytes::<IfInfoMsg>(payload).ok_or_else(|| errno!(EINVAL))?;
+	let (_, iface) = get_iface_by_index(msg.index as _)?;
+	// An empty change mask means all flags are changed
+	if msg.change == 0 || msg.change & IFF_UP != 0 {
+		iface.lock().set_up(msg.flags & IFF_UP != 0);
+	}
+	Ok(())
+}
+
+/// Handles `RTM_NEWADDR`.
+fn new_addr(req: &NlMsgHdr, payload: &[u8]) -> EResult<()> {
+	let (index, addr) = parse_addr_msg(payload)?;
+	let (name, iface) = get_iface_by_index(index)?;
+	match super::add_address(&name, &iface, addr) {
+		Err(e) if e.as_int() == errno::EEXIST && req.flags & NLM_F_EXCL == 0 => Ok(()),
+		res => res,
+	}
+}
+
+/// Handles `RTM_DELADDR`.
+fn del_addr(payload: &[u8]) -> EResult<()> {
+	let (index, addr) = parse_addr_msg(payload)?;
+	let (name, iface) = get_iface_by_index(index)?;
+	super::remove_address(&name, &iface, &addr)
+}
+
+/// Handles `RTM_GETADDR`.
+fn get_addr(req: &NlMsgHdr, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	// The request may contain only the family
+	let family = payload.first().copied().unwrap_or(0);
+	for (name, e) in list_ifaces()? {
+		let iface = e.iface.lock();
+		let loopback = iface.is_loopback();
+		for a in iface.get_addresses() {
+			let (f, bytes) = addr_bytes(&a.addr);
+			if family != 0 && family != f {
+				continue;
+			}
+			let mut b = MsgBuilder::new(req, RTM_NEWADDR, NLM_F_MULTI)?;
+			b.push(&IfAddrMsg {
+				family: f,
+				prefixlen: a.subnet_mask,
+				flags: IFA_F_PERMANENT,
+				scope: if loopback {
+					RT_SCOPE_HOST
+				} else {
+					RT_SCOPE_UNIVERSE
+				},
+				index: e.index,
+			})?;
+			b.attr(IFA_ADDRESS, bytes)?;
+			if matches!(a.addr, Address::IPv4(_)) {
+				b.attr(IFA_LOCAL, bytes)?;
+				b.attr_str(IFA_LABEL, &name)?;
+			}
+			replies.push(b.finish())?;
+		}
+	}
+	Ok(())
+}
+
+/// A route parsed from a route message. Fields that are not specified are `None`.
+struct RouteMsg {
+	/// The destination.
+	dst: Option<BindAddress>,
+	/// The gateway.
+	gateway: Option<Address>,
+	/// The index of the output interface.
+	oif: Option<u32>,
+	/// The route's metric.
+	metric: Option<u32>,
+}
+
+/// Parses the payload of a route message.
+fn parse_route_msg(payload: &[u8]) -> EResult<(u8, RouteMsg)> {
+	let msg: &RtMsg = from_bytes(payload).ok_or_else(|| errno!(EINVAL))?;
+	if msg.type_ != RTN_UNICAST || msg.src_len != 0 {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let mut route = RouteMsg {
+		dst: None,
+		gateway: None,
+		oif: None,
+		metric: None,
+	};
+	let mut dst = None;
+	for (type_, buf) in attrs(&payload[size_of::<RtMsg>()..]) {
+		let u32_val = || {
+			buf.try_into()
+				.map(u32::from_ne_bytes)
+				.map_err(|_| errno!(EINVAL))
+		};
+		match type_ {
+			RTA_DST => dst = Some(parse_addr(msg.family, buf)?),
+			RTA_GATEWAY => route.gateway = Some(parse_addr(msg.family, buf)?),
+			RTA_OIF => route.oif = Some(u32_val()?),
+			RTA_PRIORITY => route.metric = Some(u32_val()?),
+			_ => {}
+		}
+	}
+	if msg.dst_len > 0 {
+		route.dst = Some(BindAddress {
+			addr: dst.unwrap_or(unspecified(msg.family)?),
+			subnet_mask: msg.dst_len,
+		});
+	}
+	Ok((msg.family, route))
+}
+
+/// Handles `RTM_NEWROUTE`.
+fn new_route(req: &NlMsgHdr, payload: &[u8]) -> EResult<()> {
+	let (family, msg) = parse_route_msg(payload)?;
+	let gateway = msg.gateway.map(Ok).unwrap_or_else(|| unspecified(family))?;
+	let iface = match msg.oif {
+		Some(index) => get_iface_by_index(index)?.0,
+		None => get_gateway_iface(&gateway)?,
+	};
+	let route = Route {
+		dst: msg.dst,
+		iface,
+		gateway,
+		metric: msg.metric.unwrap_or(0),
+	};
+	add_route(route, req.flags & NLM_F_REPLACE != 0)
+}
+
+/// Handles `RTM_DELROUTE`.
+fn del_route(payload: &[u8]) -> EResult<()> {
+	let (family, msg) = parse_route_msg(payload)?;
+	let iface = msg.oif.map(get_iface_by_index).transpose()?;
+	let family = unspecified(family)?;
+	remove_route(|r| {
+		r.gateway.same_family(&family)
+			&& r.has_dst(&msg.dst)
+			&& msg.gateway.is_none_or(|g| g == r.gateway)
+			&& msg.metric.is_none_or(|m| m == r.metric)
+			&& iface.as_ref().is_none_or(|(name, _)| *name == r.iface)
+	})
+}
+
+/// Handles `RTM_GETROUTE`.
+fn get_route(req: &NlMsgHdr, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	// The request may contain only the family
+	let family = payload.first().copied().unwrap_or(0);
+	let ifaces = list_ifaces()?;
+	let routing_table = ROUTING_TABLE.lock();
+	for r in routing_table.iter() {
+		let (f, gateway) = addr_bytes(&r.gateway);
+		if family != 0 && family != f {
+			continue;
+		}
+		let mut b = MsgBuilder::new(req, RTM_NEWROUTE, NLM_F_MULTI)?;
+		b.push(&RtMsg {
+			family: f,
+			dst_len: r.dst.as_ref().map(|d| d.subnet_mask).unwrap_or(0),
+			src_len: 0,
+			tos: 0,
+			table: RT_TABLE_MAIN,
+			protocol: RTPROT_BOOT,
+			scope: if r.gateway.is_unspecified() {
+				RT_SCOPE_LINK
+			} else {
+				RT_SCOPE_UNIVERSE
+			},
+			type_: RTN_UNICAST,
+			flags: 0,
+		})?;
+		b.attr(RTA_TABLE, &(RT_TABLE_MAIN as u32).to_ne_bytes())?;
+		if let Some(dst) = &r.dst {
+			b.attr(RTA_DST, addr_bytes(&dst.network()).1)?;
+		}
+		if !r.gateway.is_unspecified() {
+			b.attr(RTA_GATEWAY, gateway)?;
+		}
+		if let Some((_, e)) = ifaces.iter().find(|(name, _)| *name == r.iface) {
+			b.attr(RTA_OIF, &e.index.to_ne_bytes())?;
+		}
+		if r.metric != 0 {
+			b.attr(RTA_PRIORITY, &r.metric.to_ne_bytes())?;
+		}
+		replies.push(b.finish())?;
+	}
+	Ok(())
+}
+
+/// Handles the request `req` with the given payload, pushing replies to `replies`.
+fn handle(req: &NlMsgHdr, payload: &[u8], replies: &mut Vec<Vec<u8>>) -> EResult<()> {
+	// Modifying the configuration requires privileges
+	if matches!(
+		req.type_,
+		RTM_SETLINK | RTM_NEWADDR | RTM_DELADDR | RTM_NEWROUTE | RTM_DELROUTE
+	) && !Process::current().fs.lock().access_profile.is_privileged()
+	{
+		return Err(errno!(EPERM));
+	}
+	match req.type_ {
+		NLMSG_NOOP => Ok(()),
+		RTM_GETLINK => get_link(req, payload, replies),
+		RTM_SETLINK => set_link(payload),
+		RTM_NEWADDR => new_addr(req, payload),
+		RTM_DELADDR => del_addr(payload),
+		RTM_GETADDR => get_addr(req, payload, replies),
+		RTM_NEWROUTE => new_route(req, payload),
+		RTM_DELROUTE => del_route(payload),
+		RTM_GETROUTE => get_route(req, payload, replies),
+		_ => Err(errno!(EOPNOTSUPP)),
+	}
+}
+
+/// Tells whether the given socket descriptor is valid for the netlink domain.
+pub fn check_desc(desc: &SocketDesc) -> EResult<()> {
+	if !matches!(desc.type_, SocketType::SockRaw | SocketType::SockDgram) {
+		return Err(errno!(ESOCKTNOSUPPORT));
+	}
+	if desc.protocol != NETLINK_ROUTE {
+		return Err(errno!(EPROTONOSUPPORT));
+	}
+	Ok(())
+}
+
+/// Returns the port ID the socket is bound to, if any.
+fn get_port(sock: &Socket) -> Option<u32> {
+	let sockname = sock.get_sockname().lock();
+	from_bytes::<SockAddrNl>(&sockname).map(|addr| addr.pid)
+}
+
+/// Binds the socket to the port ID `pid`.
+///
+/// If `pid` is zero, a port ID is allocated.
+fn bind_port(sock: &Socket, pid: u32) -> EResult<()> {
+	let mut sockname = sock.get_sockname().lock();
+	if !sockname.is_empty() {
+		return Err(errno!(EINVAL));
+	}
+	let pid = {
+		let mut ports = PORTS.lock();
+		let pid = if pid == 0 {
+			// Use the process's ID, or fall back to negative values like Linux does
+			let pid = Process::current().get_pid() as u32;
+			(0..)
+				.map(|i: u32| {
+					if i == 0 {
+						pid
+					} else {
+						(-4096i32 - i as i32) as u32
+					}
+				})
+				.find(|pid| !ports.contains_key(pid))
+				.unwrap()
+		} else if ports.contains_key(&pid) {
+			return Err(errno!(EADDRINUSE));
+		} else {
+			pid
+		};
+		ports.insert(pid, ())?;
+		pid
+	};
+	let addr = SockAddrNl {
+		family: SocketDomain::AfNetlink.get_id() as _,
+		pad: 0,
+		pid,
+		groups: 0,
+	};
+	*sockname = Vec::try_from(as_bytes(&addr))?;
+	Ok(())
+}
+
+/// Binds the socket to the given address.
+pub fn bind(sock: &Socket, sockaddr: &[u8]) -> EResult<()> {
+	let addr: &SockAddrNl = from_bytes(sockaddr).ok_or_else(|| errno!(EINVAL))?;
+	if addr.family as u32 != SocketDomain::AfNetlink.get_id() {
+		return Err(errno!(EINVAL));
+	}
+	// TODO support multicast groups
+	bind_port(sock, addr.pid)
+}
+
+/// Sends the messages in `buf` to the kernel, and queues the replies on the socket.
+///
+/// `dst` is the destination address. Only the kernel (port ID zero) is supported.
+pub fn send(sock: &Socket, buf: UserSlice<u8>, dst: Option<&[u8]>) -> EResult<usize> {
+	if let Some(dst) = dst {
+		let dst: &SockAddrNl = from_bytes(dst).ok_or_else(|| errno!(EINVAL))?;
+		if dst.pid != 0 {
+			return Err(errno!(ECONNREFUSED));
+		}
+	}
+	let pid = match get_port(sock) {
+		Some(pid) => pid,
+		None => {
+			bind_port(sock, 0)?;
+			get_port(sock).unwrap_or_default()
+		}
+	};
+	let data = buf.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+	let mut replies = Vec::new();
+	let mut off = 0;
+	while let Some(hdr) = data.get(off..).and_then(from_bytes::<NlMsgHdr>) {
+		let len = hdr.len as usize;
+		if len < size_of::<NlMsgHdr>() || off + len > data.len() {
+			break;
+		}
+		let mut hdr = *hdr;
+		// Replies are addressed to the sender
+		if hdr.pid == 0 {
+			hdr.pid = pid;
+		}
+		let payload = &data[(off + size_of::<NlMsgHdr>())..(off + len)];
+		off += align(len);
+		if hdr.flags & NLM_F_REQUEST == 0 {
+			continue;
+		}
+		let res = handle(&hdr, payload, &mut replies);
+		let dump = hdr.flags & NLM_F_DUMP == NLM_F_DUMP;
+		match res {
+			Ok(()) if dump => {
+				let mut b = MsgBuilder::new(&hdr, NLMSG_DONE, NLM_F_MULTI)?;
+				b.push(&0i32)?;
+				replies.push(b.finish())?;
+			}
+			Ok(()) if hdr.flags & NLM_F_ACK == 0 => {}
+			res => {
+				let mut b = MsgBuilder::new(&hdr, NLMSG_ERROR, 0)?;
+				b.push(&NlMsgErr {
+					error: res.err().map(|e| -e.as_int()).unwrap_or(0),
+					msg: hdr,
+				})?;
+				replies.push(b.finish())?;
+			}
+		}
+	}
+	// Queue replies. If they do not fit, they are dropped and the error is reported on reception,
+	// since requests have been handled
+	{
+		let mut rx = sock.rx_buff().lock();
+		if let Some(rx) = rx.as_mut() {
+			let len: usize = replies.iter().map(|r| size_of::<u32>() + r.len()).sum();
+			if unlikely(len > rx.get_available_len()) {
+				sock.set_error(errno!(ENOBUFS));
+			} else {
+				for r in &replies {
+					let len = (r.len() as u32).to_ne_bytes();
+					rx.write(unsafe { UserSlice::from_slice(&len) })?;
+					rx.write(unsafe { UserSlice::from_slice(r) })?;
+				}
+			}
+		}
+	}
+	sock.rx_queue().wake_all();
+	Ok(data.len())
+}
+
+/// Receives a message from the kernel into `buf`.
+///
+/// Arguments:
+/// - `flags` are the flags passed to the system call. `MSG_PEEK` and `MSG_TRUNC` are supported.
+/// - `nonblock` tells whether the function shall return instead of waiting for a message.
+pub fn recv(sock: &Socket, buf: UserSlice<u8>, flags: i32, nonblock: bool) -> EResult<Message> {
+	let timeout = sock.opts().lock().rcv_timeout;
+	sock.rx_queue().wait_until_timeout(timeout, || {
+		if let Some(err) = sock.take_error() {
+			return Some(Err(err));
+		}
+		let mut rx = sock.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			return Some(Ok(Message::default()));
+		};
+		if rx.is_empty() {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		Some((|| {
+			let mut len = [0u8; size_of::<u32>()];
+			rx.peek(UserSlice::from_slice_mut(&mut len))?;
+			let len = u32::from_ne_bytes(len) as usize;
+			let copied = rx.peek_at(size_of::<u32>(), buf.subslice(0, len))?;
+			if flags & MSG_PEEK == 0 {
+				rx.discard(size_of::<u32>() + len);
+			}
+			let src = SockAddrNl {
+				family: SocketDomain::AfNetlink.get_id() as _,
+				pad: 0,
+				pid: 0,
+				groups: 0,
+			};
+			Ok(Message {
+				len: if flags & MSG_TRUNC != 0 { len } else { copied },
+				src: Some(Vec::try_from(as_bytes(&src))?),
+				rights: Vec::new(),
+				truncated: copied < len,
+			})
+		})())
+	})?
+}
+
+/// Releases the socket's port ID, after the last reference to it has been dropped.
+pub fn close(sock: &Socket) {
+	if let Some(pid) = get_port(sock) {
+		PORTS.lock().remove(&pid);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn netlink_attrs() {
+		let req = NlMsgHdr {
+			len: 0,
+			type_: RTM_NEWADDR,
+			flags: NLM_F_REQUEST,
+			seq: 1,
+			pid: 2,
+		};
+		let mut b = MsgBuilder::new(&req, RTM_NEWADDR, 0).unwrap();
+		b.push(&IfAddrMsg {
+			family: SocketDomain::AfInet.get_id() as _,
+			prefixlen: 24,
+			flags: 0,
+			scope: 0,
+			index: 1,
+		})
+		.unwrap();
+		b.attr(IFA_LOCAL, &[10, 0, 0, 1]).unwrap();
+		b.attr_str(IFA_LABEL, b"eth0").unwrap();
+		let msg = b.finish();
+		let hdr: &NlMsgHdr = from_bytes(&msg).unwrap();
+		assert_eq!(hdr.len as usize, msg.len());
+		assert_eq!(msg.len(), 16 + 8 + 8 + 12);
+		let (index, addr) = parse_addr_msg(&msg[size_of::<NlMsgHdr>()..]).unwrap();
+		assert_eq!(index, 1);
+		assert_eq!(addr.addr, Address::IPv4([10, 0, 0, 1]));
+		assert_eq!(addr.subnet_mask, 24);
+		let mut attrs = attrs(&msg[(size_of::<NlMsgHdr>() + size_of::<IfAddrMsg>())..]);
+		assert_eq!(attrs.next(), Some((IFA_LOCAL, &[10, 0, 0, 1][..])));
+		assert_eq!(attrs.next(), Some((IFA_LABEL, &b"eth0\0"[..])));
+		assert_eq!(attrs.next(), None);
+	}
+}
@@ -18,30 +18,34 @@
 
 //! The Open Systems Interconnection (OSI) model defines the architecure of a network stack.
 
-use super::{SocketDesc, SocketDomain, SocketType, buf::BufList, ip};
+use super::{SocketDesc, SocketDomain, SocketType, buf::BufList, ip, tcp, udp};
 use crate::sync::mutex::Mutex;
-use core::fmt::Debug;
+use core::{any::Any, fmt::Debug};
 use utils::{boxed::Box, collections::hashmap::HashMap, errno, errno::EResult};
 
 /// An OSI layer.
 ///
 /// A layer stack acts as a pipeline, passing data from one layer to the other.
-pub trait Layer: Debug {
-	// TODO receive
-
+///
+/// Received packets do not go through layer stacks. Instead, each protocol parses them and
+/// demultiplexes them to the matching socket (see [`super::rx_task`]).
+pub trait Layer: Any + Debug {
 	/// Transmits data in the given buffer.
 	///
 	/// Arguments:
 	/// - `buff` is the list of buffer which composes the packet being built.
 	/// - `next` is the function called to pass the buffers list to the next layer.
-	fn transmit<'c, F>(&self, buff: BufList<'c>, next: F) -> EResult<()>
-	where
-		Self: Sized,
-		F: Fn(BufList<'c>) -> EResult<()>;
+	fn transmit(
+		&self,
+		buff: BufList<'_>,
+		next: &dyn Fn(BufList<'_>) -> EResult<()>,
+	) -> EResult<()>;
 }
 
 /// Function used to build a layer from a given sockaddr structure.
-pub type LayerBuilder = fn(&[u8]) -> EResult<Box<dyn Layer>>;
+///
+/// The protocol in the given descriptor is always resolved (never zero).
+pub type LayerBuilder = fn(&SocketDesc, &[u8]) -> EResult<Box<dyn Layer>>;
 
 /// Collection of OSI layers 3 (network)
 static DOMAINS: Mutex<HashMap<u32, LayerBuilder>> = Mutex::new(HashMap::new());
@@ -53,6 +57,21 @@ static PROTOCOLS: Mutex<HashMap<u32, LayerBuilder>> = Mutex::new(HashMap::new())
 /// If this collection doesn't contain a pair, it is considered invalid.
 static DEFAULT_PROTOCOLS: Mutex<HashMap<(u32, SocketType), u32>> = Mutex::new(HashMap::new());
 
+/// Returns the protocol ID to be used for the given socket descriptor.
+///
+/// If the descriptor is invalid, the function returns an error.
+pub fn resolve_protocol(desc: &SocketDesc) -> EResult<u32> {
+	if desc.protocol != 0 {
+		Ok(desc.protocol as _)
+	} else {
+		DEFAULT_PROTOCOLS
+			.lock()
+			.get(&(desc.domain.get_id(), desc.type_))
+			.cloned()
+			.ok_or_else(|| errno!(EINVAL))
+	}
+}
+
 /// A stack of layers for a socket.
 #[derive(Debug)]
 pub struct Stack {
@@ -73,26 +92,23 @@ impl Stack {
 	/// If the descriptor is invalid or if the stack cannot be created, the function returns an
 	/// error.
 	pub fn new(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Stack> {
+		let desc = SocketDesc {
+			protocol: resolve_protocol(desc)? as _,
+			..*desc
+		};
 		let domain = {
 			let guard = DOMAINS.lock();
 			let builder = guard
 				.get(&desc.domain.get_id())
 				.ok_or_else(|| errno!(EINVAL))?;
-			builder(sockaddr)?
-		};
-
-		let protocol: u32 = if desc.protocol != 0 {
-			desc.protocol as _
-		} else {
-			*DEFAULT_PROTOCOLS
-				.lock()
-				.get(&(desc.domain.get_id(), desc.type_))
-				.ok_or_else(|| errno!(EINVAL))?
+			builder(&desc, sockaddr)?
 		};
 		let protocol = {
 			let guard = PROTOCOLS.lock();
-			let builder = guard.get(&protocol).ok_or_else(|| errno!(EINVAL))?;
-			builder(sockaddr)?
+			let builder = guard
+				.get(&(desc.protocol as _))
+				.ok_or_else(|| errno!(EINVAL))?;
+			builder(&desc, sockaddr)?
 		};
 
 		Ok(Stack {
@@ -100,12 +116,34 @@ impl Stack {
 			protocol,
 		})
 	}
+
+	/// Returns the protocol layer, downcasted to `L`.
+	///
+	/// If the layer is not of type `L`, the function returns `None`.
+	pub fn protocol<L: Layer>(&self) -> Option<&L> {
+		(self.protocol.as_ref() as &dyn Any).downcast_ref()
+	}
+
+	/// Returns the protocol layer, downcasted to `L`.
+	///
+	/// If the layer is not of type `L`, the function returns `None`.
+	pub fn protocol_mut<L: Layer>(&mut self) -> Option<&mut L> {
+		(self.protocol.as_mut() as &mut dyn Any).downcast_mut()
+	}
+
+	/// Transmits the given buffer through the whole stack, then on the interface the packet is
+	/// routed to.
+	pub fn transmit(&self, buff: BufList<'_>) -> EResult<()> {
+		self.protocol
+			.transmit(buff, &|buff| self.domain.transmit(buff, &super::transmit))
+	}
 }
 
 /// Registers default domains/types/protocols.
 pub(crate) fn init() -> EResult<()> {
+	// UNIX and netlink sockets do not go through a layer stack (see `super::unix` and
+	// `super::netlink`)
 	let domains = HashMap::try_from([
-		// TODO unix
 		(
 			SocketDomain::AfInet.get_id(),
 			ip::inet_build as LayerBuilder,
@@ -114,23 +152,29 @@ pub(crate) fn init() -> EResult<()> {
 			SocketDomain::AfInet6.get_id(),
 			ip::inet6_build as LayerBuilder,
 		),
-		// TODO netlink
 		// TODO packet
 	])?;
 	let protocols = HashMap::try_from([
-		// TODO tcp
-		// TODO udp
+		(ip::PROTO_TCP as u32, tcp::build as LayerBuilder),
+		(ip::PROTO_UDP as u32, udp::build as LayerBuilder),
 	])?;
 	let default_protocols = HashMap::try_from([
-		// TODO unix
-
-		// ((SocketDomain::AfInet.get_id(), SocketType::SockStream.get_id()), /* TODO: ipv4/tcp */),
-		// ((SocketDomain::AfInet.get_id(), SocketType::SockDgram.get_id()), /* TODO: ipv4/udp */),
-
-		// ((SocketDomain::AfInet6.get_id(), SocketType::SockStream.get_id()), /* TODO: ipv6/tcp */),
-		// ((SocketDomain::AfInet6.get_id(), SocketType::SockDgram.get_id()), /* TODO: ipv6/udp */),
-
-		// TODO netlink
+		(
+			(SocketDomain::AfInet.get_id(), SocketType::SockStream),
+			ip::PROTO_TCP as u32,
+		),
+		(
+			(SocketDomain::AfInet.get_id(), SocketType::SockDgram),
+			ip::PROTO_UDP as u32,
+		),
+		(
+			(SocketDomain::AfInet6.get_id(), SocketType::SockStream),
+			ip::PROTO_TCP as u32,
+		),
+		(
+			(SocketDomain::AfInet6.get_id(), SocketType::SockDgram),
+			ip::PROTO_UDP as u32,
+		),
 		// TODO packet
 	])?;
 
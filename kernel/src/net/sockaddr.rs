@@ -19,8 +19,14 @@
 //! This module defines sockaddr structures used by system calls to define connection informations
 //! on sockets.
 
-use super::Address;
-use core::ffi::c_short;
+use super::{Address, SocketDomain};
+use core::{ffi::c_short, ptr};
+use utils::{
+	bytes::as_bytes,
+	collections::vec::Vec,
+	errno,
+	errno::{AllocResult, EResult},
+};
 
 /// Structure providing connection informations for sockets with IPv4.
 #[repr(C)]
@@ -53,16 +59,16 @@ pub struct SockAddrIn6 {
 	sin6_family: c_short,
 	/// The port on which the connection is to be opened.
 	sin6_port: c_short,
-	/// TODO doc
+	/// The IPv6 flow information.
 	sin6_flowinfo: u32,
 	/// The destination address of the connection.
 	sin6_addr: In6Addr,
-	/// TODO doc
+	/// The set of interfaces for the address's scope.
 	sin6_scope_id: u32,
 }
 
 /// A unified structure which contains data passed from userspace.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SockAddr {
 	/// The port used by the socket.
 	pub port: u16,
@@ -72,16 +78,10 @@ pub struct SockAddr {
 
 impl From<SockAddrIn> for SockAddr {
 	fn from(val: SockAddrIn) -> Self {
-		let addr: [u8; 4] = [
-			((val.sin_addr >> 24) & 0xff) as u8,
-			((val.sin_addr >> 16) & 0xff) as u8,
-			((val.sin_addr >> 8) & 0xff) as u8,
-			(val.sin_addr & 0xff) as u8,
-		];
-
 		Self {
-			port: val.sin_port as _,
-			addr: Address::IPv4(addr),
+			port: u16::from_be(val.sin_port as _),
+			// The address is stored in network byte order
+			addr: Address::IPv4(val.sin_addr.to_ne_bytes()),
 		}
 	}
 }
@@ -91,8 +91,105 @@ impl From<SockAddrIn6> for SockAddr {
 		let addr = unsafe { val.sin6_addr.__s6_addr };
 
 		Self {
-			port: val.sin6_port as _,
+			port: u16::from_be(val.sin6_port as _),
 			addr: Address::IPv6(addr),
 		}
 	}
 }
+
+impl SockAddr {
+	/// Parses a sockaddr structure from the given buffer.
+	///
+	/// If the buffer is too small, the function returns [`errno::EINVAL`]. If the family is not
+	/// supported, the function returns [`errno::EAFNOSUPPORT`].
+	pub fn from_bytes(buf: &[u8]) -> EResult<Self> {
+		let family = buf
+			.first_chunk::<2>()
+			.map(|f| u16::from_ne_bytes(*f))
+			.ok_or_else(|| errno!(EINVAL))?;
+		let domain = SocketDomain::try_from(family as u32)?;
+		if buf.len() < domain.get_sockaddr_len() {
+			return Err(errno!(EINVAL));
+		}
+		let sockaddr = match domain {
+			SocketDomain::AfInet => {
+				unsafe { ptr::read_unaligned(buf.as_ptr() as *const SockAddrIn) }.into()
+			}
+			SocketDomain::AfInet6 => {
+				unsafe { ptr::read_unaligned(buf.as_ptr() as *const SockAddrIn6) }.into()
+			}
+			_ => return Err(errno!(EAFNOSUPPORT)),
+		};
+		Ok(sockaddr)
+	}
+
+	/// Returns the domain of the address.
+	pub fn domain(&self) -> SocketDomain {
+		match self.addr {
+			Address::IPv4(_) => SocketDomain::AfInet,
+			Address::IPv6(_) => SocketDomain::AfInet6,
+		}
+	}
+
+	/// Serializes the address into a sockaddr structure.
+	pub fn to_bytes(&self) -> AllocResult<Vec<u8>> {
+		match self.addr {
+			Address::IPv4(addr) => {
+				let sockaddr = SockAddrIn {
+					sin_family: SocketDomain::AfInet.get_id() as _,
+					sin_port: self.port.to_be() as _,
+					sin_addr: u32::from_ne_bytes(addr),
+					sin_zero: [0; 8],
+				};
+				Vec::try_from(as_bytes(&sockaddr))
+			}
+			Address::IPv6(addr) => {
+				let sockaddr = SockAddrIn6 {
+					sin6_family: SocketDomain::AfInet6.get_id() as _,
+					sin6_port: self.port.to_be() as _,
+					sin6_flowinfo: 0,
+					sin6_addr: In6Addr {
+						__s6_addr: addr,
+					},
+					sin6_scope_id: 0,
+				};
+				Vec::try_from(as_bytes(&sockaddr))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn sockaddr_in_roundtrip() {
+		let addr = SockAddr {
+			port: 8080,
+			addr: Address::IPv4([192, 168, 1, 42]),
+		};
+		let buf = addr.to_bytes().unwrap();
+		assert_eq!(buf.len(), size_of::<SockAddrIn>());
+		// Port and address are in network byte order
+		assert_eq!(&buf[2..8], &[0x1f, 0x90, 192, 168, 1, 42]);
+		assert_eq!(SockAddr::from_bytes(&buf).unwrap(), addr);
+	}
+
+	#[test_case]
+	fn sockaddr_in6_roundtrip() {
+		let addr = SockAddr {
+			port: 443,
+			addr: Address::IPv6([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+		};
+		let buf = addr.to_bytes().unwrap();
+		assert_eq!(buf.len(), size_of::<SockAddrIn6>());
+		assert_eq!(SockAddr::from_bytes(&buf).unwrap(), addr);
+	}
+
+	#[test_case]
+	fn sockaddr_invalid() {
+		assert!(SockAddr::from_bytes(&[]).is_err());
+		assert!(SockAddr::from_bytes(&[2, 0, 0]).is_err());
+	}
+}
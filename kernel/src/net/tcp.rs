@@ -17,13 +17,108 @@
  */
 
 //! The Transmission Control Protocol (TCP) is a protocol transmitting sequenced, reliable,
-//! two-way, connection-based byte streams.
+//! two-way, connection-based byte streams (RFC 9293).
+//!
+//! Each connection is attached to a [`Socket`], whose receive and transmit buffers respectively
+//! hold the data received in order but not yet read by userspace, and the data written by
+//! userspace but not yet acknowledged by the peer.
+//!
+//! Retransmissions and the `TIME-WAIT` state are handled by a kernel task, see [`timer_task`].
 
-use super::{buf::BufList, osi::Layer};
-use crate::file::socket::Socket;
-use utils::errno::EResult;
+use super::{
+	Address, SocketDesc, SocketDomain,
+	buf::BufList,
+	ip,
+	ip::PROTO_TCP,
+	osi::{Layer, Stack},
+	sockaddr::SockAddr,
+};
+use crate::{
+	arch::x86::sti,
+	crypto::{rand, rand::GRND_NONBLOCK},
+	file::{socket, socket::Socket},
+	memory::user::UserSlice,
+	sync::mutex::Mutex,
+	syscall::select::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLRDHUP, POLLRDNORM, POLLWRNORM},
+	time::{
+		clock::{Clock, current_time_ns},
+		sleep_for,
+		unit::Timestamp,
+	},
+};
+use core::{
+	any::Any,
+	cmp::{max, min},
+	ffi::c_int,
+	mem,
+	mem::size_of,
+	sync::atomic::{AtomicU16, Ordering::Relaxed},
+};
+use macros::AnyRepr;
+use utils::{
+	boxed::Box,
+	bytes::{as_bytes, from_bytes},
+	collections::{hashmap::HashMap, vec::Vec},
+	errno,
+	errno::{CollectResult, EResult, Errno},
+	ptr::arc::Arc,
+};
+
+/// Flag: no more data from sender
+const FLAG_FIN: u8 = 1 << 0;
+/// Flag: synchronize sequence numbers
+const FLAG_SYN: u8 = 1 << 1;
+/// Flag: reset the connection
+const FLAG_RST: u8 = 1 << 2;
+/// Flag: push function
+const FLAG_PSH: u8 = 1 << 3;
+/// Flag: the acknowledgment field is significant
+const FLAG_ACK: u8 = 1 << 4;
+/// Flag: the urgent pointer field is significant
+const FLAG_URG: u8 = 1 << 5;
+
+/// Option kind: end of option list
+const OPT_END: u8 = 0;
+/// Option kind: no-operation
+const OPT_NOP: u8 = 1;
+/// Option kind: maximum segment size
+const OPT_MSS: u8 = 2;
+
+/// The default Maximum Segment Size, used when the peer does not specify one.
+const DEFAULT_MSS: u16 = 536;
+/// The Maximum Segment Size advertised over IPv4 (Ethernet MTU minus IPv4 and TCP headers).
+const LOCAL_MSS_V4: u16 = 1460;
+/// The Maximum Segment Size advertised over IPv6 (Ethernet MTU minus IPv6 and TCP headers).
+const LOCAL_MSS_V6: u16 = 1440;
+
+/// The initial retransmission timeout, in nanoseconds (RFC 6298).
+const INITIAL_RTO: Timestamp = 1_000_000_000;
+/// The minimum retransmission timeout, in nanoseconds.
+const MIN_RTO: Timestamp = 200_000_000;
+/// The maximum retransmission timeout, in nanoseconds.
+const MAX_RTO: Timestamp = 120_000_000_000;
+/// The maximum number of retransmissions of a `SYN` before giving up.
+const MAX_SYN_RETRIES: u32 = 6;
+/// The maximum number of retransmissions of data before giving up.
+const MAX_RETRIES: u32 = 15;
+/// The Maximum Segment Lifetime, in nanoseconds. The `TIME-WAIT` state lasts twice this value.
+const MSL: Timestamp = 30_000_000_000;
+
+/// The idle time after which keepalive probes start being sent, in nanoseconds.
+const KEEPALIVE_TIME: Timestamp = 7_200_000_000_000;
+/// The interval between two keepalive probes, in nanoseconds.
+const KEEPALIVE_INTVL: Timestamp = 75_000_000_000;
+/// The number of unanswered keepalive probes after which the connection is dropped.
+const KEEPALIVE_PROBES: u32 = 9;
+
+/// The interval between two runs of the timer task, in nanoseconds.
+const TIMER_GRANULARITY: Timestamp = 100_000_000;
+
+/// The beginning of the range of ephemeral ports.
+const EPHEMERAL_PORT_BEGIN: u16 = 49152;
 
 /// The TCP segment header.
+#[derive(AnyRepr)]
 #[repr(C, packed)]
 pub struct TCPHdr {
 	/// Source port.
@@ -34,7 +129,8 @@ pub struct TCPHdr {
 	/// Sequence number.
 	seq_nbr: u32,
 
-	/// TODO doc
+	/// Acknowledgment number: the next sequence number the sender of the segment is expecting to
+	/// receive.
 	ack_nbr: u32,
 
 	/// The size of the header in units of 4 bytes.
@@ -43,29 +139,1768 @@ pub struct TCPHdr {
 	data_offset: u8,
 	/// The segment's flags.
 	flags: u8,
-	/// TODO doc
+	/// The number of bytes the sender of the segment is willing to accept.
 	win_size: u16,
 
-	/// TODO doc
+	/// The checksum of the header, payload and IP pseudo-header (RFC 1071).
 	checksum: u16,
-	/// TODO doc
+	/// Offset from the sequence number to the end of urgent data.
 	urg_ptr: u16,
 }
 
-/// The network layer for the TCP protocol.
+/// Returns the Maximum Segment Size to advertise for the given local address.
+fn local_mss(addr: &Address) -> u16 {
+	match addr {
+		Address::IPv4(_) => LOCAL_MSS_V4,
+		Address::IPv6(_) => LOCAL_MSS_V6,
+	}
+}
+
+/// Compares sequence numbers, taking wrapping into account.
+#[inline]
+fn seq_lt(a: u32, b: u32) -> bool {
+	(a.wrapping_sub(b) as i32) < 0
+}
+
+/// Compares sequence numbers, taking wrapping into account.
+#[inline]
+fn seq_le(a: u32, b: u32) -> bool {
+	(a.wrapping_sub(b) as i32) <= 0
+}
+
+/// Tells whether the sequence number `seq` is in the window beginning at `start`, of size `len`.
+#[inline]
+fn in_window(seq: u32, start: u32, len: u32) -> bool {
+	seq.wrapping_sub(start) < len
+}
+
+/// A received segment.
+struct Segment<'s> {
+	/// Source port.
+	src_port: u16,
+	/// Destination port.
+	dst_port: u16,
+	/// Sequence number.
+	seq: u32,
+	/// Acknowledgment number.
+	ack: u32,
+	/// Flags.
+	flags: u8,
+	/// Window size.
+	wnd: u16,
+	/// The Maximum Segment Size option, if present.
+	mss: Option<u16>,
+	/// The segment's payload.
+	data: &'s [u8],
+}
+
+impl<'s> Segment<'s> {
+	/// Parses the segment in the given buffer.
+	///
+	/// If the segment is invalid, the function returns `None`.
+	fn parse(buf: &'s [u8]) -> Option<Self> {
+		let hdr: &TCPHdr = from_bytes(buf)?;
+		let hdr_len = ((hdr.data_offset >> 4) as usize) * 4;
+		if hdr_len < size_of::<TCPHdr>() || hdr_len > buf.len() {
+			return None;
+		}
+		// Parse options
+		let mut mss = None;
+		let mut opts = &buf[size_of::<TCPHdr>()..hdr_len];
+		while let Some((kind, rest)) = opts.split_first() {
+			match *kind {
+				OPT_END => break,
+				OPT_NOP => {
+					opts = rest;
+					continue;
+				}
+				_ => {}
+			}
+			let len = *rest.first()? as usize;
+			if len < 2 || len > opts.len() {
+				return None;
+			}
+			if *kind == OPT_MSS && len == 4 {
+				mss = Some(u16::from_be_bytes([opts[2], opts[3]]));
+			}
+			opts = &opts[len..];
+		}
+		Some(Self {
+			src_port: u16::from_be(hdr.src_port),
+			dst_port: u16::from_be(hdr.dst_port),
+			seq: u32::from_be(hdr.seq_nbr),
+			ack: u32::from_be(hdr.ack_nbr),
+			flags: hdr.flags,
+			wnd: u16::from_be(hdr.win_size),
+			mss,
+			data: &buf[hdr_len..],
+		})
+	}
+
+	/// Tells whether the segment has the given flag.
+	#[inline]
+	fn has(&self, flag: u8) -> bool {
+		self.flags & flag != 0
+	}
+
+	/// Returns the length of the segment in sequence space.
+	fn len(&self) -> u32 {
+		self.data.len() as u32 + self.has(FLAG_SYN) as u32 + self.has(FLAG_FIN) as u32
+	}
+}
+
+/// The state of a TCP connection (RFC 9293 section 3.3.2).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+	/// No connection.
+	Closed,
+	/// Waiting for a connection request.
+	Listen,
+	/// Waiting for a matching connection request after having sent one.
+	SynSent,
+	/// Waiting for a confirming acknowledgment after having both received and sent a connection
+	/// request.
+	SynReceived,
+	/// The connection is open.
+	Established,
+	/// Waiting for a termination request from the remote, or an acknowledgment of the termination
+	/// request previously sent.
+	FinWait1,
+	/// Waiting for a termination request from the remote.
+	FinWait2,
+	/// Waiting for a termination request from the local user.
+	CloseWait,
+	/// Waiting for a termination request acknowledgment from the remote.
+	Closing,
+	/// Waiting for an acknowledgment of the termination request previously sent to the remote.
+	LastAck,
+	/// Waiting for enough time to pass to be sure the remote received the acknowledgment of its
+	/// termination request.
+	TimeWait,
+}
+
+/// Transmission Control Block, the state of a connection.
 #[derive(Debug)]
-pub struct TCPLayer {}
+pub struct Tcb {
+	/// The state of the connection.
+	state: State,
+	/// The local endpoint.
+	local: SockAddr,
+	/// The remote endpoint.
+	remote: SockAddr,
+
+	/// Initial send sequence number.
+	iss: u32,
+	/// Oldest unacknowledged sequence number.
+	snd_una: u32,
+	/// Next sequence number to be sent.
+	snd_nxt: u32,
+	/// Highest sequence number sent so far, plus one.
+	snd_max: u32,
+	/// The send window advertised by the remote.
+	snd_wnd: u32,
+	/// Sequence number of the segment used for the last window update.
+	snd_wl1: u32,
+	/// Acknowledgment number of the segment used for the last window update.
+	snd_wl2: u32,
+	/// The maximum size of segments to be sent.
+	snd_mss: u32,
+	/// Congestion window (RFC 5681).
+	cwnd: u32,
+	/// Slow start threshold (RFC 5681).
+	ssthresh: u32,
+	/// The number of duplicate acknowledgments received in a row.
+	dup_acks: u32,
+	/// Tells whether the transmit side has been closed by the user, meaning a `FIN` must be sent
+	/// after the remaining data.
+	fin_pending: bool,
+	/// Tells whether the remote acknowledged the `FIN`.
+	fin_acked: bool,
+
+	/// Initial receive sequence number.
+	irs: u32,
+	/// Next sequence number expected to be received.
+	rcv_nxt: u32,
+	/// The last receive window advertised to the remote.
+	rcv_adv: u32,
+	/// Tells whether the remote closed its transmit side.
+	fin_received: bool,
+
+	/// Retransmission timeout, in nanoseconds.
+	rto: Timestamp,
+	/// Smoothed round-trip time, in nanoseconds.
+	srtt: Option<Timestamp>,
+	/// Round-trip time variation, in nanoseconds.
+	rttvar: Timestamp,
+	/// The sequence number being timed to measure the round-trip time, along with the timestamp
+	/// at which it has been sent.
+	rtt_sample: Option<(u32, Timestamp)>,
+	/// The timestamp at which the retransmission timer expires.
+	retransmit_at: Option<Timestamp>,
+	/// The number of retransmissions in a row.
+	retries: u32,
+	/// The timestamp at which the `TIME-WAIT` state ends.
+	time_wait_until: Option<Timestamp>,
+
+	/// The timestamp at which the last segment has been received.
+	last_recv: Timestamp,
+	/// The number of keepalive probes sent since the last received segment.
+	keepalive_probes: u32,
+	/// The pending error on the connection.
+	error: Option<Errno>,
+
+	/// The maximum number of pending connections (for listening sockets).
+	backlog: usize,
+	/// The number of connections being established (for listening sockets).
+	half_open: usize,
+	/// Established connections waiting to be accepted (for listening sockets).
+	accept_queue: Vec<Arc<Socket>>,
+	/// The listening socket which received the connection request, until the connection is
+	/// established.
+	listener: Option<Arc<Socket>>,
+}
+
+impl Tcb {
+	/// Creates a closed connection with the given endpoints.
+	fn new(local: SockAddr, remote: SockAddr) -> Self {
+		let iss = gen_isn();
+		Self {
+			state: State::Closed,
+			local,
+			remote,
+
+			iss,
+			snd_una: iss,
+			snd_nxt: iss,
+			snd_max: iss,
+			snd_wnd: 0,
+			snd_wl1: 0,
+			snd_wl2: 0,
+			snd_mss: min(DEFAULT_MSS, local_mss(&local.addr)) as _,
+			cwnd: 0,
+			ssthresh: u32::MAX,
+			dup_acks: 0,
+			fin_pending: false,
+			fin_acked: false,
+
+			irs: 0,
+			rcv_nxt: 0,
+			rcv_adv: 0,
+			fin_received: false,
+
+			rto: INITIAL_RTO,
+			srtt: None,
+			rttvar: 0,
+			rtt_sample: None,
+			retransmit_at: None,
+			retries: 0,
+			time_wait_until: None,
+
+			last_recv: current_time_ns(Clock::Monotonic),
+			keepalive_probes: 0,
+			error: None,
+
+			backlog: 0,
+			half_open: 0,
+			accept_queue: Vec::new(),
+			listener: None,
+		}
+	}
+
+	/// Returns the state of the connection.
+	#[inline]
+	pub fn state(&self) -> State {
+		self.state
+	}
+
+	/// Sets the Maximum Segment Size from the value advertised by the remote.
+	fn set_mss(&mut self, mss: Option<u16>) {
+		let mss = mss.unwrap_or(DEFAULT_MSS);
+		self.snd_mss = max(min(mss, local_mss(&self.local.addr)), 1) as _;
+		// Initial window (RFC 5681 section 3.1)
+		self.cwnd = match self.snd_mss {
+			mss if mss > 2190 => 2 * mss,
+			mss if mss > 1095 => 3 * mss,
+			mss => 4 * mss,
+		};
+	}
+
+	/// Updates the retransmission timeout with the given round-trip time sample (RFC 6298).
+	fn update_rtt(&mut self, rtt: Timestamp) {
+		let srtt = match self.srtt {
+			None => {
+				self.rttvar = rtt / 2;
+				rtt
+			}
+			Some(srtt) => {
+				self.rttvar = (3 * self.rttvar + srtt.abs_diff(rtt)) / 4;
+				(7 * srtt + rtt) / 8
+			}
+		};
+		self.srtt = Some(srtt);
+		self.rto = (srtt + max(TIMER_GRANULARITY, 4 * self.rttvar)).clamp(MIN_RTO, MAX_RTO);
+	}
+}
+
+/// The transport layer for the TCP protocol.
+#[derive(Debug)]
+pub struct TCPLayer {
+	/// The connection's state.
+	pub tcb: Tcb,
+}
 
 impl Layer for TCPLayer {
-	fn transmit<'c, F>(&self, _buff: BufList<'c>, _next: F) -> EResult<()>
-	where
-		F: Fn(BufList<'c>) -> EResult<()>,
+	fn transmit(
+		&self,
+		buff: BufList<'_>,
+		next: &dyn Fn(BufList<'_>) -> EResult<()>,
+	) -> EResult<()> {
+		// Data segment at the current position in the stream
+		let tcb = &self.tcb;
+		let mut hdr = TCPHdr {
+			src_port: tcb.local.port.to_be(),
+			dst_port: tcb.remote.port.to_be(),
+			seq_nbr: tcb.snd_nxt.to_be(),
+			ack_nbr: tcb.rcv_nxt.to_be(),
+			data_offset: ((size_of::<TCPHdr>() / 4) as u8) << 4,
+			flags: FLAG_ACK | FLAG_PSH,
+			win_size: (min(tcb.rcv_adv, u16::MAX as u32) as u16).to_be(),
+			checksum: 0,
+			urg_ptr: 0,
+		};
+		// Only a single buffer is supported for the payload
+		if buff.next().is_some() {
+			return Err(errno!(EINVAL));
+		}
+		hdr.checksum = ip::transport_checksum(
+			&tcb.local.addr,
+			&tcb.remote.addr,
+			PROTO_TCP,
+			&[as_bytes(&hdr), buff.data],
+		);
+		let mut buff = buff;
+		let buff = buff.push_front(as_bytes(&hdr).into());
+		next(buff)
+	}
+}
+
+/// Connection identifier: the local and remote endpoints.
+type ConnId = (SockAddr, SockAddr);
+
+/// Connections (either being established, established or being closed), by endpoints.
+static CONNECTIONS: Mutex<HashMap<ConnId, Arc<Socket>>> = Mutex::new(HashMap::new());
+/// Listening sockets, by local endpoint.
+static LISTENERS: Mutex<HashMap<SockAddr, Arc<Socket>>> = Mutex::new(HashMap::new());
+
+/// The next ephemeral port to try.
+static NEXT_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORT_BEGIN);
+
+/// Generates an initial sequence number.
+fn gen_isn() -> u32 {
+	let mut buf = [0u8; 4];
+	let _ = rand::getrandom(UserSlice::from_slice_mut(&mut buf), GRND_NONBLOCK);
+	// Mix with a clock ticking every 4 microseconds (RFC 9293 section 3.4.1)
+	let clk = (current_time_ns(Clock::Monotonic) / 4000) as u32;
+	u32::from_ne_bytes(buf).wrapping_add(clk)
+}
+
+/// Tells whether the given local port is used by a connection or a listening socket.
+fn is_port_used(port: u16) -> bool {
+	LISTENERS.lock().iter().any(|(local, _)| local.port == port)
+		|| CONNECTIONS
+			.lock()
+			.iter()
+			.any(|((local, _), _)| local.port == port)
+}
+
+/// Allocates an ephemeral port.
+///
+/// If no port is available, the function returns [`errno::EADDRNOTAVAIL`].
+fn alloc_port() -> EResult<u16> {
+	let count = u16::MAX - EPHEMERAL_PORT_BEGIN + 1;
+	for _ in 0..count {
+		let port = NEXT_PORT
+			.fetch_update(Relaxed, Relaxed, |p| {
+				Some(p.checked_add(1).unwrap_or(EPHEMERAL_PORT_BEGIN))
+			})
+			.unwrap();
+		if !is_port_used(port) {
+			return Ok(port);
+		}
+	}
+	Err(errno!(EADDRNOTAVAIL))
+}
+
+/// Returns the local endpoint the socket is bound to, or `None` if not bound.
+fn get_bound_addr(sock: &Socket) -> EResult<Option<SockAddr>> {
+	let sockname = sock.get_sockname().lock();
+	if sockname.is_empty() {
+		return Ok(None);
+	}
+	SockAddr::from_bytes(&sockname).map(Some)
+}
+
+/// Sets the socket's name to the given local endpoint.
+fn set_bound_addr(sock: &Socket, local: &SockAddr) -> EResult<()> {
+	*sock.get_sockname().lock() = local.to_bytes()?;
+	Ok(())
+}
+
+/// Builds a segment and transmits it through `domain`.
+///
+/// Arguments:
+/// - `local` and `remote` are the endpoints of the connection.
+/// - `seq` and `ack` are the sequence and acknowledgment numbers.
+/// - `flags` are the segment's flags.
+/// - `wnd` is the window to advertise.
+/// - `mss` is the value of the Maximum Segment Size option to send, if any.
+/// - `data` is the payload.
+#[allow(clippy::too_many_arguments)]
+fn send_segment(
+	domain: &dyn Layer,
+	local: &SockAddr,
+	remote: &SockAddr,
+	seq: u32,
+	ack: u32,
+	flags: u8,
+	wnd: u16,
+	mss: Option<u16>,
+	data: &[u8],
+) -> EResult<()> {
+	let mut hdr_buf = [0u8; size_of::<TCPHdr>() + 4];
+	let hdr_len = size_of::<TCPHdr>() + mss.map(|_| 4).unwrap_or(0);
+	let hdr = TCPHdr {
+		src_port: local.port.to_be(),
+		dst_port: remote.port.to_be(),
+		seq_nbr: seq.to_be(),
+		ack_nbr: ack.to_be(),
+		data_offset: ((hdr_len / 4) as u8) << 4,
+		flags,
+		win_size: wnd.to_be(),
+		checksum: 0,
+		urg_ptr: 0,
+	};
+	hdr_buf[..size_of::<TCPHdr>()].copy_from_slice(as_bytes(&hdr));
+	if let Some(mss) = mss {
+		let [hi, lo] = mss.to_be_bytes();
+		hdr_buf[size_of::<TCPHdr>()..].copy_from_slice(&[OPT_MSS, 4, hi, lo]);
+	}
+	let hdr_buf = &mut hdr_buf[..hdr_len];
+	let checksum = ip::transport_checksum(&local.addr, &remote.addr, PROTO_TCP, &[hdr_buf, data]);
+	hdr_buf[16..18].copy_from_slice(&checksum.to_ne_bytes());
+	if data.is_empty() {
+		domain.transmit(BufList::from(&*hdr_buf), &super::transmit)
+	} else {
+		let mut buff = BufList::from(data);
+		let buff = buff.push_front(BufList::from(&*hdr_buf));
+		domain.transmit(buff, &super::transmit)
+	}
+}
+
+/// Sends a reset in response to the given segment, which does not belong to any connection.
+fn send_reset(local: &SockAddr, remote: &SockAddr, seg: &Segment) -> EResult<()> {
+	let domain = ip::build_layer(PROTO_TCP, local.addr, remote.addr)?;
+	if seg.has(FLAG_ACK) {
+		send_segment(&*domain, local, remote, seg.ack, 0, FLAG_RST, 0, None, &[])
+	} else {
+		let ack = seg.seq.wrapping_add(seg.len());
+		send_segment(
+			&*domain,
+			local,
+			remote,
+			0,
+			ack,
+			FLAG_RST | FLAG_ACK,
+			0,
+			None,
+			&[],
+		)
+	}
+}
+
+/// A connection, with its socket's stack locked.
+struct Conn<'s> {
+	/// The connection's socket.
+	sock: &'s Socket,
+	/// The network layer.
+	domain: &'s dyn Layer,
+	/// The connection's state.
+	tcb: &'s mut Tcb,
+
+	/// If set, the connection must be removed from the connections table once the stack is
+	/// unlocked.
+	remove: bool,
+	/// If set, the listening socket must be notified once the stack is unlocked. The boolean
+	/// tells whether the connection has been established.
+	notify_listener: Option<(Arc<Socket>, bool)>,
+}
+
+impl Conn<'_> {
+	/// Returns the current receive window.
+	fn rcv_wnd(&self) -> u32 {
+		self.sock
+			.rx_buff()
+			.lock()
+			.as_ref()
+			.map(|b| min(b.get_available_len(), u16::MAX as usize) as u32)
+			// Reception has been shut down, keep accepting data to discard it
+			.unwrap_or(u16::MAX as _)
+	}
+
+	/// Sends a segment with the given parameters, acknowledging received data.
+	fn send(&mut self, seq: u32, flags: u8, data: &[u8]) -> EResult<()> {
+		let wnd = self.rcv_wnd();
+		self.tcb.rcv_adv = wnd;
+		let flags = if self.tcb.state == State::SynSent {
+			flags
+		} else {
+			flags | FLAG_ACK
+		};
+		let mss = (flags & FLAG_SYN != 0).then_some(local_mss(&self.tcb.local.addr));
+		send_segment(
+			self.domain,
+			&self.tcb.local,
+			&self.tcb.remote,
+			seq,
+			self.tcb.rcv_nxt,
+			flags,
+			wnd as _,
+			mss,
+			data,
+		)
+	}
+
+	/// Sends an acknowledgment.
+	fn send_ack(&mut self) {
+		let _ = self.send(self.tcb.snd_nxt, FLAG_ACK, &[]);
+	}
+
+	/// Sends (or retransmits) the connection request (`SYN`), or its acknowledgment in the
+	/// `SYN-RECEIVED` state.
+	fn send_syn(&mut self, now: Timestamp) {
+		let _ = self.send(self.tcb.iss, FLAG_SYN, &[]);
+		self.tcb.snd_nxt = self.tcb.iss.wrapping_add(1);
+		self.tcb.snd_max = self.tcb.snd_nxt;
+		if self.tcb.retransmit_at.is_none() {
+			self.tcb.retransmit_at = Some(now + self.tcb.rto);
+		}
+		if self.tcb.retries == 0 && self.tcb.rtt_sample.is_none() {
+			self.tcb.rtt_sample = Some((self.tcb.snd_nxt, now));
+		}
+	}
+
+	/// Sends pending data, and the `FIN` if the transmit side is closed.
+	///
+	/// If `probe` is set, a byte is sent even if the send window is closed.
+	///
+	/// The function returns `true` if at least one segment has been sent.
+	fn output(&mut self, probe: bool) -> bool {
+		if !matches!(
+			self.tcb.state,
+			State::Established
+				| State::CloseWait
+				| State::FinWait1
+				| State::Closing
+				| State::LastAck
+		) {
+			return false;
+		}
+		let now = current_time_ns(Clock::Monotonic);
+		let nodelay = self.sock.opts().lock().tcp_nodelay;
+		let mut sent = false;
+		let mut tx = self.sock.tx_buff().lock();
+		let data_len = tx.as_ref().map(|b| b.get_data_len()).unwrap_or(0);
+		let data_end = self.tcb.snd_una.wrapping_add(data_len as u32);
+		let mut remaining;
+		loop {
+			let tcb = &mut *self.tcb;
+			let in_flight = tcb.snd_nxt.wrapping_sub(tcb.snd_una);
+			// Offset of the first byte to send in the transmit buffer
+			let off = in_flight as usize;
+			remaining = data_len.saturating_sub(off);
+			let mut usable = min(tcb.snd_wnd, tcb.cwnd).saturating_sub(in_flight);
+			if probe && !sent && usable == 0 {
+				usable = 1;
+			}
+			let mut len = min(min(remaining, usable as usize), tcb.snd_mss as usize);
+			// Nagle's algorithm (RFC 896): do not send small segments while data is in flight
+			if !nodelay && len < tcb.snd_mss as usize && len < remaining && in_flight > 0 {
+				len = 0;
+			}
+			let fin = tcb.fin_pending
+				&& !tcb.fin_acked
+				&& len == remaining
+				&& seq_le(tcb.snd_nxt.wrapping_add(len as u32), data_end)
+				&& seq_le(tcb.snd_nxt, data_end);
+			if len == 0 && !fin {
+				break;
+			}
+			// Read data
+			let mut data = Vec::new();
+			if len > 0 {
+				if data.resize(len, 0).is_err() {
+					break;
+				}
+				if let Some(tx) = tx.as_mut() {
+					let _ = tx.peek_at(off, UserSlice::from_slice_mut(&mut data));
+				}
+			}
+			let mut flags = FLAG_ACK;
+			if len > 0 && len == remaining {
+				flags |= FLAG_PSH;
+			}
+			if fin {
+				flags |= FLAG_FIN;
+			}
+			let seq = tcb.snd_nxt;
+			if self.send(seq, flags, &data).is_err() {
+				break;
+			}
+			sent = true;
+			let tcb = &mut *self.tcb;
+			tcb.snd_nxt = seq.wrapping_add(len as u32 + fin as u32);
+			// Time the segment if it is not a retransmission (Karn's algorithm)
+			if seq_le(tcb.snd_max, seq) && tcb.rtt_sample.is_none() {
+				tcb.rtt_sample = Some((tcb.snd_nxt, now));
+			}
+			if seq_lt(tcb.snd_max, tcb.snd_nxt) {
+				tcb.snd_max = tcb.snd_nxt;
+			}
+			if tcb.retransmit_at.is_none() {
+				tcb.retransmit_at = Some(now + tcb.rto);
+			}
+			if fin {
+				break;
+			}
+		}
+		// Arm the persist timer if the remote closed its window
+		let tcb = &mut *self.tcb;
+		if tcb.retransmit_at.is_none() && remaining > 0 {
+			tcb.retransmit_at = Some(now + tcb.rto);
+		}
+		sent
+	}
+
+	/// Closes the connection, setting the given error, if any.
+	fn close(&mut self, err: Option<Errno>) {
+		let tcb = &mut *self.tcb;
+		tcb.state = State::Closed;
+		if err.is_some() {
+			tcb.error = err;
+		}
+		tcb.retransmit_at = None;
+		tcb.time_wait_until = None;
+		if let Some(listener) = tcb.listener.take() {
+			self.notify_listener = Some((listener, false));
+		}
+		self.remove = true;
+		self.sock.rx_queue().wake_all();
+		self.sock.tx_queue().wake_all();
+	}
+
+	/// Sends a reset to the remote and closes the connection.
+	fn reset(&mut self, err: Option<Errno>) {
+		if matches!(
+			self.tcb.state,
+			State::SynReceived
+				| State::Established
+				| State::FinWait1
+				| State::FinWait2
+				| State::CloseWait
+		) {
+			let _ = self.send(self.tcb.snd_nxt, FLAG_RST, &[]);
+		}
+		self.close(err);
+	}
+
+	/// Enters the `TIME-WAIT` state.
+	fn time_wait(&mut self, now: Timestamp) {
+		self.tcb.state = State::TimeWait;
+		self.tcb.retransmit_at = None;
+		self.tcb.time_wait_until = Some(now + 2 * MSL);
+		self.sock.tx_queue().wake_all();
+	}
+
+	/// Closes the transmit side of the connection.
+	fn shutdown(&mut self) {
+		match self.tcb.state {
+			State::SynSent => self.close(None),
+			State::SynReceived | State::Established => {
+				self.tcb.fin_pending = true;
+				self.tcb.state = State::FinWait1;
+			}
+			State::CloseWait => {
+				self.tcb.fin_pending = true;
+				self.tcb.state = State::LastAck;
+			}
+			_ => return,
+		}
+		self.output(false);
+	}
+
+	/// Handles the expiry of timers.
+	fn on_timer(&mut self, now: Timestamp) {
+		if let Some(ts) = self.tcb.time_wait_until {
+			if ts <= now {
+				self.close(None);
+			}
+			return;
+		}
+		let Some(ts) = self.tcb.retransmit_at else {
+			self.keepalive(now);
+			return;
+		};
+		if ts > now {
+			return;
+		}
+		let tcb = &mut *self.tcb;
+		tcb.retransmit_at = None;
+		tcb.retries += 1;
+		let syn = matches!(tcb.state, State::SynSent | State::SynReceived);
+		let max_retries = if syn { MAX_SYN_RETRIES } else { MAX_RETRIES };
+		if tcb.retries > max_retries {
+			self.reset(Some(errno!(ETIMEDOUT)));
+			return;
+		}
+		// Exponential backoff
+		tcb.rto = min(tcb.rto * 2, MAX_RTO);
+		tcb.rtt_sample = None;
+		if syn {
+			self.send_syn(now);
+			return;
+		}
+		// Retransmit from the oldest unacknowledged segment (RFC 5681 section 3.1)
+		let flight = tcb.snd_max.wrapping_sub(tcb.snd_una);
+		if flight > 0 {
+			tcb.ssthresh = max(flight / 2, 2 * tcb.snd_mss);
+			tcb.cwnd = tcb.snd_mss;
+		}
+		tcb.snd_nxt = tcb.snd_una;
+		let probe = tcb.snd_wnd == 0;
+		self.output(probe);
+	}
+
+	/// Sends a keepalive probe if the connection has been idle for too long, or drops it if the
+	/// remote did not answer previous probes.
+	fn keepalive(&mut self, now: Timestamp) {
+		let tcb = &mut *self.tcb;
+		if !matches!(tcb.state, State::Established | State::CloseWait)
+			|| tcb.snd_max != tcb.snd_una
+			|| !self.sock.opts().lock().keepalive
+		{
+			return;
+		}
+		let next = KEEPALIVE_TIME + tcb.keepalive_probes as Timestamp * KEEPALIVE_INTVL;
+		if now.saturating_sub(tcb.last_recv) < next {
+			return;
+		}
+		if tcb.keepalive_probes >= KEEPALIVE_PROBES {
+			self.reset(Some(errno!(ETIMEDOUT)));
+			return;
+		}
+		tcb.keepalive_probes += 1;
+		// An already acknowledged sequence number forces the remote to answer
+		let seq = tcb.snd_una.wrapping_sub(1);
+		let _ = self.send(seq, FLAG_ACK, &[]);
+	}
+
+	/// Processes an acknowledgment.
+	///
+	/// If the segment must be dropped, the function returns `false`.
+	fn process_ack(&mut self, seg: &Segment, now: Timestamp) -> bool {
+		let tcb = &mut *self.tcb;
+		if seq_lt(tcb.snd_max, seg.ack) {
+			// Acknowledges something not yet sent
+			self.send_ack();
+			return false;
+		}
+		if seq_lt(tcb.snd_una, seg.ack) {
+			let acked = seg.ack.wrapping_sub(tcb.snd_una);
+			{
+				let mut tx = self.sock.tx_buff().lock();
+				let data_len = tx.as_ref().map(|b| b.get_data_len()).unwrap_or(0);
+				if let Some(tx) = tx.as_mut() {
+					tx.discard(acked as _);
+				}
+				if tcb.fin_pending && acked as usize > data_len {
+					tcb.fin_acked = true;
+				}
+			}
+			tcb.snd_una = seg.ack;
+			if seq_lt(tcb.snd_nxt, tcb.snd_una) {
+				tcb.snd_nxt = tcb.snd_una;
+			}
+			if let Some((seq, ts)) = tcb.rtt_sample {
+				if seq_le(seq, seg.ack) {
+					tcb.update_rtt(now - ts);
+					tcb.rtt_sample = None;
+				}
+			}
+			tcb.retries = 0;
+			tcb.retransmit_at = (tcb.snd_una != tcb.snd_max).then(|| now + tcb.rto);
+			// Congestion control (RFC 5681 section 3.1)
+			if tcb.cwnd < tcb.ssthresh {
+				tcb.cwnd = tcb.cwnd.saturating_add(min(acked, tcb.snd_mss));
+			} else {
+				let inc = max(tcb.snd_mss * tcb.snd_mss / max(tcb.cwnd, 1), 1);
+				tcb.cwnd = tcb.cwnd.saturating_add(inc);
+			}
+			tcb.dup_acks = 0;
+			self.sock.tx_queue().wake_all();
+		} else if seg.ack == tcb.snd_una
+			&& seg.data.is_empty()
+			&& tcb.snd_una != tcb.snd_max
+			&& seg.wnd as u32 == tcb.snd_wnd
+		{
+			// Duplicate acknowledgment, fast retransmit (RFC 5681 section 3.2)
+			tcb.dup_acks += 1;
+			if tcb.dup_acks == 3 {
+				let flight = tcb.snd_max.wrapping_sub(tcb.snd_una);
+				tcb.ssthresh = max(flight / 2, 2 * tcb.snd_mss);
+				tcb.cwnd = tcb.ssthresh;
+				tcb.rtt_sample = None;
+				let nxt = tcb.snd_nxt;
+				tcb.snd_nxt = tcb.snd_una;
+				let cwnd = mem::replace(&mut tcb.cwnd, tcb.snd_mss);
+				self.output(false);
+				self.tcb.cwnd = cwnd;
+				if seq_lt(self.tcb.snd_nxt, nxt) {
+					self.tcb.snd_nxt = nxt;
+				}
+			}
+		}
+		// Window update
+		let tcb = &mut *self.tcb;
+		if seq_lt(tcb.snd_wl1, seg.seq) || (tcb.snd_wl1 == seg.seq && seq_le(tcb.snd_wl2, seg.ack))
+		{
+			tcb.snd_wnd = seg.wnd as _;
+			tcb.snd_wl1 = seg.seq;
+			tcb.snd_wl2 = seg.ack;
+		}
+		true
+	}
+
+	/// Handles a segment received in the `SYN-SENT` state.
+	fn syn_sent_input(&mut self, seg: &Segment, now: Timestamp) {
+		let tcb = &mut *self.tcb;
+		let ack_ok = seg.has(FLAG_ACK);
+		if ack_ok && (seq_le(seg.ack, tcb.iss) || seq_lt(tcb.snd_max, seg.ack)) {
+			if !seg.has(FLAG_RST) {
+				let _ = send_segment(
+					self.domain,
+					&tcb.local,
+					&tcb.remote,
+					seg.ack,
+					0,
+					FLAG_RST,
+					0,
+					None,
+					&[],
+				);
+			}
+			return;
+		}
+		if seg.has(FLAG_RST) {
+			if ack_ok {
+				self.close(Some(errno!(ECONNREFUSED)));
+			}
+			return;
+		}
+		if !seg.has(FLAG_SYN) {
+			return;
+		}
+		tcb.irs = seg.seq;
+		tcb.rcv_nxt = seg.seq.wrapping_add(1);
+		tcb.set_mss(seg.mss);
+		tcb.snd_wnd = seg.wnd as _;
+		tcb.snd_wl1 = seg.seq;
+		tcb.snd_wl2 = seg.ack;
+		if ack_ok {
+			tcb.snd_una = seg.ack;
+		}
+		if seq_lt(tcb.iss, tcb.snd_una) {
+			tcb.state = State::Established;
+			tcb.retransmit_at = None;
+			tcb.retries = 0;
+			if let Some((_, ts)) = tcb.rtt_sample.take() {
+				tcb.update_rtt(now - ts);
+			}
+			self.send_ack();
+			self.sock.tx_queue().wake_all();
+		} else {
+			// Simultaneous open
+			tcb.state = State::SynReceived;
+			tcb.retransmit_at = None;
+			self.send_syn(now);
+		}
+	}
+
+	/// Handles a received segment (RFC 9293 section 3.10.7).
+	fn input(&mut self, seg: &Segment) {
+		let now = current_time_ns(Clock::Monotonic);
+		match self.tcb.state {
+			State::Closed | State::Listen => return,
+			State::SynSent => {
+				self.syn_sent_input(seg, now);
+				return;
+			}
+			_ => {}
+		}
+		self.tcb.last_recv = now;
+		self.tcb.keepalive_probes = 0;
+		// Check sequence number
+		let rcv_wnd = max(self.rcv_wnd(), 1);
+		let rcv_nxt = self.tcb.rcv_nxt;
+		let acceptable = if seg.len() == 0 {
+			in_window(seg.seq, rcv_nxt, rcv_wnd)
+		} else {
+			in_window(seg.seq, rcv_nxt, rcv_wnd)
+				|| in_window(seg.seq.wrapping_add(seg.len() - 1), rcv_nxt, rcv_wnd)
+		};
+		if !acceptable {
+			if !seg.has(FLAG_RST) {
+				self.send_ack();
+			}
+			return;
+		}
+		// Check RST
+		if seg.has(FLAG_RST) {
+			if seg.seq != rcv_nxt {
+				// Challenge acknowledgment (RFC 5961 section 3.2)
+				self.send_ack();
+				return;
+			}
+			let err = match self.tcb.state {
+				State::SynReceived if self.tcb.listener.is_some() => None,
+				State::SynReceived => Some(errno!(ECONNREFUSED)),
+				State::Established | State::FinWait1 | State::FinWait2 | State::CloseWait => {
+					Some(errno!(ECONNRESET))
+				}
+				_ => None,
+			};
+			self.close(err);
+			return;
+		}
+		// Check SYN: challenge acknowledgment (RFC 5961 section 4.2)
+		if seg.has(FLAG_SYN) {
+			self.send_ack();
+			return;
+		}
+		// Check ACK
+		if !seg.has(FLAG_ACK) {
+			return;
+		}
+		if self.tcb.state == State::SynReceived {
+			let tcb = &mut *self.tcb;
+			if seq_lt(tcb.snd_una, seg.ack) && seq_le(seg.ack, tcb.snd_max) {
+				tcb.state = State::Established;
+				// The SYN is acknowledged
+				tcb.snd_una = tcb.iss.wrapping_add(1);
+				tcb.retransmit_at = None;
+				tcb.retries = 0;
+				if let Some((_, ts)) = tcb.rtt_sample.take() {
+					tcb.update_rtt(now - ts);
+				}
+				tcb.snd_wnd = seg.wnd as _;
+				tcb.snd_wl1 = seg.seq;
+				tcb.snd_wl2 = seg.ack;
+				if let Some(listener) = tcb.listener.take() {
+					self.notify_listener = Some((listener, true));
+				}
+				self.sock.tx_queue().wake_all();
+			} else {
+				let _ = send_segment(
+					self.domain,
+					&tcb.local,
+					&tcb.remote,
+					seg.ack,
+					0,
+					FLAG_RST,
+					0,
+					None,
+					&[],
+				);
+				return;
+			}
+		}
+		if !self.process_ack(seg, now) {
+			return;
+		}
+		match self.tcb.state {
+			State::FinWait1 if self.tcb.fin_acked => self.tcb.state = State::FinWait2,
+			State::Closing if self.tcb.fin_acked => self.time_wait(now),
+			State::LastAck if self.tcb.fin_acked => {
+				self.close(None);
+				return;
+			}
+			_ => {}
+		}
+		let mut need_ack = false;
+		// Process data
+		let mut data_end = seg.seq.wrapping_add(seg.data.len() as u32);
+		if matches!(
+			self.tcb.state,
+			State::Established | State::FinWait1 | State::FinWait2
+		) && !seg.data.is_empty()
+		{
+			if seq_lt(self.tcb.rcv_nxt, seg.seq) {
+				// Out of order segment, drop and send a duplicate acknowledgment
+				self.send_ack();
+				return;
+			}
+			let skip = min(
+				self.tcb.rcv_nxt.wrapping_sub(seg.seq) as usize,
+				seg.data.len(),
+			);
+			let data = &seg.data[skip..];
+			let len = {
+				let mut rx = self.sock.rx_buff().lock();
+				match rx.as_mut() {
+					Some(rx) => {
+						let buf = unsafe { UserSlice::from_slice(data) };
+						rx.write(buf).unwrap_or(0)
+					}
+					// Reception has been shut down, discard
+					None => data.len(),
+				}
+			};
+			self.tcb.rcv_nxt = self.tcb.rcv_nxt.wrapping_add(len as u32);
+			if len < data.len() {
+				// The buffer is full, the rest is dropped (including the FIN)
+				data_end = self.tcb.rcv_nxt.wrapping_sub(1);
+			}
+			need_ack = true;
+			if len > 0 {
+				self.sock.rx_queue().wake_all();
+			}
+		}
+		// Process FIN
+		if seg.has(FLAG_FIN) && data_end == self.tcb.rcv_nxt {
+			self.tcb.rcv_nxt = self.tcb.rcv_nxt.wrapping_add(1);
+			self.tcb.fin_received = true;
+			need_ack = true;
+			match (self.tcb.state, self.tcb.fin_acked) {
+				(State::SynReceived | State::Established, _) => self.tcb.state = State::CloseWait,
+				(State::FinWait1, true) | (State::FinWait2, _) => self.time_wait(now),
+				(State::FinWait1, false) => self.tcb.state = State::Closing,
+				_ => {}
+			}
+			self.sock.rx_queue().wake_all();
+		}
+		if !self.output(false) && need_ack {
+			self.send_ack();
+		}
+	}
+}
+
+/// Executes `f` on the connection of the given socket.
+///
+/// If the socket has no TCP stack, the function returns `None`.
+fn with_conn<R, F: FnOnce(&mut Conn) -> R>(sock: &Socket, f: F) -> Option<R> {
+	let (res, id, remove, notify_listener) = {
+		let mut stack = sock.stack().lock();
+		let Stack {
+			domain,
+			protocol,
+		} = stack.as_mut()?;
+		let layer = (protocol.as_mut() as &mut dyn Any).downcast_mut::<TCPLayer>()?;
+		let mut conn = Conn {
+			sock,
+			domain: domain.as_ref(),
+			tcb: &mut layer.tcb,
+
+			remove: false,
+			notify_listener: None,
+		};
+		let res = f(&mut conn);
+		let id = (conn.tcb.local, conn.tcb.remote);
+		(res, id, conn.remove, conn.notify_listener)
+	};
+	// Handle events with the stack unlocked to avoid deadlocks
+	if let Some((listener, established)) = notify_listener {
+		let this = CONNECTIONS.lock().get(&id).cloned();
+		let queued = with_tcb(&listener, |tcb| {
+			if tcb.state != State::Listen {
+				return false;
+			}
+			tcb.half_open = tcb.half_open.saturating_sub(1);
+			match this {
+				Some(this) if established => tcb.accept_queue.push(this).is_ok(),
+				_ => false,
+			}
+		})
+		.unwrap_or(false);
+		if queued {
+			listener.rx_queue().wake_all();
+		} else if established {
+			// The listening socket has been closed
+			abort(sock);
+		}
+	}
+	if remove {
+		CONNECTIONS.lock().remove(&id);
+	}
+	Some(res)
+}
+
+/// Sends a reset to the remote and closes the connection of the given socket.
+fn abort(sock: &Socket) {
+	with_conn(sock, |conn| conn.reset(None));
+}
+
+/// Executes `f` on the TCB of the given socket.
+///
+/// If the socket has no TCP stack, the function returns `None`.
+fn with_tcb<R, F: FnOnce(&mut Tcb) -> R>(sock: &Socket, f: F) -> Option<R> {
+	let mut stack = sock.stack().lock();
+	let layer = stack.as_mut()?.protocol_mut::<TCPLayer>()?;
+	Some(f(&mut layer.tcb))
+}
+
+/// Builds a TCP layer with the given `sockaddr`.
+pub fn build(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
+	let remote = SockAddr::from_bytes(sockaddr)?;
+	if remote.domain() != desc.domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	let local = SockAddr {
+		port: 0,
+		addr: remote.addr.unspecified(),
+	};
+	Ok(Box::new(TCPLayer {
+		tcb: Tcb::new(local, remote),
+	})?)
+}
+
+/// Returns the unspecified address for the given socket domain.
+pub(super) fn unspecified_addr(domain: SocketDomain) -> EResult<Address> {
+	match domain {
+		SocketDomain::AfInet => Ok(Address::IPv4([0; 4])),
+		SocketDomain::AfInet6 => Ok(Address::IPv6([0; 16])),
+		_ => Err(errno!(EAFNOSUPPORT)),
+	}
+}
+
+/// Tells whether the local addresses `a` and `b` conflict, that is if they have the same port and
+/// either the same address or one of them is the wildcard address.
+fn overlaps(a: &SockAddr, b: &SockAddr) -> bool {
+	a.port == b.port && (a.addr == b.addr || a.addr.is_unspecified() || b.addr.is_unspecified())
+}
+
+/// Binds the socket `sock` to the local endpoint `local`.
+///
+/// If the port is zero, an ephemeral port is allocated.
+///
+/// If the socket is already bound, the function returns [`errno::EINVAL`]. If the endpoint is
+/// used by a listening socket, or by a connection while `SO_REUSEADDR` is not set, the function
+/// returns [`errno::EADDRINUSE`].
+pub fn bind(sock: &Socket, mut local: SockAddr) -> EResult<()> {
+	if local.domain() != sock.desc().domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	if sock.stack().lock().is_some() || get_bound_addr(sock)?.is_some() {
+		return Err(errno!(EINVAL));
+	}
+	if local.port == 0 {
+		local.port = alloc_port()?;
+	} else {
+		if LISTENERS.lock().iter().any(|(l, _)| overlaps(l, &local)) {
+			return Err(errno!(EADDRINUSE));
+		}
+		let reuse = sock.opts().lock().reuse_addr;
+		if !reuse
+			&& CONNECTIONS
+				.lock()
+				.iter()
+				.any(|((l, _), _)| overlaps(l, &local))
+		{
+			return Err(errno!(EADDRINUSE));
+		}
+	}
+	set_bound_addr(sock, &local)
+}
+
+/// Initiates a TCP connection on the given socket `sock`, to the given address `sockaddr`.
+///
+/// If `nonblock` is set, the function returns [`errno::EINPROGRESS`] instead of waiting for the
+/// connection to be established.
+pub fn connect(sock: &Arc<Socket>, sockaddr: &[u8], nonblock: bool) -> EResult<()> {
+	let remote = SockAddr::from_bytes(sockaddr)?;
+	if remote.domain() != sock.desc().domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	{
+		let mut stack = sock.stack().lock();
+		if let Some(layer) = stack.as_ref().and_then(|s| s.protocol::<TCPLayer>()) {
+			match layer.tcb.state {
+				State::Closed => {}
+				State::SynSent | State::SynReceived => return Err(errno!(EALREADY)),
+				State::Listen => return Err(errno!(EINVAL)),
+				_ => return Err(errno!(EISCONN)),
+			}
+		}
+		// Determine the local endpoint
+		let mut local = get_bound_addr(sock)?.unwrap_or(SockAddr {
+			port: 0,
+			addr: remote.addr.unspecified(),
+		});
+		if local.addr.is_unspecified() {
+			local.addr = super::get_src_addr(&remote.addr)?;
+		}
+		if local.port == 0 {
+			local.port = alloc_port()?;
+		}
+		let id = (local, remote);
+		// Register the connection
+		{
+			let mut conns = CONNECTIONS.lock();
+			if conns.contains_key(&id) {
+				return Err(errno!(EADDRINUSE));
+			}
+			conns.insert(id, sock.clone())?;
+		}
+		set_bound_addr(sock, &local)?;
+		let mut tcb = Tcb::new(local, remote);
+		tcb.state = State::SynSent;
+		let domain = ip::build_layer(PROTO_TCP, local.addr, remote.addr);
+		let layer = Box::new(TCPLayer {
+			tcb,
+		});
+		let (domain, layer) = match (domain, layer) {
+			(Ok(domain), Ok(layer)) => (domain, layer),
+			(Err(e), _) => {
+				CONNECTIONS.lock().remove(&id);
+				return Err(e);
+			}
+			(_, Err(e)) => {
+				CONNECTIONS.lock().remove(&id);
+				return Err(e.into());
+			}
+		};
+		*stack = Some(Stack {
+			domain,
+			protocol: layer,
+		});
+	}
+	let now = current_time_ns(Clock::Monotonic);
+	with_conn(sock, |conn| conn.send_syn(now));
+	if nonblock {
+		return Err(errno!(EINPROGRESS));
+	}
+	// Wait for the connection to be established
+	let timeout = sock.opts().lock().snd_timeout;
+	let res = sock.tx_queue().wait_until_timeout(timeout, || {
+		with_tcb(sock, |tcb| match tcb.state {
+			State::SynSent | State::SynReceived => None,
+			State::Closed => Some(Err(tcb
+				.error
+				.take()
+				.unwrap_or_else(|| errno!(ECONNREFUSED)))),
+			_ => Some(Ok(())),
+		})
+		.unwrap_or(Some(Err(errno!(ENOTCONN))))
+	});
+	match res {
+		// The connection is still being established in the background
+		Err(e) if e.as_int() == errno::EAGAIN => Err(errno!(EINPROGRESS)),
+		res => res?,
+	}
+}
+
+/// Marks the socket as listening for connections.
+///
+/// `backlog` is the maximum number of pending connections.
+///
+/// If the local endpoint overlaps the one of another listening socket, the function returns
+/// [`errno::EADDRINUSE`].
+pub fn listen(sock: &Arc<Socket>, backlog: usize) -> EResult<()> {
+	let backlog = max(backlog, 1);
+	let mut stack = sock.stack().lock();
+	if let Some(layer) = stack.as_mut().and_then(|s| s.protocol_mut::<TCPLayer>()) {
+		match layer.tcb.state {
+			State::Listen => {
+				layer.tcb.backlog = backlog;
+				return Ok(());
+			}
+			State::Closed => {}
+			_ => return Err(errno!(EINVAL)),
+		}
+	}
+	// Bind if necessary
+	let mut local = get_bound_addr(sock)?.unwrap_or(SockAddr {
+		port: 0,
+		addr: unspecified_addr(sock.desc().domain)?,
+	});
+	let bound = local.port != 0;
+	if !bound {
+		local.port = alloc_port()?;
+	}
 	{
-		todo!()
+		let mut listeners = LISTENERS.lock();
+		if listeners.iter().any(|(l, _)| overlaps(l, &local)) {
+			return Err(errno!(EADDRINUSE));
+		}
+		listeners.insert(local, sock.clone())?;
+	}
+	let res = (|| {
+		if !bound {
+			set_bound_addr(sock, &local)?;
+		}
+		let mut tcb = Tcb::new(
+			local,
+			SockAddr {
+				port: 0,
+				addr: local.addr.unspecified(),
+			},
+		);
+		tcb.state = State::Listen;
+		tcb.backlog = backlog;
+		*stack = Some(Stack {
+			domain: ip::build_layer(PROTO_TCP, local.addr, local.addr.unspecified())?,
+			protocol: Box::new(TCPLayer {
+				tcb,
+			})?,
+		});
+		Ok(())
+	})();
+	if res.is_err() {
+		LISTENERS.lock().remove(&local);
+	}
+	res
+}
+
+/// Waits for a connection on the listening socket `sock` and returns it.
+///
+/// If `nonblock` is set and no connection is pending, the function returns [`errno::EAGAIN`].
+pub fn accept(sock: &Socket, nonblock: bool) -> EResult<Arc<Socket>> {
+	let timeout = sock.opts().lock().rcv_timeout;
+	sock.rx_queue().wait_until_timeout(timeout, || {
+		with_tcb(sock, |tcb| {
+			if tcb.state != State::Listen {
+				return Some(Err(errno!(EINVAL)));
+			}
+			if !tcb.accept_queue.is_empty() {
+				return Some(Ok(tcb.accept_queue.remove(0)));
+			}
+			if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			}
+		})
+		.unwrap_or(Some(Err(errno!(EINVAL))))
+	})?
+}
+
+/// Handles a connection request on a listening socket.
+fn listen_input(listener: &Arc<Socket>, local: SockAddr, remote: SockAddr, seg: &Segment) {
+	if seg.has(FLAG_RST) {
+		return;
+	}
+	if seg.has(FLAG_ACK) {
+		let _ = send_reset(&local, &remote, seg);
+		return;
+	}
+	if !seg.has(FLAG_SYN) {
+		return;
+	}
+	let accept = with_tcb(listener, |tcb| {
+		if tcb.state != State::Listen || tcb.half_open + tcb.accept_queue.len() >= tcb.backlog {
+			return false;
+		}
+		tcb.half_open += 1;
+		true
+	});
+	if accept != Some(true) {
+		return;
+	}
+	let res = (|| -> EResult<()> {
+		let child = Arc::new(listener.new_child()?)?;
+		set_bound_addr(&child, &local)?;
+		let mut tcb = Tcb::new(local, remote);
+		tcb.state = State::SynReceived;
+		tcb.irs = seg.seq;
+		tcb.rcv_nxt = seg.seq.wrapping_add(1);
+		tcb.set_mss(seg.mss);
+		tcb.snd_wnd = seg.wnd as _;
+		tcb.snd_wl1 = seg.seq;
+		tcb.listener = Some(listener.clone());
+		*child.stack().lock() = Some(Stack {
+			domain: ip::build_layer(PROTO_TCP, local.addr, remote.addr)?,
+			protocol: Box::new(TCPLayer {
+				tcb,
+			})?,
+		});
+		CONNECTIONS.lock().insert((local, remote), child.clone())?;
+		let now = current_time_ns(Clock::Monotonic);
+		with_conn(&child, |conn| conn.send_syn(now));
+		Ok(())
+	})();
+	if res.is_err() {
+		with_tcb(listener, |tcb| {
+			tcb.half_open = tcb.half_open.saturating_sub(1)
+		});
+	}
+}
+
+/// Handles a segment received from the network layer.
+///
+/// Arguments:
+/// - `src` is the source address of the packet.
+/// - `dst` is the destination address of the packet.
+/// - `buf` is the segment, header included.
+///
+/// Invalid segments are silently dropped.
+pub fn input(src: Address, dst: Address, buf: &[u8]) -> EResult<()> {
+	if ip::transport_checksum(&src, &dst, PROTO_TCP, &[buf]) != 0 {
+		return Ok(());
+	}
+	let Some(seg) = Segment::parse(buf) else {
+		return Ok(());
+	};
+	let local = SockAddr {
+		port: seg.dst_port,
+		addr: dst,
+	};
+	let remote = SockAddr {
+		port: seg.src_port,
+		addr: src,
+	};
+	let sock = CONNECTIONS.lock().get(&(local, remote)).cloned();
+	if let Some(sock) = sock {
+		with_conn(&sock, |conn| conn.input(&seg));
+		return Ok(());
+	}
+	let listener = {
+		let listeners = LISTENERS.lock();
+		listeners
+			.get(&local)
+			.or_else(|| {
+				listeners.get(&SockAddr {
+					port: local.port,
+					addr: local.addr.unspecified(),
+				})
+			})
+			.cloned()
+	};
+	if let Some(listener) = listener {
+		listen_input(&listener, local, remote, &seg);
+		return Ok(());
+	}
+	// No socket for the segment
+	if !seg.has(FLAG_RST) {
+		send_reset(&local, &remote, &seg)?;
+	}
+	Ok(())
+}
+
+/// Reads data received on the connection into `buf`.
+///
+/// If `nonblock` is set and no data is available, the function returns [`errno::EAGAIN`].
+pub fn read(sock: &Socket, buf: UserSlice<u8>, nonblock: bool) -> EResult<usize> {
+	let timeout = sock.opts().lock().rcv_timeout;
+	sock.rx_queue().wait_until_timeout(timeout, || {
+		// The state is checked first so that if the remote has closed, all its data is already in
+		// the buffer
+		let state = with_tcb(sock, |tcb| {
+			let eof = tcb.fin_received || tcb.state == State::Closed;
+			let err = tcb.error.take();
+			(tcb.state, eof, err)
+		});
+		let Some((state, eof, err)) = state else {
+			return Some(Err(errno!(ENOTCONN)));
+		};
+		if state == State::Listen {
+			return Some(Err(errno!(ENOTCONN)));
+		}
+		let len = {
+			let mut rx = sock.rx_buff().lock();
+			let Some(rx) = rx.as_mut() else {
+				// Reception has been shut down
+				return Some(Ok(0));
+			};
+			match rx.read(buf) {
+				Ok(len) => len,
+				Err(e) => return Some(Err(e)),
+			}
+		};
+		if len > 0 {
+			// Tell the remote if the window reopened significantly
+			with_conn(sock, |conn| {
+				let wnd = conn.rcv_wnd();
+				if wnd.saturating_sub(conn.tcb.rcv_adv) >= conn.tcb.snd_mss
+					&& matches!(
+						conn.tcb.state,
+						State::Established | State::FinWait1 | State::FinWait2
+					) {
+					conn.send_ack();
+				}
+			});
+			return Some(Ok(len));
+		}
+		if let Some(err) = err {
+			return Some(Err(err));
+		}
+		if eof {
+			return Some(Ok(0));
+		}
+		if nonblock {
+			Some(Err(errno!(EAGAIN)))
+		} else {
+			None
+		}
+	})?
+}
+
+/// Writes data from `buf` to the connection.
+///
+/// `flags` are the `MSG_*` flags passed to the system call.
+///
+/// If `nonblock` is set and no space is available, the function returns [`errno::EAGAIN`].
+pub fn write(sock: &Socket, buf: UserSlice<u8>, flags: c_int, nonblock: bool) -> EResult<usize> {
+	let timeout = sock.opts().lock().snd_timeout;
+	sock.tx_queue().wait_until_timeout(timeout, || {
+		with_conn(sock, |conn| {
+			if let Some(err) = conn.tcb.error.take() {
+				return Some(Err(err));
+			}
+			match conn.tcb.state {
+				State::Established | State::CloseWait if !conn.tcb.fin_pending => {}
+				State::SynSent | State::SynReceived if !nonblock => return None,
+				State::SynSent | State::SynReceived => return Some(Err(errno!(EAGAIN))),
+				State::Closed | State::Listen if !conn.tcb.fin_pending => {
+					return Some(Err(errno!(ENOTCONN)));
+				}
+				_ => return Some(Err(socket::broken_pipe(flags))),
+			}
+			let len = {
+				let mut tx = conn.sock.tx_buff().lock();
+				let Some(tx) = tx.as_mut() else {
+					return Some(Err(socket::broken_pipe(flags)));
+				};
+				match tx.write(buf) {
+					Ok(len) => len,
+					Err(e) => return Some(Err(e)),
+				}
+			};
+			if len > 0 {
+				conn.output(false);
+				return Some(Ok(len));
+			}
+			if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			}
+		})
+		.unwrap_or(Some(Err(errno!(ENOTCONN))))
+	})?
+}
+
+/// Sends the data held back by Nagle's algorithm on the connection.
+pub fn flush(sock: &Socket) {
+	with_conn(sock, |conn| conn.output(false));
+}
+
+/// Returns and clears the pending error on the connection, if any.
+pub fn take_error(sock: &Socket) -> Option<Errno> {
+	with_tcb(sock, |tcb| tcb.error.take()).flatten()
+}
+
+/// Returns the address of the remote endpoint of the connection.
+///
+/// If the socket is not connected, the function returns `None`.
+pub fn get_peer_addr(sock: &Socket) -> Option<SockAddr> {
+	with_tcb(sock, |tcb| {
+		matches!(
+			tcb.state,
+			State::SynReceived
+				| State::Established
+				| State::FinWait1
+				| State::FinWait2
+				| State::CloseWait
+				| State::Closing
+				| State::LastAck
+		)
+		.then_some(tcb.remote)
+	})
+	.flatten()
+}
+
+/// Returns the mask of `POLL*` events currently pending on the socket.
+pub fn poll(sock: &Socket) -> u32 {
+	let state = with_tcb(sock, |tcb| {
+		(
+			tcb.state,
+			tcb.fin_received,
+			tcb.fin_pending,
+			tcb.error.is_some(),
+			!tcb.accept_queue.is_empty(),
+		)
+	});
+	let Some((state, fin_received, fin_pending, error, pending)) = state else {
+		// Not connected
+		return POLLHUP;
+	};
+	let mut events = 0;
+	if error {
+		events |= POLLERR;
+	}
+	match state {
+		State::Listen if pending => events |= POLLIN | POLLRDNORM,
+		State::Listen | State::SynSent | State::SynReceived => {}
+		_ => {
+			let readable = sock
+				.rx_buff()
+				.lock()
+				.as_ref()
+				.is_none_or(|rx| !rx.is_empty());
+			if readable || fin_received || state == State::Closed {
+				events |= POLLIN | POLLRDNORM;
+			}
+			if fin_received {
+				events |= POLLRDHUP;
+			}
+			if state == State::Closed {
+				events |= POLLHUP;
+			}
+			// Writing fails right away if the transmit side is closed
+			let writable = match state {
+				State::Established | State::CloseWait if !fin_pending => sock
+					.tx_buff()
+					.lock()
+					.as_ref()
+					.is_none_or(|tx| !tx.is_full()),
+				_ => true,
+			};
+			if writable {
+				events |= POLLOUT | POLLWRNORM;
+			}
+		}
+	}
+	events
+}
+
+/// Closes the transmit side of the connection, sending a `FIN` after the remaining data.
+pub fn shutdown(sock: &Socket) {
+	with_conn(sock, |conn| conn.shutdown());
+}
+
+/// Closes the connection, after the last reference to the socket has been dropped.
+pub fn close(sock: &Socket) {
+	let queue = with_conn(sock, |conn| {
+		match conn.tcb.state {
+			State::Listen => {
+				conn.tcb.state = State::Closed;
+				return Some((conn.tcb.local, mem::take(&mut conn.tcb.accept_queue)));
+			}
+			State::SynSent => conn.close(None),
+			State::SynReceived | State::Established | State::CloseWait => {
+				let unread = conn
+					.sock
+					.rx_buff()
+					.lock()
+					.as_ref()
+					.map(|b| !b.is_empty())
+					.unwrap_or(false);
+				if unread {
+					// Data is lost, tell the remote (RFC 2525 section 2.17)
+					conn.reset(None);
+				} else {
+					conn.shutdown();
+				}
+			}
+			_ => {}
+		}
+		None
+	})
+	.flatten();
+	// Close pending connections of a listening socket
+	if let Some((local, queue)) = queue {
+		LISTENERS.lock().remove(&local);
+		for child in queue {
+			abort(&child);
+		}
+	}
+}
+
+/// The entry point of the kernel task handling TCP timers.
+pub(crate) fn timer_task() -> ! {
+	sti();
+	loop {
+		let socks = CONNECTIONS
+			.lock()
+			.iter()
+			.map(|(_, sock)| sock.clone())
+			.collect::<CollectResult<Vec<_>>>()
+			.0;
+		// On allocation failure, retry next time
+		if let Ok(socks) = socks {
+			let now = current_time_ns(Clock::Monotonic);
+			for sock in socks {
+				with_conn(&sock, |conn| conn.on_timer(now));
+			}
+		}
+		// Sleep
+		let mut remain = 0;
+		let _ = sleep_for(Clock::Monotonic, TIMER_GRANULARITY, &mut remain);
 	}
 }
 
-/// Initiates a TCP connection on the given socket `sock`.
-pub fn init_connection(_sock: &mut Socket) -> EResult<()> {
-	todo!()
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn tcp_seq_cmp() {
+		assert!(seq_lt(1, 2));
+		assert!(!seq_lt(2, 2));
+		assert!(seq_le(2, 2));
+		assert!(seq_lt(u32::MAX, 0));
+		assert!(!seq_lt(0, u32::MAX));
+		assert!(in_window(u32::MAX, u32::MAX - 1, 4));
+		assert!(in_window(1, u32::MAX - 1, 4));
+		assert!(!in_window(2, u32::MAX - 1, 4));
+	}
+
+	#[test_case]
+	fn tcp_segment_parse() {
+		let mut buf = [0u8; 28];
+		buf[0..2].copy_from_slice(&1234u16.to_be_bytes());
+		buf[2..4].copy_from_slice(&80u16.to_be_bytes());
+		buf[4..8].copy_from_slice(&42u32.to_be_bytes());
+		buf[12] = 6 << 4;
+		buf[13] = FLAG_SYN;
+		buf[14..16].copy_from_slice(&1000u16.to_be_bytes());
+		buf[20..24].copy_from_slice(&[OPT_MSS, 4, 0x05, 0xb4]);
+		buf[24] = OPT_NOP;
+		let seg = Segment::parse(&buf).unwrap();
+		assert_eq!(seg.src_port, 1234);
+		assert_eq!(seg.dst_port, 80);
+		assert_eq!(seg.seq, 42);
+		assert_eq!(seg.wnd, 1000);
+		assert_eq!(seg.mss, Some(1460));
+		assert_eq!(seg.len(), 1);
+		assert!(seg.data.is_empty());
+		// Invalid data offset
+		buf[12] = 4 << 4;
+		assert!(Segment::parse(&buf).is_none());
+	}
+
+	#[test_case]
+	fn tcp_addr_overlap() {
+		let addr = |addr, port| SockAddr {
+			port,
+			addr: Address::IPv4(addr),
+		};
+		let any = addr([0; 4], 80);
+		let local = addr([127, 0, 0, 1], 80);
+		let other = addr([10, 0, 2, 15], 80);
+		assert!(overlaps(&local, &local));
+		assert!(overlaps(&any, &local));
+		assert!(overlaps(&local, &any));
+		assert!(!overlaps(&local, &other));
+		assert!(!overlaps(&local, &addr([127, 0, 0, 1], 81)));
+		assert!(!overlaps(&any, &addr([0; 4], 81)));
+	}
 }
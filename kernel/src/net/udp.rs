@@ -0,0 +1,533 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The User Datagram Protocol (UDP) is a protocol transmitting unreliable, connectionless
+//! datagrams (RFC 768).
+//!
+//! Received datagrams are stored in the socket's receive buffer, each one being preceded by a
+//! [`DatagramHdr`] to preserve message boundaries.
+
+use super::{
+	Address, SocketDesc,
+	buf::BufList,
+	ip,
+	ip::PROTO_UDP,
+	osi::{Layer, Stack},
+	sockaddr::SockAddr,
+	tcp,
+};
+use crate::{file::socket::Socket, memory::user::UserSlice, sync::mutex::Mutex};
+use core::{
+	mem::size_of,
+	ptr,
+	sync::atomic::{AtomicU16, Ordering::Relaxed},
+};
+use macros::AnyRepr;
+use utils::{
+	boxed::Box,
+	bytes::{as_bytes, from_bytes},
+	collections::{hashmap::HashMap, vec::Vec},
+	errno,
+	errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// The maximum size of a datagram's payload.
+const MAX_PAYLOAD: usize = u16::MAX as usize - size_of::<UDPHdr>();
+
+/// The beginning of the range of ephemeral ports.
+const EPHEMERAL_PORT_BEGIN: u16 = 49152;
+
+/// The UDP header.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+pub struct UDPHdr {
+	/// Source port.
+	src_port: u16,
+	/// Destination port.
+	dst_port: u16,
+	/// The length of the datagram, header included.
+	length: u16,
+	/// The checksum of the header, payload and IP pseudo-header (RFC 1071).
+	checksum: u16,
+}
+
+/// Header preceding each datagram in a socket's receive buffer.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+struct DatagramHdr {
+	/// The length of the payload.
+	len: u16,
+	/// The source port.
+	port: u16,
+	/// If `true`, the source address is an IPv6 address.
+	ipv6: u8,
+	/// The source address. For IPv4, only the first four bytes are used.
+	addr: [u8; 16],
+}
+
+/// The transport layer for the UDP protocol.
+#[derive(Debug)]
+pub struct UDPLayer {
+	/// The local endpoint. The address may be unspecified.
+	pub local: SockAddr,
+	/// The remote endpoint, if the socket is connected.
+	pub remote: Option<SockAddr>,
+}
+
+impl Layer for UDPLayer {
+	fn transmit(
+		&self,
+		buff: BufList<'_>,
+		next: &dyn Fn(BufList<'_>) -> EResult<()>,
+	) -> EResult<()> {
+		let remote = self.remote.ok_or_else(|| errno!(EDESTADDRREQ))?;
+		// Only a single buffer is supported for the payload
+		if buff.next().is_some() {
+			return Err(errno!(EINVAL));
+		}
+		let length: u16 = (size_of::<UDPHdr>() + buff.len())
+			.try_into()
+			.map_err(|_| errno!(EMSGSIZE))?;
+		let mut hdr = UDPHdr {
+			src_port: self.local.port.to_be(),
+			dst_port: remote.port.to_be(),
+			length: length.to_be(),
+			checksum: 0,
+		};
+		let checksum = ip::transport_checksum(
+			&self.local.addr,
+			&remote.addr,
+			PROTO_UDP,
+			&[as_bytes(&hdr), buff.data],
+		);
+		// A computed checksum of zero is transmitted as all ones (RFC 768)
+		hdr.checksum = if checksum == 0 { 0xffff } else { checksum };
+		let mut buff = buff;
+		let buff = buff.push_front(as_bytes(&hdr).into());
+		next(buff)
+	}
+}
+
+/// Sockets bound to a local endpoint.
+static SOCKETS: Mutex<HashMap<SockAddr, Arc<Socket>>> = Mutex::new(HashMap::new());
+
+/// The next ephemeral port to try.
+static NEXT_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORT_BEGIN);
+
+/// Tells whether binding to `local` conflicts with an already bound socket.
+fn is_used(sockets: &HashMap<SockAddr, Arc<Socket>>, local: &SockAddr) -> bool {
+	sockets.iter().any(|(addr, _)| {
+		addr.port == local.port
+			&& addr.addr.same_family(&local.addr)
+			&& (addr.addr == local.addr
+				|| addr.addr.is_unspecified()
+				|| local.addr.is_unspecified())
+	})
+}
+
+/// Builds a UDP layer with the given `sockaddr`.
+pub fn build(desc: &SocketDesc, sockaddr: &[u8]) -> EResult<Box<dyn Layer>> {
+	let remote = SockAddr::from_bytes(sockaddr)?;
+	if remote.domain() != desc.domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	Ok(Box::new(UDPLayer {
+		local: SockAddr {
+			port: 0,
+			addr: remote.addr.unspecified(),
+		},
+		remote: Some(remote),
+	})?)
+}
+
+/// Executes `f` on the UDP layer of the given socket.
+///
+/// If the socket is not bound, the function returns `None`.
+fn with_layer<R, F: FnOnce(&mut Stack) -> R>(sock: &Socket, f: F) -> Option<R> {
+	let mut stack = sock.stack().lock();
+	let stack = stack.as_mut()?;
+	stack.protocol::<UDPLayer>()?;
+	Some(f(stack))
+}
+
+/// Returns the UDP layer of the given stack.
+fn layer(stack: &mut Stack) -> &mut UDPLayer {
+	stack.protocol_mut().unwrap()
+}
+
+/// Binds the socket to the given local endpoint.
+///
+/// If the port is zero, an ephemeral port is allocated.
+///
+/// If the socket is already bound, the function returns [`errno::EINVAL`]. If the endpoint is
+/// already in use, the function returns [`errno::EADDRINUSE`].
+pub fn bind(sock: &Arc<Socket>, mut local: SockAddr) -> EResult<()> {
+	if local.domain() != sock.desc().domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	let mut stack = sock.stack().lock();
+	if stack.is_some() {
+		return Err(errno!(EINVAL));
+	}
+	{
+		let mut sockets = SOCKETS.lock();
+		if local.port == 0 {
+			let count = u16::MAX - EPHEMERAL_PORT_BEGIN + 1;
+			let port = (0..count)
+				.map(|_| {
+					NEXT_PORT
+						.fetch_update(Relaxed, Relaxed, |p| {
+							Some(p.checked_add(1).unwrap_or(EPHEMERAL_PORT_BEGIN))
+						})
+						.unwrap()
+				})
+				.find(|port| {
+					!is_used(
+						&sockets,
+						&SockAddr {
+							port: *port,
+							addr: local.addr,
+						},
+					)
+				})
+				.ok_or_else(|| errno!(EADDRNOTAVAIL))?;
+			local.port = port;
+		} else if is_used(&sockets, &local) {
+			return Err(errno!(EADDRINUSE));
+		}
+		sockets.insert(local, sock.clone())?;
+	}
+	let res = (|| {
+		*sock.get_sockname().lock() = local.to_bytes()?;
+		*stack = Some(Stack {
+			domain: ip::build_layer(PROTO_UDP, local.addr, local.addr.unspecified())?,
+			protocol: Box::new(UDPLayer {
+				local,
+				remote: None,
+			})?,
+		});
+		Ok(())
+	})();
+	if res.is_err() {
+		SOCKETS.lock().remove(&local);
+	}
+	res
+}
+
+/// Binds the socket to an ephemeral port if not bound yet.
+fn autobind(sock: &Arc<Socket>) -> EResult<()> {
+	if sock.stack().lock().is_some() {
+		return Ok(());
+	}
+	let local = SockAddr {
+		port: 0,
+		addr: tcp::unspecified_addr(sock.desc().domain)?,
+	};
+	match bind(sock, local) {
+		// Bound concurrently
+		Err(e) if e.as_int() == errno::EINVAL => Ok(()),
+		res => res,
+	}
+}
+
+/// Sets the default destination of datagrams sent on the socket.
+pub fn connect(sock: &Arc<Socket>, sockaddr: &[u8]) -> EResult<()> {
+	let remote = SockAddr::from_bytes(sockaddr)?;
+	if remote.domain() != sock.desc().domain {
+		return Err(errno!(EAFNOSUPPORT));
+	}
+	autobind(sock)?;
+	with_layer(sock, |stack| -> EResult<()> {
+		let layer = layer(stack);
+		let src = if layer.local.addr.is_unspecified() {
+			super::get_src_addr(&remote.addr)?
+		} else {
+			layer.local.addr
+		};
+		layer.remote = Some(remote);
+		stack.domain = ip::build_layer(PROTO_UDP, src, remote.addr)?;
+		Ok(())
+	})
+	.ok_or_else(|| errno!(EINVAL))?
+}
+
+/// Returns the address of the remote endpoint the socket is connected to, if any.
+pub fn get_peer_addr(sock: &Socket) -> Option<SockAddr> {
+	with_layer(sock, |stack| layer(stack).remote).flatten()
+}
+
+/// Sends the datagram in `buf`.
+///
+/// `dst` is the destination. If `None`, the datagram is sent to the remote endpoint the socket is
+/// connected to.
+///
+/// The function returns the number of bytes sent.
+pub fn send_to(sock: &Arc<Socket>, buf: UserSlice<u8>, dst: Option<&[u8]>) -> EResult<usize> {
+	if buf.len() > MAX_PAYLOAD {
+		return Err(errno!(EMSGSIZE));
+	}
+	let dst = dst.map(SockAddr::from_bytes).transpose()?;
+	if let Some(dst) = &dst {
+		if dst.domain() != sock.desc().domain {
+			return Err(errno!(EAFNOSUPPORT));
+		}
+	}
+	autobind(sock)?;
+	let (local, remote) = with_layer(sock, |stack| {
+		let layer = layer(stack);
+		(layer.local, layer.remote)
+	})
+	.ok_or_else(|| errno!(EINVAL))?;
+	let remote = dst.or(remote).ok_or_else(|| errno!(EDESTADDRREQ))?;
+	let src = if local.addr.is_unspecified() {
+		super::get_src_addr(&remote.addr)?
+	} else {
+		local.addr
+	};
+	let data = buf.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+	let stack = Stack {
+		domain: ip::build_layer(PROTO_UDP, src, remote.addr)?,
+		protocol: Box::new(UDPLayer {
+			local: SockAddr {
+				port: local.port,
+				addr: src,
+			},
+			remote: Some(remote),
+		})?,
+	};
+	stack.transmit(BufList::from(data.as_slice()))?;
+	Ok(data.len())
+}
+
+/// Receives a datagram into `buf`. If the datagram is larger than the buffer, the remaining data
+/// is discarded.
+///
+/// If `nonblock` is set and no datagram is available, the function returns [`errno::EAGAIN`].
+///
+/// The function returns the number of bytes read and the source of the datagram. If reception
+/// has been shut down, the function returns zero and no source.
+pub fn recv_from(
+	sock: &Socket,
+	buf: UserSlice<u8>,
+	nonblock: bool,
+) -> EResult<(usize, Option<SockAddr>)> {
+	let timeout = sock.opts().lock().rcv_timeout;
+	sock.rx_queue().wait_until_timeout(timeout, || {
+		if let Some(err) = sock.take_error() {
+			return Some(Err(err));
+		}
+		let mut rx = sock.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			return Some(Ok((0, None)));
+		};
+		if rx.is_empty() {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		Some((|| {
+			let mut hdr_buf = [0u8; size_of::<DatagramHdr>()];
+			rx.read(UserSlice::from_slice_mut(&mut hdr_buf))?;
+			let hdr: &DatagramHdr = from_bytes(&hdr_buf).unwrap();
+			let src = SockAddr {
+				port: hdr.port,
+				addr: if hdr.ipv6 != 0 {
+					Address::IPv6(hdr.addr)
+				} else {
+					Address::IPv4(hdr.addr[..4].try_into().unwrap())
+				},
+			};
+			let len = hdr.len as usize;
+			let mut data = Vec::new();
+			data.resize(len, 0)?;
+			rx.read(UserSlice::from_slice_mut(&mut data))?;
+			let len = buf.copy_to_user(0, &data[..len.min(buf.len())])?;
+			Ok((len, Some(src)))
+		})())
+	})?
+}
+
+/// Unbinds the socket, after the last reference to it has been dropped.
+pub fn close(sock: &Socket) {
+	let Some(local) = with_layer(sock, |stack| layer(stack).local) else {
+		return;
+	};
+	let mut sockets = SOCKETS.lock();
+	if sockets
+		.get(&local)
+		.is_some_and(|s| ptr::eq(s.as_ref(), sock))
+	{
+		sockets.remove(&local);
+	}
+}
+
+/// Reports that the datagram sent from `src` to `dst` could not be delivered because no socket is
+/// bound to `dst`.
+///
+/// If the sender is a local socket connected to `dst`, its pending error is set to
+/// [`errno::ECONNREFUSED`].
+fn port_unreachable(dst: SockAddr, src: SockAddr) {
+	// TODO send ICMP port unreachable to remote hosts
+	if !super::is_local_addr(&src.addr) {
+		return;
+	}
+	let sock = {
+		let sockets = SOCKETS.lock();
+		sockets
+			.get(&src)
+			.or_else(|| {
+				sockets.get(&SockAddr {
+					port: src.port,
+					addr: src.addr.unspecified(),
+				})
+			})
+			.cloned()
+	};
+	let Some(sock) = sock else {
+		return;
+	};
+	if get_peer_addr(&sock) == Some(dst) {
+		sock.set_error(errno!(ECONNREFUSED));
+		sock.rx_queue().wake_all();
+	}
+}
+
+/// Handles a datagram received from the network layer.
+///
+/// Arguments:
+/// - `src` is the source address of the packet.
+/// - `dst` is the destination address of the packet.
+/// - `buf` is the datagram, header included.
+///
+/// Invalid datagrams, and datagrams for which there is no socket, are silently dropped.
+pub fn input(src: Address, dst: Address, buf: &[u8]) -> EResult<()> {
+	let Some(hdr) = from_bytes::<UDPHdr>(buf) else {
+		return Ok(());
+	};
+	let len = u16::from_be(hdr.length) as usize;
+	if len < size_of::<UDPHdr>() || len > buf.len() {
+		return Ok(());
+	}
+	let buf = &buf[..len];
+	// The checksum is optional for IPv4 only
+	let check = hdr.checksum != 0 || matches!(src, Address::IPv6(_));
+	if check && ip::transport_checksum(&src, &dst, PROTO_UDP, &[buf]) != 0 {
+		return Ok(());
+	}
+	let local = SockAddr {
+		port: u16::from_be(hdr.dst_port),
+		addr: dst,
+	};
+	let remote = SockAddr {
+		port: u16::from_be(hdr.src_port),
+		addr: src,
+	};
+	let sock = {
+		let sockets = SOCKETS.lock();
+		sockets
+			.get(&local)
+			.or_else(|| {
+				sockets.get(&SockAddr {
+					port: local.port,
+					addr: local.addr.unspecified(),
+				})
+			})
+			.cloned()
+	};
+	let Some(sock) = sock else {
+		port_unreachable(local, remote);
+		return Ok(());
+	};
+	// If connected, only accept datagrams from the remote endpoint
+	if get_peer_addr(&sock).is_some_and(|peer| peer != remote) {
+		return Ok(());
+	}
+	let payload = &buf[size_of::<UDPHdr>()..];
+	let mut addr = [0u8; 16];
+	let ipv6 = match remote.addr {
+		Address::IPv4(a) => {
+			addr[..4].copy_from_slice(&a);
+			0
+		}
+		Address::IPv6(a) => {
+			addr = a;
+			1
+		}
+	};
+	let hdr = DatagramHdr {
+		len: payload.len() as _,
+		port: remote.port,
+		ipv6,
+		addr,
+	};
+	{
+		let mut rx = sock.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			return Ok(());
+		};
+		// If the datagram does not fit, drop it
+		if rx.get_available_len() < size_of::<DatagramHdr>() + payload.len() {
+			return Ok(());
+		}
+		rx.write(unsafe { UserSlice::from_slice(as_bytes(&hdr)) })?;
+		rx.write(unsafe { UserSlice::from_slice(payload) })?;
+	}
+	sock.rx_queue().wake_all();
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn udp_checksum() {
+		let layer = UDPLayer {
+			local: SockAddr {
+				port: 1234,
+				addr: Address::IPv4([10, 0, 0, 1]),
+			},
+			remote: Some(SockAddr {
+				port: 53,
+				addr: Address::IPv4([10, 0, 0, 2]),
+			}),
+		};
+		let payload = b"hello";
+		layer
+			.transmit(BufList::from(&payload[..]), &|buff| {
+				let hdr = buff.data;
+				let data = buff.next().unwrap().data;
+				assert_eq!(hdr.len(), size_of::<UDPHdr>());
+				assert_eq!(&hdr[0..2], &1234u16.to_be_bytes());
+				assert_eq!(&hdr[2..4], &53u16.to_be_bytes());
+				assert_eq!(&hdr[4..6], &13u16.to_be_bytes());
+				let checksum = ip::transport_checksum(
+					&Address::IPv4([10, 0, 0, 1]),
+					&Address::IPv4([10, 0, 0, 2]),
+					PROTO_UDP,
+					&[hdr, data],
+				);
+				assert_eq!(checksum, 0);
+				Ok(())
+			})
+			.unwrap();
+	}
+}
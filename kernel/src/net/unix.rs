@@ -0,0 +1,792 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! UNIX domain sockets (`AF_UNIX`), used for communication between processes on the same host.
+//!
+//! A socket bound to a path is associated with its socket file's inode through the
+//! filesystem's buffers, so that other sockets can find it when connecting. Sockets can also be
+//! bound to a name in the abstract namespace, which has no presence in the filesystem.
+//!
+//! Data is written directly to the receive buffer of the destination socket. Writers waiting for
+//! space in a socket's receive buffer sleep on that socket's transmit queue.
+//!
+//! Open files can be passed along with data (`SCM_RIGHTS`).
+
+use super::SocketType;
+use crate::{
+	file::{
+		File, FileType, Stat,
+		fs::FileOps,
+		socket,
+		socket::{Message, Socket},
+		vfs,
+		vfs::{ResolutionSettings, node::Node},
+	},
+	memory::{ring_buffer::RingBuffer, user::UserSlice},
+	process::Process,
+	sync::mutex::Mutex,
+	syscall::select::{POLLHUP, POLLIN, POLLOUT, POLLRDHUP, POLLRDNORM, POLLWRNORM},
+	time::clock::{Clock, current_time_sec},
+};
+use core::{any::Any, cmp::min, ffi::c_int, mem, mem::size_of, ptr};
+use macros::AnyRepr;
+use utils::{
+	TryClone,
+	bytes::{as_bytes, from_bytes},
+	collections::{hashmap::HashMap, path::Path, string::String, vec::Vec},
+	errno,
+	errno::EResult,
+	ptr::arc::Arc,
+};
+
+/// The ID of the `AF_UNIX` family.
+const AF_UNIX: u16 = 1;
+/// The maximum length of a path in a `sockaddr_un` structure.
+const UNIX_PATH_MAX: usize = 108;
+
+/// The maximum number of files passed in a single message.
+pub const SCM_MAX_FD: usize = 253;
+
+/// Sockets bound in the abstract namespace.
+static ABSTRACT: Mutex<HashMap<String, Arc<Socket>>> = Mutex::new(HashMap::new());
+
+/// The address of a UNIX socket.
+enum UnixAddr<'a> {
+	/// A path on the filesystem.
+	Path(&'a Path),
+	/// A name in the abstract namespace.
+	Abstract(&'a [u8]),
+}
+
+impl<'a> UnixAddr<'a> {
+	/// Parses the given `sockaddr_un` structure.
+	fn parse(sockaddr: &'a [u8]) -> EResult<Self> {
+		let Some((family, path)) = sockaddr.split_first_chunk::<2>() else {
+			return Err(errno!(EINVAL));
+		};
+		if u16::from_ne_bytes(*family) != AF_UNIX {
+			return Err(errno!(EAFNOSUPPORT));
+		}
+		let path = &path[..min(path.len(), UNIX_PATH_MAX)];
+		match path {
+			[] => Err(errno!(EINVAL)),
+			[0, name @ ..] => Ok(Self::Abstract(name)),
+			path => {
+				let len = path.iter().position(|b| *b == 0).unwrap_or(path.len());
+				Ok(Self::Path(Path::new(&path[..len])?))
+			}
+		}
+	}
+}
+
+/// Returns the address of an unbound socket.
+fn unnamed_addr() -> EResult<Vec<u8>> {
+	Ok(Vec::try_from(&AF_UNIX.to_ne_bytes()[..])?)
+}
+
+/// The connection state of a UNIX socket.
+#[derive(Debug, Default)]
+enum Conn {
+	/// The socket is not connected.
+	#[default]
+	Unconnected,
+	/// The socket is accepting connections.
+	Listening {
+		/// The maximum number of pending connections.
+		backlog: usize,
+		/// The sockets for connections waiting to be accepted.
+		pending: Vec<Arc<Socket>>,
+	},
+	/// The socket is connected to a peer. For datagram sockets, this is the default destination.
+	Connected(Arc<Socket>),
+	/// The peer has been closed.
+	Disconnected,
+}
+
+/// Files in flight, attached to data in a socket's receive buffer.
+#[derive(Debug)]
+struct Rights {
+	/// For stream sockets, the position in the stream of the data the files are attached to.
+	pos: u64,
+	/// The files.
+	files: Vec<Arc<File>>,
+}
+
+/// The state of a UNIX socket.
+#[derive(Debug, Default)]
+pub struct UnixState {
+	/// Tells whether the socket is bound to an address.
+	bound: bool,
+	/// The node of the socket file the socket is bound to, if any.
+	node: Option<Arc<Node>>,
+	/// The name the socket is bound to in the abstract namespace, if any.
+	abstract_name: Option<String>,
+
+	/// The connection state.
+	conn: Conn,
+	/// If `true`, the peer will not send any more data.
+	eof: bool,
+
+	/// The total number of bytes written to the receive buffer.
+	rx_written: u64,
+	/// The total number of bytes read from the receive buffer.
+	rx_read: u64,
+	/// Files in flight, in the order of the data they are attached to.
+	rights: Vec<Rights>,
+}
+
+/// Header preceding each datagram in a socket's receive buffer.
+#[derive(AnyRepr)]
+#[repr(C, packed)]
+struct DatagramHdr {
+	/// The length of the payload.
+	len: u32,
+	/// The length of the sender's address, following the header.
+	addr_len: u8,
+	/// If non-zero, files are attached to the datagram.
+	rights: u8,
+}
+
+/// Tells whether the socket transmits datagrams.
+fn is_dgram(sock: &Socket) -> bool {
+	sock.desc().type_ == SocketType::SockDgram
+}
+
+/// Returns the socket bound to the address in `sockaddr`.
+///
+/// If no socket of type `type_` is bound to the address, the function returns an error.
+fn lookup(sockaddr: &[u8], type_: SocketType) -> EResult<Arc<Socket>> {
+	let sock = match UnixAddr::parse(sockaddr)? {
+		UnixAddr::Path(path) => {
+			let rs = ResolutionSettings::for_process(&Process::current(), true);
+			let ent = vfs::get_file_from_path(path, &rs)?;
+			let node = ent.node();
+			let stat = node.stat.lock().clone();
+			if stat.get_type() != Some(FileType::Socket) {
+				return Err(errno!(ECONNREFUSED));
+			}
+			if !rs.access_profile.can_write_file(&stat) {
+				return Err(errno!(EACCES));
+			}
+			let buf = node
+				.fs
+				.buffer_get(node.inode)
+				.ok_or_else(|| errno!(ECONNREFUSED))?;
+			(buf as Arc<dyn Any>)
+				.downcast::<Socket>()
+				.map_err(|_| errno!(ECONNREFUSED))?
+		}
+		UnixAddr::Abstract(name) => ABSTRACT
+			.lock()
+			.get(name)
+			.cloned()
+			.ok_or_else(|| errno!(ECONNREFUSED))?,
+	};
+	if sock.desc().type_ != type_ {
+		return Err(errno!(EPROTOTYPE));
+	}
+	Ok(sock)
+}
+
+/// Binds the socket to the address in `sockaddr`.
+///
+/// If the address is a path, the socket file is created. If the file already exists, the
+/// function returns [`errno::EADDRINUSE`].
+pub fn bind(sock: &Arc<Socket>, sockaddr: &[u8]) -> EResult<()> {
+	let addr = UnixAddr::parse(sockaddr)?;
+	{
+		let mut state = sock.unix().lock();
+		if state.bound {
+			return Err(errno!(EINVAL));
+		}
+		state.bound = true;
+	}
+	let res = (|| {
+		match addr {
+			UnixAddr::Path(path) => {
+				let rs = ResolutionSettings::for_process(&Process::current(), true);
+				let parent = vfs::get_file_from_path(path.parent().unwrap_or(Path::root()), &rs)?;
+				let name = path.file_name().ok_or_else(|| errno!(EINVAL))?;
+				let umask = Process::current().fs.lock().umask();
+				let ts = current_time_sec(Clock::Realtime);
+				let ent = vfs::create_file(
+					parent,
+					name,
+					&rs.access_profile,
+					Stat {
+						mode: FileType::Socket.to_mode() | (0o777 & !umask),
+						ctime: ts,
+						mtime: ts,
+						atime: ts,
+						..Default::default()
+					},
+				)
+				.map_err(|e| {
+					if e.as_int() == errno::EEXIST {
+						errno!(EADDRINUSE)
+					} else {
+						e
+					}
+				})?;
+				let node = ent.node().clone();
+				node.fs.buffer_insert(node.inode, sock.clone())?;
+				sock.unix().lock().node = Some(node);
+			}
+			UnixAddr::Abstract(name) => {
+				let name = String::try_from(name)?;
+				let mut abs = ABSTRACT.lock();
+				if abs.contains_key(&name) {
+					return Err(errno!(EADDRINUSE));
+				}
+				abs.insert(name.try_clone()?, sock.clone())?;
+				sock.unix().lock().abstract_name = Some(name);
+			}
+		}
+		*sock.get_sockname().lock() = Vec::try_from(sockaddr)?;
+		Ok(())
+	})();
+	if res.is_err() {
+		sock.unix().lock().bound = false;
+	}
+	res
+}
+
+/// Marks the socket as accepting connections.
+///
+/// `backlog` is the maximum number of pending connections.
+pub fn listen(sock: &Socket, backlog: usize) -> EResult<()> {
+	if is_dgram(sock) {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let mut state = sock.unix().lock();
+	if !state.bound {
+		return Err(errno!(EINVAL));
+	}
+	match &mut state.conn {
+		Conn::Unconnected => {
+			state.conn = Conn::Listening {
+				backlog,
+				pending: Vec::new(),
+			};
+		}
+		Conn::Listening {
+			backlog: b, ..
+		} => *b = backlog,
+		_ => return Err(errno!(EINVAL)),
+	}
+	Ok(())
+}
+
+/// Connects the socket to the socket bound to the address in `sockaddr`.
+///
+/// For datagram sockets, this sets the default destination of datagrams.
+///
+/// For stream sockets, if the listening socket's backlog is full and `nonblock` is set, the
+/// function returns [`errno::EAGAIN`].
+pub fn connect(sock: &Arc<Socket>, sockaddr: &[u8], nonblock: bool) -> EResult<()> {
+	let target = lookup(sockaddr, sock.desc().type_)?;
+	if is_dgram(sock) {
+		let prev = mem::replace(&mut sock.unix().lock().conn, Conn::Connected(target));
+		// Drop outside of the lock
+		drop(prev);
+		return Ok(());
+	}
+	let child = Arc::new(target.new_child()?)?;
+	*child.get_sockname().lock() = Vec::try_from(&**target.get_sockname().lock())?;
+	child.unix().lock().conn = Conn::Connected(sock.clone());
+	{
+		let mut state = sock.unix().lock();
+		match state.conn {
+			Conn::Unconnected => state.conn = Conn::Connected(child.clone()),
+			Conn::Listening {
+				..
+			} => return Err(errno!(EINVAL)),
+			_ => return Err(errno!(EISCONN)),
+		}
+	}
+	let timeout = sock.opts().lock().snd_timeout;
+	let res = target.tx_queue().wait_until_timeout(timeout, || {
+		let mut state = target.unix().lock();
+		let Conn::Listening {
+			backlog,
+			pending,
+		} = &mut state.conn
+		else {
+			return Some(Err(errno!(ECONNREFUSED)));
+		};
+		if pending.len() > *backlog {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		Some(pending.push(child.clone()).map_err(Into::into))
+	});
+	match res {
+		Ok(Ok(())) => {
+			target.rx_queue().wake_all();
+			Ok(())
+		}
+		Ok(Err(e)) | Err(e) => {
+			let prev = mem::take(&mut sock.unix().lock().conn);
+			drop(prev);
+			close(&child);
+			Err(e)
+		}
+	}
+}
+
+/// Accepts a pending connection, returning the socket for it.
+///
+/// If `nonblock` is set and no connection is pending, the function returns [`errno::EAGAIN`].
+pub fn accept(sock: &Socket, nonblock: bool) -> EResult<Arc<Socket>> {
+	if is_dgram(sock) {
+		return Err(errno!(EOPNOTSUPP));
+	}
+	let timeout = sock.opts().lock().rcv_timeout;
+	let child = sock.rx_queue().wait_until_timeout(timeout, || {
+		let mut state = sock.unix().lock();
+		let Conn::Listening {
+			pending, ..
+		} = &mut state.conn
+		else {
+			return Some(Err(errno!(EINVAL)));
+		};
+		if pending.is_empty() {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		Some(Ok(pending.remove(0)))
+	})??;
+	// Wake connecting sockets waiting for room in the backlog
+	sock.tx_queue().wake_all();
+	Ok(child)
+}
+
+/// Connects both sockets to each other.
+pub fn pair(a: &Arc<Socket>, b: &Arc<Socket>) {
+	a.unix().lock().conn = Conn::Connected(b.clone());
+	b.unix().lock().conn = Conn::Connected(a.clone());
+}
+
+/// Returns the address of the peer the socket is connected to.
+///
+/// If the socket is not connected, the function returns [`errno::ENOTCONN`].
+pub fn get_peer_addr(sock: &Socket) -> EResult<Vec<u8>> {
+	let peer = match &sock.unix().lock().conn {
+		Conn::Connected(peer) => peer.clone(),
+		_ => return Err(errno!(ENOTCONN)),
+	};
+	let name = peer.get_sockname().lock();
+	if name.is_empty() {
+		unnamed_addr()
+	} else {
+		Ok(Vec::try_from(&**name)?)
+	}
+}
+
+/// Sends the message in `buf`.
+///
+/// Arguments:
+/// - `dst` is the destination address. If `None`, the message is sent to the connected peer.
+/// - `rights` is the list of files to be passed along with the message.
+/// - `flags` are the `MSG_*` flags passed to the system call.
+/// - `nonblock` tells whether the function shall return instead of waiting for space in the
+///   destination's buffer.
+///
+/// The function returns the number of bytes sent.
+pub fn send(
+	sock: &Socket,
+	buf: UserSlice<u8>,
+	dst: Option<&[u8]>,
+	rights: Vec<Arc<File>>,
+	flags: c_int,
+	nonblock: bool,
+) -> EResult<usize> {
+	if is_dgram(sock) {
+		return send_dgram(sock, buf, dst, rights, nonblock);
+	}
+	// Connection-mode sockets ignore the destination
+	let peer = match &sock.unix().lock().conn {
+		Conn::Connected(peer) => peer.clone(),
+		Conn::Disconnected => return Err(socket::broken_pipe(flags)),
+		_ => return Err(errno!(ENOTCONN)),
+	};
+	if sock.tx_buff().lock().is_none() {
+		return Err(socket::broken_pipe(flags));
+	}
+	if buf.is_empty() {
+		return Ok(0);
+	}
+	let mut rights = Some(rights);
+	let timeout = sock.opts().lock().snd_timeout;
+	let len = peer.tx_queue().wait_until_timeout(timeout, || {
+		let mut rx = peer.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			return Some(Err(socket::broken_pipe(flags)));
+		};
+		if rx.get_available_len() == 0 {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		let mut state = peer.unix().lock();
+		// Attach the files to the first byte
+		if let Some(files) = rights.take().filter(|f| !f.is_empty()) {
+			let pos = state.rx_written;
+			if let Err(e) = state.rights.push(Rights {
+				pos,
+				files,
+			}) {
+				return Some(Err(e.into()));
+			}
+		}
+		let res = rx.write(buf);
+		if let Ok(len) = res {
+			state.rx_written += len as u64;
+		}
+		Some(res)
+	})??;
+	peer.rx_queue().wake_all();
+	Ok(len)
+}
+
+/// Sends a datagram. Arguments are the same as for [`send`].
+fn send_dgram(
+	sock: &Socket,
+	buf: UserSlice<u8>,
+	dst: Option<&[u8]>,
+	rights: Vec<Arc<File>>,
+	nonblock: bool,
+) -> EResult<usize> {
+	let target = match dst {
+		Some(dst) => lookup(dst, sock.desc().type_)?,
+		None => match &sock.unix().lock().conn {
+			Conn::Connected(peer) => peer.clone(),
+			_ => return Err(errno!(ENOTCONN)),
+		},
+	};
+	// A socket connected to another peer does not accept datagrams from other sockets
+	if matches!(&target.unix().lock().conn, Conn::Connected(peer) if !ptr::eq(peer.as_ref(), sock))
+	{
+		return Err(errno!(EPERM));
+	}
+	let src = Vec::try_from(&**sock.get_sockname().lock())?;
+	let data = buf.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+	let hdr = DatagramHdr {
+		len: data.len() as _,
+		addr_len: src.len() as _,
+		rights: !rights.is_empty() as _,
+	};
+	let total = size_of::<DatagramHdr>() + src.len() + data.len();
+	let mut rights = Some(rights);
+	let timeout = sock.opts().lock().snd_timeout;
+	target.tx_queue().wait_until_timeout(timeout, || {
+		let mut rx = target.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			return Some(Err(errno!(ECONNREFUSED)));
+		};
+		if total > rx.capacity() {
+			return Some(Err(errno!(EMSGSIZE)));
+		}
+		if rx.get_available_len() < total {
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		let mut state = target.unix().lock();
+		if let Some(files) = rights.take().filter(|f| !f.is_empty()) {
+			if let Err(e) = state.rights.push(Rights {
+				pos: 0,
+				files,
+			}) {
+				return Some(Err(e.into()));
+			}
+		}
+		let res = (|| {
+			rx.write(unsafe { UserSlice::from_slice(as_bytes(&hdr)) })?;
+			rx.write(unsafe { UserSlice::from_slice(&src) })?;
+			rx.write(unsafe { UserSlice::from_slice(&data) })
+		})();
+		Some(res)
+	})??;
+	target.rx_queue().wake_all();
+	Ok(data.len())
+}
+
+/// Receives a message into `buf`.
+///
+/// If `nonblock` is set and no data is available, the function returns [`errno::EAGAIN`].
+///
+/// For datagram sockets, if the datagram is larger than `buf`, the remaining data is discarded.
+pub fn recv(sock: &Socket, buf: UserSlice<u8>, nonblock: bool) -> EResult<Message> {
+	let timeout = sock.opts().lock().rcv_timeout;
+	let msg = sock.rx_queue().wait_until_timeout(timeout, || {
+		let mut rx = sock.rx_buff().lock();
+		let Some(rx) = rx.as_mut() else {
+			// Reception has been shut down
+			return Some(Ok(Message::default()));
+		};
+		let mut state = sock.unix().lock();
+		if rx.is_empty() {
+			if !is_dgram(sock) {
+				match state.conn {
+					Conn::Unconnected
+					| Conn::Listening {
+						..
+					} => return Some(Err(errno!(ENOTCONN))),
+					// Report the loss of unread data before the end of the stream
+					Conn::Disconnected => {
+						return Some(sock.take_error().map(Err).unwrap_or(Ok(Message::default())));
+					}
+					Conn::Connected(_) if state.eof => return Some(Ok(Message::default())),
+					Conn::Connected(_) => {}
+				}
+			}
+			return if nonblock {
+				Some(Err(errno!(EAGAIN)))
+			} else {
+				None
+			};
+		}
+		let res = if is_dgram(sock) {
+			recv_dgram(rx, &mut state, buf)
+		} else {
+			// Files are received with the data they are attached to. Do not read past the data
+			// of the next files, so that they are received with their data
+			let rights = match state.rights.first() {
+				Some(r) if r.pos <= state.rx_read => state.rights.remove(0).files,
+				_ => Vec::new(),
+			};
+			let limit = state
+				.rights
+				.first()
+				.map(|r| (r.pos - state.rx_read) as usize)
+				.unwrap_or(usize::MAX);
+			rx.read(buf.subslice(0, limit)).map(|len| {
+				state.rx_read += len as u64;
+				Message {
+					len,
+					rights,
+					..Default::default()
+				}
+			})
+		};
+		Some(res)
+	})??;
+	// Wake writers waiting for space
+	sock.tx_queue().wake_all();
+	// The peer may be polling for space on its own queue
+	if !is_dgram(sock) {
+		let peer = match &sock.unix().lock().conn {
+			Conn::Connected(peer) => Some(peer.clone()),
+			_ => None,
+		};
+		if let Some(peer) = peer {
+			peer.tx_queue().wake_all();
+		}
+	}
+	Ok(msg)
+}
+
+/// Receives a datagram from the receive buffer `rx`.
+fn recv_dgram(rx: &mut RingBuffer, state: &mut UnixState, buf: UserSlice<u8>) -> EResult<Message> {
+	let mut hdr_buf = [0u8; size_of::<DatagramHdr>()];
+	rx.read(UserSlice::from_slice_mut(&mut hdr_buf))?;
+	let hdr: &DatagramHdr = from_bytes(&hdr_buf).unwrap();
+	let (len, addr_len, has_rights) = (hdr.len as usize, hdr.addr_len as usize, hdr.rights != 0);
+	let src = if addr_len > 0 {
+		let mut src = Vec::new();
+		src.resize(addr_len, 0)?;
+		rx.read(UserSlice::from_slice_mut(&mut src))?;
+		src
+	} else {
+		unnamed_addr()?
+	};
+	let read_len = rx.read(buf.subslice(0, len))?;
+	rx.discard(len - read_len);
+	let rights = if has_rights {
+		state.rights.remove(0).files
+	} else {
+		Vec::new()
+	};
+	Ok(Message {
+		len: read_len,
+		src: Some(src),
+		rights,
+		truncated: read_len < len,
+	})
+}
+
+/// Returns the mask of `POLL*` events currently pending on the socket.
+pub fn poll(sock: &Socket) -> u32 {
+	let mut events = 0;
+	let peer = {
+		let readable = sock
+			.rx_buff()
+			.lock()
+			.as_ref()
+			.is_none_or(|rx| !rx.is_empty());
+		let state = sock.unix().lock();
+		if is_dgram(sock) {
+			if readable {
+				events |= POLLIN | POLLRDNORM;
+			}
+			return events | POLLOUT | POLLWRNORM;
+		}
+		match &state.conn {
+			Conn::Unconnected => return POLLOUT | POLLWRNORM,
+			Conn::Listening {
+				pending, ..
+			} => {
+				return if pending.is_empty() {
+					0
+				} else {
+					POLLIN | POLLRDNORM
+				};
+			}
+			Conn::Connected(peer) => {
+				if readable || state.eof {
+					events |= POLLIN | POLLRDNORM;
+				}
+				if state.eof {
+					events |= POLLRDHUP;
+				}
+				peer.clone()
+			}
+			Conn::Disconnected => {
+				return POLLIN | POLLRDNORM | POLLRDHUP | POLLHUP | POLLOUT | POLLWRNORM;
+			}
+		}
+	};
+	// Data is written directly to the peer's receive buffer. Writing fails right away if either
+	// side is shut down
+	let writable = sock.tx_buff().lock().is_none()
+		|| peer
+			.rx_buff()
+			.lock()
+			.as_ref()
+			.is_none_or(|rx| !rx.is_full());
+	if writable {
+		events |= POLLOUT | POLLWRNORM;
+	}
+	events
+}
+
+/// Shuts down the transmit side of the socket, signaling the end of the stream to the peer.
+pub fn shutdown(sock: &Socket) {
+	let peer = match &sock.unix().lock().conn {
+		Conn::Connected(peer) => peer.clone(),
+		_ => return,
+	};
+	if !is_dgram(sock) {
+		peer.unix().lock().eof = true;
+	}
+	peer.rx_queue().wake_all();
+}
+
+/// Closes the socket, after the last reference to it has been dropped.
+pub fn close(sock: &Socket) {
+	// Stop receiving, dropping remaining data
+	let rx = sock.rx_buff().lock().take();
+	let unread = rx.as_ref().is_some_and(|rx| !rx.is_empty());
+	drop(rx);
+	let (node, abstract_name, conn, rights) = {
+		let mut state = sock.unix().lock();
+		(
+			state.node.take(),
+			state.abstract_name.take(),
+			mem::take(&mut state.conn),
+			mem::take(&mut state.rights),
+		)
+	};
+	// Unbind
+	let is_sock = |buf: &Arc<dyn FileOps>| ptr::addr_eq(Arc::as_ptr(buf), sock as *const Socket);
+	if let Some(node) = node {
+		if node.fs.buffer_get(node.inode).is_some_and(|b| is_sock(&b)) {
+			node.fs.buffer_remove(node.inode);
+		}
+	}
+	if let Some(name) = abstract_name {
+		let mut abs = ABSTRACT.lock();
+		if abs.get(&name).is_some_and(|s| ptr::eq(s.as_ref(), sock)) {
+			abs.remove(&name);
+		}
+	}
+	match conn {
+		Conn::Connected(peer) => {
+			{
+				let mut state = peer.unix().lock();
+				if matches!(&state.conn, Conn::Connected(p) if ptr::eq(p.as_ref(), sock)) {
+					state.conn = Conn::Disconnected;
+				}
+			}
+			// Data sent by the peer has been lost
+			if unread && !is_dgram(sock) {
+				peer.set_error(errno!(ECONNRESET));
+			}
+			peer.rx_queue().wake_all();
+			peer.tx_queue().wake_all();
+		}
+		// Refuse pending connections
+		Conn::Listening {
+			pending, ..
+		} => {
+			for child in pending {
+				close(&child);
+			}
+		}
+		_ => {}
+	}
+	// Files in flight are closed here, outside of locks
+	drop(rights);
+	sock.rx_queue().wake_all();
+	sock.tx_queue().wake_all();
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn unix_addr_parse() {
+		let mut sockaddr = [0u8; 12];
+		sockaddr[..2].copy_from_slice(&AF_UNIX.to_ne_bytes());
+		sockaddr[2..8].copy_from_slice(b"/tmp/s");
+		assert!(matches!(
+			UnixAddr::parse(&sockaddr),
+			Ok(UnixAddr::Path(p)) if p.as_bytes() == b"/tmp/s"
+		));
+		sockaddr[2] = 0;
+		assert!(matches!(
+			UnixAddr::parse(&sockaddr[..5]),
+			Ok(UnixAddr::Abstract(b"tm"))
+		));
+		assert!(UnixAddr::parse(&sockaddr[..2]).is_err());
+		sockaddr[..2].copy_from_slice(&2u16.to_ne_bytes());
+		assert!(UnixAddr::parse(&sockaddr).is_err());
+	}
+}
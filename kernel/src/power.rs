@@ -18,15 +18,18 @@
 
 //! This module handles system power.
 
-use crate::arch::x86::{
-	cli, hlt,
-	io::{inb, outb},
+use crate::{
+	arch::x86::{
+		cli, hlt,
+		io::{inb, outb},
+	},
+	process::scheduler::cpu,
 };
 use core::arch::asm;
 
 /// Halts the kernel until reboot.
 pub fn halt() -> ! {
-	// TODO Send a signal to all other cores to stop them
+	cpu::halt_others();
 	loop {
 		cli();
 		hlt();
@@ -31,7 +31,7 @@ use crate::{
 	arch::x86::{idt::IntFrame, tss},
 	file::vfs::ResolutionSettings,
 	memory::VirtAddr,
-	process::{Process, mem_space::MemSpace},
+	process::{Process, mem_space::MemSpace, signal},
 	sync::mutex::Mutex,
 };
 use utils::{
@@ -78,7 +78,7 @@ pub fn exec(proc: &Process, frame: &mut IntFrame, image: ProgramImage) -> EResul
 			Ok(Arc::new(Mutex::new(new_fds))?)
 		})
 		.transpose()?;
-	let signal_handlers = Arc::new(Default::default())?;
+	let signal_handlers = Arc::new(Mutex::new(signal::default_handlers()))?;
 	// All fallible operations succeeded, flush to process
 	MemSpace::bind(&image.mem_space);
 	// Safe because no other thread can execute this function at the same time for the same process
@@ -91,6 +91,8 @@ pub fn exec(proc: &Process, frame: &mut IntFrame, image: ProgramImage) -> EResul
 		let mut signal_manager = proc.signal.lock();
 		signal_manager.handlers = signal_handlers;
 		signal_manager.sigpending = Default::default();
+		signal_manager.queue.clear();
+		signal_manager.altstack = None;
 	}
 	proc.vfork_wake();
 	*proc.tls.lock() = Default::default();
@@ -0,0 +1,442 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Fast userspace mutexes (futexes) allow userspace to implement synchronization primitives,
+//! only calling the kernel when a thread has to sleep or wake other threads.
+//!
+//! Waiting processes are stored in a table keyed on the futex's identity:
+//! - For private futexes, the memory space and the virtual address
+//! - For futexes located in shared mappings, the physical address, so that processes with
+//!   different memory spaces can synchronize
+//!
+//! This module also implements robust futex lists, which allow the kernel to release the locks
+//! held by a thread when it exits.
+
+use crate::{
+	memory::{PhysAddr, VirtAddr, user::UserPtr},
+	process,
+	process::{
+		Process,
+		mem_space::MemSpace,
+		pid::Pid,
+		scheduler::Scheduler,
+		signal::{SIGEV_NONE, SigEvent},
+	},
+	sync::mutex::IntMutex,
+	syscall::FromSyscallArg,
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::Timer,
+		unit::Timestamp,
+	},
+};
+use core::{cmp::min, mem, mem::size_of, sync::atomic::Ordering::Relaxed};
+use utils::{
+	collections::{hashmap::HashMap, vec::Vec},
+	errno,
+	errno::{CollectResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// Bitset matching any waiter.
+pub const BITSET_MATCH_ANY: u32 = 0xffffffff;
+
+/// Robust futex value bit: there are waiters on the futex.
+const FUTEX_WAITERS: u32 = 0x80000000;
+/// Robust futex value bit: the owner of the futex died without releasing it.
+const FUTEX_OWNER_DIED: u32 = 0x40000000;
+/// Mask of the robust futex value's bits containing the owner's TID.
+const FUTEX_TID_MASK: u32 = 0x3fffffff;
+
+/// The maximum number of entries walked in a robust list, to protect against circular lists.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// A robust futex list registered by a thread with `set_robust_list`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RobustList {
+	/// The address of the head of the list in userspace. If zero, no list is registered.
+	pub head: usize,
+	/// Tells whether the list uses the 32-bit layout.
+	pub compat: bool,
+}
+
+/// The identity of a futex.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Key {
+	/// A futex private to a memory space, identified by the memory space and virtual address.
+	Private(usize, VirtAddr),
+	/// A futex located in a shared mapping, identified by its physical address.
+	Shared(PhysAddr),
+}
+
+impl Key {
+	/// Returns the key for the futex at `addr` in `mem_space`.
+	///
+	/// If `private` is set, the futex is assumed to be used only inside the memory space.
+	fn new(mem_space: &Arc<MemSpace>, addr: VirtAddr, private: bool) -> EResult<Self> {
+		if addr.is_null() || !addr.is_aligned_to(size_of::<u32>()) {
+			return Err(errno!(EINVAL));
+		}
+		if !private {
+			if let Some(phys_addr) = mem_space.get_shared_phys(addr)? {
+				return Ok(Self::Shared(phys_addr));
+			}
+		}
+		Ok(Self::Private(Arc::as_ptr(mem_space) as usize, addr))
+	}
+}
+
+/// A process waiting on a futex.
+#[derive(Clone, Copy, Debug)]
+struct Waiter {
+	/// The PID of the process.
+	pid: Pid,
+	/// The bitset the process is waiting with. Wakes are restricted to waiters whose bitset
+	/// intersects with the wake's bitset.
+	bitset: u32,
+}
+
+/// Processes waiting on futexes, by futex, in order of arrival.
+///
+/// A process is removed from the table when woken up by a wake operation.
+static WAITERS: IntMutex<HashMap<Key, Vec<Waiter>>> = IntMutex::new(HashMap::new());
+
+/// Removes the process with the given PID from the table.
+///
+/// If the process was not waiting, the function returns `false`.
+fn remove_waiter(table: &mut HashMap<Key, Vec<Waiter>>, pid: Pid) -> bool {
+	let mut found = false;
+	table.retain(|_, waiters| {
+		waiters.retain(|w| {
+			let matches = w.pid == pid;
+			found |= matches;
+			!matches
+		});
+		!waiters.is_empty()
+	});
+	found
+}
+
+/// Wakes at most `count` processes waiting on `key` with a bitset intersecting with `bitset`.
+///
+/// The function returns the number of woken processes.
+fn wake_locked(
+	table: &mut HashMap<Key, Vec<Waiter>>,
+	key: Key,
+	count: usize,
+	bitset: u32,
+) -> usize {
+	let Some(waiters) = table.get_mut(&key) else {
+		return 0;
+	};
+	let mut woken = 0;
+	waiters.retain(|w| {
+		if woken >= count || w.bitset & bitset == 0 {
+			return true;
+		}
+		if let Some(proc) = Process::get_by_tid(w.pid) {
+			proc.wake();
+		}
+		woken += 1;
+		false
+	});
+	if waiters.is_empty() {
+		table.remove(&key);
+	}
+	woken
+}
+
+/// Makes the current process wait on the futex at `uaddr`, as long as it contains the value
+/// `val`.
+///
+/// Arguments:
+/// - `mem_space` is the memory space of the current process
+/// - `uaddr` is the address of the futex
+/// - `private` tells whether the futex is private to the memory space
+/// - `val` is the expected value. If the futex contains a different value, the function returns
+///   [`errno::EAGAIN`]
+/// - `bitset` is the bitset of the waiter, which must not be zero
+/// - `deadline` is the clock and the timestamp at which waiting is aborted with
+///   [`errno::ETIMEDOUT`]. If `None`, the function waits indefinitely
+///
+/// If waiting is interrupted by a signal, the function returns [`errno::EINTR`].
+pub fn wait(
+	mem_space: &Arc<MemSpace>,
+	uaddr: UserPtr<u32>,
+	private: bool,
+	val: u32,
+	bitset: u32,
+	deadline: Option<(Clock, Timestamp)>,
+) -> EResult<()> {
+	if bitset == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let key = Key::new(mem_space, VirtAddr(uaddr.as_ptr() as _), private)?;
+	let pid = Process::current().get_tid();
+	// The timer wakes the process up when expiring
+	let timer = deadline
+		.map(|(clock, deadline)| -> EResult<_> {
+			let now = current_time_ns(clock);
+			if now >= deadline {
+				return Err(errno!(ETIMEDOUT));
+			}
+			let mut timer = Timer::new(
+				clock,
+				pid,
+				SigEvent {
+					sigev_notify: SIGEV_NONE,
+					..Default::default()
+				},
+			)?;
+			timer.set_time(0, deadline - now)?;
+			Ok((clock, timer))
+		})
+		.transpose()?;
+	loop {
+		// Fault the page in, so that it does not need to be done with the table locked
+		uaddr.copy_from_user()?;
+		// Queue
+		{
+			let proc = Process::current();
+			if proc.has_pending_signal() {
+				return Err(errno!(EINTR));
+			}
+			let mut table = WAITERS.lock();
+			// Checking the value with the table locked ensures a wake cannot happen between the
+			// check and the moment the process is queued
+			let cur = uaddr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+			if cur != val {
+				return Err(errno!(EAGAIN));
+			}
+			table.entry(key).or_insert(Vec::new())?.push(Waiter {
+				pid,
+				bitset,
+			})?;
+			proc.set_state(process::State::Sleeping);
+		}
+		// Yield
+		Scheduler::tick();
+		// If the process is not in the table anymore, it has been woken up by a wake operation
+		if !remove_waiter(&mut WAITERS.lock(), pid) {
+			return Ok(());
+		}
+		if Process::current().has_pending_signal() {
+			return Err(errno!(EINTR));
+		}
+		if let Some((clock, timer)) = &timer {
+			if timer.has_expired(current_time_ns(*clock)) {
+				return Err(errno!(ETIMEDOUT));
+			}
+		}
+		// Spurious wakeup, try again
+	}
+}
+
+/// Wakes at most `count` processes waiting on the futex at `uaddr` with a bitset intersecting
+/// with `bitset`.
+///
+/// `private` tells whether the futex is private to the memory space.
+///
+/// The function returns the number of woken processes.
+pub fn wake(
+	mem_space: &Arc<MemSpace>,
+	uaddr: UserPtr<u32>,
+	private: bool,
+	count: usize,
+	bitset: u32,
+) -> EResult<usize> {
+	if bitset == 0 {
+		return Err(errno!(EINVAL));
+	}
+	let key = Key::new(mem_space, VirtAddr(uaddr.as_ptr() as _), private)?;
+	Ok(wake_locked(&mut WAITERS.lock(), key, count, bitset))
+}
+
+/// Wakes at most `count` processes waiting on the futex at `uaddr`, then moves at most
+/// `requeue_count` of the remaining waiters to the futex at `uaddr2`.
+///
+/// Arguments:
+/// - `private` tells whether the futexes are private to the memory space
+/// - `cmp` is the value the futex at `uaddr` is expected to contain. If it contains a different
+///   value, the function returns [`errno::EAGAIN`]. If `None`, the value is not checked
+///
+/// The function returns the number of woken processes and the number of moved processes.
+pub fn requeue(
+	mem_space: &Arc<MemSpace>,
+	uaddr: UserPtr<u32>,
+	uaddr2: UserPtr<u32>,
+	private: bool,
+	count: usize,
+	requeue_count: usize,
+	cmp: Option<u32>,
+) -> EResult<(usize, usize)> {
+	let key = Key::new(mem_space, VirtAddr(uaddr.as_ptr() as _), private)?;
+	let key2 = Key::new(mem_space, VirtAddr(uaddr2.as_ptr() as _), private)?;
+	if cmp.is_some() {
+		// Fault the page in, so that it does not need to be done with the table locked
+		uaddr.copy_from_user()?;
+	}
+	let mut table = WAITERS.lock();
+	if let Some(cmp) = cmp {
+		let cur = uaddr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+		if cur != cmp {
+			return Err(errno!(EAGAIN));
+		}
+	}
+	let woken = wake_locked(&mut table, key, count, BITSET_MATCH_ANY);
+	if key == key2 {
+		return Ok((woken, 0));
+	}
+	let Some(waiters) = table.get(&key) else {
+		return Ok((woken, 0));
+	};
+	let requeued = min(requeue_count, waiters.len());
+	// Allocate before removing waiters from the first futex, so that none is lost on failure
+	let mut moved = waiters[..requeued]
+		.iter()
+		.cloned()
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	table
+		.entry(key2)
+		.or_insert(Vec::new())?
+		.append(&mut moved)?;
+	if let Some(waiters) = table.get_mut(&key) {
+		waiters.rotate_left(requeued);
+		waiters.truncate(waiters.len() - requeued);
+		if waiters.is_empty() {
+			table.remove(&key);
+		}
+	}
+	Ok((woken, requeued))
+}
+
+/// Marks the robust futex at `uaddr` as abandoned if it is owned by the thread `tid`, then wakes
+/// a waiter if any.
+fn handle_futex_death(mem_space: &Arc<MemSpace>, uaddr: UserPtr<u32>, tid: Pid) -> EResult<()> {
+	loop {
+		let Some(val) = uaddr.copy_from_user()? else {
+			return Ok(());
+		};
+		if val & FUTEX_TID_MASK != tid as u32 {
+			return Ok(());
+		}
+		let new = (val & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+		if uaddr.compare_exchange(val, new)? != val {
+			// The value changed in between, retry
+			continue;
+		}
+		if val & FUTEX_WAITERS != 0 {
+			wake(mem_space, uaddr, false, 1, BITSET_MATCH_ANY)?;
+		}
+		return Ok(());
+	}
+}
+
+/// Reads a pointer-sized value at `addr`, with the size depending on `compat`.
+fn read_word(addr: usize, compat: bool) -> EResult<usize> {
+	let val = if compat {
+		UserPtr::<u32>::from_ptr(addr)
+			.copy_from_user()?
+			.map(|v| v as usize)
+	} else {
+		UserPtr::<usize>::from_ptr(addr).copy_from_user()?
+	};
+	val.ok_or_else(|| errno!(EFAULT))
+}
+
+/// Returns the size of the head of a robust list in userspace.
+///
+/// `compat` tells whether the list uses the 32-bit layout.
+pub fn robust_list_head_size(compat: bool) -> usize {
+	if compat {
+		3 * size_of::<u32>()
+	} else {
+		3 * size_of::<usize>()
+	}
+}
+
+/// Walks the robust list of the thread `tid` and releases every futex it still owns.
+///
+/// Arguments:
+/// - `head` is the address of the head of the list
+/// - `compat` tells whether the list uses the 32-bit layout
+fn exit_robust_list(
+	mem_space: &Arc<MemSpace>,
+	head: usize,
+	compat: bool,
+	tid: Pid,
+) -> EResult<()> {
+	let word = if compat {
+		size_of::<u32>()
+	} else {
+		size_of::<usize>()
+	};
+	let mut entry = read_word(head, compat)?;
+	let futex_offset = read_word(head + word, compat)?;
+	let pending = read_word(head + 2 * word, compat)?;
+	// Sign extend the offset
+	let futex_offset = if compat {
+		futex_offset as u32 as i32 as isize
+	} else {
+		futex_offset as isize
+	};
+	let futex_addr =
+		|entry: usize| UserPtr::<u32>::from_ptr((entry & !1).wrapping_add_signed(futex_offset));
+	for _ in 0..ROBUST_LIST_LIMIT {
+		if entry == head {
+			break;
+		}
+		// Read the next entry before releasing the futex, since releasing it lets other threads
+		// modify the entry
+		let next = read_word(entry & !1, compat)?;
+		if entry != pending {
+			handle_futex_death(mem_space, futex_addr(entry), tid)?;
+		}
+		entry = next;
+	}
+	if pending != 0 {
+		handle_futex_death(mem_space, futex_addr(pending), tid)?;
+	}
+	Ok(())
+}
+
+/// Releases the futexes of the current process `proc`, which is exiting.
+///
+/// This clears the thread ID pointed to by `clear_child_tid` and wakes a waiter on it, then walks
+/// the process's robust list.
+///
+/// Errors are ignored since the process is exiting anyway.
+pub fn exit(proc: &Process) {
+	let Some(mem_space) = proc.mem_space.as_ref() else {
+		return;
+	};
+	let clear_child_tid = UserPtr::<u32>::from_ptr(proc.clear_child_tid.swap(0, Relaxed));
+	if !clear_child_tid.as_ptr().is_null() && clear_child_tid.copy_to_user(&0).is_ok() {
+		let _ = wake(mem_space, clear_child_tid, false, 1, BITSET_MATCH_ANY);
+	}
+	let robust_list = mem::take(&mut *proc.robust_list.lock());
+	if robust_list.head != 0 {
+		let _ = exit_robust_list(
+			mem_space,
+			robust_list.head,
+			robust_list.compat,
+			proc.get_tid(),
+		);
+	}
+}
@@ -29,6 +29,8 @@ use crate::{
 		PhysAddr, VirtAddr,
 		buddy::ZONE_USER,
 		cache::{FrameOwner, RcFrame},
+		swap,
+		swap::{SwapArea, SwapSlot},
 		vmem::{VMem, write_ro},
 	},
 	process::mem_space::{
@@ -36,10 +38,13 @@ use crate::{
 	},
 	time::clock::{Clock, current_time_ms},
 };
-use core::{num::NonZeroUsize, ops::Deref, sync::atomic::Ordering::Release};
+use core::{
+	cmp::min, hint::unlikely, num::NonZeroUsize, ops::Deref, ptr, sync::atomic::Ordering::Release,
+};
 use utils::{
 	TryClone,
 	collections::vec::Vec,
+	errno,
 	errno::{AllocResult, EResult},
 	limits::PAGE_SIZE,
 	ptr::arc::Arc,
@@ -91,6 +96,35 @@ impl Drop for MappedFrame {
 	}
 }
 
+/// A page of a mapping.
+#[derive(Clone, Debug)]
+pub(super) enum MappedPage {
+	/// The page is present in memory
+	Frame(MappedFrame),
+	/// The page has been evicted to a swap area
+	Swap(SwapSlot),
+}
+
+impl MappedPage {
+	/// Returns the frame of the page, if present in memory.
+	pub fn frame(&self) -> Option<&MappedFrame> {
+		match self {
+			Self::Frame(frame) => Some(frame),
+			Self::Swap(_) => None,
+		}
+	}
+}
+
+/// Tells whether the frame may be evicted to a swap area.
+///
+/// This is the case for anonymous frames that are not shared with anything else.
+fn is_evictable(frame: &RcFrame) -> bool {
+	matches!(frame.owner(), FrameOwner::Anon)
+		&& frame.order() == 0
+		&& frame.is_unique()
+		&& frame.phys_addr().kernel_to_virtual().is_some()
+}
+
 /// Returns virtual memory context flags.
 ///
 /// Arguments:
@@ -165,6 +199,9 @@ pub struct MemMapping {
 	pub(super) prot: u8,
 	/// Mapping flags
 	pub(super) flags: i32,
+	/// Tells whether the pages of the mapping are locked in memory, preventing them from being
+	/// evicted
+	pub(super) locked: bool,
 
 	/// The mapped file, if any
 	file: Option<Arc<File>>,
@@ -173,7 +210,7 @@ pub struct MemMapping {
 
 	// TODO use a sparse array?
 	/// The list of allocated physical pages
-	pub(super) pages: Vec<Option<MappedFrame>>,
+	pub(super) pages: Vec<Option<MappedPage>>,
 }
 
 impl MemMapping {
@@ -203,6 +240,7 @@ impl MemMapping {
 			size,
 			prot,
 			flags,
+			locked: false,
 
 			file,
 			off,
@@ -226,7 +264,12 @@ impl MemMapping {
 	/// error.
 	pub fn map(&mut self, offset: usize, vmem: &mut VMem, write: bool) -> EResult<()> {
 		let virtaddr = self.addr + offset * PAGE_SIZE;
-		if let Some(page) = &self.pages[offset] {
+		if let Some(MappedPage::Swap(slot)) = &self.pages[offset] {
+			// Read the page back from the swap area
+			let page = slot.read()?;
+			self.pages[offset] = Some(MappedPage::Frame(MappedFrame::new(page)));
+		}
+		if let Some(MappedPage::Frame(page)) = &self.pages[offset] {
 			// A page is already present, use it
 			let mut phys_addr = page.phys_addr();
 			let pending_cow = self.flags & MAP_SHARED == 0 && page.is_shared();
@@ -235,7 +278,7 @@ impl MemMapping {
 				// reading or writing)
 				let page = init_page(vmem, self.prot, Some(page), virtaddr)?;
 				phys_addr = page.phys_addr();
-				self.pages[offset] = Some(MappedFrame::new(page));
+				self.pages[offset] = Some(MappedPage::Frame(MappedFrame::new(page)));
 			}
 			// Map the page
 			let flags = vmem_flags(self.prot, false);
@@ -249,7 +292,7 @@ impl MemMapping {
 				let phys_addr = if write {
 					let page = init_page(vmem, self.prot, None, virtaddr)?;
 					let phys_addr = page.phys_addr();
-					self.pages[offset] = Some(MappedFrame::new(page));
+					self.pages[offset] = Some(MappedPage::Frame(MappedFrame::new(page)));
 					phys_addr
 				} else {
 					// Lazy allocation: map the zeroed page
@@ -270,7 +313,7 @@ impl MemMapping {
 					page = init_page(vmem, self.prot, Some(&page), virtaddr)?;
 				}
 				let phys_addr = page.phys_addr();
-				self.pages[offset] = Some(MappedFrame::new(page));
+				self.pages[offset] = Some(MappedPage::Frame(MappedFrame::new(page)));
 				// Map
 				let flags = vmem_flags(self.prot, !write);
 				vmem.map(phys_addr, virtaddr, flags);
@@ -279,6 +322,117 @@ impl MemMapping {
 		Ok(())
 	}
 
+	/// Returns the swap entry of the page at the offset `offset` of the mapping, if it has been
+	/// evicted.
+	pub fn swap_entry(&self, offset: usize) -> Option<usize> {
+		match &self.pages[offset] {
+			Some(MappedPage::Swap(slot)) => Some(slot.entry()),
+			_ => None,
+		}
+	}
+
+	/// Returns the number of pages of the mapping that are allocated, either in memory or in a
+	/// swap area.
+	pub fn get_phys_usage(&self) -> usize {
+		self.pages.iter().flatten().count()
+	}
+
+	/// Evicts up to `count` pages of the mapping to swap areas.
+	///
+	/// Only anonymous pages of private mappings that are not locked are evicted. Pages that have
+	/// been accessed since the last call are given a second chance and remain in memory.
+	///
+	/// The function returns the number of evicted pages.
+	pub fn evict(&mut self, vmem: &mut VMem, count: usize) -> usize {
+		// Pages of shared mappings may be accessed by other processes
+		if self.flags & MAP_SHARED != 0 || self.locked {
+			return 0;
+		}
+		let mut evicted = 0;
+		for (i, page) in self.pages.iter_mut().enumerate() {
+			if evicted >= count {
+				break;
+			}
+			let Some(MappedPage::Frame(frame)) = page else {
+				continue;
+			};
+			if !is_evictable(frame) {
+				continue;
+			}
+			let virtaddr = self.addr + i * PAGE_SIZE;
+			if vmem.poll_accessed(virtaddr) {
+				continue;
+			}
+			let Some(slot) = swap::reserve() else {
+				break;
+			};
+			// Revoke access before writing so that the content cannot change anymore
+			vmem.map_swap(virtaddr, slot.entry());
+			if slot.write(frame).is_err() {
+				// The page is mapped again on the next access
+				vmem.unmap(virtaddr);
+				break;
+			}
+			*page = Some(MappedPage::Swap(slot));
+			evicted += 1;
+		}
+		evicted
+	}
+
+	/// Reads every page of the mapping that has been evicted to `area` back into memory.
+	pub fn swap_in_area(&mut self, vmem: &mut VMem, area: &SwapArea) -> EResult<()> {
+		for (i, page) in self.pages.iter_mut().enumerate() {
+			let Some(MappedPage::Swap(slot)) = page else {
+				continue;
+			};
+			if !ptr::eq(slot.area().as_ref(), area) {
+				continue;
+			}
+			let frame = slot.read()?;
+			*page = Some(MappedPage::Frame(MappedFrame::new(frame)));
+			// Remove the swap entry. The page is mapped on the next access
+			vmem.unmap(self.addr + i * PAGE_SIZE);
+		}
+		Ok(())
+	}
+
+	/// Releases the pages of the mapping, unmapping them from `vmem`.
+	///
+	/// Only pages of private mappings that are not locked are released. Subsequent accesses to
+	/// the mapping are handled as if the pages had never been accessed.
+	///
+	/// The function returns the number of released pages.
+	pub fn release(&mut self, vmem: &mut VMem) -> usize {
+		// Pages of shared mappings may be accessed by other processes
+		if self.flags & MAP_SHARED != 0 || self.locked {
+			return 0;
+		}
+		let mut released = 0;
+		for (i, page) in self.pages.iter_mut().enumerate() {
+			if page.take().is_some() {
+				vmem.unmap(self.addr + i * PAGE_SIZE);
+				released += 1;
+			}
+		}
+		released
+	}
+
+	/// Returns a new mapping for the `size` pages of the current mapping starting at `begin`.
+	fn sub(&self, begin: usize, size: NonZeroUsize) -> AllocResult<Self> {
+		Ok(Self {
+			addr: self.addr + begin * PAGE_SIZE,
+			size,
+			prot: self.prot,
+			flags: self.flags,
+			locked: self.locked,
+
+			file: self.file.clone(),
+			off: self.off + (begin * PAGE_SIZE) as u64,
+
+			pages: Vec::try_from(&self.pages[begin..(begin + size.get())])?,
+		})
+	}
+
 	/// Splits the current mapping, creating up to two new mappings and one gap.
 	///
 	/// Arguments:
@@ -298,19 +452,7 @@ impl MemMapping {
 		size: usize,
 	) -> AllocResult<(Option<Self>, Option<MemGap>, Option<Self>)> {
 		let prev = NonZeroUsize::new(begin)
-			.map(|size| {
-				Ok(MemMapping {
-					addr: self.addr,
-					size,
-					prot: self.prot,
-					flags: self.flags,
-
-					file: self.file.clone(),
-					off: self.off,
-
-					pages: Vec::try_from(&self.pages[..size.get()])?,
-				})
-			})
+			.map(|size| self.sub(0, size))
 			.transpose()?;
 		let gap = NonZeroUsize::new(size).map(|size| {
 			let addr = self.addr + begin * PAGE_SIZE;
@@ -323,23 +465,81 @@ impl MemMapping {
 			.get()
 			.checked_sub(end)
 			.and_then(NonZeroUsize::new)
-			.map(|size| {
-				Ok(Self {
-					addr: self.addr + end * PAGE_SIZE,
-					size,
-					prot: self.prot,
-					flags: self.flags,
-
-					file: self.file.clone(),
-					off: self.off + end as u64,
-
-					pages: Vec::try_from(&self.pages[end..])?,
-				})
-			})
+			.map(|size| self.sub(end, size))
 			.transpose()?;
 		Ok((prev, gap, next))
 	}
 
+	/// Splits the current mapping around the range of pages beginning at `begin` with size
+	/// `size`, so that attributes can be changed on the range only.
+	///
+	/// The function returns the mappings before the range, on the range, and after the range.
+	pub fn split_range(
+		&self,
+		begin: usize,
+		size: NonZeroUsize,
+	) -> AllocResult<(Option<Self>, Self, Option<Self>)> {
+		let (prev, _, next) = self.split(begin, size.get())?;
+		let mid = self.sub(begin, size)?;
+		Ok((prev, mid, next))
+	}
+
+	/// Returns a new mapping at `addr` with size `size`, containing the pages of the current
+	/// mapping starting at `begin`.
+	///
+	/// If the new mapping is larger than the remaining pages, the extra pages are not allocated.
+	/// If smaller, the pages that do not fit are left out.
+	pub fn remap(&self, begin: usize, addr: VirtAddr, size: NonZeroUsize) -> AllocResult<Self> {
+		let end = min(self.size.get(), begin + size.get());
+		let mut pages = Vec::try_from(&self.pages[begin..end])?;
+		pages.resize(size.get(), None)?;
+		Ok(Self {
+			addr,
+			size,
+			prot: self.prot,
+			flags: self.flags,
+			locked: self.locked,
+
+			file: self.file.clone(),
+			off: self.off + (begin * PAGE_SIZE) as u64,
+
+			pages,
+		})
+	}
+
+	/// Maps every page of the mapping onto `vmem`, allocating them if necessary.
+	///
+	/// Pages of writable private mappings are mapped for writing, so that no allocation is
+	/// required on later accesses.
+	///
+	/// **Note**: it is assumed the associated virtual memory is bound.
+	pub fn populate(&mut self, vmem: &mut VMem) -> EResult<()> {
+		let write = self.prot & PROT_WRITE != 0;
+		for offset in 0..self.size.get() {
+			self.map(offset, vmem, write)?;
+		}
+		Ok(())
+	}
+
+	/// Tells whether the page at the offset `offset` of the mapping is resident in memory.
+	///
+	/// For mapped files, a page that has not been accessed yet is resident if it is present in
+	/// the page cache.
+	pub fn is_resident(&self, offset: usize) -> bool {
+		match &self.pages[offset] {
+			Some(MappedPage::Frame(_)) => true,
+			Some(MappedPage::Swap(_)) => false,
+			None => self
+				.file
+				.as_ref()
+				.and_then(|file| file.node())
+				.is_some_and(|node| {
+					let file_off = self.off / PAGE_SIZE as u64 + offset as u64;
+					node.mapped.get(file_off).is_some()
+				}),
+		}
+	}
+
 	/// Synchronizes the data on the memory mapping back to the filesystem.
 	///
 	/// Arguments:
@@ -350,17 +550,20 @@ impl MemMapping {
 	/// - The mapping is not shared
 	/// - The mapping is not associated with a file
 	///
-	/// If the mapping is locked, the function returns [`utils::errno::EBUSY`].
-	pub fn sync(&self, vmem: &VMem, sync: bool) -> EResult<()> {
+	/// If `invalidate` is set and the mapping is locked, the function returns
+	/// [`utils::errno::EBUSY`].
+	pub fn sync(&self, vmem: &VMem, sync: bool, invalidate: bool) -> EResult<()> {
+		if unlikely(invalidate && self.locked) {
+			return Err(errno!(EBUSY));
+		}
 		if self.flags & (MAP_ANONYMOUS | MAP_PRIVATE) != 0 {
 			return Ok(());
 		}
-		// TODO if locked, EBUSY
 		if self.file.is_none() {
 			return Ok(());
 		}
 		let ts = current_time_ms(Clock::Boottime);
-		for frame in self.pages.iter().flatten() {
+		for frame in self.pages.iter().flatten().filter_map(MappedPage::frame) {
 			vmem.poll_dirty(self.addr, self.size.get());
 			if sync {
 				// TODO warn on error?
@@ -378,6 +581,7 @@ impl TryClone for MemMapping {
 			size: self.size,
 			prot: self.prot,
 			flags: self.flags,
+			locked: self.locked,
 
 			file: self.file.clone(),
 			off: self.off,
@@ -33,12 +33,18 @@ use crate::{
 		paging::{PAGE_FAULT_INSTRUCTION, PAGE_FAULT_WRITE},
 	},
 	file::{File, perm::AccessProfile, vfs},
-	memory::{COMPAT_PROCESS_END, PROCESS_END, VirtAddr, cache::RcFrame, vmem::VMem},
-	process::{mem_space::mapping::MappedFrame, scheduler::core_local},
+	memory::{
+		COMPAT_PROCESS_END, PROCESS_END, PhysAddr, VirtAddr, cache::RcFrame, swap::SwapArea, vmem,
+		vmem::VMem,
+	},
+	process::{
+		mem_space::mapping::{MappedFrame, MappedPage},
+		scheduler::core_local,
+	},
 	sync::mutex::IntMutex,
 };
 use core::{
-	alloc::AllocError, cmp::min, ffi::c_void, fmt, hint::unlikely, mem, num::NonZeroUsize,
+	alloc::AllocError, cmp::min, ffi::c_void, fmt, hint::unlikely, mem, num::NonZeroUsize, ptr,
 };
 use gap::MemGap;
 use mapping::MemMapping;
@@ -127,6 +133,16 @@ fn remove_gaps_in_range(
 	Ok(())
 }
 
+/// Locking of the mappings to be created, requested with `mlockall`.
+#[derive(Debug)]
+struct LockFuture {
+	/// The maximum number of locked pages, according to the `RLIMIT_MEMLOCK` limit at the time
+	/// of the request.
+	limit: usize,
+	/// If set, pages are not populated when locked, but when accessed.
+	on_fault: bool,
+}
+
 /// Inner state of the memory space, to use as a model for the virtual memory context.
 #[derive(Default, Debug)]
 struct MemSpaceState {
@@ -147,9 +163,20 @@ struct MemSpaceState {
 
 	/// The number of used virtual memory pages.
 	vmem_usage: usize,
+	/// If set, mappings are locked in memory upon creation.
+	lock_future: Option<LockFuture>,
 }
 
 impl MemSpaceState {
+	/// Returns the number of pages in locked mappings.
+	fn locked_pages(&self) -> usize {
+		self.mappings
+			.iter()
+			.filter(|(_, m)| m.locked)
+			.map(|(_, m)| m.size.get())
+			.sum()
+	}
+
 	/// Returns a reference to a gap with at least size `size`.
 	///
 	/// `size` is the minimum size of the gap to be returned.
@@ -267,21 +294,36 @@ impl MemSpace {
 		self.state.lock().vmem_usage
 	}
 
-	fn map_impl(
+	/// Returns the number of pages of the memory space that are allocated, either in memory or
+	/// in a swap area.
+	pub fn get_phys_usage(&self) -> usize {
+		let state = self.state.lock();
+		state.mappings.iter().map(|(_, m)| m.get_phys_usage()).sum()
+	}
+
+	/// Same as [`Self::get_phys_usage`], except the function returns `None` if the memory space
+	/// is already locked.
+	pub fn try_get_phys_usage(&self) -> Option<usize> {
+		let state = self.state.try_lock()?;
+		Some(state.mappings.iter().map(|(_, m)| m.get_phys_usage()).sum())
+	}
+
+	/// Reserves a range of `size` pages of virtual memory for a new mapping, removing the gaps
+	/// it covers.
+	///
+	/// `addr` and `flags` have the same meaning as for [`Self::map`]. Only the flags
+	/// [`MAP_FIXED`] and [`MAP_FIXED_NOREPLACE`] are relevant.
+	///
+	/// On success, the function returns the address of the range.
+	fn reserve_range(
 		transaction: &mut MemSpaceTransaction,
 		addr: VirtAddr,
 		size: NonZeroUsize,
-		prot: u8,
 		flags: i32,
-		file: Option<Arc<File>>,
-		off: u64,
-	) -> EResult<MemMapping> {
+	) -> EResult<VirtAddr> {
 		if unlikely(!addr.is_aligned_to(PAGE_SIZE)) {
 			return Err(errno!(EINVAL));
 		}
-		if unlikely(flags & (MAP_PRIVATE | MAP_SHARED) == 0) {
-			return Err(errno!(EINVAL));
-		}
 		if flags & MAP_FIXED_NOREPLACE != 0 {
 			// Check for mappings already present in range TODO: can be optimized
 			let used = transaction.state.mappings.iter().any(|(_, m)| {
@@ -293,11 +335,11 @@ impl MemSpace {
 				return Err(errno!(EEXIST));
 			}
 			remove_gaps_in_range(transaction, addr, size.get())?;
-			Ok(MemMapping::new(addr, size, prot, flags, file, off)?)
+			Ok(addr)
 		} else if flags & MAP_FIXED != 0 {
 			Self::unmap_impl(transaction, addr, size, true)?;
 			remove_gaps_in_range(transaction, addr, size.get())?;
-			Ok(MemMapping::new(addr, size, prot, flags, file, off)?)
+			Ok(addr)
 		} else {
 			// Use the address as a hint
 			let (gap, gap_off) = transaction
@@ -329,11 +371,35 @@ impl MemSpace {
 			if let Some(new_gap) = right_gap {
 				transaction.insert_gap(new_gap)?;
 			}
-			let addr = gap.get_begin() + gap_off * PAGE_SIZE;
-			Ok(MemMapping::new(addr, size, prot, flags, file, off)?)
+			Ok(gap.get_begin() + gap_off * PAGE_SIZE)
 		}
 	}
 
+	fn map_impl(
+		transaction: &mut MemSpaceTransaction,
+		addr: VirtAddr,
+		size: NonZeroUsize,
+		prot: u8,
+		flags: i32,
+		file: Option<Arc<File>>,
+		off: u64,
+	) -> EResult<MemMapping> {
+		if unlikely(flags & (MAP_PRIVATE | MAP_SHARED) == 0) {
+			return Err(errno!(EINVAL));
+		}
+		let addr = Self::reserve_range(transaction, addr, size, flags)?;
+		let mut mapping = MemMapping::new(addr, size, prot, flags, file, off)?;
+		// Lock the mapping if requested by `mlockall`
+		if let Some(lock_future) = &transaction.state.lock_future {
+			let locked = transaction.state.locked_pages();
+			if unlikely(locked + size.get() > lock_future.limit) {
+				return Err(errno!(EAGAIN));
+			}
+			mapping.locked = true;
+		}
+		Ok(mapping)
+	}
+
 	/// Maps a chunk of memory.
 	///
 	/// The function has complexity `O(log n)`.
@@ -364,8 +430,18 @@ impl MemSpace {
 		let mut transaction = MemSpaceTransaction::new(self);
 		let map = Self::map_impl(&mut transaction, addr, size, prot, flags, file, off)?;
 		let addr = map.addr;
+		let populate = map.locked
+			&& transaction
+				.state
+				.lock_future
+				.as_ref()
+				.is_some_and(|l| !l.on_fault);
 		transaction.insert_mapping(map)?;
 		transaction.commit();
+		if populate {
+			// Pages that cannot be populated now are allocated on access
+			let _ = self.populate(addr, size.get());
+		}
 		Ok(addr)
 	}
 
@@ -389,7 +465,7 @@ impl MemSpace {
 		map.pages
 			.iter_mut()
 			.zip(pages.iter().cloned())
-			.for_each(|(dst, src)| *dst = Some(MappedFrame::new(src)));
+			.for_each(|(dst, src)| *dst = Some(MappedPage::Frame(MappedFrame::new(src))));
 		// Commit
 		let addr = map.addr;
 		transaction.insert_mapping(map)?;
@@ -530,7 +606,11 @@ impl MemSpace {
 		let state = self.state.lock();
 		let mut vmem = self.vmem.lock();
 		// Clone first to mark as shared
-		let mappings = state.mappings.try_clone()?;
+		let mut mappings = state.mappings.try_clone()?;
+		// Memory locks are not inherited
+		for (_, m) in mappings.iter_mut() {
+			m.locked = false;
+		}
 		// Unmap to invalidate the virtual memory context
 		for (_, m) in &state.mappings {
 			vmem.unmap_range(m.addr, m.size.get());
@@ -544,6 +624,7 @@ impl MemSpace {
 				brk: state.brk,
 
 				vmem_usage: state.vmem_usage,
+				lock_future: None,
 			}),
 			vmem: IntMutex::new(unsafe { VMem::new() }),
 
@@ -625,29 +706,473 @@ impl MemSpace {
 			}
 		}
 		transaction.state.brk = addr;
+		let populate = transaction
+			.state
+			.lock_future
+			.as_ref()
+			.is_some_and(|l| !l.on_fault);
 		transaction.commit();
+		if populate && addr > old {
+			let begin = old.align_to(PAGE_SIZE);
+			let pages = (addr.0 - begin.0).div_ceil(PAGE_SIZE);
+			let _ = self.populate(begin, pages);
+		}
 		addr
 	}
 
+	/// Maps every page of the mappings in the range of `pages` pages beginning at `addr`,
+	/// allocating them if necessary.
+	///
+	/// **Note**: the memory space must be bound.
+	fn populate(&self, addr: VirtAddr, pages: usize) -> EResult<()> {
+		let mut state = self.state.lock();
+		let mut vmem = self.vmem.lock();
+		let end = addr + pages * PAGE_SIZE;
+		for (_, mapping) in state.mappings.range_mut(..end) {
+			let mapping_end = mapping.addr + mapping.size.get() * PAGE_SIZE;
+			if mapping_end > addr {
+				mapping.populate(&mut vmem)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Sets the `locked` attribute on the mappings in the range of `pages` pages beginning at
+	/// `addr`, splitting them if necessary.
+	///
+	/// `limit` is the maximum number of locked pages in the memory space.
+	///
+	/// If a page of the range is not mapped, the function returns [`errno::ENOMEM`]. If the
+	/// limit is exceeded, the function returns [`errno::EAGAIN`].
+	fn set_locked_impl(
+		transaction: &mut MemSpaceTransaction,
+		addr: VirtAddr,
+		pages: usize,
+		locked: bool,
+		limit: usize,
+	) -> EResult<()> {
+		// Check the whole range is mapped and count the pages to be locked
+		let mut new_locked = 0;
+		let mut i = 0;
+		while i < pages {
+			let mapping = transaction
+				.state
+				.get_mapping_for_addr(addr + i * PAGE_SIZE)
+				.ok_or_else(|| errno!(ENOMEM))?;
+			let inner_off = (addr.0 + i * PAGE_SIZE - mapping.addr.0) / PAGE_SIZE;
+			let len = min(pages - i, mapping.size.get() - inner_off);
+			if !mapping.locked {
+				new_locked += len;
+			}
+			i += len;
+		}
+		if locked && unlikely(transaction.state.locked_pages() + new_locked > limit) {
+			return Err(errno!(EAGAIN));
+		}
+		// Apply
+		let mut i = 0;
+		while i < pages {
+			let mapping = transaction
+				.state
+				.get_mapping_for_addr(addr + i * PAGE_SIZE)
+				.unwrap();
+			let mapping_begin = mapping.addr;
+			let inner_off = (addr.0 + i * PAGE_SIZE - mapping_begin.0) / PAGE_SIZE;
+			let len = min(pages - i, mapping.size.get() - inner_off);
+			i += len;
+			if mapping.locked == locked {
+				continue;
+			}
+			let (prev, mut mid, next) =
+				mapping.split_range(inner_off, NonZeroUsize::new(len).unwrap())?;
+			mid.locked = locked;
+			transaction.remove_mapping(mapping_begin)?;
+			if let Some(m) = prev {
+				transaction.insert_mapping(m)?;
+			}
+			transaction.insert_mapping(mid)?;
+			if let Some(m) = next {
+				transaction.insert_mapping(m)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Locks the pages in the range of `pages` pages beginning at `addr` in memory, preventing
+	/// them from being evicted.
+	///
+	/// Arguments:
+	/// - `limit` is the maximum number of locked pages in the memory space
+	/// - `on_fault` tells whether pages are to be populated when accessed instead of right away
+	///
+	/// If a page of the range is not mapped, the function returns [`errno::ENOMEM`]. If the
+	/// limit is exceeded, the function returns [`errno::EAGAIN`].
+	///
+	/// **Note**: the memory space must be bound.
+	pub fn lock(&self, addr: VirtAddr, pages: usize, limit: usize, on_fault: bool) -> EResult<()> {
+		let mut transaction = MemSpaceTransaction::new(self);
+		Self::set_locked_impl(&mut transaction, addr, pages, true, limit)?;
+		transaction.commit();
+		if !on_fault {
+			// Pages that cannot be populated now are allocated on access
+			let _ = self.populate(addr, pages);
+		}
+		Ok(())
+	}
+
+	/// Unlocks the pages in the range of `pages` pages beginning at `addr`.
+	///
+	/// If a page of the range is not mapped, the function returns [`errno::ENOMEM`].
+	pub fn unlock(&self, addr: VirtAddr, pages: usize) -> EResult<()> {
+		let mut transaction = MemSpaceTransaction::new(self);
+		Self::set_locked_impl(&mut transaction, addr, pages, false, usize::MAX)?;
+		transaction.commit();
+		Ok(())
+	}
+
+	/// Locks every mapping of the memory space in memory.
+	///
+	/// Arguments:
+	/// - `current` tells whether the existing mappings are locked
+	/// - `future` tells whether the mappings created afterward are locked
+	/// - `limit` is the maximum number of locked pages in the memory space
+	/// - `on_fault` tells whether pages are to be populated when accessed instead of right away
+	///
+	/// If the limit is exceeded, the function returns [`errno::EAGAIN`].
+	///
+	/// **Note**: the memory space must be bound.
+	pub fn lock_all(
+		&self,
+		current: bool,
+		future: bool,
+		limit: usize,
+		on_fault: bool,
+	) -> EResult<()> {
+		let mut transaction = MemSpaceTransaction::new(self);
+		if current {
+			let mappings = transaction
+				.state
+				.mappings
+				.iter()
+				.map(|(addr, m)| (*addr, m.size.get()))
+				.collect::<CollectResult<Vec<_>>>()
+				.0?;
+			let pages = mappings.iter().map(|(_, size)| size).sum::<usize>();
+			if unlikely(pages > limit) {
+				return Err(errno!(EAGAIN));
+			}
+			for (addr, size) in mappings {
+				Self::set_locked_impl(&mut transaction, addr, size, true, limit)?;
+			}
+		}
+		transaction.state.lock_future = future.then_some(LockFuture {
+			limit,
+			on_fault,
+		});
+		transaction.commit();
+		if current && !on_fault {
+			// Pages that cannot be populated now are allocated on access
+			let _ = self.populate(VirtAddr(0), COPY_BUFFER.0 / PAGE_SIZE);
+		}
+		Ok(())
+	}
+
+	/// Unlocks every mapping of the memory space, and stops locking the mappings created
+	/// afterward.
+	pub fn unlock_all(&self) -> EResult<()> {
+		let mut transaction = MemSpaceTransaction::new(self);
+		let mappings = transaction
+			.state
+			.mappings
+			.iter()
+			.filter(|(_, m)| m.locked)
+			.map(|(addr, m)| (*addr, m.size.get()))
+			.collect::<CollectResult<Vec<_>>>()
+			.0?;
+		for (addr, size) in mappings {
+			Self::set_locked_impl(&mut transaction, addr, size, false, usize::MAX)?;
+		}
+		transaction.state.lock_future = None;
+		transaction.commit();
+		Ok(())
+	}
+
+	/// Resizes and possibly moves the range of `old_size` pages beginning at `old_addr`.
+	///
+	/// Arguments:
+	/// - `new_size` is the new size of the range in pages
+	/// - `may_move` tells whether the range may be moved if it cannot be resized in place
+	/// - `new_addr` is the address at which the range must be moved. If `None`, the range is moved
+	///   only if necessary
+	/// - `limit` returns the maximum number of locked pages in the memory space. It is called only
+	///   if the range belongs to a locked mapping which grows
+	///
+	/// The range must be located in a single mapping. Pages beyond the end of the original range
+	/// are allocated on access.
+	///
+	/// On success, the function returns the new address of the range.
+	///
+	/// **Note**: the memory space must be bound.
+	pub fn remap(
+		&self,
+		old_addr: VirtAddr,
+		old_size: NonZeroUsize,
+		new_size: NonZeroUsize,
+		may_move: bool,
+		new_addr: Option<VirtAddr>,
+		limit: impl FnOnce() -> usize,
+	) -> EResult<VirtAddr> {
+		let mut transaction = MemSpaceTransaction::new(self);
+		let mapping = transaction
+			.state
+			.get_mapping_for_addr(old_addr)
+			.ok_or_else(|| errno!(EFAULT))?;
+		let mapping_begin = mapping.addr;
+		let mapping_end = mapping_begin + mapping.size.get() * PAGE_SIZE;
+		let inner_off = (old_addr.0 - mapping_begin.0) / PAGE_SIZE;
+		if unlikely(inner_off + old_size.get() > mapping.size.get()) {
+			return Err(errno!(EFAULT));
+		}
+		let locked = mapping.locked;
+		if locked && new_size > old_size {
+			let extra = new_size.get() - old_size.get();
+			if unlikely(transaction.state.locked_pages() + extra > limit()) {
+				return Err(errno!(EAGAIN));
+			}
+		}
+		// Shrink in place
+		if new_addr.is_none() && new_size <= old_size {
+			if let Some(tail) = NonZeroUsize::new(old_size.get() - new_size.get()) {
+				let tail_addr = old_addr + new_size.get() * PAGE_SIZE;
+				Self::unmap_impl(&mut transaction, tail_addr, tail, false)?;
+			}
+			transaction.commit();
+			return Ok(old_addr);
+		}
+		// Grow in place, if the range is at the end of the mapping and followed by a large enough
+		// gap
+		let extra = new_size.get().saturating_sub(old_size.get());
+		let in_place = new_addr.is_none()
+			&& old_addr + old_size.get() * PAGE_SIZE == mapping_end
+			&& transaction
+				.state
+				.get_gap_for_addr(mapping_end)
+				.is_some_and(|gap| {
+					gap.get_begin() == mapping_end && gap.get_size().get() >= extra
+				});
+		if in_place {
+			let size = NonZeroUsize::new(mapping.size.get() + extra).unwrap();
+			let new_mapping = mapping.remap(0, mapping_begin, size)?;
+			remove_gaps_in_range(&mut transaction, mapping_end, extra)?;
+			transaction.remove_mapping(mapping_begin)?;
+			transaction.insert_mapping(new_mapping)?;
+			transaction.commit();
+			if locked {
+				// Pages that cannot be populated now are allocated on access
+				let _ = self.populate(old_addr, new_size.get());
+			}
+			return Ok(old_addr);
+		}
+		if unlikely(!may_move) {
+			return Err(errno!(ENOMEM));
+		}
+		let flags = match new_addr {
+			Some(new_addr) => {
+				// The new range must not overlap the old one
+				let old_end = old_addr + old_size.get() * PAGE_SIZE;
+				let new_end = new_addr + new_size.get() * PAGE_SIZE;
+				if unlikely(new_addr < old_end && old_addr < new_end) {
+					return Err(errno!(EINVAL));
+				}
+				MAP_FIXED
+			}
+			None => 0,
+		};
+		// Insert the new mapping, then remove the old range in a separate transaction since the
+		// state is not updated until the first one is committed
+		let mut new_mapping = mapping.remap(inner_off, VirtAddr(0), new_size)?;
+		let addr = Self::reserve_range(
+			&mut transaction,
+			new_addr.unwrap_or_default(),
+			new_size,
+			flags,
+		)?;
+		new_mapping.addr = addr;
+		transaction.insert_mapping(new_mapping)?;
+		transaction.commit();
+		let mut transaction = MemSpaceTransaction::new(self);
+		if let Err(e) = Self::unmap_impl(&mut transaction, old_addr, old_size, false) {
+			drop(transaction);
+			// Remove the new mapping to restore the previous state
+			let _ = self.unmap(addr, new_size);
+			return Err(e);
+		}
+		transaction.commit();
+		if locked {
+			// Pages that cannot be populated now are allocated on access
+			let _ = self.populate(addr, new_size.get());
+		}
+		Ok(addr)
+	}
+
+	/// Writes the residency of the pages in the range beginning at `addr` to `vec`, one byte per
+	/// page. The least significant bit of each byte is set if the page is resident in memory.
+	///
+	/// If a page of the range is not mapped, the function returns [`errno::ENOMEM`].
+	pub fn mincore(&self, addr: VirtAddr, vec: &mut [u8]) -> EResult<()> {
+		let state = self.state.lock();
+		for (i, b) in vec.iter_mut().enumerate() {
+			let page_addr = addr + i * PAGE_SIZE;
+			let mapping = state
+				.get_mapping_for_addr(page_addr)
+				.ok_or_else(|| errno!(ENOMEM))?;
+			let offset = (page_addr.0 - mapping.addr.0) / PAGE_SIZE;
+			*b = mapping.is_resident(offset) as u8;
+		}
+		Ok(())
+	}
+
 	/// Synchronizes memory to the backing storage on the given range.
 	///
 	/// Arguments:
 	/// - `addr` is the address to the beginning of the range
 	/// - `pages` is the number of pages in the range
 	/// - `sync` tells whether the synchronization should be performed synchronously
-	pub fn sync(&self, addr: VirtAddr, pages: usize, sync: bool) -> EResult<()> {
+	/// - `invalidate` tells whether cached copies of the mappings are to be invalidated, which is
+	///   not possible on locked mappings
+	pub fn sync(&self, addr: VirtAddr, pages: usize, sync: bool, invalidate: bool) -> EResult<()> {
 		let state = self.state.lock();
 		let vmem = self.vmem.lock();
 		// Iterate over mappings
 		let mut i = 0;
 		while i < pages {
-			let mapping = state.get_mapping_for_addr(addr).ok_or(AllocError)?;
-			mapping.sync(&vmem, sync)?;
-			i += mapping.size.get();
+			let mapping = state
+				.get_mapping_for_addr(addr + i * PAGE_SIZE)
+				.ok_or(AllocError)?;
+			mapping.sync(&vmem, sync, invalidate)?;
+			let end = mapping.addr + mapping.size.get() * PAGE_SIZE;
+			i = (end.0 - addr.0) / PAGE_SIZE;
+		}
+		Ok(())
+	}
+
+	/// Returns the physical address backing the given virtual address, if it is located in a
+	/// shared mapping.
+	///
+	/// If the address is located in a private mapping, the function returns `None`. If it is not
+	/// mapped at all, the function returns [`errno::EFAULT`].
+	///
+	/// If the page is not present yet, the function allocates it.
+	pub fn get_shared_phys(&self, addr: VirtAddr) -> EResult<Option<PhysAddr>> {
+		let mut state = self.state.lock();
+		let mapping = state
+			.get_mut_mapping_for_addr(addr)
+			.ok_or_else(|| errno!(EFAULT))?;
+		if mapping.flags & MAP_SHARED == 0 {
+			return Ok(None);
+		}
+		let page_offset = (addr.0 - mapping.addr.0) / PAGE_SIZE;
+		if !matches!(mapping.pages[page_offset], Some(MappedPage::Frame(_))) {
+			let mut vmem = self.vmem.lock();
+			mapping.map(page_offset, &mut vmem, true)?;
+		}
+		let phys_addr = mapping.pages[page_offset]
+			.as_ref()
+			.and_then(MappedPage::frame)
+			.map(|page| page.phys_addr() + addr.0 % PAGE_SIZE);
+		Ok(phys_addr)
+	}
+
+	/// Writes `buf` at `addr`, regardless of the write permission of private mappings.
+	///
+	/// This allows debuggers to insert breakpoints in executable code. The written pages of
+	/// private mappings are copied first, so that the change is not visible to other processes.
+	///
+	/// If the range is not mapped, or covers a shared mapping without write permission, the
+	/// function returns [`errno::EFAULT`].
+	///
+	/// **Note**: the memory space must be bound.
+	pub fn write_force(&self, addr: VirtAddr, buf: &[u8]) -> EResult<()> {
+		let mut state = self.state.lock();
+		let mut vmem = self.vmem.lock();
+		let mut off = 0;
+		while off < buf.len() {
+			let addr = addr + off;
+			let mapping = state
+				.get_mut_mapping_for_addr(addr)
+				.ok_or_else(|| errno!(EFAULT))?;
+			if unlikely(mapping.flags & MAP_SHARED != 0 && mapping.prot & PROT_WRITE == 0) {
+				return Err(errno!(EFAULT));
+			}
+			// Get a private copy of the page
+			let page_offset = (addr.0 - mapping.addr.0) / PAGE_SIZE;
+			mapping.map(page_offset, &mut vmem, true)?;
+			let len = min(buf.len() - off, PAGE_SIZE - addr.0 % PAGE_SIZE);
+			unsafe {
+				vmem::smap_disable(|| {
+					vmem::write_ro(|| {
+						ptr::copy_nonoverlapping(buf[off..].as_ptr(), addr.as_ptr(), len);
+					});
+				});
+			}
+			off += len;
 		}
 		Ok(())
 	}
 
+	/// Evicts up to `count` pages of the memory space to swap areas.
+	///
+	/// Since this function is called to reclaim memory, it does nothing if the memory space is
+	/// already in use.
+	///
+	/// The function returns the number of evicted pages.
+	pub fn evict(&self, count: usize) -> usize {
+		let Some(mut state) = self.state.try_lock() else {
+			return 0;
+		};
+		let Some(mut vmem) = self.vmem.try_lock() else {
+			return 0;
+		};
+		let mut evicted = 0;
+		for (_, mapping) in state.mappings.iter_mut() {
+			if evicted >= count {
+				break;
+			}
+			evicted += mapping.evict(&mut vmem, count - evicted);
+		}
+		evicted
+	}
+
+	/// Reads every page of the memory space that has been evicted to `area` back into memory.
+	pub fn swap_in_area(&self, area: &SwapArea) -> EResult<()> {
+		let mut state = self.state.lock();
+		let mut vmem = self.vmem.lock();
+		for (_, mapping) in state.mappings.iter_mut() {
+			mapping.swap_in_area(&mut vmem, area)?;
+		}
+		Ok(())
+	}
+
+	/// Releases the pages of the private mappings of the memory space, so that the memory of a
+	/// process killed by the OOM killer can be reclaimed before it exits.
+	///
+	/// If the memory space is already locked, the function does nothing.
+	///
+	/// The function returns the number of released pages.
+	pub fn reap(&self) -> usize {
+		let Some(mut state) = self.state.try_lock() else {
+			return 0;
+		};
+		let Some(mut vmem) = self.vmem.try_lock() else {
+			return 0;
+		};
+		state
+			.mappings
+			.iter_mut()
+			.map(|(_, mapping)| mapping.release(&mut vmem))
+			.sum()
+	}
+
 	/// Function called whenever the CPU triggered a page fault for the context.
 	///
 	/// This function determines whether the process should continue or not.
@@ -676,6 +1201,11 @@ impl MemSpace {
 		}
 		// Map the accessed page
 		let page_offset = (addr.0 - mapping.addr.0) / PAGE_SIZE;
+		// A swap entry left in the page table must match the mapping's
+		debug_assert!(
+			vmem.swap_entry(addr)
+				.is_none_or(|entry| mapping.swap_entry(page_offset) == Some(entry))
+		);
 		mapping.map(page_offset, &mut vmem, write)?;
 		Ok(true)
 	}
@@ -695,7 +1225,7 @@ impl Drop for MemSpace {
 		let mappings = mem::take(&mut state.mappings);
 		for (_, m) in mappings {
 			// Ignore I/O errors
-			let _ = m.sync(&vmem, true);
+			let _ = m.sync(&vmem, true, false);
 		}
 	}
 }
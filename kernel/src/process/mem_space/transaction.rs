@@ -178,7 +178,7 @@ impl<'m> MemSpaceTransaction<'m> {
 		if let Some(mapping) = self.state.mappings.get(&mapping_begin) {
 			self.mappings_discard.insert(mapping_begin)?;
 			// Sync to disk
-			mapping.sync(&self.vmem, true)?;
+			mapping.sync(&self.vmem, true, false)?;
 			// Apply to vmem. No rollback is required since this would be corrected by a page fault
 			self.vmem.unmap_range(mapping.addr, mapping.size.get());
 			// Update usage
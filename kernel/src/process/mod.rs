@@ -23,8 +23,11 @@
 //! a scheduler.
 
 pub mod exec;
+pub mod futex;
 pub mod mem_space;
 pub mod pid;
+pub mod ptrace;
+pub mod rlimit;
 pub mod rusage;
 pub mod scheduler;
 pub mod signal;
@@ -39,24 +42,38 @@ use crate::{
 		File, O_RDWR,
 		fd::{FileDescriptorTable, NewFDConstraint},
 		perm::AccessProfile,
-		vfs,
+		signalfd, vfs,
 		vfs::ResolutionSettings,
 	},
 	memory::{VirtAddr, buddy, buddy::FrameOrder, oom, user, user::UserPtr},
 	process::{
 		pid::{IDLE_PID, INIT_PID, PidHandle},
-		rusage::Rusage,
+		rlimit::{DEFAULT_MEMLOCK, RLimit},
+		rusage::{CpuTime, Rusage},
 		scheduler::{
-			SCHEDULER, Scheduler, core_local, switch,
+			SCHEDULER, Scheduler, core_local,
+			policy::SchedEntity,
+			switch,
 			switch::{KThreadEntry, idle_task},
 		},
-		signal::{SIGNALS_COUNT, SigSet},
+		signal::{
+			AltStack, BUS_ADRALN, BUS_ADRERR, CLD_CONTINUED, CLD_EXITED, CLD_KILLED, CLD_STOPPED,
+			FPE_INTDIV, ILL_ILLOPN, SEGV_ACCERR, SEGV_MAPERR, SI_KERNEL, SI_TKILL, SI_USER,
+			SIGNALS_COUNT, SS_DISABLE, SS_ONSTACK, SigInfo, SigSet, TRAP_BRKPT, TRAP_TRACE,
+		},
 	},
 	register_get,
 	sync::mutex::Mutex,
 	syscall::FromSyscallArg,
-	time::timer::TimerManager,
+	time::{
+		clock::{Clock, current_time_ns},
+		timer::TimerManager,
+		unit::{TimeUnit, Timestamp, Timeval},
+	},
+	tty::TTY,
 };
+#[cfg(target_arch = "x86_64")]
+use core::sync::atomic::AtomicU64;
 use core::{
 	ffi::c_int,
 	fmt,
@@ -66,13 +83,15 @@ use core::{
 	mem::ManuallyDrop,
 	ptr::NonNull,
 	sync::atomic::{
-		AtomicBool, AtomicPtr, AtomicU8, AtomicU32,
+		AtomicBool, AtomicI16, AtomicPtr, AtomicU8, AtomicU32, AtomicUsize,
 		Ordering::{Acquire, Relaxed, Release, SeqCst},
 	},
 };
+use futex::RobustList;
 use mem_space::MemSpace;
 use pid::Pid;
-use signal::{Signal, SignalHandler};
+use ptrace::Ptrace;
+use signal::{Signal, SignalAction, SignalHandler};
 use utils::{
 	collections::{
 		path::{Path, PathBuf},
@@ -116,6 +135,8 @@ pub const TLS_ENTRIES_COUNT: usize = 3;
 /// When handling a signal, the kernel must make sure not to clobber this zone, thus an offset is
 /// added.
 const REDZONE_SIZE: usize = 128;
+/// The maximum number of pending realtime signals of a thread.
+const SIGQUEUE_MAX: usize = 1024;
 
 /// An enumeration containing possible states for a process.
 #[repr(u8)]
@@ -179,6 +200,10 @@ pub struct ForkOptions {
 	/// If `true`, the parent and child processes both share the same signal
 	/// handlers table.
 	pub share_sighand: bool,
+	/// If `true`, the child is a new thread in the parent's thread group.
+	///
+	/// This requires both `share_memory` and `share_sighand` to be set.
+	pub thread: bool,
 }
 
 /// Wrapper for the kernel stack, allowing to free it on drop.
@@ -218,6 +243,16 @@ pub struct ProcessLinks {
 	group_leader: Option<Arc<Process>>,
 	/// The list of processes in the process group.
 	pub process_group: Vec<Pid>,
+	/// The process's session leader. The PID of the session leader is the SID of this process.
+	///
+	/// If `None`, the process is its own leader (to avoid self reference).
+	session_leader: Option<Arc<Process>>,
+	/// The leader of the thread group.
+	///
+	/// If `None`, the thread is its own leader (to avoid self reference).
+	thread_leader: Option<Arc<Process>>,
+	/// The TIDs of the other threads in the thread group. This is used only on the leader.
+	pub threads: Vec<Pid>,
 }
 
 /// A process's filesystem access information.
@@ -258,8 +293,18 @@ pub struct ProcessSignal {
 	pub handlers: Arc<Mutex<[SignalHandler; SIGNALS_COUNT]>>,
 	/// A bitfield storing the set of blocked signals.
 	pub sigmask: SigSet,
+	/// The signal mask to restore once a signal has been handled, if a system call temporarily
+	/// replaced it (see [`signal::with_sigmask`]).
+	pub saved_sigmask: Option<SigSet>,
 	/// A bitfield storing the set of pending signals.
 	sigpending: SigSet,
+	/// Information about pending signals, in the order they have been sent.
+	///
+	/// A standard signal has at most one entry, while a realtime signal has one per instance.
+	/// A pending signal may have no entry if memory could not be allocated.
+	queue: Vec<SigInfo>,
+	/// The alternate stack on which signal handlers can be executed.
+	pub altstack: Option<AltStack>,
 
 	/// The exit status of the process after exiting.
 	pub exit_status: ExitStatus,
@@ -271,9 +316,12 @@ impl ProcessSignal {
 	/// Creates a new instance.
 	pub fn new() -> AllocResult<Self> {
 		Ok(ProcessSignal {
-			handlers: Arc::new(Default::default())?,
+			handlers: Arc::new(Mutex::new(signal::default_handlers()))?,
 			sigmask: Default::default(),
+			saved_sigmask: None,
 			sigpending: Default::default(),
+			queue: Vec::new(),
+			altstack: None,
 
 			exit_status: 0,
 			termsig: 0,
@@ -285,37 +333,124 @@ impl ProcessSignal {
 		self.sigmask.is_set(sig as _)
 	}
 
-	/// Returns the ID of the next signal to be handled, clearing it from the pending signals mask.
+	/// Tells whether the signal `sig` is ignored, either explicitly or by default.
+	pub fn is_ignored(&self, sig: Signal) -> bool {
+		match &self.handlers.lock()[sig as usize] {
+			SignalHandler::Ignore => true,
+			SignalHandler::Default => sig.get_default_action() == SignalAction::Ignore,
+			SignalHandler::Handler(_) => false,
+		}
+	}
+
+	/// Returns the signal mask to be restored after a signal handler returns, taking the mask
+	/// saved by a system call, if any.
+	pub fn take_saved_sigmask(&mut self) -> SigSet {
+		self.saved_sigmask.take().unwrap_or(self.sigmask)
+	}
+
+	/// Restores the signal mask saved by a system call, if any.
+	pub fn restore_sigmask(&mut self) {
+		if let Some(sigmask) = self.saved_sigmask.take() {
+			self.sigmask = sigmask;
+		}
+	}
+
+	/// Returns the description of the alternate signal stack as a tuple `(ss_sp, ss_flags,
+	/// ss_size)`, in the format of the `stack_t` structure.
+	///
+	/// `sp` is the current stack pointer of the thread.
+	pub fn altstack_info(&self, sp: usize) -> (usize, i32, usize) {
+		match self.altstack {
+			Some(stack) if stack.contains(sp) => (stack.sp, SS_ONSTACK, stack.size),
+			Some(stack) => (stack.sp, 0, stack.size),
+			None => (0, SS_DISABLE, 0),
+		}
+	}
+
+	/// Makes the signal described by `info` pending.
+	///
+	/// A standard signal that is already pending is discarded.
+	///
+	/// If the signal cannot be queued, the function returns [`errno::EAGAIN`].
+	fn queue(&mut self, info: SigInfo) -> EResult<()> {
+		let sig = info.si_signo;
+		if !sig.is_realtime() {
+			if !self.sigpending.is_set(sig as _) {
+				self.sigpending.set(sig as _);
+				// On failure, the signal remains pending without information
+				let _ = self.queue.push(info);
+			}
+			return Ok(());
+		}
+		let res = if self.queue.len() < SIGQUEUE_MAX {
+			self.queue.push(info).map_err(Into::into)
+		} else {
+			Err(errno!(EAGAIN))
+		};
+		// A realtime signal sent by `sigqueue` or a timer fails when it cannot be queued.
+		// Otherwise, it remains pending without information
+		match res {
+			Err(e) if info.si_code < 0 && info.si_code != SI_TKILL => return Err(e),
+			_ => {}
+		}
+		self.sigpending.set(sig as _);
+		Ok(())
+	}
+
+	/// Removes an instance of the pending signal `sig`, returning its information.
+	fn dequeue(&mut self, sig: Signal) -> SigInfo {
+		let info = self
+			.queue
+			.iter()
+			.position(|info| info.si_signo == sig)
+			.map(|i| self.queue.remove(i));
+		if !self.queue.iter().any(|info| info.si_signo == sig) {
+			self.sigpending.clear(sig as _);
+		}
+		info.unwrap_or_else(|| SigInfo::new(sig, SI_USER))
+	}
+
+	/// Returns the information of the next signal to be handled, removing it from the pending
+	/// signals.
 	///
 	/// If no signal is pending, the function returns `None`.
-	pub fn next_signal(&mut self) -> Option<Signal> {
+	pub fn next_signal(&mut self) -> Option<SigInfo> {
 		if self.sigpending.is_empty() {
 			return None;
 		}
-		let sig = self
-			.sigpending
+		let sig = self.sigpending.iter().find_map(|i| {
+			let s = Signal::try_from(i as c_int).ok()?;
+			(!s.can_catch() || !self.sigmask.is_set(i)).then_some(s)
+		})?;
+		Some(self.dequeue(sig))
+	}
+
+	/// Returns the set of pending signals, including blocked ones.
+	pub fn pending(&self) -> SigSet {
+		self.sigpending
+	}
+
+	/// Returns the information of the lowest pending signal in `mask`, removing it from the
+	/// pending signals, whether it is blocked or not.
+	///
+	/// If no such signal is pending, the function returns `None`.
+	pub fn take_signal(&mut self, mask: SigSet) -> Option<SigInfo> {
+		let pending = SigSet(self.sigpending.0 & mask.0);
+		let sig = pending
 			.iter()
-			.enumerate()
-			.filter(|(_, b)| *b)
-			.filter_map(|(i, _)| {
-				let s = Signal::try_from(i as c_int).ok()?;
-				(!s.can_catch() || !self.sigmask.is_set(i)).then_some(s)
-			})
-			.next();
-		if let Some(id) = sig {
-			self.sigpending.clear(id as _);
-		}
-		sig
+			.find_map(|i| Signal::try_from(i as c_int).ok())?;
+		Some(self.dequeue(sig))
 	}
 }
 
 /// The **Process Control Block** (PCB). This structure stores all the information
 /// about a process.
 pub struct Process {
-	/// The ID of the process.
-	pid: PidHandle,
-	/// The thread ID of the process.
-	pub tid: Pid,
+	/// The ID of the thread, unique among all threads.
+	tid: PidHandle,
+	/// The ID of the process, shared by all threads of the thread group. This is the TID of the
+	/// group's leader.
+	pid: Pid,
 
 	/// The current state of the process.
 	state: AtomicU8,
@@ -328,10 +463,23 @@ pub struct Process {
 	kernel_stack: KernelStack,
 	/// Kernel stack pointer of saved context.
 	kernel_sp: AtomicPtr<u8>,
+	/// The index of the CPU core whose run queue holds the process.
+	core: AtomicUsize,
+	/// Tells whether the process's context is in use by a core, either running or being switched
+	/// out. If set, the process cannot move to another core.
+	on_core: AtomicBool,
+	/// Scheduling parameters.
+	pub sched: SchedEntity,
 	/// The process's FPU state.
-	fpu: Mutex<FxState>,
+	pub fpu: Mutex<FxState>,
 	/// TLS entries.
 	pub tls: Mutex<[gdt::Entry; TLS_ENTRIES_COUNT]>, // TODO rwlock
+	/// The userspace FS segment base, saved while the process is switched out.
+	#[cfg(target_arch = "x86_64")]
+	pub fs_base: AtomicU64,
+	/// The userspace GS segment base, saved while the process is switched out.
+	#[cfg(target_arch = "x86_64")]
+	pub gs_base: AtomicU64,
 
 	/// The virtual memory of the process.
 	pub mem_space: UnsafeMut<Option<Arc<MemSpace>>>,
@@ -348,6 +496,23 @@ pub struct Process {
 
 	/// The process's resources usage.
 	pub rusage: Mutex<Rusage>,
+	/// The CPU time consumed by the thread.
+	pub cputime: CpuTime,
+	/// The adjustment applied to the process's OOM score, in the range `-1000..=1000`.
+	///
+	/// This value is meaningful on the thread group's leader only.
+	pub oom_score_adj: AtomicI16,
+	/// The limit on the number of bytes of memory the process may lock into RAM.
+	///
+	/// This value is meaningful on the thread group's leader only.
+	pub memlock_limit: Mutex<RLimit>,
+
+	/// The address of the thread ID to clear and wake upon exit. If zero, nothing is done.
+	pub clear_child_tid: AtomicUsize,
+	/// The robust futex list of the thread.
+	pub robust_list: Mutex<RobustList>,
+	/// The tracing state of the process.
+	pub ptrace: Mutex<Ptrace>,
 }
 
 /// Initializes processes system. This function must be called only once, at
@@ -365,15 +530,21 @@ pub(crate) fn init() -> EResult<()> {
 		if unlikely(proc.is_idle_task()) {
 			return CallbackResult::Panic;
 		}
+		let pc = frame.get_program_counter();
 		match id {
 			// Divide-by-zero
 			// x87 Floating-Point Exception
 			// SIMD Floating-Point Exception
-			0x00 | 0x10 | 0x13 => proc.kill(Signal::SIGFPE),
+			0x00 | 0x10 | 0x13 => {
+				let code = if id == 0x00 { FPE_INTDIV } else { SI_KERNEL };
+				proc.kill_fault(SigInfo::fault(Signal::SIGFPE, code, pc))
+			}
+			// Debug (single-step)
+			0x01 => proc.kill_fault(SigInfo::fault(Signal::SIGTRAP, TRAP_TRACE, pc)),
 			// Breakpoint
-			0x03 => proc.kill(Signal::SIGTRAP),
+			0x03 => proc.kill_fault(SigInfo::fault(Signal::SIGTRAP, TRAP_BRKPT, pc)),
 			// Invalid Opcode
-			0x06 => proc.kill(Signal::SIGILL),
+			0x06 => proc.kill_fault(SigInfo::fault(Signal::SIGILL, ILL_ILLOPN, pc)),
 			// General Protection Fault
 			0x0d => {
 				// Get the instruction opcode
@@ -383,11 +554,11 @@ pub(crate) fn init() -> EResult<()> {
 				if opcode == Ok(Some(HLT_INSTRUCTION)) {
 					proc.exit(frame.get_syscall_id() as _);
 				} else {
-					proc.kill(Signal::SIGSEGV);
+					proc.kill_fault(SigInfo::new(Signal::SIGSEGV, SI_KERNEL));
 				}
 			}
 			// Alignment Check
-			0x11 => proc.kill(Signal::SIGBUS),
+			0x11 => proc.kill_fault(SigInfo::fault(Signal::SIGBUS, BUS_ADRALN, pc)),
 			_ => {}
 		}
 		CallbackResult::Continue
@@ -412,14 +583,30 @@ pub(crate) fn init() -> EResult<()> {
 						return CallbackResult::Panic;
 					}
 				} else {
-					Process::current().kill(Signal::SIGSEGV);
+					// Bit 0 of the error code is set if the page is present
+					let code = if code & 0x1 != 0 {
+						SEGV_ACCERR
+					} else {
+						SEGV_MAPERR
+					};
+					let info = SigInfo::fault(Signal::SIGSEGV, code, accessed_addr.0);
+					Process::current().kill_fault(info);
 				}
 			}
-			Err(_) => Process::current().kill(Signal::SIGBUS),
+			// Retry once memory has been reclaimed. If the process has been killed by the OOM
+			// killer instead, it exits before retrying
+			Err(e) if e.as_int() == errno::ENOMEM => {
+				let _ = oom::reclaim();
+			}
+			Err(_) => {
+				let info = SigInfo::fault(Signal::SIGBUS, BUS_ADRERR, accessed_addr.0);
+				Process::current().kill_fault(info);
+			}
 		}
 		CallbackResult::Continue
 	};
 	let _ = ManuallyDrop::new(event::register_callback(0x00, callback)?);
+	let _ = ManuallyDrop::new(event::register_callback(0x01, callback)?);
 	let _ = ManuallyDrop::new(event::register_callback(0x03, callback)?);
 	let _ = ManuallyDrop::new(event::register_callback(0x06, callback)?);
 	let _ = ManuallyDrop::new(event::register_callback(0x0d, callback)?);
@@ -461,16 +648,16 @@ impl Process {
 		entry: KThreadEntry,
 		queue: bool,
 	) -> AllocResult<Arc<Self>> {
-		let pid = match pid {
+		let tid = match pid {
 			Some(pid) => PidHandle::mark_used(pid)?,
 			None => PidHandle::unique()?,
 		};
-		let tid = *pid;
+		let pid = *tid;
 		let kernel_stack = KernelStack::new()?;
 		let kernel_sp = unsafe { switch::init_kthread(kernel_stack.top(), entry) };
 		let thread = Arc::new(Self {
-			pid,
 			tid,
+			pid,
 
 			state: AtomicU8::new(State::Running as _),
 			vfork_done: AtomicBool::new(false),
@@ -478,8 +665,15 @@ impl Process {
 
 			kernel_stack,
 			kernel_sp: AtomicPtr::new(kernel_sp),
+			core: AtomicUsize::new(0),
+			on_core: AtomicBool::new(false),
+			sched: Default::default(),
 			fpu: Mutex::new(FxState([0; 512])),
 			tls: Default::default(),
+			#[cfg(target_arch = "x86_64")]
+			fs_base: AtomicU64::new(0),
+			#[cfg(target_arch = "x86_64")]
+			gs_base: AtomicU64::new(0),
 
 			// TODO this is not needed. find a way to avoid init
 			mem_space: Default::default(),
@@ -495,6 +689,13 @@ impl Process {
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: Default::default(),
+			oom_score_adj: Default::default(),
+			memlock_limit: Mutex::new(RLimit::new(DEFAULT_MEMLOCK)),
+
+			clear_child_tid: Default::default(),
+			robust_list: Default::default(),
+			ptrace: Default::default(),
 		})?;
 		if queue {
 			SCHEDULER.lock().add_process(thread.clone())?;
@@ -538,8 +739,8 @@ impl Process {
 		};
 		let root_dir = vfs::get_file_from_path(Path::root(), &rs)?;
 		let proc = Arc::new(Self {
-			pid: PidHandle::mark_used(INIT_PID)?,
-			tid: INIT_PID,
+			tid: PidHandle::mark_used(INIT_PID)?,
+			pid: INIT_PID,
 
 			state: AtomicU8::new(State::Running as _),
 			vfork_done: AtomicBool::new(false),
@@ -547,8 +748,15 @@ impl Process {
 
 			kernel_stack: KernelStack::new()?,
 			kernel_sp: AtomicPtr::default(),
+			core: AtomicUsize::new(0),
+			on_core: AtomicBool::new(false),
+			sched: Default::default(),
 			fpu: Mutex::new(FxState([0; 512])),
 			tls: Default::default(),
+			#[cfg(target_arch = "x86_64")]
+			fs_base: AtomicU64::new(0),
+			#[cfg(target_arch = "x86_64")]
+			gs_base: AtomicU64::new(0),
 
 			mem_space: UnsafeMut::new(None),
 			fs: Mutex::new(ProcessFs {
@@ -560,9 +768,12 @@ impl Process {
 			file_descriptors: UnsafeMut::new(Some(Arc::new(Mutex::new(file_descriptors))?)),
 			timer_manager: Arc::new(Mutex::new(TimerManager::new(INIT_PID)?))?,
 			signal: Mutex::new(ProcessSignal {
-				handlers: Arc::new(Default::default())?,
+				handlers: Arc::new(Mutex::new(signal::default_handlers()))?,
 				sigmask: Default::default(),
+				saved_sigmask: None,
 				sigpending: Default::default(),
+				queue: Vec::new(),
+				altstack: None,
 
 				exit_status: 0,
 				termsig: 0,
@@ -570,26 +781,62 @@ impl Process {
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: Default::default(),
+			oom_score_adj: Default::default(),
+			memlock_limit: Mutex::new(RLimit::new(DEFAULT_MEMLOCK)),
+
+			clear_child_tid: Default::default(),
+			robust_list: Default::default(),
+			ptrace: Default::default(),
 		})?;
 		SCHEDULER.lock().add_process(proc.clone())?;
+		// The TTY is the controlling terminal of the init process's session
+		TTY.display.lock().set_session(INIT_PID, 0);
 		Ok(proc)
 	}
 
-	/// Returns the process's ID.
+	/// Returns the process's ID, that is the ID of the thread group.
 	#[inline]
 	pub fn get_pid(&self) -> Pid {
-		*self.pid
+		self.pid
+	}
+
+	/// Returns the thread's ID.
+	#[inline]
+	pub fn get_tid(&self) -> Pid {
+		*self.tid
+	}
+
+	/// Tells whether the thread is the leader of its thread group.
+	#[inline]
+	pub fn is_thread_leader(&self) -> bool {
+		*self.tid == self.pid
+	}
+
+	/// Returns the index of the CPU core the process is attached to.
+	#[inline]
+	pub fn get_core(&self) -> usize {
+		self.core.load(Acquire)
 	}
 
 	/// Tells whether the process is an idle task.
 	pub fn is_idle_task(&self) -> bool {
-		*self.pid == IDLE_PID
+		*self.tid == IDLE_PID
 	}
 
 	/// Tells whether the process is the init process.
 	#[inline(always)]
 	pub fn is_init(&self) -> bool {
-		*self.pid == INIT_PID
+		*self.tid == INIT_PID
+	}
+
+	/// Returns the leader of the process's thread group.
+	pub fn thread_leader(this: &Arc<Self>) -> Arc<Self> {
+		this.links
+			.lock()
+			.thread_leader
+			.clone()
+			.unwrap_or_else(|| this.clone())
 	}
 
 	/// Returns the process group ID.
@@ -621,12 +868,40 @@ impl Process {
 		if let Some(leader) = new_leader {
 			let mut links = leader.links.lock();
 			if let Err(i) = links.process_group.binary_search(&pid) {
-				oom::wrap(|| links.process_group.insert(i, pid));
+				oom::wrap_nofail(|| links.process_group.insert(i, pid));
 			}
 		}
 		Ok(())
 	}
 
+	/// Returns the session ID.
+	pub fn get_sid(&self) -> Pid {
+		self.links
+			.lock()
+			.session_leader
+			.as_ref()
+			.map(|p| p.get_pid())
+			.unwrap_or(self.get_pid())
+	}
+
+	/// Makes the process the leader of a new session and of a new process group in it.
+	///
+	/// The new session has no controlling terminal.
+	///
+	/// If the process is already a process group leader, the function returns
+	/// [`errno::EPERM`].
+	pub fn set_sid(&self) -> EResult<()> {
+		{
+			let links = self.links.lock();
+			if links.group_leader.is_none() || !links.process_group.is_empty() {
+				return Err(errno!(EPERM));
+			}
+		}
+		self.set_pgid(self.get_pid())?;
+		self.links.lock().session_leader = None;
+		Ok(())
+	}
+
 	/// The function tells whether the process is in an orphaned process group.
 	pub fn is_in_orphan_process_group(&self) -> bool {
 		self.links
@@ -714,9 +989,9 @@ impl Process {
 			);
 			// Update the number of running processes
 			if new_state == State::Running {
-				SCHEDULER.lock().increment_running();
+				SCHEDULER.lock().activate(self);
 			} else if old_state == State::Running {
-				SCHEDULER.lock().decrement_running();
+				SCHEDULER.lock().deactivate(self);
 			}
 			if new_state == State::Zombie {
 				if self.is_init() {
@@ -728,39 +1003,130 @@ impl Process {
 					// bound
 					*self.file_descriptors.get_mut() = None;
 				}
-				// Attach every child to the init process
-				let init_proc = Process::get_by_pid(INIT_PID).unwrap();
-				let children = mem::take(&mut self.links.lock().children);
-				for child_pid in children {
-					// Check just in case
-					if child_pid == *self.pid {
-						continue;
+				// Set vfork as done just in case
+				self.vfork_wake();
+				ptrace::exit(self);
+				let thread_leader = self.links.lock().thread_leader.clone();
+				match thread_leader {
+					Some(leader) => {
+						let group_exited = {
+							let mut links = leader.links.lock();
+							if let Ok(i) = links.threads.binary_search(&self.get_tid()) {
+								links.threads.remove(i);
+							}
+							links.threads.is_empty() && leader.get_state() == State::Zombie
+						};
+						// Nobody waits on threads other than the leader: release it right away
+						SCHEDULER.lock().remove_process(self.get_tid());
+						if group_exited {
+							leader.notify_exit();
+						}
 					}
-					// TODO do the same for process group members
-					if let Some(child) = Process::get_by_pid(child_pid) {
-						child.links.lock().parent = Some(init_proc.clone());
-						oom::wrap(|| init_proc.add_child(child_pid));
+					None => {
+						let group_exited = self.links.lock().threads.is_empty();
+						if group_exited {
+							self.notify_exit();
+						}
 					}
 				}
-				// Set vfork as done just in case
-				self.vfork_wake();
+				return;
 			}
 			// Send SIGCHLD
-			if matches!(new_state, State::Running | State::Stopped | State::Zombie) {
+			if matches!(new_state, State::Running | State::Stopped) {
 				let links = self.links.lock();
-				if let Some(parent) = &links.parent {
-					parent.kill(Signal::SIGCHLD);
+				if let (None, Some(parent)) = (&links.thread_leader, &links.parent) {
+					let (code, status) = if new_state == State::Stopped {
+						(CLD_STOPPED, self.signal.lock().termsig as _)
+					} else {
+						(CLD_CONTINUED, Signal::SIGCONT as _)
+					};
+					let _ = parent.kill_process_info(self.sigchld_info(code, status));
 				}
 			}
 		});
 	}
 
+	/// Notifies the parent that the process, which is the leader of its thread group, has exited
+	/// along with every other thread of the group.
+	///
+	/// Children of the process are attached to the init process.
+	fn notify_exit(&self) {
+		// Attach every child to the init process
+		let init_proc = Process::get_by_pid(INIT_PID).unwrap();
+		let children = mem::take(&mut self.links.lock().children);
+		for child_pid in children {
+			// Check just in case
+			if child_pid == self.pid {
+				continue;
+			}
+			// TODO do the same for process group members
+			if let Some(child) = Process::get_by_pid(child_pid) {
+				child.links.lock().parent = Some(init_proc.clone());
+				oom::wrap_nofail(|| init_proc.add_child(child_pid));
+			}
+		}
+		// The controlling process exited: hang up the session's terminal
+		if self.get_sid() == self.pid {
+			TTY.hangup(self.pid);
+		}
+		// Send SIGCHLD
+		let info = {
+			let signal = self.signal.lock();
+			if signal.termsig != 0 {
+				self.sigchld_info(CLD_KILLED, signal.termsig as _)
+			} else {
+				self.sigchld_info(CLD_EXITED, signal.exit_status as _)
+			}
+		};
+		let links = self.links.lock();
+		if let Some(parent) = &links.parent {
+			let _ = parent.kill_process_info(info);
+		}
+	}
+
+	/// Returns the information of the `SIGCHLD` signal telling the parent about a change of
+	/// state of the process.
+	///
+	/// Arguments:
+	/// - `code` is the code of the change.
+	/// - `status` is the exit status or the signal that caused the change.
+	fn sigchld_info(&self, code: i32, status: i32) -> SigInfo {
+		let uid = self.fs.lock().access_profile.uid;
+		let (utime, stime) = self.cputime.get();
+		// Times are expressed in clock ticks
+		let ticks = |ns: Timestamp| (ns / 10_000_000) as i64;
+		SigInfo {
+			si_pid: self.get_pid(),
+			si_uid: uid,
+			si_status: status,
+			si_utime: ticks(utime),
+			si_stime: ticks(stime),
+			..SigInfo::new(Signal::SIGCHLD, code)
+		}
+	}
+
+	/// Returns the resources usage of the thread.
+	pub fn get_rusage(&self) -> Rusage {
+		let mut rusage = self.rusage.lock().clone();
+		let (utime, stime) = self.cputime.get();
+		rusage.ru_utime = Timeval::from_nano(utime);
+		rusage.ru_stime = Timeval::from_nano(stime);
+		rusage
+	}
+
 	/// Tells whether there is a pending signal on the process.
 	pub fn has_pending_signal(&self) -> bool {
 		let signal = self.signal.lock();
 		signal.sigpending.0 & !signal.sigmask.0 != 0
 	}
 
+	/// Tells whether the process is being killed, that is if it has a pending `SIGKILL` or has
+	/// already exited.
+	pub fn is_killed(&self) -> bool {
+		self.get_state() == State::Zombie
+			|| self.signal.lock().sigpending.is_set(Signal::SIGKILL as _)
+	}
+
 	/// Wakes up the process if in [`State::Sleeping`] state.
 	pub fn wake(&self) {
 		// TODO make sure the ordering is right
@@ -776,7 +1142,7 @@ impl Process {
 		);
 		// Update the number of running processes
 		if res.is_ok() {
-			SCHEDULER.lock().increment_running();
+			SCHEDULER.lock().activate(self);
 		}
 	}
 
@@ -811,6 +1177,22 @@ impl Process {
 		}
 	}
 
+	/// Overwrites the userspace registers state that is restored when the process returns to
+	/// userspace.
+	///
+	/// # Safety
+	///
+	/// The process must not be running, else the state may be overwritten when it is saved.
+	pub unsafe fn set_user_regs(&self, regs: &IntFrame) {
+		unsafe {
+			self.kernel_stack
+				.top()
+				.cast::<IntFrame>()
+				.sub(1)
+				.write_volatile(regs.clone());
+		}
+	}
+
 	/// Forks the current process.
 	///
 	/// Arguments:
@@ -822,8 +1204,17 @@ impl Process {
 	/// If the `this` is not running, the behaviour is undefined.
 	pub fn fork(this: Arc<Self>, fork_options: ForkOptions) -> EResult<Arc<Self>> {
 		debug_assert!(matches!(this.get_state(), State::Running));
-		let pid = PidHandle::unique()?;
-		let pid_int = *pid;
+		let tid = PidHandle::unique()?;
+		let tid_int = *tid;
+		let thread_leader = Process::thread_leader(&this);
+		// A new thread shares the process's ID and parent. A new process is a child of the whole
+		// thread group
+		let (pid, parent) = if fork_options.thread {
+			let parent = thread_leader.links.lock().parent.clone();
+			(this.pid, parent)
+		} else {
+			(tid_int, Some(thread_leader.clone()))
+		};
 		// Clone memory space
 		let mem_space = {
 			let curr_mem_space = this.mem_space.as_ref().unwrap();
@@ -846,48 +1237,72 @@ impl Process {
 				})
 				.transpose()?
 		};
-		// Clone signal handlers
-		let signal_handlers = {
+		// Clone signal handlers. The alternate signal stack is inherited, unless the memory is
+		// shared
+		let (signal_handlers, altstack) = {
 			let signal_manager = this.signal.lock();
-			if fork_options.share_sighand {
+			let handlers = if fork_options.share_sighand {
 				signal_manager.handlers.clone()
 			} else {
 				let handlers = signal_manager.handlers.lock().clone();
 				Arc::new(Mutex::new(handlers))?
-			}
+			};
+			let altstack = signal_manager
+				.altstack
+				.filter(|_| !fork_options.share_memory);
+			(handlers, altstack)
+		};
+		let (group_leader, session_leader) = {
+			let links = this.links.lock();
+			(
+				links.group_leader.clone().unwrap_or_else(|| this.clone()),
+				links.session_leader.clone().unwrap_or_else(|| this.clone()),
+			)
+		};
+		let timer_manager = if fork_options.thread {
+			this.timer_manager.clone()
+		} else {
+			Arc::new(Mutex::new(TimerManager::new(pid)?))?
 		};
-		let group_leader = this
-			.links
-			.lock()
-			.group_leader
-			.clone()
-			.unwrap_or_else(|| this.clone());
 		let proc = Arc::new(Self {
+			tid,
 			pid,
-			tid: pid_int,
 
 			state: AtomicU8::new(State::Running as _),
 			vfork_done: AtomicBool::new(false),
 			links: Mutex::new(ProcessLinks {
-				parent: Some(this.clone()),
+				parent,
 				group_leader: Some(group_leader.clone()),
+				session_leader: Some(session_leader),
+				thread_leader: fork_options.thread.then(|| thread_leader.clone()),
 				..Default::default()
 			}),
 
 			kernel_stack: KernelStack::new()?,
 			kernel_sp: AtomicPtr::default(),
+			core: AtomicUsize::new(0),
+			// The child is started right away by the current core
+			on_core: AtomicBool::new(true),
+			sched: this.sched.fork(),
 			fpu: Mutex::new(this.fpu.lock().clone()),
 			tls: Mutex::new(*this.tls.lock()),
+			// Saved when the child is switched out
+			#[cfg(target_arch = "x86_64")]
+			fs_base: AtomicU64::new(0),
+			#[cfg(target_arch = "x86_64")]
+			gs_base: AtomicU64::new(0),
 
 			mem_space: UnsafeMut::new(Some(mem_space)),
 			fs: Mutex::new(this.fs.lock().clone()),
 			file_descriptors: UnsafeMut::new(file_descriptors),
-			// TODO if creating a thread: timer_manager: this.timer_manager.clone(),
-			timer_manager: Arc::new(Mutex::new(TimerManager::new(pid_int)?))?,
+			timer_manager,
 			signal: Mutex::new(ProcessSignal {
 				handlers: signal_handlers,
 				sigmask: this.signal.lock().sigmask,
+				saved_sigmask: None,
 				sigpending: Default::default(),
+				queue: Vec::new(),
+				altstack,
 
 				exit_status: 0,
 				termsig: 0,
@@ -895,13 +1310,25 @@ impl Process {
 			parent_event: Default::default(),
 
 			rusage: Default::default(),
+			cputime: Default::default(),
+			oom_score_adj: AtomicI16::new(thread_leader.oom_score_adj.load(Relaxed)),
+			memlock_limit: Mutex::new(*thread_leader.memlock_limit.lock()),
+
+			clear_child_tid: Default::default(),
+			robust_list: Default::default(),
+			ptrace: Default::default(),
 		})?;
 		// TODO on failure, must undo
-		this.add_child(pid_int)?;
-		{
+		if fork_options.thread {
+			let mut links = thread_leader.links.lock();
+			if let Err(i) = links.threads.binary_search(&tid_int) {
+				links.threads.insert(i, tid_int)?;
+			}
+		} else {
+			thread_leader.add_child(tid_int)?;
 			let mut links = group_leader.links.lock();
-			if let Err(i) = links.process_group.binary_search(&pid_int) {
-				links.process_group.insert(i, pid_int)?;
+			if let Err(i) = links.process_group.binary_search(&tid_int) {
+				links.process_group.insert(i, tid_int)?;
 			}
 		}
 		SCHEDULER.lock().add_process(proc.clone())?;
@@ -913,20 +1340,106 @@ impl Process {
 	/// If the process doesn't have a signal handler, the default action for the signal is
 	/// executed.
 	pub fn kill(&self, sig: Signal) {
-		let mut signal_manager = self.signal.lock();
-		// Ignore blocked signals
-		if sig.can_catch() && signal_manager.sigmask.is_set(sig as _) {
-			return;
+		let _ = self.kill_info(SigInfo::new(sig, SI_KERNEL));
+	}
+
+	/// Kills the process with the signal described by `info`.
+	///
+	/// If the signal is a realtime signal sent by `sigqueue` or a timer and it cannot be queued,
+	/// the function returns [`errno::EAGAIN`].
+	pub fn kill_info(&self, info: SigInfo) -> EResult<()> {
+		let sig = info.si_signo;
+		let traced = self.ptrace.lock().is_traced();
+		let blocked = {
+			let mut signal_manager = self.signal.lock();
+			// Statistics
+			self.rusage.lock().ru_nsignals += 1;
+			/*#[cfg(feature = "strace")]
+			println!(
+				"[strace {pid}] received signal `{sig}`",
+				pid = self.get_pid(),
+				sig = sig as c_int
+			);*/
+			// Blocked signals remain pending until they are unblocked or consumed through a
+			// signalfd. Ignored signals are discarded, unless blocked since the handler may change
+			// in the meantime, or reported to the tracer
+			let blocked = sig.can_catch() && signal_manager.is_signal_blocked(sig);
+			if blocked || traced || !signal_manager.is_ignored(sig) {
+				signal_manager.queue(info)?;
+			}
+			blocked
+		};
+		signalfd::notify();
+		// `SIGKILL` cannot be held off by stopping the process. `SIGCONT` resumes it even if
+		// blocked, unless it is stopped by its tracer
+		let resume = match sig {
+			Signal::SIGKILL => true,
+			Signal::SIGCONT => !traced,
+			_ => false,
+		};
+		if resume && self.get_state() == State::Stopped {
+			self.set_state(State::Running);
+		}
+		// Interrupt the thread if sleeping. An ignored signal wakes it up as well, so that a
+		// parent waiting for its children is notified by `SIGCHLD`
+		if !blocked {
+			self.wake();
+		}
+		Ok(())
+	}
+
+	/// Kills the current process with the signal described by `info`, caused by the execution of
+	/// the process itself, such as a fault.
+	///
+	/// Since the process cannot make progress until the signal is handled, the signal cannot be
+	/// blocked or ignored. If it is, the default action is restored.
+	pub fn kill_fault(&self, info: SigInfo) {
+		let sig = info.si_signo;
+		{
+			let mut signal_manager = self.signal.lock();
+			let mut handlers = signal_manager.handlers.lock();
+			let handler = &mut handlers[sig as usize];
+			let blocked = signal_manager.sigmask.is_set(sig as _);
+			if blocked || matches!(handler, SignalHandler::Ignore) {
+				*handler = SignalHandler::Default;
+				drop(handlers);
+				signal_manager.sigmask.clear(sig as _);
+			}
+		}
+		let _ = self.kill_info(info);
+	}
+
+	/// Sends the signal `sig` to the whole process, that is to its thread group.
+	///
+	/// The signal is delivered to the first thread that does not block it, starting with the
+	/// group's leader. If every thread blocks it, the signal is left pending on the leader.
+	pub fn kill_process(&self, sig: Signal) {
+		let _ = self.kill_process_info(SigInfo::new(sig, SI_KERNEL));
+	}
+
+	/// Same as [`Self::kill_process`], with the signal described by `info`.
+	///
+	/// Errors are the same as [`Self::kill_info`].
+	pub fn kill_process_info(&self, info: SigInfo) -> EResult<()> {
+		let sig = info.si_signo;
+		let leader = self.links.lock().thread_leader.clone();
+		let leader = leader.as_deref().unwrap_or(self);
+		let can_take = |thread: &Process| {
+			thread.get_state() != State::Zombie
+				&& !(sig.can_catch() && thread.signal.lock().is_signal_blocked(sig))
+		};
+		if can_take(leader) {
+			return leader.kill_info(info);
+		}
+		let threads = oom::wrap_nofail(|| Vec::try_from(leader.links.lock().threads.as_slice()));
+		let thread = threads
+			.into_iter()
+			.filter_map(Process::get_by_tid)
+			.find(|thread| can_take(thread));
+		match thread {
+			Some(thread) => thread.kill_info(info),
+			None => leader.kill_info(info),
 		}
-		// Statistics
-		self.rusage.lock().ru_nsignals += 1;
-		/*#[cfg(feature = "strace")]
-		println!(
-			"[strace {pid}] received signal `{sig}`",
-			pid = self.get_pid(),
-			sig = sig as c_int
-		);*/
-		signal_manager.sigpending.set(sig as _);
 	}
 
 	/// Kills every process in the process group.
@@ -937,28 +1450,58 @@ impl Process {
 			.iter()
 			.filter_map(|pid| Process::get_by_pid(*pid))
 			.for_each(|proc| {
-				proc.kill(sig);
+				proc.kill_process(sig);
 			});
-		self.kill(sig);
+		self.kill_process(sig);
 	}
 
-	/// Exits the process with the given `status`.
+	/// Exits the thread with the given `status`.
 	///
-	/// This function changes the process's status to `Zombie`.
+	/// This function changes the thread's status to `Zombie`. The process itself exits when its
+	/// last thread does.
 	pub fn exit(&self, status: u32) {
 		#[cfg(feature = "strace")]
 		println!(
 			"[strace {pid}] exited with status `{status}`",
-			pid = *self.pid
+			pid = *self.tid
 		);
 		self.signal.lock().exit_status = status as ExitStatus;
+		futex::exit(self);
 		self.set_state(State::Zombie);
 	}
+
+	/// Exits every thread of the thread group with the given `status`, except `self`.
+	pub fn exit_siblings(&self, status: u32) {
+		let leader = self.links.lock().thread_leader.clone();
+		let leader = leader.as_deref().unwrap_or(self);
+		let threads = oom::wrap_nofail(|| Vec::try_from(leader.links.lock().threads.as_slice()));
+		for tid in threads {
+			if tid == self.get_tid() {
+				continue;
+			}
+			if let Some(thread) = Process::get_by_tid(tid) {
+				thread.exit(status);
+			}
+		}
+		if !self.is_thread_leader() {
+			leader.exit(status);
+		}
+	}
+
+	/// Exits every thread of the thread group with the given `status`, `self` being the last
+	/// one.
+	pub fn exit_group(&self, status: u32) {
+		self.exit_siblings(status);
+		self.exit(status);
+	}
 }
 
 impl fmt::Debug for Process {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		f.debug_struct("Process").field("pid", &self.pid).finish()
+		f.debug_struct("Process")
+			.field("pid", &self.pid)
+			.field("tid", &self.tid)
+			.finish()
 	}
 }
 
@@ -986,6 +1529,29 @@ impl Drop for Process {
 	}
 }
 
+/// Accounts the time the current thread spent in userspace, upon entering the kernel from
+/// userspace.
+pub fn enter_kernel() {
+	let now = current_time_ns(Clock::Boottime);
+	Process::current().cputime.account(now, true);
+}
+
+/// Accounts the time the current thread spent in kernelspace before returning to userspace, then
+/// charges the CPU time consumed since the last call to the CPU interval timers of its process.
+fn charge_cputime() {
+	let proc = Process::current();
+	proc.cputime
+		.account(current_time_ns(Clock::Boottime), false);
+	let (user, total) = proc.cputime.take_uncharged();
+	let (virt, prof) = proc.timer_manager.lock().charge_cputime(user, total);
+	if virt {
+		proc.kill_process(Signal::SIGVTALRM);
+	}
+	if prof {
+		proc.kill_process(Signal::SIGPROF);
+	}
+}
+
 /// Returns `true` if the execution shall continue. Else, the execution shall be paused.
 fn yield_current_impl(frame: &mut IntFrame) -> bool {
 	// Disable interruptions to prevent execution from being stopped before the reference to
@@ -996,17 +1562,35 @@ fn yield_current_impl(frame: &mut IntFrame) -> bool {
 	if proc.get_state() != State::Running {
 		return false;
 	}
-	// Get signal handler to execute, if any
-	let (sig, handler) = {
-		let mut signal_manager = proc.signal.lock();
-		let Some(sig) = signal_manager.next_signal() else {
-			return true;
-		};
-		let handler = signal_manager.handlers.lock()[sig as usize].clone();
-		(sig, handler)
+	// Get signal to handle, if any. A signal injected by the tracer is delivered without stopping
+	// again
+	let injected = ptrace::take_injected(&proc);
+	let info = match injected {
+		Some(sig) => SigInfo::new(sig, SI_USER),
+		None => {
+			let info = {
+				let mut signal = proc.signal.lock();
+				let info = signal.next_signal();
+				if info.is_none() {
+					signal.restore_sigmask();
+				}
+				info
+			};
+			let Some(info) = info else {
+				return true;
+			};
+			// Let the tracer decide whether the signal is delivered
+			if ptrace::signal_stop(&proc, info.si_signo) {
+				return false;
+			}
+			info
+		}
 	};
+	let handler = proc.signal.lock().handlers.lock()[info.si_signo as usize].clone();
 	// Prepare for execution of signal handler
-	handler.exec(sig, &proc, frame);
+	handler.exec(&info, &proc, frame);
+	// If no handler has been set up, the saved mask has not been consumed
+	proc.signal.lock().restore_sigmask();
 	// If the process is still running, continue execution
 	proc.get_state() == State::Running
 }
@@ -1030,9 +1614,10 @@ pub fn yield_current(ring: u8, frame: &mut IntFrame) {
 	if ring < 3 {
 		return;
 	}
-	// Use a separate function to drop everything, since `Scheduler::tick` may never return
-	let cont = yield_current_impl(frame);
-	if !cont {
+	charge_cputime();
+	// Use a separate function to drop everything, since `Scheduler::tick` may never return.
+	// Once resumed, check again since the process may have been stopped with signals pending
+	while !yield_current_impl(frame) {
 		Scheduler::tick();
 	}
 }
@@ -0,0 +1,337 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Process tracing, used by debuggers and system call tracers.
+//!
+//! A traced process (the *tracee*) stops whenever it is about to receive a signal, and
+//! optionally at the entry and exit of each system call. Its tracer is then notified through
+//! `waitpid` and may inspect or modify the tracee's registers and memory before resuming it.
+
+use crate::{
+	arch::x86::idt::IntFrame,
+	memory::oom,
+	process::{Process, State, pid::Pid, scheduler::Scheduler, signal::Signal},
+};
+use core::{hint::likely, mem};
+use utils::{collections::vec::Vec, errno, errno::EResult, ptr::arc::Arc};
+
+/// Option: set bit 7 of the signal number on system call stops.
+pub const PTRACE_O_TRACESYSGOOD: u32 = 0x1;
+/// Option: stop the tracee on `execve`.
+pub const PTRACE_O_TRACEEXEC: u32 = 0x10;
+/// Option: kill the tracee when the tracer exits.
+pub const PTRACE_O_EXITKILL: u32 = 0x100000;
+/// The set of supported options.
+pub const PTRACE_O_MASK: u32 = PTRACE_O_TRACESYSGOOD | PTRACE_O_TRACEEXEC | PTRACE_O_EXITKILL;
+
+/// Event stop: the tracee executed a program.
+pub const PTRACE_EVENT_EXEC: u32 = 4;
+
+/// The trap flag in the `rflags` register, enabling single-stepping.
+const TRAP_FLAG: usize = 0x100;
+
+/// Tracing state of a process.
+#[derive(Default)]
+pub struct Ptrace {
+	/// The TID of the tracer. If `None`, the process is not traced.
+	pub tracer: Option<Pid>,
+	/// Options set by the tracer.
+	pub options: u32,
+	/// If `true`, the tracee stops at the entry and exit of system calls.
+	syscall_stops: bool,
+	/// The ID of the system call the tracee is stopped at, if any.
+	///
+	/// The tracer may change it at the entry of the system call. The value `-1` skips it.
+	pub syscall: Option<usize>,
+	/// The reason of the current stop: the signal, plus the event shifted by 8 bits.
+	stop_code: u32,
+	/// If `true`, the current stop has yet to be reported to the tracer.
+	stop_pending: bool,
+	/// The signal to deliver when resuming, bypassing the signal-delivery-stop.
+	injected: Option<Signal>,
+
+	/// The TIDs of the processes traced by this process.
+	tracees: Vec<Pid>,
+}
+
+impl Ptrace {
+	/// Tells whether the process is traced.
+	#[inline]
+	pub fn is_traced(&self) -> bool {
+		self.tracer.is_some()
+	}
+
+	/// Resets the state of the tracee, except for its own tracees, returning the options that
+	/// were set.
+	fn untrace(&mut self) -> u32 {
+		let tracees = mem::take(&mut self.tracees);
+		let old = mem::replace(
+			self,
+			Self {
+				tracees,
+				..Default::default()
+			},
+		);
+		old.options
+	}
+
+	/// Returns the stop code for system call stops.
+	fn syscall_stop_code(&self) -> u32 {
+		let mut code = Signal::SIGTRAP as u32;
+		if self.options & PTRACE_O_TRACESYSGOOD != 0 {
+			code |= 0x80;
+		}
+		code
+	}
+}
+
+/// Stops the tracee `proc` with the given stop `code` and notifies its tracer.
+///
+/// `proc` must be the current process. The caller is responsible for letting the scheduler
+/// switch to another process afterward.
+fn stop(proc: &Process, code: u32) {
+	let tracer = {
+		let mut ptrace = proc.ptrace.lock();
+		ptrace.stop_code = code;
+		ptrace.stop_pending = true;
+		ptrace.tracer
+	};
+	proc.set_state(State::Stopped);
+	if let Some(tracer) = tracer.and_then(Process::get_by_tid) {
+		tracer.kill(Signal::SIGCHLD);
+		tracer.wake();
+	}
+}
+
+/// Tells whether `tracee` is traced by `tracer` and is stopped, which is required for most
+/// tracing operations.
+pub fn is_stopped_tracee(tracer: &Process, tracee: &Process) -> bool {
+	tracee.ptrace.lock().tracer == Some(tracer.get_tid()) && tracee.get_state() == State::Stopped
+}
+
+/// Attaches `tracer` to `tracee`.
+///
+/// If the tracee is already traced, the function returns [`errno::EPERM`].
+pub fn attach(tracer: &Process, tracee: &Process, options: u32) -> EResult<()> {
+	{
+		let mut ptrace = tracee.ptrace.lock();
+		if ptrace.is_traced() {
+			return Err(errno!(EPERM));
+		}
+		ptrace.tracer = Some(tracer.get_tid());
+		ptrace.options = options;
+	}
+	tracer.ptrace.lock().tracees.push(tracee.get_tid())?;
+	Ok(())
+}
+
+/// Resumes the stopped `tracee`.
+///
+/// Arguments:
+/// - `sig` is the signal to deliver to the tracee, if any.
+/// - `syscall_stops` tells whether the tracee has to stop at the next system call entry or exit.
+/// - `single_step` tells whether the tracee has to stop after executing one instruction.
+pub fn resume(tracee: &Process, sig: Option<Signal>, syscall_stops: bool, single_step: bool) {
+	{
+		let mut ptrace = tracee.ptrace.lock();
+		ptrace.injected = sig;
+		ptrace.syscall_stops = syscall_stops;
+		ptrace.stop_pending = false;
+	}
+	let mut regs = tracee.user_regs();
+	let rflags = regs.rflags as usize;
+	regs.rflags = if single_step {
+		rflags | TRAP_FLAG
+	} else {
+		rflags & !TRAP_FLAG
+	} as _;
+	unsafe {
+		tracee.set_user_regs(&regs);
+	}
+	tracee.set_state(State::Running);
+}
+
+/// Detaches `tracee` from its tracer, resuming it with the signal `sig` if stopped.
+pub fn detach(tracee: &Process, sig: Option<Signal>) {
+	let tracer = {
+		let mut ptrace = tracee.ptrace.lock();
+		let tracer = ptrace.tracer;
+		ptrace.untrace();
+		tracer
+	};
+	if let Some(tracer) = tracer.and_then(Process::get_by_tid) {
+		tracer
+			.ptrace
+			.lock()
+			.tracees
+			.retain(|tid| *tid != tracee.get_tid());
+	}
+	if tracee.get_state() == State::Stopped {
+		resume(tracee, sig, false, false);
+	}
+}
+
+/// Releases the tracing relationships of the exiting process `proc`, both as a tracee and as a
+/// tracer.
+pub fn exit(proc: &Process) {
+	let (tracer, tracees) = {
+		let mut ptrace = proc.ptrace.lock();
+		(ptrace.tracer.take(), mem::take(&mut ptrace.tracees))
+	};
+	if let Some(tracer) = tracer.and_then(Process::get_by_tid) {
+		tracer
+			.ptrace
+			.lock()
+			.tracees
+			.retain(|tid| *tid != proc.get_tid());
+		// The tracer may be waiting for this process
+		tracer.wake();
+	}
+	for tracee in tracees.into_iter().filter_map(Process::get_by_tid) {
+		let options = tracee.ptrace.lock().untrace();
+		if options & PTRACE_O_EXITKILL != 0 {
+			tracee.kill(Signal::SIGKILL);
+		}
+		if tracee.get_state() == State::Stopped {
+			resume(&tracee, None, false, false);
+		}
+	}
+}
+
+/// Tells whether `tracer` has a tracee matching the `waitpid` constraint `pid`.
+pub fn has_tracee(tracer: &Process, pid: i32) -> bool {
+	let ptrace = tracer.ptrace.lock();
+	match pid {
+		-1 => !ptrace.tracees.is_empty(),
+		1.. => ptrace.tracees.contains(&(pid as Pid)),
+		_ => false,
+	}
+}
+
+/// Returns a tracee of `tracer` matching the `waitpid` constraint `pid` whose stop has not been
+/// reported yet, along with the associated wait status.
+///
+/// If `consume` is `true`, the stop is marked as reported.
+pub fn take_stop(tracer: &Process, pid: i32, consume: bool) -> Option<(Arc<Process>, i32)> {
+	// Copy the list so that the tracer's lock is not held while locking tracees
+	let tracees = oom::wrap(|| Vec::try_from(tracer.ptrace.lock().tracees.as_slice())).ok()?;
+	tracees
+		.into_iter()
+		.filter(|tid| pid == -1 || pid == *tid as i32)
+		.filter_map(Process::get_by_tid)
+		.find_map(|tracee| {
+			let mut ptrace = tracee.ptrace.lock();
+			if !ptrace.stop_pending {
+				return None;
+			}
+			if consume {
+				ptrace.stop_pending = false;
+			}
+			let wstatus = ((ptrace.stop_code as i32) << 8) | 0x7f;
+			drop(ptrace);
+			Some((tracee, wstatus))
+		})
+}
+
+/// If the current process is traced, performs a signal-delivery-stop for `sig`, letting the
+/// tracer decide whether to deliver it.
+///
+/// If the process stopped, the function returns `true`.
+pub fn signal_stop(proc: &Process, sig: Signal) -> bool {
+	// `SIGKILL` cannot be intercepted
+	if likely(!proc.ptrace.lock().is_traced()) || sig == Signal::SIGKILL {
+		return false;
+	}
+	stop(proc, sig as _);
+	true
+}
+
+/// Takes the signal injected by the tracer when resuming the current process `proc`, if any.
+pub fn take_injected(proc: &Process) -> Option<Signal> {
+	proc.ptrace.lock().injected.take()
+}
+
+/// If the current process is traced, performs an event stop for the execution of a new program.
+///
+/// If the tracer did not request event stops, `SIGTRAP` is sent instead.
+pub fn exec(proc: &Process) {
+	let options = {
+		let ptrace = proc.ptrace.lock();
+		if likely(!ptrace.is_traced()) {
+			return;
+		}
+		ptrace.options
+	};
+	if options & PTRACE_O_TRACEEXEC != 0 {
+		stop(proc, Signal::SIGTRAP as u32 | (PTRACE_EVENT_EXEC << 8));
+	} else {
+		proc.kill(Signal::SIGTRAP);
+	}
+}
+
+/// Performs a syscall-enter-stop if requested by the tracer of the current process.
+///
+/// The function returns the ID of the system call to execute, which may have been changed by
+/// the tracer. If the system call has to be skipped, the function returns `None`.
+pub fn syscall_enter(frame: &mut IntFrame) -> Option<usize> {
+	let id = frame.get_syscall_id();
+	// Use a scope to drop everything before calling `tick`
+	{
+		let proc = Process::current();
+		let mut ptrace = proc.ptrace.lock();
+		if likely(!ptrace.syscall_stops) {
+			return Some(id);
+		}
+		ptrace.syscall = Some(id);
+		let code = ptrace.syscall_stop_code();
+		drop(ptrace);
+		// Like Linux, the tracer sees `-ENOSYS` as the return value on entry
+		frame.set_syscall_return(Err(errno!(ENOSYS)));
+		stop(&proc, code);
+	}
+	Scheduler::tick();
+	let proc = Process::current();
+	let id = proc.ptrace.lock().syscall.take().unwrap_or(id);
+	// Do not execute the system call if killed while stopped
+	let killed = proc.signal.lock().sigpending.is_set(Signal::SIGKILL as _);
+	if id as isize == -1 || killed {
+		return None;
+	}
+	frame.rax = id as _;
+	Some(id)
+}
+
+/// Performs a syscall-exit-stop if requested by the tracer of the current process.
+///
+/// `id` is the ID of the system call that has been executed.
+pub fn syscall_exit(id: usize) {
+	// Use a scope to drop everything before calling `tick`
+	{
+		let proc = Process::current();
+		let mut ptrace = proc.ptrace.lock();
+		if likely(!ptrace.syscall_stops) {
+			return;
+		}
+		ptrace.syscall = Some(id);
+		let code = ptrace.syscall_stop_code();
+		drop(ptrace);
+		stop(&proc, code);
+	}
+	Scheduler::tick();
+	Process::current().ptrace.lock().syscall = None;
+}
@@ -0,0 +1,45 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Resource limits of processes.
+
+/// Value of a resource limit meaning the resource is not limited.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The default limit on the number of bytes of memory that may be locked into RAM.
+pub const DEFAULT_MEMLOCK: u64 = 8 * 1024 * 1024;
+
+/// A resource limit.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit {
+	/// Soft limit
+	pub rlim_cur: u64,
+	/// Hard limit (ceiling for [`Self::rlim_cur`])
+	pub rlim_max: u64,
+}
+
+impl RLimit {
+	/// Creates a limit whose soft and hard limits are both `limit`.
+	pub const fn new(limit: u64) -> Self {
+		Self {
+			rlim_cur: limit,
+			rlim_max: limit,
+		}
+	}
+}
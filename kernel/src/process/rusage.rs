@@ -18,7 +18,11 @@
 
 //! Monitoring of the resource usage of processes.
 
-use crate::time::unit::Timeval;
+use crate::{
+	sync::atomic::AtomicU64,
+	time::unit::{Timestamp, Timeval},
+};
+use core::sync::atomic::Ordering::Relaxed;
 
 // TODO Place calls in kernel's code to update usage
 
@@ -58,3 +62,56 @@ pub struct Rusage {
 	/// Involuntary context switches.
 	pub ru_nivcsw: i64,
 }
+
+/// CPU time consumed by a thread, in nanoseconds.
+///
+/// Time is accounted upon transitions between userspace and kernelspace, and when the thread is
+/// scheduled.
+#[derive(Debug, Default)]
+pub struct CpuTime {
+	/// Time spent executing in userspace.
+	user: AtomicU64,
+	/// Time spent executing in kernelspace.
+	system: AtomicU64,
+	/// The timestamp at which the current accounting period started.
+	last: AtomicU64,
+
+	/// The user time already charged to the CPU interval timers.
+	charged_user: AtomicU64,
+	/// The total time already charged to the CPU interval timers.
+	charged_total: AtomicU64,
+}
+
+impl CpuTime {
+	/// Accounts the time elapsed since the last accounting until `now`, as user time if `user` is
+	/// set, or as system time otherwise.
+	pub fn account(&self, now: Timestamp, user: bool) {
+		let delta = now.saturating_sub(self.last.swap(now, Relaxed));
+		let counter = if user { &self.user } else { &self.system };
+		counter.fetch_add(delta, Relaxed);
+	}
+
+	/// Starts a new accounting period at `now`, discarding the time elapsed since the last
+	/// accounting.
+	///
+	/// This function is called when the thread is scheduled back, since it did not run in the
+	/// meantime.
+	pub fn resume(&self, now: Timestamp) {
+		self.last.store(now, Relaxed);
+	}
+
+	/// Returns the user and system time, in nanoseconds.
+	pub fn get(&self) -> (Timestamp, Timestamp) {
+		(self.user.load(Relaxed), self.system.load(Relaxed))
+	}
+
+	/// Returns the user and total time consumed since the last call, to be charged to the CPU
+	/// interval timers.
+	pub fn take_uncharged(&self) -> (Timestamp, Timestamp) {
+		let (user, system) = self.get();
+		let total = user + system;
+		let user = user - self.charged_user.swap(user, Relaxed);
+		let total = total - self.charged_total.swap(total, Relaxed);
+		(user, total)
+	}
+}
@@ -0,0 +1,413 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! CPU cores management.
+//!
+//! At boot, only the Bootstrap Processor (BSP) is running. Other cores, called Application
+//! Processors (APs), are listed by the ACPI, then started by the BSP once the scheduler is ready.
+//!
+//! Each core has its own [`CoreLocal`] structure, pointed to by the `gs` segment under `x86_64`.
+
+use crate::{
+	arch::{
+		x86,
+		x86::{
+			apic,
+			apic::{IpiDest, RESCHEDULE_VECTOR},
+			cli, enable_sse, gdt, hlt, idt, paging, smp, tss,
+			tss::Tss,
+		},
+	},
+	boot::{GDT_VIRT_ADDR, InitGdt},
+	memory::{PhysAddr, VirtAddr, vmem, vmem::KERNEL_VMEM},
+	println,
+	process::{
+		Process,
+		mem_space::MemSpace,
+		scheduler::{SCHEDULER, switch::idle_task},
+	},
+};
+use core::{
+	cell::UnsafeCell,
+	hint, mem, ptr,
+	sync::atomic::{
+		AtomicBool, AtomicPtr, AtomicU8, AtomicU32, AtomicUsize,
+		Ordering::{Acquire, Relaxed, Release, SeqCst},
+	},
+};
+use utils::ptr::arc::{Arc, RelaxedArcCell};
+
+/// The maximum number of supported CPU cores.
+pub const MAX_CORES: usize = 64;
+
+/// The maximum number of milliseconds to wait for an AP to start.
+const AP_START_TIMEOUT: usize = 1000;
+
+/// Kernel core-local storage.
+#[repr(C)]
+pub struct CoreLocal {
+	/// The current kernel stack
+	pub kernel_stack: AtomicUsize,
+	/// The stashed user stack
+	pub user_stack: AtomicUsize,
+	/// Pointer to the structure itself, read through `gs`
+	this: AtomicPtr<CoreLocal>,
+
+	/// The index of the core.
+	pub id: usize,
+	/// The Local APIC ID of the core.
+	pub apic_id: AtomicU32,
+	/// Tells whether the core is running.
+	pub online: AtomicBool,
+
+	/// Attached memory space.
+	///
+	/// The pointer stored by this field is returned by [`Arc::into_raw`].
+	pub mem_space: RelaxedArcCell<MemSpace>,
+	/// The physical address of the page directory bound on the core, used to filter TLB
+	/// shootdowns.
+	page_dir: AtomicUsize,
+	/// The process that was running before the last context switch, released once the switch is
+	/// complete.
+	pub(super) prev: UnsafeCell<Option<Arc<Process>>>,
+
+	/// The core's GDT.
+	gdt: UnsafeCell<InitGdt>,
+	/// The core's TSS.
+	tss: UnsafeCell<Tss>,
+
+	/// TLB shootdown request: the address of the first page to invalidate.
+	shootdown_addr: AtomicUsize,
+	/// TLB shootdown request: the number of pages to invalidate.
+	shootdown_pages: AtomicUsize,
+	/// Tells whether a TLB shootdown request is pending.
+	shootdown_pending: AtomicBool,
+}
+
+// Fields that are not atomic are accessed only by the core itself, with interrupts disabled
+unsafe impl Sync for CoreLocal {}
+
+impl CoreLocal {
+	/// Returns the pointer to the core's GDT.
+	#[inline]
+	pub fn gdt(&self) -> *mut InitGdt {
+		self.gdt.get()
+	}
+
+	/// Returns the pointer to the core's TSS.
+	#[inline]
+	pub fn tss(&self) -> *mut Tss {
+		self.tss.get()
+	}
+
+	/// Sets the physical address of the page directory bound on the core.
+	#[inline]
+	pub(crate) fn set_page_dir(&self, page_dir: PhysAddr) {
+		self.page_dir.store(page_dir.0, SeqCst);
+	}
+}
+
+/// The structures of all cores.
+static CORES: [CoreLocal; MAX_CORES] = const {
+	let mut cores = [const {
+		CoreLocal {
+			kernel_stack: AtomicUsize::new(0),
+			user_stack: AtomicUsize::new(0),
+			this: AtomicPtr::new(ptr::null_mut()),
+
+			id: 0,
+			apic_id: AtomicU32::new(0),
+			online: AtomicBool::new(false),
+
+			mem_space: RelaxedArcCell::new(),
+			page_dir: AtomicUsize::new(0),
+			prev: UnsafeCell::new(None),
+
+			gdt: UnsafeCell::new([gdt::Entry(0); 11]),
+			tss: UnsafeCell::new(unsafe { mem::zeroed() }),
+
+			shootdown_addr: AtomicUsize::new(0),
+			shootdown_pages: AtomicUsize::new(0),
+			shootdown_pending: AtomicBool::new(false),
+		}
+	}; MAX_CORES];
+	// TODO use for loop when stabilized
+	let mut i = 0;
+	while i < MAX_CORES {
+		cores[i].id = i;
+		i += 1;
+	}
+	cores
+};
+/// The number of cores present on the system.
+static COUNT: AtomicUsize = AtomicUsize::new(1);
+/// The number of running cores.
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+/// Local APIC IDs to core indexes.
+static APIC_TO_CORE: [AtomicU8; 256] = [const { AtomicU8::new(0) }; 256];
+/// The index of the AP being started.
+static BOOTING: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells whether the system is halting.
+static HALTED: AtomicBool = AtomicBool::new(false);
+/// Lock serializing TLB shootdowns, since each core has only one request slot.
+static SHOOTDOWN_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the core-local structure of the current core.
+fn init_core(core: &'static CoreLocal) {
+	core.this.store(core as *const _ as *mut _, Release);
+	// Set `IA32_GS_BASE` instead of `IA32_KERNEL_GS_BASE` since it will get swapped when
+	// switching to userspace
+	#[cfg(target_arch = "x86_64")]
+	x86::wrmsr(x86::IA32_GS_BASE, core as *const _ as u64);
+	// Use a GDT of its own
+	unsafe {
+		core.gdt().write(GDT_VIRT_ADDR.as_ptr::<InitGdt>().read());
+	}
+	gdt::flush();
+}
+
+/// Initializes the structure of the bootstrap processor.
+///
+/// This function must be called only once, at boot, before memory management is initialized.
+pub(crate) fn init() {
+	let apic_id = x86::cpuid(1, 0, 0, 0).1 >> 24;
+	CORES[0].apic_id.store(apic_id, Relaxed);
+	CORES[0].online.store(true, Relaxed);
+	init_core(&CORES[0]);
+}
+
+/// Registers a core with the given Local APIC ID, as listed by the ACPI.
+///
+/// If the core is the current one or if there are too many cores, the function does nothing.
+pub(crate) fn register(apic_id: u32) {
+	if apic_id == CORES[0].apic_id.load(Relaxed) || apic_id as usize >= APIC_TO_CORE.len() {
+		return;
+	}
+	let id = COUNT.load(Relaxed);
+	let Some(core) = CORES.get(id) else {
+		return;
+	};
+	core.apic_id.store(apic_id, Relaxed);
+	APIC_TO_CORE[apic_id as usize].store(id as _, Relaxed);
+	COUNT.store(id + 1, Relaxed);
+}
+
+/// Returns the number of cores present on the system.
+#[inline]
+pub fn count() -> usize {
+	COUNT.load(Relaxed)
+}
+
+/// Returns an iterator over running cores.
+pub fn online() -> impl Iterator<Item = &'static CoreLocal> {
+	CORES[..count()]
+		.iter()
+		.filter(|core| core.online.load(Acquire))
+}
+
+/// Returns the core-local structure for the core with the given Local APIC ID.
+///
+/// This function does not rely on the `gs` segment, which may not be set properly in
+/// non-maskable interrupts.
+fn by_apic_id(apic_id: u32) -> &'static CoreLocal {
+	let id = APIC_TO_CORE
+		.get(apic_id as usize)
+		.map(|id| id.load(Relaxed) as usize)
+		.unwrap_or(0);
+	&CORES[id]
+}
+
+/// Returns the core-local structure for the current core.
+#[inline]
+pub fn core_local() -> &'static CoreLocal {
+	#[cfg(target_arch = "x86")]
+	{
+		if !apic::is_enabled() {
+			return &CORES[0];
+		}
+		by_apic_id(apic::id())
+	}
+	#[cfg(target_arch = "x86_64")]
+	unsafe {
+		let ptr: *const CoreLocal;
+		core::arch::asm!(
+			"mov {}, gs:[{off}]",
+			out(reg) ptr,
+			off = const mem::offset_of!(CoreLocal, this),
+			options(nostack, preserves_flags, readonly)
+		);
+		&*ptr
+	}
+}
+
+/// Returns the index of the current core.
+#[inline]
+pub fn current_id() -> usize {
+	core_local().id
+}
+
+/// Asks the core with index `id` to run its scheduler.
+///
+/// If the core is the current one, the scheduler runs as soon as interrupts are enabled. If the
+/// Local APIC is not in use, the current core relies on the next periodic tick instead.
+///
+/// If the core is not running, the function does nothing.
+pub fn reschedule(id: usize) {
+	let Some(core) = CORES.get(id) else {
+		return;
+	};
+	if !apic::is_enabled() || !core.online.load(Acquire) {
+		return;
+	}
+	let dest = if id == current_id() {
+		IpiDest::Current
+	} else {
+		IpiDest::Core(core.apic_id.load(Relaxed))
+	};
+	apic::send_ipi(dest, RESCHEDULE_VECTOR);
+}
+
+/// Asks all other running cores to run their scheduler.
+pub fn reschedule_others() {
+	if ONLINE.load(Acquire) > 1 {
+		apic::send_ipi(IpiDest::AllButSelf, RESCHEDULE_VECTOR);
+	}
+}
+
+/// Invalidates the TLB entries for `pages` pages starting at `addr` on the other cores.
+///
+/// If `page_dir` is not `None`, only the cores on which the page directory at this physical
+/// address is bound are targeted. Else, all cores are targeted.
+///
+/// Requests are delivered through non-maskable interrupts so that cores spinning with interrupts
+/// disabled can still process them.
+pub fn shootdown(page_dir: Option<PhysAddr>, addr: VirtAddr, pages: usize) {
+	if ONLINE.load(Acquire) <= 1 {
+		return;
+	}
+	idt::wrap_disable_interrupts(|| {
+		while SHOOTDOWN_LOCK.swap(true, Acquire) {
+			hint::spin_loop();
+		}
+		let this = core_local();
+		let targets = || {
+			online().filter(move |core| {
+				core.id != this.id
+					&& page_dir.is_none_or(|dir| core.page_dir.load(SeqCst) == dir.0)
+			})
+		};
+		for core in targets() {
+			core.shootdown_addr.store(addr.0, Relaxed);
+			core.shootdown_pages.store(pages, Relaxed);
+			core.shootdown_pending.store(true, Release);
+			apic::send_nmi(IpiDest::Core(core.apic_id.load(Relaxed)));
+		}
+		// Wait for acknowledgement
+		for core in targets() {
+			while core.shootdown_pending.load(Acquire) && !HALTED.load(Relaxed) {
+				hint::spin_loop();
+			}
+		}
+		SHOOTDOWN_LOCK.store(false, Release);
+	});
+}
+
+/// Handles a non-maskable interrupt on the current core.
+pub(crate) fn handle_nmi() {
+	if HALTED.load(Acquire) {
+		loop {
+			cli();
+			hlt();
+		}
+	}
+	if !apic::is_enabled() {
+		return;
+	}
+	let core = by_apic_id(apic::id());
+	if core.shootdown_pending.load(Acquire) {
+		let addr = VirtAddr(core.shootdown_addr.load(Relaxed));
+		let pages = core.shootdown_pages.load(Relaxed);
+		vmem::invalidate_range_current(addr, pages);
+		core.shootdown_pending.store(false, Release);
+	}
+}
+
+/// Stops all other cores.
+pub fn halt_others() {
+	HALTED.store(true, Release);
+	if ONLINE.load(Acquire) > 1 {
+		apic::send_nmi(IpiDest::AllButSelf);
+	}
+}
+
+/// The entry point of application processors.
+extern "C" fn ap_main() -> ! {
+	let core = &CORES[BOOTING.load(Acquire)];
+	init_core(core);
+	paging::prepare();
+	KERNEL_VMEM.lock().bind();
+	enable_sse();
+	idt::load();
+	tss::init();
+	apic::enable();
+	core.online.store(true, Release);
+	ONLINE.fetch_add(1, Release);
+	unsafe {
+		idle_task();
+	}
+}
+
+/// Starts all application processors.
+///
+/// Each core begins by running its idle task.
+pub(crate) fn start_aps() {
+	if !apic::is_enabled() {
+		return;
+	}
+	for core in &CORES[1..count()] {
+		let stack = SCHEDULER.lock().idle_task(core.id).kernel_stack.top();
+		BOOTING.store(core.id, Release);
+		unsafe {
+			smp::start(core.apic_id.load(Relaxed), stack, ap_main);
+		}
+		let started = (0..AP_START_TIMEOUT).any(|_| {
+			if core.online.load(Acquire) {
+				return true;
+			}
+			smp::delay(1000);
+			false
+		});
+		if !started {
+			println!("Core {} did not start", core.id);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn core_local_bsp() {
+		let core = core_local();
+		assert_eq!(core.id, 0);
+		assert!(ptr::eq(core, &CORES[0]));
+		assert!(core.online.load(Relaxed));
+	}
+}
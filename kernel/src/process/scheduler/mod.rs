@@ -18,124 +18,281 @@
 
 //! The role of the process scheduler is to interrupt the currently running
 //! process periodically to switch to another process that is in running state.
+//!
+//! Each CPU core has its own run queue. A core with nothing to run steals runnable processes
+//! from the queues of other cores.
+//!
+//! Run queues are ordered according to each process's scheduling policy, see [`policy`].
 
+pub mod cpu;
+pub mod policy;
 pub mod switch;
 
 use crate::{
-	arch::x86::{cli, idt::IntFrame, pic},
+	arch::x86::{
+		apic::{RESCHEDULE_VECTOR, TIMER_VECTOR},
+		cli, idt,
+		idt::IntFrame,
+	},
 	event,
 	event::{CallbackHook, CallbackResult},
-	process::{Process, State, mem_space::MemSpace, pid::Pid, scheduler::switch::switch},
+	memory::oom,
+	process::{Process, State, pid::Pid, scheduler::switch::switch},
 	sync::{atomic::AtomicU64, mutex::IntMutex, once::OnceInit},
 	time,
+	time::clock::{Clock, current_time_ns},
 };
 use core::{
+	cmp::{Reverse, max},
 	mem,
 	sync::{
 		atomic,
-		atomic::{AtomicUsize, Ordering::Release},
+		atomic::{
+			AtomicBool,
+			Ordering::{Acquire, Relaxed, Release},
+		},
 	},
 };
+pub use cpu::{CoreLocal, core_local};
+use policy::{Policy, RR_TIMESLICE, SchedEntity, WAKEUP_CREDIT};
 use utils::{
-	collections::btreemap::{BTreeMap, MapIterator},
+	collections::{
+		btreemap::{BTreeMap, MapIterator},
+		vec::Vec,
+	},
 	errno::AllocResult,
-	ptr::arc::{Arc, RelaxedArcCell},
+	ptr::arc::Arc,
 };
 
 /// The process scheduler.
 pub static SCHEDULER: OnceInit<IntMutex<Scheduler>> = unsafe { OnceInit::new() };
-/// Core-local storage.
-static CORE_LOCAL: CoreLocal = CoreLocal {
-	kernel_stack: AtomicUsize::new(0),
-	user_stack: AtomicUsize::new(0),
-
-	mem_space: RelaxedArcCell::new(),
-};
+/// Tells whether [`SCHEDULER`] has been initialized.
+static READY: AtomicBool = AtomicBool::new(false);
 
 /// Initializes schedulers.
 pub fn init() -> AllocResult<()> {
 	unsafe {
 		OnceInit::init(&SCHEDULER, IntMutex::new(Scheduler::new()?));
 	}
-	// Set GS base on the current core
-	#[cfg(target_arch = "x86_64")]
-	{
-		use crate::arch::x86;
-		use core::ptr::addr_of;
-		// Set to `IA32_GS_BASE` instead of `IA32_KERNEL_GS_BASE` since it will get swapped
-		// when switching to userspace
-		x86::wrmsr(x86::IA32_GS_BASE, addr_of!(CORE_LOCAL) as u64);
-	}
+	READY.store(true, Release);
 	Ok(())
 }
 
-/// Kernel core-local storage.
-#[repr(C)]
-pub struct CoreLocal {
-	/// The current kernel stack
-	pub kernel_stack: AtomicUsize,
-	/// The stashed user stack
-	pub user_stack: AtomicUsize,
-
-	/// Attached memory space.
-	///
-	/// The pointer stored by this field is returned by [`Arc::into_raw`].
-	pub mem_space: RelaxedArcCell<MemSpace>,
+/// Tells whether the scheduler has been initialized, that is whether [`SCHEDULER`] can be
+/// accessed.
+pub fn is_ready() -> bool {
+	READY.load(Acquire)
 }
 
-/// Returns the core-local structure for the current core.
-#[inline]
-pub fn core_local() -> &'static CoreLocal {
-	// TODO use `gs`
-	&CORE_LOCAL
+/// The reason for a process to join a run queue.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Placement {
+	/// The process has just been created.
+	New,
+	/// The process was not running and became runnable.
+	Wakeup,
+	/// The process was running on the core and got preempted.
+	Preempted,
 }
 
-/// A process scheduler.
+/// The run queue of a CPU core.
 ///
-/// Each CPU core has its own scheduler.
+/// The process currently running on the core is not in the queue.
+struct RunQueue {
+	/// Runnable processes with a realtime policy, by decreasing priority, then by order of
+	/// arrival.
+	realtime: BTreeMap<(Reverse<u8>, u64), Arc<Process>>,
+	/// Other runnable processes, by virtual runtime.
+	fair: BTreeMap<(u64, Pid), Arc<Process>>,
+	/// The next arrival sequence number for realtime processes.
+	next_seq: u64,
+	/// The lowest virtual runtime of the queue. This value never decreases.
+	min_vruntime: u64,
+
+	/// The process currently being executed by the core.
+	curr_proc: Arc<Process>,
+	/// The task used to idle.
+	idle_task: Arc<Process>,
+}
+
+impl RunQueue {
+	/// Inserts `proc` in the queue.
+	///
+	/// If the process is already queued, the function does nothing.
+	fn enqueue(&mut self, proc: &Arc<Process>, placement: Placement) -> AllocResult<()> {
+		let sched = &proc.sched;
+		if sched.queued.load(Relaxed) {
+			return Ok(());
+		}
+		let policy = sched.get_policy();
+		let yielded = sched.yielded.load(Relaxed);
+		if policy.is_realtime() {
+			let expired = policy == Policy::Rr && sched.slice.load(Relaxed) == 0;
+			// A preempted process keeps its place at the head of its priority level, unless it
+			// gives it up
+			if placement != Placement::Preempted || yielded || expired {
+				sched.seq.store(self.next_seq, Relaxed);
+				self.next_seq += 1;
+				sched.slice.store(RR_TIMESLICE, Relaxed);
+			}
+			let key = (Reverse(sched.get_rt_priority()), sched.seq.load(Relaxed));
+			self.realtime.insert(key, proc.clone())?;
+		} else {
+			let vruntime = sched.vruntime.load(Relaxed);
+			let vruntime = match placement {
+				// Do not let new processes take over the CPU
+				Placement::New => max(vruntime, self.min_vruntime),
+				// Give a small advantage to processes that have been sleeping, without allowing
+				// them to make up for the whole time
+				Placement::Wakeup => {
+					let credit = if policy == Policy::Batch {
+						0
+					} else {
+						WAKEUP_CREDIT
+					};
+					max(vruntime, self.min_vruntime.saturating_sub(credit))
+				}
+				// Let the next process run
+				Placement::Preempted if yielded => match self.fair.first_key_value() {
+					Some(((first, _), _)) => max(vruntime, *first + 1),
+					None => vruntime,
+				},
+				Placement::Preempted => vruntime,
+			};
+			sched.vruntime.store(vruntime, Relaxed);
+			self.fair.insert((vruntime, proc.get_tid()), proc.clone())?;
+		}
+		sched.yielded.store(false, Relaxed);
+		sched.queued.store(true, Relaxed);
+		Ok(())
+	}
+
+	/// Removes `proc` from the queue.
+	///
+	/// If the process is not queued, the function does nothing.
+	fn dequeue(&mut self, proc: &Process) {
+		let sched = &proc.sched;
+		if !sched.queued.swap(false, Relaxed) {
+			return;
+		}
+		if sched.get_policy().is_realtime() {
+			let key = (Reverse(sched.get_rt_priority()), sched.seq.load(Relaxed));
+			self.realtime.remove(&key);
+		} else {
+			let key = (sched.vruntime.load(Relaxed), proc.get_tid());
+			self.fair.remove(&key);
+		}
+	}
+
+	/// Removes the next process to run from the queue, and returns it.
+	fn pick(&mut self) -> Option<Arc<Process>> {
+		loop {
+			let proc = self
+				.realtime
+				.pop_first()
+				.map(|(_, proc)| proc)
+				.or_else(|| self.fair.pop_first().map(|(_, proc)| proc))?;
+			proc.sched.queued.store(false, Relaxed);
+			if proc.get_state() == State::Running {
+				break Some(proc);
+			}
+		}
+	}
+
+	/// Updates the lowest virtual runtime of the queue.
+	fn update_min_vruntime(&mut self) {
+		if let Some(((vruntime, _), _)) = self.fair.first_key_value() {
+			self.min_vruntime = max(self.min_vruntime, *vruntime);
+		}
+	}
+
+	/// Tells whether the queue is empty.
+	fn is_empty(&self) -> bool {
+		self.realtime.is_empty() && self.fair.is_empty()
+	}
+
+	/// Iterates over the processes of the queue, in no particular order.
+	fn iter(&self) -> impl Iterator<Item = &Arc<Process>> {
+		self.realtime
+			.iter()
+			.map(|(_, proc)| proc)
+			.chain(self.fair.iter().map(|(_, proc)| proc))
+	}
+}
+
+/// The process scheduler.
 pub struct Scheduler {
 	/// The ticking callback hook, called at a regular interval to make the
 	/// scheduler work.
 	tick_callback_hook: CallbackHook,
+	/// The callback hook for requests from other cores to run the scheduler.
+	reschedule_callback_hook: CallbackHook,
 	/// The total number of ticks since the instantiation of the scheduler.
 	total_ticks: AtomicU64,
 
-	/// A binary tree containing all processes registered to the current
-	/// scheduler.
+	/// A binary tree containing all threads registered to the scheduler, by TID.
 	processes: BTreeMap<Pid, Arc<Process>>,
-	/// The process currently being executed by the scheduler's core.
-	curr_proc: Arc<Process>,
+	/// The run queue of each core, by core index.
+	run_queues: Vec<RunQueue>,
 	/// The current number of processes in running state.
 	running_procs: usize,
-
-	/// The task used to idle.
-	idle_task: Arc<Process>,
 }
 
 impl Scheduler {
 	/// Creates a new instance of scheduler.
 	pub(super) fn new() -> AllocResult<Self> {
 		// Register tick callback
-		let mut clocks = time::hw::CLOCKS.lock();
-		let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
-		let tick_callback_hook = event::register_callback(
-			pit.get_interrupt_vector(),
+		let tick_callback_hook = if time::is_tickless() {
+			// Each core has its own timer
+			event::register_callback(
+				TIMER_VECTOR as _,
+				|_: u32, _: u32, _: &mut IntFrame, _: u8| {
+					Scheduler::tick();
+					CallbackResult::Continue
+				},
+			)?
+		} else {
+			let mut clocks = time::hw::CLOCKS.lock();
+			let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
+			event::register_callback(
+				pit.get_interrupt_vector(),
+				|_: u32, _: u32, _: &mut IntFrame, _: u8| {
+					cpu::reschedule_others();
+					Scheduler::tick();
+					CallbackResult::Continue
+				},
+			)?
+		}
+		.unwrap();
+		let reschedule_callback_hook = event::register_callback(
+			RESCHEDULE_VECTOR as _,
 			|_: u32, _: u32, _: &mut IntFrame, _: u8| {
 				Scheduler::tick();
 				CallbackResult::Continue
 			},
 		)?
 		.unwrap();
-		let idle_task = Process::idle_task()?;
+		let mut run_queues = Vec::new();
+		for _ in 0..cpu::count() {
+			let idle_task = Process::idle_task()?;
+			run_queues.push(RunQueue {
+				realtime: BTreeMap::new(),
+				fair: BTreeMap::new(),
+				next_seq: 0,
+				min_vruntime: 0,
+
+				curr_proc: idle_task.clone(),
+				idle_task,
+			})?;
+		}
 		Ok(Self {
 			tick_callback_hook,
+			reschedule_callback_hook,
 			total_ticks: AtomicU64::new(0),
 
 			processes: BTreeMap::new(),
-			curr_proc: idle_task.clone(),
+			run_queues,
 			running_procs: 0,
-
-			idle_task,
 		})
 	}
 
@@ -156,62 +313,178 @@ impl Scheduler {
 		self.processes.iter()
 	}
 
-	/// Returns the process with PID `pid`.
+	/// Returns the process with PID `pid`, that is the leader of the thread group `pid`.
 	///
 	/// If the process doesn't exist, the function returns `None`.
 	pub fn get_by_pid(&self, pid: Pid) -> Option<Arc<Process>> {
-		Some(self.processes.get(&pid)?.clone())
+		self.processes
+			.get(&pid)
+			.filter(|proc| proc.is_thread_leader())
+			.cloned()
 	}
 
-	/// Returns the process with TID `tid`.
+	/// Returns the thread with TID `tid`.
 	///
-	/// If the process doesn't exist, the function returns `None`.
-	pub fn get_by_tid(&self, _tid: Pid) -> Option<Arc<Process>> {
-		todo!()
+	/// If the thread doesn't exist, the function returns `None`.
+	pub fn get_by_tid(&self, tid: Pid) -> Option<Arc<Process>> {
+		Some(self.processes.get(&tid)?.clone())
 	}
 
-	/// Returns the current running process.
+	/// Returns the idle task of the core with index `core`.
+	pub(super) fn idle_task(&self, core: usize) -> &Arc<Process> {
+		&self.run_queues[core].idle_task
+	}
+
+	/// Returns the process running on the current core.
 	pub fn get_current_process(&self) -> Arc<Process> {
-		self.curr_proc.clone()
+		self.run_queues[cpu::current_id()].curr_proc.clone()
 	}
 
-	/// Swaps the current running process for `new`, returning the previous.
+	/// Swaps the process running on the current core for `new`, returning the previous.
+	///
+	/// If the previous process is still runnable, it is placed back in the run queue.
 	pub fn swap_current_process(&mut self, new: Arc<Process>) -> Arc<Process> {
-		core_local()
-			.kernel_stack
+		let core = core_local();
+		let now = current_time_ns(Clock::Boottime);
+		self.run_queues[new.get_core()].dequeue(&new);
+		new.core.store(core.id, Release);
+		new.sched.exec_start.store(now, Relaxed);
+		new.cputime.resume(now);
+		core.kernel_stack
 			.store(new.kernel_stack.top().as_ptr() as _, Release);
-		mem::replace(&mut self.curr_proc, new)
+		new.on_core.store(true, Release);
+		let queue = &mut self.run_queues[core.id];
+		let prev = mem::replace(&mut queue.curr_proc, new);
+		// The process is switched out from kernelspace
+		prev.cputime.account(now, false);
+		if !prev.is_idle_task() {
+			prev.sched.account(now);
+			if prev.get_state() == State::Running {
+				oom::wrap_nofail(|| queue.enqueue(&prev, Placement::Preempted));
+			}
+		}
+		prev
 	}
 
-	/// Adds a process to the scheduler.
+	/// Adds a process to the scheduler, attaching it to the current core.
 	pub fn add_process(&mut self, proc: Arc<Process>) -> AllocResult<()> {
+		let core = cpu::current_id();
+		proc.core.store(core, Release);
+		if proc.get_state() == State::Running {
+			self.run_queues[core].enqueue(&proc, Placement::New)?;
+		}
+		if let Err(e) = self.processes.insert(proc.get_tid(), proc.clone()) {
+			self.run_queues[core].dequeue(&proc);
+			return Err(e);
+		}
 		if proc.get_state() == State::Running {
 			self.increment_running();
 		}
-		self.processes.insert(*proc.pid, proc)?;
 		Ok(())
 	}
 
-	/// Removes the process with the given pid `pid`.
+	/// Removes the thread with the given TID `tid`.
 	///
-	/// If the process is not attached to this scheduler, the function does nothing.
-	pub fn remove_process(&mut self, pid: Pid) {
-		let proc = self.processes.remove(&pid);
+	/// If the thread is not attached to this scheduler, the function does nothing.
+	pub fn remove_process(&mut self, tid: Pid) {
+		let proc = self.processes.remove(&tid);
 		if let Some(proc) = proc {
+			self.run_queues[proc.get_core()].dequeue(&proc);
 			if proc.get_state() == State::Running {
 				self.decrement_running();
 			}
 		}
 	}
 
+	/// Places the process, which has just entered the running state, on the run queue of its
+	/// core.
+	pub fn activate(&mut self, proc: &Process) {
+		self.increment_running();
+		let core = proc.get_core();
+		let Some(proc) = self.processes.get(&proc.get_tid()).cloned() else {
+			return;
+		};
+		let queue = &mut self.run_queues[core];
+		// The current process is placed back in the queue when preempted
+		if queue.curr_proc.get_tid() != proc.get_tid() {
+			oom::wrap_nofail(|| queue.enqueue(&proc, Placement::Wakeup));
+		}
+		cpu::reschedule(core);
+		// Idle cores do not tick, so they have to be told to take the process if its core is busy
+		if time::is_tickless() && !self.run_queues[core].curr_proc.is_idle_task() {
+			let idle = self
+				.run_queues
+				.iter()
+				.position(|queue| queue.curr_proc.is_idle_task() && queue.is_empty());
+			if let Some(idle) = idle {
+				cpu::reschedule(idle);
+			}
+		}
+	}
+
+	/// Removes the process, which has just left the running state, from the run queue of its
+	/// core.
+	pub fn deactivate(&mut self, proc: &Process) {
+		self.decrement_running();
+		let core = proc.get_core();
+		let queue = &mut self.run_queues[core];
+		queue.dequeue(proc);
+		// A process running on another core has to be switched out by that core
+		if core != cpu::current_id() && queue.curr_proc.get_tid() == proc.get_tid() {
+			cpu::reschedule(core);
+		}
+	}
+
+	/// Changes the scheduling parameters of `proc` with `f`, moving the process in its run queue
+	/// accordingly.
+	fn update_params<F: FnOnce(&SchedEntity)>(&mut self, proc: &Arc<Process>, f: F) {
+		let core = proc.get_core();
+		let queue = &mut self.run_queues[core];
+		let queued = proc.sched.queued.load(Relaxed);
+		queue.dequeue(proc);
+		// Account for the time spent running with the previous parameters
+		if queue.curr_proc.get_tid() == proc.get_tid() {
+			proc.sched.account(current_time_ns(Clock::Boottime));
+		}
+		f(&proc.sched);
+		if queued {
+			oom::wrap_nofail(|| queue.enqueue(proc, Placement::Wakeup));
+		}
+		cpu::reschedule(core);
+	}
+
+	/// Sets the nice value of `proc`.
+	pub fn set_nice(&mut self, proc: &Arc<Process>, nice: i8) {
+		self.update_params(proc, |sched| sched.set_nice(nice));
+	}
+
+	/// Sets the scheduling policy of `proc`, with the static priority `rt_priority`.
+	///
+	/// If `reset_on_fork` is set, children of the process do not inherit the policy.
+	pub fn set_policy(
+		&mut self,
+		proc: &Arc<Process>,
+		policy: Policy,
+		rt_priority: u8,
+		reset_on_fork: bool,
+	) {
+		self.update_params(proc, |sched| {
+			sched.set_policy(policy, rt_priority, reset_on_fork)
+		});
+	}
+
 	/// Returns the current ticking frequency of the scheduler.
 	pub fn get_ticking_frequency(&self) -> u32 {
 		(10 * self.running_procs) as _
 	}
 
 	/// Increments the number of running processes.
-	pub fn increment_running(&mut self) {
+	fn increment_running(&mut self) {
 		self.running_procs += 1;
+		// When tickless, timer interrupts are programmed on each tick instead
+		if time::is_tickless() {
+			return;
+		}
 		let mut clocks = time::hw::CLOCKS.lock();
 		let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
 		if self.running_procs >= 1 {
@@ -221,8 +494,11 @@ impl Scheduler {
 	}
 
 	/// Decrements the number of running processes.
-	pub fn decrement_running(&mut self) {
+	fn decrement_running(&mut self) {
 		self.running_procs -= 1;
+		if time::is_tickless() {
+			return;
+		}
 		let mut clocks = time::hw::CLOCKS.lock();
 		let pit = clocks.get_mut(b"pit".as_slice()).unwrap();
 		if self.running_procs == 0 {
@@ -232,51 +508,89 @@ impl Scheduler {
 		}
 	}
 
-	/// Returns the next process to run with its PID.
-	fn get_next_process(&self) -> Option<Arc<Process>> {
-		// Get the current process, or take the first process in the list if no
-		// process is running
-		let curr_pid = self.curr_proc.get_pid();
-		let process_filter =
-			|(_, proc): &(&Pid, &Arc<Process>)| matches!(proc.get_state(), State::Running);
-		self.processes
-			.range((curr_pid + 1)..)
-			.find(process_filter)
-			.or_else(|| {
-				// If no suitable process is found, go back to the beginning to check processes
-				// located before the previous process (looping)
-				self.processes.range(..=curr_pid).find(process_filter)
-			})
-			.map(|(_, proc)| proc.clone())
+	/// Takes a process ready to run from the run queue of another core, and attaches it to the
+	/// core with index `core`.
+	///
+	/// Processes whose context is in use by another core cannot be taken.
+	fn steal(&mut self, core: usize) -> Option<Arc<Process>> {
+		let (victim, proc) = self
+			.run_queues
+			.iter()
+			.enumerate()
+			.filter(|(i, _)| *i != core)
+			.find_map(|(i, queue)| {
+				queue
+					.iter()
+					.find(|proc| proc.get_state() == State::Running && !proc.on_core.load(Acquire))
+					.map(|proc| (i, proc.clone()))
+			})?;
+		self.run_queues[victim].dequeue(&proc);
+		// Keep the position of the process relative to the others
+		let vruntime = proc.sched.vruntime.load(Relaxed);
+		let vruntime = vruntime.saturating_sub(self.run_queues[victim].min_vruntime)
+			+ self.run_queues[core].min_vruntime;
+		proc.sched.vruntime.store(vruntime, Relaxed);
+		proc.core.store(core, Release);
+		Some(proc)
 	}
 
 	/// Ticking the scheduler.
 	///
-	/// The function looks for the next process to run, then switches context to it.
+	/// The function looks for the next process to run on the current core, then switches context
+	/// to it.
 	///
 	/// If no process is ready to run, the scheduler halts the current core until a process becomes
 	/// runnable.
+	///
+	/// When tickless, the function also programs the next timer interrupt of the current core,
+	/// which does not occur if the process to run does not have to be preempted.
 	pub fn tick() {
 		// Disable interrupts so that no interrupt can occur before switching to the next process
 		cli();
 		let (prev, next) = {
 			let mut sched = SCHEDULER.lock();
 			sched.total_ticks.fetch_add(1, atomic::Ordering::Relaxed);
+			let core = cpu::current_id();
+			// Make the current process compete with the others
+			let queue = &mut sched.run_queues[core];
+			let curr = queue.curr_proc.clone();
+			let now = current_time_ns(Clock::Boottime);
+			if !curr.is_idle_task() {
+				curr.sched.account(now);
+				if curr.get_state() == State::Running {
+					oom::wrap_nofail(|| queue.enqueue(&curr, Placement::Preempted));
+				}
+			}
+			queue.update_min_vruntime();
 			// Find the next process to run
-			let next = sched
-				.get_next_process()
-				.unwrap_or_else(|| sched.idle_task.clone());
+			let next = queue
+				.pick()
+				.or_else(|| sched.steal(core))
+				.unwrap_or_else(|| sched.run_queues[core].idle_task.clone());
+			// Preempt the process only if others are waiting for the CPU
+			let deadline = if next.is_idle_task() || sched.run_queues[core].is_empty() {
+				None
+			} else {
+				next.sched.preempt_delay().map(|delay| now + delay)
+			};
+			time::set_next_event(deadline);
 			// If the process to run is the current, do nothing
-			if next.get_pid() == sched.curr_proc.get_pid() {
+			if next.get_tid() == curr.get_tid() {
 				return;
 			}
 			// Swap current running process. We use pointers to avoid cloning the Arc
 			let next_ptr = Arc::as_ptr(&next);
 			let prev = sched.swap_current_process(next);
-			(Arc::as_ptr(&prev), next_ptr)
+			let prev_ptr = Arc::as_ptr(&prev);
+			// Keep the previous process alive until the switch is complete, since it may have
+			// been removed from the scheduler in the meantime
+			unsafe {
+				*core_local().prev.get() = Some(prev);
+			}
+			(prev_ptr, next_ptr)
 		};
 		// Send end of interrupt, so that the next tick can be received
-		pic::end_of_interrupt(0);
+		idt::end_of_interrupt(0);
 		unsafe {
 			switch(prev, next);
 		}
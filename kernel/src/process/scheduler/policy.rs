@@ -0,0 +1,326 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Scheduling policies and per-process scheduling parameters.
+//!
+//! Processes with a realtime policy ([`Policy::Fifo`] and [`Policy::Rr`]) always run before
+//! other processes. Among them, the process with the highest priority runs first.
+//!
+//! Other processes share the CPU according to their weight, which is derived from their nice
+//! value: the scheduler runs the process with the lowest *virtual runtime*, which is the time
+//! spent running scaled by the inverse of the weight.
+
+use crate::sync::atomic::AtomicU64;
+use core::{
+	ffi::c_int,
+	sync::atomic::{
+		AtomicBool, AtomicI8, AtomicU8,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+
+/// Default scheduling policy.
+pub const SCHED_OTHER: c_int = 0;
+/// First-in first-out realtime policy.
+pub const SCHED_FIFO: c_int = 1;
+/// Round-robin realtime policy.
+pub const SCHED_RR: c_int = 2;
+/// Policy for CPU-intensive, non-interactive processes.
+pub const SCHED_BATCH: c_int = 3;
+/// Policy for processes running only when the CPU would be idle otherwise.
+pub const SCHED_IDLE: c_int = 5;
+/// Flag for `sched_setscheduler`: children do not inherit privileged scheduling parameters.
+pub const SCHED_RESET_ON_FORK: c_int = 0x40000000;
+
+/// The lowest nice value, giving the highest weight.
+pub const NICE_MIN: i8 = -20;
+/// The highest nice value, giving the lowest weight.
+pub const NICE_MAX: i8 = 19;
+/// The lowest priority for realtime policies.
+pub const RT_PRIORITY_MIN: u8 = 1;
+/// The highest priority for realtime policies.
+pub const RT_PRIORITY_MAX: u8 = 99;
+
+/// The timeslice of processes with policy [`Policy::Rr`], in nanoseconds.
+pub const RR_TIMESLICE: u64 = 100_000_000;
+/// The maximum amount of virtual runtime, in nanoseconds, a process waking up can be ahead of
+/// others, so that interactive processes get to run quickly.
+pub(super) const WAKEUP_CREDIT: u64 = 3_000_000;
+/// The duration, in nanoseconds, after which a process competing with others for the CPU is
+/// preempted, when timer interrupts are programmed on demand.
+pub(super) const PREEMPT_PERIOD: u64 = 4_000_000;
+
+/// The weight of a process with a nice value of zero.
+const NICE_0_WEIGHT: u64 = 1024;
+/// The weight of processes with policy [`Policy::Idle`].
+const IDLE_WEIGHT: u64 = 3;
+/// Weights for each nice value, starting from [`NICE_MIN`].
+///
+/// Each step changes the share of CPU time by roughly 10%.
+const WEIGHTS: [u64; 40] = [
+	88761, 71755, 56483, 46273, 36291, // -20
+	29154, 23254, 18705, 14949, 11916, // -15
+	9548, 7620, 6100, 4904, 3906, // -10
+	3121, 2501, 1991, 1586, 1277, // -5
+	1024, 820, 655, 526, 423, // 0
+	335, 272, 215, 172, 137, // 5
+	110, 87, 70, 56, 45, // 10
+	36, 29, 23, 18, 15, // 15
+];
+
+/// A scheduling policy.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Policy {
+	/// Time-sharing according to the nice value.
+	Other = 0,
+	/// Realtime, running until it blocks, yields or gets preempted by a higher priority.
+	Fifo = 1,
+	/// Realtime, like [`Self::Fifo`] but with a timeslice among processes of the same priority.
+	Rr = 2,
+	/// Like [`Self::Other`], but without the wake up bonus.
+	Batch = 3,
+	/// Time-sharing with a very low weight.
+	Idle = 5,
+}
+
+impl Policy {
+	/// Returns the policy with the given ID.
+	///
+	/// If the ID is invalid, the function returns `None`.
+	pub fn from_id(id: c_int) -> Option<Self> {
+		match id {
+			SCHED_OTHER => Some(Self::Other),
+			SCHED_FIFO => Some(Self::Fifo),
+			SCHED_RR => Some(Self::Rr),
+			SCHED_BATCH => Some(Self::Batch),
+			SCHED_IDLE => Some(Self::Idle),
+			_ => None,
+		}
+	}
+
+	/// Tells whether the policy is realtime.
+	#[inline]
+	pub fn is_realtime(self) -> bool {
+		matches!(self, Self::Fifo | Self::Rr)
+	}
+
+	/// Returns the range of valid static priorities for the policy.
+	pub fn priority_range(self) -> (u8, u8) {
+		if self.is_realtime() {
+			(RT_PRIORITY_MIN, RT_PRIORITY_MAX)
+		} else {
+			(0, 0)
+		}
+	}
+}
+
+/// Scheduling parameters and bookkeeping of a process.
+///
+/// Bookkeeping fields are modified only with the scheduler locked.
+pub struct SchedEntity {
+	/// The scheduling policy.
+	policy: AtomicU8,
+	/// The nice value.
+	nice: AtomicI8,
+	/// The static priority, for realtime policies.
+	rt_priority: AtomicU8,
+	/// If set, children are reset to the default policy and a non-negative nice value.
+	reset_on_fork: AtomicBool,
+
+	/// The virtual runtime, in nanoseconds.
+	pub(super) vruntime: AtomicU64,
+	/// The timestamp at which the process has been accounted for the last time while running.
+	pub(super) exec_start: AtomicU64,
+	/// The remaining timeslice for [`Policy::Rr`], in nanoseconds.
+	pub(super) slice: AtomicU64,
+	/// The arrival sequence number of the process in its realtime queue.
+	pub(super) seq: AtomicU64,
+	/// Tells whether the process is in a run queue.
+	pub(super) queued: AtomicBool,
+	/// Tells whether the process yielded the CPU.
+	pub(super) yielded: AtomicBool,
+}
+
+impl Default for SchedEntity {
+	fn default() -> Self {
+		Self {
+			policy: AtomicU8::new(Policy::Other as _),
+			nice: AtomicI8::new(0),
+			rt_priority: AtomicU8::new(0),
+			reset_on_fork: AtomicBool::new(false),
+
+			vruntime: AtomicU64::new(0),
+			exec_start: AtomicU64::new(0),
+			slice: AtomicU64::new(RR_TIMESLICE),
+			seq: AtomicU64::new(0),
+			queued: AtomicBool::new(false),
+			yielded: AtomicBool::new(false),
+		}
+	}
+}
+
+impl SchedEntity {
+	/// Returns the parameters for a child of the process.
+	pub fn fork(&self) -> Self {
+		let this = Self::default();
+		if self.reset_on_fork.load(Relaxed) {
+			this.nice.store(self.get_nice().max(0), Relaxed);
+		} else {
+			this.policy.store(self.policy.load(Relaxed), Relaxed);
+			this.nice.store(self.get_nice(), Relaxed);
+			this.rt_priority.store(self.get_rt_priority(), Relaxed);
+		}
+		this.vruntime.store(self.vruntime.load(Relaxed), Relaxed);
+		this
+	}
+
+	/// Returns the scheduling policy.
+	#[inline]
+	pub fn get_policy(&self) -> Policy {
+		Policy::from_id(self.policy.load(Acquire) as _).unwrap()
+	}
+
+	/// Returns the nice value.
+	#[inline]
+	pub fn get_nice(&self) -> i8 {
+		self.nice.load(Acquire)
+	}
+
+	/// Returns the static priority, which is non-zero only for realtime policies.
+	#[inline]
+	pub fn get_rt_priority(&self) -> u8 {
+		self.rt_priority.load(Acquire)
+	}
+
+	/// Tells whether children are reset to the default policy.
+	#[inline]
+	pub fn is_reset_on_fork(&self) -> bool {
+		self.reset_on_fork.load(Acquire)
+	}
+
+	/// Makes the process give up the CPU, placing it after the other processes of the same
+	/// priority on the next scheduler tick.
+	pub fn yield_cpu(&self) {
+		self.yielded.store(true, Relaxed);
+	}
+
+	/// Sets the nice value, clamped to the valid range.
+	///
+	/// If the process is in a run queue, the caller must remove it beforehand.
+	pub(super) fn set_nice(&self, nice: i8) {
+		self.nice.store(nice.clamp(NICE_MIN, NICE_MAX), Release);
+	}
+
+	/// Sets the policy and its static priority.
+	///
+	/// If the process is in a run queue, the caller must remove it beforehand.
+	pub(super) fn set_policy(&self, policy: Policy, rt_priority: u8, reset_on_fork: bool) {
+		self.policy.store(policy as _, Release);
+		self.rt_priority.store(rt_priority, Release);
+		self.reset_on_fork.store(reset_on_fork, Release);
+		self.slice.store(RR_TIMESLICE, Relaxed);
+	}
+
+	/// Returns the weight of the process, determining its share of CPU time.
+	pub fn weight(&self) -> u64 {
+		if self.get_policy() == Policy::Idle {
+			return IDLE_WEIGHT;
+		}
+		WEIGHTS[(self.get_nice() - NICE_MIN) as usize]
+	}
+
+	/// Returns the delay, in nanoseconds, after which the process, running while others are
+	/// waiting, has to be preempted.
+	///
+	/// If the process does not need to be preempted, the function returns `None`.
+	pub(super) fn preempt_delay(&self) -> Option<u64> {
+		match self.get_policy() {
+			Policy::Fifo => None,
+			Policy::Rr => Some(self.slice.load(Relaxed)),
+			_ => Some(PREEMPT_PERIOD),
+		}
+	}
+
+	/// Accounts for the time the process has been running until `now`, in nanoseconds.
+	pub(super) fn account(&self, now: u64) {
+		let delta = now.saturating_sub(self.exec_start.load(Relaxed));
+		self.exec_start.store(now, Relaxed);
+		match self.get_policy() {
+			Policy::Fifo => {}
+			Policy::Rr => {
+				let slice = self.slice.load(Relaxed);
+				self.slice.store(slice.saturating_sub(delta), Relaxed);
+			}
+			_ => {
+				let delta = delta * NICE_0_WEIGHT / self.weight();
+				self.vruntime.fetch_add(delta, Relaxed);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn weight_nice() {
+		let ent = SchedEntity::default();
+		assert_eq!(ent.weight(), NICE_0_WEIGHT);
+		ent.set_nice(NICE_MIN - 1);
+		assert_eq!(ent.get_nice(), NICE_MIN);
+		assert_eq!(ent.weight(), WEIGHTS[0]);
+		ent.set_nice(NICE_MAX);
+		assert_eq!(ent.weight(), WEIGHTS[39]);
+		ent.set_policy(Policy::Idle, 0, false);
+		assert_eq!(ent.weight(), IDLE_WEIGHT);
+	}
+
+	#[test_case]
+	fn account_vruntime() {
+		let ent = SchedEntity::default();
+		ent.account(1000);
+		assert_eq!(ent.vruntime.load(Relaxed), 1000);
+		// Heavier processes accumulate virtual runtime slower
+		ent.set_nice(-5);
+		ent.account(2000);
+		assert!(ent.vruntime.load(Relaxed) < 2000);
+		// Realtime processes consume their timeslice instead
+		ent.set_policy(Policy::Rr, RT_PRIORITY_MIN, false);
+		let vruntime = ent.vruntime.load(Relaxed);
+		ent.account(3000);
+		assert_eq!(ent.vruntime.load(Relaxed), vruntime);
+		assert_eq!(ent.slice.load(Relaxed), RR_TIMESLICE - 1000);
+	}
+
+	#[test_case]
+	fn fork_reset() {
+		let ent = SchedEntity::default();
+		ent.set_nice(-10);
+		ent.set_policy(Policy::Fifo, 50, true);
+		let child = ent.fork();
+		assert_eq!(child.get_policy(), Policy::Other);
+		assert_eq!(child.get_nice(), 0);
+		assert_eq!(child.get_rt_priority(), 0);
+		ent.set_policy(Policy::Rr, 50, false);
+		let child = ent.fork();
+		assert_eq!(child.get_policy(), Policy::Rr);
+		assert_eq!(child.get_rt_priority(), 50);
+	}
+}
@@ -21,9 +21,11 @@
 use crate::{
 	arch::x86::{fxrstor, fxsave, gdt, idt::IntFrame, tss},
 	memory::vmem::KERNEL_VMEM,
-	process::{Process, mem_space::MemSpace},
+	process::{Process, mem_space::MemSpace, scheduler::core_local},
 };
-use core::{arch::global_asm, mem::offset_of, ptr::NonNull};
+#[cfg(target_arch = "x86_64")]
+use core::sync::atomic::Ordering::Relaxed;
+use core::{arch::global_asm, mem::offset_of, ptr::NonNull, sync::atomic::Ordering::Release};
 
 /// Stashes current segment values during execution of `f`, restoring them after.
 pub fn stash_segments<F: FnOnce() -> T, T>(f: F) -> T {
@@ -36,9 +38,8 @@ pub fn stash_segments<F: FnOnce() -> T, T>(f: F) -> T {
 	{
 		use crate::arch::x86;
 		use core::arch::asm;
-		// Save MSR
+		// Save MSR. `IA32_GS_BASE` points to the core-local structure in kernelspace
 		let fs_base = x86::rdmsr(x86::IA32_FS_BASE);
-		let gs_base = x86::rdmsr(x86::IA32_GS_BASE);
 		let kernel_gs_base = x86::rdmsr(x86::IA32_KERNEL_GS_BASE);
 		// Save segment selectors
 		let mut fs: u16;
@@ -52,6 +53,8 @@ pub fn stash_segments<F: FnOnce() -> T, T>(f: F) -> T {
 			);
 		}
 		let res = f();
+		// Reloading segment selectors clears the associated base
+		let gs_base = x86::rdmsr(x86::IA32_GS_BASE);
 		// Restore segment selectors
 		unsafe {
 			asm!(
@@ -77,7 +80,25 @@ pub fn stash_segments<F: FnOnce() -> T, T>(f: F) -> T {
 ///
 /// The pointers must point to valid processes.
 pub unsafe fn switch(prev: *const Process, next: *const Process) {
+	// Save userspace segment bases so that they can be accessed while switched out
+	#[cfg(target_arch = "x86_64")]
+	{
+		use crate::arch::x86;
+		(*prev)
+			.fs_base
+			.store(x86::rdmsr(x86::IA32_FS_BASE), Relaxed);
+		(*prev)
+			.gs_base
+			.store(x86::rdmsr(x86::IA32_KERNEL_GS_BASE), Relaxed);
+	}
 	stash_segments(|| switch_asm(prev, next));
+	// The bases may have been changed by a tracer in the meantime
+	#[cfg(target_arch = "x86_64")]
+	{
+		use crate::arch::x86;
+		x86::wrmsr(x86::IA32_FS_BASE, (*prev).fs_base.load(Relaxed));
+		x86::wrmsr(x86::IA32_KERNEL_GS_BASE, (*prev).gs_base.load(Relaxed));
+	}
 }
 
 // Note: the functions below are saving only the registers that are not clobbered by the call to
@@ -123,6 +144,8 @@ fork_asm:
 	push edi
     mov eax, [esp + 20]
     mov [eax + {off}], esp
+	# The parent's context is saved, it may now run on another core
+	mov byte ptr [eax + {on_core}], 0
 
 	# Set stack at the frame's position (shift by 4 to fake `eip`)
 	add esp, 24
@@ -152,7 +175,10 @@ switch_asm:
 	mov [esp + 4], eax
 	mov [esp + 8], edx
 	jmp switch_finish
-"#, off = const offset_of!(Process, kernel_sp));
+"#,
+	off = const offset_of!(Process, kernel_sp),
+	on_core = const offset_of!(Process, on_core)
+);
 
 #[cfg(target_arch = "x86_64")]
 global_asm!(r#"
@@ -172,6 +198,8 @@ fork_asm:
 	push r14
 	push r15
     mov [rdi + {off}], rsp
+	# The parent's context is saved, it may now run on another core
+	mov byte ptr [rdi + {on_core}], 0
 
 	mov rdi, rdx
 	jmp init_ctx
@@ -196,14 +224,29 @@ switch_asm:
 	pop rbp
 
 	jmp switch_finish
-"#, off = const offset_of!(Process, kernel_sp));
+"#,
+	off = const offset_of!(Process, kernel_sp),
+	on_core = const offset_of!(Process, on_core)
+);
 
-/// Finishes switching context from `prev` to `next`, that is restore everything else than
-/// general-purpose registers.
+/// Finishes switching context from `prev` to `next`, then releases `prev`.
 ///
 /// This function is jumped to from [`switch`].
 #[unsafe(export_name = "switch_finish")]
-pub extern "C" fn finish(prev: &Process, next: &Process) {
+extern "C" fn switch_finish(prev: *const Process, next: *const Process) {
+	unsafe {
+		finish(&*prev, &*next);
+		// The context of `prev` is saved, it may now run on another core
+		(*prev).on_core.store(false, Release);
+	}
+	// Release the previous process, which may be the last reference to it
+	let prev = unsafe { (*core_local().prev.get()).take() };
+	drop(prev);
+}
+
+/// Finishes switching context from `prev` to `next`, that is restore everything else than
+/// general-purpose registers.
+pub fn finish(prev: &Process, next: &Process) {
 	// Bind the memory space
 	match next.mem_space.as_ref() {
 		Some(mem_space) => MemSpace::bind(mem_space),
@@ -23,14 +23,16 @@ pub mod ucontext;
 use super::{Process, REDZONE_SIZE, State};
 use crate::{
 	arch::x86::idt::IntFrame,
-	file::perm::Uid,
+	file::{perm::Uid, signalfd, wait_queue::PollTable},
 	memory::VirtAddr,
-	process::{mem_space::MemSpace, pid::Pid},
+	process::{futex, mem_space::MemSpace, pid::Pid, scheduler::Scheduler},
 	syscall::wait::{WCONTINUED, WUNTRACED},
-	time::unit::ClockIdT,
+	time::unit::Timestamp,
 };
 use core::{
+	array,
 	ffi::{c_int, c_void},
+	mem,
 	mem::{size_of, transmute},
 	ptr,
 	ptr::NonNull,
@@ -40,7 +42,10 @@ use core::{
 use ucontext::UContext32;
 #[cfg(target_pointer_width = "64")]
 use ucontext::UContext64;
-use utils::{errno, errno::Errno};
+use utils::{
+	errno,
+	errno::{EResult, Errno},
+};
 
 /// Signal handler value: Ignoring the signal.
 pub const SIG_IGN: usize = 0x0;
@@ -52,6 +57,8 @@ pub const SIG_DFL: usize = 0x1;
 pub const SA_SIGINFO: u64 = 0x00000004;
 /// [`SigAction`] flag: If set, use [`SigAction::sa_restorer`] as signal trampoline.
 pub const SA_RESTORER: u64 = 0x04000000;
+/// [`SigAction`] flag: If set, the handler is executed on the alternate signal stack, if any.
+pub const SA_ONSTACK: u64 = 0x08000000;
 /// [`SigAction`] flag: If set, the system call must restart after being interrupted by a signal.
 pub const SA_RESTART: u64 = 0x10000000;
 /// [`SigAction`] flag: If set, the signal is not added to the signal mask of the process when
@@ -65,9 +72,20 @@ pub const SIGEV_NONE: c_int = 1;
 /// Notify method: starts a function as a new thread
 pub const SIGEV_THREAD: c_int = 2;
 
+/// Alternate signal stack flag: the thread is currently executing on the stack.
+pub const SS_ONSTACK: i32 = 1;
+/// Alternate signal stack flag: the stack is disabled.
+pub const SS_DISABLE: i32 = 2;
+/// The minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 2048;
+
 /// The size of the signal handlers table (the number of signals + 1, since
 /// indexing begins at 1 instead of 0).
-pub const SIGNALS_COUNT: usize = 32;
+pub const SIGNALS_COUNT: usize = 65;
+/// The first realtime signal.
+pub const SIGRTMIN: i32 = 32;
+/// The last realtime signal.
+pub const SIGRTMAX: i32 = 64;
 
 /// Enumeration representing the action to perform for a signal.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -89,7 +107,13 @@ impl SignalAction {
 	pub fn exec(self, process: &Process) {
 		match self {
 			// TODO when `Abort`ing, dump core
-			SignalAction::Terminate | SignalAction::Abort => process.set_state(State::Zombie),
+			SignalAction::Terminate | SignalAction::Abort => {
+				// The whole process terminates
+				let status = process.signal.lock().exit_status;
+				process.exit_siblings(status as _);
+				futex::exit(process);
+				process.set_state(State::Zombie);
+			}
 			SignalAction::Ignore => {}
 			SignalAction::Stop => {
 				process.set_state(State::Stopped);
@@ -106,58 +130,259 @@ impl SignalAction {
 /// A signal handler value.
 pub type SigVal = usize;
 
-// FIXME: fields are incorrect (check musl source)
+/// The size of the userspace `siginfo_t` structure.
+pub const SIGINFO_SIZE: usize = 128;
+
+/// `si_code` value: the signal has been sent by `kill`.
+pub const SI_USER: i32 = 0;
+/// `si_code` value: the signal has been sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+/// `si_code` value: the signal has been sent by `sigqueue`.
+pub const SI_QUEUE: i32 = -1;
+/// `si_code` value: the signal has been sent by the expiration of a timer.
+pub const SI_TIMER: i32 = -2;
+/// `si_code` value: the signal has been sent by the arrival of a message on a message queue.
+pub const SI_MESGQ: i32 = -3;
+/// `si_code` value: the signal has been sent by `tkill` or `tgkill`.
+pub const SI_TKILL: i32 = -6;
+
+/// `si_code` value for `SIGILL`: illegal opcode.
+pub const ILL_ILLOPN: i32 = 2;
+/// `si_code` value for `SIGFPE`: integer divide by zero.
+pub const FPE_INTDIV: i32 = 1;
+/// `si_code` value for `SIGSEGV`: address not mapped to object.
+pub const SEGV_MAPERR: i32 = 1;
+/// `si_code` value for `SIGSEGV`: invalid permissions for mapped object.
+pub const SEGV_ACCERR: i32 = 2;
+/// `si_code` value for `SIGBUS`: invalid address alignment.
+pub const BUS_ADRALN: i32 = 1;
+/// `si_code` value for `SIGBUS`: nonexistent physical address.
+pub const BUS_ADRERR: i32 = 2;
+/// `si_code` value for `SIGTRAP`: process breakpoint.
+pub const TRAP_BRKPT: i32 = 1;
+/// `si_code` value for `SIGTRAP`: process trace trap.
+pub const TRAP_TRACE: i32 = 2;
+/// `si_code` value for `SIGCHLD`: child has exited.
+pub const CLD_EXITED: i32 = 1;
+/// `si_code` value for `SIGCHLD`: child was killed.
+pub const CLD_KILLED: i32 = 2;
+/// `si_code` value for `SIGCHLD`: child has stopped.
+pub const CLD_STOPPED: i32 = 5;
+/// `si_code` value for `SIGCHLD`: stopped child has continued.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// The part of `siginfo_t` that is meaningful for a given signal and code.
+enum SigInfoLayout {
+	Kill,
+	Timer,
+	Rt,
+	Chld,
+	Fault,
+	Poll,
+}
+
 /// Signal information.
-#[repr(C)]
+///
+/// In the userspace structure, most fields share the same storage. Which ones are meaningful
+/// depends on the signal and on [`Self::si_code`].
+#[derive(Clone, Copy, Debug)]
 pub struct SigInfo {
 	/// Signal number.
-	si_signo: i32,
+	pub si_signo: Signal,
 	/// An errno value.
-	si_errno: i32,
-	/// Signal code.
-	si_code: i32,
-	/// Trap number that caused hardware-generated signal.
-	si_trapno: i32,
+	pub si_errno: i32,
+	/// Signal code, telling where the signal comes from.
+	pub si_code: i32,
 	/// Sending process ID.
-	si_pid: Pid,
+	pub si_pid: Pid,
 	/// Real user ID of sending process.
-	si_uid: Uid,
+	pub si_uid: Uid,
 	/// Exit value or signal.
-	si_status: i32,
+	pub si_status: i32,
 	/// User time consumed.
-	si_utime: ClockIdT,
+	pub si_utime: i64,
 	/// System time consumed.
-	si_stime: ClockIdT,
-	/// Signal value
-	si_value: SigVal,
-	/// POSIX.1b signal.
-	si_int: i32,
-	/// POSIX.1b signal.
-	si_ptr: *mut c_void,
-	/// Timer overrun count.
-	si_overrun: i32,
+	pub si_stime: i64,
+	/// Signal value.
+	pub si_value: SigVal,
 	/// Timer ID.
-	si_timerid: i32,
+	pub si_timerid: i32,
+	/// Timer overrun count.
+	pub si_overrun: i32,
 	/// Memory location which caused fault.
-	si_addr: *mut c_void,
+	pub si_addr: usize,
 	/// Band event.
-	si_band: i32, // FIXME long (64bits?)
+	pub si_band: i64,
 	/// File descriptor.
-	si_fd: i32,
-	/// Least significant bit of address.
-	si_addr_lsb: i16,
-	/// Lower bound when address violation.
-	si_lower: *mut c_void,
-	/// Upper bound when address violation.
-	si_upper: *mut c_void,
-	/// Protection key on PTE that caused fault.
-	si_pkey: i32,
-	/// Address of system call instruction.
-	si_call_addr: *mut c_void,
-	/// Number of attempted system call.
-	si_syscall: i32,
-	/// Architecture of attempted system call.
-	si_arch: u32,
+	pub si_fd: i32,
+}
+
+impl SigInfo {
+	/// Creates an instance for `signal` with the code `code`, leaving other fields cleared.
+	pub fn new(signal: Signal, code: i32) -> Self {
+		Self {
+			si_signo: signal,
+			si_errno: 0,
+			si_code: code,
+			si_pid: 0,
+			si_uid: 0,
+			si_status: 0,
+			si_utime: 0,
+			si_stime: 0,
+			si_value: 0,
+			si_timerid: 0,
+			si_overrun: 0,
+			si_addr: 0,
+			si_band: 0,
+			si_fd: 0,
+		}
+	}
+
+	/// Creates an instance for a fault of type `code` at address `addr`.
+	pub fn fault(signal: Signal, code: i32, addr: usize) -> Self {
+		Self {
+			si_addr: addr,
+			..Self::new(signal, code)
+		}
+	}
+
+	/// Returns the layout of the userspace structure.
+	fn layout(&self) -> SigInfoLayout {
+		match self.si_code {
+			SI_TIMER => SigInfoLayout::Timer,
+			SI_QUEUE | SI_MESGQ => SigInfoLayout::Rt,
+			1..SI_KERNEL => match self.si_signo {
+				Signal::SIGCHLD => SigInfoLayout::Chld,
+				Signal::SIGILL
+				| Signal::SIGFPE
+				| Signal::SIGSEGV
+				| Signal::SIGBUS
+				| Signal::SIGTRAP => SigInfoLayout::Fault,
+				Signal::SIGPOLL => SigInfoLayout::Poll,
+				_ => SigInfoLayout::Kill,
+			},
+			_ => SigInfoLayout::Kill,
+		}
+	}
+
+	/// Returns the offsets of the fields of the userspace structure that depend on the
+	/// architecture.
+	///
+	/// The function returns the offset of the union, the size of pointers and `long`s, and the
+	/// offset of `si_utime` in the union.
+	fn user_offsets(compat: bool) -> (usize, usize, usize) {
+		if compat { (12, 4, 12) } else { (16, 8, 16) }
+	}
+
+	/// Creates an instance for `signal` from the userspace structure `buf`.
+	///
+	/// `compat` tells whether the structure has the 32-bit layout.
+	pub fn from_user(signal: Signal, buf: &[u8; SIGINFO_SIZE], compat: bool) -> Self {
+		let (base, long, utime) = Self::user_offsets(compat);
+		let get = |off: usize, size: usize| {
+			let mut val = [0; 8];
+			val[..size].copy_from_slice(&buf[off..(off + size)]);
+			u64::from_le_bytes(val)
+		};
+		let sign_extend = |val: u64| {
+			if long == 4 {
+				val as i32 as i64
+			} else {
+				val as i64
+			}
+		};
+		let mut info = Self::new(signal, get(8, 4) as i32);
+		info.si_errno = get(4, 4) as _;
+		match info.layout() {
+			SigInfoLayout::Kill => {
+				info.si_pid = get(base, 4) as _;
+				info.si_uid = get(base + 4, 4) as _;
+			}
+			SigInfoLayout::Timer => {
+				info.si_timerid = get(base, 4) as _;
+				info.si_overrun = get(base + 4, 4) as _;
+				info.si_value = get(base + 8, long) as _;
+			}
+			SigInfoLayout::Rt => {
+				info.si_pid = get(base, 4) as _;
+				info.si_uid = get(base + 4, 4) as _;
+				info.si_value = get(base + 8, long) as _;
+			}
+			SigInfoLayout::Chld => {
+				info.si_pid = get(base, 4) as _;
+				info.si_uid = get(base + 4, 4) as _;
+				info.si_status = get(base + 8, 4) as _;
+				info.si_utime = sign_extend(get(base + utime, long));
+				info.si_stime = sign_extend(get(base + utime + long, long));
+			}
+			SigInfoLayout::Fault => info.si_addr = get(base, long) as _,
+			SigInfoLayout::Poll => {
+				info.si_band = sign_extend(get(base, long));
+				info.si_fd = get(base + long, 4) as _;
+			}
+		}
+		info
+	}
+
+	/// Returns the userspace structure.
+	///
+	/// `compat` tells whether the structure has the 32-bit layout.
+	pub fn to_user(&self, compat: bool) -> [u8; SIGINFO_SIZE] {
+		let (base, long, utime) = Self::user_offsets(compat);
+		let mut buf = [0; SIGINFO_SIZE];
+		let mut put = |off: usize, size: usize, val: u64| {
+			buf[off..(off + size)].copy_from_slice(&val.to_le_bytes()[..size]);
+		};
+		put(0, 4, self.si_signo as _);
+		put(4, 4, self.si_errno as _);
+		put(8, 4, self.si_code as _);
+		match self.layout() {
+			SigInfoLayout::Kill => {
+				put(base, 4, self.si_pid as _);
+				put(base + 4, 4, self.si_uid as _);
+			}
+			SigInfoLayout::Timer => {
+				put(base, 4, self.si_timerid as _);
+				put(base + 4, 4, self.si_overrun as _);
+				put(base + 8, long, self.si_value as _);
+			}
+			SigInfoLayout::Rt => {
+				put(base, 4, self.si_pid as _);
+				put(base + 4, 4, self.si_uid as _);
+				put(base + 8, long, self.si_value as _);
+			}
+			SigInfoLayout::Chld => {
+				put(base, 4, self.si_pid as _);
+				put(base + 4, 4, self.si_uid as _);
+				put(base + 8, 4, self.si_status as _);
+				put(base + utime, long, self.si_utime as _);
+				put(base + utime + long, long, self.si_stime as _);
+			}
+			SigInfoLayout::Fault => put(base, long, self.si_addr as _),
+			SigInfoLayout::Poll => {
+				put(base, long, self.si_band as _);
+				put(base + long, 4, self.si_fd as _);
+			}
+		}
+		buf
+	}
+}
+
+/// An alternate stack on which signal handlers can be executed, set by `sigaltstack`.
+#[derive(Clone, Copy, Debug)]
+pub struct AltStack {
+	/// The lowest address of the stack.
+	pub sp: usize,
+	/// The size of the stack in bytes.
+	pub size: usize,
+}
+
+impl AltStack {
+	/// Tells whether the stack pointer `sp` is on the stack.
+	#[inline]
+	pub fn contains(&self, sp: usize) -> bool {
+		sp > self.sp && sp - self.sp <= self.size
+	}
 }
 
 /// Kernelspace signal mask.
@@ -171,28 +396,30 @@ impl SigSet {
 		self.0 == 0
 	}
 
-	/// Tells whether the `n`th bit is set.
+	/// Tells whether the signal `n` is in the set.
+	///
+	/// As in userspace, signal `n` is represented by the bit `n - 1`.
 	#[inline]
 	pub fn is_set(&self, n: usize) -> bool {
-		self.0 & (1 << n) != 0
+		self.0 & (1 << (n - 1)) != 0
 	}
 
-	/// Sets the `n`th bit.
+	/// Adds the signal `n` to the set.
 	#[inline]
 	pub fn set(&mut self, n: usize) {
-		self.0 |= (1 << n) as u64;
+		self.0 |= 1 << (n - 1);
 	}
 
-	/// Sets the `n`th bit.
+	/// Removes the signal `n` from the set.
 	#[inline]
 	pub fn clear(&mut self, n: usize) {
-		self.0 &= !((1 << n) as u64);
+		self.0 &= !(1 << (n - 1));
 	}
 
-	/// Returns an iterator over the bitset's values
+	/// Returns an iterator over the signals in the set.
 	#[inline]
-	pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
-		(0..64).map(|n| self.is_set(n))
+	pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+		(1..=64).filter(|n| self.is_set(*n))
 	}
 }
 
@@ -348,8 +575,10 @@ impl SignalHandler {
 		}
 	}
 
-	/// Executes the action for `signal` on the **current** process `process`.
-	pub fn exec(&self, signal: Signal, process: &Process, frame: &mut IntFrame) {
+	/// Executes the action for the signal described by `info` on the **current** process
+	/// `process`.
+	pub fn exec(&self, info: &SigInfo, process: &Process, frame: &mut IntFrame) {
+		let signal = info.si_signo;
 		let process_state = process.get_state();
 		if matches!(process_state, State::Zombie) {
 			return;
@@ -368,52 +597,79 @@ impl SignalHandler {
 			}
 		};
 		// TODO trigger EFAULT if SA_RESTORER is not set
-		// TODO handle SA_SIGINFO
-		// TODO Handle the case where an alternate stack is specified (sigaltstack + flag
-		// SA_ONSTACK)
-		// Prepare the signal handler stack
-		let stack_addr = VirtAddr(frame.get_stack_address()) - REDZONE_SIZE;
-		// Size of the `ucontext_t` struct and arguments *on the stack*
-		let (ctx_size, ctx_align, arg_len) = if frame.is_compat() {
-			(
-				size_of::<UContext32>(),
-				align_of::<UContext32>(),
-				size_of::<u32>() * 2,
-			)
-		} else {
-			#[cfg(target_pointer_width = "32")]
-			unreachable!();
-			#[cfg(target_pointer_width = "64")]
-			(
-				size_of::<UContext64>(),
-				align_of::<UContext64>(),
-				size_of::<u64>(),
-			)
+		let compat = frame.is_compat();
+		let siginfo = action.sa_flags & SA_SIGINFO != 0;
+		// Prepare the signal handler stack, switching to the alternate stack if requested and
+		// not already on it
+		let sp = frame.get_stack_address();
+		let altstack = process.signal.lock().altstack;
+		let stack_addr = match altstack {
+			Some(stack) if action.sa_flags & SA_ONSTACK != 0 && !stack.contains(sp) => {
+				VirtAddr(stack.sp + stack.size)
+			}
+			_ => VirtAddr(sp) - REDZONE_SIZE,
+		};
+		let cr2 = match signal {
+			Signal::SIGSEGV | Signal::SIGBUS => info.si_addr,
+			_ => 0,
 		};
-		let ctx_addr = (stack_addr - ctx_size).down_align_to(ctx_align);
-		let signal_sp = ctx_addr - arg_len;
 		// Bind virtual memory
 		let mem_space = process.mem_space.as_ref().unwrap();
 		MemSpace::bind(mem_space);
 		// Write data on stack
-		if frame.is_compat() {
-			let args = unsafe {
-				ptr::write_volatile(ctx_addr.as_ptr(), UContext32::new(process, frame));
-				// Arguments slice
-				slice::from_raw_parts_mut(signal_sp.as_ptr::<u32>(), 2)
+		let signal_sp = if compat {
+			// With `SA_SIGINFO`, the arguments are followed by the `siginfo_t` structure, then by
+			// the context. Otherwise, the context directly follows the arguments
+			let ctx_addr = (stack_addr - size_of::<UContext32>()).down_align_to(16);
+			let (signal_sp, info_addr) = if siginfo {
+				let info_addr = ctx_addr - SIGINFO_SIZE;
+				(info_addr - size_of::<u32>() * 4, info_addr)
+			} else {
+				(ctx_addr - size_of::<u32>() * 2, ctx_addr)
 			};
-			// Argument
-			args[1] = signal as _;
-			// Return pointer
-			args[0] = action.sa_restorer as _;
-		} else {
-			#[cfg(target_pointer_width = "64")]
 			unsafe {
-				ptr::write_volatile(ctx_addr.as_ptr(), UContext64::new(process, frame));
+				ptr::write_volatile(
+					ctx_addr.as_ptr(),
+					UContext32::new(process, frame, ctx_addr, cr2),
+				);
+				let args_count = if siginfo { 4 } else { 2 };
+				let args = slice::from_raw_parts_mut(signal_sp.as_ptr::<u32>(), args_count);
 				// Return pointer
-				ptr::write_volatile(signal_sp.as_ptr::<u64>(), action.sa_restorer as _);
+				args[0] = action.sa_restorer as _;
+				// Arguments
+				args[1] = signal as _;
+				if siginfo {
+					ptr::write_volatile(info_addr.as_ptr(), info.to_user(true));
+					args[2] = info_addr.0 as _;
+					args[3] = ctx_addr.0 as _;
+				}
 			}
-		}
+			signal_sp
+		} else {
+			#[cfg(target_pointer_width = "32")]
+			unreachable!();
+			// The return pointer is followed by the context, then by the `siginfo_t` structure
+			#[cfg(target_pointer_width = "64")]
+			{
+				let info_addr = (stack_addr - SIGINFO_SIZE).down_align_to(16);
+				let ctx_addr = (info_addr - size_of::<UContext64>()).down_align_to(16);
+				let signal_sp = ctx_addr - size_of::<u64>();
+				unsafe {
+					ptr::write_volatile(
+						ctx_addr.as_ptr(),
+						UContext64::new(process, frame, ctx_addr, cr2),
+					);
+					ptr::write_volatile(info_addr.as_ptr(), info.to_user(false));
+					// Return pointer
+					ptr::write_volatile(signal_sp.as_ptr::<u64>(), action.sa_restorer as _);
+				}
+				// Arguments
+				frame.rdi = signal as _;
+				frame.rsi = info_addr.0 as _;
+				frame.rdx = ctx_addr.0 as _;
+				signal_sp
+			}
+		};
 		// Block signal from `sa_mask`
 		{
 			let mut signals_manager = process.signal.lock();
@@ -427,14 +683,17 @@ impl SignalHandler {
 		frame.rsp = signal_sp.0 as _;
 		frame.rip = action.sa_handler as _;
 		#[cfg(target_pointer_width = "64")]
-		if !frame.is_compat() {
+		if !compat {
 			frame.rcx = frame.rip;
-			// Argument
-			frame.rdi = signal as _;
 		}
 	}
 }
 
+/// Returns a table of signal handlers, all set to the default action.
+pub fn default_handlers() -> [SignalHandler; SIGNALS_COUNT] {
+	array::from_fn(|_| SignalHandler::Default)
+}
+
 /// Enumeration of signal types.
 #[repr(i32)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -469,6 +728,8 @@ pub enum Signal {
 	SIGALRM = 14,
 	/// Termination.
 	SIGTERM = 15,
+	/// Stack fault on coprocessor.
+	SIGSTKFLT = 16,
 	/// Child process terminated.
 	SIGCHLD = 17,
 	/// Continue executing.
@@ -495,8 +756,76 @@ pub enum Signal {
 	SIGWINCH = 28,
 	/// Pollable event.
 	SIGPOLL = 29,
+	/// Power failure.
+	SIGPWR = 30,
 	/// Bad system call.
 	SIGSYS = 31,
+	/// Realtime signal 0.
+	SIGRT0 = 32,
+	/// Realtime signal 1.
+	SIGRT1 = 33,
+	/// Realtime signal 2.
+	SIGRT2 = 34,
+	/// Realtime signal 3.
+	SIGRT3 = 35,
+	/// Realtime signal 4.
+	SIGRT4 = 36,
+	/// Realtime signal 5.
+	SIGRT5 = 37,
+	/// Realtime signal 6.
+	SIGRT6 = 38,
+	/// Realtime signal 7.
+	SIGRT7 = 39,
+	/// Realtime signal 8.
+	SIGRT8 = 40,
+	/// Realtime signal 9.
+	SIGRT9 = 41,
+	/// Realtime signal 10.
+	SIGRT10 = 42,
+	/// Realtime signal 11.
+	SIGRT11 = 43,
+	/// Realtime signal 12.
+	SIGRT12 = 44,
+	/// Realtime signal 13.
+	SIGRT13 = 45,
+	/// Realtime signal 14.
+	SIGRT14 = 46,
+	/// Realtime signal 15.
+	SIGRT15 = 47,
+	/// Realtime signal 16.
+	SIGRT16 = 48,
+	/// Realtime signal 17.
+	SIGRT17 = 49,
+	/// Realtime signal 18.
+	SIGRT18 = 50,
+	/// Realtime signal 19.
+	SIGRT19 = 51,
+	/// Realtime signal 20.
+	SIGRT20 = 52,
+	/// Realtime signal 21.
+	SIGRT21 = 53,
+	/// Realtime signal 22.
+	SIGRT22 = 54,
+	/// Realtime signal 23.
+	SIGRT23 = 55,
+	/// Realtime signal 24.
+	SIGRT24 = 56,
+	/// Realtime signal 25.
+	SIGRT25 = 57,
+	/// Realtime signal 26.
+	SIGRT26 = 58,
+	/// Realtime signal 27.
+	SIGRT27 = 59,
+	/// Realtime signal 28.
+	SIGRT28 = 60,
+	/// Realtime signal 29.
+	SIGRT29 = 61,
+	/// Realtime signal 30.
+	SIGRT30 = 62,
+	/// Realtime signal 31.
+	SIGRT31 = 63,
+	/// Realtime signal 32.
+	SIGRT32 = 64,
 }
 
 impl TryFrom<i32> for Signal {
@@ -504,7 +833,7 @@ impl TryFrom<i32> for Signal {
 
 	/// `id` is the signal ID.
 	fn try_from(id: i32) -> Result<Self, Self::Error> {
-		if matches!(id, (1..=15) | (17..=29) | 31) {
+		if matches!(id, 1..=SIGRTMAX) {
 			// Safe because the value is in range
 			unsafe { Ok(transmute::<i32, Self>(id)) }
 		} else {
@@ -532,6 +861,7 @@ impl Signal {
 			Self::SIGPIPE => SignalAction::Terminate,
 			Self::SIGALRM => SignalAction::Terminate,
 			Self::SIGTERM => SignalAction::Terminate,
+			Self::SIGSTKFLT => SignalAction::Terminate,
 			Self::SIGCHLD => SignalAction::Ignore,
 			Self::SIGCONT => SignalAction::Continue,
 			Self::SIGSTOP => SignalAction::Stop,
@@ -545,15 +875,154 @@ impl Signal {
 			Self::SIGPROF => SignalAction::Terminate,
 			Self::SIGWINCH => SignalAction::Ignore,
 			Self::SIGPOLL => SignalAction::Terminate,
+			Self::SIGPWR => SignalAction::Terminate,
 			Self::SIGSYS => SignalAction::Abort,
+			// Realtime signals
+			_ => SignalAction::Terminate,
 		}
 	}
 
 	/// Tells whether the signal can be caught.
 	pub fn can_catch(&self) -> bool {
-		!matches!(
-			self,
-			Self::SIGKILL | Self::SIGSEGV | Self::SIGSTOP | Self::SIGSYS
-		)
+		!matches!(self, Self::SIGKILL | Self::SIGSTOP)
+	}
+
+	/// Tells whether the signal is a realtime signal.
+	///
+	/// Unlike other signals, several instances of a realtime signal can be pending at once.
+	pub fn is_realtime(&self) -> bool {
+		*self as i32 >= SIGRTMIN
+	}
+}
+
+/// Executes `f` with the signal mask of the current thread temporarily replaced by `sigmask`, as
+/// done by `pselect6` or `ppoll`. If `sigmask` is `None`, the mask is left unchanged.
+///
+/// If `f` is interrupted by a signal ([`errno::EINTR`]), the previous mask is restored only once
+/// the signal has been handled, so that the handler runs with the temporary mask.
+pub fn with_sigmask<T, F: FnOnce() -> EResult<T>>(sigmask: Option<SigSet>, f: F) -> EResult<T> {
+	let Some(sigmask) = sigmask else {
+		return f();
+	};
+	let proc = Process::current();
+	{
+		let mut signal = proc.signal.lock();
+		let prev = mem::replace(&mut signal.sigmask, sigmask);
+		signal.saved_sigmask = Some(prev);
+	}
+	let res = f();
+	if !matches!(&res, Err(e) if e.as_int() == errno::EINTR) {
+		proc.signal.lock().restore_sigmask();
+	}
+	res
+}
+
+/// Makes the current thread sleep until a signal is delivered to it, as done by `pause` or
+/// `sigsuspend`.
+///
+/// If `sigmask` is not `None`, the signal mask is replaced by it while waiting (see
+/// [`with_sigmask`]). `SIGKILL` and `SIGSTOP` cannot be blocked.
+///
+/// The function always returns [`errno::EINTR`].
+pub fn suspend(sigmask: Option<SigSet>) -> EResult<usize> {
+	let sigmask = sigmask.map(|mut sigmask| {
+		sigmask.clear(Signal::SIGKILL as _);
+		sigmask.clear(Signal::SIGSTOP as _);
+		sigmask
+	});
+	with_sigmask(sigmask, || {
+		let proc = Process::current();
+		loop {
+			proc.set_state(State::Sleeping);
+			// Check once sleeping, since a signal sent before would not have woken the thread up
+			if proc.has_pending_signal() {
+				proc.set_state(State::Running);
+				return Err(errno!(EINTR));
+			}
+			Scheduler::tick();
+		}
+	})
+}
+
+/// Waits for a signal in `set` to be pending on the current thread, then removes it from the
+/// pending signals and returns its information, as done by `sigtimedwait`.
+///
+/// The signals in `set` are usually blocked, so that they are not handled otherwise.
+///
+/// `timeout` is the maximum duration to wait for, in nanoseconds. If `None`, the function waits
+/// indefinitely.
+///
+/// If no signal arrived before the timeout, the function returns [`errno::EAGAIN`]. If waiting is
+/// interrupted by a signal that is not in `set`, the function returns [`errno::EINTR`].
+pub fn wait_signal(mut set: SigSet, timeout: Option<Timestamp>) -> EResult<SigInfo> {
+	set.clear(Signal::SIGKILL as _);
+	set.clear(Signal::SIGSTOP as _);
+	let proc = Process::current();
+	let mut info = None;
+	PollTable::wait(timeout, |table| {
+		// Register before checking, so that a signal sent in between is not missed
+		if let Some(table) = table {
+			table.register(&signalfd::WAIT_QUEUE)?;
+		}
+		info = proc.signal.lock().take_signal(set);
+		Ok(info.is_some() as usize)
+	})?;
+	info.ok_or_else(|| errno!(EAGAIN))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::process::ProcessSignal;
+
+	#[test_case]
+	fn siginfo_user_layout() {
+		let info = SigInfo {
+			si_pid: 42,
+			si_uid: 1000,
+			si_value: 0xdead,
+			..SigInfo::new(Signal::SIGRT1, SI_QUEUE)
+		};
+		let buf = info.to_user(false);
+		assert_eq!(buf[0..4], (Signal::SIGRT1 as u32).to_le_bytes());
+		assert_eq!(buf[16..20], 42u32.to_le_bytes());
+		assert_eq!(buf[24..32], 0xdeadu64.to_le_bytes());
+		let buf = info.to_user(true);
+		assert_eq!(buf[12..16], 42u32.to_le_bytes());
+		assert_eq!(buf[20..24], 0xdeadu32.to_le_bytes());
+		for compat in [false, true] {
+			let res = SigInfo::from_user(Signal::SIGRT1, &info.to_user(compat), compat);
+			assert_eq!(res.si_code, SI_QUEUE);
+			assert_eq!(res.si_pid, 42);
+			assert_eq!(res.si_uid, 1000);
+			assert_eq!(res.si_value, 0xdead);
+		}
+	}
+
+	#[test_case]
+	fn signal_queue() {
+		let mut signal = ProcessSignal::new().unwrap();
+		for value in 0..3 {
+			let info = SigInfo {
+				si_value: value,
+				..SigInfo::new(Signal::SIGRT1, SI_QUEUE)
+			};
+			signal.queue(info).unwrap();
+		}
+		signal
+			.queue(SigInfo::new(Signal::SIGUSR1, SI_USER))
+			.unwrap();
+		signal
+			.queue(SigInfo::new(Signal::SIGUSR1, SI_USER))
+			.unwrap();
+		// Standard signals are not queued and come first
+		assert_eq!(signal.next_signal().unwrap().si_signo, Signal::SIGUSR1);
+		for value in 0..3 {
+			let info = signal.next_signal().unwrap();
+			assert_eq!(info.si_signo, Signal::SIGRT1);
+			assert_eq!(info.si_value, value);
+		}
+		assert!(signal.next_signal().is_none());
+		assert!(signal.pending().is_empty());
 	}
 }
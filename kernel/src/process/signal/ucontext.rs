@@ -21,12 +21,32 @@
 #![allow(missing_docs)]
 
 use crate::{
-	arch::x86::{gdt, idt::IntFrame},
+	arch::x86::{FxState, fxrstor, fxsave, gdt, idt::IntFrame},
+	memory::VirtAddr,
 	process::{Process, signal::SigSet},
 };
+use core::mem::offset_of;
 
 // TODO restore everything
 
+/// Returns the current FPU state.
+fn save_fpu() -> FxState {
+	let mut fx = FxState([0; 512]);
+	fxsave(&mut fx);
+	fx
+}
+
+/// Loads the FPU state `fx`, provided by userspace.
+fn load_fpu(mut fx: FxState) {
+	// Reserved bits of MXCSR must be cleared, else `fxrstor` faults
+	let cur = save_fpu();
+	let mask = u32::from_le_bytes(cur.0[28..32].try_into().unwrap());
+	let mask = if mask != 0 { mask } else { 0xffbf };
+	let mxcsr = u32::from_le_bytes(fx.0[24..28].try_into().unwrap()) & mask;
+	fx.0[24..28].copy_from_slice(&mxcsr.to_le_bytes());
+	fxrstor(&fx);
+}
+
 // ------------------------------
 //    32 bit structures
 
@@ -65,19 +85,30 @@ pub struct UContext32 {
 	pub uc_sigmask: SigSet,
 	pub __fpregs_mem: FpState32,
 	pub __ssp: [u64; 4],
+	/// The full FPU state, in the format of `fxsave`.
+	pub __fxstate: FxState,
 }
 
 impl UContext32 {
 	/// Creates a context structure from the current.
-	pub fn new(process: &Process, frame: &IntFrame) -> Self {
+	///
+	/// Arguments:
+	/// - `ctx_addr` is the address at which the structure is written in userspace.
+	/// - `cr2` is the address of the fault that triggered the signal, if any.
+	pub fn new(process: &Process, frame: &IntFrame, ctx_addr: VirtAddr, cr2: usize) -> Self {
+		let (uc_sigmask, (ss_sp, ss_flags, ss_size)) = {
+			let mut signal = process.signal.lock();
+			let stack = signal.altstack_info(frame.get_stack_address());
+			(signal.take_saved_sigmask(), stack)
+		};
+		let fx = save_fpu();
 		Self {
 			uc_flags: 0, // TODO
 			uc_link: 0,
-			// TODO
 			uc_stack: Stack32 {
-				ss_sp: 0,
-				ss_flags: 0,
-				ss_size: 0,
+				ss_sp: ss_sp as _,
+				ss_flags,
+				ss_size: ss_size as _,
 			},
 			uc_mcontext: MContext32 {
 				gregs: [
@@ -93,35 +124,22 @@ impl UContext32 {
 					frame.rdx as _,
 					frame.rcx as _,
 					frame.rax as _,
-					0, // TODO trapno
-					0, // TODO err
+					frame.int as _,
+					frame.code as _,
 					frame.rip as _,
 					frame.cs as _,
 					frame.rflags as _,
 					0, // TODO uesp
 					frame.ss as _,
 				],
-				fpregs: 0,  // TODO
-				oldmask: 0, // TODO
-				cr2: 0,
-			},
-			uc_sigmask: process.signal.lock().sigmask,
-			// TODO
-			__fpregs_mem: FpState32 {
-				cw: 0,
-				sw: 0,
-				tag: 0,
-				ipoff: 0,
-				cssel: 0,
-				dataoff: 0,
-				datasel: 0,
-				_st: [FpReg32 {
-					significand: [0; 4],
-					exponent: 0,
-				}; 8],
-				status: 0,
+				fpregs: (ctx_addr.0 + offset_of!(Self, __fpregs_mem)) as _,
+				oldmask: uc_sigmask.0 as _,
+				cr2: cr2 as _,
 			},
+			uc_sigmask,
+			__fpregs_mem: FpState32::from_fxsave(&fx),
 			__ssp: [0; 4],
+			__fxstate: fx,
 		}
 	}
 
@@ -140,7 +158,7 @@ impl UContext32 {
 		frame.rsp = self.uc_mcontext.gregs[GReg32::Esp as usize] as _;
 		frame.rip = self.uc_mcontext.gregs[GReg32::Eip as usize] as _;
 		frame.rflags = self.uc_mcontext.gregs[GReg32::Efl as usize] as _;
-		// TODO restore fpstate
+		load_fpu(self.__fxstate.clone());
 		proc.signal.lock().sigmask = self.uc_sigmask;
 	}
 }
@@ -179,6 +197,37 @@ pub struct FpState32 {
 	pub status: u32,
 }
 
+impl FpState32 {
+	/// Creates the legacy representation of the FPU state `fx`, saved with `fxsave`.
+	pub fn from_fxsave(fx: &FxState) -> Self {
+		let get16 = |off: usize| u16::from_le_bytes([fx.0[off], fx.0[off + 1]]);
+		let get32 = |off: usize| u32::from_le_bytes(fx.0[off..(off + 4)].try_into().unwrap());
+		// The abridged tag only tells whether registers are empty
+		let ftw = fx.0[4];
+		let tag = (0..8)
+			.filter(|i| ftw & (1 << i) == 0)
+			.fold(0xffff0000, |tag, i| tag | (0b11 << (i * 2)));
+		Self {
+			cw: get16(0) as u32 | 0xffff0000,
+			sw: get16(2) as u32 | 0xffff0000,
+			tag,
+			ipoff: get32(8),
+			cssel: get16(12) as u32 | ((get16(6) as u32) << 16),
+			dataoff: get32(16),
+			datasel: get16(20) as u32 | 0xffff0000,
+			_st: core::array::from_fn(|i| {
+				let off = 32 + i * 16;
+				FpReg32 {
+					significand: core::array::from_fn(|j| get16(off + j * 2)),
+					exponent: get16(off + 8),
+				}
+			}),
+			// The upper half is the magic value telling that the `fxsave` state is present
+			status: get16(2) as u32,
+		}
+	}
+}
+
 /// TODO doc
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -193,12 +242,17 @@ pub struct FpReg32 {
 #[cfg(target_arch = "x86_64")]
 /// 64 bit structures.
 mod long {
+	use super::{load_fpu, save_fpu};
 	use crate::{
-		arch::x86::idt::IntFrame,
+		arch::x86::{FxState, idt::IntFrame},
+		memory::VirtAddr,
 		process::{Process, mem_space::bound_check, signal::SigSet},
 	};
-	use core::hint::unlikely;
-	use utils::{errno, errno::EResult};
+	use core::{
+		hint::unlikely,
+		mem::{offset_of, transmute},
+	};
+	use utils::{bytes::as_bytes, errno, errno::EResult};
 
 	/// General purpose registers (64 bit).
 	#[repr(usize)]
@@ -243,15 +297,24 @@ mod long {
 
 	impl UContext64 {
 		/// Creates a context structure from the current.
-		pub fn new(process: &Process, frame: &IntFrame) -> Self {
+		///
+		/// Arguments:
+		/// - `ctx_addr` is the address at which the structure is written in userspace.
+		/// - `cr2` is the address of the fault that triggered the signal, if any.
+		pub fn new(process: &Process, frame: &IntFrame, ctx_addr: VirtAddr, cr2: usize) -> Self {
+			let (uc_sigmask, (ss_sp, ss_flags, ss_size)) = {
+				let mut signal = process.signal.lock();
+				let stack = signal.altstack_info(frame.get_stack_address());
+				(signal.take_saved_sigmask(), stack)
+			};
+			let fx = save_fpu();
 			Self {
 				uc_flags: 0, // TODO
 				uc_link: 0,
-				// TODO
 				uc_stack: Stack64 {
-					ss_sp: 0,
-					ss_flags: 0,
-					ss_size: 0,
+					ss_sp: ss_sp as _,
+					ss_flags,
+					ss_size,
 				},
 				uc_mcontext: MContext64 {
 					gregs: [
@@ -274,35 +337,17 @@ mod long {
 						frame.rip,
 						frame.rflags,
 						0, // TODO csgsfs
-						0, // TODO err
-						0, // TODO trapno
-						0, // TODO oldmask
-						0, // cr2
+						frame.code,
+						frame.int,
+						uc_sigmask.0,
+						cr2 as _,
 					],
-					fpregs: 0, // TODO
+					fpregs: (ctx_addr.0 + offset_of!(Self, __fpregs_mem)) as _,
 					__reserved1: [0; 8],
 				},
-				uc_sigmask: process.signal.lock().sigmask,
-				// TODO
-				__fpregs_mem: FpState64 {
-					cwd: 0,
-					swd: 0,
-					ftw: 0,
-					fop: 0,
-					rip: 0,
-					rdp: 0,
-					mxcsr: 0,
-					mxcr_mask: 0,
-					_st: [FpReg64 {
-						significand: [0; 4],
-						exponent: 0,
-						__glibc_reserved1: [0; 3],
-					}; 8],
-					_xmm: [XmmReg64 {
-						element: [0; 4],
-					}; 16],
-					__glibc_reserved1: [0; 24],
-				},
+				uc_sigmask,
+				// The structure has the layout of `fxsave`
+				__fpregs_mem: unsafe { transmute::<[u8; 512], FpState64>(fx.0) },
 				__ssp: [0; 4],
 			}
 		}
@@ -334,7 +379,9 @@ mod long {
 			frame.rsp = rsp;
 			frame.rip = rip;
 			frame.rflags = self.uc_mcontext.gregs[GReg64::Efl as usize] as _;
-			// TODO restore fpstate
+			let mut fx = FxState([0; 512]);
+			fx.0.copy_from_slice(as_bytes(&self.__fpregs_mem));
+			load_fpu(fx);
 			proc.signal.lock().sigmask = self.uc_sigmask;
 			Ok(())
 		}
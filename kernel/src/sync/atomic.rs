@@ -71,6 +71,22 @@ impl AtomicU64 {
 		}
 	}
 
+	/// Stores a value into the atomic integer, returning the previous value.
+	#[allow(unused_variables)]
+	pub fn swap(&self, val: u64, order: atomic::Ordering) -> u64 {
+		#[cfg(target_has_atomic = "64")]
+		{
+			self.0.swap(val, order)
+		}
+		#[cfg(not(target_has_atomic = "64"))]
+		{
+			let mut guard = self.0.lock();
+			let prev = *guard;
+			*guard = val;
+			prev
+		}
+	}
+
 	/// Adds to the current value, returning the previous value.
 	#[allow(unused_variables)]
 	pub fn fetch_add(&self, val: u64, order: atomic::Ordering) -> u64 {
@@ -86,6 +102,22 @@ impl AtomicU64 {
 			prev
 		}
 	}
+
+	/// Stores the maximum of the current value and `val`, returning the previous value.
+	#[allow(unused_variables)]
+	pub fn fetch_max(&self, val: u64, order: atomic::Ordering) -> u64 {
+		#[cfg(target_has_atomic = "64")]
+		{
+			self.0.fetch_max(val, order)
+		}
+		#[cfg(not(target_has_atomic = "64"))]
+		{
+			let mut guard = self.0.lock();
+			let prev = *guard;
+			*guard = prev.max(val);
+			prev
+		}
+	}
 }
 
 impl fmt::Debug for AtomicU64 {
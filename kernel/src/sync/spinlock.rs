@@ -47,6 +47,14 @@ impl Spinlock {
 		}
 	}
 
+	/// Attempts to lock the spinlock without waiting.
+	///
+	/// If the spinlock is already locked, the function returns `false`.
+	#[inline(always)]
+	pub fn try_lock(&mut self) -> bool {
+		!self.0.swap(true, atomic::Ordering::Acquire)
+	}
+
 	/// Unlocks the spinlock.
 	#[inline(always)]
 	pub fn unlock(&mut self) {
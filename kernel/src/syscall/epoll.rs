@@ -0,0 +1,205 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The epoll system calls allow to monitor a large number of files at once.
+
+use crate::{
+	file,
+	file::{
+		File, FileType,
+		epoll::{
+			EPOLLERR, EPOLLET, EPOLLEXCLUSIVE, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLWAKEUP, EPoll,
+			EPollEvent,
+		},
+		fd::{FD_CLOEXEC, FileDescriptorTable},
+		wait_queue::PollTable,
+	},
+	memory::user::{UserPtr, UserSlice},
+	process::signal::{SigSet, with_sigmask},
+	sync::mutex::Mutex,
+	syscall::{Args, select::read_sigmask},
+	time::unit::{TimeUnit, Timespec, Timestamp},
+};
+use core::{ffi::c_int, hint::unlikely};
+use utils::{collections::vec::Vec, errno, errno::EResult, ptr::arc::Arc};
+
+/// Operation: adds a file to the interest list.
+const EPOLL_CTL_ADD: c_int = 1;
+/// Operation: removes a file from the interest list.
+const EPOLL_CTL_DEL: c_int = 2;
+/// Operation: modifies the events of a file in the interest list.
+const EPOLL_CTL_MOD: c_int = 3;
+
+/// The maximum number of events that can be returned at once.
+const EP_MAX_EVENTS: usize = i32::MAX as usize / size_of::<EPollEvent>();
+
+/// Creates an epoll instance.
+///
+/// `cloexec` tells whether the file descriptor has the `FD_CLOEXEC` flag enabled.
+fn do_epoll_create(fds: Arc<Mutex<FileDescriptorTable>>, cloexec: bool) -> EResult<usize> {
+	let file = File::open_floating(Arc::new(EPoll::default())?, file::O_RDWR)?;
+	let fd_flags = if cloexec { FD_CLOEXEC } else { 0 };
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
+
+pub fn epoll_create(
+	Args(size): Args<c_int>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// The size is ignored, but must be positive
+	if unlikely(size <= 0) {
+		return Err(errno!(EINVAL));
+	}
+	do_epoll_create(fds, false)
+}
+
+pub fn epoll_create1(
+	Args(flags): Args<c_int>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if unlikely(flags & !file::O_CLOEXEC != 0) {
+		return Err(errno!(EINVAL));
+	}
+	do_epoll_create(fds, flags & file::O_CLOEXEC != 0)
+}
+
+pub fn epoll_ctl(
+	Args((epfd, op, fd, event)): Args<(c_int, c_int, c_int, UserPtr<EPollEvent>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let (epoll_file, file) = {
+		let fds = fds.lock();
+		(
+			fds.get_fd(epfd)?.get_file().clone(),
+			fds.get_fd(fd)?.get_file().clone(),
+		)
+	};
+	// Regular files and directories are always ready, monitoring them is meaningless
+	if matches!(
+		file.stat()?.get_type(),
+		Some(FileType::Regular | FileType::Directory)
+	) {
+		return Err(errno!(EPERM));
+	}
+	let Some(epoll) = epoll_file.get_buffer::<EPoll>() else {
+		return Err(errno!(EINVAL));
+	};
+	if unlikely(epfd == fd) {
+		return Err(errno!(EINVAL));
+	}
+	let event = if op != EPOLL_CTL_DEL {
+		event.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?
+	} else {
+		EPollEvent::default()
+	};
+	if event.events & EPOLLEXCLUSIVE != 0 {
+		const ALLOWED: u32 =
+			EPOLLIN | EPOLLOUT | EPOLLERR | EPOLLHUP | EPOLLWAKEUP | EPOLLET | EPOLLEXCLUSIVE;
+		if op == EPOLL_CTL_MOD
+			|| event.events & !ALLOWED != 0
+			|| file.get_buffer::<EPoll>().is_some()
+		{
+			return Err(errno!(EINVAL));
+		}
+	}
+	match op {
+		EPOLL_CTL_ADD => epoll.add(&epoll_file, fd, &file, event)?,
+		EPOLL_CTL_DEL => epoll.remove(fd, &file)?,
+		EPOLL_CTL_MOD => epoll.modify(fd, &file, event)?,
+		_ => return Err(errno!(EINVAL)),
+	}
+	Ok(0)
+}
+
+/// Waits for events on an epoll instance.
+///
+/// Arguments:
+/// - `fds` is the process's file descriptors table.
+/// - `epfd` is the file descriptor of the epoll instance.
+/// - `events` is the buffer to write the events to.
+/// - `maxevents` is the capacity of `events`.
+/// - `timeout` is the timeout in nanoseconds. If `None`, the syscall waits indefinitely.
+/// - `sigmask` is the signal mask to apply while waiting, if any.
+fn do_epoll_wait(
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	epfd: c_int,
+	events: *mut EPollEvent,
+	maxevents: c_int,
+	timeout: Option<Timestamp>,
+	sigmask: Option<SigSet>,
+) -> EResult<usize> {
+	let maxevents: usize = maxevents.try_into().map_err(|_| errno!(EINVAL))?;
+	if unlikely(maxevents == 0 || maxevents > EP_MAX_EVENTS) {
+		return Err(errno!(EINVAL));
+	}
+	let events = UserSlice::from_user(events, maxevents)?;
+	let file = fds.lock().get_fd(epfd)?.get_file().clone();
+	let Some(epoll) = file.get_buffer::<EPoll>() else {
+		return Err(errno!(EINVAL));
+	};
+	let mut buf = Vec::new();
+	let count = with_sigmask(sigmask, || {
+		PollTable::wait(timeout, |table| epoll.collect(table, maxevents, &mut buf))
+	})?;
+	events.copy_to_user(0, &buf)?;
+	Ok(count)
+}
+
+pub fn epoll_wait(
+	Args((epfd, events, maxevents, timeout)): Args<(c_int, *mut EPollEvent, c_int, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// A negative timeout means no timeout
+	let timeout = (timeout >= 0).then(|| timeout as Timestamp * 1_000_000);
+	do_epoll_wait(fds, epfd, events, maxevents, timeout, None)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn epoll_pwait(
+	Args((epfd, events, maxevents, timeout, sigmask, sigsetsize)): Args<(
+		c_int,
+		*mut EPollEvent,
+		c_int,
+		c_int,
+		UserPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let timeout = (timeout >= 0).then(|| timeout as Timestamp * 1_000_000);
+	let sigmask = read_sigmask(sigmask, sigsetsize)?;
+	do_epoll_wait(fds, epfd, events, maxevents, timeout, sigmask)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn epoll_pwait2(
+	Args((epfd, events, maxevents, timeout, sigmask, sigsetsize)): Args<(
+		c_int,
+		*mut EPollEvent,
+		c_int,
+		UserPtr<Timespec>,
+		UserPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let timeout = timeout.copy_from_user()?.map(|t| t.to_nano());
+	let sigmask = read_sigmask(sigmask, sigsetsize)?;
+	do_epoll_wait(fds, epfd, events, maxevents, timeout, sigmask)
+}
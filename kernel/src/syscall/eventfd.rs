@@ -0,0 +1,57 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `eventfd` system calls create a file used as an event wait/notify mechanism.
+
+use crate::{
+	file,
+	file::{
+		File,
+		eventfd::EventFd,
+		fd::{FD_CLOEXEC, FileDescriptorTable},
+	},
+	sync::mutex::Mutex,
+	syscall::Args,
+};
+use core::{ffi::c_int, hint::unlikely};
+use utils::{errno, errno::EResult, ptr::arc::Arc};
+
+/// Flag: reading decrements the counter by one instead of resetting it.
+const EFD_SEMAPHORE: c_int = 1;
+
+pub fn eventfd(Args(initval): Args<u32>, fds: Arc<Mutex<FileDescriptorTable>>) -> EResult<usize> {
+	eventfd2(Args((initval, 0)), fds)
+}
+
+pub fn eventfd2(
+	Args((initval, flags)): Args<(u32, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	if unlikely(flags & !(EFD_SEMAPHORE | file::O_CLOEXEC | file::O_NONBLOCK) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let eventfd = Arc::new(EventFd::new(initval, flags & EFD_SEMAPHORE != 0))?;
+	let file = File::open_floating(eventfd, file::O_RDWR | (flags & file::O_NONBLOCK))?;
+	let fd_flags = if flags & file::O_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
@@ -26,7 +26,9 @@ use crate::{
 	process::{
 		Process,
 		exec::{ExecInfo, elf, exec},
+		ptrace,
 		scheduler::switch::init_ctx,
+		yield_current,
 	},
 };
 use core::hint::unlikely;
@@ -156,7 +158,10 @@ pub fn execve(
 		)?;
 		let proc = Process::current();
 		exec(&proc, frame, program_image)?;
+		ptrace::exec(&proc);
 	}
+	// Handle a potential stop for the tracer before starting the program
+	yield_current(3, frame);
 	// Use `init_ctx` to handle transition to compatibility mode
 	unsafe {
 		init_ctx(frame);
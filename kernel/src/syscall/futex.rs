@@ -0,0 +1,284 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `futex` system call allows userspace to wait on and wake up processes through a value in
+//! memory.
+
+use crate::{
+	arch::x86::idt::IntFrame,
+	memory::user::UserPtr,
+	process::{
+		Process, futex,
+		futex::{BITSET_MATCH_ANY, RobustList},
+		mem_space::MemSpace,
+		pid::Pid,
+	},
+	syscall::{Args, FromSyscallArg},
+	time::{
+		clock::{Clock, current_time_ns},
+		unit::{TimeUnit, Timespec, Timespec32},
+	},
+};
+use core::ffi::c_int;
+use utils::{errno, errno::EResult, ptr::arc::Arc};
+
+/// Waits until the futex's value changes.
+const FUTEX_WAIT: c_int = 0;
+/// Wakes processes waiting on the futex.
+const FUTEX_WAKE: c_int = 1;
+/// Wakes processes waiting on the futex, and moves the others to another futex.
+const FUTEX_REQUEUE: c_int = 3;
+/// Same as [`FUTEX_REQUEUE`], but checks the futex's value first.
+const FUTEX_CMP_REQUEUE: c_int = 4;
+/// Modifies a second futex, then wakes processes waiting on both futexes depending on the second
+/// futex's previous value.
+const FUTEX_WAKE_OP: c_int = 5;
+/// Same as [`FUTEX_WAIT`], with a bitset and an absolute timeout.
+const FUTEX_WAIT_BITSET: c_int = 9;
+/// Same as [`FUTEX_WAKE`], with a bitset.
+const FUTEX_WAKE_BITSET: c_int = 10;
+
+/// Flag telling the futex is private to the process's memory space.
+const FUTEX_PRIVATE_FLAG: c_int = 128;
+/// Flag telling the timeout is measured against [`Clock::Realtime`].
+const FUTEX_CLOCK_REALTIME: c_int = 256;
+
+/// `FUTEX_WAKE_OP` flag: the operand is `1 << oparg`.
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+
+/// `FUTEX_WAKE_OP` operation: `uaddr2 = oparg`.
+const FUTEX_OP_SET: u32 = 0;
+/// `FUTEX_WAKE_OP` operation: `uaddr2 += oparg`.
+const FUTEX_OP_ADD: u32 = 1;
+/// `FUTEX_WAKE_OP` operation: `uaddr2 |= oparg`.
+const FUTEX_OP_OR: u32 = 2;
+/// `FUTEX_WAKE_OP` operation: `uaddr2 &= !oparg`.
+const FUTEX_OP_ANDN: u32 = 3;
+/// `FUTEX_WAKE_OP` operation: `uaddr2 ^= oparg`.
+const FUTEX_OP_XOR: u32 = 4;
+
+/// `FUTEX_WAKE_OP` comparison: `oldval == cmparg`.
+const FUTEX_OP_CMP_EQ: u32 = 0;
+/// `FUTEX_WAKE_OP` comparison: `oldval != cmparg`.
+const FUTEX_OP_CMP_NE: u32 = 1;
+/// `FUTEX_WAKE_OP` comparison: `oldval < cmparg`.
+const FUTEX_OP_CMP_LT: u32 = 2;
+/// `FUTEX_WAKE_OP` comparison: `oldval <= cmparg`.
+const FUTEX_OP_CMP_LE: u32 = 3;
+/// `FUTEX_WAKE_OP` comparison: `oldval > cmparg`.
+const FUTEX_OP_CMP_GT: u32 = 4;
+/// `FUTEX_WAKE_OP` comparison: `oldval >= cmparg`.
+const FUTEX_OP_CMP_GE: u32 = 5;
+
+/// Sign extends the 12 bits value `val`.
+fn sign_extend12(val: u32) -> i32 {
+	((val << 20) as i32) >> 20
+}
+
+/// Atomically applies the operation encoded in `encoded_op` to the futex at `uaddr`, then
+/// returns whether the comparison encoded in `encoded_op` holds for the previous value.
+fn wake_op_apply(uaddr: UserPtr<u32>, encoded_op: u32) -> EResult<bool> {
+	let mut op = (encoded_op >> 28) & 0xf;
+	let cmp = (encoded_op >> 24) & 0xf;
+	let mut oparg = sign_extend12((encoded_op >> 12) & 0xfff);
+	let cmparg = sign_extend12(encoded_op & 0xfff);
+	if op & FUTEX_OP_OPARG_SHIFT != 0 {
+		if !(0..32).contains(&oparg) {
+			return Err(errno!(EINVAL));
+		}
+		oparg = 1 << oparg;
+		op &= !FUTEX_OP_OPARG_SHIFT;
+	}
+	let oparg = oparg as u32;
+	let mut old = uaddr.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	loop {
+		let new = match op {
+			FUTEX_OP_SET => oparg,
+			FUTEX_OP_ADD => old.wrapping_add(oparg),
+			FUTEX_OP_OR => old | oparg,
+			FUTEX_OP_ANDN => old & !oparg,
+			FUTEX_OP_XOR => old ^ oparg,
+			_ => return Err(errno!(ENOSYS)),
+		};
+		let cur = uaddr.compare_exchange(old, new)?;
+		if cur == old {
+			break;
+		}
+		old = cur;
+	}
+	let old = old as i32;
+	let res = match cmp {
+		FUTEX_OP_CMP_EQ => old == cmparg,
+		FUTEX_OP_CMP_NE => old != cmparg,
+		FUTEX_OP_CMP_LT => old < cmparg,
+		FUTEX_OP_CMP_LE => old <= cmparg,
+		FUTEX_OP_CMP_GT => old > cmparg,
+		FUTEX_OP_CMP_GE => old >= cmparg,
+		_ => return Err(errno!(ENOSYS)),
+	};
+	Ok(res)
+}
+
+/// Performs the futex operation.
+///
+/// `T` is the type of the timeout structure. For operations that do not take a timeout,
+/// `timeout` is interpreted as an integer value instead.
+fn do_futex<T: TimeUnit>(
+	uaddr: UserPtr<u32>,
+	futex_op: c_int,
+	val: u32,
+	timeout: usize,
+	uaddr2: UserPtr<u32>,
+	val3: u32,
+	mem_space: Arc<MemSpace>,
+) -> EResult<usize> {
+	let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+	let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+	let cmd = futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+	if realtime && !matches!(cmd, FUTEX_WAIT | FUTEX_WAIT_BITSET) {
+		return Err(errno!(ENOSYS));
+	}
+	let clock = if realtime {
+		Clock::Realtime
+	} else {
+		Clock::Monotonic
+	};
+	let count = val as usize;
+	match cmd {
+		FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+			let (bitset, deadline) = if cmd == FUTEX_WAIT {
+				// The timeout is relative
+				let deadline = UserPtr::<T>::from_ptr(timeout)
+					.copy_from_user()?
+					.map(|ts| (clock, current_time_ns(clock) + ts.to_nano()));
+				(BITSET_MATCH_ANY, deadline)
+			} else {
+				// The timeout is absolute
+				let deadline = UserPtr::<T>::from_ptr(timeout)
+					.copy_from_user()?
+					.map(|ts| (clock, ts.to_nano()));
+				(val3, deadline)
+			};
+			futex::wait(&mem_space, uaddr, private, val, bitset, deadline)?;
+			Ok(0)
+		}
+		FUTEX_WAKE => futex::wake(&mem_space, uaddr, private, count, BITSET_MATCH_ANY),
+		FUTEX_WAKE_BITSET => futex::wake(&mem_space, uaddr, private, count, val3),
+		FUTEX_REQUEUE => {
+			let (woken, _) =
+				futex::requeue(&mem_space, uaddr, uaddr2, private, count, timeout, None)?;
+			Ok(woken)
+		}
+		FUTEX_CMP_REQUEUE => {
+			let (woken, requeued) = futex::requeue(
+				&mem_space,
+				uaddr,
+				uaddr2,
+				private,
+				count,
+				timeout,
+				Some(val3),
+			)?;
+			Ok(woken + requeued)
+		}
+		FUTEX_WAKE_OP => {
+			let wake2 = wake_op_apply(uaddr2, val3)?;
+			let mut woken = futex::wake(&mem_space, uaddr, private, count, BITSET_MATCH_ANY)?;
+			if wake2 {
+				let count2 = timeout as u32 as usize;
+				woken += futex::wake(&mem_space, uaddr2, private, count2, BITSET_MATCH_ANY)?;
+			}
+			Ok(woken)
+		}
+		_ => Err(errno!(ENOSYS)),
+	}
+}
+
+#[allow(clippy::type_complexity)]
+pub fn futex32(
+	Args((uaddr, futex_op, val, timeout, uaddr2, val3)): Args<(
+		UserPtr<u32>,
+		c_int,
+		u32,
+		usize,
+		UserPtr<u32>,
+		u32,
+	)>,
+	mem_space: Arc<MemSpace>,
+) -> EResult<usize> {
+	do_futex::<Timespec32>(uaddr, futex_op, val, timeout, uaddr2, val3, mem_space)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn futex64(
+	Args((uaddr, futex_op, val, timeout, uaddr2, val3)): Args<(
+		UserPtr<u32>,
+		c_int,
+		u32,
+		usize,
+		UserPtr<u32>,
+		u32,
+	)>,
+	mem_space: Arc<MemSpace>,
+) -> EResult<usize> {
+	do_futex::<Timespec>(uaddr, futex_op, val, timeout, uaddr2, val3, mem_space)
+}
+
+pub fn set_robust_list(
+	Args((head, len)): Args<(usize, usize)>,
+	proc: Arc<Process>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let compat = frame.is_compat();
+	if len != futex::robust_list_head_size(compat) {
+		return Err(errno!(EINVAL));
+	}
+	*proc.robust_list.lock() = RobustList {
+		head,
+		compat,
+	};
+	Ok(0)
+}
+
+pub fn get_robust_list(
+	Args((pid, head_ptr, len_ptr)): Args<(c_int, usize, usize)>,
+	proc: Arc<Process>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let target = if pid == 0 {
+		proc
+	} else {
+		let target = Process::get_by_pid(pid as Pid).ok_or_else(|| errno!(ESRCH))?;
+		let ap = proc.fs.lock().access_profile;
+		if !ap.can_kill(&target) {
+			return Err(errno!(EPERM));
+		}
+		target
+	};
+	let head = target.robust_list.lock().head;
+	let compat = frame.is_compat();
+	let len = futex::robust_list_head_size(compat);
+	if compat {
+		UserPtr::<u32>::from_ptr(head_ptr).copy_to_user(&(head as u32))?;
+		UserPtr::<u32>::from_ptr(len_ptr).copy_to_user(&(len as u32))?;
+	} else {
+		UserPtr::<usize>::from_ptr(head_ptr).copy_to_user(&head)?;
+		UserPtr::<usize>::from_ptr(len_ptr).copy_to_user(&len)?;
+	}
+	Ok(0)
+}
@@ -130,8 +130,8 @@ pub fn sysinfo(Args(info): Args<UserPtr<Sysinfo>>) -> EResult<usize> {
 		freeram: mem_info.mem_free as _,
 		sharedram: 0, // TODO
 		bufferram: 0, // TODO
-		totalswap: 0, // TODO
-		freeswap: 0,  // TODO
+		totalswap: mem_info.swap_total as _,
+		freeswap: mem_info.swap_free as _,
 		procs: procs as _,
 		pad: 0,
 		totalhigh: 0, // TODO
@@ -49,6 +49,8 @@ pub const TCSETSW: c_ulong = 0x00005403;
 /// ioctl request: Sets the serial port settings. Making the change only when
 /// all currently written data has been transmitted.
 pub const TCSETSF: c_ulong = 0x00005404;
+/// ioctl request: Make the terminal the controlling terminal of the session.
+pub const TIOCSCTTY: c_ulong = 0x0000540e;
 /// ioctl request: Get the foreground process group ID on the terminal.
 pub const TIOCGPGRP: c_ulong = 0x0000540f;
 /// ioctl request: Set the foreground process group ID on the terminal.
@@ -59,6 +61,41 @@ pub const TIOCGWINSZ: c_ulong = 0x00005413;
 pub const TIOCSWINSZ: c_ulong = 0x00005414;
 /// ioctl request: Returns the number of bytes available on the file descriptor.
 pub const FIONREAD: c_ulong = 0x0000541b;
+/// ioctl request: Give up the controlling terminal.
+pub const TIOCNOTTY: c_ulong = 0x00005422;
+/// ioctl request: Get the ID of the session the terminal is the controlling terminal of.
+pub const TIOCGSID: c_ulong = 0x00005429;
+
+// ioctl requests: sockets
+
+/// ioctl request: add a route to the routing table.
+pub const SIOCADDRT: c_ulong = 0x0000890b;
+/// ioctl request: delete a route from the routing table.
+pub const SIOCDELRT: c_ulong = 0x0000890c;
+/// ioctl request: get the name of a network interface from its index.
+pub const SIOCGIFNAME: c_ulong = 0x00008910;
+/// ioctl request: get the list of network interface addresses.
+pub const SIOCGIFCONF: c_ulong = 0x00008912;
+/// ioctl request: get the flags of a network interface.
+pub const SIOCGIFFLAGS: c_ulong = 0x00008913;
+/// ioctl request: set the flags of a network interface.
+pub const SIOCSIFFLAGS: c_ulong = 0x00008914;
+/// ioctl request: get the address of a network interface.
+pub const SIOCGIFADDR: c_ulong = 0x00008915;
+/// ioctl request: set the address of a network interface.
+pub const SIOCSIFADDR: c_ulong = 0x00008916;
+/// ioctl request: get the broadcast address of a network interface.
+pub const SIOCGIFBRDADDR: c_ulong = 0x00008919;
+/// ioctl request: get the network mask of a network interface.
+pub const SIOCGIFNETMASK: c_ulong = 0x0000891b;
+/// ioctl request: set the network mask of a network interface.
+pub const SIOCSIFNETMASK: c_ulong = 0x0000891c;
+/// ioctl request: get the MTU of a network interface.
+pub const SIOCGIFMTU: c_ulong = 0x00008921;
+/// ioctl request: get the hardware address of a network interface.
+pub const SIOCGIFHWADDR: c_ulong = 0x00008927;
+/// ioctl request: get the index of a network interface.
+pub const SIOCGIFINDEX: c_ulong = 0x00008933;
 
 /// IO directions for ioctl requests.
 #[derive(Eq, PartialEq)]
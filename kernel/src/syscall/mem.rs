@@ -21,17 +21,38 @@
 use crate::{
 	file::{FileType, fd::FileDescriptorTable, perm::AccessProfile},
 	memory,
-	memory::VirtAddr,
-	process::mem_space::{MAP_ANONYMOUS, MAP_SHARED, MemSpace, PROT_WRITE},
+	memory::{VirtAddr, user::UserSlice},
+	process::{
+		Process,
+		mem_space::{MAP_ANONYMOUS, MAP_SHARED, MemSpace, PROT_WRITE, bound_check},
+	},
 	sync::mutex::Mutex,
 	syscall::Args,
 };
 use core::{
-	ffi::{c_int, c_void},
+	cmp::min,
+	ffi::{c_int, c_uint, c_void},
 	hint::unlikely,
 	num::NonZeroUsize,
 };
-use utils::{errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc};
+use utils::{errno, errno::EResult, limits::PAGE_SIZE, ptr::arc::Arc, vec};
+
+/// The mapping may be moved if it cannot be resized in place.
+const MREMAP_MAYMOVE: c_int = 0x1;
+/// The mapping is moved to the given address.
+const MREMAP_FIXED: c_int = 0x2;
+/// The old mapping is not unmapped after being moved.
+const MREMAP_DONTUNMAP: c_int = 0x4;
+
+/// Pages are locked as they are accessed instead of right away.
+const MLOCK_ONFAULT: c_uint = 0x1;
+
+/// Locks the current mappings.
+const MCL_CURRENT: c_int = 0x1;
+/// Locks the mappings created afterward.
+const MCL_FUTURE: c_int = 0x2;
+/// Pages are locked as they are accessed instead of right away.
+const MCL_ONFAULT: c_int = 0x4;
 
 /// Performs the `mmap` system call.
 #[allow(clippy::too_many_arguments)]
@@ -153,3 +174,187 @@ pub fn munmap(
 	mem_space.unmap(addr, NonZeroUsize::new(pages).unwrap())?;
 	Ok(0)
 }
+
+pub fn mremap(
+	Args((old_address, old_size, new_size, flags, new_address)): Args<(
+		VirtAddr,
+		usize,
+		usize,
+		c_int,
+		VirtAddr,
+	)>,
+	proc: Arc<Process>,
+	mem_space: Arc<MemSpace>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	// TODO support MREMAP_DONTUNMAP
+	if unlikely(flags & !(MREMAP_MAYMOVE | MREMAP_FIXED | MREMAP_DONTUNMAP) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(flags & MREMAP_DONTUNMAP != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(flags & MREMAP_FIXED != 0 && flags & MREMAP_MAYMOVE == 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(!old_address.is_aligned_to(PAGE_SIZE)) {
+		return Err(errno!(EINVAL));
+	}
+	// TODO support duplicating shared mappings when `old_size` is zero
+	let (Some(old_size), Some(new_size)) = (
+		NonZeroUsize::new(old_size.div_ceil(PAGE_SIZE)),
+		NonZeroUsize::new(new_size.div_ceil(PAGE_SIZE)),
+	) else {
+		return Err(errno!(EINVAL));
+	};
+	let new_address = if flags & MREMAP_FIXED != 0 {
+		if unlikely(
+			!new_address.is_aligned_to(PAGE_SIZE)
+				|| !bound_check(new_address.0, new_size.get() * PAGE_SIZE),
+		) {
+			return Err(errno!(EINVAL));
+		}
+		Some(new_address)
+	} else {
+		None
+	};
+	// The limit only applies to locked mappings. If the process may not lock memory, growing a
+	// locked mapping fails
+	let limit = || memlock_limit(&proc, &ap).unwrap_or(0);
+	let addr = mem_space.remap(
+		old_address,
+		old_size,
+		new_size,
+		flags & MREMAP_MAYMOVE != 0,
+		new_address,
+		limit,
+	)?;
+	Ok(addr.0)
+}
+
+/// Returns the maximum number of pages the process `proc` may lock in memory.
+///
+/// If the process is not allowed to lock memory, the function returns [`errno::EPERM`].
+fn memlock_limit(proc: &Arc<Process>, ap: &AccessProfile) -> EResult<usize> {
+	if ap.is_privileged() {
+		return Ok(usize::MAX);
+	}
+	let limit = Process::thread_leader(proc).memlock_limit.lock().rlim_cur;
+	if unlikely(limit == 0) {
+		return Err(errno!(EPERM));
+	}
+	Ok(usize::try_from(limit / PAGE_SIZE as u64).unwrap_or(usize::MAX))
+}
+
+/// Returns the range of pages covering the `len` bytes beginning at `addr`, as its first page and
+/// number of pages.
+fn page_range(addr: VirtAddr, len: usize) -> EResult<(VirtAddr, usize)> {
+	let begin = addr.down_align_to(PAGE_SIZE);
+	let end = addr
+		.0
+		.checked_add(len)
+		.and_then(|end| end.checked_next_multiple_of(PAGE_SIZE))
+		.ok_or_else(|| errno!(EINVAL))?;
+	Ok((begin, (end - begin.0) / PAGE_SIZE))
+}
+
+fn do_mlock(
+	addr: VirtAddr,
+	len: usize,
+	on_fault: bool,
+	proc: Arc<Process>,
+	mem_space: Arc<MemSpace>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	let (begin, pages) = page_range(addr, len)?;
+	if pages == 0 {
+		return Ok(0);
+	}
+	let limit = memlock_limit(&proc, &ap)?;
+	mem_space.lock(begin, pages, limit, on_fault)?;
+	Ok(0)
+}
+
+pub fn mlock(
+	Args((addr, len)): Args<(VirtAddr, usize)>,
+	proc: Arc<Process>,
+	mem_space: Arc<MemSpace>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	do_mlock(addr, len, false, proc, mem_space, ap)
+}
+
+pub fn mlock2(
+	Args((addr, len, flags)): Args<(VirtAddr, usize, c_uint)>,
+	proc: Arc<Process>,
+	mem_space: Arc<MemSpace>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	if unlikely(flags & !MLOCK_ONFAULT != 0) {
+		return Err(errno!(EINVAL));
+	}
+	do_mlock(addr, len, flags & MLOCK_ONFAULT != 0, proc, mem_space, ap)
+}
+
+pub fn munlock(
+	Args((addr, len)): Args<(VirtAddr, usize)>,
+	mem_space: Arc<MemSpace>,
+) -> EResult<usize> {
+	let (begin, pages) = page_range(addr, len)?;
+	if pages == 0 {
+		return Ok(0);
+	}
+	mem_space.unlock(begin, pages)?;
+	Ok(0)
+}
+
+pub fn mlockall(
+	Args(flags): Args<c_int>,
+	proc: Arc<Process>,
+	mem_space: Arc<MemSpace>,
+	ap: AccessProfile,
+) -> EResult<usize> {
+	if unlikely(
+		flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0
+			|| flags & (MCL_CURRENT | MCL_FUTURE) == 0,
+	) {
+		return Err(errno!(EINVAL));
+	}
+	let limit = memlock_limit(&proc, &ap)?;
+	mem_space.lock_all(
+		flags & MCL_CURRENT != 0,
+		flags & MCL_FUTURE != 0,
+		limit,
+		flags & MCL_ONFAULT != 0,
+	)?;
+	Ok(0)
+}
+
+pub fn munlockall(mem_space: Arc<MemSpace>) -> EResult<usize> {
+	mem_space.unlock_all()?;
+	Ok(0)
+}
+
+pub fn mincore(
+	Args((addr, length, vec)): Args<(VirtAddr, usize, *mut u8)>,
+	mem_space: Arc<MemSpace>,
+) -> EResult<usize> {
+	if unlikely(!addr.is_aligned_to(PAGE_SIZE)) {
+		return Err(errno!(EINVAL));
+	}
+	let pages = length.div_ceil(PAGE_SIZE);
+	if unlikely(!bound_check(addr.0, pages * PAGE_SIZE)) {
+		return Err(errno!(ENOMEM));
+	}
+	let vec = UserSlice::from_user(vec, pages)?;
+	// Process the range in chunks to bound the size of the buffer
+	let mut buf = vec![0u8; min(pages, PAGE_SIZE)]?;
+	let mut off = 0;
+	while off < pages {
+		let len = min(pages - off, buf.len());
+		mem_space.mincore(addr + off * PAGE_SIZE, &mut buf[..len])?;
+		vec.copy_to_user(off, &buf[..len])?;
+		off += len;
+	}
+	Ok(0)
+}
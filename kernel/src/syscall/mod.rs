@@ -22,10 +22,13 @@
 //! command: `man 2 <syscall>`
 
 mod dirent;
+mod epoll;
+mod eventfd;
 mod execve;
 mod fcntl;
 mod fd;
 mod fs;
+mod futex;
 mod getrandom;
 mod host;
 pub mod ioctl;
@@ -34,10 +37,13 @@ mod module;
 mod mount;
 mod pipe;
 mod process;
+mod ptrace;
+mod sched;
 pub mod select;
 mod signal;
 mod socket;
 mod stat;
+mod swap;
 mod sync;
 mod time;
 mod user;
@@ -48,10 +54,18 @@ pub mod wait;
 use crate::{
 	arch::x86::idt::IntFrame,
 	file::{Mode, fd::FileDescriptorTable, perm::AccessProfile, vfs::ResolutionSettings},
-	process::{Process, mem_space::MemSpace, signal::Signal, yield_current},
+	process::{
+		Process, enter_kernel,
+		mem_space::MemSpace,
+		ptrace::{syscall_enter, syscall_exit},
+		signal::Signal,
+		yield_current,
+	},
 	sync::mutex::Mutex,
 	syscall::{
 		dirent::{getdents, getdents64},
+		epoll::{epoll_create, epoll_create1, epoll_ctl, epoll_pwait, epoll_pwait2, epoll_wait},
+		eventfd::{eventfd, eventfd2},
 		execve::execve,
 		fcntl::{fcntl, fcntl64},
 		fd::{
@@ -64,35 +78,50 @@ use crate::{
 			readlink, rename, renameat2, rmdir, symlink, symlinkat, truncate, umask, unlink,
 			unlinkat, utimensat,
 		},
+		futex::{futex32, futex64, get_robust_list, set_robust_list},
 		getrandom::getrandom,
 		host::{reboot, sethostname, sysinfo, uname},
 		ioctl::ioctl,
-		mem::{brk, madvise, mmap, mmap2, mprotect, munmap},
+		mem::{
+			brk, madvise, mincore, mlock, mlock2, mlockall, mmap, mmap2, mprotect, mremap,
+			munlock, munlockall, munmap,
+		},
 		module::{delete_module, finit_module, init_module},
 		mount::{mount, umount, umount2},
 		pipe::{pipe, pipe2},
 		process::{
-			_exit, arch_prctl, clone, compat_clone, exit_group, fork, getpgid, getpid, getppid,
-			getrusage, gettid, prlimit64, sched_yield, set_thread_area, set_tid_address, setpgid,
-			vfork,
+			_exit, arch_prctl, clone, compat_clone, exit_group, fork, getpgid, getpgrp, getpid,
+			getppid, getrusage, getsid, gettid, prlimit64, set_thread_area, set_tid_address,
+			setpgid, setsid, vfork,
+		},
+		ptrace::ptrace,
+		sched::{
+			getpriority, nice, sched_get_priority_max, sched_get_priority_min, sched_getparam,
+			sched_getscheduler, sched_rr_get_interval32, sched_rr_get_interval64, sched_setparam,
+			sched_setscheduler, sched_yield, setpriority,
 		},
-		select::{_newselect, poll, pselect6, select},
+		select::{_newselect, poll, ppoll, ppoll_time64, pselect6, pselect6_time64, select},
 		signal::{
-			compat_rt_sigaction, kill, rt_sigaction, rt_sigprocmask, rt_sigreturn, signal,
-			sigreturn, tkill,
+			compat_rt_sigaction, kill, pause, rt_sigaction, rt_sigpending, rt_sigprocmask,
+			rt_sigqueueinfo, rt_sigreturn, rt_sigsuspend, rt_sigtimedwait, rt_sigtimedwait_time64,
+			rt_tgsigqueueinfo, sigaltstack, signal, signalfd, signalfd4, sigpending, sigreturn,
+			sigsuspend, tgkill, tkill,
 		},
 		socket::{
-			bind, connect, getsockname, getsockopt, sendto, setsockopt, shutdown, socket,
-			socketpair,
+			accept, accept4, bind, connect, getpeername, getsockname, getsockopt, listen,
+			recvfrom, recvmsg, sendmsg, sendto, setsockopt, shutdown, socket, socketpair,
 		},
 		stat::{
 			fstat, fstat64, fstatfs, fstatfs64, lstat, lstat64, stat, stat64, statfs, statfs64,
 			statx,
 		},
+		swap::{swapoff, swapon},
 		sync::{fdatasync, fsync, msync, sync, syncfs},
 		time::{
-			clock_gettime, clock_gettime64, nanosleep32, nanosleep64, time32, time64,
-			timer_create, timer_delete, timer_settime,
+			alarm, clock_gettime, clock_gettime64, getitimer32, getitimer64, nanosleep32,
+			nanosleep64, setitimer32, setitimer64, time32, time64, timer_create, timer_delete,
+			timer_settime, timerfd_create, timerfd_gettime, timerfd_gettime64, timerfd_settime,
+			timerfd_settime64,
 		},
 		user::{
 			getegid, geteuid, getgid, getresgid, getresuid, getuid, setgid, setregid, setresgid,
@@ -362,15 +391,15 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x017 => syscall!(setuid, frame),
 		0x018 => syscall!(getuid, frame),
 		// TODO 0x019 => syscall!(stime, frame),
-		// TODO 0x01a => syscall!(ptrace, frame),
-		// TODO 0x01b => syscall!(alarm, frame),
+		0x01a => syscall!(ptrace, frame),
+		0x01b => syscall!(alarm, frame),
 		// TODO 0x01c => syscall!(oldfstat, frame),
-		// TODO 0x01d => syscall!(pause, frame),
+		0x01d => syscall!(pause, frame),
 		// TODO 0x01e => syscall!(utime, frame),
 		// 0x01f: unimplemented (stty),
 		// 0x020: unimplemented_syscall (gtty)
 		0x021 => syscall!(access, frame),
-		// TODO 0x022 => syscall!(nice, frame),
+		0x022 => syscall!(nice, frame),
 		// 0x023: unimplemented (ftime),
 		0x024 => syscall!(sync, frame),
 		0x025 => syscall!(kill, frame),
@@ -401,15 +430,15 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x03e => syscall!(ustat, frame),
 		0x03f => syscall!(dup2, frame),
 		0x040 => syscall!(getppid, frame),
-		// TODO 0x041 => syscall!(getpgrp, frame),
-		// TODO 0x042 => syscall!(setsid, frame),
+		0x041 => syscall!(getpgrp, frame),
+		0x042 => syscall!(setsid, frame),
 		// TODO 0x043 => syscall!(sigaction, frame),
 		// TODO 0x044 => syscall!(sgetmask, frame),
 		// TODO 0x045 => syscall!(ssetmask, frame),
 		0x046 => syscall!(setreuid, frame),
 		0x047 => syscall!(setregid, frame),
-		// TODO 0x048 => syscall!(sigsuspend, frame),
-		// TODO 0x049 => syscall!(sigpending, frame),
+		0x048 => syscall!(sigsuspend, frame),
+		0x049 => syscall!(sigpending, frame),
 		0x04a => syscall!(sethostname, frame),
 		// TODO 0x04b => syscall!(setrlimit, frame),
 		// TODO 0x04c => syscall!(getrlimit, frame),
@@ -423,7 +452,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x054 => syscall!(oldlstat, frame),
 		0x055 => syscall!(readlink, frame),
 		// TODO 0x056 => syscall!(uselib, frame),
-		// TODO 0x057 => syscall!(swapon, frame),
+		0x057 => syscall!(swapon, frame),
 		0x058 => syscall!(reboot, frame),
 		// TODO 0x059 => syscall!(readdir, frame),
 		0x05a => syscall!(mmap, frame),
@@ -432,16 +461,16 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x05d => syscall!(ftruncate, frame),
 		0x05e => syscall!(fchmod, frame),
 		// TODO 0x05f => syscall!(fchown, frame),
-		// TODO 0x060 => syscall!(getpriority, frame),
-		// TODO 0x061 => syscall!(setpriority, frame),
+		0x060 => syscall!(getpriority, frame),
+		0x061 => syscall!(setpriority, frame),
 		// 0x062: unimplemented (profil),
 		0x063 => syscall!(statfs, frame),
 		0x064 => syscall!(fstatfs, frame),
 		// TODO 0x065 => syscall!(ioperm, frame),
 		// TODO 0x066 => syscall!(socketcall, frame),
 		// TODO 0x067 => syscall!(syslog, frame),
-		// TODO 0x068 => syscall!(setitimer, frame),
-		// TODO 0x069 => syscall!(getitimer, frame),
+		0x068 => syscall!(setitimer32, frame),
+		0x069 => syscall!(getitimer32, frame),
 		0x06a => syscall!(stat, frame),
 		0x06b => syscall!(lstat, frame),
 		0x06c => syscall!(fstat, frame),
@@ -451,7 +480,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x070 => syscall!(idle, frame),
 		// TODO 0x071 => syscall!(vm86old, frame),
 		0x072 => syscall!(wait4, frame),
-		// TODO 0x073 => syscall!(swapoff, frame),
+		0x073 => syscall!(swapoff, frame),
 		0x074 => syscall!(sysinfo, frame),
 		// TODO 0x075 => syscall!(ipc, frame),
 		0x076 => syscall!(fsync, frame),
@@ -481,23 +510,23 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x090 => syscall!(msync, frame),
 		0x091 => syscall!(readv, frame),
 		0x092 => syscall!(writev, frame),
-		// TODO 0x093 => syscall!(getsid, frame),
+		0x093 => syscall!(getsid, frame),
 		0x094 => syscall!(fdatasync, frame),
 		// TODO 0x095 => syscall!(_sysctl, frame),
-		// TODO 0x096 => syscall!(mlock, frame),
-		// TODO 0x097 => syscall!(munlock, frame),
-		// TODO 0x098 => syscall!(mlockall, frame),
-		// TODO 0x099 => syscall!(munlockall, frame),
-		// TODO 0x09a => syscall!(sched_setparam, frame),
-		// TODO 0x09b => syscall!(sched_getparam, frame),
-		// TODO 0x09c => syscall!(sched_setscheduler, frame),
-		// TODO 0x09d => syscall!(sched_getscheduler, frame),
+		0x096 => syscall!(mlock, frame),
+		0x097 => syscall!(munlock, frame),
+		0x098 => syscall!(mlockall, frame),
+		0x099 => syscall!(munlockall, frame),
+		0x09a => syscall!(sched_setparam, frame),
+		0x09b => syscall!(sched_getparam, frame),
+		0x09c => syscall!(sched_setscheduler, frame),
+		0x09d => syscall!(sched_getscheduler, frame),
 		0x09e => syscall!(sched_yield, frame),
-		// TODO 0x09f => syscall!(sched_get_priority_max, frame),
-		// TODO 0x0a0 => syscall!(sched_get_priority_min, frame),
-		// TODO 0x0a1 => syscall!(sched_rr_get_interval, frame),
+		0x09f => syscall!(sched_get_priority_max, frame),
+		0x0a0 => syscall!(sched_get_priority_min, frame),
+		0x0a1 => syscall!(sched_rr_get_interval32, frame),
 		0x0a2 => syscall!(nanosleep32, frame),
-		// TODO 0x0a3 => syscall!(mremap, frame),
+		0x0a3 => syscall!(mremap, frame),
 		0x0a4 => syscall!(setresuid, frame),
 		0x0a5 => syscall!(getresuid, frame),
 		// TODO 0x0a6 => syscall!(vm86, frame),
@@ -510,17 +539,17 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x0ad => syscall!(sigreturn, frame),
 		0x0ae => syscall!(compat_rt_sigaction, frame),
 		0x0af => syscall!(rt_sigprocmask, frame),
-		// TODO 0x0b0 => syscall!(rt_sigpending, frame),
-		// TODO 0x0b1 => syscall!(rt_sigtimedwait, frame),
-		// TODO 0x0b2 => syscall!(rt_sigqueueinfo, frame),
-		// TODO 0x0b3 => syscall!(rt_sigsuspend, frame),
+		0x0b0 => syscall!(rt_sigpending, frame),
+		0x0b1 => syscall!(rt_sigtimedwait, frame),
+		0x0b2 => syscall!(rt_sigqueueinfo, frame),
+		0x0b3 => syscall!(rt_sigsuspend, frame),
 		// TODO 0x0b4 => syscall!(pread64, frame),
 		// TODO 0x0b5 => syscall!(pwrite64, frame),
 		0x0b6 => syscall!(chown, frame),
 		0x0b7 => syscall!(getcwd, frame),
 		// TODO 0x0b8 => syscall!(capget, frame),
 		// TODO 0x0b9 => syscall!(capset, frame),
-		// TODO 0x0ba => syscall!(sigaltstack, frame),
+		0x0ba => syscall!(sigaltstack, frame),
 		// TODO 0x0bb => syscall!(sendfile, frame),
 		// 0x0bc: unimplemented (getpmsg),
 		// 0x0bd: unimplemented (putpmsg),
@@ -552,7 +581,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0d7 => syscall!(setfsuid32, frame),
 		// TODO 0x0d8 => syscall!(setfsgid32, frame),
 		// TODO 0x0d9 => syscall!(pivot_root, frame),
-		// TODO 0x0da => syscall!(mincore, frame),
+		0x0da => syscall!(mincore, frame),
 		0x0db => syscall!(madvise, frame),
 		0x0dc => syscall!(getdents64, frame),
 		0x0dd => syscall!(fcntl64, frame),
@@ -572,7 +601,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0ed => syscall!(fremovexattr, frame),
 		0x0ee => syscall!(tkill, frame),
 		// TODO 0x0ef => syscall!(sendfile64, frame),
-		// TODO 0x0f0 => syscall!(futex, frame),
+		0x0f0 => syscall!(futex32, frame),
 		// TODO 0x0f1 => syscall!(sched_setaffinity, frame),
 		// TODO 0x0f2 => syscall!(sched_getaffinity, frame),
 		0x0f3 => syscall!(set_thread_area, frame),
@@ -585,9 +614,9 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0fa => syscall!(fadvise64, frame),
 		0x0fc => syscall!(exit_group, frame),
 		// TODO 0x0fd => syscall!(lookup_dcookie, frame),
-		// TODO 0x0fe => syscall!(epoll_create, frame),
-		// TODO 0x0ff => syscall!(epoll_ctl, frame),
-		// TODO 0x100 => syscall!(epoll_wait, frame),
+		0x0fe => syscall!(epoll_create, frame),
+		0x0ff => syscall!(epoll_ctl, frame),
+		0x100 => syscall!(epoll_wait, frame),
 		// TODO 0x101 => syscall!(remap_file_pages, frame),
 		0x102 => syscall!(set_tid_address, frame),
 		0x103 => syscall!(timer_create, frame),
@@ -601,7 +630,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x10b => syscall!(clock_nanosleep, frame),
 		0x10c => syscall!(statfs64, frame),
 		0x10d => syscall!(fstatfs64, frame),
-		// TODO 0x10e => syscall!(tgkill, frame),
+		0x10e => syscall!(tgkill, frame),
 		// TODO 0x10f => syscall!(utimes, frame),
 		0x110 => syscall!(fadvise64_64, frame),
 		// 0x111: unimplemented (vserver),
@@ -639,33 +668,33 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x132 => syscall!(fchmodat, frame),
 		0x133 => syscall!(faccessat, frame),
 		0x134 => syscall!(pselect6, frame),
-		// TODO 0x135 => syscall!(ppoll, frame),
+		0x135 => syscall!(ppoll, frame),
 		// TODO 0x136 => syscall!(unshare, frame),
-		// TODO 0x137 => syscall!(set_robust_list, frame),
-		// TODO 0x138 => syscall!(get_robust_list, frame),
+		0x137 => syscall!(set_robust_list, frame),
+		0x138 => syscall!(get_robust_list, frame),
 		// TODO 0x139 => syscall!(splice, frame),
 		// TODO 0x13a => syscall!(sync_file_range, frame),
 		// TODO 0x13b => syscall!(tee, frame),
 		// TODO 0x13c => syscall!(vmsplice, frame),
 		// TODO 0x13d => syscall!(move_pages, frame),
 		// TODO 0x13e => syscall!(getcpu, frame),
-		// TODO 0x13f => syscall!(epoll_pwait, frame),
+		0x13f => syscall!(epoll_pwait, frame),
 		0x140 => syscall!(utimensat, frame),
-		// TODO 0x141 => syscall!(signalfd, frame),
-		// TODO 0x142 => syscall!(timerfd_create, frame),
-		// TODO 0x143 => syscall!(eventfd, frame),
+		0x141 => syscall!(signalfd, frame),
+		0x142 => syscall!(timerfd_create, frame),
+		0x143 => syscall!(eventfd, frame),
 		// TODO 0x144 => syscall!(fallocate, frame),
-		// TODO 0x145 => syscall!(timerfd_settime, frame),
-		// TODO 0x146 => syscall!(timerfd_gettime, frame),
-		// TODO 0x147 => syscall!(signalfd4, frame),
-		// TODO 0x148 => syscall!(eventfd2, frame),
-		// TODO 0x149 => syscall!(epoll_create1, frame),
+		0x145 => syscall!(timerfd_settime, frame),
+		0x146 => syscall!(timerfd_gettime, frame),
+		0x147 => syscall!(signalfd4, frame),
+		0x148 => syscall!(eventfd2, frame),
+		0x149 => syscall!(epoll_create1, frame),
 		// TODO 0x14a => syscall!(dup3, frame),
 		0x14b => syscall!(pipe2, frame),
 		// TODO 0x14c => syscall!(inotify_init1, frame),
 		0x14d => syscall!(preadv, frame),
 		0x14e => syscall!(pwritev, frame),
-		// TODO 0x14f => syscall!(rt_tgsigqueueinfo, frame),
+		0x14f => syscall!(rt_tgsigqueueinfo, frame),
 		// TODO 0x150 => syscall!(perf_event_open, frame),
 		// TODO 0x151 => syscall!(recvmmsg, frame),
 		// TODO 0x152 => syscall!(fanotify_init, frame),
@@ -693,20 +722,20 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x168 => syscall!(socketpair, frame),
 		0x169 => syscall!(bind, frame),
 		0x16a => syscall!(connect, frame),
-		// TODO 0x16b => syscall!(listen, frame),
-		// TODO 0x16c => syscall!(accept4, frame),
+		0x16b => syscall!(listen, frame),
+		0x16c => syscall!(accept4, frame),
 		0x16d => syscall!(getsockopt, frame),
 		0x16e => syscall!(setsockopt, frame),
 		0x16f => syscall!(getsockname, frame),
-		// TODO 0x170 => syscall!(getpeername, frame),
+		0x170 => syscall!(getpeername, frame),
 		0x171 => syscall!(sendto, frame),
-		// TODO 0x172 => syscall!(sendmsg, frame),
-		// TODO 0x173 => syscall!(recvfrom, frame),
-		// TODO 0x174 => syscall!(recvmsg, frame),
+		0x172 => syscall!(sendmsg, frame),
+		0x173 => syscall!(recvfrom, frame),
+		0x174 => syscall!(recvmsg, frame),
 		0x175 => syscall!(shutdown, frame),
 		// TODO 0x176 => syscall!(userfaultfd, frame),
 		// TODO 0x177 => syscall!(membarrier, frame),
-		// TODO 0x178 => syscall!(mlock2, frame),
+		0x178 => syscall!(mlock2, frame),
 		// TODO 0x179 => syscall!(copy_file_range, frame),
 		0x17a => syscall!(preadv2, frame),
 		0x17b => syscall!(pwritev2, frame),
@@ -734,19 +763,19 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x197 => syscall!(clock_nanosleep_time64, frame),
 		// TODO 0x198 => syscall!(timer_gettime64, frame),
 		// TODO 0x199 => syscall!(timer_settime64, frame),
-		// TODO 0x19a => syscall!(timerfd_gettime64, frame),
-		// TODO 0x19b => syscall!(timerfd_settime64, frame),
+		0x19a => syscall!(timerfd_gettime64, frame),
+		0x19b => syscall!(timerfd_settime64, frame),
 		// TODO 0x19c => syscall!(utimensat_time64, frame),
-		// TODO 0x19d => syscall!(pselect6_time64, frame),
-		// TODO 0x19e => syscall!(ppoll_time64, frame),
+		0x19d => syscall!(pselect6_time64, frame),
+		0x19e => syscall!(ppoll_time64, frame),
 		// TODO 0x1a0 => syscall!(io_pgetevents_time64, frame),
 		// TODO 0x1a1 => syscall!(recvmmsg_time64, frame),
 		// TODO 0x1a2 => syscall!(mq_timedsend_time64, frame),
 		// TODO 0x1a3 => syscall!(mq_timedreceive_time64, frame),
 		// TODO 0x1a4 => syscall!(semtimedop_time64, frame),
-		// TODO 0x1a5 => syscall!(rt_sigtimedwait_time64, frame),
-		// TODO 0x1a6 => syscall!(futex_time64, frame),
-		// TODO 0x1a7 => syscall!(sched_rr_get_interval_time64, frame),
+		0x1a5 => syscall!(rt_sigtimedwait_time64, frame),
+		0x1a6 => syscall!(futex64, frame),
+		0x1a7 => syscall!(sched_rr_get_interval64, frame),
 		// TODO 0x1a8 => syscall!(pidfd_send_signal, frame),
 		// TODO 0x1a9 => syscall!(io_uring_setup, frame),
 		// TODO 0x1aa => syscall!(io_uring_enter, frame),
@@ -764,7 +793,7 @@ fn do_syscall32(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x1b6 => syscall!(pidfd_getfd, frame),
 		0x1b7 => syscall!(faccessat2, frame),
 		// TODO 0x1b8 => syscall!(process_madvise, frame),
-		// TODO 0x1b9 => syscall!(epoll_pwait2, frame),
+		0x1b9 => syscall!(epoll_pwait2, frame),
 		// TODO 0x1ba => syscall!(mount_setattr, frame),
 		// TODO 0x1bb => syscall!(quotactl_fd, frame),
 		// TODO 0x1bc => syscall!(landlock_create_ruleset, frame),
@@ -807,34 +836,34 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x016 => syscall!(pipe, frame),
 		0x017 => syscall!(select, frame),
 		0x018 => syscall!(sched_yield, frame),
-		// TODO 0x019 => syscall!(mremap, frame),
+		0x019 => syscall!(mremap, frame),
 		0x01a => syscall!(msync, frame),
-		// TODO 0x01b => syscall!(mincore, frame),
+		0x01b => syscall!(mincore, frame),
 		0x01c => syscall!(madvise, frame),
 		// TODO 0x01d => syscall!(shmget, frame),
 		// TODO 0x01e => syscall!(shmat, frame),
 		// TODO 0x01f => syscall!(shmctl, frame),
 		0x020 => syscall!(dup, frame),
 		0x021 => syscall!(dup2, frame),
-		// TODO 0x022 => syscall!(pause, frame),
+		0x022 => syscall!(pause, frame),
 		0x023 => syscall!(nanosleep64, frame),
-		// TODO 0x024 => syscall!(getitimer, frame),
-		// TODO 0x025 => syscall!(alarm, frame),
-		// TODO 0x026 => syscall!(setitimer, frame),
+		0x024 => syscall!(getitimer64, frame),
+		0x025 => syscall!(alarm, frame),
+		0x026 => syscall!(setitimer64, frame),
 		0x027 => syscall!(getpid, frame),
 		// TODO 0x028 => syscall!(sendfile, frame),
 		0x029 => syscall!(socket, frame),
 		0x02a => syscall!(connect, frame),
-		// TODO 0x02b => syscall!(accept, frame),
+		0x02b => syscall!(accept, frame),
 		0x02c => syscall!(sendto, frame),
-		// TODO 0x02d => syscall!(recvfrom, frame),
-		// TODO 0x02e => syscall!(sendmsg, frame),
-		// TODO 0x02f => syscall!(recvmsg, frame),
+		0x02d => syscall!(recvfrom, frame),
+		0x02e => syscall!(sendmsg, frame),
+		0x02f => syscall!(recvmsg, frame),
 		0x030 => syscall!(shutdown, frame),
 		0x031 => syscall!(bind, frame),
-		// TODO 0x032 => syscall!(listen, frame),
+		0x032 => syscall!(listen, frame),
 		0x033 => syscall!(getsockname, frame),
-		// TODO 0x034 => syscall!(getpeername, frame),
+		0x034 => syscall!(getpeername, frame),
 		0x035 => syscall!(socketpair, frame),
 		0x036 => syscall!(setsockopt, frame),
 		0x037 => syscall!(getsockopt, frame),
@@ -883,7 +912,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x062 => syscall!(getrusage, frame),
 		0x063 => syscall!(sysinfo, frame),
 		// TODO 0x064 => syscall!(times, frame),
-		// TODO 0x065 => syscall!(ptrace, frame),
+		0x065 => syscall!(ptrace, frame),
 		0x066 => syscall!(getuid, frame),
 		// TODO 0x067 => syscall!(syslog, frame),
 		0x068 => syscall!(getgid, frame),
@@ -893,8 +922,8 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x06c => syscall!(getegid, frame),
 		0x06d => syscall!(setpgid, frame),
 		0x06e => syscall!(getppid, frame),
-		// TODO 0x06f => syscall!(getpgrp, frame),
-		// TODO 0x070 => syscall!(setsid, frame),
+		0x06f => syscall!(getpgrp, frame),
+		0x070 => syscall!(setsid, frame),
 		0x071 => syscall!(setreuid, frame),
 		0x072 => syscall!(setregid, frame),
 		// TODO 0x073 => syscall!(getgroups, frame),
@@ -906,14 +935,14 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x079 => syscall!(getpgid, frame),
 		// TODO 0x07a => syscall!(setfsuid, frame),
 		// TODO 0x07b => syscall!(setfsgid, frame),
-		// TODO 0x07c => syscall!(getsid, frame),
+		0x07c => syscall!(getsid, frame),
 		// TODO 0x07d => syscall!(capget, frame),
 		// TODO 0x07e => syscall!(capset, frame),
-		// TODO 0x07f => syscall!(rt_sigpending, frame),
-		// TODO 0x080 => syscall!(rt_sigtimedwait, frame),
-		// TODO 0x081 => syscall!(rt_sigqueueinfo, frame),
-		// TODO 0x082 => syscall!(rt_sigsuspend, frame),
-		// TODO 0x083 => syscall!(sigaltstack, frame),
+		0x07f => syscall!(rt_sigpending, frame),
+		0x080 => syscall!(rt_sigtimedwait_time64, frame),
+		0x081 => syscall!(rt_sigqueueinfo, frame),
+		0x082 => syscall!(rt_sigsuspend, frame),
+		0x083 => syscall!(sigaltstack, frame),
 		// TODO 0x084 => syscall!(utime, frame),
 		0x085 => syscall!(mknod, frame),
 		// TODO 0x086 => syscall!(useli, frame),
@@ -922,19 +951,19 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		0x089 => syscall!(statfs, frame),
 		0x08a => syscall!(fstatfs, frame),
 		// TODO 0x08b => syscall!(sysfs, frame),
-		// TODO 0x08c => syscall!(getpriority, frame),
-		// TODO 0x08d => syscall!(setpriority, frame),
-		// TODO 0x08e => syscall!(sched_setparam, frame),
-		// TODO 0x08f => syscall!(sched_getparam, frame),
-		// TODO 0x090 => syscall!(sched_setscheduler, frame),
-		// TODO 0x091 => syscall!(sched_getscheduler, frame),
-		// TODO 0x092 => syscall!(sched_get_priority_max, frame),
-		// TODO 0x093 => syscall!(sched_get_priority_min, frame),
-		// TODO 0x094 => syscall!(sched_rr_get_interval, frame),
-		// TODO 0x095 => syscall!(mlock, frame),
-		// TODO 0x096 => syscall!(munlock, frame),
-		// TODO 0x097 => syscall!(mlockall, frame),
-		// TODO 0x098 => syscall!(munlockall, frame),
+		0x08c => syscall!(getpriority, frame),
+		0x08d => syscall!(setpriority, frame),
+		0x08e => syscall!(sched_setparam, frame),
+		0x08f => syscall!(sched_getparam, frame),
+		0x090 => syscall!(sched_setscheduler, frame),
+		0x091 => syscall!(sched_getscheduler, frame),
+		0x092 => syscall!(sched_get_priority_max, frame),
+		0x093 => syscall!(sched_get_priority_min, frame),
+		0x094 => syscall!(sched_rr_get_interval64, frame),
+		0x095 => syscall!(mlock, frame),
+		0x096 => syscall!(munlock, frame),
+		0x097 => syscall!(mlockall, frame),
+		0x098 => syscall!(munlockall, frame),
 		// TODO 0x099 => syscall!(vhangup, frame),
 		// TODO 0x09a => syscall!(modify_ldt, frame),
 		// TODO 0x09b => syscall!(pivot_root, frame),
@@ -949,8 +978,8 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0a4 => syscall!(settimeofday, frame),
 		0x0a5 => syscall!(mount, frame),
 		0x0a6 => syscall!(umount2, frame),
-		// TODO 0x0a7 => syscall!(swapon, frame),
-		// TODO 0x0a8 => syscall!(swapoff, frame),
+		0x0a7 => syscall!(swapon, frame),
+		0x0a8 => syscall!(swapoff, frame),
 		0x0a9 => syscall!(reboot, frame),
 		0x0aa => syscall!(sethostname, frame),
 		// TODO 0x0ab => syscall!(setdomainname, frame),
@@ -984,7 +1013,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0c7 => syscall!(fremovexattr, frame),
 		0x0c8 => syscall!(tkill, frame),
 		0x0c9 => syscall!(time64, frame),
-		// TODO 0x0ca => syscall!(futex, frame),
+		0x0ca => syscall!(futex64, frame),
 		// TODO 0x0cb => syscall!(sched_setaffinity, frame),
 		// TODO 0x0cc => syscall!(sched_getaffinity, frame),
 		// TODO 0x0cd => syscall!(set_thread_are, frame),
@@ -995,7 +1024,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0d2 => syscall!(io_cancel, frame),
 		// TODO 0x0d3 => syscall!(get_thread_are, frame),
 		// TODO 0x0d4 => syscall!(lookup_dcooki, frame),
-		// TODO 0x0d5 => syscall!(epoll_create, frame),
+		0x0d5 => syscall!(epoll_create, frame),
 		// TODO 0x0d6 => syscall!(epoll_ctl_ol, frame),
 		// TODO 0x0d7 => syscall!(epoll_wait_ol, frame),
 		// TODO 0x0d8 => syscall!(remap_file_pages, frame),
@@ -1014,9 +1043,9 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x0e5 => syscall!(clock_getres, frame),
 		// TODO 0x0e6 => syscall!(clock_nanosleep, frame),
 		0x0e7 => syscall!(exit_group, frame),
-		// TODO 0x0e8 => syscall!(epoll_wait, frame),
-		// TODO 0x0e9 => syscall!(epoll_ctl, frame),
-		// TODO 0x0ea => syscall!(tgkill, frame),
+		0x0e8 => syscall!(epoll_wait, frame),
+		0x0e9 => syscall!(epoll_ctl, frame),
+		0x0ea => syscall!(tgkill, frame),
 		// TODO 0x0eb => syscall!(utimes, frame),
 		// TODO 0x0ec => syscall!(vserve, frame),
 		// TODO 0x0ed => syscall!(mbind, frame),
@@ -1052,34 +1081,34 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x10b => syscall!(readlinkat, frame),
 		0x10c => syscall!(fchmodat, frame),
 		0x10d => syscall!(faccessat, frame),
-		0x10e => syscall!(pselect6, frame),
-		// TODO 0x10f => syscall!(ppoll, frame),
+		0x10e => syscall!(pselect6_time64, frame),
+		0x10f => syscall!(ppoll_time64, frame),
 		// TODO 0x110 => syscall!(unshare, frame),
-		// TODO 0x111 => syscall!(set_robust_list, frame),
-		// TODO 0x112 => syscall!(get_robust_list, frame),
+		0x111 => syscall!(set_robust_list, frame),
+		0x112 => syscall!(get_robust_list, frame),
 		// TODO 0x113 => syscall!(splice, frame),
 		// TODO 0x114 => syscall!(tee, frame),
 		// TODO 0x115 => syscall!(sync_file_range, frame),
 		// TODO 0x116 => syscall!(vmsplice, frame),
 		// TODO 0x117 => syscall!(move_pages, frame),
 		0x118 => syscall!(utimensat, frame),
-		// TODO 0x119 => syscall!(epoll_pwait, frame),
-		// TODO 0x11a => syscall!(signalfd, frame),
-		// TODO 0x11b => syscall!(timerfd_create, frame),
-		// TODO 0x11c => syscall!(eventfd, frame),
+		0x119 => syscall!(epoll_pwait, frame),
+		0x11a => syscall!(signalfd, frame),
+		0x11b => syscall!(timerfd_create, frame),
+		0x11c => syscall!(eventfd, frame),
 		// TODO 0x11d => syscall!(fallocate, frame),
-		// TODO 0x11e => syscall!(timerfd_settime, frame),
-		// TODO 0x11f => syscall!(timerfd_gettime, frame),
-		// TODO 0x120 => syscall!(accept4, frame),
-		// TODO 0x121 => syscall!(signalfd4, frame),
-		// TODO 0x122 => syscall!(eventfd2, frame),
-		// TODO 0x123 => syscall!(epoll_create1, frame),
+		0x11e => syscall!(timerfd_settime64, frame),
+		0x11f => syscall!(timerfd_gettime64, frame),
+		0x120 => syscall!(accept4, frame),
+		0x121 => syscall!(signalfd4, frame),
+		0x122 => syscall!(eventfd2, frame),
+		0x123 => syscall!(epoll_create1, frame),
 		// TODO 0x124 => syscall!(dup3, frame),
 		0x125 => syscall!(pipe2, frame),
 		// TODO 0x126 => syscall!(inotify_init1, frame),
 		0x127 => syscall!(preadv, frame),
 		0x128 => syscall!(pwritev, frame),
-		// TODO 0x129 => syscall!(rt_tgsigqueueinfo, frame),
+		0x129 => syscall!(rt_tgsigqueueinfo, frame),
 		// TODO 0x12a => syscall!(perf_event_open, frame),
 		// TODO 0x12b => syscall!(recvmmsg, frame),
 		// TODO 0x12c => syscall!(fanotify_init, frame),
@@ -1107,7 +1136,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x142 => syscall!(execveat, frame),
 		// TODO 0x143 => syscall!(userfaultfd, frame),
 		// TODO 0x144 => syscall!(membarrier, frame),
-		// TODO 0x145 => syscall!(mlock2, frame),
+		0x145 => syscall!(mlock2, frame),
 		// TODO 0x146 => syscall!(copy_file_range, frame),
 		0x147 => syscall!(preadv2, frame),
 		0x148 => syscall!(pwritev2, frame),
@@ -1134,7 +1163,7 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 		// TODO 0x1b6 => syscall!(pidfd_getfd, frame),
 		0x1b7 => syscall!(faccessat2, frame),
 		// TODO 0x1b8 => syscall!(process_madvise, frame),
-		// TODO 0x1b9 => syscall!(epoll_pwait2, frame),
+		0x1b9 => syscall!(epoll_pwait2, frame),
 		// TODO 0x1ba => syscall!(mount_setattr, frame),
 		// TODO 0x1bb => syscall!(quotactl_fd, frame),
 		// TODO 0x1bc => syscall!(landlock_create_ruleset, frame),
@@ -1157,26 +1186,32 @@ fn do_syscall64(id: usize, frame: &mut IntFrame) -> EResult<usize> {
 /// Called whenever a system call is triggered.
 #[unsafe(no_mangle)]
 pub extern "C" fn syscall_handler(frame: &mut IntFrame) {
-	let id = frame.get_syscall_id();
-	#[cfg(target_arch = "x86")]
-	let res = do_syscall32(id, frame);
-	#[cfg(target_arch = "x86_64")]
-	let res = if frame.is_compat() {
-		do_syscall32(id, frame)
-	} else {
-		do_syscall64(id, frame)
-	};
-	frame.set_syscall_return(res);
-	// If the system call does not exist, kill the process with SIGSYS
-	if unlikely(matches!(res, Err(e) if e.as_int() == ENOSYS)) {
-		let proc = Process::current();
-		#[cfg(feature = "strace")]
-		crate::println!(
-			"[strace PID: {pid}] invalid syscall (ID: 0x{id:x})",
-			pid = proc.get_pid()
-		);
-		proc.kill(Signal::SIGSYS);
+	enter_kernel();
+	// Let the tracer inspect, and possibly change or skip, the system call
+	let id = syscall_enter(frame);
+	if let Some(id) = id {
+		#[cfg(target_arch = "x86")]
+		let res = do_syscall32(id, frame);
+		#[cfg(target_arch = "x86_64")]
+		let res = if frame.is_compat() {
+			do_syscall32(id, frame)
+		} else {
+			do_syscall64(id, frame)
+		};
+		frame.set_syscall_return(res);
+		// If the system call does not exist, kill the process with SIGSYS
+		if unlikely(matches!(res, Err(e) if e.as_int() == ENOSYS)) {
+			let proc = Process::current();
+			#[cfg(feature = "strace")]
+			crate::println!(
+				"[strace PID: {pid}] invalid syscall (ID: 0x{id:x})",
+				pid = proc.get_pid()
+			);
+			proc.kill(Signal::SIGSYS);
+		}
 	}
+	// A skipped system call is reported with the ID `-1`
+	syscall_exit(id.unwrap_or(usize::MAX));
 	// If the process has been killed, handle it
 	yield_current(3, frame);
 }
@@ -19,14 +19,16 @@
 //! Process management system calls.
 
 #[cfg(target_arch = "x86_64")]
-use crate::{arch::x86, syscall::FromSyscallArg};
+use crate::arch::x86;
 use crate::{
 	arch::x86::{cli, gdt, idt::IntFrame},
+	file::perm::AccessProfile,
 	memory::user::UserPtr,
 	process,
 	process::{
 		ForkOptions, Process, State,
 		pid::Pid,
+		rlimit::RLimit,
 		rusage::Rusage,
 		scheduler::{
 			SCHEDULER, Scheduler, switch,
@@ -34,12 +36,13 @@ use crate::{
 		},
 		user_desc::UserDesc,
 	},
-	syscall::Args,
+	syscall::{Args, FromSyscallArg},
 };
 use core::{
 	ffi::{c_int, c_ulong, c_void},
 	hint::unlikely,
 	ptr::null_mut,
+	sync::atomic::Ordering::Relaxed,
 };
 use utils::{errno, errno::EResult, ptr::arc::Arc};
 
@@ -59,27 +62,31 @@ pub const CLONE_SIGHAND: c_ulong = 0x800;
 pub const CLONE_PIDFD: c_ulong = 0x1000;
 /// TODO doc
 pub const CLONE_PTRACE: c_ulong = 0x2000;
-/// TODO doc
+/// If specified, the parent is suspended until the child exits or executes a program.
 pub const CLONE_VFORK: c_ulong = 0x4000;
 /// TODO doc
 pub const CLONE_PARENT: c_ulong = 0x8000;
-/// TODO doc
+/// If specified, the child is a thread in the same thread group as the calling process.
+///
+/// This flag requires [`CLONE_SIGHAND`] and [`CLONE_VM`].
 pub const CLONE_THREAD: c_ulong = 0x10000;
 /// TODO doc
 pub const CLONE_NEWNS: c_ulong = 0x20000;
 /// TODO doc
 pub const CLONE_SYSVSEM: c_ulong = 0x40000;
-/// TODO doc
+/// If specified, the TLS of the child is set from the `tls` argument.
 pub const CLONE_SETTLS: c_ulong = 0x80000;
-/// TODO doc
+/// If specified, the child's TID is written at the `parent_tid` address in the parent's
+/// memory.
 pub const CLONE_PARENT_SETTID: c_ulong = 0x100000;
-/// TODO doc
+/// If specified, the child's TID is cleared at the `child_tid` address when it exits, and a
+/// wake operation is performed on the futex at this address.
 pub const CLONE_CHILD_CLEARTID: c_ulong = 0x200000;
 /// TODO doc
 pub const CLONE_DETACHED: c_ulong = 0x400000;
 /// TODO doc
 pub const CLONE_UNTRACED: c_ulong = 0x800000;
-/// TODO doc
+/// If specified, the child's TID is written at the `child_tid` address in the child's memory.
 pub const CLONE_CHILD_SETTID: c_ulong = 0x1000000;
 /// TODO doc
 pub const CLONE_NEWCGROUP: c_ulong = 0x2000000;
@@ -178,42 +185,70 @@ pub fn getpgid(Args(pid): Args<Pid>) -> EResult<usize> {
 }
 
 pub fn setpgid(Args((mut pid, mut pgid)): Args<(Pid, Pid)>, proc: Arc<Process>) -> EResult<usize> {
-	// TODO Check processes SID
 	if pid == 0 {
 		pid = proc.get_pid();
 	}
 	if pgid == 0 {
 		pgid = pid;
 	}
-	if pid == proc.get_pid() {
-		proc.set_pgid(pgid)?;
+	let sid = proc.get_sid();
+	let target = if pid == proc.get_pid() {
+		proc
 	} else {
 		// Avoid deadlock
 		drop(proc);
-		Process::get_by_pid(pid)
-			.ok_or_else(|| errno!(ESRCH))?
-			.set_pgid(pgid)?;
+		Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?
+	};
+	// A session leader cannot leave its group, and a process cannot join another session
+	let target_sid = target.get_sid();
+	if target_sid == target.get_pid() || target_sid != sid {
+		return Err(errno!(EPERM));
 	}
+	if pgid != pid {
+		let leader = Process::get_by_pid(pgid).ok_or_else(|| errno!(EPERM))?;
+		if leader.get_sid() != sid {
+			return Err(errno!(EPERM));
+		}
+	}
+	target.set_pgid(pgid)?;
 	Ok(0)
 }
 
+pub fn getpgrp(proc: Arc<Process>) -> EResult<usize> {
+	Ok(proc.get_pgid() as _)
+}
+
+pub fn getsid(Args(pid): Args<Pid>, proc: Arc<Process>) -> EResult<usize> {
+	if pid == 0 {
+		return Ok(proc.get_sid() as _);
+	}
+	let proc = Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?;
+	Ok(proc.get_sid() as _)
+}
+
+pub fn setsid(proc: Arc<Process>) -> EResult<usize> {
+	let proc = Process::thread_leader(&proc);
+	proc.set_sid()?;
+	Ok(proc.get_pid() as _)
+}
+
 pub fn gettid(proc: Arc<Process>) -> EResult<usize> {
-	Ok(proc.tid as _)
+	Ok(proc.get_tid() as _)
 }
 
-pub fn set_tid_address(Args(_tidptr): Args<UserPtr<c_int>>, proc: Arc<Process>) -> EResult<usize> {
-	// TODO set process's clear_child_tid
-	Ok(proc.tid as _)
+pub fn set_tid_address(Args(tidptr): Args<UserPtr<c_int>>, proc: Arc<Process>) -> EResult<usize> {
+	proc.clear_child_tid.store(tidptr.as_ptr() as _, Relaxed);
+	Ok(proc.get_tid() as _)
 }
 
 /// Wait for the vfork operation to complete.
-fn wait_vfork_done(child_pid: Pid) {
+fn wait_vfork_done(child_tid: Pid) {
 	loop {
 		// Use a scope to avoid holding references that could be lost, since `tick` could never
 		// return
 		{
 			let proc = Process::current();
-			let Some(child) = Process::get_by_pid(child_pid) else {
+			let Some(child) = Process::get_by_tid(child_tid) else {
 				// Child disappeared for some reason, stop
 				break;
 			};
@@ -236,7 +271,7 @@ fn wait_vfork_done(child_pid: Pid) {
 
 #[allow(clippy::type_complexity)]
 pub fn compat_clone(
-	Args((flags, stack, _parent_tid, _tls, _child_tid)): Args<(
+	Args((flags, stack, parent_tid, tls, child_tid)): Args<(
 		c_ulong,
 		*mut c_void,
 		UserPtr<c_int>,
@@ -246,7 +281,28 @@ pub fn compat_clone(
 	proc: Arc<Process>,
 	frame: &mut IntFrame,
 ) -> EResult<usize> {
-	let (child_pid, child_tid) = {
+	// A thread shares the signal handlers and memory of its process
+	if flags & CLONE_THREAD != 0 && flags & (CLONE_SIGHAND | CLONE_VM) != CLONE_SIGHAND | CLONE_VM
+	{
+		return Err(errno!(EINVAL));
+	}
+	// Handlers can only be shared along with the memory space
+	if flags & CLONE_SIGHAND != 0 && flags & CLONE_VM == 0 {
+		return Err(errno!(EINVAL));
+	}
+	// On compat, the TLS is described by a `user_desc` structure
+	let tls_entry = if flags & CLONE_SETTLS != 0 && frame.is_compat() {
+		let info = UserPtr::<UserDesc>::from_ptr(tls as _)
+			.copy_from_user()?
+			.ok_or(errno!(EFAULT))?;
+		// The child's entries are a copy of the parent's
+		let mut entries = *proc.tls.lock();
+		let (id, _) = get_tls_entry(&mut entries, info.get_entry_number())?;
+		Some((id, info.to_descriptor()))
+	} else {
+		None
+	};
+	let tid = {
 		// Disable interruptions so that the scheduler does not attempt to start the new process
 		cli();
 		let child = Process::fork(
@@ -255,27 +311,49 @@ pub fn compat_clone(
 				share_memory: flags & CLONE_VM != 0,
 				share_fd: flags & CLONE_FILES != 0,
 				share_sighand: flags & CLONE_SIGHAND != 0,
+				thread: flags & CLONE_THREAD != 0,
 			},
 		)?;
-		let child_pid = child.get_pid();
-		let child_tid = child.tid;
+		let tid = child.get_tid();
+		if let Some((id, entry)) = tls_entry {
+			child.tls.lock()[id] = entry;
+		}
+		// Like Linux, failures to write the TID are ignored
+		if flags & CLONE_PARENT_SETTID != 0 {
+			let _ = parent_tid.copy_to_user(&(tid as _));
+		}
+		if flags & CLONE_CHILD_CLEARTID != 0 {
+			child
+				.clear_child_tid
+				.store(child_tid.as_ptr() as _, Relaxed);
+		}
 		// Switch
 		switch::finish(&proc, &child);
+		// The child's memory space is now bound
+		if flags & CLONE_CHILD_SETTID != 0 {
+			let _ = child_tid.copy_to_user(&(tid as _));
+		}
 		SCHEDULER.lock().swap_current_process(child.clone());
 		let mut child_frame = frame.clone();
 		child_frame.rax = 0; // Return value
 		if !stack.is_null() {
 			child_frame.rsp = stack as _;
 		}
-		stash_segments(|| unsafe {
-			fork_asm(Arc::as_ptr(&proc), Arc::as_ptr(&child), &child_frame);
+		stash_segments(|| {
+			#[cfg(target_arch = "x86_64")]
+			if flags & CLONE_SETTLS != 0 && !frame.is_compat() {
+				x86::wrmsr(x86::IA32_FS_BASE, tls as _);
+			}
+			unsafe {
+				fork_asm(Arc::as_ptr(&proc), Arc::as_ptr(&child), &child_frame);
+			}
 		});
-		(child_pid, child_tid)
+		tid
 	};
 	if flags & CLONE_VFORK != 0 {
-		wait_vfork_done(child_pid);
+		wait_vfork_done(tid);
 	}
-	Ok(child_tid as _)
+	Ok(tid as _)
 }
 
 #[allow(clippy::type_complexity)]
@@ -384,7 +462,7 @@ pub fn arch_prctl(Args((code, addr)): Args<(c_int, usize)>) -> EResult<usize> {
 		}
 		#[cfg(target_arch = "x86_64")]
 		ARCH_GET_GS => {
-			let val = x86::rdmsr(x86::IA32_GS_BASE) as usize;
+			let val = x86::rdmsr(x86::IA32_KERNEL_GS_BASE) as usize;
 			let ptr = UserPtr::<usize>::from_ptr(addr);
 			ptr.copy_to_user(&val)?;
 		}
@@ -399,7 +477,7 @@ pub fn arch_prctl(Args((code, addr)): Args<(c_int, usize)>) -> EResult<usize> {
 pub fn getrusage(Args((who, usage)): Args<(c_int, UserPtr<Rusage>)>) -> EResult<usize> {
 	let proc = Process::current();
 	let rusage = match who {
-		RUSAGE_SELF => proc.rusage.lock().clone(),
+		RUSAGE_SELF => proc.get_rusage(),
 		RUSAGE_CHILDREN => {
 			// TODO Return resources of terminated children
 			Rusage::default()
@@ -410,30 +488,21 @@ pub fn getrusage(Args((who, usage)): Args<(c_int, UserPtr<Rusage>)>) -> EResult<
 	Ok(0)
 }
 
-/// A resource limit.
-#[repr(C)]
-#[derive(Debug)]
-pub struct RLimit {
-	/// Soft limit
-	rlim_cur: u64,
-	/// Hard limit (ceiling for [`rlim_cur`])
-	rlim_max: u64,
-}
-
 pub fn prlimit64(
-	Args((pid, resource, _new_limit, _old_limit)): Args<(
+	Args((pid, resource, new_limit, old_limit)): Args<(
 		Pid,
 		c_int,
 		UserPtr<RLimit>,
 		UserPtr<RLimit>,
 	)>,
+	proc: Arc<Process>,
+	ap: AccessProfile,
 ) -> EResult<usize> {
-	// The target process. If None, the current process is the target
-	let _target_proc = if pid != 0 {
+	let target_proc = if pid != 0 {
 		// TODO Check permission
-		Some(Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?)
+		Process::get_by_pid(pid).ok_or_else(|| errno!(ESRCH))?
 	} else {
-		None
+		Process::thread_leader(&proc)
 	};
 	// TODO Implement all
 	match resource {
@@ -445,7 +514,19 @@ pub fn prlimit64(
 		RLIMIT_RSS => {}
 		RLIMIT_NPROC => {}
 		RLIMIT_NOFILE => {}
-		RLIMIT_MEMLOCK => {}
+		RLIMIT_MEMLOCK => {
+			let mut limit = target_proc.memlock_limit.lock();
+			old_limit.copy_to_user(&limit)?;
+			if let Some(new_limit) = new_limit.copy_from_user()? {
+				if unlikely(new_limit.rlim_cur > new_limit.rlim_max) {
+					return Err(errno!(EINVAL));
+				}
+				if unlikely(new_limit.rlim_max > limit.rlim_max && !ap.is_privileged()) {
+					return Err(errno!(EPERM));
+				}
+				*limit = new_limit;
+			}
+		}
 		RLIMIT_AS => {}
 		RLIMIT_LOCKS => {}
 		RLIMIT_SIGPENDING => {}
@@ -459,29 +540,21 @@ pub fn prlimit64(
 	Ok(0)
 }
 
-pub fn sched_yield() -> EResult<usize> {
-	Scheduler::tick();
-	Ok(0)
-}
-
 /// Exits the current process.
 ///
 /// Arguments:
 /// - `status` is the exit status.
-/// - `thread_group`: if `true`, the function exits the whole process group.
-/// - `proc` is the current process.
+/// - `thread_group`: if `true`, the function exits every thread of the current process.
 pub fn do_exit(status: u32, thread_group: bool) -> ! {
 	// Disable interruptions to prevent execution from being stopped before the reference to
 	// `Process` is dropped
 	cli();
 	{
 		let proc = Process::current();
-		proc.exit(status);
-		let _pid = proc.get_pid();
-		let _tid = proc.tid;
 		if thread_group {
-			// TODO Iterate on every process of thread group `tid`, except the
-			// process with pid `pid`
+			proc.exit_group(status);
+		} else {
+			proc.exit(status);
 		}
 	}
 	Scheduler::tick();
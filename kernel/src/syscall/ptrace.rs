@@ -0,0 +1,475 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The `ptrace` system call allows a process to observe and control the execution of another
+//! process.
+
+use crate::{
+	arch::x86::{gdt, idt::IntFrame},
+	file::perm::AccessProfile,
+	memory::{
+		VirtAddr,
+		user::{UserPtr, UserSlice},
+	},
+	process::{
+		Process,
+		mem_space::{MemSpace, bound_check},
+		pid::Pid,
+		ptrace,
+		ptrace::PTRACE_O_MASK,
+		signal::Signal,
+	},
+	syscall::{Args, FromSyscallArg},
+};
+use core::ffi::{c_int, c_long};
+#[cfg(target_arch = "x86_64")]
+use core::sync::atomic::Ordering::Relaxed;
+#[cfg(target_arch = "x86_64")]
+use utils::limits::PAGE_SIZE;
+use utils::{errno, errno::EResult, ptr::arc::Arc};
+
+/// Request: make the current process traced by its parent.
+const PTRACE_TRACEME: c_long = 0;
+/// Request: read a word in the tracee's text.
+const PTRACE_PEEKTEXT: c_long = 1;
+/// Request: read a word in the tracee's data.
+const PTRACE_PEEKDATA: c_long = 2;
+/// Request: read a word in the tracee's `user` structure.
+const PTRACE_PEEKUSER: c_long = 3;
+/// Request: write a word in the tracee's text.
+const PTRACE_POKETEXT: c_long = 4;
+/// Request: write a word in the tracee's data.
+const PTRACE_POKEDATA: c_long = 5;
+/// Request: resume the tracee.
+const PTRACE_CONT: c_long = 7;
+/// Request: kill the tracee.
+const PTRACE_KILL: c_long = 8;
+/// Request: resume the tracee for a single instruction.
+const PTRACE_SINGLESTEP: c_long = 9;
+/// Request: read the tracee's general purpose registers.
+const PTRACE_GETREGS: c_long = 12;
+/// Request: write the tracee's general purpose registers.
+const PTRACE_SETREGS: c_long = 13;
+/// Request: read the tracee's floating point registers.
+const PTRACE_GETFPREGS: c_long = 14;
+/// Request: write the tracee's floating point registers.
+const PTRACE_SETFPREGS: c_long = 15;
+/// Request: attach to a process, stopping it.
+const PTRACE_ATTACH: c_long = 16;
+/// Request: detach from the tracee.
+const PTRACE_DETACH: c_long = 17;
+/// Request: resume the tracee until the next entry or exit of a system call.
+const PTRACE_SYSCALL: c_long = 24;
+/// Request: set tracing options.
+const PTRACE_SETOPTIONS: c_long = 0x4200;
+/// Request: attach to a process without stopping it.
+const PTRACE_SEIZE: c_long = 0x4206;
+
+/// Mask of the flags the tracer is allowed to change in the `rflags` register.
+const USER_FLAGS_MASK: usize = 0x50dd5;
+
+/// The size of the `user` structure for a 32 bit tracer.
+const USER_SIZE_32: usize = 284;
+/// The size of the `user` structure for a 64 bit tracer.
+#[cfg(target_arch = "x86_64")]
+const USER_SIZE_64: usize = 912;
+
+/// General purpose registers, as seen by a 32 bit tracer.
+#[repr(C)]
+#[derive(Debug)]
+struct UserRegs32 {
+	ebx: u32,
+	ecx: u32,
+	edx: u32,
+	esi: u32,
+	edi: u32,
+	ebp: u32,
+	eax: u32,
+	xds: u32,
+	xes: u32,
+	xfs: u32,
+	xgs: u32,
+	orig_eax: u32,
+	eip: u32,
+	xcs: u32,
+	eflags: u32,
+	esp: u32,
+	xss: u32,
+}
+
+impl UserRegs32 {
+	/// Creates an instance from the tracee's registers `frame`.
+	///
+	/// `syscall` is the ID of the system call the tracee is stopped at, if any.
+	fn new(frame: &IntFrame, syscall: Option<usize>) -> Self {
+		Self {
+			ebx: frame.rbx as _,
+			ecx: frame.rcx as _,
+			edx: frame.rdx as _,
+			esi: frame.rsi as _,
+			edi: frame.rdi as _,
+			ebp: frame.rbp as _,
+			eax: frame.rax as _,
+			xds: (gdt::USER_DS | 3) as _,
+			xes: (gdt::USER_DS | 3) as _,
+			xfs: frame.fs as _,
+			xgs: frame.gs as _,
+			orig_eax: syscall.map(|id| id as u32).unwrap_or(u32::MAX),
+			eip: frame.rip as _,
+			xcs: frame.cs as _,
+			eflags: frame.rflags as _,
+			esp: frame.rsp as _,
+			xss: frame.ss as _,
+		}
+	}
+
+	/// Writes the registers to the tracee's `frame`, returning the new system call ID.
+	fn apply(&self, frame: &mut IntFrame) -> EResult<usize> {
+		set_control_regs(frame, self.eip as _, self.esp as _, self.eflags as _)?;
+		frame.rbx = self.ebx as _;
+		frame.rcx = self.ecx as _;
+		frame.rdx = self.edx as _;
+		frame.rsi = self.esi as _;
+		frame.rdi = self.edi as _;
+		frame.rbp = self.ebp as _;
+		frame.rax = self.eax as _;
+		Ok(self.orig_eax as i32 as _)
+	}
+}
+
+/// General purpose registers, as seen by a 64 bit tracer.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug)]
+struct UserRegs64 {
+	r15: u64,
+	r14: u64,
+	r13: u64,
+	r12: u64,
+	rbp: u64,
+	rbx: u64,
+	r11: u64,
+	r10: u64,
+	r9: u64,
+	r8: u64,
+	rax: u64,
+	rcx: u64,
+	rdx: u64,
+	rsi: u64,
+	rdi: u64,
+	orig_rax: u64,
+	rip: u64,
+	cs: u64,
+	eflags: u64,
+	rsp: u64,
+	ss: u64,
+	fs_base: u64,
+	gs_base: u64,
+	ds: u64,
+	es: u64,
+	fs: u64,
+	gs: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl UserRegs64 {
+	/// Creates an instance from the tracee's registers `frame`.
+	///
+	/// `syscall` is the ID of the system call the tracee is stopped at, if any. Segment bases
+	/// are taken from `tracee`, which must be switched out.
+	fn new(tracee: &Process, frame: &IntFrame, syscall: Option<usize>) -> Self {
+		Self {
+			r15: frame.r15,
+			r14: frame.r14,
+			r13: frame.r13,
+			r12: frame.r12,
+			rbp: frame.rbp,
+			rbx: frame.rbx,
+			r11: frame.r11,
+			r10: frame.r10,
+			r9: frame.r9,
+			r8: frame.r8,
+			rax: frame.rax,
+			rcx: frame.rcx,
+			rdx: frame.rdx,
+			rsi: frame.rsi,
+			rdi: frame.rdi,
+			orig_rax: syscall.map(|id| id as u64).unwrap_or(u64::MAX),
+			rip: frame.rip,
+			cs: frame.cs,
+			eflags: frame.rflags,
+			rsp: frame.rsp,
+			ss: frame.ss,
+			fs_base: tracee.fs_base.load(Relaxed),
+			gs_base: tracee.gs_base.load(Relaxed),
+			ds: (gdt::USER_DS | 3) as _,
+			es: (gdt::USER_DS | 3) as _,
+			fs: frame.fs,
+			gs: frame.gs,
+		}
+	}
+
+	/// Writes the registers to the tracee's `frame` and segment bases to `tracee`, returning the
+	/// new system call ID.
+	fn apply(&self, tracee: &Process, frame: &mut IntFrame) -> EResult<usize> {
+		if !is_valid_base(self.fs_base) || !is_valid_base(self.gs_base) {
+			return Err(errno!(EIO));
+		}
+		set_control_regs(frame, self.rip as _, self.rsp as _, self.eflags as _)?;
+		tracee.fs_base.store(self.fs_base, Relaxed);
+		tracee.gs_base.store(self.gs_base, Relaxed);
+		frame.r15 = self.r15;
+		frame.r14 = self.r14;
+		frame.r13 = self.r13;
+		frame.r12 = self.r12;
+		frame.rbp = self.rbp;
+		frame.rbx = self.rbx;
+		frame.r11 = self.r11;
+		frame.r10 = self.r10;
+		frame.r9 = self.r9;
+		frame.r8 = self.r8;
+		frame.rax = self.rax;
+		frame.rcx = self.rcx;
+		frame.rdx = self.rdx;
+		frame.rsi = self.rsi;
+		frame.rdi = self.rdi;
+		Ok(self.orig_rax as _)
+	}
+}
+
+/// Tells whether `base` can be used as a segment base by userspace.
+#[cfg(target_arch = "x86_64")]
+fn is_valid_base(base: u64) -> bool {
+	base < PAGE_SIZE as u64 || bound_check(base as _, 0)
+}
+
+/// Returns the word of type `W` at offset `off` in the `user` structure of size `size`, whose
+/// first field is `regs`.
+///
+/// Only general purpose registers are supported, other fields read as zero.
+fn peek_user<R, W: Copy + Into<u64>>(regs: &R, off: usize, size: usize) -> EResult<usize> {
+	if off % size_of::<W>() != 0 || off >= size {
+		return Err(errno!(EIO));
+	}
+	if off >= size_of::<R>() {
+		return Ok(0);
+	}
+	let val = unsafe { (regs as *const R).byte_add(off).cast::<W>().read() };
+	Ok(val.into() as _)
+}
+
+/// Sets the program counter, stack pointer and flags of `frame`.
+///
+/// The program counter must point to userspace, and only the flags that userspace is allowed to
+/// change are updated. Segments cannot be changed.
+fn set_control_regs(frame: &mut IntFrame, pc: usize, sp: usize, flags: usize) -> EResult<()> {
+	if !bound_check(pc, 0) {
+		return Err(errno!(EIO));
+	}
+	frame.rip = pc as _;
+	frame.rsp = sp as _;
+	let rflags = frame.rflags as usize;
+	frame.rflags = ((rflags & !USER_FLAGS_MASK) | (flags & USER_FLAGS_MASK)) as _;
+	Ok(())
+}
+
+/// Returns the signal to deliver when resuming, given the `data` argument.
+fn resume_signal(data: usize) -> EResult<Option<Signal>> {
+	(data != 0)
+		.then(|| Signal::try_from(data as c_int).map_err(|_| errno!(EIO)))
+		.transpose()
+}
+
+/// Reads a word of size `size` in the memory of `tracee` at `addr`.
+fn peek(tracee: &Process, addr: usize, size: usize) -> EResult<usize> {
+	let mem_space = tracee.mem_space.as_ref().ok_or_else(|| errno!(EIO))?;
+	let mut buf = [0u8; size_of::<usize>()];
+	let slice = UserSlice::from_user(VirtAddr(addr).as_ptr(), size)?;
+	let len =
+		unsafe { MemSpace::switch(mem_space, |_| slice.copy_from_user(0, &mut buf[..size])) }
+			.map_err(|_| errno!(EIO))?;
+	if len != size {
+		return Err(errno!(EIO));
+	}
+	Ok(usize::from_ne_bytes(buf))
+}
+
+/// Writes the word `val` of size `size` in the memory of `tracee` at `addr`.
+fn poke(tracee: &Process, addr: usize, val: usize, size: usize) -> EResult<()> {
+	let mem_space = tracee.mem_space.as_ref().ok_or_else(|| errno!(EIO))?;
+	if !bound_check(addr, size) {
+		return Err(errno!(EIO));
+	}
+	let buf = val.to_ne_bytes();
+	unsafe {
+		MemSpace::switch(mem_space, |mem_space| {
+			mem_space.write_force(VirtAddr(addr), &buf[..size])
+		})
+	}
+	.map_err(|_| errno!(EIO))
+}
+
+#[allow(clippy::type_complexity)]
+pub fn ptrace(
+	Args((request, pid, addr, data)): Args<(c_long, Pid, usize, usize)>,
+	proc: Arc<Process>,
+	access_profile: AccessProfile,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	match request {
+		PTRACE_TRACEME => {
+			let parent =
+				Process::get_by_pid(proc.get_parent_pid()).ok_or_else(|| errno!(EPERM))?;
+			ptrace::attach(&parent, &proc, 0)?;
+			return Ok(0);
+		}
+		PTRACE_ATTACH | PTRACE_SEIZE => {
+			let tracee = Process::get_by_tid(pid).ok_or_else(|| errno!(ESRCH))?;
+			// Kernel threads and threads of the current process cannot be traced
+			if tracee.get_pid() == proc.get_pid()
+				|| tracee.mem_space.is_none()
+				|| !access_profile.can_kill(&tracee)
+			{
+				return Err(errno!(EPERM));
+			}
+			let options = if request == PTRACE_SEIZE {
+				data as u32
+			} else {
+				0
+			};
+			if options & !PTRACE_O_MASK != 0 {
+				return Err(errno!(EINVAL));
+			}
+			ptrace::attach(&proc, &tracee, options)?;
+			if request == PTRACE_ATTACH {
+				tracee.kill(Signal::SIGSTOP);
+				// Interrupt a potential blocking operation so that the tracee stops
+				tracee.wake();
+			}
+			return Ok(0);
+		}
+		_ => {}
+	}
+	let tracee = Process::get_by_tid(pid).ok_or_else(|| errno!(ESRCH))?;
+	// Killing does not require the tracee to be stopped
+	if request == PTRACE_KILL {
+		if tracee.ptrace.lock().tracer != Some(proc.get_tid()) {
+			return Err(errno!(ESRCH));
+		}
+		tracee.kill(Signal::SIGKILL);
+		return Ok(0);
+	}
+	if !ptrace::is_stopped_tracee(&proc, &tracee) {
+		return Err(errno!(ESRCH));
+	}
+	// The size of a word for the tracer
+	let word_size = if frame.is_compat() {
+		size_of::<u32>()
+	} else {
+		size_of::<usize>()
+	};
+	match request {
+		PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+			let val = peek(&tracee, addr, word_size)?;
+			if frame.is_compat() {
+				UserPtr::<u32>::from_ptr(data).copy_to_user(&(val as _))?;
+			} else {
+				UserPtr::<usize>::from_ptr(data).copy_to_user(&val)?;
+			}
+		}
+		PTRACE_PEEKUSER => {
+			let regs = tracee.user_regs();
+			let syscall = tracee.ptrace.lock().syscall;
+			if frame.is_compat() {
+				let regs = UserRegs32::new(&regs, syscall);
+				let val = peek_user::<_, u32>(&regs, addr, USER_SIZE_32)?;
+				UserPtr::<u32>::from_ptr(data).copy_to_user(&(val as _))?;
+			} else {
+				#[cfg(target_arch = "x86_64")]
+				{
+					let regs = UserRegs64::new(&tracee, &regs, syscall);
+					let val = peek_user::<_, u64>(&regs, addr, USER_SIZE_64)?;
+					UserPtr::<usize>::from_ptr(data).copy_to_user(&val)?;
+				}
+			}
+		}
+		PTRACE_POKETEXT | PTRACE_POKEDATA => poke(&tracee, addr, data, word_size)?,
+		PTRACE_GETREGS => {
+			let regs = tracee.user_regs();
+			let syscall = tracee.ptrace.lock().syscall;
+			if frame.is_compat() {
+				let regs = UserRegs32::new(&regs, syscall);
+				UserPtr::from_ptr(data).copy_to_user(&regs)?;
+			} else {
+				#[cfg(target_arch = "x86_64")]
+				{
+					let regs = UserRegs64::new(&tracee, &regs, syscall);
+					UserPtr::from_ptr(data).copy_to_user(&regs)?;
+				}
+			}
+		}
+		PTRACE_SETREGS => {
+			let mut regs = tracee.user_regs();
+			let syscall = if frame.is_compat() {
+				UserPtr::<UserRegs32>::from_ptr(data)
+					.copy_from_user()?
+					.ok_or_else(|| errno!(EFAULT))?
+					.apply(&mut regs)?
+			} else {
+				#[cfg(target_arch = "x86")]
+				unreachable!();
+				#[cfg(target_arch = "x86_64")]
+				UserPtr::<UserRegs64>::from_ptr(data)
+					.copy_from_user()?
+					.ok_or_else(|| errno!(EFAULT))?
+					.apply(&tracee, &mut regs)?
+			};
+			unsafe {
+				tracee.set_user_regs(&regs);
+			}
+			// The system call can be changed only when stopped at it
+			let mut ptrace = tracee.ptrace.lock();
+			if let Some(id) = &mut ptrace.syscall {
+				*id = syscall;
+			}
+		}
+		// The floating point registers are in the `fxsave` format for 64 bit tracers only
+		PTRACE_GETFPREGS if !frame.is_compat() => {
+			let fpu = tracee.fpu.lock();
+			UserSlice::from_user(VirtAddr(data).as_ptr(), fpu.0.len())?.copy_to_user(0, &fpu.0)?;
+		}
+		PTRACE_SETFPREGS if !frame.is_compat() => {
+			let mut fpu = tracee.fpu.lock();
+			UserSlice::from_user(VirtAddr(data).as_ptr(), fpu.0.len())?
+				.copy_from_user(0, &mut fpu.0)?;
+		}
+		PTRACE_CONT => ptrace::resume(&tracee, resume_signal(data)?, false, false),
+		PTRACE_SYSCALL => ptrace::resume(&tracee, resume_signal(data)?, true, false),
+		PTRACE_SINGLESTEP => ptrace::resume(&tracee, resume_signal(data)?, false, true),
+		PTRACE_DETACH => ptrace::detach(&tracee, resume_signal(data)?),
+		PTRACE_SETOPTIONS => {
+			let options = data as u32;
+			if options & !PTRACE_O_MASK != 0 {
+				return Err(errno!(EINVAL));
+			}
+			tracee.ptrace.lock().options = options;
+		}
+		_ => return Err(errno!(EIO)),
+	}
+	Ok(0)
+}
@@ -0,0 +1,291 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Scheduling system calls.
+
+use crate::{
+	file::perm::AccessProfile,
+	memory::user::UserPtr,
+	process::{
+		Process,
+		pid::Pid,
+		scheduler::{
+			SCHEDULER, Scheduler,
+			policy::{NICE_MAX, NICE_MIN, Policy, RR_TIMESLICE, SCHED_RESET_ON_FORK},
+		},
+	},
+	syscall::Args,
+	time::unit::{TimeUnit, Timespec, Timespec32},
+};
+use core::ffi::c_int;
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{CollectResult, EResult},
+	ptr::arc::Arc,
+};
+
+/// `which` value for `getpriority` and `setpriority`: `who` is a process ID.
+const PRIO_PROCESS: c_int = 0;
+/// `which` value for `getpriority` and `setpriority`: `who` is a process group ID.
+const PRIO_PGRP: c_int = 1;
+/// `which` value for `getpriority` and `setpriority`: `who` is a user ID.
+const PRIO_USER: c_int = 2;
+
+/// Scheduling parameters.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SchedParam {
+	/// The static priority.
+	sched_priority: c_int,
+}
+
+/// Returns the thread with TID `pid`, or the current thread if `pid` is zero.
+fn get_target(pid: c_int) -> EResult<Arc<Process>> {
+	match pid {
+		0 => Ok(Process::current()),
+		1.. => Process::get_by_tid(pid as Pid).ok_or_else(|| errno!(ESRCH)),
+		_ => Err(errno!(EINVAL)),
+	}
+}
+
+/// Returns all processes.
+///
+/// Processes are collected so that their locks can be acquired without holding the scheduler's.
+fn all_processes() -> EResult<Vec<Arc<Process>>> {
+	let procs = SCHEDULER
+		.lock()
+		.iter_process()
+		.map(|(_, proc)| proc.clone())
+		.collect::<CollectResult<Vec<_>>>()
+		.0?;
+	Ok(procs)
+}
+
+/// Returns the processes designated by `which` and `who`.
+fn get_targets(which: c_int, who: c_int) -> EResult<Vec<Arc<Process>>> {
+	let mut procs = Vec::new();
+	match which {
+		PRIO_PROCESS => procs.push(get_target(who)?)?,
+		PRIO_PGRP => {
+			let pgid = match who {
+				0 => Process::current().get_pgid(),
+				1.. => who as Pid,
+				_ => return Err(errno!(EINVAL)),
+			};
+			let all = all_processes()?;
+			for proc in all {
+				if proc.get_pgid() == pgid {
+					procs.push(proc)?;
+				}
+			}
+		}
+		PRIO_USER => {
+			let uid = match who {
+				0 => Process::current().fs.lock().access_profile.uid,
+				_ => who as _,
+			};
+			let all = all_processes()?;
+			for proc in all {
+				if proc.fs.lock().access_profile.uid == uid {
+					procs.push(proc)?;
+				}
+			}
+		}
+		_ => return Err(errno!(EINVAL)),
+	}
+	// Do not expose idle tasks
+	procs.retain(|proc| !proc.is_idle_task());
+	if procs.is_empty() {
+		return Err(errno!(ESRCH));
+	}
+	Ok(procs)
+}
+
+/// Checks that the agent with access profile `ap` can change the scheduling parameters of
+/// `proc`.
+fn check_owner(ap: &AccessProfile, proc: &Process) -> EResult<()> {
+	if ap.is_privileged() {
+		return Ok(());
+	}
+	let target = proc.fs.lock().access_profile;
+	if ap.euid == target.uid || ap.euid == target.euid {
+		Ok(())
+	} else {
+		Err(errno!(EPERM))
+	}
+}
+
+/// Sets the nice value of `proc` to `nice`, checking permissions.
+fn set_nice(ap: &AccessProfile, proc: &Arc<Process>, nice: i8) -> EResult<()> {
+	check_owner(ap, proc)?;
+	// Increasing the priority requires privileges
+	if nice < proc.sched.get_nice() && !ap.is_privileged() {
+		return Err(errno!(EACCES));
+	}
+	SCHEDULER.lock().set_nice(proc, nice);
+	Ok(())
+}
+
+pub fn nice(
+	Args(inc): Args<c_int>,
+	proc: Arc<Process>,
+	access_profile: AccessProfile,
+) -> EResult<usize> {
+	let inc = inc.clamp(-40, 40);
+	let nice = (proc.sched.get_nice() as c_int + inc).clamp(NICE_MIN as _, NICE_MAX as _);
+	set_nice(&access_profile, &proc, nice as _).map_err(|_| errno!(EPERM))?;
+	Ok(0)
+}
+
+pub fn getpriority(Args((which, who)): Args<(c_int, c_int)>) -> EResult<usize> {
+	let nice = get_targets(which, who)?
+		.iter()
+		.map(|proc| proc.sched.get_nice())
+		.min()
+		.unwrap();
+	// Return a positive value to avoid confusion with errors
+	Ok((20 - nice as isize) as _)
+}
+
+pub fn setpriority(
+	Args((which, who, prio)): Args<(c_int, c_int, c_int)>,
+	access_profile: AccessProfile,
+) -> EResult<usize> {
+	let nice = prio.clamp(NICE_MIN as _, NICE_MAX as _) as i8;
+	for proc in get_targets(which, who)? {
+		set_nice(&access_profile, &proc, nice)?;
+	}
+	Ok(0)
+}
+
+/// Sets the scheduling policy and parameters of the process with PID `pid`.
+///
+/// If `policy` is `None`, the current policy of the process is kept.
+fn do_setscheduler(
+	pid: c_int,
+	policy: Option<c_int>,
+	param: UserPtr<SchedParam>,
+	ap: &AccessProfile,
+) -> EResult<usize> {
+	let param = param.copy_from_user()?.ok_or_else(|| errno!(EINVAL))?;
+	let proc = get_target(pid)?;
+	let (policy, reset_on_fork) = match policy {
+		Some(policy) => {
+			let reset_on_fork = policy & SCHED_RESET_ON_FORK != 0;
+			let policy =
+				Policy::from_id(policy & !SCHED_RESET_ON_FORK).ok_or_else(|| errno!(EINVAL))?;
+			(policy, reset_on_fork)
+		}
+		None => (proc.sched.get_policy(), proc.sched.is_reset_on_fork()),
+	};
+	let (min, max) = policy.priority_range();
+	if !(min as c_int..=max as c_int).contains(&param.sched_priority) {
+		return Err(errno!(EINVAL));
+	}
+	check_owner(ap, &proc)?;
+	if !ap.is_privileged() {
+		// TODO allow according to `RLIMIT_RTPRIO`
+		if policy.is_realtime() {
+			return Err(errno!(EPERM));
+		}
+		if proc.sched.is_reset_on_fork() && !reset_on_fork {
+			return Err(errno!(EPERM));
+		}
+	}
+	SCHEDULER
+		.lock()
+		.set_policy(&proc, policy, param.sched_priority as _, reset_on_fork);
+	Ok(0)
+}
+
+pub fn sched_setscheduler(
+	Args((pid, policy, param)): Args<(c_int, c_int, UserPtr<SchedParam>)>,
+	access_profile: AccessProfile,
+) -> EResult<usize> {
+	do_setscheduler(pid, Some(policy), param, &access_profile)
+}
+
+pub fn sched_setparam(
+	Args((pid, param)): Args<(c_int, UserPtr<SchedParam>)>,
+	access_profile: AccessProfile,
+) -> EResult<usize> {
+	do_setscheduler(pid, None, param, &access_profile)
+}
+
+pub fn sched_getscheduler(Args(pid): Args<c_int>) -> EResult<usize> {
+	let proc = get_target(pid)?;
+	let mut policy = proc.sched.get_policy() as c_int;
+	if proc.sched.is_reset_on_fork() {
+		policy |= SCHED_RESET_ON_FORK;
+	}
+	Ok(policy as _)
+}
+
+pub fn sched_getparam(Args((pid, param)): Args<(c_int, UserPtr<SchedParam>)>) -> EResult<usize> {
+	if param.as_ptr().is_null() {
+		return Err(errno!(EINVAL));
+	}
+	let proc = get_target(pid)?;
+	param.copy_to_user(&SchedParam {
+		sched_priority: proc.sched.get_rt_priority() as _,
+	})?;
+	Ok(0)
+}
+
+pub fn sched_get_priority_max(Args(policy): Args<c_int>) -> EResult<usize> {
+	let policy = Policy::from_id(policy).ok_or_else(|| errno!(EINVAL))?;
+	Ok(policy.priority_range().1 as _)
+}
+
+pub fn sched_get_priority_min(Args(policy): Args<c_int>) -> EResult<usize> {
+	let policy = Policy::from_id(policy).ok_or_else(|| errno!(EINVAL))?;
+	Ok(policy.priority_range().0 as _)
+}
+
+/// Returns the timeslice of the process with PID `pid`, in nanoseconds.
+fn rr_interval(pid: c_int) -> EResult<u64> {
+	let proc = get_target(pid)?;
+	Ok(match proc.sched.get_policy() {
+		Policy::Rr => RR_TIMESLICE,
+		_ => 0,
+	})
+}
+
+pub fn sched_rr_get_interval32(
+	Args((pid, tp)): Args<(c_int, UserPtr<Timespec32>)>,
+) -> EResult<usize> {
+	let interval = rr_interval(pid)?;
+	tp.copy_to_user(&Timespec32::from_nano(interval))?;
+	Ok(0)
+}
+
+pub fn sched_rr_get_interval64(
+	Args((pid, tp)): Args<(c_int, UserPtr<Timespec>)>,
+) -> EResult<usize> {
+	let interval = rr_interval(pid)?;
+	tp.copy_to_user(&Timespec::from_nano(interval))?;
+	Ok(0)
+}
+
+pub fn sched_yield(proc: Arc<Process>) -> EResult<usize> {
+	proc.sched.yield_cpu();
+	drop(proc);
+	Scheduler::tick();
+	Ok(0)
+}
@@ -20,28 +20,39 @@
 //! writable or for an exception to occur.
 
 use crate::{
-	file::fd::FileDescriptorTable,
+	arch::x86::idt::IntFrame,
+	file::{fd::FileDescriptorTable, wait_queue::PollTable},
 	memory::user::{UserPtr, UserSlice},
-	process::scheduler::Scheduler,
+	process::signal::{SigSet, with_sigmask},
 	sync::mutex::Mutex,
-	syscall::Args,
-	time::{
-		clock::{Clock, current_time_ms, current_time_ns},
-		unit::{TimeUnit, Timespec, Timestamp, Timeval},
-	},
+	syscall::{Args, FromSyscallArg},
+	time::unit::{TimeUnit, Timespec, Timespec32, Timestamp, Timeval},
 };
 use core::{
 	cmp::min,
 	ffi::{c_int, c_long},
+	hint::unlikely,
+};
+use utils::{
+	collections::vec::Vec,
+	errno,
+	errno::{CollectResult, EResult},
+	ptr::arc::Arc,
 };
-use utils::{errno, errno::EResult, ptr::arc::Arc};
 
 /// The number of file descriptors in FDSet.
 pub const FD_SETSIZE: usize = 1024;
 
+/// The events reporting a file as readable for `select`.
+const SELECT_READ: u32 = POLLIN | POLLRDNORM | POLLRDBAND | POLLHUP | POLLERR;
+/// The events reporting a file as writable for `select`.
+const SELECT_WRITE: u32 = POLLOUT | POLLWRNORM | POLLWRBAND | POLLERR;
+/// The events reporting an exceptional condition on a file for `select`.
+const SELECT_EXCEPT: u32 = POLLPRI;
+
 /// Structure representing `fd_set`.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct FDSet {
 	/// The set's bitfield.
 	fds_bits: [c_long; FD_SETSIZE / c_long::BITS as usize],
@@ -53,14 +64,12 @@ impl FDSet {
 		if fd as usize >= FD_SETSIZE {
 			return false;
 		}
-		// TODO Check correctness
 		let i = (fd as usize) / c_long::BITS as usize;
-		self.fds_bits[i] >> (fd % c_long::BITS) != 0
+		(self.fds_bits[i] >> (fd % c_long::BITS)) & 1 != 0
 	}
 
 	/// Sets or clears the bit for file descriptor `fd`.
 	fn set(&mut self, fd: u32, val: bool) {
-		// TODO Check correctness
 		let i = (fd as usize) / c_long::BITS as usize;
 		if val {
 			self.fds_bits[i] |= 1 << (fd % c_long::BITS);
@@ -70,17 +79,33 @@ impl FDSet {
 	}
 }
 
+/// Reads the signal mask at `sigmask`, of size `sigsetsize`.
+///
+/// If the pointer is null, the function returns `None`.
+pub(super) fn read_sigmask(
+	sigmask: UserPtr<SigSet>,
+	sigsetsize: usize,
+) -> EResult<Option<SigSet>> {
+	let Some(sigmask) = sigmask.copy_from_user()? else {
+		return Ok(None);
+	};
+	if unlikely(sigsetsize != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	Ok(Some(sigmask))
+}
+
 /// Performs the select operation.
 ///
 /// Arguments:
-/// - `mem_space` is the process's memory space.
 /// - `fds` is the process's file descriptors table.
 /// - `nfds` is the number of the highest checked fd + 1.
 /// - `readfds` is the bitfield of fds to check for read operations.
 /// - `writefds` is the bitfield of fds to check for write operations.
 /// - `exceptfds` is the bitfield of fds to check for exceptional conditions.
-/// - `timeout` is the timeout after which the syscall returns.
-/// - `sigmask` TODO
+/// - `timeout` is the timeout after which the syscall returns. If null, the syscall waits
+///   indefinitely.
+/// - `sigmask` is the signal mask to apply while waiting, if any.
 pub fn do_select<T: TimeUnit>(
 	fds: Arc<Mutex<FileDescriptorTable>>,
 	nfds: u32,
@@ -88,100 +113,69 @@ pub fn do_select<T: TimeUnit>(
 	writefds: UserPtr<FDSet>,
 	exceptfds: UserPtr<FDSet>,
 	timeout: UserPtr<T>,
-	_sigmask: Option<*mut u8>,
+	sigmask: Option<SigSet>,
 ) -> EResult<usize> {
-	let start = current_time_ns(Clock::Monotonic);
-	// Get timeout
-	let timeout = timeout
-		.copy_from_user()?
-		.map(|t| t.to_nano())
-		.unwrap_or_default();
-	// Tells whether the syscall immediately returns
-	let polling = timeout == 0;
-	// The end timestamp
-	let end = start + timeout;
-	// Read
-	let mut readfds_set = readfds.copy_from_user()?;
-	let mut writefds_set = writefds.copy_from_user()?;
-	let mut exceptfds_set = exceptfds.copy_from_user()?;
-	let res = loop {
-		let mut events_count = 0;
-		// Set if every bitfields are set to zero
-		let mut all_zeros = true;
+	let timeout = timeout.copy_from_user()?.map(|t| t.to_nano());
+	let readfds_set = readfds.copy_from_user()?;
+	let writefds_set = writefds.copy_from_user()?;
+	let exceptfds_set = exceptfds.copy_from_user()?;
+	// Get the files to poll, along with the events to look for
+	let files = {
+		let fds = fds.lock();
+		let mut files = Vec::new();
 		for fd_id in 0..min(nfds, FD_SETSIZE as u32) {
-			let read = readfds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			let write = writefds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			let except = exceptfds_set
-				.as_ref()
-				.map(|fds| fds.is_set(fd_id))
-				.unwrap_or(false);
-			// Build event mask
+			let is_set = |set: &Option<FDSet>| set.as_ref().is_some_and(|s| s.is_set(fd_id));
 			let mut mask = 0;
-			if read {
-				mask |= POLLIN;
-			}
-			if write {
-				mask |= POLLOUT;
-			}
-			if except {
-				mask |= POLLPRI;
-			}
-			if mask != 0 {
-				all_zeros = false;
+			if is_set(&readfds_set) {
+				mask |= SELECT_READ;
 			}
-			// Poll file
-			let result = {
-				let fds = fds.lock();
-				let Ok(fd) = fds.get_fd(fd_id as _) else {
-					if mask != 0 {
-						return Err(errno!(EBADF));
-					}
-					continue;
-				};
-				let file = fd.get_file();
-				file.ops.poll(file, mask)?
-			};
-			// Set results
-			let read = read && result & POLLIN != 0;
-			let write = write && result & POLLOUT != 0;
-			let except = except && result & POLLPRI != 0;
-			if let Some(fds) = &mut readfds_set {
-				fds.set(fd_id, read);
+			if is_set(&writefds_set) {
+				mask |= SELECT_WRITE;
 			}
-			if let Some(fds) = &mut writefds_set {
-				fds.set(fd_id, write);
+			if is_set(&exceptfds_set) {
+				mask |= SELECT_EXCEPT;
 			}
-			if let Some(fds) = &mut exceptfds_set {
-				fds.set(fd_id, except);
+			if mask == 0 {
+				continue;
 			}
-			events_count += read as usize + write as usize + except as usize;
-		}
-		// If one or more events occurred, return
-		if all_zeros || polling || events_count > 0 {
-			break events_count;
+			let file = fds.get_fd(fd_id as _)?.get_file().clone();
+			files.push((fd_id, mask, file))?;
 		}
-		let ts = current_time_ns(Clock::Monotonic);
-		// On timeout, return 0
-		if ts >= end {
-			break 0;
-		}
-		// TODO Make the process sleep?
-		Scheduler::tick();
+		files
 	};
+	// Results
+	let mut readfds_res = readfds_set.as_ref().map(|_| FDSet::default());
+	let mut writefds_res = writefds_set.as_ref().map(|_| FDSet::default());
+	let mut exceptfds_res = exceptfds_set.as_ref().map(|_| FDSet::default());
+	let res = with_sigmask(sigmask, || {
+		PollTable::wait(timeout, |mut table| {
+			let mut events_count = 0;
+			for (fd_id, mask, file) in &files {
+				let events = file.ops.poll(file, *mask, table.as_deref_mut())? & *mask;
+				for (res, set) in [
+					(&mut readfds_res, SELECT_READ),
+					(&mut writefds_res, SELECT_WRITE),
+					(&mut exceptfds_res, SELECT_EXCEPT),
+				] {
+					let Some(res) = res else {
+						continue;
+					};
+					let val = events & set != 0;
+					res.set(*fd_id, val);
+					events_count += val as usize;
+				}
+			}
+			Ok(events_count)
+		})
+	})?;
 	// Write back
-	if let Some(val) = readfds_set {
+	if let Some(val) = readfds_res {
 		readfds.copy_to_user(&val)?;
 	}
-	if let Some(val) = writefds_set {
+	if let Some(val) = writefds_res {
 		writefds.copy_to_user(&val)?;
 	}
-	if let Some(val) = exceptfds_set {
+	if let Some(val) = exceptfds_res {
 		exceptfds.copy_to_user(&val)?;
 	}
 	Ok(res)
@@ -215,26 +209,76 @@ pub(super) fn _newselect(
 	do_select(fds, nfds as _, readfds, writefds, exceptfds, timeout, None)
 }
 
+/// Reads the signal mask argument of `pselect6`, which is a pointer to a structure containing a
+/// pointer to the signal mask and its size.
+fn read_pselect_sigmask(ptr: usize, compat: bool) -> EResult<Option<SigSet>> {
+	let (sigmask, sigsetsize) = if compat {
+		let Some([sigmask, sigsetsize]) = UserPtr::<[u32; 2]>::from_ptr(ptr).copy_from_user()?
+		else {
+			return Ok(None);
+		};
+		(sigmask as usize, sigsetsize as usize)
+	} else {
+		let Some(arg) = UserPtr::<[usize; 2]>::from_ptr(ptr).copy_from_user()? else {
+			return Ok(None);
+		};
+		arg.into()
+	};
+	read_sigmask(UserPtr::from_ptr(sigmask), sigsetsize)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_pselect6<T: TimeUnit>(
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	nfds: c_int,
+	readfds: UserPtr<FDSet>,
+	writefds: UserPtr<FDSet>,
+	exceptfds: UserPtr<FDSet>,
+	timeout: UserPtr<T>,
+	sigmask: usize,
+	compat: bool,
+) -> EResult<usize> {
+	let sigmask = read_pselect_sigmask(sigmask, compat)?;
+	do_select(
+		fds, nfds as _, readfds, writefds, exceptfds, timeout, sigmask,
+	)
+}
+
 #[allow(clippy::type_complexity)]
 pub(super) fn pselect6(
+	Args((nfds, readfds, writefds, exceptfds, timeout, sigmask)): Args<(
+		c_int,
+		UserPtr<FDSet>,
+		UserPtr<FDSet>,
+		UserPtr<FDSet>,
+		UserPtr<Timespec32>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let compat = frame.is_compat();
+	do_pselect6(
+		fds, nfds, readfds, writefds, exceptfds, timeout, sigmask, compat,
+	)
+}
+
+#[allow(clippy::type_complexity)]
+pub(super) fn pselect6_time64(
 	Args((nfds, readfds, writefds, exceptfds, timeout, sigmask)): Args<(
 		c_int,
 		UserPtr<FDSet>,
 		UserPtr<FDSet>,
 		UserPtr<FDSet>,
 		UserPtr<Timespec>,
-		*mut u8,
+		usize,
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	frame: &mut IntFrame,
 ) -> EResult<usize> {
-	do_select(
-		fds,
-		nfds as _,
-		readfds,
-		writefds,
-		exceptfds,
-		timeout,
-		Some(sigmask),
+	let compat = frame.is_compat();
+	do_pselect6(
+		fds, nfds, readfds, writefds, exceptfds, timeout, sigmask, compat,
 	)
 }
 
@@ -274,58 +318,119 @@ pub struct PollFD {
 	revents: i16,
 }
 
-pub(super) fn poll(
-	Args((fds, nfds, timeout)): Args<(*mut PollFD, usize, c_int)>,
+/// Performs the poll operation.
+///
+/// Arguments:
+/// - `fds` is the process's file descriptors table.
+/// - `ufds` is the list of file descriptors to poll.
+/// - `nfds` is the number of elements in `ufds`.
+/// - `timeout` is the timeout in nanoseconds. If `None`, the syscall waits indefinitely.
+/// - `sigmask` is the signal mask to apply while waiting, if any.
+fn do_poll(
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	ufds: *mut PollFD,
+	nfds: usize,
+	timeout: Option<Timestamp>,
+	sigmask: Option<SigSet>,
 ) -> EResult<usize> {
-	let fds = UserSlice::from_user(fds, nfds)?;
-	// The timeout. `None` means no timeout
-	let to = (timeout >= 0).then_some(timeout as Timestamp);
-	let start_ts = current_time_ms(Clock::Monotonic);
-	loop {
-		// Check whether the system call timed out
-		if let Some(timeout) = to {
-			let now = current_time_ms(Clock::Monotonic);
-			if now >= start_ts + timeout {
-				return Ok(0);
-			}
-		}
-		{
-			let fds_arr = fds.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
-			// Check the file descriptors list
-			for fd in &fds_arr {
-				if fd.events as u32 & POLLIN != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLPRI != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLOUT != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLRDNORM != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLRDBAND != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLWRNORM != 0 {
-					todo!();
-				}
-				if fd.events as u32 & POLLWRBAND != 0 {
-					todo!();
-				}
-			}
-			// The number of file descriptor with at least one event
-			let fd_event_count = fds_arr.iter().filter(|fd| fd.revents != 0).count();
-			// If at least on event happened, return the number of file descriptors
-			// concerned
-			if fd_event_count > 0 {
-				fds.copy_to_user(0, &fds_arr)?;
-				return Ok(fd_event_count as _);
+	let ufds = UserSlice::from_user(ufds, nfds)?;
+	let mut pollfds = match ufds.copy_from_user_vec(0)? {
+		Some(pollfds) => pollfds,
+		None if nfds == 0 => Vec::new(),
+		None => return Err(errno!(EFAULT)),
+	};
+	// Get the files to poll. Negative file descriptors are ignored
+	let files = {
+		let fds = fds.lock();
+		pollfds
+			.iter()
+			.map(|pfd| {
+				(pfd.fd >= 0)
+					.then(|| fds.get_fd(pfd.fd).ok().map(|fd| fd.get_file().clone()))
+					.flatten()
+			})
+			.collect::<CollectResult<Vec<_>>>()
+			.0?
+	};
+	let res = with_sigmask(sigmask, || {
+		PollTable::wait(timeout, |mut table| {
+			let mut count = 0;
+			for (pfd, file) in pollfds.iter_mut().zip(&files) {
+				let revents = match file {
+					Some(file) => {
+						// Errors and hang ups are always reported
+						let mask = pfd.events as u16 as u32 | POLLERR | POLLHUP;
+						file.ops.poll(file, mask, table.as_deref_mut())? & mask
+					}
+					None if pfd.fd < 0 => 0,
+					None => POLLNVAL,
+				};
+				pfd.revents = revents as _;
+				count += (revents != 0) as usize;
 			}
-		}
-		// TODO Make process sleep until an event occurs on a file descriptor in
-		// `fds`
-		Scheduler::tick();
+			Ok(count)
+		})
+	})?;
+	ufds.copy_to_user(0, &pollfds)?;
+	Ok(res)
+}
+
+pub(super) fn poll(
+	Args((ufds, nfds, timeout)): Args<(*mut PollFD, usize, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// A negative timeout means no timeout
+	let timeout = (timeout >= 0).then(|| timeout as Timestamp * 1_000_000);
+	do_poll(fds, ufds, nfds, timeout, None)
+}
+
+#[allow(clippy::type_complexity)]
+pub(super) fn ppoll(
+	Args((ufds, nfds, tmo_p, sigmask, sigsetsize)): Args<(
+		*mut PollFD,
+		usize,
+		UserPtr<Timespec32>,
+		UserPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let timeout = tmo_p.copy_from_user()?.map(|t| t.to_nano());
+	let sigmask = read_sigmask(sigmask, sigsetsize)?;
+	do_poll(fds, ufds, nfds, timeout, sigmask)
+}
+
+#[allow(clippy::type_complexity)]
+pub(super) fn ppoll_time64(
+	Args((ufds, nfds, tmo_p, sigmask, sigsetsize)): Args<(
+		*mut PollFD,
+		usize,
+		UserPtr<Timespec>,
+		UserPtr<SigSet>,
+		usize,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let timeout = tmo_p.copy_from_user()?.map(|t| t.to_nano());
+	let sigmask = read_sigmask(sigmask, sigsetsize)?;
+	do_poll(fds, ufds, nfds, timeout, sigmask)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn fdset() {
+		let mut set = FDSet::default();
+		set.set(0, true);
+		set.set(65, true);
+		assert!(set.is_set(0));
+		assert!(!set.is_set(1));
+		assert!(!set.is_set(64));
+		assert!(set.is_set(65));
+		assert!(!set.is_set(FD_SETSIZE as u32));
+		set.set(65, false);
+		assert!(!set.is_set(65));
 	}
 }
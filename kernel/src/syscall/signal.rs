@@ -20,16 +20,29 @@
 
 use crate::{
 	arch::x86::idt::IntFrame,
-	file::perm::AccessProfile,
+	file,
+	file::{
+		File,
+		fd::{FD_CLOEXEC, FileDescriptorTable},
+		perm::AccessProfile,
+		signalfd::SignalFd,
+	},
 	memory::user::UserPtr,
 	process,
 	process::{
 		Process, State,
+		mem_space::bound_check,
 		pid::Pid,
 		scheduler::SCHEDULER,
-		signal::{CompatSigAction, SigAction, SigSet, Signal, SignalHandler, ucontext},
+		signal,
+		signal::{
+			AltStack, CompatSigAction, MINSIGSTKSZ, SI_TKILL, SI_USER, SIGINFO_SIZE, SS_DISABLE,
+			SS_ONSTACK, SigAction, SigInfo, SigSet, Signal, SignalHandler, ucontext,
+		},
 	},
+	sync::mutex::Mutex,
 	syscall::{Args, FromSyscallArg},
+	time::unit::{TimeUnit, Timespec, Timespec32, Timestamp},
 };
 use core::{
 	ffi::{c_int, c_void},
@@ -73,6 +86,9 @@ fn do_rt_sigaction<S: Debug + From<SigAction> + Into<SigAction>>(
 	oldact.copy_to_user(&old)?;
 	// Set the new structure
 	if let Some(new) = act.copy_from_user()? {
+		if unlikely(!signal.can_catch()) {
+			return Err(errno!(EINVAL));
+		}
 		signal_handlers[signal as usize] = SignalHandler::from(new.into());
 	}
 	Ok(0)
@@ -115,24 +131,113 @@ pub fn rt_sigprocmask(
 	Ok(0)
 }
 
-pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
+pub fn pause() -> EResult<usize> {
+	signal::suspend(None)
+}
+
+pub fn sigsuspend(Args((_, _, mask)): Args<(c_int, c_int, u32)>) -> EResult<usize> {
+	signal::suspend(Some(SigSet(mask as _)))
+}
+
+pub fn rt_sigsuspend(Args((mask, sigsetsize)): Args<(UserPtr<SigSet>, usize)>) -> EResult<usize> {
+	if unlikely(sigsetsize != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	let mask = mask.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	signal::suspend(Some(mask))
+}
+
+/// Returns the set of signals that are pending on the current thread `proc` while blocked.
+fn blocked_pending(proc: &Process) -> SigSet {
+	let signal = proc.signal.lock();
+	SigSet(signal.pending().0 & signal.sigmask.0)
+}
+
+pub fn sigpending(Args(set): Args<UserPtr<u32>>, proc: Arc<Process>) -> EResult<usize> {
+	set.copy_to_user(&(blocked_pending(&proc).0 as u32))?;
+	Ok(0)
+}
+
+pub fn rt_sigpending(
+	Args((set, sigsetsize)): Args<(UserPtr<SigSet>, usize)>,
+	proc: Arc<Process>,
+) -> EResult<usize> {
+	if unlikely(sigsetsize > size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	set.copy_to_user(&blocked_pending(&proc))?;
+	Ok(0)
+}
+
+/// Waits for a signal in `set`, then writes its information to `info` and returns its number.
+///
+/// Arguments:
+/// - `timeout` is the maximum duration to wait for, in nanoseconds. If `None`, the function waits
+///   indefinitely.
+/// - `sigsetsize` is the size of `set` in bytes.
+/// - `compat` tells whether `info` has the layout of 32 bit userspace.
+fn do_sigtimedwait(
+	set: UserPtr<SigSet>,
+	info: UserPtr<[u8; SIGINFO_SIZE]>,
+	timeout: Option<Timestamp>,
+	sigsetsize: usize,
+	compat: bool,
+) -> EResult<usize> {
+	if unlikely(sigsetsize != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	let set = set.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let received = signal::wait_signal(set, timeout)?;
+	info.copy_to_user(&received.to_user(compat))?;
+	Ok(received.si_signo as _)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn rt_sigtimedwait(
+	Args((set, info, timeout, sigsetsize)): Args<(
+		UserPtr<SigSet>,
+		UserPtr<[u8; SIGINFO_SIZE]>,
+		UserPtr<Timespec32>,
+		usize,
+	)>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let timeout = timeout.copy_from_user()?.map(|t| t.to_nano());
+	do_sigtimedwait(set, info, timeout, sigsetsize, frame.is_compat())
+}
+
+#[allow(clippy::type_complexity)]
+pub fn rt_sigtimedwait_time64(
+	Args((set, info, timeout, sigsetsize)): Args<(
+		UserPtr<SigSet>,
+		UserPtr<[u8; SIGINFO_SIZE]>,
+		UserPtr<Timespec>,
+		usize,
+	)>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let timeout = timeout.copy_from_user()?.map(|t| t.to_nano());
+	do_sigtimedwait(set, info, timeout, sigsetsize, frame.is_compat())
+}
+
+/// Restores the state saved in the context at `ctx_addr` before the execution of a signal
+/// handler.
+fn do_sigreturn(ctx_addr: usize, frame: &mut IntFrame) -> EResult<usize> {
 	let proc = Process::current();
-	// Retrieve and restore previous state
-	let stack_ptr = frame.get_stack_address();
 	if frame.is_compat() {
-		let ctx = UserPtr::<ucontext::UContext32>::from_ptr(stack_ptr)
+		let ctx = UserPtr::<ucontext::UContext32>::from_ptr(ctx_addr)
 			.copy_from_user()?
 			.ok_or_else(|| errno!(EFAULT))?;
 		ctx.restore_regs(&proc, frame);
 	} else {
 		#[cfg(target_arch = "x86_64")]
 		{
-			let ctx = UserPtr::<ucontext::UContext64>::from_ptr(stack_ptr)
+			let ctx = UserPtr::<ucontext::UContext64>::from_ptr(ctx_addr)
 				.copy_from_user()?
 				.ok_or_else(|| errno!(EFAULT))?;
 			let res = ctx.restore_regs(&proc, frame);
 			if unlikely(res.is_err()) {
-				proc.kill(Signal::SIGSEGV);
+				proc.kill_fault(SigInfo::new(Signal::SIGSEGV, process::signal::SI_KERNEL));
 			}
 		}
 	}
@@ -140,12 +245,37 @@ pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
 	Ok(frame.get_syscall_id())
 }
 
+pub fn sigreturn(frame: &mut IntFrame) -> EResult<usize> {
+	// The context follows the arguments of the handler, which have been popped
+	do_sigreturn(frame.get_stack_address(), frame)
+}
+
 pub fn rt_sigreturn(frame: &mut IntFrame) -> EResult<usize> {
-	sigreturn(frame)
+	let stack_ptr = frame.get_stack_address();
+	// On 32 bit, the handler's arguments (besides the return pointer, which has been popped) and
+	// the `siginfo_t` structure come before the context
+	let ctx_addr = if frame.is_compat() {
+		stack_ptr + size_of::<u32>() * 3 + SIGINFO_SIZE
+	} else {
+		stack_ptr
+	};
+	do_sigreturn(ctx_addr, frame)
+}
+
+/// Returns the information of the signal `sig`, sent by the current process with the code
+/// `code`.
+fn sender_info(sig: Signal, code: i32, ap: &AccessProfile) -> SigInfo {
+	SigInfo {
+		si_pid: Process::current().get_pid(),
+		si_uid: ap.uid,
+		..SigInfo::new(sig, code)
+	}
 }
 
 /// Tries to kill the process with PID `pid` with the signal `sig`.
 ///
+/// The signal is directed to the whole process, that is to any of its threads.
+///
 /// If `sig` is `None`, the function doesn't send a signal, but still checks if
 /// there is a process that could be killed.
 fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
@@ -153,14 +283,15 @@ fn try_kill(pid: Pid, sig: Option<Signal>) -> EResult<()> {
 	let ap = proc.fs.lock().access_profile;
 	// Closure sending the signal
 	let f = |target: &Process| {
-		if matches!(target.get_state(), State::Zombie) {
+		// A process is dead once all its threads are
+		if matches!(target.get_state(), State::Zombie) && target.links.lock().threads.is_empty() {
 			return Ok(());
 		}
 		if !ap.can_kill(target) {
 			return Err(errno!(EPERM));
 		}
 		if let Some(sig) = sig {
-			target.kill(sig);
+			target.kill_process_info(sender_info(sig, SI_USER, &ap))?;
 		}
 		Ok(())
 	};
@@ -207,8 +338,8 @@ pub fn kill(Args((pid, sig)): Args<(c_int, c_int)>) -> EResult<usize> {
 		// Kill all processes for which the current process has the permission
 		-1 => {
 			let sched = SCHEDULER.lock();
-			for (pid, _) in sched.iter_process() {
-				if *pid == process::pid::INIT_PID {
+			for (pid, proc) in sched.iter_process() {
+				if *pid == process::pid::INIT_PID || !proc.is_thread_leader() {
 					continue;
 				}
 				// TODO Check permission
@@ -230,6 +361,215 @@ pub fn tkill(
 	if !access_profile.can_kill(&thread) {
 		return Err(errno!(EPERM));
 	}
-	thread.kill(signal);
+	thread.kill_info(sender_info(signal, SI_TKILL, &access_profile))?;
 	Ok(0)
 }
+
+pub fn tgkill(
+	Args((tgid, tid, sig)): Args<(Pid, Pid, c_int)>,
+	access_profile: AccessProfile,
+) -> EResult<usize> {
+	let sig = (sig != 0).then(|| Signal::try_from(sig)).transpose()?;
+	let thread = Process::get_by_tid(tid).ok_or(errno!(ESRCH))?;
+	// The thread must belong to the given thread group
+	if thread.get_pid() != tgid {
+		return Err(errno!(ESRCH));
+	}
+	if !access_profile.can_kill(&thread) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(sig) = sig {
+		thread.kill_info(sender_info(sig, SI_TKILL, &access_profile))?;
+	}
+	Ok(0)
+}
+
+/// Reads the information of the signal `sig` to be queued by `rt_sigqueueinfo` or
+/// `rt_tgsigqueueinfo` to the thread group `tgid`.
+///
+/// If `sig` is zero, the function returns `None`.
+fn read_queued_info(
+	tgid: Pid,
+	sig: c_int,
+	info: UserPtr<[u8; SIGINFO_SIZE]>,
+	compat: bool,
+) -> EResult<Option<SigInfo>> {
+	let sig = (sig != 0).then(|| Signal::try_from(sig)).transpose()?;
+	let buf = info.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let Some(sig) = sig else {
+		return Ok(None);
+	};
+	let info = SigInfo::from_user(sig, &buf, compat);
+	// Only the kernel may pretend a signal comes from `kill` or the kernel, except to the
+	// sender itself
+	let forged = info.si_code >= 0 || info.si_code == SI_TKILL;
+	if unlikely(forged && tgid != Process::current().get_pid()) {
+		return Err(errno!(EPERM));
+	}
+	Ok(Some(info))
+}
+
+pub fn rt_sigqueueinfo(
+	Args((tgid, sig, info)): Args<(Pid, c_int, UserPtr<[u8; SIGINFO_SIZE]>)>,
+	access_profile: AccessProfile,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let info = read_queued_info(tgid, sig, info, frame.is_compat())?;
+	let target = Process::get_by_pid(tgid).ok_or_else(|| errno!(ESRCH))?;
+	if !access_profile.can_kill(&target) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(info) = info {
+		target.kill_process_info(info)?;
+	}
+	Ok(0)
+}
+
+pub fn rt_tgsigqueueinfo(
+	Args((tgid, tid, sig, info)): Args<(Pid, Pid, c_int, UserPtr<[u8; SIGINFO_SIZE]>)>,
+	access_profile: AccessProfile,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let info = read_queued_info(tgid, sig, info, frame.is_compat())?;
+	let thread = Process::get_by_tid(tid).ok_or_else(|| errno!(ESRCH))?;
+	// The thread must belong to the given thread group
+	if thread.get_pid() != tgid {
+		return Err(errno!(ESRCH));
+	}
+	if !access_profile.can_kill(&thread) {
+		return Err(errno!(EPERM));
+	}
+	if let Some(info) = info {
+		thread.kill_info(info)?;
+	}
+	Ok(0)
+}
+
+/// Sets the alternate signal stack of the current thread `proc` and returns the previous one.
+///
+/// Arguments:
+/// - `sp` is the current stack pointer of the thread.
+/// - `ss` is the new stack as a tuple `(ss_sp, ss_flags, ss_size)`. If `None`, the stack is left
+///   unchanged.
+///
+/// The previous stack is returned in the same format.
+fn do_sigaltstack(
+	proc: &Process,
+	sp: usize,
+	ss: Option<(usize, i32, usize)>,
+) -> EResult<(usize, i32, usize)> {
+	let mut signal = proc.signal.lock();
+	let old = signal.altstack_info(sp);
+	let Some((ss_sp, ss_flags, ss_size)) = ss else {
+		return Ok(old);
+	};
+	// The stack cannot be changed while in use
+	if unlikely(old.1 == SS_ONSTACK) {
+		return Err(errno!(EPERM));
+	}
+	signal.altstack = match ss_flags {
+		SS_DISABLE => None,
+		0 | SS_ONSTACK => {
+			if unlikely(ss_size < MINSIGSTKSZ) {
+				return Err(errno!(ENOMEM));
+			}
+			if unlikely(!bound_check(ss_sp, ss_size)) {
+				return Err(errno!(EFAULT));
+			}
+			Some(AltStack {
+				sp: ss_sp,
+				size: ss_size,
+			})
+		}
+		_ => return Err(errno!(EINVAL)),
+	};
+	Ok(old)
+}
+
+pub fn sigaltstack(
+	Args((ss, old_ss)): Args<(usize, usize)>,
+	proc: Arc<Process>,
+	frame: &mut IntFrame,
+) -> EResult<usize> {
+	let sp = frame.get_stack_address();
+	if frame.is_compat() {
+		let ss = UserPtr::<ucontext::Stack32>::from_ptr(ss)
+			.copy_from_user()?
+			.map(|ss| (ss.ss_sp as usize, ss.ss_flags, ss.ss_size as usize));
+		let (ss_sp, ss_flags, ss_size) = do_sigaltstack(&proc, sp, ss)?;
+		UserPtr::from_ptr(old_ss).copy_to_user(&ucontext::Stack32 {
+			ss_sp: ss_sp as _,
+			ss_flags,
+			ss_size: ss_size as _,
+		})?;
+	} else {
+		#[cfg(target_arch = "x86_64")]
+		{
+			let ss = UserPtr::<ucontext::Stack64>::from_ptr(ss)
+				.copy_from_user()?
+				.map(|ss| (ss.ss_sp as usize, ss.ss_flags, ss.ss_size));
+			let (ss_sp, ss_flags, ss_size) = do_sigaltstack(&proc, sp, ss)?;
+			UserPtr::from_ptr(old_ss).copy_to_user(&ucontext::Stack64 {
+				ss_sp: ss_sp as _,
+				ss_flags,
+				ss_size,
+			})?;
+		}
+	}
+	Ok(0)
+}
+
+/// Creates a signalfd or, if `fd` is not `-1`, changes the mask of the signalfd `fd`.
+///
+/// Arguments:
+/// - `fds` is the process's file descriptors table.
+/// - `fd` is the file descriptor of the signalfd to modify, or `-1`.
+/// - `mask` is the set of signals to accept.
+/// - `sizemask` is the size of `mask` in bytes.
+/// - `flags` is the set of flags to create the file with.
+fn do_signalfd(
+	fds: Arc<Mutex<FileDescriptorTable>>,
+	fd: c_int,
+	mask: UserPtr<SigSet>,
+	sizemask: usize,
+	flags: c_int,
+) -> EResult<usize> {
+	if unlikely(flags & !(file::O_CLOEXEC | file::O_NONBLOCK) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	if unlikely(sizemask != size_of::<SigSet>()) {
+		return Err(errno!(EINVAL));
+	}
+	let mask = mask.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if fd != -1 {
+		let file = fds.lock().get_fd(fd)?.get_file().clone();
+		let signalfd = file
+			.get_buffer::<SignalFd>()
+			.ok_or_else(|| errno!(EINVAL))?;
+		signalfd.set_mask(mask);
+		return Ok(fd as _);
+	}
+	let signalfd = Arc::new(SignalFd::new(mask))?;
+	let file = File::open_floating(signalfd, file::O_RDWR | (flags & file::O_NONBLOCK))?;
+	let fd_flags = if flags & file::O_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
+
+pub fn signalfd(
+	Args((fd, mask, sizemask)): Args<(c_int, UserPtr<SigSet>, usize)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_signalfd(fds, fd, mask, sizemask, 0)
+}
+
+pub fn signalfd4(
+	Args((fd, mask, sizemask, flags)): Args<(c_int, UserPtr<SigSet>, usize, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	do_signalfd(fds, fd, mask, sizemask, flags)
+}
@@ -19,15 +19,41 @@
 //! Socket interface system calls.
 
 use crate::{
+	arch::x86::idt::IntFrame,
 	file,
-	file::{File, fd::FileDescriptorTable, perm::AccessProfile, socket::Socket},
-	memory::user::{UserPtr, UserSlice},
-	net::{SocketDesc, SocketDomain, SocketType},
+	file::{
+		File, O_NONBLOCK,
+		fd::{FD_CLOEXEC, FileDescriptorTable},
+		perm::AccessProfile,
+		socket::{MSG_TRUNC, SOL_SOCKET, Socket},
+	},
+	memory::user::{UserIOVec, UserPtr, UserSlice},
+	net::{SocketDesc, SocketDomain, SocketType, netlink, unix},
 	sync::mutex::Mutex,
-	syscall::Args,
+	syscall::{Args, FromSyscallArg},
 };
-use core::{cmp::min, ffi::c_int, hint::unlikely};
-use utils::{errno, errno::EResult, ptr::arc::Arc};
+use core::{cmp::min, ffi::c_int, hint::unlikely, mem::size_of, ptr};
+use utils::{collections::vec::Vec, errno, errno::EResult, limits::IOV_MAX, ptr::arc::Arc};
+
+/// Socket flag: set the `O_NONBLOCK` flag on the new open file description.
+const SOCK_NONBLOCK: c_int = file::O_NONBLOCK;
+/// Socket flag: set the `FD_CLOEXEC` flag on the new file descriptor.
+const SOCK_CLOEXEC: c_int = file::O_CLOEXEC;
+
+/// Message flag: control data was truncated.
+const MSG_CTRUNC: c_int = 0x8;
+/// Message flag: do not block.
+const MSG_DONTWAIT: c_int = 0x40;
+/// Message flag: set the close-on-exec flag on file descriptors received with `SCM_RIGHTS`.
+const MSG_CMSG_CLOEXEC: c_int = 0x40000000;
+
+/// Control message type: passing file descriptors.
+const SCM_RIGHTS: c_int = 1;
+
+/// The maximum size of the data of a message passed to `sendmsg` or `recvmsg`.
+const MSG_MAX: usize = 65536;
+/// The maximum size of the ancillary data passed to `sendmsg`.
+const CONTROL_MAX: usize = 20480;
 
 /// Shutdown receive side of the connection.
 const SHUT_RD: c_int = 0;
@@ -36,13 +62,232 @@ const SHUT_WR: c_int = 1;
 /// Both sides are shutdown.
 const SHUT_RDWR: c_int = 2;
 
+/// Returns the open file description flags and the file descriptor flags for a new socket, from
+/// the `SOCK_*` flags in `flags`.
+fn get_flags(flags: c_int) -> (i32, i32) {
+	let file_flags = file::O_RDWR | (flags & SOCK_NONBLOCK);
+	let fd_flags = if flags & SOCK_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	(file_flags, fd_flags)
+}
+
+/// Writes the socket address `name` to the userspace buffer `addr`, truncating it if it is
+/// larger than the size pointed to by `addrlen`.
+///
+/// The actual size of the address is written back to `addrlen`.
+fn write_sockaddr(name: &[u8], addr: *mut u8, addrlen: UserPtr<u32>) -> EResult<()> {
+	let addrlen_val = addrlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely((addrlen_val as i32) < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let len = min(name.len(), addrlen_val as _);
+	let addr = UserSlice::from_user(addr, len)?;
+	addr.copy_to_user(0, &name[..len])?;
+	addrlen.copy_to_user(&(name.len() as _))?;
+	Ok(())
+}
+
+/// Message header for `sendmsg` and `recvmsg`.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct MsgHdr {
+	/// Optional address.
+	msg_name: *mut u8,
+	/// The size of the address.
+	msg_namelen: u32,
+	/// Scatter/gather array.
+	msg_iov: *mut u8,
+	/// The number of elements in `msg_iov`.
+	msg_iovlen: usize,
+	/// Ancillary data.
+	msg_control: *mut u8,
+	/// The length of the ancillary data.
+	msg_controllen: usize,
+	/// Flags on received message.
+	msg_flags: c_int,
+}
+
+/// A [`MsgHdr`] for compatibility mode.
+#[repr(C)]
+#[derive(Clone, Debug)]
+struct MsgHdrCompat {
+	msg_name: u32,
+	msg_namelen: u32,
+	msg_iov: u32,
+	msg_iovlen: u32,
+	msg_control: u32,
+	msg_controllen: u32,
+	msg_flags: c_int,
+}
+
+/// A [`MsgHdr`] as a system call argument.
+#[derive(Debug)]
+pub struct UserMsgHdr {
+	/// The pointer to the structure.
+	ptr: usize,
+	/// Tells whether the userspace is in compatibility mode.
+	compat: bool,
+}
+
+impl FromSyscallArg for UserMsgHdr {
+	fn from_syscall_arg(ptr: usize, compat: bool) -> Self {
+		Self {
+			ptr,
+			compat,
+		}
+	}
+}
+
+impl UserMsgHdr {
+	/// Reads the structure from userspace.
+	fn read(&self) -> EResult<MsgHdr> {
+		if self.compat {
+			let hdr = UserPtr::<MsgHdrCompat>::from_syscall_arg(self.ptr, true)
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))?;
+			Ok(MsgHdr {
+				msg_name: ptr::with_exposed_provenance_mut(hdr.msg_name as _),
+				msg_namelen: hdr.msg_namelen,
+				msg_iov: ptr::with_exposed_provenance_mut(hdr.msg_iov as _),
+				msg_iovlen: hdr.msg_iovlen as _,
+				msg_control: ptr::with_exposed_provenance_mut(hdr.msg_control as _),
+				msg_controllen: hdr.msg_controllen as _,
+				msg_flags: hdr.msg_flags,
+			})
+		} else {
+			UserPtr::<MsgHdr>::from_syscall_arg(self.ptr, false)
+				.copy_from_user()?
+				.ok_or_else(|| errno!(EFAULT))
+		}
+	}
+
+	/// Writes the structure back to userspace.
+	fn write(&self, hdr: &MsgHdr) -> EResult<()> {
+		if self.compat {
+			UserPtr::<MsgHdrCompat>::from_syscall_arg(self.ptr, true).copy_to_user(&MsgHdrCompat {
+				msg_name: hdr.msg_name.expose_provenance() as _,
+				msg_namelen: hdr.msg_namelen,
+				msg_iov: hdr.msg_iov.expose_provenance() as _,
+				msg_iovlen: hdr.msg_iovlen as _,
+				msg_control: hdr.msg_control.expose_provenance() as _,
+				msg_controllen: hdr.msg_controllen as _,
+				msg_flags: hdr.msg_flags,
+			})
+		} else {
+			UserPtr::<MsgHdr>::from_syscall_arg(self.ptr, false).copy_to_user(hdr)
+		}
+	}
+}
+
+/// Returns the size of the header of a control message, and the alignment of control messages.
+///
+/// `compat` tells whether the userspace is in compatibility mode.
+fn cmsg_layout(compat: bool) -> (usize, usize) {
+	let len_size = if compat { 4 } else { size_of::<usize>() };
+	(len_size + 2 * size_of::<c_int>(), len_size)
+}
+
+/// Parses the control messages in `control` and returns the files passed with `SCM_RIGHTS`.
+///
+/// `compat` tells whether the userspace is in compatibility mode.
+fn parse_cmsgs(
+	control: &[u8],
+	compat: bool,
+	fds: &FileDescriptorTable,
+) -> EResult<Vec<Arc<File>>> {
+	let (hdr_len, align) = cmsg_layout(compat);
+	let len_size = align;
+	let mut rights = Vec::new();
+	let mut off = 0;
+	while let Some(cmsg) = control.get(off..).filter(|c| c.len() >= hdr_len) {
+		let mut len = [0u8; size_of::<usize>()];
+		len[..len_size].copy_from_slice(&cmsg[..len_size]);
+		let len = usize::from_le_bytes(len);
+		let level = c_int::from_ne_bytes(cmsg[len_size..(len_size + 4)].try_into().unwrap());
+		let type_ = c_int::from_ne_bytes(cmsg[(len_size + 4)..hdr_len].try_into().unwrap());
+		if unlikely(len < hdr_len || len > cmsg.len()) {
+			return Err(errno!(EINVAL));
+		}
+		match (level, type_) {
+			(SOL_SOCKET, SCM_RIGHTS) => {
+				for fd in cmsg[hdr_len..len].chunks_exact(size_of::<c_int>()) {
+					if unlikely(rights.len() >= unix::SCM_MAX_FD) {
+						return Err(errno!(EINVAL));
+					}
+					let fd = c_int::from_ne_bytes(fd.try_into().unwrap());
+					rights.push(fds.get_fd(fd)?.get_file().clone())?;
+				}
+			}
+			_ => return Err(errno!(EINVAL)),
+		}
+		off += len.next_multiple_of(align);
+	}
+	Ok(rights)
+}
+
+/// Installs the received files `rights` in the file descriptor table and writes the
+/// corresponding `SCM_RIGHTS` control message to the buffer described by `hdr`.
+///
+/// Files which do not fit in the buffer are closed, and `MSG_CTRUNC` is set.
+///
+/// Arguments:
+/// - `flags` are the flags passed to `recvmsg`.
+/// - `compat` tells whether the userspace is in compatibility mode.
+fn write_rights(
+	hdr: &mut MsgHdr,
+	rights: Vec<Arc<File>>,
+	flags: c_int,
+	compat: bool,
+	fds: &Mutex<FileDescriptorTable>,
+) -> EResult<()> {
+	let (hdr_len, align) = cmsg_layout(compat);
+	let capacity = if hdr.msg_control.is_null() {
+		0
+	} else {
+		hdr.msg_controllen.saturating_sub(hdr_len) / size_of::<c_int>()
+	};
+	let count = min(rights.len(), capacity);
+	if count < rights.len() {
+		hdr.msg_flags |= MSG_CTRUNC;
+	}
+	if count == 0 {
+		hdr.msg_controllen = 0;
+		return Ok(());
+	}
+	let fd_flags = if flags & MSG_CMSG_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let len = hdr_len + count * size_of::<c_int>();
+	let mut buf = Vec::new();
+	buf.resize(len, 0)?;
+	buf[..align].copy_from_slice(&len.to_le_bytes()[..align]);
+	buf[align..(align + 4)].copy_from_slice(&SOL_SOCKET.to_ne_bytes());
+	buf[(align + 4)..hdr_len].copy_from_slice(&SCM_RIGHTS.to_ne_bytes());
+	{
+		let mut fds = fds.lock();
+		for (i, file) in rights.into_iter().take(count).enumerate() {
+			let (id, _) = fds.create_fd(fd_flags, file)?;
+			let off = hdr_len + i * size_of::<c_int>();
+			buf[off..(off + size_of::<c_int>())].copy_from_slice(&(id as c_int).to_ne_bytes());
+		}
+	}
+	UserSlice::from_user(hdr.msg_control, len)?.copy_to_user(0, &buf)?;
+	hdr.msg_controllen = min(len.next_multiple_of(align), hdr.msg_controllen);
+	Ok(())
+}
+
 pub fn socket(
 	Args((domain, r#type, protocol)): Args<(c_int, c_int, c_int)>,
 	ap: AccessProfile,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let sock_type = SocketType::try_from((r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) as u32)?;
 	// Check permissions
 	if !ap.can_use_sock_domain(&sock_domain) || !ap.can_use_sock_type(&sock_type) {
 		return Err(errno!(EACCES));
@@ -52,10 +297,14 @@ pub fn socket(
 		type_: sock_type,
 		protocol,
 	};
+	if sock_domain == SocketDomain::AfNetlink {
+		netlink::check_desc(&desc)?;
+	}
+	let (file_flags, fd_flags) = get_flags(r#type);
 	// Create socket
 	let sock = Arc::new(Socket::new(desc)?)?;
-	let file = File::open_floating(sock, file::O_RDWR)?;
-	let (sock_fd_id, _) = fds.lock().create_fd(0, file)?;
+	let file = File::open_floating(sock, file_flags)?;
+	let (sock_fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
 	Ok(sock_fd_id as _)
 }
 
@@ -65,7 +314,7 @@ pub fn socketpair(
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	let sock_domain = SocketDomain::try_from(domain as u32)?;
-	let sock_type = SocketType::try_from(r#type as u32)?;
+	let sock_type = SocketType::try_from((r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) as u32)?;
 	// Check permissions
 	if !ap.can_use_sock_domain(&sock_domain) || !ap.can_use_sock_type(&sock_type) {
 		return Err(errno!(EACCES));
@@ -75,10 +324,13 @@ pub fn socketpair(
 		type_: sock_type,
 		protocol,
 	};
-	// Create socket
-	let sock = Arc::new(Socket::new(desc)?)?;
-	let file0 = File::open_floating(sock.clone(), file::O_RDWR)?;
-	let file1 = File::open_floating(sock, file::O_RDWR)?;
+	let (file_flags, _) = get_flags(r#type);
+	// Create sockets
+	let sock0 = Arc::new(Socket::new(desc)?)?;
+	let sock1 = Arc::new(Socket::new(desc)?)?;
+	Socket::pair(&sock0, &sock1)?;
+	let file0 = File::open_floating(sock0, file_flags)?;
+	let file1 = File::open_floating(sock1, file_flags)?;
 	// Create file descriptors
 	let (fd0_id, fd1_id) = fds.lock().create_fd_pair(file0, file1)?;
 	sv.copy_to_user(&[fd0_id as _, fd1_id as _])?;
@@ -86,43 +338,60 @@ pub fn socketpair(
 }
 
 pub fn getsockname(
-	Args((sockfd, addr, addrlen)): Args<(c_int, *mut u8, UserPtr<isize>)>,
+	Args((sockfd, addr, addrlen)): Args<(c_int, *mut u8, UserPtr<u32>)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	// Read and check buffer length
-	let addrlen_val = addrlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
-	if addrlen_val < 0 {
-		return Err(errno!(EINVAL));
-	}
 	let name = sock.get_sockname().lock();
-	let len = min(name.len(), addrlen_val as _);
-	let addr = UserSlice::from_user(addr, len)?;
-	addr.copy_to_user(0, &name[..len])?;
-	addrlen.copy_to_user(&(len as _))?;
+	write_sockaddr(&name, addr, addrlen)?;
+	Ok(0)
+}
+
+pub fn getpeername(
+	Args((sockfd, addr, addrlen)): Args<(c_int, *mut u8, UserPtr<u32>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let name = sock.get_peername()?;
+	write_sockaddr(&name, addr, addrlen)?;
 	Ok(0)
 }
 
 pub fn getsockopt(
-	Args((sockfd, level, optname, optval, optlen)): Args<(c_int, c_int, c_int, *mut u8, usize)>,
+	Args((sockfd, level, optname, optval, optlen)): Args<(
+		c_int,
+		c_int,
+		c_int,
+		*mut u8,
+		UserPtr<c_int>,
+	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	frame: &mut IntFrame,
 ) -> EResult<usize> {
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	let val = sock.get_opt(level, optname)?;
+	let max_len = optlen.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	if unlikely(max_len < 0) {
+		return Err(errno!(EINVAL));
+	}
+	let val = sock.get_opt(level, optname, frame.is_compat())?;
 	// Write
-	let len = min(val.len(), optlen);
-	let optval = UserSlice::from_user(optval, optlen)?;
+	let len = min(val.len(), max_len as usize);
+	let optval = UserSlice::from_user(optval, len)?;
 	optval.copy_to_user(0, &val[..len])?;
-	Ok(len as _)
+	optlen.copy_to_user(&(len as _))?;
+	Ok(0)
 }
 
 pub fn setsockopt(
 	Args((sockfd, level, optname, optval, optlen)): Args<(c_int, c_int, c_int, *mut u8, usize)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
+	frame: &mut IntFrame,
 ) -> EResult<usize> {
 	let optval = UserSlice::from_user(optval, optlen)?;
 	// Get socket
@@ -130,7 +399,8 @@ pub fn setsockopt(
 	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
 	// Set opt
 	let optval = optval.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	sock.set_opt(level, optname, &optval).map(|opt| opt as _)
+	sock.set_opt(level, optname, &optval, frame.is_compat())?;
+	Ok(0)
 }
 
 pub fn connect(
@@ -143,11 +413,53 @@ pub fn connect(
 	}
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let sock: Arc<Socket> = file.get_buffer_arc().ok_or_else(|| errno!(ENOTSOCK))?;
 	let addr = UserSlice::from_user(addr, addrlen as _)?;
-	let _addr = addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
-	// TODO connect socket
-	todo!()
+	let addr = addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0;
+	Socket::connect(&sock, &addr, nonblock)?;
+	Ok(0)
+}
+
+pub fn listen(
+	Args((sockfd, backlog)): Args<(c_int, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: Arc<Socket> = file.get_buffer_arc().ok_or_else(|| errno!(ENOTSOCK))?;
+	Socket::listen(&sock, backlog.max(0) as _)?;
+	Ok(0)
+}
+
+pub fn accept(
+	Args((sockfd, addr, addrlen)): Args<(c_int, *mut u8, UserPtr<u32>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	accept4(Args((sockfd, addr, addrlen, 0)), fds)
+}
+
+pub fn accept4(
+	Args((sockfd, addr, addrlen, flags)): Args<(c_int, *mut u8, UserPtr<u32>, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Validation
+	if unlikely(flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0;
+	let new_sock = sock.accept(nonblock)?;
+	if !addr.is_null() {
+		let name = new_sock.get_peername().unwrap_or_default();
+		write_sockaddr(&name, addr, addrlen)?;
+	}
+	let (file_flags, fd_flags) = get_flags(flags);
+	let new_file = File::open_floating(new_sock, file_flags)?;
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, new_file)?;
+	Ok(fd_id as _)
 }
 
 pub fn bind(
@@ -160,17 +472,17 @@ pub fn bind(
 	}
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
-	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let sock: Arc<Socket> = file.get_buffer_arc().ok_or_else(|| errno!(ENOTSOCK))?;
 	let addr = UserSlice::from_user(addr, addrlen as _)?;
 	let addr = addr.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?;
-	sock.bind(&addr)?;
+	Socket::bind(&sock, &addr)?;
 	Ok(0)
 }
 
-// TODO implement flags
+// TODO implement other flags
 #[allow(clippy::type_complexity)]
 pub fn sendto(
-	Args((sockfd, buf, len, _flags, dest_addr, addrlen)): Args<(
+	Args((sockfd, buf, len, flags, dest_addr, addrlen)): Args<(
 		c_int,
 		*mut u8,
 		usize,
@@ -180,19 +492,158 @@ pub fn sendto(
 	)>,
 	fds: Arc<Mutex<FileDescriptorTable>>,
 ) -> EResult<usize> {
-	let buf = UserSlice::from_user(buf, len)?;
-	let dest_addr = UserSlice::from_user(dest_addr, addrlen as _)?;
 	// Validation
 	if unlikely(addrlen < 0) {
 		return Err(errno!(EINVAL));
 	}
 	// Get socket
 	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
-	let _sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
-	// Get slices
-	let _buf_slice = buf.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	let _dest_addr_slice = dest_addr.copy_from_user_vec(0)?.ok_or(errno!(EFAULT))?;
-	todo!()
+	let sock: Arc<Socket> = file.get_buffer_arc().ok_or_else(|| errno!(ENOTSOCK))?;
+	let buf = UserSlice::from_user(buf, len)?;
+	let dest_addr = if !dest_addr.is_null() {
+		let dest_addr = UserSlice::from_user(dest_addr, addrlen as _)?;
+		Some(
+			dest_addr
+				.copy_from_user_vec(0)?
+				.ok_or_else(|| errno!(EFAULT))?,
+		)
+	} else {
+		None
+	};
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	Socket::send_msg(
+		&sock,
+		buf,
+		dest_addr.as_deref(),
+		Vec::new(),
+		flags,
+		nonblock,
+	)
+}
+
+// TODO implement other flags
+#[allow(clippy::type_complexity)]
+pub fn recvfrom(
+	Args((sockfd, buf, len, flags, src_addr, addrlen)): Args<(
+		c_int,
+		*mut u8,
+		usize,
+		c_int,
+		*mut u8,
+		UserPtr<u32>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let buf = UserSlice::from_user(buf, len)?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	// Files received along with the data are discarded
+	let msg = sock.recv_msg(buf, flags, nonblock)?;
+	if let Some(src) = msg.src {
+		if !src_addr.is_null() {
+			write_sockaddr(&src, src_addr, addrlen)?;
+		}
+	}
+	Ok(msg.len)
+}
+
+pub fn sendmsg(
+	Args((sockfd, msg, flags)): Args<(c_int, UserMsgHdr, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: Arc<Socket> = file.get_buffer_arc().ok_or_else(|| errno!(ENOTSOCK))?;
+	let hdr = msg.read()?;
+	if unlikely(hdr.msg_iovlen > IOV_MAX) {
+		return Err(errno!(EMSGSIZE));
+	}
+	// Destination address
+	let dst = if !hdr.msg_name.is_null() {
+		let name = UserSlice::from_user(hdr.msg_name, hdr.msg_namelen as _)?;
+		Some(name.copy_from_user_vec(0)?.ok_or_else(|| errno!(EFAULT))?)
+	} else {
+		None
+	};
+	// Ancillary data
+	let rights = if !hdr.msg_control.is_null() && hdr.msg_controllen > 0 {
+		if unlikely(hdr.msg_controllen > CONTROL_MAX) {
+			return Err(errno!(ENOBUFS));
+		}
+		let control = UserSlice::from_user(hdr.msg_control, hdr.msg_controllen)?;
+		let control = control
+			.copy_from_user_vec(0)?
+			.ok_or_else(|| errno!(EFAULT))?;
+		parse_cmsgs(&control, msg.compat, &fds.lock())?
+	} else {
+		Vec::new()
+	};
+	// Gather data. Stream sockets may send only part of the data
+	let stream = sock.desc().type_.is_stream();
+	let iov = UserIOVec::from_syscall_arg(hdr.msg_iov.expose_provenance(), msg.compat);
+	let mut data = Vec::new();
+	for i in iov.iter(hdr.msg_iovlen) {
+		let i = i?;
+		let remain = MSG_MAX - data.len();
+		if unlikely(i.iov_len > remain && !stream) {
+			return Err(errno!(EMSGSIZE));
+		}
+		let len = min(i.iov_len, remain);
+		let off = data.len();
+		data.resize(off + len, 0)?;
+		UserSlice::from_user(i.iov_base, len)?.copy_from_user(0, &mut data[off..])?;
+	}
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	let buf = unsafe { UserSlice::from_slice(&data) };
+	Socket::send_msg(&sock, buf, dst.as_deref(), rights, flags, nonblock)
+}
+
+pub fn recvmsg(
+	Args((sockfd, msg, flags)): Args<(c_int, UserMsgHdr, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	// Get socket
+	let file = fds.lock().get_fd(sockfd)?.get_file().clone();
+	let sock: &Socket = file.get_buffer().ok_or_else(|| errno!(ENOTSOCK))?;
+	let mut hdr = msg.read()?;
+	if unlikely(hdr.msg_iovlen > IOV_MAX) {
+		return Err(errno!(EMSGSIZE));
+	}
+	let iov = UserIOVec::from_syscall_arg(hdr.msg_iov.expose_provenance(), msg.compat);
+	let mut total = 0usize;
+	for i in iov.iter(hdr.msg_iovlen) {
+		total = total.saturating_add(i?.iov_len);
+	}
+	let mut data = Vec::new();
+	data.resize(min(total, MSG_MAX), 0)?;
+	let nonblock = file.get_flags() & O_NONBLOCK != 0 || flags & MSG_DONTWAIT != 0;
+	let m = sock.recv_msg(UserSlice::from_slice_mut(&mut data), flags, nonblock)?;
+	// Scatter data
+	let copied = min(m.len, data.len());
+	let mut off = 0;
+	for i in iov.iter(hdr.msg_iovlen) {
+		if off >= copied {
+			break;
+		}
+		let i = i?;
+		let len = min(i.iov_len, copied - off);
+		UserSlice::from_user(i.iov_base, len)?.copy_to_user(0, &data[off..(off + len)])?;
+		off += len;
+	}
+	hdr.msg_flags = if m.truncated { MSG_TRUNC } else { 0 };
+	// Source address
+	if !hdr.msg_name.is_null() {
+		let src = m.src.unwrap_or_default();
+		let len = min(src.len(), hdr.msg_namelen as usize);
+		UserSlice::from_user(hdr.msg_name, len)?.copy_to_user(0, &src[..len])?;
+		hdr.msg_namelen = src.len() as _;
+	}
+	// Ancillary data
+	write_rights(&mut hdr, m.rights, flags, msg.compat, &fds)?;
+	msg.write(&hdr)?;
+	Ok(m.len)
 }
 
 pub fn shutdown(
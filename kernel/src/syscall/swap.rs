@@ -0,0 +1,61 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Swap areas system calls.
+
+use crate::{
+	file::{File, O_RDWR, vfs, vfs::ResolutionSettings},
+	memory::{swap, user::UserString},
+	syscall::Args,
+};
+use core::ffi::c_int;
+use utils::{collections::path::PathBuf, errno, errno::EResult};
+
+/// Tells the priority of the area is specified in the flags.
+const SWAP_FLAG_PREFER: c_int = 0x8000;
+/// Mask of the priority in the flags.
+const SWAP_FLAG_PRIO_MASK: c_int = 0x7fff;
+
+pub fn swapon(
+	Args((path, swapflags)): Args<(UserString, c_int)>,
+	rs: ResolutionSettings,
+) -> EResult<usize> {
+	if !rs.access_profile.is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	let path = path.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let path = PathBuf::try_from(path)?;
+	let ent = vfs::get_file_from_path(&path, &rs)?;
+	let file = File::open_entry(ent, O_RDWR)?;
+	// TODO handle discard flags
+	let prio =
+		(swapflags & SWAP_FLAG_PREFER != 0).then_some((swapflags & SWAP_FLAG_PRIO_MASK) as _);
+	swap::swapon(file, prio)?;
+	Ok(0)
+}
+
+pub fn swapoff(Args(path): Args<UserString>, rs: ResolutionSettings) -> EResult<usize> {
+	if !rs.access_profile.is_privileged() {
+		return Err(errno!(EPERM));
+	}
+	let path = path.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let path = PathBuf::try_from(path)?;
+	let ent = vfs::get_file_from_path(&path, &rs)?;
+	swap::swapoff(ent.node())?;
+	Ok(0)
+}
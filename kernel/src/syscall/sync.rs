@@ -96,8 +96,9 @@ pub fn msync(
 		return Err(errno!(EINVAL));
 	}
 	let sync = flags & MS_SYNC != 0;
+	// Shared mappings use the page cache directly, so there is no other copy to invalidate
+	let invalidate = flags & MS_INVALIDATE != 0;
 	let pages = length.div_ceil(PAGE_SIZE);
-	// TODO MS_INVALIDATE
-	mem_space.sync(addr, pages, sync)?;
+	mem_space.sync(addr, pages, sync, invalidate)?;
 	Ok(0)
 }
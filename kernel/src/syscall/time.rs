@@ -20,24 +20,43 @@
 //! the UNIX Epoch.
 
 use crate::{
+	file,
+	file::{
+		File,
+		fd::{FD_CLOEXEC, FileDescriptorTable},
+		timerfd::TimerFd,
+	},
 	memory::user::UserPtr,
 	process::{
 		Process,
 		signal::{SIGEV_SIGNAL, SigEvent, Signal},
 	},
+	sync::mutex::Mutex,
 	syscall::Args,
 	time::{
 		clock::{Clock, current_time_ns, current_time_sec},
 		sleep_for,
-		unit::{ClockIdT, ITimerspec32, TimeUnit, TimerT, Timespec, Timespec32},
+		timer::ITIMER_REAL,
+		unit::{
+			ClockIdT, ITimerspec, ITimerspec32, ITimerval, ITimerval32, TimeUnit, TimerT,
+			Timespec, Timespec32, Timestamp, Timeval, Timeval32,
+		},
 	},
 };
-use core::ffi::c_int;
+use core::{
+	ffi::{c_int, c_uint},
+	hint::unlikely,
+};
 use utils::{errno, errno::EResult, ptr::arc::Arc};
 
 /// If set, the specified time is *not* relative to the timer's current counter.
 const TIMER_ABSTIME: c_int = 1;
 
+/// timerfd flag: the value of the timer is an absolute time.
+const TFD_TIMER_ABSTIME: c_int = 1;
+/// timerfd flag: reading fails with `ECANCELED` if the real time clock is changed.
+const TFD_TIMER_CANCEL_ON_SET: c_int = 2;
+
 pub fn time32(Args(tloc): Args<UserPtr<u32>>) -> EResult<usize> {
 	let time = current_time_sec(Clock::Monotonic);
 	let time: u32 = time.try_into().map_err(|_| errno!(EOVERFLOW))?;
@@ -115,7 +134,7 @@ pub fn timer_create(
 		sigev_value: timerid_val,
 		sigev_notify_function: None,
 		sigev_notify_attributes: None,
-		sigev_notify_thread_id: proc.tid,
+		sigev_notify_thread_id: proc.get_tid(),
 	});
 	let id = proc.timer_manager.lock().create_timer(clock, sevp_val)?;
 	timerid.copy_to_user(&(id as _))?;
@@ -155,3 +174,216 @@ pub fn timer_settime(
 	)?;
 	Ok(0)
 }
+
+pub fn timerfd_create(
+	Args((clockid, flags)): Args<(ClockIdT, c_int)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let clock = match Clock::from_id(clockid) {
+		Some(
+			clock @ (Clock::Realtime
+			| Clock::Monotonic
+			| Clock::Boottime
+			| Clock::RealtimeAlarm
+			| Clock::BoottimeAlarm),
+		) => clock,
+		_ => return Err(errno!(EINVAL)),
+	};
+	if unlikely(flags & !(file::O_CLOEXEC | file::O_NONBLOCK) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	let timerfd = Arc::new(TimerFd::new(clock)?)?;
+	let file = File::open_floating(timerfd, file::O_RDWR | (flags & file::O_NONBLOCK))?;
+	let fd_flags = if flags & file::O_CLOEXEC != 0 {
+		FD_CLOEXEC
+	} else {
+		0
+	};
+	let (fd_id, _) = fds.lock().create_fd(fd_flags, file)?;
+	Ok(fd_id as _)
+}
+
+/// Returns the timerfd open with the file descriptor `fd`.
+fn get_timerfd(fds: &Mutex<FileDescriptorTable>, fd: c_int) -> EResult<Arc<TimerFd>> {
+	fds.lock()
+		.get_fd(fd)?
+		.get_file()
+		.get_buffer_arc::<TimerFd>()
+		.ok_or_else(|| errno!(EINVAL))
+}
+
+/// Sets the timer of the timerfd `fd`, returning the previous interval and value, in
+/// nanoseconds.
+fn do_timerfd_settime(
+	fds: &Mutex<FileDescriptorTable>,
+	fd: c_int,
+	flags: c_int,
+	interval: Timestamp,
+	value: Timestamp,
+) -> EResult<(Timestamp, Timestamp)> {
+	if unlikely(flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0) {
+		return Err(errno!(EINVAL));
+	}
+	// TODO handle TFD_TIMER_CANCEL_ON_SET
+	let timerfd = get_timerfd(fds, fd)?;
+	Ok(timerfd.set_time(interval, value, flags & TFD_TIMER_ABSTIME != 0)?)
+}
+
+pub fn timerfd_settime(
+	Args((fd, flags, new_value, old_value)): Args<(
+		c_int,
+		c_int,
+		UserPtr<ITimerspec32>,
+		UserPtr<ITimerspec32>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let (interval, value) = do_timerfd_settime(
+		&fds,
+		fd,
+		flags,
+		new_value.it_interval.to_nano(),
+		new_value.it_value.to_nano(),
+	)?;
+	old_value.copy_to_user(&ITimerspec32 {
+		it_interval: Timespec32::from_nano(interval),
+		it_value: Timespec32::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn timerfd_settime64(
+	Args((fd, flags, new_value, old_value)): Args<(
+		c_int,
+		c_int,
+		UserPtr<ITimerspec>,
+		UserPtr<ITimerspec>,
+	)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let new_value = new_value.copy_from_user()?.ok_or_else(|| errno!(EFAULT))?;
+	let (interval, value) = do_timerfd_settime(
+		&fds,
+		fd,
+		flags,
+		new_value.it_interval.to_nano(),
+		new_value.it_value.to_nano(),
+	)?;
+	old_value.copy_to_user(&ITimerspec {
+		it_interval: Timespec::from_nano(interval),
+		it_value: Timespec::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn timerfd_gettime(
+	Args((fd, curr_value)): Args<(c_int, UserPtr<ITimerspec32>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let (interval, value) = get_timerfd(&fds, fd)?.get_time();
+	curr_value.copy_to_user(&ITimerspec32 {
+		it_interval: Timespec32::from_nano(interval),
+		it_value: Timespec32::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn timerfd_gettime64(
+	Args((fd, curr_value)): Args<(c_int, UserPtr<ITimerspec>)>,
+	fds: Arc<Mutex<FileDescriptorTable>>,
+) -> EResult<usize> {
+	let (interval, value) = get_timerfd(&fds, fd)?.get_time();
+	curr_value.copy_to_user(&ITimerspec {
+		it_interval: Timespec::from_nano(interval),
+		it_value: Timespec::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn alarm(Args(seconds): Args<c_uint>, proc: Arc<Process>) -> EResult<usize> {
+	let (_, old) = proc.timer_manager.lock().set_itimer(
+		ITIMER_REAL,
+		0,
+		seconds as Timestamp * 1_000_000_000,
+	)?;
+	// Round to the nearest second, without reporting a pending alarm as zero
+	let sec = old / 1_000_000_000;
+	let usec = (old % 1_000_000_000) / 1000;
+	let sec = if (sec == 0 && usec > 0) || usec >= 500_000 {
+		sec + 1
+	} else {
+		sec
+	};
+	Ok(sec as _)
+}
+
+pub fn getitimer32(
+	Args((which, curr_value)): Args<(c_int, UserPtr<ITimerval32>)>,
+	proc: Arc<Process>,
+) -> EResult<usize> {
+	let (interval, value) = proc.timer_manager.lock().get_itimer(which)?;
+	curr_value.copy_to_user(&ITimerval32 {
+		it_interval: Timeval32::from_nano(interval),
+		it_value: Timeval32::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn getitimer64(
+	Args((which, curr_value)): Args<(c_int, UserPtr<ITimerval>)>,
+	proc: Arc<Process>,
+) -> EResult<usize> {
+	let (interval, value) = proc.timer_manager.lock().get_itimer(which)?;
+	curr_value.copy_to_user(&ITimerval {
+		it_interval: Timeval::from_nano(interval),
+		it_value: Timeval::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn setitimer32(
+	Args((which, new_value, old_value)): Args<(c_int, UserPtr<ITimerval32>, UserPtr<ITimerval32>)>,
+	proc: Arc<Process>,
+) -> EResult<usize> {
+	// A null value disarms the timer
+	let new_value = new_value.copy_from_user()?.unwrap_or_default();
+	if unlikely(
+		new_value.it_interval.tv_usec >= 1_000_000 || new_value.it_value.tv_usec >= 1_000_000,
+	) {
+		return Err(errno!(EINVAL));
+	}
+	let (interval, value) = proc.timer_manager.lock().set_itimer(
+		which,
+		new_value.it_interval.to_nano(),
+		new_value.it_value.to_nano(),
+	)?;
+	old_value.copy_to_user(&ITimerval32 {
+		it_interval: Timeval32::from_nano(interval),
+		it_value: Timeval32::from_nano(value),
+	})?;
+	Ok(0)
+}
+
+pub fn setitimer64(
+	Args((which, new_value, old_value)): Args<(c_int, UserPtr<ITimerval>, UserPtr<ITimerval>)>,
+	proc: Arc<Process>,
+) -> EResult<usize> {
+	// A null value disarms the timer
+	let new_value = new_value.copy_from_user()?.unwrap_or_default();
+	if unlikely(
+		new_value.it_interval.tv_usec >= 1_000_000 || new_value.it_value.tv_usec >= 1_000_000,
+	) {
+		return Err(errno!(EINVAL));
+	}
+	let (interval, value) = proc.timer_manager.lock().set_itimer(
+		which,
+		new_value.it_interval.to_nano(),
+		new_value.it_value.to_nano(),
+	)?;
+	old_value.copy_to_user(&ITimerval {
+		it_interval: Timeval::from_nano(interval),
+		it_value: Timeval::from_nano(value),
+	})?;
+	Ok(0)
+}
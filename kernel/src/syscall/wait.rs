@@ -23,6 +23,7 @@ use crate::{
 	process::{
 		Process, State,
 		pid::Pid,
+		ptrace,
 		rusage::Rusage,
 		scheduler::{SCHEDULER, Scheduler},
 	},
@@ -52,7 +53,8 @@ pub const WNOWAIT: i32 = 0x1000000;
 /// constraint.
 ///
 /// Arguments:
-/// - `curr_proc` is the current process.
+/// - `curr_proc` is the leader of the current thread group, which holds the children of every
+///   thread.
 /// - `pid` is the constraint given to the system call.
 fn iter_targets(curr_proc: &Process, pid: i32) -> impl Iterator<Item = Pid> + '_ {
 	let mut i = 0;
@@ -94,19 +96,28 @@ fn get_wstatus(proc: &Process) -> i32 {
 /// `None`.
 ///
 /// Arguments:
-/// - `curr_proc` is the current process.
+/// - `curr_proc` is the leader of the current thread group, which holds the children of every
+///   thread.
+/// - `tracer` is the current thread, which holds its tracees.
 /// - `pid` is the constraint given to the system call.
 /// - `wstatus` is the pointer to the wait status.
 /// - `options` is a set of flags.
 /// - `rusage` is the pointer to the resource usage structure.
 fn get_waitable(
 	curr_proc: &Process,
+	tracer: &Process,
 	pid: i32,
 	wstatus: UserPtr<i32>,
 	options: i32,
 	rusage: UserPtr<Rusage>,
 ) -> EResult<Option<Pid>> {
-	let mut empty = true;
+	// Stops of tracees are reported regardless of `WUNTRACED`
+	if let Some((tracee, status)) = ptrace::take_stop(tracer, pid, options & WNOWAIT == 0) {
+		wstatus.copy_to_user(&status)?;
+		rusage.copy_to_user(&tracee.get_rusage())?;
+		return Ok(Some(tracee.get_tid()));
+	}
+	let mut empty = !ptrace::has_tracee(tracer, pid);
 	let mut sched = SCHEDULER.lock();
 	// Find a waitable process
 	let proc = iter_targets(curr_proc, pid)
@@ -120,7 +131,10 @@ fn get_waitable(
 				proc.parent_event.load(Acquire)
 			};
 			let stopped = options & WUNTRACED != 0 && events & WUNTRACED as u8 != 0;
-			let exited = options & WEXITED != 0 && proc.get_state() == State::Zombie;
+			// A process has exited once all its threads have
+			let exited = options & WEXITED != 0
+				&& proc.get_state() == State::Zombie
+				&& proc.links.lock().threads.is_empty();
 			let continued = options & WCONTINUED != 0 && events & WCONTINUED as u8 != 0;
 			stopped || exited || continued
 		});
@@ -134,7 +148,7 @@ fn get_waitable(
 	};
 	// Write values back
 	wstatus.copy_to_user(&get_wstatus(&proc))?;
-	rusage.copy_to_user(&proc.rusage.lock())?;
+	rusage.copy_to_user(&proc.get_rusage())?;
 	// Remove zombie process if requested
 	let pid = proc.get_pid();
 	if options & WNOWAIT == 0 && proc.get_state() == State::Zombie {
@@ -154,7 +168,8 @@ pub fn do_waitpid(
 	loop {
 		{
 			let proc = Process::current();
-			let result = get_waitable(&proc, pid, wstatus, options, rusage.clone())?;
+			let leader = Process::thread_leader(&proc);
+			let result = get_waitable(&leader, &proc, pid, wstatus, options, rusage.clone())?;
 			// On success, return
 			if let Some(p) = result {
 				return Ok(p as _);
@@ -18,13 +18,15 @@
 
 //! System clocks.
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::time::hw::hpet;
 use crate::{
 	sync::atomic::AtomicU64,
 	time::{Timestamp, unit::ClockIdT},
 };
 use core::{
 	cmp::max,
-	sync::atomic::Ordering::{Acquire, Release},
+	sync::atomic::Ordering::{AcqRel, Acquire, Release},
 };
 
 /// Available clocks
@@ -77,6 +79,8 @@ static REALTIME: AtomicU64 = AtomicU64::new(0);
 static MONOTONIC: AtomicU64 = AtomicU64::new(0);
 /// The time elapsed since boot time, in nanoseconds.
 static BOOTTIME: AtomicU64 = AtomicU64::new(0);
+/// The value of the hardware counter, in nanoseconds, at the last update of clocks.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
 
 /// Updates clocks with the given delta value in nanoseconds.
 pub fn update(delta: Timestamp) {
@@ -85,6 +89,22 @@ pub fn update(delta: Timestamp) {
 	BOOTTIME.fetch_add(delta as _, Release);
 }
 
+/// Updates clocks with the time elapsed since the last update, as measured by the hardware
+/// counter.
+///
+/// If no hardware counter is running, clocks are updated by timer interrupts instead and the
+/// function does nothing.
+fn sync() {
+	#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+	if let Some(now) = hpet::counter_ns() {
+		// Several cores may read the counter concurrently: only account for the most recent value
+		let prev = COUNTER.fetch_max(now, AcqRel);
+		if now > prev {
+			update(now - prev);
+		}
+	}
+}
+
 /// Returns the current timestamp in nanoseconds.
 ///
 /// `clk` is the clock to use.
@@ -93,6 +113,7 @@ pub fn update(delta: Timestamp) {
 ///
 /// If the clock is invalid, the function returns an error.
 pub fn current_time_ns(clk: Clock) -> Timestamp {
+	sync();
 	match clk {
 		Clock::Realtime | Clock::RealtimeAlarm => REALTIME.load(Acquire),
 		Clock::Monotonic => {
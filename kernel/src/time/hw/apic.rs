@@ -0,0 +1,141 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The Local APIC timer is a timer attached to each CPU core, which can be programmed either in
+//! periodic or one-shot mode.
+//!
+//! Its frequency is not known in advance, so it is measured against the HPET.
+
+use super::{HwClock, hpet};
+use crate::{arch::x86::apic, time::unit::Timestamp};
+use core::hint;
+
+/// The duration of the measure of the timer's frequency, in nanoseconds.
+const CALIBRATION_DELAY: Timestamp = 10_000_000;
+
+/// The Local APIC timer.
+///
+/// Settings are shared by all cores, but operations apply only to the timer of the current core.
+pub struct ApicTimer {
+	/// The frequency of the timer, in ticks per second.
+	freq: u64,
+	/// The number of ticks between two interrupts in periodic mode. If zero, the timer is in
+	/// one-shot mode.
+	period: u32,
+	/// Tells whether the timer is enabled.
+	enabled: bool,
+}
+
+impl ApicTimer {
+	/// Creates a new instance, measuring the frequency of the timer.
+	///
+	/// The Local APIC must be enabled. If the HPET is not running, the frequency cannot be
+	/// measured and the function returns `None`.
+	///
+	/// By default, the timer is disabled and in one-shot mode.
+	pub fn new() -> Option<Self> {
+		let begin = hpet::counter_ns()?;
+		apic::timer_start(u32::MAX, false);
+		let mut end = begin;
+		while end - begin < CALIBRATION_DELAY {
+			hint::spin_loop();
+			end = hpet::counter_ns()?;
+		}
+		let ticks = u32::MAX - apic::timer_count();
+		apic::timer_start(0, false);
+		let freq = ticks as u64 * 1_000_000_000 / (end - begin);
+		if freq == 0 {
+			return None;
+		}
+		Some(Self {
+			freq,
+			period: 0,
+			enabled: false,
+		})
+	}
+
+	/// Converts `ns` nanoseconds to a number of ticks of the timer.
+	fn ns_to_ticks(&self, ns: Timestamp) -> u32 {
+		let ticks = ns as u128 * self.freq as u128 / 1_000_000_000;
+		ticks.clamp(1, u32::MAX as _) as _
+	}
+}
+
+impl HwClock for ApicTimer {
+	fn set_enabled(&mut self, enable: bool) {
+		self.enabled = enable;
+		if !enable {
+			apic::timer_start(0, false);
+		} else if self.period != 0 {
+			apic::timer_start(self.period, true);
+		}
+	}
+
+	fn set_frequency(&mut self, freq: u32) {
+		self.period = (self.freq / freq.max(1) as u64).clamp(1, u32::MAX as _) as _;
+		if self.enabled {
+			apic::timer_start(self.period, true);
+		}
+	}
+
+	fn set_oneshot(&mut self, delay: Option<Timestamp>) -> bool {
+		self.period = 0;
+		if self.enabled {
+			let count = delay.map(|delay| self.ns_to_ticks(delay)).unwrap_or(0);
+			apic::timer_start(count, false);
+		}
+		true
+	}
+
+	fn get_interrupt_vector(&self) -> u32 {
+		apic::TIMER_VECTOR as _
+	}
+}
+
+impl Drop for ApicTimer {
+	fn drop(&mut self) {
+		self.set_enabled(false);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn apic_ns_to_ticks() {
+		let timer = ApicTimer {
+			freq: 1_000_000_000,
+			period: 0,
+			enabled: false,
+		};
+		assert_eq!(timer.ns_to_ticks(1000), 1000);
+		// Clamped to at least one tick
+		assert_eq!(timer.ns_to_ticks(0), 1);
+		// Clamped to the width of the counter
+		assert_eq!(timer.ns_to_ticks(u64::MAX), u32::MAX);
+		let timer = ApicTimer {
+			freq: 250_000_000,
+			period: 0,
+			enabled: false,
+		};
+		assert_eq!(timer.ns_to_ticks(1_000_000_000), 250_000_000);
+		assert_eq!(timer.ns_to_ticks(3), 1);
+		assert_eq!(timer.ns_to_ticks(9), 2);
+	}
+}
@@ -0,0 +1,307 @@
+/*
+ * Copyright 2024 Luc Lenôtre
+ *
+ * This file is part of Maestro.
+ *
+ * Maestro is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Maestro is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+ * A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Maestro. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The High Precision Event Timer (HPET) provides a main counter running at a fixed frequency,
+//! along with comparators able to trigger interrupts.
+//!
+//! The main counter is used as the source of system clocks. The first comparator's interrupt is
+//! delivered through the legacy replacement routing, taking the place of the PIT's.
+
+use super::HwClock;
+use crate::{
+	arch::x86::idt,
+	memory::{PhysAddr, mmio::MMIO},
+	time::unit::Timestamp,
+};
+use core::{
+	mem,
+	num::NonZeroUsize,
+	ptr,
+	ptr::null_mut,
+	sync::atomic::{
+		AtomicBool, AtomicPtr, AtomicU32,
+		Ordering::{Acquire, Relaxed, Release},
+	},
+};
+use utils::errno::AllocResult;
+
+/// Register: General Capabilities and ID
+const REG_CAP: usize = 0x000;
+/// Register: General Configuration
+const REG_CONF: usize = 0x010;
+/// Register: Main Counter Value
+const REG_COUNTER: usize = 0x0f0;
+/// Register: Timer 0 Configuration and Capabilities
+const REG_TIMER0_CONF: usize = 0x100;
+/// Register: Timer 0 Comparator Value
+const REG_TIMER0_CMP: usize = 0x108;
+
+/// Capability: the main counter is 64 bits wide
+const CAP_COUNT_SIZE: u64 = 1 << 13;
+/// Capability: legacy replacement routing is supported
+const CAP_LEGACY_ROUTE: u64 = 1 << 15;
+
+/// Configuration: the main counter is running
+const CONF_ENABLE: u64 = 1 << 0;
+/// Configuration: legacy replacement routing
+const CONF_LEGACY_ROUTE: u64 = 1 << 1;
+
+/// Timer configuration: interrupts enabled
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+/// Timer configuration: periodic mode
+const TIMER_PERIODIC: u64 = 1 << 3;
+/// Timer capability: periodic mode is supported
+const TIMER_PERIODIC_CAP: u64 = 1 << 4;
+/// Timer configuration: the next write to the comparator sets the period
+const TIMER_VAL_SET: u64 = 1 << 6;
+
+/// The maximum valid period of the main counter, in femtoseconds.
+const MAX_PERIOD: u64 = 100_000_000;
+/// The minimum delay of a one-shot interrupt, in nanoseconds, so that the main counter does not
+/// pass the comparator before it is written.
+const MIN_DELAY: Timestamp = 1000;
+
+/// The pointer to the HPET's registers. If null, no HPET has been registered.
+static REGS: AtomicPtr<u32> = AtomicPtr::new(null_mut());
+/// The period of the main counter, in femtoseconds.
+static PERIOD: AtomicU32 = AtomicU32::new(0);
+/// Tells whether the main counter is running.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Reads the 64 bits register at offset `reg`.
+#[inline]
+fn read(reg: usize) -> u64 {
+	let regs = REGS.load(Acquire);
+	#[cfg(target_pointer_width = "64")]
+	unsafe {
+		ptr::read_volatile(regs.byte_add(reg).cast::<u64>())
+	}
+	#[cfg(not(target_pointer_width = "64"))]
+	unsafe {
+		let low = ptr::read_volatile(regs.byte_add(reg)) as u64;
+		let high = ptr::read_volatile(regs.byte_add(reg + 4)) as u64;
+		(high << 32) | low
+	}
+}
+
+/// Writes `val` to the 64 bits register at offset `reg`.
+#[inline]
+fn write(reg: usize, val: u64) {
+	let regs = REGS.load(Acquire);
+	#[cfg(target_pointer_width = "64")]
+	unsafe {
+		ptr::write_volatile(regs.byte_add(reg).cast::<u64>(), val);
+	}
+	#[cfg(not(target_pointer_width = "64"))]
+	unsafe {
+		ptr::write_volatile(regs.byte_add(reg), val as u32);
+		ptr::write_volatile(regs.byte_add(reg + 4), (val >> 32) as u32);
+	}
+}
+
+/// Returns the value of the main counter.
+#[inline]
+fn counter() -> u64 {
+	#[cfg(target_pointer_width = "64")]
+	{
+		read(REG_COUNTER)
+	}
+	// The counter may carry into its high half between the two reads
+	#[cfg(not(target_pointer_width = "64"))]
+	loop {
+		let regs = REGS.load(Acquire);
+		unsafe {
+			let high = ptr::read_volatile(regs.byte_add(REG_COUNTER + 4));
+			let low = ptr::read_volatile(regs.byte_add(REG_COUNTER));
+			if ptr::read_volatile(regs.byte_add(REG_COUNTER + 4)) == high {
+				break ((high as u64) << 32) | low as u64;
+			}
+		}
+	}
+}
+
+/// Converts a number of ticks of a counter with the given `period` in femtoseconds to
+/// nanoseconds.
+#[inline]
+fn ticks_to_ns(ticks: u64, period: u32) -> Timestamp {
+	(ticks as u128 * period as u128 / 1_000_000) as _
+}
+
+/// Converts nanoseconds to a number of ticks of a counter with the given `period` in
+/// femtoseconds.
+#[inline]
+fn ns_to_ticks(ns: Timestamp, period: u32) -> u64 {
+	(ns as u128 * 1_000_000 / period as u128) as _
+}
+
+/// Tells whether the capabilities register `cap` describes a usable HPET.
+#[inline]
+fn is_valid_cap(cap: u64) -> bool {
+	let period = cap >> 32;
+	cap & CAP_COUNT_SIZE != 0 && (1..=MAX_PERIOD).contains(&period)
+}
+
+/// Registers the HPET whose registers are at the physical address `addr`, as listed by the ACPI.
+pub(crate) fn register(addr: PhysAddr) -> AllocResult<()> {
+	let mmio = MMIO::new(addr, NonZeroUsize::MIN, false)?;
+	REGS.store(mmio.as_ptr().cast().as_ptr(), Release);
+	// The registers remain mapped for the whole lifetime of the system
+	mem::forget(mmio);
+	Ok(())
+}
+
+/// Tells whether a usable HPET is present.
+///
+/// Only HPETs with a 64 bits main counter are supported, so that it never wraps around.
+pub fn is_present() -> bool {
+	if REGS.load(Acquire).is_null() {
+		return false;
+	}
+	is_valid_cap(read(REG_CAP))
+}
+
+/// Tells whether the main counter is running.
+#[inline]
+pub fn is_running() -> bool {
+	ENABLED.load(Acquire)
+}
+
+/// Returns the value of the main counter in nanoseconds.
+///
+/// If the main counter is not running, the function returns `None`.
+#[inline]
+pub fn counter_ns() -> Option<Timestamp> {
+	is_running().then(|| ticks_to_ns(counter(), PERIOD.load(Relaxed)))
+}
+
+/// The HPET.
+pub struct HPET {}
+
+impl HPET {
+	/// Creates a new instance, resetting and starting the main counter.
+	///
+	/// The HPET must be present, see [`is_present`]. By default, the comparator's interrupt is
+	/// disabled.
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> Self {
+		PERIOD.store((read(REG_CAP) >> 32) as _, Relaxed);
+		// Halt and reset the main counter
+		write(
+			REG_CONF,
+			read(REG_CONF) & !(CONF_ENABLE | CONF_LEGACY_ROUTE),
+		);
+		let conf = read(REG_TIMER0_CONF) & !(TIMER_INT_ENABLE | TIMER_PERIODIC);
+		write(REG_TIMER0_CONF, conf);
+		write(REG_COUNTER, 0);
+		write(REG_CONF, read(REG_CONF) | CONF_ENABLE);
+		ENABLED.store(true, Release);
+		Self {}
+	}
+}
+
+impl HwClock for HPET {
+	fn set_enabled(&mut self, enable: bool) {
+		// Without legacy replacement routing, the comparator is not connected to any IRQ we know
+		if read(REG_CAP) & CAP_LEGACY_ROUTE == 0 {
+			return;
+		}
+		let conf = read(REG_TIMER0_CONF);
+		if enable {
+			write(REG_CONF, read(REG_CONF) | CONF_LEGACY_ROUTE);
+			write(REG_TIMER0_CONF, conf | TIMER_INT_ENABLE);
+			idt::enable_irq(0x0);
+		} else {
+			write(REG_TIMER0_CONF, conf & !TIMER_INT_ENABLE);
+			write(REG_CONF, read(REG_CONF) & !CONF_LEGACY_ROUTE);
+		}
+	}
+
+	fn set_frequency(&mut self, freq: u32) {
+		let conf = read(REG_TIMER0_CONF);
+		if freq == 0 || conf & TIMER_PERIODIC_CAP == 0 {
+			return;
+		}
+		let period = ns_to_ticks(1_000_000_000 / freq as u64, PERIOD.load(Relaxed)).max(1);
+		write(REG_TIMER0_CONF, conf | TIMER_PERIODIC | TIMER_VAL_SET);
+		write(REG_TIMER0_CMP, counter() + period);
+		// The second write sets the period
+		write(REG_TIMER0_CMP, period);
+	}
+
+	fn set_oneshot(&mut self, delay: Option<Timestamp>) -> bool {
+		write(REG_TIMER0_CONF, read(REG_TIMER0_CONF) & !TIMER_PERIODIC);
+		let cmp = match delay {
+			Some(delay) => counter() + ns_to_ticks(delay.max(MIN_DELAY), PERIOD.load(Relaxed)),
+			// Never reached
+			None => u64::MAX,
+		};
+		write(REG_TIMER0_CMP, cmp);
+		true
+	}
+
+	fn get_value(&self) -> Option<Timestamp> {
+		counter_ns()
+	}
+
+	fn get_interrupt_vector(&self) -> u32 {
+		0x20
+	}
+}
+
+impl Drop for HPET {
+	fn drop(&mut self) {
+		self.set_enabled(false);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn period_conversion() {
+		// 14.31818 MHz, the frequency of the HPET emulated by QEMU
+		let period = 69_841_279;
+		assert_eq!(ticks_to_ns(0, period), 0);
+		assert_eq!(ticks_to_ns(14_318_180, period), 1_000_000_004);
+		assert_eq!(ns_to_ticks(1_000_000_000, period), 14_318_179);
+		// 100 MHz
+		let period = 10_000_000;
+		assert_eq!(ticks_to_ns(1, period), 10);
+		assert_eq!(ns_to_ticks(10, period), 1);
+		assert_eq!(ns_to_ticks(9, period), 0);
+		for ns in [0, 10, 1_000, 123_456_780, 1 << 40] {
+			assert_eq!(ticks_to_ns(ns_to_ticks(ns, period), period), ns);
+		}
+		// No overflow on large values
+		assert_eq!(ticks_to_ns(u64::MAX / 1000, 1_000_000), u64::MAX / 1000);
+	}
+
+	#[test_case]
+	fn cap_validity() {
+		let cap =
+			|period: u64, count_size| (period << 32) | if count_size { CAP_COUNT_SIZE } else { 0 };
+		assert!(is_valid_cap(cap(69_841_279, true)));
+		assert!(is_valid_cap(cap(MAX_PERIOD, true)));
+		// 32 bits main counter
+		assert!(!is_valid_cap(cap(69_841_279, false)));
+		// Out of range periods
+		assert!(!is_valid_cap(cap(0, true)));
+		assert!(!is_valid_cap(cap(MAX_PERIOD + 1, true)));
+	}
+}
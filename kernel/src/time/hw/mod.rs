@@ -18,12 +18,16 @@
 
 //! This module implements hardware clocks.
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub mod apic;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub mod hpet;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub mod pit;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub mod rtc;
 
-use crate::{sync::mutex::Mutex, time::unit::Timestamp};
+use crate::{sync::mutex::IntMutex, time::unit::Timestamp};
 use utils::{
 	boxed::Box,
 	collections::{hashmap::HashMap, string::String},
@@ -38,6 +42,15 @@ pub trait HwClock {
 	/// The actual frequency is the closest possible rounded down according to the clock's
 	/// resolution.
 	fn set_frequency(&mut self, freq: u32);
+	/// Programs the clock to fire a single interrupt after `delay` nanoseconds, leaving periodic
+	/// mode.
+	///
+	/// If `delay` is `None`, the pending interrupt is cancelled.
+	///
+	/// If the clock does not support one-shot mode, the function returns `false`.
+	fn set_oneshot(&mut self, _delay: Option<Timestamp>) -> bool {
+		false
+	}
 
 	/// Returns the value of the clock, if applicable.
 	fn get_value(&self) -> Option<Timestamp> {
@@ -51,4 +64,6 @@ pub trait HwClock {
 /// The list of hardware clock sources.
 ///
 /// The key is the name of the clock.
-pub static CLOCKS: Mutex<HashMap<String, Box<dyn HwClock>>> = Mutex::new(HashMap::new());
+///
+/// Clocks may be programmed from interrupt handlers, hence the use of an [`IntMutex`].
+pub static CLOCKS: IntMutex<HashMap<String, Box<dyn HwClock>>> = IntMutex::new(HashMap::new());
@@ -23,6 +23,11 @@
 //!   give the ability to measure the passage of time, notably by producing interruptions at a
 //!   given frequency.
 //! - Software Clocks, which maintain a timestamp based on hardware clocks.
+//!
+//! When the HPET and the Local APIC are available, the kernel runs *tickless*: clocks are read
+//! from the HPET's counter and each core programs its Local APIC timer in one-shot mode, only
+//! when it has something to do. Otherwise, clocks and timers are updated by the RTC's periodic
+//! interrupt.
 
 pub mod clock;
 pub mod hw;
@@ -30,11 +35,12 @@ pub mod timer;
 pub mod unit;
 
 use crate::{
+	arch::x86::apic,
 	event,
 	event::CallbackResult,
 	process::{
 		Process, State,
-		scheduler::Scheduler,
+		scheduler::{Scheduler, cpu},
 		signal::{SIGEV_NONE, SigEvent},
 	},
 	time::{
@@ -43,12 +49,57 @@ use crate::{
 		unit::TimeUnit,
 	},
 };
-use core::{hint::unlikely, mem::ManuallyDrop};
+use core::{
+	hint::unlikely,
+	mem::ManuallyDrop,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::{Acquire, Release},
+	},
+};
+use hw::HwClock;
 use unit::Timestamp;
 use utils::{boxed::Box, errno, errno::EResult};
 
 /// Timer frequency.
 const FREQUENCY: u32 = 1024;
+/// The name of the hardware clock used to trigger timer interrupts when tickless.
+const EVENT_CLOCK: &[u8] = b"apic";
+
+/// Tells whether timer interrupts are programmed on demand instead of occurring periodically.
+static TICKLESS: AtomicBool = AtomicBool::new(false);
+
+/// Tells whether timer interrupts are programmed on demand instead of occurring periodically.
+#[inline]
+pub fn is_tickless() -> bool {
+	TICKLESS.load(Acquire)
+}
+
+/// Programs the next timer interrupt on the current core.
+///
+/// `deadline` is the boot time, in nanoseconds, at which the scheduler of the current core needs
+/// to run next. On the first core, the expiration of timers is also taken into account. If there
+/// is no deadline, no interrupt occurs.
+///
+/// If not tickless, the function does nothing.
+pub fn set_next_event(deadline: Option<Timestamp>) {
+	if !is_tickless() {
+		return;
+	}
+	let timers = if cpu::current_id() == 0 {
+		timer::next_expiry()
+	} else {
+		None
+	};
+	let deadline = match (deadline, timers) {
+		(Some(a), Some(b)) => Some(a.min(b)),
+		(a, b) => a.or(b),
+	};
+	let delay = deadline.map(|d| d.saturating_sub(current_time_ns(Clock::Boottime)));
+	if let Some(clock) = hw::CLOCKS.lock().get_mut(EVENT_CLOCK) {
+		clock.set_oneshot(delay);
+	}
+}
 
 /// Makes the current thread sleep for `delay`, in nanoseconds.
 ///
@@ -58,7 +109,7 @@ const FREQUENCY: u32 = 1024;
 /// sets the remaining time in `remain`.
 pub fn sleep_for(clock: Clock, delay: Timestamp, remain: &mut Timestamp) -> EResult<()> {
 	// Setup timer
-	let pid = Process::current().get_pid();
+	let pid = Process::current().get_tid();
 	// FIXME: there can be allocation failures here
 	let mut timer = Timer::new(
 		clock,
@@ -95,15 +146,34 @@ pub(crate) fn init() -> EResult<()> {
 	let mut hw_clocks = hw::CLOCKS.lock();
 	hw_clocks.insert(b"pit".try_into()?, Box::new(hw::pit::PIT::new())?)?;
 	hw_clocks.insert(b"rtc".try_into()?, Box::new(hw::rtc::RTC::new())?)?;
-	// TODO implement HPET
-	// TODO implement APIC timer
+	if hw::hpet::is_present() {
+		hw_clocks.insert(b"hpet".try_into()?, Box::new(hw::hpet::HPET::new())?)?;
+	}
+	let apic_timer = apic::is_enabled().then(hw::apic::ApicTimer::new).flatten();
+	if let Some(mut apic_timer) = apic_timer {
+		// Each core triggers timer interrupts for itself. The first core handles timers
+		let hook = event::register_callback(apic_timer.get_interrupt_vector(), |_, _, _, _| {
+			if cpu::current_id() == 0 {
+				timer::tick();
+			}
+			CallbackResult::Continue
+		})?;
+		let _ = ManuallyDrop::new(hook);
+		apic_timer.set_enabled(true);
+		hw_clocks.insert(EVENT_CLOCK.try_into()?, Box::new(apic_timer)?)?;
+		TICKLESS.store(true, Release);
+		return Ok(());
+	}
 	// Link hardware clock to software clock
 	let rtc = hw_clocks.get_mut(b"rtc".as_slice()).unwrap();
 	rtc.set_frequency(FREQUENCY);
-	let hook = event::register_callback(rtc.get_interrupt_vector(), move |_, _, _, _| {
+	let hook = event::register_callback(rtc.get_interrupt_vector(), |_, _, _, _| {
 		hw::rtc::RTC::reset();
-		// FIXME: we are loosing precision here
-		clock::update((1_000_000_000 / FREQUENCY) as _);
+		// If the HPET is running, clocks are read from it instead
+		if !hw::hpet::is_running() {
+			// FIXME: we are loosing precision here
+			clock::update((1_000_000_000 / FREQUENCY) as _);
+		}
 		timer::tick();
 		CallbackResult::Continue
 	})?;
@@ -24,7 +24,8 @@ use crate::{
 	process::{
 		Process,
 		pid::Pid,
-		signal::{SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD, SigEvent, Signal},
+		scheduler::cpu,
+		signal::{SI_TIMER, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD, SigEvent, SigInfo, Signal},
 	},
 	sync::mutex::IntMutex,
 	time::{
@@ -32,7 +33,7 @@ use crate::{
 		unit::{TimeUnit, Timespec32, Timestamp},
 	},
 };
-use core::hint::unlikely;
+use core::{ffi::c_int, hint::unlikely};
 use utils::{
 	boxed::Box,
 	collections::{btreemap::BTreeMap, hashmap::HashMap, id_allocator::IDAllocator},
@@ -43,6 +44,15 @@ use utils::{
 // TODO make sure a timer doesn't send a signal to a thread that do not belong to the manager's
 // process
 
+/// Interval timer decrementing in real time, sending `SIGALRM` on expiration.
+pub const ITIMER_REAL: c_int = 0;
+/// Interval timer decrementing while the process executes in userspace, sending `SIGVTALRM` on
+/// expiration.
+pub const ITIMER_VIRTUAL: c_int = 1;
+/// Interval timer decrementing while the process executes, in userspace or kernelspace, sending
+/// `SIGPROF` on expiration.
+pub const ITIMER_PROF: c_int = 2;
+
 #[derive(Default)]
 struct TimerSpec {
 	/// The timer's interval, in nanoseconds.
@@ -53,13 +63,24 @@ struct TimerSpec {
 	next: Option<Timestamp>,
 }
 
+/// The action performed when a timer expires.
+enum Action {
+	/// Notify a thread.
+	Notify {
+		/// TID of the thread to notify.
+		pid: Pid,
+		/// Definition of the action to perform when the timer is triggered.
+		sevp: SigEvent,
+	},
+	/// Call a function.
+	Callback(Box<dyn Fn()>),
+}
+
 struct TimerInner {
 	/// The clock to user.
 	clock: Clock,
-	/// PID of the process to notify.
-	pid: Pid,
-	/// Definition of the action to perform when the timer is triggered.
-	sevp: SigEvent,
+	/// The action to perform when the timer is triggered.
+	action: Action,
 
 	/// Timer setting.
 	spec: IntMutex<TimerSpec>,
@@ -86,17 +107,30 @@ impl TimerInner {
 
 	/// Fires the timer.
 	fn fire(&self) {
-		let Some(proc) = Process::get_by_pid(self.pid) else {
+		let (pid, sevp) = match &self.action {
+			Action::Notify {
+				pid,
+				sevp,
+			} => (*pid, sevp),
+			Action::Callback(f) => {
+				f();
+				return;
+			}
+		};
+		let Some(proc) = Process::get_by_tid(pid) else {
 			return;
 		};
-		match self.sevp.sigev_notify {
+		match sevp.sigev_notify {
 			SIGEV_NONE => proc.wake(),
 			SIGEV_SIGNAL => {
-				let Ok(signal) = Signal::try_from(self.sevp.sigev_signo) else {
+				let Ok(signal) = Signal::try_from(sevp.sigev_signo) else {
 					return;
 				};
-				// TODO on sigint_t, set si_code to SI_TIMER
-				proc.kill(signal);
+				let info = SigInfo {
+					si_value: sevp.sigev_value,
+					..SigInfo::new(signal, SI_TIMER)
+				};
+				let _ = proc.kill_process_info(info);
 			}
 			SIGEV_THREAD => todo!(),
 			_ => {}
@@ -140,7 +174,7 @@ impl Timer {
 	///
 	/// Arguments:
 	/// - `clock` is the clock to use.
-	/// - `pid` is the PID of the process to notify.
+	/// - `pid` is the TID of the thread to notify. Signals are directed to its whole process.
 	/// - `sevp` describes the event to be triggered by the clock.
 	pub fn new(clock: Clock, pid: Pid, sevp: SigEvent) -> EResult<Self> {
 		// Validation
@@ -149,23 +183,52 @@ impl Timer {
 		}
 		Ok(Self(Box::new(TimerInner {
 			clock,
-			pid,
-			sevp,
+			action: Action::Notify {
+				pid,
+				sevp,
+			},
 
 			spec: Default::default(),
 		})?))
 	}
 
-	/// Returns the current state of the timer.
+	/// Creates a timer calling `f` each time it expires.
+	///
+	/// `f` is called from the timer interrupt handler, so it must not sleep.
+	pub fn with_callback<F: Fn() + 'static>(clock: Clock, f: F) -> AllocResult<Self> {
+		Ok(Self(Box::new(TimerInner {
+			clock,
+			action: Action::Callback(Box::new(f)?),
+
+			spec: Default::default(),
+		})?))
+	}
+
+	/// Returns the clock used by the timer.
 	#[inline]
-	pub fn get_time(&self) -> ITimerspec32 {
+	pub fn get_clock(&self) -> Clock {
+		self.0.clock
+	}
+
+	/// Returns the interval of the timer and the remaining time until its next expiration, in
+	/// nanoseconds.
+	///
+	/// If the timer is unarmed, the remaining time is zero.
+	pub fn get_time_ns(&self) -> (Timestamp, Timestamp) {
 		let spec = self.0.spec.lock();
 		let value = spec
 			.next
 			.map(|next| next.saturating_sub(current_time_ns(self.0.clock)))
 			.unwrap_or(0);
+		(spec.interval, value)
+	}
+
+	/// Returns the current state of the timer.
+	#[inline]
+	pub fn get_time(&self) -> ITimerspec32 {
+		let (interval, value) = self.get_time_ns();
 		ITimerspec32 {
-			it_interval: Timespec32::from_nano(spec.interval),
+			it_interval: Timespec32::from_nano(interval),
 			it_value: Timespec32::from_nano(value),
 		}
 	}
@@ -195,6 +258,16 @@ impl Timer {
 			// Insert back in queue
 			queue.insert((next, self.0.as_ptr()), ())?;
 		}
+		let first = queue
+			.first_key_value()
+			.is_some_and(|((_, timer), _)| *timer == self.0.as_ptr());
+		drop(spec);
+		drop(queue);
+		// If the timer is the next to expire, the first core has to program its timer interrupt
+		// accordingly
+		if first && super::is_tickless() {
+			cpu::reschedule(0);
+		}
 		Ok(())
 	}
 
@@ -216,6 +289,38 @@ impl Drop for Timer {
 	}
 }
 
+/// An interval timer decrementing with the CPU time consumed by a process.
+#[derive(Default)]
+struct CpuITimer {
+	/// The interval with which the timer is reloaded on expiration, in nanoseconds.
+	interval: Timestamp,
+	/// The remaining CPU time until the next expiration, in nanoseconds.
+	///
+	/// If zero, the timer is unarmed.
+	value: Timestamp,
+}
+
+impl CpuITimer {
+	/// Decrements the timer by `delta` nanoseconds of CPU time.
+	///
+	/// If the timer expires, the function returns `true`. Expirations occurring several times
+	/// within `delta` are reported once.
+	fn charge(&mut self, delta: Timestamp) -> bool {
+		if self.value == 0 {
+			return false;
+		}
+		if delta < self.value {
+			self.value -= delta;
+			return false;
+		}
+		self.value = match self.interval {
+			0 => 0,
+			interval => interval - (delta - self.value) % interval,
+		};
+		true
+	}
+}
+
 /// Manager for a process's timers.
 pub struct TimerManager {
 	/// The PID of the process to which the manager is associated.
@@ -225,6 +330,13 @@ pub struct TimerManager {
 	id_allocator: IDAllocator,
 	/// The list of timers for the process. The key is the ID of the timer.
 	timers: HashMap<u32, Timer>,
+
+	/// The real time interval timer, allocated the first time it is armed.
+	itimer_real: Option<Timer>,
+	/// The virtual time interval timer.
+	itimer_virtual: CpuITimer,
+	/// The profiling interval timer.
+	itimer_prof: CpuITimer,
 }
 
 impl TimerManager {
@@ -237,6 +349,10 @@ impl TimerManager {
 
 			id_allocator: IDAllocator::new(TIMER_MAX as _)?,
 			timers: HashMap::new(),
+
+			itimer_real: None,
+			itimer_virtual: Default::default(),
+			itimer_prof: Default::default(),
 		})
 	}
 
@@ -274,6 +390,86 @@ impl TimerManager {
 			.ok_or_else(|| errno!(EINVAL))?;
 		Ok(())
 	}
+
+	/// Returns the interval and the remaining time until the next expiration of the interval
+	/// timer `which`, in nanoseconds.
+	///
+	/// If `which` is invalid, the function returns [`errno::EINVAL`].
+	pub fn get_itimer(&self, which: c_int) -> EResult<(Timestamp, Timestamp)> {
+		match which {
+			ITIMER_REAL => Ok(self
+				.itimer_real
+				.as_ref()
+				.map(Timer::get_time_ns)
+				.unwrap_or_default()),
+			ITIMER_VIRTUAL => Ok((self.itimer_virtual.interval, self.itimer_virtual.value)),
+			ITIMER_PROF => Ok((self.itimer_prof.interval, self.itimer_prof.value)),
+			_ => Err(errno!(EINVAL)),
+		}
+	}
+
+	/// Sets the interval timer `which`, returning its previous interval and value.
+	///
+	/// Arguments:
+	/// - `interval` is the interval with which the timer is reloaded on expiration, in nanoseconds
+	/// - `value` is the time until the next expiration, in nanoseconds. If zero, the timer is
+	///   disarmed
+	///
+	/// If `which` is invalid, the function returns [`errno::EINVAL`].
+	pub fn set_itimer(
+		&mut self,
+		which: c_int,
+		interval: Timestamp,
+		value: Timestamp,
+	) -> EResult<(Timestamp, Timestamp)> {
+		let old = self.get_itimer(which)?;
+		match which {
+			ITIMER_REAL => {
+				let timer = match &mut self.itimer_real {
+					Some(timer) => timer,
+					// Do not allocate a timer just to disarm it
+					None if value == 0 => return Ok(old),
+					None => {
+						let pid = self.pid;
+						let timer = Timer::with_callback(Clock::Monotonic, move || {
+							if let Some(proc) = Process::get_by_pid(pid) {
+								proc.kill_process(Signal::SIGALRM);
+							}
+						})?;
+						self.itimer_real.insert(timer)
+					}
+				};
+				timer.set_time(interval, value)?;
+			}
+			_ => {
+				let timer = if which == ITIMER_VIRTUAL {
+					&mut self.itimer_virtual
+				} else {
+					&mut self.itimer_prof
+				};
+				*timer = CpuITimer {
+					interval: if value != 0 { interval } else { 0 },
+					value,
+				};
+			}
+		}
+		Ok(old)
+	}
+
+	/// Charges CPU time consumed by the process to the virtual and profiling interval timers.
+	///
+	/// Arguments:
+	/// - `user` is the time spent in userspace, in nanoseconds
+	/// - `total` is the time spent in userspace and kernelspace, in nanoseconds
+	///
+	/// The function returns whether the virtual and profiling timers, respectively, expired. It
+	/// is the caller's responsibility to send the corresponding signals.
+	pub fn charge_cputime(&mut self, user: Timestamp, total: Timestamp) -> (bool, bool) {
+		(
+			self.itimer_virtual.charge(user),
+			self.itimer_prof.charge(total),
+		)
+	}
 }
 
 /// The queue of timers to be fired next.
@@ -284,6 +480,17 @@ impl TimerManager {
 static TIMERS_QUEUE: IntMutex<BTreeMap<(Timestamp, *const TimerInner), ()>> =
 	IntMutex::new(BTreeMap::new());
 
+/// Returns the boot time, in nanoseconds, at which the next timer expires.
+///
+/// If no timer is armed, the function returns `None`.
+pub(super) fn next_expiry() -> Option<Timestamp> {
+	let queue = TIMERS_QUEUE.lock();
+	let ((next, timer), _) = queue.first_key_value()?;
+	let clock = unsafe { &**timer }.clock;
+	let remain = next.saturating_sub(current_time_ns(clock));
+	Some(current_time_ns(Clock::Boottime) + remain)
+}
+
 /// Triggers all expired timers.
 pub(super) fn tick() {
 	let mut times: [Option<Timestamp>; 12] = Default::default();
@@ -304,7 +511,62 @@ pub(super) fn tick() {
 		if timer.is_oneshot() {
 			queue.pop_first();
 		} else {
-			oom::wrap(|| timer.reset(&mut queue, ts));
+			oom::wrap_nofail(|| timer.reset(&mut queue, ts));
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test_case]
+	fn cpu_itimer_charge() {
+		let mut timer = CpuITimer::default();
+		assert!(!timer.charge(1000));
+		// Oneshot
+		timer.value = 1000;
+		assert!(!timer.charge(400));
+		assert_eq!(timer.value, 600);
+		assert!(timer.charge(600));
+		assert_eq!(timer.value, 0);
+		assert!(!timer.charge(1000));
+		// Periodic, with the overrun deducted from the next period
+		timer.interval = 1000;
+		timer.value = 500;
+		assert!(timer.charge(2700));
+		assert_eq!(timer.value, 800);
+		assert!(timer.charge(800));
+		assert_eq!(timer.value, 1000);
+	}
+
+	#[test_case]
+	fn next_expiry_order() {
+		const SEC: Timestamp = 1_000_000_000;
+		/// Asserts the next expiry is `value` nanoseconds from now, give or take a second.
+		fn assert_next(value: Timestamp) {
+			let now = current_time_ns(Clock::Boottime);
+			let next = next_expiry().unwrap();
+			assert!((now + value - SEC..=now + value + SEC).contains(&next));
 		}
+		let mut a = Timer::with_callback(Clock::Monotonic, || {}).unwrap();
+		let mut b = Timer::with_callback(Clock::Boottime, || {}).unwrap();
+		let mut c = Timer::with_callback(Clock::Realtime, || {}).unwrap();
+		a.set_time(0, 2000 * SEC).unwrap();
+		assert_next(2000 * SEC);
+		// Armed later but expiring first
+		b.set_time(0, 1000 * SEC).unwrap();
+		assert_next(1000 * SEC);
+		c.set_time(0, 3000 * SEC).unwrap();
+		assert_next(1000 * SEC);
+		// Disarming the first timer
+		b.set_time(0, 0).unwrap();
+		assert_next(2000 * SEC);
+		// Dropping an armed timer removes it
+		drop(a);
+		assert_next(3000 * SEC);
+		// Rearming moves the timer in the queue
+		c.set_time(0, 500 * SEC).unwrap();
+		assert_next(500 * SEC);
 	}
 }
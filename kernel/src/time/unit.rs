@@ -107,6 +107,31 @@ impl PartialOrd for Timeval {
 	}
 }
 
+/// Same as [`Timeval`], but with 32 bits values.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Timeval32 {
+	/// Seconds
+	pub tv_sec: u32,
+	/// Microseconds
+	pub tv_usec: u32,
+}
+
+impl TimeUnit for Timeval32 {
+	fn from_nano(timestamp: u64) -> Self {
+		Self {
+			tv_sec: (timestamp / 1_000_000_000) as _,
+			tv_usec: ((timestamp % 1_000_000_000) / 1000) as _,
+		}
+	}
+
+	fn to_nano(&self) -> u64 {
+		(self.tv_sec as u64)
+			.wrapping_mul(1_000_000_000)
+			.wrapping_add((self.tv_usec as u64).wrapping_mul(1000))
+	}
+}
+
 /// Same as [`Timeval`], but with nanosecond precision.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 #[repr(C)]
@@ -238,3 +263,33 @@ pub struct ITimerspec32 {
 	/// Start value of the timer.
 	pub it_value: Timespec32,
 }
+
+/// Same as [`ITimerspec32`], but with 64 bits values.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerspec {
+	/// The interval between each firing of the timer.
+	pub it_interval: Timespec,
+	/// Start value of the timer.
+	pub it_value: Timespec,
+}
+
+/// An interval timer's state.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerval32 {
+	/// The interval between each expiration of the timer.
+	pub it_interval: Timeval32,
+	/// The time until the next expiration of the timer.
+	pub it_value: Timeval32,
+}
+
+/// Same as [`ITimerval32`], but with 64 bits values.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ITimerval {
+	/// The interval between each expiration of the timer.
+	pub it_interval: Timeval,
+	/// The time until the next expiration of the timer.
+	pub it_value: Timeval,
+}
@@ -30,9 +30,13 @@ pub mod vga;
 
 use crate::{
 	device::serial,
-	file::wait_queue::WaitQueue,
+	file::wait_queue::{PollTable, WaitQueue},
 	memory::{user::UserSlice, vmem},
-	process::{Process, pid::Pid, signal::Signal},
+	process::{
+		Process,
+		pid::Pid,
+		signal::{Signal, SignalHandler},
+	},
 	sync::mutex::IntMutex,
 	tty::{
 		ansi::{ANSIBuffer, ESCAPE},
@@ -40,7 +44,10 @@ use crate::{
 	},
 };
 use core::{cmp::min, ptr};
-use utils::errno::EResult;
+use utils::{
+	errno,
+	errno::{AllocResult, EResult},
+};
 
 /// The number of history lines for one TTY.
 const HISTORY_LINES: vga::Pos = 128;
@@ -122,6 +129,10 @@ pub struct TTYDisplay {
 
 	/// The current foreground Program Group ID.
 	pgrp: Pid,
+	/// The ID of the session the TTY is the controlling terminal of.
+	///
+	/// If zero, the TTY is not the controlling terminal of any session.
+	sid: Pid,
 
 	/// Tells whether the cursor is currently visible on screen.
 	cursor_visible: bool,
@@ -379,6 +390,27 @@ impl TTYDisplay {
 		self.pgrp = pgrp;
 	}
 
+	/// Returns the ID of the session the TTY is the controlling terminal of.
+	///
+	/// If the TTY is not a controlling terminal, the function returns `0`.
+	pub fn get_sid(&self) -> Pid {
+		self.sid
+	}
+
+	/// Makes the TTY the controlling terminal of the session `sid`, with `pgrp` as the
+	/// foreground Program Group ID.
+	///
+	/// If `sid` is zero, the TTY is detached from its session.
+	pub fn set_session(&mut self, sid: Pid, pgrp: Pid) {
+		self.sid = sid;
+		self.pgrp = pgrp;
+	}
+
+	/// Tells whether the TTY is the controlling terminal of the given process.
+	pub fn is_controlling(&self, proc: &Process) -> bool {
+		self.sid != 0 && self.sid == proc.get_sid()
+	}
+
 	/// Returns the window size of the TTY.
 	pub fn get_winsize(&self) -> &WinSize {
 		&self.winsize
@@ -447,6 +479,7 @@ pub static TTY: TTY = TTY {
 		ansi_buffer: ANSIBuffer::new(),
 
 		pgrp: 0,
+		sid: 0,
 
 		cursor_visible: true,
 		current_color: vga::DEFAULT_COLOR,
@@ -466,6 +499,10 @@ impl TTY {
 	/// The function returns the number of bytes read.
 	pub fn read(&self, buf: UserSlice<u8>) -> EResult<usize> {
 		self.rd_queue.wait_until(|| {
+			// The process group might have been moved to the background while waiting
+			if let Err(e) = self.check_job_control(Signal::SIGTTIN) {
+				return Some(Err(e));
+			}
 			let termios = self.display.lock().get_termios().clone();
 			let mut input = self.input.lock();
 			// Canonical mode
@@ -515,6 +552,90 @@ impl TTY {
 		})?
 	}
 
+	/// Writes the content of the buffer `buf` onto the TTY.
+	///
+	/// The function returns the number of bytes written.
+	pub fn write(&self, buf: UserSlice<u8>) -> EResult<usize> {
+		let tostop = self.display.lock().get_termios().c_lflag & TOSTOP != 0;
+		if tostop {
+			self.check_job_control(Signal::SIGTTOU)?;
+		}
+		let mut i = 0;
+		let mut b: [u8; 128] = [0; 128];
+		while i < buf.len() {
+			let l = buf.copy_from_user(i, &mut b)?;
+			self.display.lock().write(&b[..l]);
+			i += l;
+		}
+		Ok(buf.len())
+	}
+
+	/// Checks whether the current process is allowed to access the TTY, regarding job control.
+	///
+	/// `sig` is the signal to send to the process's group if it is in the background: `SIGTTIN`
+	/// for reading, `SIGTTOU` for writing or changing the TTY's settings.
+	///
+	/// If the signal is sent, the function returns [`errno::EINTR`]. If it cannot be sent, the
+	/// function returns [`errno::EIO`].
+	pub fn check_job_control(&self, sig: Signal) -> EResult<()> {
+		let proc = Process::current();
+		let pgrp = {
+			let display = self.display.lock();
+			if !display.is_controlling(&proc) {
+				return Ok(());
+			}
+			display.pgrp
+		};
+		let pgid = proc.get_pgid();
+		if pgrp == 0 || pgid == pgrp {
+			return Ok(());
+		}
+		let ignored = {
+			let signal_manager = proc.signal.lock();
+			let handler = signal_manager.handlers.lock()[sig as usize].clone();
+			signal_manager.is_signal_blocked(sig) || matches!(handler, SignalHandler::Ignore)
+		};
+		if ignored {
+			// A background process ignoring `SIGTTOU` is allowed to write
+			return if sig == Signal::SIGTTOU {
+				Ok(())
+			} else {
+				Err(errno!(EIO))
+			};
+		}
+		// Nobody would be able to resume the process group
+		if proc.is_in_orphan_process_group() {
+			return Err(errno!(EIO));
+		}
+		send_signal(sig, pgid);
+		Err(errno!(EINTR))
+	}
+
+	/// Hangs up the TTY if it is the controlling terminal of the session `sid`.
+	///
+	/// The foreground process group is sent `SIGHUP` and `SIGCONT`, then the TTY is detached from
+	/// the session.
+	pub fn hangup(&self, sid: Pid) {
+		let pgrp = {
+			let mut display = self.display.lock();
+			if sid == 0 || display.sid != sid {
+				return;
+			}
+			let pgrp = display.pgrp;
+			display.set_session(0, 0);
+			pgrp
+		};
+		send_signal(Signal::SIGHUP, pgrp);
+		send_signal(Signal::SIGCONT, pgrp);
+		// Interrupt readers
+		self.rd_queue.wake_all();
+	}
+
+	/// Registers the queue of processes waiting for incoming data on `table`.
+	pub fn poll_register(&'static self, table: &mut PollTable) -> AllocResult<()> {
+		table.register(&self.rd_queue)
+	}
+
 	/// Tells whether the TTY has any data available to be read.
 	pub fn has_input_available(&self) -> bool {
 		let display = self.display.lock();
@@ -638,7 +759,7 @@ impl TTY {
 			}
 		}
 
-		self.rd_queue.wake_next();
+		self.rd_queue.wake_all();
 	}
 
 	/// Erases `count` characters in TTY.
@@ -668,6 +789,6 @@ impl TTY {
 			}
 		}
 
-		self.rd_queue.wake_next();
+		self.rd_queue.wake_all();
 	}
 }
@@ -16,15 +16,22 @@
  * Maestro. If not, see <https://www.gnu.org/licenses/>.
  */
 
-//! This module implements the driver structure.
+//! This module implements the driver, registering a network interface for each supported NIC.
 
-use crate::nic::NIC;
-use core::{any::Any, convert::TryInto};
+use crate::nic::Nic;
+use core::any::Any;
 use kernel::{
 	device::{bus::pci::PCIManager, manager, manager::PhysicalDevice},
 	net,
+	net::Interface,
+	println,
 	sync::mutex::Mutex,
-	utils::ptr::arc::Arc,
+	utils::{
+		TryClone,
+		collections::{string::String, vec::Vec},
+		errno::EResult,
+		format,
+	},
 };
 
 /// Vendor ID for Intel.
@@ -33,66 +40,51 @@ const VENDOR_INTEL: u16 = 0x8086;
 const DEVICE_EMU: u16 = 0x100e;
 // TODO Add real NICs
 
-/// The e1000 driver.
-pub struct E1000Driver;
+/// The names of the interfaces registered by the driver.
+static IFACES: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
-impl E1000Driver {
-	/// Creates a new instance.
-	pub fn new() -> Self {
-		let s = Self;
-
-		let manager = manager::get::<PCIManager>();
-		if let Some(manager_mutex) = manager {
-			let manager = manager_mutex.lock();
-			let pci_manager = (&*manager as &dyn Any)
-				.downcast_ref::<PCIManager>()
-				.unwrap();
-
-			for dev in pci_manager.get_devices() {
-				s.on_plug(dev);
-			}
-		}
-
-		s
-	}
-}
-
-impl Driver for E1000Driver {
-	fn get_name(&self) -> &str {
-		"e1000"
+/// Registers an interface for `dev` if it is a supported NIC.
+fn probe(dev: &dyn PhysicalDevice) -> EResult<()> {
+	// TODO Add real NICs
+	if dev.get_vendor_id() != VENDOR_INTEL || dev.get_device_id() != DEVICE_EMU {
+		return Ok(());
 	}
-
-	fn on_plug(&self, dev: &dyn PhysicalDevice) {
-		if dev.get_vendor_id() != VENDOR_INTEL {
-			return;
+	// TODO support devices with multiple interfaces
+	let mut nic = match Nic::new(dev) {
+		Ok(nic) => nic,
+		Err(e) => {
+			println!("e1000 error: {e}");
+			return Ok(());
 		}
+	};
+	nic.set_up(true);
+	let mut ifaces = IFACES.lock();
+	let name = format!("eth{}", ifaces.len())?;
+	net::register_iface(name.try_clone()?, nic)?;
+	ifaces.push(name)?;
+	Ok(())
+}
 
-		match dev.get_device_id() {
-			// TODO Add real NICs
-			DEVICE_EMU => {
-				// TODO support devices with multiple interfaces
-				match NIC::new(dev) {
-					Ok(nic) => {
-						// TODO do not unwrap errors
-						// TODO figure out how to get the name of the interface
-						let name = b"TODO".try_into().unwrap();
-						let iface = Arc::new(Mutex::new(nic)).unwrap();
-
-						let mut ifaces = net::INTERFACES.lock();
-						ifaces.insert(name, iface).unwrap();
-					}
-
-					Err(e) => {
-						kernel::println!("e1000 error: {e}");
-					}
-				}
-			}
-
-			_ => {}
-		}
+/// Registers an interface for each supported NIC on the PCI bus.
+pub fn init() -> EResult<()> {
+	let Some(manager) = manager::get::<PCIManager>() else {
+		return Ok(());
+	};
+	let manager = manager.lock();
+	let pci_manager = (&*manager as &dyn Any)
+		.downcast_ref::<PCIManager>()
+		.unwrap();
+	for dev in pci_manager.get_devices() {
+		probe(dev)?;
 	}
+	Ok(())
+}
 
-	fn on_unplug(&self, _dev: &dyn PhysicalDevice) {
-		todo!()
+/// Unregisters the interfaces registered by the driver.
+pub fn fini() {
+	let mut ifaces = IFACES.lock();
+	for name in ifaces.iter() {
+		net::unregister_iface(name);
 	}
+	ifaces.clear();
 }
@@ -26,8 +26,7 @@
 #[no_link]
 extern crate kernel;
 
-// FIXME
-//mod driver;
+mod driver;
 mod nic;
 
 kernel::module!([]);
@@ -35,14 +34,11 @@ kernel::module!([]);
 /// Called on module load
 #[unsafe(no_mangle)]
 pub extern "C" fn init() -> bool {
-	// FIXME
-	//kernel::device::driver::register(E1000Driver::new()).is_ok()
-	todo!()
+	driver::init().is_ok()
 }
 
 /// Called on module unload
 #[unsafe(no_mangle)]
 pub extern "C" fn fini() {
-	// FIXME
-	//kernel::device::driver::unregister("e1000");
+	driver::fini();
 }
@@ -20,13 +20,15 @@
 
 use core::{cmp::min, hint::unlikely, mem::size_of, num::NonZeroUsize, ptr, slice};
 use kernel::{
+	arch::x86::idt,
 	device::{bar::BAR, manager::PhysicalDevice},
 	event,
-	event::CallbackHook,
+	event::{CallbackHook, CallbackResult},
 	memory::{PhysAddr, VirtAddr, buddy},
 	net,
-	net::{BindAddress, MAC, buf::BufList},
-	utils::{errno::EResult, limits::PAGE_SIZE},
+	net::{Address, BindAddress, MAC, buf::BufList},
+	sync::mutex::IntMutex,
+	utils::{collections::vec::Vec, errno, errno::EResult, limits::PAGE_SIZE},
 };
 
 /// The number of receive descriptors.
@@ -38,6 +40,10 @@ const TX_DESC_COUNT: usize = 128;
 /// The size of a transmit descriptor's buffer.
 const TX_BUFF_SIZE: usize = 16384;
 
+/// Register address: Device Control
+const REG_CTRL: u16 = 0x0;
+/// Register address: Device Status
+const REG_STATUS: u16 = 0x8;
 /// Register address: EEPROM/Flash Control & Data
 const REG_EECD: u16 = 0x10;
 /// Register address: EEPROM Read Register
@@ -49,6 +55,8 @@ const REG_ICR: u16 = 0xc0;
 const REG_ITR: u16 = 0xc4;
 /// Register address: Interrupt Mask Set/Read Register
 const REG_IMS: u16 = 0xd0;
+/// Register address: Interrupt Mask Clear Register
+const REG_IMC: u16 = 0xd8;
 
 /// Register address: Receive Control
 const REG_RCTL: u16 = 0x100;
@@ -94,6 +102,12 @@ const IMS_RXO: u32 = 1 << 6;
 /// Interrupt Mask Set flag: Receiver Timer Interrupt
 const IMS_RTX0: u32 = 1 << 7;
 
+/// STATUS flag: Link Up
+const STATUS_LU: u32 = 1 << 1;
+
+/// Control flag: Set Link Up
+const CTRL_SLU: u32 = 1 << 6;
+
 /// RCTL flag: Receiver Enable
 const RCTL_EN: u32 = 1 << 1;
 /// RCTL flag: Store Bad Packets
@@ -171,6 +185,26 @@ const TX_STA_LC: u8 = 1 << 2;
 /// Transmit descriptor status flag: Transmit Underrun
 const TX_STA_TU: u8 = 1 << 3;
 
+/// The BAR0 of every NIC, used by the interrupt handler to acknowledge interrupts.
+///
+/// Since interrupt lines may be shared, the handler acknowledges interrupts on every NIC.
+static INT_BARS: IntMutex<Vec<BAR>> = IntMutex::new(Vec::new());
+
+/// Interrupt handler for NICs.
+fn int_handler(_: u32, _: u32, _: &mut idt::IntFrame, _: u8) -> CallbackResult {
+	let mut rx = false;
+	for bar in INT_BARS.lock().iter() {
+		// Reading the register acknowledges the interrupt
+		let icr = bar.read::<u32>(REG_ICR as _) as u32;
+		rx |= icr != 0;
+	}
+	if rx {
+		// Frames are handled by the kernel's receive task
+		net::rx_notify();
+	}
+	CallbackResult::Continue
+}
+
 // TODO caches need to be flushed before reading/writing from/to receive/transmit buffers
 
 /// The receive descriptor.
@@ -229,6 +263,8 @@ pub struct Nic {
 
 	/// The NIC's mac address.
 	mac: [u8; 6],
+	/// The addresses bound to the interface.
+	addresses: Vec<BindAddress>,
 
 	/// The list of receive descriptors.
 	rx_descs: *mut RXDesc,
@@ -253,10 +289,13 @@ impl Nic {
 
 		let bar0 = dev.get_bars()[0].clone().ok_or("Invalid BAR for NIC")?;
 
-		let int_line = dev.get_interrupt_line().ok_or("Invalid BAR for NIC")?;
-		let int_hook = event::register_callback(int_line as _, |_, _, _, _| todo!())
+		let int_line = dev
+			.get_interrupt_line()
+			.ok_or("Invalid interrupt line for NIC")?;
+		// IRQs are remapped after CPU exceptions
+		let int_hook = event::register_callback(0x20 + int_line as u32, int_handler)
 			.map_err(|_| "Memory allocation failed")?
-			.unwrap();
+			.ok_or("Invalid interrupt line for NIC")?;
 
 		let rx_pages =
 			NonZeroUsize::new((RX_DESC_COUNT * size_of::<RXDesc>()).div_ceil(PAGE_SIZE)).unwrap();
@@ -283,6 +322,7 @@ impl Nic {
 			eeprom_exists: false,
 
 			mac: [0; 6],
+			addresses: Vec::new(),
 
 			rx_descs: rx_descs.as_ptr() as _,
 			rx_cur: 0,
@@ -294,6 +334,12 @@ impl Nic {
 		n.read_mac();
 		n.init_desc().map_err(|_| "Memory allocation failed")?;
 
+		INT_BARS
+			.lock()
+			.push(n.bar0.clone())
+			.map_err(|_| "Memory allocation failed")?;
+		idt::enable_irq(int_line);
+
 		Ok(n)
 	}
 
@@ -355,7 +401,7 @@ impl Nic {
 	/// Initializes transmit and receive descriptors.
 	fn init_desc(&self) -> EResult<()> {
 		// Set interrupts mask
-		self.write_command(REG_IMS, IMS_TXQE | IMS_RXDMT0);
+		self.write_command(REG_IMS, IMS_RTX0 | IMS_RXO | IMS_RXDMT0 | IMS_LSC);
 
 		// Init receive ring buffer
 		let rx_buffs_pages =
@@ -364,10 +410,10 @@ impl Nic {
 		let rx_buffs = buddy::alloc_kernel(rx_buffs_order, 0)?;
 		for i in 0..RX_DESC_COUNT {
 			let desc = unsafe { &mut *self.rx_descs.add(i) };
-			let ptr = unsafe { rx_buffs.add(i * TX_BUFF_SIZE) };
+			let ptr = unsafe { rx_buffs.add(i * RX_BUFF_SIZE) };
 
 			*desc = RXDesc::default();
-			desc.addr = ptr.as_ptr() as _;
+			desc.addr = VirtAddr::from(ptr.as_ptr()).kernel_to_physical().unwrap().0 as _;
 		}
 
 		// Set receive ring buffer address
@@ -397,7 +443,7 @@ impl Nic {
 			let ptr = unsafe { tx_buffs.add(i * TX_BUFF_SIZE) };
 
 			*desc = TXDesc::default();
-			desc.addr = ptr.as_ptr() as _;
+			desc.addr = VirtAddr::from(ptr.as_ptr()).kernel_to_physical().unwrap().0 as _;
 			desc.status = TX_STA_DD;
 		}
 
@@ -416,7 +462,7 @@ impl Nic {
 		// Set transmit flags
 		let retry_count = 0xf;
 		let collision_dist = 0x200;
-		let flags = TCTL_EN | (retry_count << 4) | (collision_dist << 12);
+		let flags = TCTL_EN | TCTL_PSP | (retry_count << 4) | (collision_dist << 12);
 		self.write_command(REG_TCTL, flags);
 		let flags = 0; // TODO
 		self.write_command(REG_TIPG, flags);
@@ -431,7 +477,17 @@ impl net::Interface for Nic {
 	}
 
 	fn is_up(&self) -> bool {
-		todo!()
+		self.read_command(REG_STATUS) & STATUS_LU != 0
+	}
+
+	fn set_up(&mut self, up: bool) {
+		let ctrl = self.read_command(REG_CTRL);
+		let ctrl = if up {
+			ctrl | CTRL_SLU
+		} else {
+			ctrl & !CTRL_SLU
+		};
+		self.write_command(REG_CTRL, ctrl);
 	}
 
 	fn get_mac(&self) -> &MAC {
@@ -439,19 +495,40 @@ impl net::Interface for Nic {
 	}
 
 	fn get_addresses(&self) -> &[BindAddress] {
-		todo!()
+		&self.addresses
+	}
+
+	fn add_address(&mut self, addr: BindAddress) -> EResult<()> {
+		if self.addresses.iter().any(|a| a.addr == addr.addr) {
+			return Err(errno!(EEXIST));
+		}
+		self.addresses.push(addr)?;
+		Ok(())
+	}
+
+	fn remove_address(&mut self, addr: &Address) -> EResult<()> {
+		let i = self
+			.addresses
+			.iter()
+			.position(|a| a.addr == *addr)
+			.ok_or_else(|| errno!(EADDRNOTAVAIL))?;
+		self.addresses.remove(i);
+		Ok(())
 	}
 
 	fn read(&mut self, buff: &mut [u8]) -> EResult<u64> {
 		let mut i = 0;
 		let mut prev_cursor = None;
 
-		while i < buff.len() {
-			let desc = unsafe { &mut *self.tx_descs.add(self.rx_cur) };
-			if desc.status & RX_STA_DD == 0 {
+		// A frame may span several descriptors. Read until the end of the frame
+		loop {
+			let desc = unsafe { &mut *self.rx_descs.add(self.rx_cur) };
+			let status = unsafe { ptr::read_volatile(&desc.status) };
+			if status & RX_STA_DD == 0 {
 				break;
 			}
 
+			// If the frame is larger than the buffer, discard the remaining data
 			let addr = PhysAddr(desc.addr as _).kernel_to_virtual().unwrap();
 			let len = min(buff.len() - i, desc.length as usize);
 			let slice = unsafe { slice::from_raw_parts(addr.as_ptr(), len) };
@@ -463,101 +540,74 @@ impl net::Interface for Nic {
 
 			prev_cursor = Some(self.rx_cur);
 			self.rx_cur = (self.rx_cur + 1) % RX_DESC_COUNT;
+
+			if status & RX_STA_EOP != 0 {
+				break;
+			}
 		}
 
 		if let Some(prev_cursor) = prev_cursor {
 			self.write_command(REG_RDT, prev_cursor as _);
 		}
 
-		todo!() // return number of read bytes
+		Ok(i as _)
 	}
 
-	fn write(&mut self, mut buf: &BufList<'_>) -> EResult<u64> {
-		// The function takes a set of input buffers and has to write them onto another set of
-		// buffers (the descriptors in use in the device's ring buffer) which may have a different
-		// size.
-		//
-		// To do so, it uses a loop which iterates on both buffers and copies data at each
-		// iteration.
-		//
-		// The length of the copy is the smallest length of remaining data in both buffer, to make
-		// sure there is no overflow.
-		//
-		// At each iteration, at least one of both buffers will be exhausted, which means at least
-		// the complexity is at least `O(min(a, b))` and at most `O(max(a, b))`, where `a` and `b`
-		// are the number of input buffers and the number of descriptors.
-		//
-		// If the next descriptors are busy, the function must wait until it becomes available by
-		// waiting for an interruption.
-
-		let descriptors = unsafe { slice::from_raw_parts_mut(self.tx_descs, TX_DESC_COUNT) };
-
-		// get the next descriptor and wait if necessary
-		fn next_desc(s: &mut Nic, descriptors: &[TXDesc]) {
-			s.tx_cur = (s.tx_cur + 1) % TX_DESC_COUNT;
-			let desc = &descriptors[s.tx_cur];
-
-			// if the descriptor is busy, wait for it to become available again
-			loop {
-				let status = unsafe { ptr::read(&desc.status) };
-				if status & RX_STA_DD == 0 {
-					break;
-				}
-
-				// TODO wait for transmit interrupt
-				todo!()
-			}
-		}
-
-		let mut buf_off = 0;
-		if unlikely(buf.len() == 0) {
+	fn write(&mut self, buf: &BufList<'_>) -> EResult<u64> {
+		// Each frame is written onto a single descriptor
+		let len = buf.len();
+		if unlikely(len == 0) {
 			return Ok(0);
 		}
-		let mut buf = Some(buf);
-
-		while let Some(b) = buf {
-			let desc = &mut descriptors[self.tx_cur];
-			let desc_len = desc.length as usize;
-			// if the descriptor is full, go to next
-			if desc_len >= TX_BUFF_SIZE {
-				next_desc(self, descriptors);
-				continue;
-			}
-
-			let dst = unsafe { slice::from_raw_parts_mut(desc.addr as *mut u8, TX_BUFF_SIZE) };
+		if unlikely(len > TX_BUFF_SIZE) {
+			return Err(errno!(EMSGSIZE));
+		}
 
-			// copy data
-			let copy_len = min(b.data.len() - buf_off, TX_BUFF_SIZE - desc_len);
-			dst[desc_len..].copy_from_slice(&b.data[buf_off..(buf_off + copy_len)]);
-			buf_off += copy_len;
+		let desc = unsafe { &mut *self.tx_descs.add(self.tx_cur) };
+		// If the descriptor has not been transmitted yet, the ring buffer is full
+		let status = unsafe { ptr::read_volatile(&desc.status) };
+		if status & TX_STA_DD == 0 {
+			return Err(errno!(EAGAIN));
+		}
 
-			desc.length = (desc_len + copy_len) as _;
-			desc.cmd = TX_CMD_RS;
-			desc.status = 0;
+		// Copy data
+		let addr = PhysAddr(desc.addr as _).kernel_to_virtual().unwrap();
+		let dst = unsafe { slice::from_raw_parts_mut(addr.as_ptr::<u8>(), len) };
+		let mut off = 0;
+		let mut cur = Some(buf);
+		while let Some(b) = cur {
+			dst[off..(off + b.data.len())].copy_from_slice(b.data);
+			off += b.data.len();
+			cur = b.next();
+		}
 
-			// get next buffer if the current is exhausted
-			if buf_off >= b.data.len() {
-				buf = b.next();
-				buf_off = 0;
-			}
+		desc.length = len as _;
+		desc.cmd = TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS;
+		unsafe {
+			ptr::write_volatile(&mut desc.status, 0);
 		}
 
-		// flush descriptors
-		let desc = &mut descriptors[self.tx_cur];
-		desc.cmd |= TX_CMD_EOP | TX_CMD_IFCS;
+		// Flush the descriptor
+		self.tx_cur = (self.tx_cur + 1) % TX_DESC_COUNT;
 		self.write_command(REG_TDT, self.tx_cur as _);
 
-		todo!() // return the number of bytes written
+		Ok(len as _)
 	}
 }
 
 impl Drop for Nic {
 	fn drop(&mut self) {
+		// Stop receiving interrupts for the NIC
+		self.write_command(REG_IMC, !0);
+		INT_BARS.lock().retain(|b| *b != self.bar0);
 		unsafe {
 			let rx_buffs_pages =
 				NonZeroUsize::new((RX_DESC_COUNT * RX_BUFF_SIZE).div_ceil(PAGE_SIZE)).unwrap();
 			let rx_buffs_order = buddy::get_order(rx_buffs_pages);
-			buddy::free_kernel((*self.rx_descs).addr as _, rx_buffs_order);
+			let rx_buffs = PhysAddr((*self.rx_descs).addr as _)
+				.kernel_to_virtual()
+				.unwrap();
+			buddy::free_kernel(rx_buffs.as_ptr(), rx_buffs_order);
 
 			let rx_pages =
 				NonZeroUsize::new((RX_DESC_COUNT * size_of::<RXDesc>()).div_ceil(PAGE_SIZE))
@@ -568,7 +618,10 @@ impl Drop for Nic {
 			let tx_buffs_pages =
 				NonZeroUsize::new((TX_DESC_COUNT * TX_BUFF_SIZE).div_ceil(PAGE_SIZE)).unwrap();
 			let tx_buffs_order = buddy::get_order(tx_buffs_pages);
-			buddy::free_kernel((*self.tx_descs).addr as _, tx_buffs_order);
+			let tx_buffs = PhysAddr((*self.tx_descs).addr as _)
+				.kernel_to_virtual()
+				.unwrap();
+			buddy::free_kernel(tx_buffs.as_ptr(), tx_buffs_order);
 
 			let tx_pages =
 				NonZeroUsize::new((TX_DESC_COUNT * size_of::<TXDesc>()).div_ceil(PAGE_SIZE))
@@ -22,6 +22,7 @@
 use crate::{__alloc, __dealloc, boxed::Box, errno::AllocResult};
 use core::{
 	alloc::{AllocError, Layout},
+	any::Any,
 	borrow::Borrow,
 	fmt,
 	hash::{Hash, Hasher},
@@ -193,6 +194,23 @@ impl<T: ?Sized> Arc<T> {
 	}
 }
 
+impl Arc<dyn Any> {
+	/// Attempts to downcast the `Arc` to a concrete type.
+	///
+	/// If the inner object is not of type `T`, the function returns the `Arc` unchanged.
+	pub fn downcast<T: Any>(self) -> Result<Arc<T>, Self> {
+		if (*self).is::<T>() {
+			let inner = self.inner.cast();
+			mem::forget(self);
+			Ok(Arc {
+				inner,
+			})
+		} else {
+			Err(self)
+		}
+	}
+}
+
 impl<T: ?Sized> AsRef<T> for Arc<T> {
 	fn as_ref(&self) -> &T {
 		&self.inner().obj